@@ -9,20 +9,24 @@ use std::{
     fs::File,
     hash::BuildHasherDefault,
     io,
-    io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
     os::{
         fd::{AsFd, OwnedFd},
         unix::fs::FileExt,
     },
     path::{Path, PathBuf},
+    process::ExitCode,
     str,
     sync::Arc,
+    thread,
+    time::Duration,
 };
 
 use arrayvec::ArrayVec;
 use ask::Answer;
 use base64_serde::base64_serde_type;
-use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum, ValueHint};
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
+use clap_complete::{ArgValueCandidates, CompleteEnv, CompletionCandidate};
 use clap_num::si_number;
 use error_stack::Report;
 use rand::{
@@ -38,22 +42,29 @@ use regex::bytes::Regex;
 use ringboard_sdk::{
     api::{
         connect_to_server, connect_to_server_with, AddRequest, GarbageCollectRequest,
-        MoveToFrontRequest, RemoveRequest, SwapRequest,
+        negotiate_with_server, set_client_name, BackupNowRequest, MoveToFrontRequest,
+        RemoveRequest, ServerInfoRequest, StatsRequest, SwapRequest, TrimMemoryRequest,
     },
     core::{
         bucket_to_length, copy_file_range_all,
         dirs::{data_dir, socket_file},
+        protocol,
         protocol::{
-            decompose_id, AddResponse, GarbageCollectResponse, IdNotFoundError, MimeType,
-            MoveToFrontResponse, RemoveResponse, Response, RingKind, SwapResponse,
+            decompose_id, AddResponse, BackupOutcome, BackupResponse, GarbageCollectResponse,
+            IdNotFoundError, MimeType, MoveToFrontResponse, RemoveResponse, Response, RingKind,
+            ServerInfoResponse, SwapResponse, TrimMemoryResponse,
         },
         read_lock_file_pid,
         ring::Mmap,
-        size_to_bucket, BucketAndIndex, Error as CoreError, IoErr, NUM_BUCKETS,
+        size_to_bucket, Error as CoreError, IoErr, NUM_BUCKETS,
     },
-    duplicate_detection::DuplicateDetector,
-    search::{CaselessQuery, EntryLocation, Query, QueryResult},
-    ClientError, DatabaseReader, EntryReader, Kind,
+    duplicate_detection,
+    duplicate_detection::{DuplicateDetector, DuplicateGroup, Matching},
+    export,
+    export::ExportedEntry,
+    one_liner,
+    search::{CaselessQuery, EntryIndex, EntryLocation, Query},
+    ClientError, DatabaseReader, Entry, EntryReader, Kind,
 };
 use rustc_hash::FxHasher;
 use rustix::{
@@ -73,21 +84,28 @@ use thiserror::Error;
 /// exclusive write access to the clipboard database and clients must ask the
 /// server to perform the modifications they need. This CLI is a non-interactive
 /// client and a debugging tool.
+///
+/// Failures exit with status 3 if an entry ID doesn't exist, 2 for invalid
+/// arguments, and 1 otherwise.
 #[derive(Parser, Debug)]
 #[command(version, author = "Alex Saveau (@SUPERCILEX)")]
 #[command(infer_subcommands = true, infer_long_args = true)]
-#[command(disable_help_flag = true)]
-#[command(arg_required_else_help = true)]
+#[command(disable_help_flag = true, disable_version_flag = true)]
+#[command(arg_required_else_help = true, args_conflicts_with_subcommands = true)]
 #[command(max_term_width = 100)]
 #[cfg_attr(test, command(help_expected = true))]
 struct Cli {
     #[command(subcommand)]
-    cmd: Cmd,
+    cmd: Option<Cmd>,
 
     #[arg(short, long, short_alias = '?', global = true)]
     #[arg(action = ArgAction::Help, help = "Print help (use `--help` for more detail)")]
     #[arg(long_help = "Print help (use `-h` for a summary)")]
     help: Option<bool>,
+
+    /// Print the client and server versions
+    #[arg(short = 'V', long)]
+    version: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -98,6 +116,14 @@ enum Cmd {
     #[command(aliases = ["g", "at", "gimme"])]
     Get(EntryAction),
 
+    /// List the most recent entries.
+    ///
+    /// Prints the ID, mime type, and one-line preview of each entry (as shown
+    /// in the UIs), newest first and separated by tabs. Entries that aren't
+    /// text have an empty preview.
+    #[command(alias = "ls")]
+    List(List),
+
     /// Searches the Ringboard database for entries matching a query.
     #[command(aliases = ["f", "find", "query"])]
     Search(Search),
@@ -110,14 +136,14 @@ enum Cmd {
 
     /// Favorite an entry.
     #[command(alias = "star")]
-    Favorite(EntryAction),
+    Favorite(MoveEntry),
 
     /// Unfavorite an entry.
     #[command(alias = "unstar")]
-    Unfavorite(EntryAction),
+    Unfavorite(MoveEntry),
 
     /// Move an entry to the front, making it the most recent entry.
-    MoveToFront(EntryAction),
+    MoveToFront(MoveEntry),
 
     /// Swap the positions of two entries.
     ///
@@ -142,12 +168,47 @@ enum Cmd {
     /// Migrate from other clipboard managers to Ringboard.
     Migrate(Migrate),
 
+    /// Export the database to a file.
+    ///
+    /// Entries are written as JSON lines after a format version header, with
+    /// text kept as is and everything else encoded in base64. Favorites come
+    /// first and each ring is listed from oldest to newest.
+    Export(Export),
+
+    /// Import an export made by the export command.
+    ///
+    /// Entries are added back to their ring in the order they were exported,
+    /// so they get new IDs.
+    #[command(alias = "restore")]
+    Import(Import),
+
     /// Run garbage collection on the database.
     ///
-    /// Prints the amount of freed space.
+    /// Prints the amount of freed space, broken down by bucket file.
     #[command(aliases = ["gc", "clean"])]
     GarbageCollect(GarbageCollect),
 
+    /// Delete entries whose content is identical to another entry.
+    ///
+    /// Favorites are never deleted in favor of a copy in the main ring.
+    #[command(alias = "dedup")]
+    Dedupe(Dedupe),
+
+    /// Back up the database right away.
+    ///
+    /// Backups are written to the server's `backup_dir` setting and are
+    /// otherwise taken every `backup_interval` seconds. Waits for the backup
+    /// to finish.
+    #[command(alias = "snapshot")]
+    Backup,
+
+    /// Print the paths and server this client resolves to.
+    ///
+    /// Shell completions are printed by running `COMPLETE=<shell> ringboard`
+    /// where `<shell>` is one of bash, zsh, fish, elvish, or powershell.
+    #[command(alias = "where")]
+    Env(Env),
+
     /// Debugging tools for developers.
     #[command(aliases = ["d", "dev"])]
     #[command(subcommand)]
@@ -185,6 +246,11 @@ enum Dev {
 
     /// Spam the server with random commands.
     Fuzz(Fuzz),
+
+    /// Ask the server to release memory it can do without.
+    ///
+    /// Prints the server's resident memory before and after trimming.
+    TrimMemory,
 }
 
 #[derive(Args, Debug)]
@@ -206,6 +272,10 @@ struct Add {
     /// The entry mime type.
     #[clap(short, long)]
     mime_type: Option<MimeType>,
+
+    /// Print the result as a JSON object.
+    #[clap(short, long)]
+    json: bool,
 }
 
 #[derive(ValueEnum, Copy, Clone, Debug)]
@@ -228,21 +298,59 @@ impl From<CliRingKind> for RingKind {
 struct EntryAction {
     /// The entry ID.
     #[arg(required = true)]
+    #[arg(add = ArgValueCandidates::new(entry_id_candidates))]
     id: u64,
 }
 
 #[derive(Args, Debug)]
 #[command(arg_required_else_help = true)]
+struct MoveEntry {
+    /// The entry ID.
+    #[arg(required = true)]
+    #[arg(add = ArgValueCandidates::new(entry_id_candidates))]
+    id: u64,
+
+    /// Print the entry's new ID as a JSON object.
+    #[arg(short, long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct List {
+    /// The maximum number of entries to print.
+    #[arg(short = 'n', long, default_value_t = 25)]
+    limit: usize,
+
+    /// List the favorites instead of the main ring.
+    #[arg(short, long)]
+    favorites: bool,
+
+    /// Print each entry as a JSON object on its own line.
+    #[arg(short, long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+#[command(arg_required_else_help = true)]
+#[allow(clippy::struct_excessive_bools)]
 struct Search {
     /// Interpret the query string as regex instead of a plain-text match.
     #[arg(short, long)]
     regex: bool,
 
-    /// Ignore ASCII casing when searching.
+    /// Ignore casing when searching.
     #[arg(short, long)]
     #[arg(conflicts_with = "regex")]
     ignore_case: bool,
 
+    /// Print every match instead of only the first one per entry.
+    #[arg(short, long)]
+    all_matches: bool,
+
+    /// Print each match as a JSON object on its own line.
+    #[arg(short, long)]
+    json: bool,
+
     /// The query string to search for.
     #[arg(required = true)]
     query: String,
@@ -253,13 +361,22 @@ struct Search {
 struct Swap {
     /// The first entry ID.
     #[arg(required = true)]
+    #[arg(add = ArgValueCandidates::new(entry_id_candidates))]
     id1: u64,
 
     /// The second entry ID.
     #[arg(required = true)]
+    #[arg(add = ArgValueCandidates::new(entry_id_candidates))]
     id2: u64,
 }
 
+#[derive(Args, Debug)]
+struct Env {
+    /// Print the environment as a JSON object.
+    #[arg(short, long)]
+    json: bool,
+}
+
 #[derive(Args, Debug)]
 #[command(arg_required_else_help = true)]
 struct Migrate {
@@ -275,6 +392,23 @@ struct Migrate {
     database: Option<PathBuf>,
 }
 
+#[derive(Args, Debug)]
+struct Export {
+    /// The file to write the export to, or `-` for stdout.
+    #[clap(value_hint = ValueHint::FilePath)]
+    #[arg(default_value = "-")]
+    file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+#[command(arg_required_else_help = true)]
+struct Import {
+    /// The export to import, or `-` for stdin.
+    #[arg(required = true)]
+    #[clap(value_hint = ValueHint::FilePath)]
+    file: PathBuf,
+}
+
 #[derive(ValueEnum, Copy, Clone, Debug)]
 enum MigrateFromClipboard {
     /// [Gnome Clipboard History](https://extensions.gnome.org/extension/4839/clipboard-history/)
@@ -305,6 +439,35 @@ struct GarbageCollect {
     max_wasted_bytes: u64,
 }
 
+#[derive(Args, Debug)]
+struct Dedupe {
+    /// Print the duplicates that would be deleted without deleting them.
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// The copy to keep in each group of duplicates.
+    #[arg(short, long)]
+    #[arg(default_value = "newest")]
+    keep: DedupeKeep,
+
+    /// Treat text that only differs in case or whitespace as duplicates.
+    #[arg(short = 'i', long)]
+    normalized: bool,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum DedupeKeep {
+    /// Keep the most recent copy, or the most recent favorite if the content
+    /// was favorited.
+    ///
+    /// Favorites are never deleted in favor of a main ring copy, so the
+    /// favorite is kept even if the main ring has a newer copy.
+    Newest,
+
+    /// Keep every favorited copy and delete only the copies in the main ring.
+    Favorite,
+}
+
 #[derive(Args, Debug)]
 struct Generate {
     /// The number of random entries to generate.
@@ -371,6 +534,10 @@ enum CliError {
     Regex(#[from] regex::Error),
     #[error("internal search error")]
     InternalSearchError,
+    #[error("no backup directory configured")]
+    BackupNotConfigured,
+    #[error("backup failed")]
+    BackupFailed,
 }
 
 #[derive(Error, Debug)]
@@ -379,33 +546,62 @@ enum Wrapper {
     W(String),
 }
 
-fn main() -> error_stack::Result<(), Wrapper> {
+impl CliError {
+    /// Missing entries get their own exit code so scripts can tell them apart
+    /// from other failures. Clap already uses 2 for usage errors.
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            Self::Core(CoreError::IdNotFound(_))
+            | Self::Sdk(ClientError::Core(CoreError::IdNotFound(_))) => ExitCode::from(3),
+            _ => ExitCode::FAILURE,
+        }
+    }
+}
+
+fn main() -> ExitCode {
     #[cfg(not(debug_assertions))]
     error_stack::Report::install_debug_hook::<std::panic::Location>(|_, _| {});
 
-    run().map_err(|e| {
-        let wrapper = Wrapper::W(e.to_string());
-        match e {
-            CliError::Core(e) => e.into_report(wrapper),
-            CliError::Fuc(fuc_engine::Error::Io { error, context }) => Report::new(error)
-                .attach_printable(context)
-                .change_context(wrapper),
-            CliError::Sdk(e) => e.into_report(wrapper),
-            CliError::DatabaseNotFound(db) => Report::new(wrapper)
-                .attach_printable(
-                    "Make sure to run the Ringboard server or fix the XDG_DATA_HOME path.",
-                )
-                .attach_printable(format!("Expected database directory: {:?}", db.display())),
-            CliError::Fuc(e) => Report::new(e).change_context(wrapper),
-            CliError::SerdeJson(e) => Report::new(e).change_context(wrapper),
-            CliError::QuickXml(e) => Report::new(e).change_context(wrapper),
-            CliError::QuickXmlDe(e) => Report::new(e).change_context(wrapper),
-            CliError::Regex(e) => Report::new(e).change_context(wrapper),
-            CliError::InternalSearchError => Report::new(wrapper).attach_printable(
-                "Please report this bug at https://github.com/SUPERCILEX/clipboard-history/issues/new",
-            ),
+    CompleteEnv::with_factory(Cli::command)
+        .bin("ringboard")
+        .complete();
+
+    let Err(e) = run() else {
+        return ExitCode::SUCCESS;
+    };
+    let exit_code = e.exit_code();
+    eprintln!("Error: {:?}", into_report(e));
+    exit_code
+}
+
+fn into_report(e: CliError) -> Report<Wrapper> {
+    let wrapper = Wrapper::W(e.to_string());
+    match e {
+        CliError::Core(e) => e.into_report(wrapper),
+        CliError::Fuc(fuc_engine::Error::Io { error, context }) => Report::new(error)
+            .attach_printable(context)
+            .change_context(wrapper),
+        CliError::Sdk(e) => e.into_report(wrapper),
+        CliError::DatabaseNotFound(db) => Report::new(wrapper)
+            .attach_printable(
+                "Make sure to run the Ringboard server or fix the XDG_DATA_HOME path.",
+            )
+            .attach_printable(format!("Expected database directory: {:?}", db.display())),
+        CliError::Fuc(e) => Report::new(e).change_context(wrapper),
+        CliError::SerdeJson(e) => Report::new(e).change_context(wrapper),
+        CliError::QuickXml(e) => Report::new(e).change_context(wrapper),
+        CliError::QuickXmlDe(e) => Report::new(e).change_context(wrapper),
+        CliError::Regex(e) => Report::new(e).change_context(wrapper),
+        CliError::BackupNotConfigured => Report::new(wrapper).attach_printable(
+            "Set `backup_dir` in the server's settings file and restart the server.",
+        ),
+        CliError::BackupFailed => {
+            Report::new(wrapper).attach_printable("Check the server's logs for details.")
         }
-    })
+        CliError::InternalSearchError => Report::new(wrapper).attach_printable(
+            "Please report this bug at https://github.com/SUPERCILEX/clipboard-history/issues/new",
+        ),
+    }
 }
 
 impl From<IdNotFoundError> for CliError {
@@ -415,15 +611,25 @@ impl From<IdNotFoundError> for CliError {
 }
 
 fn run() -> Result<(), CliError> {
-    let Cli { cmd, help: _ } = Cli::parse();
+    let Cli {
+        cmd,
+        help: _,
+        version: _,
+    } = Cli::parse();
+    set_client_name(env!("CARGO_BIN_NAME"));
 
     let server_addr = {
         let socket_file = socket_file();
         SocketAddrUnix::new(&socket_file)
             .map_io_err(|| format!("Failed to make socket address: {socket_file:?}"))?
     };
+    let Some(cmd) = cmd else {
+        // Clap only lets the subcommand be missing when the version was requested.
+        return version(&server_addr);
+    };
     match cmd {
         Cmd::Get(data) => get(data),
+        Cmd::List(data) => list(data),
         Cmd::Search(data) => search(data),
         Cmd::Add(data) => add(connect_to_server(&server_addr)?, data),
         Cmd::Favorite(data) => move_to_front(
@@ -439,14 +645,128 @@ fn run() -> Result<(), CliError> {
         Cmd::Remove(data) => remove(connect_to_server(&server_addr)?, data),
         Cmd::Wipe => wipe(),
         Cmd::GarbageCollect(data) => garbage_collect(connect_to_server(&server_addr)?, data),
+        Cmd::Dedupe(data) => dedupe(&server_addr, data),
+        Cmd::Backup => backup(&connect_to_server(&server_addr)?),
         Cmd::Migrate(data) => migrate(connect_to_server(&server_addr)?, data),
+        Cmd::Export(data) => export(data),
+        Cmd::Import(data) => import(connect_to_server(&server_addr)?, data),
+        Cmd::Env(data) => env(data),
         Cmd::Debug(Dev::Stats) => stats(),
         Cmd::Debug(Dev::Dump) => dump(),
         Cmd::Debug(Dev::Generate(data)) => generate(connect_to_server(&server_addr)?, data),
         Cmd::Debug(Dev::Fuzz(data)) => fuzz(&server_addr, data),
+        Cmd::Debug(Dev::TrimMemory) => trim_memory(connect_to_server(&server_addr)?),
     }
 }
 
+fn version(server_addr: &SocketAddrUnix) -> Result<(), CliError> {
+    println!("ringboard {}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "client-sdk {} (protocol {}-{})",
+        ringboard_sdk::VERSION,
+        protocol::MIN_VERSION,
+        protocol::VERSION
+    );
+    match negotiate_with_server(server_addr, SocketFlags::empty()) {
+        Ok((server, capabilities)) => {
+            let ServerInfoResponse { version, git_hash } = ServerInfoRequest::response(server)?;
+            println!(
+                "server {version} ({git_hash}, protocol {})",
+                capabilities.version()
+            );
+        }
+        Err(ClientError::VersionMismatch { actual }) => {
+            println!("server incompatible (protocol {actual})");
+        }
+        Err(ClientError::Core(_)) => println!("server offline"),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+fn env(Env { json }: Env) -> Result<(), CliError> {
+    #[derive(Serialize)]
+    struct Environment {
+        data_dir: PathBuf,
+        socket_file: PathBuf,
+        server_pid: Option<i32>,
+        protocol_version: u8,
+    }
+
+    let data_dir = data_dir();
+    let server_pid = read_lock_file_pid(CWD, &data_dir.join("server.lock"))
+        .ok()
+        .flatten()
+        .map(|pid| pid.as_raw_nonzero().get());
+    let env = Environment {
+        data_dir,
+        socket_file: socket_file(),
+        server_pid,
+        protocol_version: protocol::VERSION,
+    };
+
+    if json {
+        serde_json::to_writer(io::stdout().lock(), &env)?;
+        println!();
+    } else {
+        let Environment {
+            data_dir,
+            socket_file,
+            server_pid,
+            protocol_version,
+        } = env;
+        println!("data dir: {}", data_dir.display());
+        println!("socket: {}", socket_file.display());
+        if let Some(pid) = server_pid {
+            println!("server pid: {pid}");
+        } else {
+            println!("server pid: not running");
+        }
+        println!("protocol: {protocol_version}");
+    }
+    Ok(())
+}
+
+/// Offers the entries shown on the first page of the UIs as completions.
+///
+/// This runs inside the user's shell, so failures produce no completions
+/// instead of an error.
+fn entry_id_candidates() -> Vec<CompletionCandidate> {
+    let Ok((database, reader)) = open_db() else {
+        return Vec::new();
+    };
+    database
+        .favorites()
+        .rev()
+        .chain(database.main().rev().take(100))
+        .map(|entry| {
+            let candidate = CompletionCandidate::new(entry.id().to_string());
+            match entry_preview(entry, &reader) {
+                Some(preview) => candidate.help(Some(preview.into())),
+                None => candidate,
+            }
+        })
+        .collect()
+}
+
+fn entry_preview(entry: Entry, reader: &EntryReader) -> Option<String> {
+    const MAX_CHARS: usize = 60;
+
+    let loaded = entry.to_slice(reader).ok()?;
+    let mime_type = loaded.mime_type().ok()?;
+    if !mime_type.is_empty() && !mime_type.starts_with("text/") {
+        return Some(mime_type.to_string());
+    }
+
+    let text = str::from_utf8(&loaded).ok()?;
+    let line = text.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let mut preview = line.chars().take(MAX_CHARS).collect::<String>();
+    if preview.len() < line.len() {
+        preview.push('…');
+    }
+    Some(preview)
+}
+
 fn open_db() -> Result<(DatabaseReader, EntryReader), CliError> {
     let mut database = data_dir();
     if !database
@@ -463,10 +783,58 @@ fn open_db() -> Result<(DatabaseReader, EntryReader), CliError> {
 }
 
 fn get(EntryAction { id }: EntryAction) -> Result<(), CliError> {
-    let (database, mut reader) = open_db()?;
+    let (database, reader) = open_db()?;
     let entry = database.get_raw(id)?;
-    io::copy(&mut *entry.to_file(&mut reader)?, &mut io::stdout().lock())
-        .map_io_err(|| "Failed to write entry to stdout")?;
+    entry.write_to(&reader, &mut io::stdout().lock())?;
+    Ok(())
+}
+
+fn list(
+    List {
+        limit,
+        favorites,
+        json,
+    }: List,
+) -> Result<(), CliError> {
+    #[derive(Serialize)]
+    struct ListedEntry<'a> {
+        id: u64,
+        mime_type: &'a str,
+        preview: Option<String>,
+    }
+
+    let (database, reader) = open_db()?;
+    let ring = if favorites {
+        database.favorites()
+    } else {
+        database.main()
+    };
+    let mut output = io::stdout().lock();
+    for entry in ring.rev().take(limit) {
+        let loaded = entry.to_slice(&reader)?;
+        let mime_type = loaded.mime_type()?;
+        let preview = one_liner(&loaded, None).map(|(preview, _)| preview);
+
+        if json {
+            serde_json::to_writer(
+                &mut output,
+                &ListedEntry {
+                    id: entry.id(),
+                    mime_type: &mime_type,
+                    preview,
+                },
+            )?;
+            writeln!(output)
+        } else {
+            writeln!(
+                output,
+                "{}\t{mime_type}\t{}",
+                entry.id(),
+                preview.unwrap_or_default()
+            )
+        }
+        .map_io_err(|| "Failed to write to stdout.")?;
+    }
     Ok(())
 }
 
@@ -474,30 +842,58 @@ fn search(
     Search {
         regex,
         ignore_case,
+        all_matches,
+        json,
         query,
     }: Search,
 ) -> Result<(), CliError> {
     const PREFIX_CONTEXT: usize = 40;
     const CONTEXT_WINDOW: usize = 100;
 
+    #[derive(Serialize)]
+    struct Match<'a> {
+        id: u64,
+        mime_type: &'a str,
+        matched_in: Option<&'a str>,
+        start: usize,
+        end: usize,
+        snippet: Cow<'a, str>,
+    }
+
     let (mut database, reader) = open_db()?;
     let mut output = io::stdout().lock();
     let mut print_entry = |entry_id,
                            buf: &[u8],
                            mime_type: &str,
+                           matched_in: Option<&str>,
                            start: usize,
                            end: usize|
-     -> Result<(), CoreError> {
-        writeln!(
-            output,
-            "--- ENTRY {entry_id}{} ---",
-            if mime_type.is_empty() {
-                String::new()
-            } else {
-                format!("; {mime_type}")
-            }
-        )
-        .map_io_err(|| "Failed to write to stdout.")?;
+     -> Result<(), CliError> {
+        if json {
+            serde_json::to_writer(
+                &mut output,
+                &Match {
+                    id: entry_id,
+                    mime_type,
+                    matched_in,
+                    start,
+                    end,
+                    snippet: String::from_utf8_lossy(buf),
+                },
+            )?;
+            writeln!(output).map_io_err(|| "Failed to write to stdout.")?;
+            return Ok(());
+        }
+
+        write!(output, "--- ENTRY {entry_id}").map_io_err(|| "Failed to write to stdout.")?;
+        if !mime_type.is_empty() {
+            write!(output, "; {mime_type}").map_io_err(|| "Failed to write to stdout.")?;
+        }
+        if let Some(matched_in) = matched_in {
+            write!(output, "; matched in {matched_in}")
+                .map_io_err(|| "Failed to write to stdout.")?;
+        }
+        writeln!(output, " ---").map_io_err(|| "Failed to write to stdout.")?;
 
         let bold_start = start.min(PREFIX_CONTEXT);
         let (prefix, suffix) = buf.split_at(bold_start);
@@ -522,6 +918,7 @@ fn search(
     };
 
     let reader = Arc::new(reader);
+    let index = Arc::new(EntryIndex::new(&database));
     let (result_stream, threads) = {
         // TODO https://github.com/rust-lang/rust-clippy/issues/13227
         #[allow(clippy::redundant_locals)]
@@ -534,25 +931,30 @@ fn search(
             } else {
                 Query::Plain(query.as_bytes())
             },
+            None,
+            None,
             reader.clone(),
+            index,
         )
     };
-    let mut results = BTreeMap::<BucketAndIndex, (u16, u16)>::new();
+    let result_stream = if all_matches {
+        result_stream.keep_duplicates()
+    } else {
+        result_stream
+    };
+    let mut results = Vec::<(u64, (u16, u16))>::new();
     let mut buf = [0; CONTEXT_WINDOW];
     for result in result_stream {
-        let QueryResult {
-            location,
-            start,
-            end,
-        } = result?;
-        match location {
-            EntryLocation::Bucketed { bucket, index } => {
-                results.insert(
-                    BucketAndIndex::new(bucket, index),
+        let result = result?;
+        let (entry_id, start, end) = (result.id, result.start, result.end);
+        match result.location() {
+            EntryLocation::Bucketed { .. } | EntryLocation::Packed { .. } => {
+                results.push((
+                    entry_id,
                     (u16::try_from(start).unwrap(), u16::try_from(end).unwrap()),
-                );
+                ));
             }
-            EntryLocation::File { entry_id } => {
+            EntryLocation::File { .. } => {
                 let entry = unsafe { database.get(entry_id)? };
                 let file = entry.to_file_raw(&reader)?.unwrap();
 
@@ -581,6 +983,36 @@ fn search(
                     entry_id,
                     &buf[..buf.len() - remaining],
                     &file.mime_type()?,
+                    None,
+                    start,
+                    end,
+                )?;
+            }
+            EntryLocation::ImageText { .. } => {
+                let entry = unsafe { database.get(entry_id)? };
+                let file = entry.to_file_raw(&reader)?.unwrap();
+                let text = file.image_text()?;
+
+                let text = &text[start.saturating_sub(PREFIX_CONTEXT)..];
+                print_entry(
+                    entry_id,
+                    &text[..text.len().min(CONTEXT_WINDOW)],
+                    &file.mime_type()?,
+                    Some("image text"),
+                    start,
+                    end,
+                )?;
+            }
+            EntryLocation::Label { .. } => {
+                let entry = unsafe { database.get(entry_id)? };
+                let file = entry.to_file_raw(&reader)?.unwrap();
+                let label = file.label()?;
+
+                print_entry(
+                    entry_id,
+                    &label.as_bytes()[start.saturating_sub(PREFIX_CONTEXT)..],
+                    &file.mime_type()?,
+                    Some("label"),
                     start,
                     end,
                 )?;
@@ -590,26 +1022,20 @@ fn search(
     for thread in threads {
         thread.join().map_err(|_| CliError::InternalSearchError)?;
     }
-    let mut reader = Arc::into_inner(reader).unwrap();
+    let reader = Arc::into_inner(reader).unwrap();
 
-    for entry in database.favorites().chain(database.main()) {
-        let Kind::Bucket(bucket) = entry.kind() else {
-            continue;
-        };
-        let Some(&(start, end)) = results.get(&BucketAndIndex::new(
-            size_to_bucket(bucket.size()),
-            bucket.index(),
-        )) else {
-            continue;
-        };
+    results.sort_by_key(|&(entry_id, _)| entry_id);
+    for (entry_id, (start, end)) in results {
+        let entry = unsafe { database.get(entry_id)? };
         let (start, end) = (usize::from(start), usize::from(end));
 
-        let bytes = entry.to_slice(&mut reader)?;
+        let bytes = entry.to_slice(&reader)?;
         let prefix_start = start.saturating_sub(PREFIX_CONTEXT);
         print_entry(
-            entry.id(),
+            entry_id,
             &bytes[prefix_start..(prefix_start + CONTEXT_WINDOW).min(bytes.len())],
             &bytes.mime_type()?,
+            None,
             start,
             end,
         )?;
@@ -624,9 +1050,20 @@ fn add(
         data_file,
         target,
         mime_type,
+        json,
     }: Add,
 ) -> Result<(), CliError> {
-    let AddResponse::Success { id } = {
+    #[derive(Serialize)]
+    #[allow(clippy::struct_excessive_bools)]
+    struct Added {
+        id: u64,
+        normalized: bool,
+        coalesced: bool,
+        deduplicated: bool,
+        truncated: bool,
+    }
+
+    let response = {
         let file = if data_file == Path::new("-") {
             None
         } else {
@@ -649,24 +1086,76 @@ fn add(
             file.as_ref().map_or(stdin(), |file| file.as_fd()),
         )?
     };
+    let (id, normalized, coalesced, deduplicated, truncated) = match response {
+        AddResponse::Success {
+            id,
+            normalized,
+            coalesced,
+            deduplicated,
+            truncated,
+        } => (id, normalized, coalesced, deduplicated, truncated),
+        AddResponse::DatabaseFull => return Err(ClientError::DatabaseFull.into()),
+        AddResponse::UnsupportedData => return Err(ClientError::UnsupportedData.into()),
+        AddResponse::Empty => return Err(ClientError::EmptyEntry.into()),
+        AddResponse::TooLarge { max } => return Err(ClientError::EntryTooLarge { max }.into()),
+    };
 
-    println!("Entry added: {id}");
+    if json {
+        serde_json::to_writer(
+            io::stdout().lock(),
+            &Added {
+                id,
+                normalized,
+                coalesced,
+                deduplicated,
+                truncated,
+            },
+        )?;
+        println!();
+        return Ok(());
+    }
+
+    if coalesced {
+        println!("Identical to an entry added moments ago: {id}");
+    } else if deduplicated {
+        println!("Moved identical entry to the front: {id}");
+    } else {
+        println!("Entry added: {id}");
+    }
+    if normalized {
+        println!("Its text was normalized per the server settings.");
+    }
+    if truncated {
+        println!("It was truncated to the server's maximum entry size.");
+    }
 
     Ok(())
 }
 
 fn move_to_front(
     server: OwnedFd,
-    EntryAction { id }: EntryAction,
+    MoveEntry { id, json }: MoveEntry,
     to: Option<RingKind>,
 ) -> Result<(), CliError> {
+    #[derive(Serialize)]
+    struct Moved {
+        id: u64,
+    }
+
     match MoveToFrontRequest::response(server, id, to)? {
+        MoveToFrontResponse::Success { id } if json => {
+            serde_json::to_writer(io::stdout().lock(), &Moved { id })?;
+            println!();
+        }
         MoveToFrontResponse::Success { id } => {
             println!("Entry moved: {id}");
         }
         MoveToFrontResponse::Error(e) => {
             return Err(e.into());
         }
+        MoveToFrontResponse::FavoritesFull => {
+            return Err(ClientError::FavoritesFull.into());
+        }
     }
 
     Ok(())
@@ -764,6 +1253,7 @@ fn garbage_collect(
             unsafe { RemoveRequest::recv(&server, flags) }.and_then(
                 |Response {
                      sequence_number: _,
+                     trace: _,
                      value: RemoveResponse { error },
                  }| { error.map_or_else(|| Ok(()), |e| Err(e.into())) },
             )
@@ -784,9 +1274,133 @@ fn garbage_collect(
         println!("Removed {num_duplicates} duplicate entries.");
     }
 
-    let GarbageCollectResponse { bytes_freed } =
-        GarbageCollectRequest::response(server, max_wasted_bytes)?;
+    let GarbageCollectResponse {
+        bytes_freed,
+        bucket_bytes_freed,
+        packed_bytes_freed,
+    } = GarbageCollectRequest::response(server, max_wasted_bytes)?;
     println!("{bytes_freed} bytes of garbage freed.");
+    for (bucket, bytes_freed) in bucket_bytes_freed.into_iter().enumerate() {
+        if bytes_freed > 0 {
+            println!(
+                "  {bytes_freed} bytes from the {} byte bucket",
+                bucket_to_length(bucket)
+            );
+        }
+    }
+    if packed_bytes_freed > 0 {
+        println!("  {packed_bytes_freed} bytes from the packed log");
+    }
+    Ok(())
+}
+
+fn trim_memory(server: OwnedFd) -> Result<(), CliError> {
+    let TrimMemoryResponse {
+        resident_bytes_before,
+        resident_bytes_after,
+    } = TrimMemoryRequest::response(server)?;
+    println!(
+        "Resident server memory went from {resident_bytes_before} to {resident_bytes_after} bytes."
+    );
+    Ok(())
+}
+
+fn backup(server: &OwnedFd) -> Result<(), CliError> {
+    let failures = StatsRequest::response(server)?.backups.failures;
+    let BackupResponse {
+        outcome,
+        last_backup,
+    } = BackupNowRequest::response(server)?;
+    match outcome {
+        BackupOutcome::Started | BackupOutcome::InProgress => {}
+        BackupOutcome::Unchanged => {
+            println!("Nothing changed since the last backup at {last_backup} (Unix time).");
+            return Ok(());
+        }
+        BackupOutcome::NotConfigured => return Err(CliError::BackupNotConfigured),
+    }
+
+    loop {
+        let backups = StatsRequest::response(server)?.backups;
+        if backups.in_progress {
+            thread::sleep(Duration::from_millis(100));
+        } else if backups.failures == failures {
+            println!("Backed up the database.");
+            return Ok(());
+        } else {
+            return Err(CliError::BackupFailed);
+        }
+    }
+}
+
+fn dedupe(
+    server_addr: &SocketAddrUnix,
+    Dedupe {
+        dry_run,
+        keep,
+        normalized,
+    }: Dedupe,
+) -> Result<(), CliError> {
+    let (database, reader) = open_db()?;
+    let groups = duplicate_detection::find_duplicates(
+        &reader,
+        &database,
+        if normalized {
+            Matching::Normalized
+        } else {
+            Matching::Exact
+        },
+    )?;
+    let server = if dry_run {
+        None
+    } else {
+        Some(connect_to_server(server_addr)?)
+    };
+
+    let recv = |flags| {
+        unsafe { RemoveRequest::recv(server.as_ref().unwrap(), flags) }.and_then(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value: RemoveResponse { error },
+             }| { error.map_or_else(|| Ok(()), |e| Err(e.into())) },
+        )
+    };
+    let mut pending_requests = 0;
+    let mut num_duplicates = 0;
+    for DuplicateGroup { entries } in groups {
+        let (mut kept, mut removed) = (Vec::new(), Vec::new());
+        for (i, entry) in entries.iter().enumerate() {
+            // Groups list favorites first, so the first entry is always safe to keep.
+            let keep_entry = i == 0
+                || matches!(keep, DedupeKeep::Favorite) && entry.ring() == RingKind::Favorites;
+            if keep_entry {
+                kept.push(entry.id());
+            } else {
+                removed.push(entry.id());
+            }
+        }
+        num_duplicates += removed.len();
+
+        if let Some(server) = &server {
+            for id in removed {
+                pipeline_request(
+                    |flags| RemoveRequest::send(server, id, flags),
+                    recv,
+                    &mut pending_requests,
+                )?;
+            }
+        } else {
+            println!("Keeping {kept:?}, removing {removed:?}.");
+        }
+    }
+
+    if server.is_some() {
+        drain_requests(recv, 0, &mut pending_requests)?;
+        println!("Removed {num_duplicates} duplicate entries.");
+    } else {
+        println!("Would remove {num_duplicates} duplicate entries.");
+    }
     Ok(())
 }
 
@@ -922,6 +1536,9 @@ fn migrate_from_gch(server: OwnedFd, database: Option<PathBuf>) -> Result<(), Cl
                     MoveToFrontResponse::Error(e) => {
                         api_error!(e);
                     }
+                    MoveToFrontResponse::FavoritesFull => {
+                        return Err(ClientError::FavoritesFull.into());
+                    }
                 }
                 i += 4;
             }
@@ -1183,6 +1800,8 @@ fn stats() -> Result<(), CliError> {
         len: u32,
         bucketed_entry_count: u32,
         file_entry_count: u32,
+        packed_entry_count: u32,
+        uninitialized_entry_count: u32,
         num_duplicates: u32,
         min_entry_size: u64,
         max_entry_size: u64,
@@ -1210,6 +1829,7 @@ fn stats() -> Result<(), CliError> {
         rings: HashMap<RingKind, RingStats, BuildHasherDefault<FxHasher>>,
         buckets: [BucketStats; NUM_BUCKETS],
         direct_files: DirectFileStats,
+        packed_bytes: u64,
     }
 
     impl Display for Stats {
@@ -1221,6 +1841,7 @@ fn stats() -> Result<(), CliError> {
                     .field("rings", &self.rings)
                     .field("buckets", &self.buckets)
                     .field("direct_files", &self.direct_files)
+                    .field("packed_bytes", &self.packed_bytes)
                     .finish()
             });
             s.field_with("computed", |f| {
@@ -1231,9 +1852,11 @@ fn stats() -> Result<(), CliError> {
                             kind,
                             &RingStats {
                                 capacity: _,
-                                len,
+                                len: _,
                                 bucketed_entry_count,
                                 file_entry_count,
+                                packed_entry_count,
+                                uninitialized_entry_count: _,
                                 num_duplicates: _,
                                 min_entry_size: _,
                                 max_entry_size: _,
@@ -1242,14 +1865,13 @@ fn stats() -> Result<(), CliError> {
                         ) in &self.rings
                         {
                             rings.key(kind).value_with(|f| {
-                                let num_entries = bucketed_entry_count + file_entry_count;
+                                let num_entries =
+                                    bucketed_entry_count + file_entry_count + packed_entry_count;
                                 let mut s = f.debug_struct("Ring");
-                                s.field("num_entries", &num_entries)
-                                    .field("uninitialized_entry_count", &(len - num_entries))
-                                    .field(
-                                        "mean_entry_size",
-                                        &(owned_bytes as f64 / f64::from(num_entries)),
-                                    );
+                                s.field("num_entries", &num_entries).field(
+                                    "mean_entry_size",
+                                    &(owned_bytes as f64 / f64::from(num_entries)),
+                                );
                                 s.finish()
                             });
                         }
@@ -1310,6 +1932,7 @@ fn stats() -> Result<(), CliError> {
                 allocated_bytes,
                 mime_types,
             },
+        packed_bytes,
     } = &mut stats;
 
     let (database, mut reader) = open_db()?;
@@ -1339,6 +1962,8 @@ fn stats() -> Result<(), CliError> {
             len,
             bucketed_entry_count,
             file_entry_count,
+            packed_entry_count,
+            uninitialized_entry_count,
             num_duplicates,
             min_entry_size,
             max_entry_size,
@@ -1346,6 +1971,7 @@ fn stats() -> Result<(), CliError> {
         } = &mut ring_stats;
         *capacity = ring_reader.ring().capacity();
         *len = ring_reader.ring().len();
+        *uninitialized_entry_count = ring_reader.ring().count_by_state().uninitialized;
         *min_entry_size = u64::MAX;
         let kind = ring_reader.kind();
 
@@ -1354,6 +1980,14 @@ fn stats() -> Result<(), CliError> {
             let duplicate;
 
             match entry.kind() {
+                Kind::Bucket(packed) if packed.is_packed() => {
+                    *packed_entry_count += 1;
+
+                    entry_size = u64::from(packed.size());
+                    *packed_bytes += entry_size;
+
+                    duplicate = duplicates.add_entry(&entry, &database, &mut reader)?;
+                }
                 Kind::Bucket(bucket) => {
                     *bucketed_entry_count += 1;
 
@@ -1373,7 +2007,7 @@ fn stats() -> Result<(), CliError> {
                 Kind::File => {
                     *file_entry_count += 1;
 
-                    let file = entry.to_file(&mut reader)?;
+                    let file = entry.to_file(&reader)?;
                     let stats = statx(
                         &*file,
                         c"",
@@ -1432,11 +2066,11 @@ enum ExportData<'a> {
 }
 
 fn dump() -> Result<(), CliError> {
-    let (database, mut reader) = open_db()?;
+    let (database, reader) = open_db()?;
     let mut seq = serde_json::Serializer::new(io::stdout().lock());
     let mut seq = seq.serialize_seq(None)?;
     for entry in database.favorites().chain(database.main()) {
-        let loaded = entry.to_slice(&mut reader)?;
+        let loaded = entry.to_slice(&reader)?;
         let mime_type = loaded.mime_type()?;
         seq.serialize_element(&ExportEntry {
             id: entry.id(),
@@ -1452,6 +2086,54 @@ fn dump() -> Result<(), CliError> {
     Ok(())
 }
 
+fn export(Export { file }: Export) -> Result<(), CliError> {
+    let (database, reader) = open_db()?;
+    if file == Path::new("-") {
+        export::export(&database, &reader, BufWriter::new(io::stdout().lock()))?;
+    } else {
+        let out = File::create(&file).map_io_err(|| format!("Failed to create file: {file:?}"))?;
+        export::export(&database, &reader, BufWriter::new(out))?;
+    }
+    Ok(())
+}
+
+fn import(server: OwnedFd, Import { file }: Import) -> Result<(), CliError> {
+    let input: Box<dyn BufRead> = if file == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(
+            File::open(&file).map_io_err(|| format!("Failed to open file: {file:?}"))?,
+        ))
+    };
+
+    let mut pending_adds = 0;
+    let mut num_imported = 0;
+    for entry in export::read(input)? {
+        let ExportedEntry {
+            id: _,
+            ring,
+            mime_type,
+            data,
+        } = entry?;
+        let data_file = File::from(
+            memfd_create(c"ringboard_import", MemfdFlags::empty())
+                .map_io_err(|| "Failed to create data entry file.")?,
+        );
+        data_file
+            .write_all_at(&data, 0)
+            .map_io_err(|| "Failed to copy data to entry file.")?;
+
+        unsafe {
+            pipeline_add_request(&server, data_file, ring, mime_type, None, &mut pending_adds)?;
+        }
+        num_imported += 1;
+    }
+
+    unsafe { drain_add_requests(server, None, &mut pending_adds) }?;
+    println!("Imported {num_imported} entries.");
+    Ok(())
+}
+
 fn migrate_from_ringboard_export(server: OwnedFd, dump_file: PathBuf) -> Result<(), CliError> {
     fn generate_entry_file(data: &[u8]) -> Result<File, CliError> {
         let file = File::from(
@@ -1465,6 +2147,14 @@ fn migrate_from_ringboard_export(server: OwnedFd, dump_file: PathBuf) -> Result<
         Ok(file)
     }
 
+    let (mut database, mut reader) = open_db()?;
+    let mut duplicates = DuplicateDetector::default();
+    let mut duplicates = duplicates.begin_bulk(&mut database, &mut reader)?;
+    let mut num_duplicates = 0;
+    // Entries sent to the server whose IDs haven't come back yet.
+    let mut in_flight = VecDeque::<Vec<u8>>::new();
+    let mut added_ids = Vec::new();
+
     let mut pending_adds = 0;
     let mut process = |ExportEntry {
                            id,
@@ -1472,13 +2162,32 @@ fn migrate_from_ringboard_export(server: OwnedFd, dump_file: PathBuf) -> Result<
                            mime_type,
                        }|
      -> Result<(), CliError> {
-        let data = generate_entry_file(match &data {
+        let data = match &data {
             ExportData::Human(str) => str.as_bytes(),
             ExportData::Bytes(bytes) => bytes,
-        })?;
+        };
+        if in_flight.iter().any(|pending| **pending == *data) || duplicates.check(data)?.is_some() {
+            num_duplicates += 1;
+            return Ok(());
+        }
 
         let (to, _) = decompose_id(id).unwrap_or_default();
-        unsafe { pipeline_add_request(&server, data, to, mime_type, None, &mut pending_adds) }
+        unsafe {
+            pipeline_add_request(
+                &server,
+                generate_entry_file(data)?,
+                to,
+                mime_type,
+                Some(&mut added_ids),
+                &mut pending_adds,
+            )?;
+        }
+        in_flight.push_back(data.to_vec());
+        #[allow(clippy::iter_with_drain)] // https://github.com/rust-lang/rust-clippy/issues/8539
+        for id in added_ids.drain(..) {
+            duplicates.record(&in_flight.pop_front().unwrap(), id)?;
+        }
+        Ok(())
     };
 
     if dump_file == Path::new("-") {
@@ -1500,7 +2209,9 @@ fn migrate_from_ringboard_export(server: OwnedFd, dump_file: PathBuf) -> Result<
         }
     };
 
-    unsafe { drain_add_requests(server, None, &mut pending_adds) }
+    unsafe { drain_add_requests(server, None, &mut pending_adds) }?;
+    println!("Skipped {num_duplicates} duplicate entries.");
+    Ok(())
 }
 
 fn generate(
@@ -1658,6 +2369,7 @@ fn fuzz(
             PendingOp::Add { .. } => {
                 let Response {
                     sequence_number,
+                    trace: _,
                     value,
                 } = unsafe { AddRequest::recv(server, flags) }?;
                 let PendingOp::Add { data } = pending.pop_front().unwrap() else {
@@ -1668,6 +2380,7 @@ fn fuzz(
             PendingOp::Move { .. } => {
                 let Response {
                     sequence_number,
+                    trace: _,
                     value,
                 } = unsafe { MoveToFrontRequest::recv(server, flags) }?;
                 let PendingOp::Move { id: move_id } = pending.pop_front().unwrap() else {
@@ -1678,6 +2391,7 @@ fn fuzz(
             PendingOp::Swap { .. } => {
                 let Response {
                     sequence_number,
+                    trace: _,
                     value,
                 } = unsafe { SwapRequest::recv(server, flags) }?;
                 let PendingOp::Swap { id1, id2 } = pending.pop_front().unwrap() else {
@@ -1688,6 +2402,7 @@ fn fuzz(
             PendingOp::Remove { .. } => {
                 let Response {
                     sequence_number,
+                    trace: _,
                     value,
                 } = unsafe { RemoveRequest::recv(server, flags) }?;
                 let PendingOp::Remove { id } = pending.pop_front().unwrap() else {
@@ -1698,6 +2413,7 @@ fn fuzz(
             PendingOp::Gc => {
                 let Response {
                     sequence_number,
+                    trace: _,
                     value,
                 } = unsafe { GarbageCollectRequest::recv(server, flags) }?;
                 let PendingOp::Gc = pending.pop_front().unwrap() else {
@@ -1718,10 +2434,26 @@ fn fuzz(
             match kind {
                 ResponseKind::Add {
                     data: NoDebug(data),
-                    value: AddResponse::Success { id },
+                    value: AddResponse::Success { id, .. },
                 } => {
                     database.insert(id, data);
                 }
+                ResponseKind::Add {
+                    data: _,
+                    value: AddResponse::DatabaseFull,
+                } => return Err(ClientError::DatabaseFull),
+                ResponseKind::Add {
+                    data: _,
+                    value: AddResponse::UnsupportedData,
+                } => return Err(ClientError::UnsupportedData),
+                ResponseKind::Add {
+                    data: _,
+                    value: AddResponse::Empty,
+                } => return Err(ClientError::EmptyEntry),
+                ResponseKind::Add {
+                    data: _,
+                    value: AddResponse::TooLarge { max },
+                } => return Err(ClientError::EntryTooLarge { max }),
                 ResponseKind::Move { move_id, value } => match value {
                     MoveToFrontResponse::Success { id } => {
                         let file = database.remove(&move_id).unwrap();
@@ -1730,6 +2462,9 @@ fn fuzz(
                     MoveToFrontResponse::Error(_) => {
                         assert!(!database.contains_key(&move_id));
                     }
+                    MoveToFrontResponse::FavoritesFull => {
+                        assert!(database.contains_key(&move_id));
+                    }
                 },
                 ResponseKind::Swap { id1, id2, value } => match value {
                     SwapResponse {
@@ -1764,7 +2499,11 @@ fn fuzz(
                         assert!(!database.contains_key(&id));
                     }
                 },
-                ResponseKind::Gc(GarbageCollectResponse { bytes_freed: _ }) => {}
+                ResponseKind::Gc(GarbageCollectResponse {
+                    bytes_freed: _,
+                    bucket_bytes_freed: _,
+                    packed_bytes_freed: _,
+                }) => {}
             }
         }
 
@@ -1785,7 +2524,7 @@ fn fuzz(
     let mut sequence_num = 1;
     let mut linearizable_ops = VecDeque::new();
 
-    let (mut database, mut reader) = open_db()?;
+    let (mut database, reader) = open_db()?;
     let mut out = io::stdout().lock();
     loop {
         match distr.sample(&mut rng) {
@@ -1973,7 +2712,7 @@ fn fuzz(
 
                 for (&id, a) in &data {
                     let entry = unsafe { database.get(id) }?;
-                    let b = &**entry.to_slice(&mut reader)?;
+                    let b = &**entry.to_slice(&reader)?;
 
                     assert_eq!(**a, *b, "{entry:?}");
                 }
@@ -2043,14 +2782,25 @@ fn pipelined_add_recv<'a>(
     mut translation: Option<&'a mut Vec<u64>>,
 ) -> impl FnMut(RecvFlags) -> Result<(), ClientError> + 'a {
     move |flags| {
-        unsafe { AddRequest::recv(&server, flags) }.map(
+        unsafe { AddRequest::recv(&server, flags) }.and_then(
             |Response {
                  sequence_number: _,
-                 value: AddResponse::Success { id },
+                 trace: _,
+                 value,
              }| {
+                let id = match value {
+                    AddResponse::Success { id, .. } => id,
+                    AddResponse::DatabaseFull => return Err(ClientError::DatabaseFull),
+                    AddResponse::UnsupportedData => return Err(ClientError::UnsupportedData),
+                    AddResponse::Empty => return Err(ClientError::EmptyEntry),
+                    AddResponse::TooLarge { max } => {
+                        return Err(ClientError::EntryTooLarge { max });
+                    }
+                };
                 if let Some(translation) = translation.as_deref_mut() {
                     translation.push(id);
                 }
+                Ok(())
             },
         )
     }
@@ -2081,8 +2831,6 @@ unsafe fn drain_add_requests(
 
 #[cfg(test)]
 mod cli_tests {
-    use clap::CommandFactory;
-
     use super::*;
 
     #[test]
@@ -0,0 +1,71 @@
+//! Times loading the newest page of entries from a cold cache one by one, as
+//! clients show them, with and without prefetching the page's buckets first.
+//!
+//! Run from this crate with `cargo bench --features testing --bench prefetch`.
+
+use std::{
+    env, fs,
+    fs::File,
+    hint::black_box,
+    path::Path,
+    process,
+    time::{Duration, Instant},
+};
+
+use clipboard_history_client_sdk::{testing::FixtureDatabase, DatabaseReader, EntryReader};
+use rustix::fs::{fadvise, Advice};
+
+/// About a year's worth of copying.
+const ENTRIES: u32 = 100_000;
+/// The first page clients load.
+const PAGE: usize = 100;
+const RUNS: usize = 25;
+
+fn main() {
+    let fixture = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-prefetch-bench-{}", process::id())),
+    );
+    fixture.fill_main(ENTRIES);
+
+    for prefetch in [false, true] {
+        let mut times = Vec::with_capacity(RUNS);
+        for _ in 0..RUNS {
+            evict(fixture.path());
+            let mut dir = fixture.path().to_path_buf();
+            let database = DatabaseReader::open(&mut dir).unwrap();
+            let reader = EntryReader::open(&mut dir).unwrap();
+
+            let start = Instant::now();
+            let page = database.main().rev().take(PAGE).collect::<Vec<_>>();
+            if prefetch {
+                reader.prefetch(page.iter().copied());
+            }
+            for entry in page {
+                black_box(entry.to_slice(&reader).unwrap().first().copied());
+            }
+            times.push(start.elapsed());
+        }
+        println!(
+            "{}: {PAGE} entries in {:?} (median of {RUNS})",
+            if prefetch { "prefetched" } else { "one by one" },
+            median(&mut times),
+        );
+    }
+}
+
+/// Drops the database's files from the page cache, as after a reboot.
+fn evict(dir: &Path) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            evict(&path);
+        } else {
+            fadvise(File::open(path).unwrap(), 0, 0, Advice::DontNeed).unwrap();
+        }
+    }
+}
+
+fn median(times: &mut [Duration]) -> Duration {
+    times.sort_unstable();
+    times[times.len() / 2]
+}
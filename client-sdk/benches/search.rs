@@ -0,0 +1,72 @@
+//! Times regex searches through a database of short text entries, from
+//! patterns most entries can be ruled out for cheaply to ones every entry has
+//! to be run through the regex engine for. Patterns match few entries so
+//! scanning dominates.
+//!
+//! Run from this crate with `cargo bench --features testing --bench search`.
+
+use std::{
+    env, process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clipboard_history_client_sdk::{
+    search::{search, EntryIndex, Query},
+    testing::FixtureDatabase,
+    DatabaseReader, EntryReader,
+};
+use regex::bytes::Regex;
+
+/// About a year's worth of copying.
+const ENTRIES: u32 = 100_000;
+const RUNS: usize = 25;
+
+fn main() {
+    let fixture = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-search-bench-{}", process::id())),
+    );
+    fixture.fill_main(ENTRIES);
+    let mut dir = fixture.path().to_path_buf();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = Arc::new(EntryReader::open(&mut dir).unwrap());
+    let index = Arc::new(EntryIndex::new(&database));
+
+    for pattern in [
+        // A literal no entry contains.
+        "hello[0-9]+",
+        // Only the longest entries can match.
+        "[a-z]{199}",
+        // Nothing to go on but the regex itself.
+        "(?i)xyz",
+        r"\bq[a-z]{8}z\b",
+    ] {
+        let regex = Regex::new(pattern).unwrap();
+        let mut times = Vec::with_capacity(RUNS);
+        let mut matches = 0;
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            let (results, threads) = search(
+                Query::Regex(regex.clone()),
+                None,
+                None,
+                reader.clone(),
+                index.clone(),
+            );
+            matches = results.map(Result::unwrap).count();
+            times.push(start.elapsed());
+            for thread in threads {
+                thread.join().unwrap();
+            }
+        }
+        println!(
+            "{pattern}: {matches} matches in {:?} (median of {RUNS})",
+            median(&mut times)
+        );
+    }
+}
+
+fn median(times: &mut [Duration]) -> Duration {
+    times.sort_unstable();
+    times[times.len() / 2]
+}
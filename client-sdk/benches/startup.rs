@@ -0,0 +1,95 @@
+//! Times how long a client takes to open the database and read its first page
+//! of entries, both with the database in the page cache and evicted from it.
+//!
+//! Run from this crate with `cargo bench --features testing --bench startup`.
+
+use std::{
+    env, fs,
+    fs::File,
+    hint::black_box,
+    path::Path,
+    process,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use clipboard_history_client_sdk::{
+    testing::{FixtureDatabase, MockDatabase},
+    ui_actor::{Controller, LocalSource, Message},
+};
+use rustix::fs::{fadvise, Advice};
+
+/// About a year's worth of copying.
+const ENTRIES: u32 = 100_000;
+const RUNS: usize = 25;
+
+fn main() {
+    let home = env::temp_dir().join(format!("ringboard-startup-bench-{}", process::id()));
+    let fixture = FixtureDatabase::create(home.join("clipboard-history"));
+    fixture.fill_main(ENTRIES);
+    // Clients open the database in the usual place.
+    env::set_var("XDG_DATA_HOME", &home);
+
+    for cached in [false, true] {
+        let mut connected = Vec::with_capacity(RUNS);
+        let mut first_page = Vec::with_capacity(RUNS);
+        for _ in 0..RUNS {
+            if !cached {
+                evict(fixture.path());
+            }
+            let (c, f) = startup();
+            connected.push(c);
+            first_page.push(f);
+        }
+        println!(
+            "{}: connected in {:?}, first page in {:?} (median of {RUNS})",
+            if cached { "cached" } else { "evicted" },
+            median(&mut connected),
+            median(&mut first_page),
+        );
+    }
+
+    drop(fixture);
+    let _ = fs::remove_dir_all(home);
+}
+
+/// Runs a controller until it has sent its first page, returning when it
+/// connected and when the page arrived.
+fn startup() -> (Duration, Duration) {
+    let start = Instant::now();
+    let mut connected = None;
+    let mut first_page = None;
+    let source = LocalSource::open().unwrap();
+    // No commands beyond the first page.
+    let (_, commands) = mpsc::channel();
+    Controller::new(source, MockDatabase::default()).run(&commands, |message| {
+        match message {
+            Message::Connected => connected = Some(start.elapsed()),
+            Message::LoadedFirstPage { entries, .. } => {
+                black_box(entries);
+                first_page = Some(start.elapsed());
+            }
+            Message::Error(e) => panic!("Failed to load the first page: {e:?}"),
+            _ => {}
+        }
+        Ok::<_, ()>(())
+    });
+    (connected.unwrap(), first_page.unwrap())
+}
+
+/// Drops the database's files from the page cache, as after a reboot.
+fn evict(dir: &Path) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            evict(&path);
+        } else {
+            fadvise(File::open(path).unwrap(), 0, 0, Advice::DontNeed).unwrap();
+        }
+    }
+}
+
+fn median(times: &mut [Duration]) -> Duration {
+    times.sort_unstable();
+    times[times.len() / 2]
+}
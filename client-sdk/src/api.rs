@@ -1,30 +1,53 @@
 use std::{
-    any::TypeId,
+    cmp::min,
+    env,
     fs::File,
     io,
-    io::{IoSlice, IoSliceMut, Seek, SeekFrom},
-    mem::ManuallyDrop,
-    os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd},
+    io::{ErrorKind, IoSlice, IoSliceMut, Seek, SeekFrom},
+    mem::{offset_of, ManuallyDrop},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    path::{Path, PathBuf},
+    process,
+    process::Stdio,
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
 };
 
+use arrayvec::{ArrayString, ArrayVec};
 use ringboard_core::{
+    origins::MAX_NAME_LEN,
     protocol,
     protocol::{
-        AddResponse, GarbageCollectResponse, MimeType, MoveToFrontResponse, RemoveResponse,
-        Request, Response, RingKind, SwapResponse,
+        AddResponse, BackupResponse, EmptyTrashResponse, GarbageCollectResponse,
+        GenerationResponse, Label, ListResponse, ListTagsResponse, MimeType, MoveToFrontResponse,
+        ReadOutcome, ReadResponse, RemoveResponse, Request, RequestFailed, Response,
+        RestoreResponse, RingKind, SearchMatch, SearchQuery, SearchResponse, SearchStatus,
+        ServerInfoResponse, SetClipboardResponse, SetLabelResponse, StatsResponse, SwapResponse,
+        Tag, TagResponse, TraceId, TrimMemoryResponse, UnknownRequest, MAX_BATCH_LEN,
     },
     AsBytes, IoErr,
 };
 use rustix::{
     fs::{openat, statx, AtFlags, FileType, Mode, OFlags, StatxFlags, CWD},
     net::{
-        connect_unix, recvmsg, sendmsg, socket_with, AddressFamily, RecvAncillaryBuffer, RecvFlags,
-        SendAncillaryBuffer, SendAncillaryMessage, SendFlags, SocketAddrUnix, SocketFlags,
-        SocketType,
+        connect_unix, recvmsg, sendmsg, socket_with, AddressFamily, RecvAncillaryBuffer,
+        RecvAncillaryMessage, RecvFlags, SendAncillaryBuffer, SendAncillaryMessage, SendFlags,
+        SocketAddrUnix, SocketFlags, SocketType,
     },
+    process::setsid,
+};
+
+use crate::{
+    ring_reader::{decode_direct_file, xattr_mime_type},
+    ClientError,
 };
 
-use crate::ClientError;
+#[cfg(feature = "tokio")]
+pub mod r#async;
 
 macro_rules! response {
     ($t:ty) => {
@@ -38,15 +61,30 @@ macro_rules! response {
             server: Server,
             flags: RecvFlags,
         ) -> Result<Response<$t>, ClientError> {
-            if TypeId::of::<$t>() == TypeId::of::<VersionResponse>() {
-                response::<$t, { size_of::<$t>() }>(&server, flags)
-            } else {
-                response::<$t, { size_of::<Response<$t>>() }>(&server, flags)
-            }
+            let ancillary = &mut RecvAncillaryBuffer::default();
+            response::<$t, { size_of::<Response<$t>>() }>(&server, flags, ancillary)
         }
     };
 }
 
+static CLIENT_NAME: OnceLock<ArrayString<MAX_NAME_LEN>> = OnceLock::new();
+
+/// Names this process in every connection it makes from now on.
+///
+/// The server records the name as the origin of the entries it adds. Names are
+/// truncated to [`MAX_NAME_LEN`] bytes and only the first call has an effect.
+///
+/// Unnamed clients are named after their process by the server.
+pub fn set_client_name(name: &str) {
+    let mut truncated = ArrayString::new();
+    for c in name.chars() {
+        if truncated.try_push(c).is_err() {
+            break;
+        }
+    }
+    let _ = CLIENT_NAME.set(truncated);
+}
+
 pub fn connect_to_server(addr: &SocketAddrUnix) -> Result<OwnedFd, ClientError> {
     connect_to_server_with(addr, SocketFlags::empty())
 }
@@ -55,37 +93,247 @@ pub fn connect_to_server_with(
     addr: &SocketAddrUnix,
     flags: SocketFlags,
 ) -> Result<OwnedFd, ClientError> {
-    let socket = socket_with(AddressFamily::UNIX, SocketType::SEQPACKET, flags, None)
-        .map_io_err(|| format!("Failed to create socket: {addr:?}"))?;
-    connect_unix(&socket, addr).map_io_err(|| format!("Failed to connect to server: {addr:?}"))?;
+    negotiate_with_server(addr, flags).map(|(socket, _)| socket)
+}
 
-    {
-        sendmsg(
-            &socket,
-            &[IoSlice::new(&[protocol::VERSION])],
-            &mut SendAncillaryBuffer::default(),
-            SendFlags::empty(),
-        )
-        .map_io_err(|| format!("Failed to send version to {addr:?}."))?;
+/// The requests a server understands, learned while connecting to it.
+#[derive(Copy, Clone, Debug)]
+pub struct ServerCapabilities {
+    version: u8,
+    /// Missing for servers that predate negotiation.
+    request_kinds: Option<u8>,
+}
 
-        let Response {
-            sequence_number: _,
-            value: VersionResponse(version),
-        } = unsafe {
-            response!(VersionResponse);
-            recv(&socket, RecvFlags::empty())
-        }?;
-        if version != protocol::VERSION {
-            return Err(ClientError::VersionMismatch { actual: version });
-        }
+impl ServerCapabilities {
+    /// The newest protocol version both this client and the server speak,
+    /// which requests on the connection are read as.
+    #[must_use]
+    pub const fn version(&self) -> u8 {
+        self.version
     }
 
-    Ok(socket)
+    /// Whether the server knows this kind of request and everything in it.
+    /// Servers that predate negotiation are assumed to know every request.
+    #[must_use]
+    pub fn supports(&self, request: &Request) -> bool {
+        let known = self
+            .request_kinds
+            .is_none_or(|kinds| request.tag() < u32::from(kinds));
+        // Older servers would add the entry as copied now.
+        let dated_add = matches!(request, Request::Add { copied_at: 1.., .. });
+        // And keep sensitive data forever.
+        let sensitive_add = matches!(
+            request,
+            Request::Add {
+                sensitive: true,
+                ..
+            }
+        );
+        known && (self.version >= 2 || !dated_add) && (self.version >= 3 || !sensitive_add)
+    }
 }
 
-#[repr(transparent)]
+/// What a client says when connecting.
 #[derive(Copy, Clone, Debug)]
-struct VersionResponse(u8);
+enum Hello {
+    /// Every protocol version this client speaks.
+    Range,
+    /// Only this version, for servers that predate version ranges.
+    Legacy(u8),
+}
+
+/// How the server answered a [`Hello`].
+#[derive(Debug)]
+enum Handshake {
+    Accepted(ServerCapabilities),
+    /// A server that predates version ranges turned down the range but speaks
+    /// this version, so connecting again with [`Hello::Legacy`] works.
+    Retry(u8),
+}
+
+/// Connects to the server like [`connect_to_server_with`] while also asking
+/// which requests it understands so that features it's too old for can be
+/// turned off instead of failing.
+///
+/// Fails with [`ClientError::VersionMismatch`] only if the server speaks none
+/// of the protocol versions this client does.
+pub fn negotiate_with_server(
+    addr: &SocketAddrUnix,
+    flags: SocketFlags,
+) -> Result<(OwnedFd, ServerCapabilities), ClientError> {
+    let mut hello = Hello::Range;
+    loop {
+        let socket = socket_with(AddressFamily::UNIX, SocketType::SEQPACKET, flags, None)
+            .map_io_err(|| format!("Failed to create socket: {addr:?}"))?;
+        connect_unix(&socket, addr)
+            .map_io_err(|| format!("Failed to connect to server: {addr:?}"))?;
+
+        send_version(&socket, addr, hello, SendFlags::empty())?;
+        match recv_version(&socket, hello, RecvFlags::empty())? {
+            Handshake::Accepted(capabilities) => return Ok((socket, capabilities)),
+            Handshake::Retry(version) => hello = Hello::Legacy(version),
+        }
+    }
+}
+
+/// How long [`connect_to_server_with_startup`] waits for a server it started
+/// to begin listening.
+const SERVER_STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connects to the server like [`negotiate_with_server`], starting it first if
+/// it isn't running.
+///
+/// The server started is `server` if given, or else the `ringboard-server`
+/// next to this program if there is one or the one on `$PATH`. It runs in its
+/// own session so it outlives this process. Clients racing to start the server
+/// end up connected to the same one since only one server can hold the
+/// database lock, the others exiting right away.
+pub fn connect_to_server_with_startup(
+    addr: &SocketAddrUnix,
+    flags: SocketFlags,
+    server: Option<&Path>,
+) -> Result<(OwnedFd, ServerCapabilities), ClientError> {
+    connect_or_start(addr, flags, || start_server(server))
+}
+
+fn connect_or_start(
+    addr: &SocketAddrUnix,
+    flags: SocketFlags,
+    start: impl FnOnce() -> Result<(), ClientError>,
+) -> Result<(OwnedFd, ServerCapabilities), ClientError> {
+    match negotiate_with_server(addr, flags) {
+        Err(e) if is_not_listening(&e) => {}
+        r => return r,
+    }
+    start()?;
+
+    let deadline = Instant::now() + SERVER_STARTUP_TIMEOUT;
+    let mut delay = Duration::from_millis(5);
+    loop {
+        thread::sleep(delay);
+        match negotiate_with_server(addr, flags) {
+            Err(e) if is_not_listening(&e) && Instant::now() < deadline => {
+                delay = min(delay * 2, Duration::from_millis(250));
+            }
+            r => return r,
+        }
+    }
+}
+
+/// Whether connecting failed because no server is listening: either the
+/// socket doesn't exist yet or it was left behind by a server that's gone.
+fn is_not_listening(e: &ClientError) -> bool {
+    matches!(
+        e,
+        ClientError::Core(ringboard_core::Error::Io { error, .. })
+            if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused)
+    )
+}
+
+fn start_server(server: Option<&Path>) -> Result<(), ClientError> {
+    let sibling = env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("ringboard-server")))
+        .filter(|path| path.is_file());
+    let program = server
+        .map(Path::to_path_buf)
+        .or(sibling)
+        .unwrap_or_else(|| PathBuf::from("ringboard-server"));
+
+    let mut command = process::Command::new(&program);
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: setsid is async-signal-safe.
+    unsafe {
+        command.pre_exec(|| setsid().map(drop).map_err(io::Error::from));
+    }
+    let mut child = command
+        .spawn()
+        .map_io_err(|| format!("Failed to start server: {program:?}"))?;
+    // The server may well exit before this process does, e.g. after losing a
+    // race to start, so don't leave a zombie behind.
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+fn send_version(
+    socket: impl AsFd,
+    addr: &SocketAddrUnix,
+    hello: Hello,
+    flags: SendFlags,
+) -> Result<(), ClientError> {
+    let (version, len) = match hello {
+        Hello::Range => (protocol::VERSION, 3),
+        Hello::Legacy(version) => (version, 2),
+    };
+    let header = [
+        version,
+        u8::try_from(Request::KINDS).unwrap(),
+        protocol::MIN_VERSION,
+    ];
+    sendmsg(
+        socket,
+        &[
+            IoSlice::new(&header[..len]),
+            IoSlice::new(CLIENT_NAME.get().map_or(&[], |name| name.as_bytes())),
+        ],
+        &mut SendAncillaryBuffer::default(),
+        flags,
+    )
+    .map_io_err(|| format!("Failed to send version to {addr:?}."))?;
+    Ok(())
+}
+
+fn recv_version(
+    socket: impl AsFd,
+    hello: Hello,
+    flags: RecvFlags,
+) -> Result<Handshake, ClientError> {
+    let mut buf = [0u8; 3];
+    let result = recvmsg(
+        socket,
+        &mut [IoSliceMut::new(buf.as_mut_slice())],
+        &mut RecvAncillaryBuffer::default(),
+        RecvFlags::TRUNC | flags,
+    )
+    .map_io_err(|| "Failed to receive VersionResponse.")?;
+    let version = buf[0];
+    let spoken = protocol::MIN_VERSION..=protocol::VERSION;
+    // Servers always lead with a version, so a mismatch is reported as such even
+    // if the rest of the response is in a format this client doesn't know.
+    let capabilities = match (hello, result.bytes) {
+        (_, 1) if version == protocol::RATE_LIMITED => return Err(ClientError::RateLimited),
+        (_, 1) if version == protocol::PERMISSION_DENIED => {
+            return Err(ClientError::PermissionDenied);
+        }
+        (Hello::Range, 3..) if spoken.contains(&version) => ServerCapabilities {
+            version,
+            request_kinds: Some(buf[1]),
+        },
+        (Hello::Range, 1 | 2)
+            if spoken.contains(&version) && version < protocol::RANGED_VERSION =>
+        {
+            return Ok(Handshake::Retry(version));
+        }
+        (Hello::Legacy(expected), 1 | 2) if version == expected => ServerCapabilities {
+            version,
+            request_kinds: (result.bytes == 2).then_some(buf[1]),
+        },
+        (_, 1..) if !spoken.contains(&version) => {
+            return Err(ClientError::VersionMismatch { actual: version });
+        }
+        _ => {
+            return Err(ClientError::InvalidResponse {
+                context: "Bad VersionResponse.".into(),
+                trace: None,
+            });
+        }
+    };
+
+    Ok(Handshake::Accepted(capabilities))
+}
 
 pub struct AddRequest;
 
@@ -131,6 +379,37 @@ impl AddRequest {
         unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
             |Response {
                  sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    /// Adds data like a password that the server deletes once it expires.
+    ///
+    /// Servers that [don't support](ServerCapabilities::supports) sensitive
+    /// entries keep them like any other entry.
+    pub fn response_add_sensitive<Server: AsFd, Data: AsFd>(
+        server: Server,
+        to: RingKind,
+        mime_type: MimeType,
+        data: Data,
+    ) -> Result<AddResponse, ClientError> {
+        request_with_fd(
+            &server,
+            Request::Add {
+                to,
+                sensitive: true,
+                mime_type,
+                copied_at: 0,
+            },
+            data,
+            SendFlags::empty(),
+        )?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
                  value,
              }| value,
         )
@@ -143,7 +422,17 @@ impl AddRequest {
         data: Data,
         flags: SendFlags,
     ) -> Result<(), ClientError> {
-        request_with_fd(&server, Request::Add { to, mime_type }, data, flags)
+        request_with_fd(
+            &server,
+            Request::Add {
+                to,
+                sensitive: false,
+                mime_type,
+                copied_at: 0,
+            },
+            data,
+            flags,
+        )
     }
 
     response!(AddResponse);
@@ -161,6 +450,7 @@ impl MoveToFrontRequest {
         unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
             |Response {
                  sequence_number: _,
+                 trace: _,
                  value,
              }| value,
         )
@@ -190,6 +480,7 @@ impl SwapRequest {
         unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
             |Response {
                  sequence_number: _,
+                 trace: _,
                  value,
              }| value,
         )
@@ -215,6 +506,7 @@ impl RemoveRequest {
         unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
             |Response {
                  sequence_number: _,
+                 trace: _,
                  value,
              }| value,
         )
@@ -242,6 +534,7 @@ impl GarbageCollectRequest {
         unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
             |Response {
                  sequence_number: _,
+                 trace: _,
                  value,
              }| value,
         )
@@ -258,78 +551,1277 @@ impl GarbageCollectRequest {
     response!(GarbageCollectResponse);
 }
 
-fn request(server: impl AsFd, request: Request, flags: SendFlags) -> Result<(), ClientError> {
-    request_with_ancillary(server, request, &mut SendAncillaryBuffer::default(), flags)
-}
+pub struct ReadRequest;
 
-fn request_with_fd(
-    server: impl AsFd,
-    request: Request,
-    fd: impl AsFd,
-    flags: SendFlags,
-) -> Result<(), ClientError> {
-    let mut space = [0; rustix::cmsg_space!(ScmRights(1))];
-    let mut buf = SendAncillaryBuffer::new(&mut space);
-    let fds = [fd.as_fd()];
-    {
-        let success = buf.push(SendAncillaryMessage::ScmRights(&fds));
-        debug_assert!(success);
+impl ReadRequest {
+    pub fn response<Server: AsFd>(
+        server: Server,
+        id: u64,
+        offset: u64,
+        len: u32,
+    ) -> Result<(ReadResponse, Option<OwnedFd>), ClientError> {
+        Self::send(&server, id, offset, len, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |(
+                Response {
+                    sequence_number: _,
+                    trace: _,
+                    value,
+                },
+                fd,
+            )| (value, fd),
+        )
     }
 
-    request_with_ancillary(server, request, &mut buf, flags)
+    pub fn send<Server: AsFd>(
+        server: Server,
+        id: u64,
+        offset: u64,
+        len: u32,
+        flags: SendFlags,
+    ) -> Result<(), ClientError> {
+        request(&server, Request::Read { id, offset, len }, flags)
+    }
+
+    /// This is a low-level method that can be used for high-throughput requests
+    /// through the use of pipelining via [`Self::send`].
+    ///
+    /// # Safety
+    ///
+    /// The received response must have been for a request of this type.
+    pub unsafe fn recv<Server: AsFd>(
+        server: Server,
+        flags: RecvFlags,
+    ) -> Result<(Response<ReadResponse>, Option<OwnedFd>), ClientError> {
+        let mut space = [0; rustix::cmsg_space!(ScmRights(1))];
+        let mut ancillary = RecvAncillaryBuffer::new(&mut space);
+        let response = unsafe {
+            response::<ReadResponse, { size_of::<Response<ReadResponse>>() }>(
+                &server,
+                flags,
+                &mut ancillary,
+            )
+        }?;
+
+        let mut fd = None;
+        for message in ancillary.drain() {
+            if let RecvAncillaryMessage::ScmRights(received_fds) = message {
+                for received in received_fds {
+                    fd = Some(received);
+                }
+            }
+        }
+        if response.value.outcome == ReadOutcome::File && fd.is_none() {
+            return Err(ClientError::InvalidResponse {
+                context: "Read response is missing its file descriptor.".into(),
+                trace: Some(response.trace),
+            });
+        }
+
+        Ok((response, fd))
+    }
 }
 
-fn request_with_ancillary(
-    server: impl AsFd,
-    request: Request,
-    ancillary: &mut SendAncillaryBuffer,
-    flags: SendFlags,
-) -> Result<(), ClientError> {
-    sendmsg(
-        server,
-        &[IoSlice::new(request.as_bytes())],
-        ancillary,
-        flags,
-    )
-    .map_io_err(|| format!("Failed to send request: {request:?}"))?;
-    Ok(())
+/// An entry's contents as fetched through the server.
+#[derive(Debug)]
+pub enum EntryPayload {
+    /// Bucketed and packed entries are small enough to be sent back in the
+    /// response.
+    Inline(Vec<u8>),
+    /// Direct entries are sent back as their file, opened read-only, or as a
+    /// copy of their contents if the server stored them compressed.
+    File(OwnedFd),
 }
 
-unsafe fn response<T: Copy + 'static, const N: usize>(
-    server: impl AsFd,
-    flags: RecvFlags,
-) -> Result<Response<T>, ClientError> {
-    let type_name = || {
-        let name = std::any::type_name::<T>();
-        if let Some((_, name)) = name.rsplit_once(':') {
-            name
-        } else {
-            name
+/// Fetches an entry through the server for clients that can't read the
+/// database themselves, along with its mime type.
+///
+/// Only direct entries have a mime type, so it's empty for inline payloads.
+/// Ids that don't point at an entry fail with
+/// [`IdNotFound`](ringboard_core::Error::IdNotFound).
+pub fn fetch<Server: AsFd>(
+    server: Server,
+    id: u64,
+) -> Result<(MimeType, EntryPayload), ClientError> {
+    let (response, fd) = ReadRequest::response(server, id, 0, u32::MAX)?;
+    match response.outcome {
+        ReadOutcome::Inline => Ok((
+            MimeType::new(),
+            EntryPayload::Inline(response.data().to_vec()),
+        )),
+        ReadOutcome::File => {
+            let (file, original) = decode_direct_file(File::from(fd.unwrap()))?;
+            let mime_type =
+                xattr_mime_type(original.as_ref().map_or_else(|| file.as_fd(), AsFd::as_fd))?;
+            Ok((mime_type, EntryPayload::File(file.into())))
         }
-    };
+        ReadOutcome::Error(e) => Err(e.into()),
+    }
+}
 
-    let mut buf = [0u8; N];
-    let result = recvmsg(
-        server,
-        &mut [IoSliceMut::new(buf.as_mut_slice())],
-        &mut RecvAncillaryBuffer::default(),
-        RecvFlags::TRUNC | flags,
-    )
-    .map_io_err(|| format!("Failed to receive {}.", type_name()))?;
+pub struct ListRequest;
 
-    if result.bytes != N {
-        return Err(ClientError::InvalidResponse {
-            context: format!("Bad {}.", type_name()).into(),
-        });
+impl ListRequest {
+    pub fn response<Server: AsFd>(
+        server: Server,
+        ring: RingKind,
+        start: u32,
+        count: u32,
+    ) -> Result<ListResponse, ClientError> {
+        Self::send(&server, ring, start, count, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
     }
-    debug_assert!(!result.flags.contains(RecvFlags::TRUNC));
 
-    if TypeId::of::<T>() == TypeId::of::<VersionResponse>() {
-        Ok(Response {
-            sequence_number: 0,
-            value: *unsafe { &buf.as_ptr().cast::<T>().read_unaligned() },
+    pub fn send<Server: AsFd>(
+        server: Server,
+        ring: RingKind,
+        start: u32,
+        count: u32,
+        flags: SendFlags,
+    ) -> Result<(), ClientError> {
+        request(&server, Request::List { ring, start, count }, flags)
+    }
+
+    response!(ListResponse);
+}
+
+pub struct StatsRequest;
+
+impl StatsRequest {
+    pub fn response<Server: AsFd>(server: Server) -> Result<StatsResponse, ClientError> {
+        Self::send(&server, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(server: Server, flags: SendFlags) -> Result<(), ClientError> {
+        request(&server, Request::Stats, flags)
+    }
+
+    response!(StatsResponse);
+}
+
+pub struct ServerInfoRequest;
+
+impl ServerInfoRequest {
+    pub fn response<Server: AsFd>(server: Server) -> Result<ServerInfoResponse, ClientError> {
+        Self::send(&server, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(server: Server, flags: SendFlags) -> Result<(), ClientError> {
+        request(&server, Request::ServerInfo, flags)
+    }
+
+    response!(ServerInfoResponse);
+}
+
+pub struct TrimMemoryRequest;
+
+impl TrimMemoryRequest {
+    pub fn response<Server: AsFd>(server: Server) -> Result<TrimMemoryResponse, ClientError> {
+        Self::send(&server, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(server: Server, flags: SendFlags) -> Result<(), ClientError> {
+        request(&server, Request::TrimMemory, flags)
+    }
+
+    response!(TrimMemoryResponse);
+}
+
+pub struct SetClipboardRequest;
+
+impl SetClipboardRequest {
+    pub fn response<Server: AsFd>(
+        server: Server,
+        id: Option<u64>,
+    ) -> Result<SetClipboardResponse, ClientError> {
+        Self::send(&server, id, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(
+        server: Server,
+        id: Option<u64>,
+        flags: SendFlags,
+    ) -> Result<(), ClientError> {
+        request(&server, Request::SetClipboard { id }, flags)
+    }
+
+    response!(SetClipboardResponse);
+}
+
+pub struct SetLabelRequest;
+
+impl SetLabelRequest {
+    pub fn response<Server: AsFd>(
+        server: Server,
+        id: u64,
+        label: Label,
+    ) -> Result<SetLabelResponse, ClientError> {
+        Self::send(&server, id, label, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(
+        server: Server,
+        id: u64,
+        label: Label,
+        flags: SendFlags,
+    ) -> Result<(), ClientError> {
+        request(&server, Request::SetLabel { id, label }, flags)
+    }
+
+    response!(SetLabelResponse);
+}
+
+pub struct BackupNowRequest;
+
+impl BackupNowRequest {
+    pub fn response<Server: AsFd>(server: Server) -> Result<BackupResponse, ClientError> {
+        Self::send(&server, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(server: Server, flags: SendFlags) -> Result<(), ClientError> {
+        request(&server, Request::BackupNow, flags)
+    }
+
+    response!(BackupResponse);
+}
+
+pub struct RestoreRequest;
+
+impl RestoreRequest {
+    pub fn response<Server: AsFd>(
+        server: Server,
+        tombstone_id: u64,
+    ) -> Result<RestoreResponse, ClientError> {
+        Self::send(&server, tombstone_id, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(
+        server: Server,
+        tombstone_id: u64,
+        flags: SendFlags,
+    ) -> Result<(), ClientError> {
+        request(&server, Request::Restore { tombstone_id }, flags)
+    }
+
+    response!(RestoreResponse);
+}
+
+pub struct EmptyTrashRequest;
+
+impl EmptyTrashRequest {
+    pub fn response<Server: AsFd>(server: Server) -> Result<EmptyTrashResponse, ClientError> {
+        Self::send(&server, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(server: Server, flags: SendFlags) -> Result<(), ClientError> {
+        request(&server, Request::EmptyTrash, flags)
+    }
+
+    response!(EmptyTrashResponse);
+}
+
+pub struct GenerationRequest;
+
+impl GenerationRequest {
+    pub fn response<Server: AsFd>(server: Server) -> Result<GenerationResponse, ClientError> {
+        Self::send(&server, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(server: Server, flags: SendFlags) -> Result<(), ClientError> {
+        request(&server, Request::Generation, flags)
+    }
+
+    response!(GenerationResponse);
+}
+
+pub struct AddTagRequest;
+
+impl AddTagRequest {
+    pub fn response<Server: AsFd>(
+        server: Server,
+        id: u64,
+        tag: Tag,
+    ) -> Result<TagResponse, ClientError> {
+        Self::send(&server, id, tag, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(
+        server: Server,
+        id: u64,
+        tag: Tag,
+        flags: SendFlags,
+    ) -> Result<(), ClientError> {
+        request(&server, Request::AddTag { id, tag }, flags)
+    }
+
+    response!(TagResponse);
+}
+
+pub struct RemoveTagRequest;
+
+impl RemoveTagRequest {
+    pub fn response<Server: AsFd>(
+        server: Server,
+        id: u64,
+        tag: Tag,
+    ) -> Result<TagResponse, ClientError> {
+        Self::send(&server, id, tag, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(
+        server: Server,
+        id: u64,
+        tag: Tag,
+        flags: SendFlags,
+    ) -> Result<(), ClientError> {
+        request(&server, Request::RemoveTag { id, tag }, flags)
+    }
+
+    response!(TagResponse);
+}
+
+pub struct ListTagsRequest;
+
+impl ListTagsRequest {
+    pub fn response<Server: AsFd>(
+        server: Server,
+        id: u64,
+    ) -> Result<ListTagsResponse, ClientError> {
+        Self::send(&server, id, SendFlags::empty())?;
+        unsafe { Self::recv(&server, RecvFlags::empty()) }.map(
+            |Response {
+                 sequence_number: _,
+                 trace: _,
+                 value,
+             }| value,
+        )
+    }
+
+    pub fn send<Server: AsFd>(
+        server: Server,
+        id: u64,
+        flags: SendFlags,
+    ) -> Result<(), ClientError> {
+        request(&server, Request::ListTags { id }, flags)
+    }
+
+    response!(ListTagsResponse);
+}
+
+pub struct SearchRequest;
+
+impl SearchRequest {
+    pub fn send<Server: AsFd>(
+        server: Server,
+        query: SearchQuery,
+        regex: bool,
+        limit: u32,
+        flags: SendFlags,
+    ) -> Result<(), ClientError> {
+        request(
+            &server,
+            Request::Search {
+                query,
+                regex,
+                limit,
+            },
+            flags,
+        )
+    }
+
+    response!(SearchResponse);
+}
+
+/// Searches the database through the server for clients that can't read it
+/// themselves.
+///
+/// This works like a local [`search`](crate::search::search) without a mime
+/// filter: `query` is matched exactly as written unless it's a regex, and each
+/// entry is only reported once.
+///
+/// The server stops after `limit` matches. Dropping the iterator early still
+/// waits for the rest of them so the connection can be reused, which makes the
+/// limit worth keeping to what will actually be shown.
+pub fn remote_search<Server: AsFd>(
+    server: Server,
+    query: &str,
+    regex: bool,
+    limit: u32,
+) -> Result<RemoteSearch<Server>, ClientError> {
+    let query = SearchQuery::from(query).map_err(|_| ClientError::InvalidQuery {
+        context: format!(
+            "Searches through the server are limited to {} bytes.",
+            SearchQuery::new().capacity()
+        )
+        .into(),
+    })?;
+    SearchRequest::send(&server, query, regex, limit, SendFlags::empty())?;
+    Ok(RemoteSearch {
+        server,
+        response: None,
+        next: 0,
+        status: SearchStatus::More,
+        trace: None,
+    })
+}
+
+/// The matches of a [`remote_search`] as the server streams them in.
+#[derive(Debug)]
+pub struct RemoteSearch<Server: AsFd> {
+    server: Server,
+    response: Option<SearchResponse>,
+    next: usize,
+    status: SearchStatus,
+    trace: Option<TraceId>,
+}
+
+impl<Server: AsFd> RemoteSearch<Server> {
+    fn recv(&mut self) -> Result<(), ClientError> {
+        let response = unsafe { SearchRequest::recv(&self.server, RecvFlags::empty()) };
+        let Response {
+            sequence_number: _,
+            trace,
+            value,
+        } = response.inspect_err(|_| self.status = SearchStatus::Done)?;
+        self.status = value.status;
+        self.trace = Some(trace);
+        self.response = Some(value);
+        self.next = 0;
+        Ok(())
+    }
+}
+
+impl<Server: AsFd> Iterator for RemoteSearch<Server> {
+    type Item = Result<SearchMatch, ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(&m) = self
+                .response
+                .as_ref()
+                .and_then(|response| response.matches().get(self.next))
+            {
+                self.next += 1;
+                return Some(Ok(m));
+            }
+
+            let error = match self.status {
+                SearchStatus::More => {
+                    if let Err(e) = self.recv() {
+                        return Some(Err(e));
+                    }
+                    continue;
+                }
+                SearchStatus::Done => return None,
+                SearchStatus::InvalidRegex => ClientError::InvalidQuery {
+                    context: "The server couldn't parse the query as a regex.".into(),
+                },
+                SearchStatus::Failed => ClientError::SearchFailed { trace: self.trace },
+            };
+            self.status = SearchStatus::Done;
+            return Some(Err(error));
+        }
+    }
+}
+
+impl<Server: AsFd> Drop for RemoteSearch<Server> {
+    fn drop(&mut self) {
+        while self.status == SearchStatus::More {
+            if self.recv().is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Starts an empty [`Batch`].
+pub fn batch<'fd>() -> Batch<'fd> {
+    Batch::default()
+}
+
+/// Several requests sent together, saving a round trip per request.
+///
+/// The requests go out in one message and their responses all come back in
+/// one message. Requests are handled in the order they were added, and adding
+/// more than [`MAX_BATCH_LEN`] panics.
+///
+/// Servers that predate batches reject them with
+/// [`ClientError::UnsupportedByServer`], which can be ruled out ahead of time
+/// by checking [`ServerCapabilities::supports`] for [`Request::Batch`].
+#[derive(Default, Debug)]
+#[must_use]
+pub struct Batch<'fd> {
+    requests: ArrayVec<Request, MAX_BATCH_LEN>,
+    fds: ArrayVec<BorrowedFd<'fd>, MAX_BATCH_LEN>,
+}
+
+macro_rules! batch_response_type {
+    ($t:ty) => {
+        $t
+    };
+    ($t:ty, boxed) => {
+        Box<$t>
+    };
+}
+
+macro_rules! batch_responses {
+    ($($request:ident => $response:ident($t:ty) $($boxed:ident)?,)*) => {
+        /// The response to one of a [`Batch`]'s requests.
+        #[derive(Clone, Debug)]
+        #[must_use]
+        pub enum BatchResponse {
+            $($response(batch_response_type!($t $(, $boxed)?)),)*
+        }
+
+        fn batch_response_len(request: &Request) -> usize {
+            match request {
+                $(Request::$request { .. } => size_of::<Response<$t>>(),)*
+                Request::Read { .. } | Request::Batch { .. } | Request::Search { .. } => {
+                    unreachable!()
+                }
+            }
+        }
+
+        /// # Safety
+        ///
+        /// `bytes` must start with the response to `request`.
+        unsafe fn read_batch_response(request: &Request, bytes: &[u8]) -> BatchResponse {
+            match request {
+                $(Request::$request { .. } => BatchResponse::$response(
+                    unsafe { bytes.as_ptr().cast::<Response<$t>>().read_unaligned() }
+                        .value
+                        .into(),
+                ),)*
+                Request::Read { .. } | Request::Batch { .. } | Request::Search { .. } => {
+                    unreachable!()
+                }
+            }
+        }
+    };
+}
+
+// Boxed responses are big enough that every other response would pay for
+// their size.
+batch_responses! {
+    Add => Add(AddResponse),
+    MoveToFront => MoveToFront(MoveToFrontResponse),
+    Swap => Swap(SwapResponse),
+    Remove => Remove(RemoveResponse),
+    GarbageCollect => GarbageCollect(GarbageCollectResponse),
+    List => List(ListResponse) boxed,
+    Stats => Stats(StatsResponse) boxed,
+    ServerInfo => ServerInfo(ServerInfoResponse),
+    TrimMemory => TrimMemory(TrimMemoryResponse),
+    SetClipboard => SetClipboard(SetClipboardResponse),
+    SetLabel => SetLabel(SetLabelResponse),
+    BackupNow => Backup(BackupResponse),
+    Restore => Restore(RestoreResponse),
+    EmptyTrash => EmptyTrash(EmptyTrashResponse),
+    Generation => Generation(GenerationResponse),
+    AddTag => AddTag(TagResponse),
+    RemoveTag => RemoveTag(TagResponse),
+    ListTags => ListTags(ListTagsResponse),
+}
+
+impl<'fd> Batch<'fd> {
+    /// Like [`AddRequest::response_add_unchecked`], `data` must be a regular
+    /// file.
+    pub fn add(mut self, to: RingKind, mime_type: MimeType, data: BorrowedFd<'fd>) -> Self {
+        self.requests.push(Request::Add {
+            to,
+            sensitive: false,
+            mime_type,
+            copied_at: 0,
+        });
+        self.fds.push(data);
+        self
+    }
+
+    pub fn move_to_front(self, id: u64, to: Option<RingKind>) -> Self {
+        self.push(Request::MoveToFront { id, to })
+    }
+
+    pub fn swap(self, id1: u64, id2: u64) -> Self {
+        self.push(Request::Swap { id1, id2 })
+    }
+
+    pub fn remove(self, id: u64) -> Self {
+        self.push(Request::Remove { id })
+    }
+
+    pub fn garbage_collect(self, max_wasted_bytes: u64) -> Self {
+        self.push(Request::GarbageCollect { max_wasted_bytes })
+    }
+
+    pub fn list(self, ring: RingKind, start: u32, count: u32) -> Self {
+        self.push(Request::List { ring, start, count })
+    }
+
+    pub fn stats(self) -> Self {
+        self.push(Request::Stats)
+    }
+
+    pub fn server_info(self) -> Self {
+        self.push(Request::ServerInfo)
+    }
+
+    pub fn trim_memory(self) -> Self {
+        self.push(Request::TrimMemory)
+    }
+
+    pub fn set_clipboard(self, id: Option<u64>) -> Self {
+        self.push(Request::SetClipboard { id })
+    }
+
+    pub fn set_label(self, id: u64, label: Label) -> Self {
+        self.push(Request::SetLabel { id, label })
+    }
+
+    pub fn backup_now(self) -> Self {
+        self.push(Request::BackupNow)
+    }
+
+    pub fn restore(self, tombstone_id: u64) -> Self {
+        self.push(Request::Restore { tombstone_id })
+    }
+
+    pub fn empty_trash(self) -> Self {
+        self.push(Request::EmptyTrash)
+    }
+
+    pub fn generation(self) -> Self {
+        self.push(Request::Generation)
+    }
+
+    pub fn add_tag(self, id: u64, tag: Tag) -> Self {
+        self.push(Request::AddTag { id, tag })
+    }
+
+    pub fn remove_tag(self, id: u64, tag: Tag) -> Self {
+        self.push(Request::RemoveTag { id, tag })
+    }
+
+    pub fn list_tags(self, id: u64) -> Self {
+        self.push(Request::ListTags { id })
+    }
+
+    fn push(mut self, request: Request) -> Self {
+        self.requests.push(request);
+        self
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Sends the requests and waits for their responses, which are returned in
+    /// the same order.
+    pub fn response<Server: AsFd>(
+        &self,
+        server: Server,
+    ) -> Result<ArrayVec<BatchResponse, MAX_BATCH_LEN>, ClientError> {
+        self.send(&server, SendFlags::empty())?;
+        unsafe { self.recv(&server, RecvFlags::empty()) }
+    }
+
+    /// Sends the requests in a single message. Empty batches send nothing.
+    pub fn send<Server: AsFd>(&self, server: Server, flags: SendFlags) -> Result<(), ClientError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let header = Request::Batch {
+            len: u8::try_from(self.len()).unwrap(),
+        };
+        let mut payload = ArrayVec::<_, { MAX_BATCH_LEN + 1 }>::new();
+        payload.push(IoSlice::new(header.as_bytes()));
+        payload.extend(self.requests.iter().map(|r| IoSlice::new(r.as_bytes())));
+
+        let mut space = [0; rustix::cmsg_space!(ScmRights(MAX_BATCH_LEN))];
+        let mut ancillary = SendAncillaryBuffer::new(&mut space);
+        if !self.fds.is_empty() {
+            let success = ancillary.push(SendAncillaryMessage::ScmRights(&self.fds));
+            debug_assert!(success);
+        }
+
+        sendmsg(server, &payload, &mut ancillary, flags)
+            .map_io_err(|| format!("Failed to send batch: {:?}", self.requests))?;
+        Ok(())
+    }
+
+    /// This is a low-level method that can be used for high-throughput requests
+    /// through the use of pipelining via [`Self::send`].
+    ///
+    /// # Safety
+    ///
+    /// The received response must have been for this batch.
+    pub unsafe fn recv<Server: AsFd>(
+        &self,
+        server: Server,
+        flags: RecvFlags,
+    ) -> Result<ArrayVec<BatchResponse, MAX_BATCH_LEN>, ClientError> {
+        if self.is_empty() {
+            return Ok(ArrayVec::new());
+        }
+        let len = self.requests.iter().map(batch_response_len).sum::<usize>();
+        let mut buf = vec![0u8; len.max(RequestFailed::LEN)];
+        let result = recvmsg(
+            server,
+            &mut [IoSliceMut::new(&mut buf)],
+            &mut RecvAncillaryBuffer::default(),
+            RecvFlags::TRUNC | flags,
+        )
+        .map_io_err(|| "Failed to receive batch responses.")?;
+
+        // Each response is larger than an unknown request error and padded
+        // unlike a failure, so no batch's responses can be mistaken for either.
+        if result.bytes == size_of::<Response<UnknownRequest>>() {
+            return Err(unsafe { unknown_request(&buf) });
+        }
+        if result.bytes == RequestFailed::LEN {
+            return Err(unsafe { request_failed(&[&buf]) });
+        }
+        if result.bytes != len {
+            return Err(ClientError::InvalidResponse {
+                context: "Bad batch response.".into(),
+                trace: None,
+            });
+        }
+
+        let mut offset = 0;
+        Ok(self
+            .requests
+            .iter()
+            .map(|request| {
+                let response = unsafe { read_batch_response(request, &buf[offset..]) };
+                offset += batch_response_len(request);
+                response
+            })
+            .collect())
+    }
+}
+
+fn request(server: impl AsFd, request: Request, flags: SendFlags) -> Result<(), ClientError> {
+    request_with_ancillary(server, request, &mut SendAncillaryBuffer::default(), flags)
+}
+
+fn request_with_fd(
+    server: impl AsFd,
+    request: Request,
+    fd: impl AsFd,
+    flags: SendFlags,
+) -> Result<(), ClientError> {
+    let mut space = [0; rustix::cmsg_space!(ScmRights(1))];
+    let mut buf = SendAncillaryBuffer::new(&mut space);
+    let fds = [fd.as_fd()];
+    {
+        let success = buf.push(SendAncillaryMessage::ScmRights(&fds));
+        debug_assert!(success);
+    }
+
+    request_with_ancillary(server, request, &mut buf, flags)
+}
+
+fn request_with_ancillary(
+    server: impl AsFd,
+    request: Request,
+    ancillary: &mut SendAncillaryBuffer,
+    flags: SendFlags,
+) -> Result<(), ClientError> {
+    sendmsg(
+        server,
+        &[IoSlice::new(request.as_bytes())],
+        ancillary,
+        flags,
+    )
+    .map_io_err(|| format!("Failed to send request: {request:?}"))?;
+    Ok(())
+}
+
+unsafe fn response<T: Copy, const N: usize>(
+    server: impl AsFd,
+    flags: RecvFlags,
+    ancillary: &mut RecvAncillaryBuffer,
+) -> Result<Response<T>, ClientError> {
+    // Otherwise errors would be mistaken for the response.
+    const { assert!(N != size_of::<Response<UnknownRequest>>() && N != RequestFailed::LEN) };
+    let type_name = || {
+        let name = std::any::type_name::<T>();
+        if let Some((_, name)) = name.rsplit_once(':') {
+            name
+        } else {
+            name
+        }
+    };
+
+    let mut buf = [0u8; N];
+    // Failures can be longer than the response.
+    let mut overflow = [0u8; RequestFailed::LEN];
+    let result = recvmsg(
+        server,
+        &mut [
+            IoSliceMut::new(buf.as_mut_slice()),
+            IoSliceMut::new(overflow.as_mut_slice()),
+        ],
+        ancillary,
+        RecvFlags::TRUNC | flags,
+    )
+    .map_io_err(|| format!("Failed to receive {}.", type_name()))?;
+
+    if result.bytes != N {
+        if result.bytes == size_of::<Response<UnknownRequest>>() {
+            return Err(unsafe { unknown_request(&buf) });
+        }
+        if result.bytes == RequestFailed::LEN {
+            return Err(unsafe { request_failed(&[&buf, &overflow]) });
+        }
+
+        let has_trace = result.bytes >= offset_of!(Response<T>, trace) + size_of::<TraceId>();
+        return Err(ClientError::InvalidResponse {
+            context: format!("Bad {}.", type_name()).into(),
+            trace: has_trace.then(|| unsafe {
+                buf.as_ptr()
+                    .add(offset_of!(Response<T>, trace))
+                    .cast::<TraceId>()
+                    .read_unaligned()
+            }),
+        });
+    }
+    debug_assert!(!result.flags.contains(RecvFlags::TRUNC));
+
+    Ok(*unsafe { &buf.as_ptr().cast::<Response<T>>().read_unaligned() })
+}
+
+/// # Safety
+///
+/// `buf` must hold an unknown request error.
+const unsafe fn unknown_request(buf: &[u8]) -> ClientError {
+    let Response {
+        sequence_number: _,
+        trace,
+        value: UnknownRequest { tag },
+    } = unsafe {
+        buf.as_ptr()
+            .cast::<Response<UnknownRequest>>()
+            .read_unaligned()
+    };
+    ClientError::UnsupportedByServer {
+        tag,
+        trace: Some(trace),
+    }
+}
+
+/// # Safety
+///
+/// `parts` must hold a request failure once put back to back.
+unsafe fn request_failed(parts: &[&[u8]]) -> ClientError {
+    let mut buf = [0u8; size_of::<Response<RequestFailed>>()];
+    let mut len = 0;
+    for part in parts {
+        let n = part.len().min(RequestFailed::LEN - len);
+        buf[len..len + n].copy_from_slice(&part[..n]);
+        len += n;
+    }
+    let Response {
+        sequence_number: _,
+        trace,
+        value: RequestFailed { code, context },
+    } = unsafe {
+        buf.as_ptr()
+            .cast::<Response<RequestFailed>>()
+            .read_unaligned()
+    };
+    ClientError::Server {
+        code,
+        context: context.to_string().into(),
+        trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::IoSliceMut, os::fd::AsFd, process, thread, time::Duration};
+
+    use ringboard_core::{
+        protocol,
+        protocol::{
+            AddResponse, ErrorCode, MimeType, Request, RequestFailed, Response, RingKind,
+            SearchMatch, SearchResponse, SearchStatus, SetClipboardResponse, TraceId,
+            UnknownRequest, MAX_SEARCH_MATCHES,
+        },
+    };
+    use rustix::{
+        fs::{memfd_create, MemfdFlags},
+        net::{
+            accept, bind_unix, listen, recv, recvmsg, send, socket, socketpair, AddressFamily,
+            RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, SendFlags, SocketAddrUnix,
+            SocketFlags, SocketType,
+        },
+    };
+
+    use super::{
+        batch, connect_or_start, negotiate_with_server, remote_search, BatchResponse,
+        GenerationRequest,
+    };
+    use crate::ClientError;
+
+    fn response<T: Copy>(buf: &mut Vec<u8>, value: T) {
+        let response = Response {
+            sequence_number: 0,
+            trace: TraceId::default(),
+            value,
+        };
+        buf.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(
+                std::ptr::from_ref(&response).cast::<u8>(),
+                size_of::<Response<T>>(),
+            )
+        });
+    }
+
+    #[test]
+    fn batches_are_sent_and_answered_in_one_message() {
+        let (client, server) = socketpair(
+            AddressFamily::UNIX,
+            SocketType::SEQPACKET,
+            SocketFlags::empty(),
+            None,
+        )
+        .unwrap();
+        let data = memfd_create(c"ringboard-batch", MemfdFlags::empty()).unwrap();
+        let batch = batch()
+            .add(RingKind::Main, MimeType::new(), data.as_fd())
+            .set_clipboard(Some(7));
+        batch.send(&client, SendFlags::empty()).unwrap();
+
+        let mut buf = [0; 1024];
+        let mut space = [0; rustix::cmsg_space!(ScmRights(4))];
+        let mut ancillary = RecvAncillaryBuffer::new(&mut space);
+        let received = recvmsg(
+            &server,
+            &mut [IoSliceMut::new(&mut buf)],
+            &mut ancillary,
+            RecvFlags::empty(),
+        )
+        .unwrap();
+        assert_eq!(received.bytes, 3 * size_of::<Request>());
+        let requests = buf
+            .chunks_exact(size_of::<Request>())
+            .take(3)
+            .map(|request| Request::decode(request, protocol::VERSION).unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            requests[..],
+            [
+                Request::Batch { len: 2 },
+                Request::Add { .. },
+                Request::SetClipboard { id: Some(7) }
+            ]
+        ));
+        let fds = ancillary
+            .drain()
+            .map(|message| match message {
+                RecvAncillaryMessage::ScmRights(fds) => fds.count(),
+                _ => 0,
+            })
+            .sum::<usize>();
+        assert_eq!(fds, 1);
+
+        let mut reply = Vec::new();
+        response(&mut reply, AddResponse::Empty);
+        response(&mut reply, SetClipboardResponse { error: None });
+        send(&server, &reply, SendFlags::empty()).unwrap();
+        let responses = unsafe { batch.recv(&client, RecvFlags::empty()) }.unwrap();
+        assert!(matches!(
+            responses[..],
+            [
+                BatchResponse::Add(AddResponse::Empty),
+                BatchResponse::SetClipboard(SetClipboardResponse { error: None })
+            ]
+        ));
+
+        // Servers that predate batches reject them like any other new request.
+        batch.send(&client, SendFlags::empty()).unwrap();
+        let mut reply = Vec::new();
+        response(
+            &mut reply,
+            UnknownRequest {
+                tag: Request::Batch { len: 0 }.tag(),
+            },
+        );
+        send(&server, &reply, SendFlags::empty()).unwrap();
+        assert!(matches!(
+            unsafe { batch.recv(&client, RecvFlags::empty()) },
+            Err(ClientError::UnsupportedByServer { .. })
+        ));
+
+        // Failed requests cut batches short.
+        let mut failed = Vec::new();
+        response(
+            &mut failed,
+            RequestFailed::new(ErrorCode::Io, "Failed to write."),
+        );
+        failed.truncate(RequestFailed::LEN);
+        batch.send(&client, SendFlags::empty()).unwrap();
+        send(&server, &failed, SendFlags::empty()).unwrap();
+        assert!(matches!(
+            unsafe { batch.recv(&client, RecvFlags::empty()) },
+            Err(ClientError::Server {
+                code: ErrorCode::Io,
+                ..
+            })
+        ));
+        // Even responses shorter than the failure tell it apart.
+        send(&server, &failed, SendFlags::empty()).unwrap();
+        assert!(matches!(
+            GenerationRequest::response(&client),
+            Err(ClientError::Server { context, .. }) if context == "Failed to write."
+        ));
+    }
+
+    fn search_response(status: SearchStatus, ids: &[u64]) -> SearchResponse {
+        let mut response = SearchResponse {
+            status,
+            count: u32::try_from(ids.len()).unwrap(),
+            matches: [SearchMatch::default(); MAX_SEARCH_MATCHES],
+        };
+        for (m, &id) in response.matches.iter_mut().zip(ids) {
+            *m = SearchMatch {
+                id,
+                start: 0,
+                end: 1,
+            };
+        }
+        response
+    }
+
+    #[test]
+    fn remote_searches_stream_in_until_the_last_response() {
+        let (client, server) = socketpair(
+            AddressFamily::UNIX,
+            SocketType::SEQPACKET,
+            SocketFlags::empty(),
+            None,
+        )
+        .unwrap();
+        let reply = |status, ids: &[u64]| {
+            let mut reply = Vec::new();
+            response(&mut reply, search_response(status, ids));
+            send(&server, &reply, SendFlags::empty()).unwrap();
+        };
+
+        reply(SearchStatus::More, &[1, 2]);
+        reply(SearchStatus::Failed, &[3]);
+        let results = remote_search(&client, "needle", false, 10)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            results[..],
+            [
+                Ok(SearchMatch { id: 1, .. }),
+                Ok(SearchMatch { id: 2, .. }),
+                Ok(SearchMatch { id: 3, .. }),
+                Err(ClientError::SearchFailed { trace: Some(_) })
+            ]
+        ));
+        let mut request = [0; size_of::<Request>()];
+        recv(&server, &mut request, RecvFlags::empty()).unwrap();
+        assert!(matches!(
+            Request::decode(&request, protocol::VERSION).unwrap(),
+            Request::Search { query, regex: false, limit: 10 } if query.as_str() == "needle"
+        ));
+
+        // Stopping early still consumes the rest of the search's responses.
+        reply(SearchStatus::More, &[1]);
+        reply(SearchStatus::More, &[2]);
+        reply(SearchStatus::Done, &[]);
+        let mut search = remote_search(&client, "needle", false, 10).unwrap();
+        assert!(matches!(search.next(), Some(Ok(SearchMatch { id: 1, .. }))));
+        drop(search);
+        reply(SearchStatus::InvalidRegex, &[]);
+        let results = remote_search(&client, "(", true, 10)
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            results[..],
+            [Err(ClientError::InvalidQuery { .. })]
+        ));
+
+        assert!(matches!(
+            remote_search(&client, &"x".repeat(100), false, 10),
+            Err(ClientError::InvalidQuery { .. })
+        ));
+    }
+
+    #[test]
+    fn servers_that_predate_version_ranges_are_spoken_to_in_their_version() {
+        let path = env::temp_dir().join(format!("ringboard-old-server-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let addr = SocketAddrUnix::new(&path).unwrap();
+        let listener = socket(AddressFamily::UNIX, SocketType::SEQPACKET, None).unwrap();
+        bind_unix(&listener, &addr).unwrap();
+        listen(&listener, 2).unwrap();
+        let kinds = u8::try_from(Request::KINDS).unwrap();
+
+        // Version 1 servers reply with their version and reject any other.
+        let server = thread::spawn(move || {
+            (0..2)
+                .map(|_| {
+                    let client = accept(&listener).unwrap();
+                    let mut hello = [0; 64];
+                    let len = recv(&client, &mut hello, RecvFlags::empty()).unwrap();
+                    send(&client, &[1, kinds], SendFlags::empty()).unwrap();
+                    hello[..len].to_vec()
+                })
+                .collect::<Vec<_>>()
+        });
+        let (_, capabilities) = negotiate_with_server(&addr, SocketFlags::empty()).unwrap();
+        let hellos = server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            hellos[0][..3],
+            [protocol::VERSION, kinds, protocol::MIN_VERSION]
+        );
+        assert_eq!(hellos[1][..2], [1, kinds]);
+        assert_eq!(capabilities.version(), 1);
+        let add = |sensitive, copied_at| Request::Add {
+            to: RingKind::Main,
+            sensitive,
+            mime_type: MimeType::new(),
+            copied_at,
+        };
+        assert!(capabilities.supports(&add(false, 0)));
+        assert!(!capabilities.supports(&add(false, 1_700_000_000)));
+        assert!(!capabilities.supports(&add(true, 0)));
+    }
+
+    #[test]
+    fn servers_are_started_when_nothing_is_listening() {
+        let path = env::temp_dir().join(format!("ringboard-startup-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        let addr = SocketAddrUnix::new(&path).unwrap();
+        let kinds = u8::try_from(Request::KINDS).unwrap();
+
+        let mut server = None;
+        let (_, capabilities) = connect_or_start(&addr, SocketFlags::empty(), || {
+            let addr = addr.clone();
+            // Like a real server, this one takes a moment to start listening.
+            server = Some(thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                let listener = socket(AddressFamily::UNIX, SocketType::SEQPACKET, None).unwrap();
+                bind_unix(&listener, &addr).unwrap();
+                listen(&listener, 2).unwrap();
+                for _ in 0..2 {
+                    let client = accept(&listener).unwrap();
+                    recv(&client, &mut [0; 64], RecvFlags::empty()).unwrap();
+                    send(
+                        &client,
+                        &[protocol::VERSION, kinds, protocol::VERSION],
+                        SendFlags::empty(),
+                    )
+                    .unwrap();
+                }
+            }));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(capabilities.version(), protocol::VERSION);
+
+        // Running servers are connected to without starting another one.
+        connect_or_start(&addr, SocketFlags::empty(), || {
+            panic!("The server was started twice.")
         })
-    } else {
-        Ok(*unsafe { &buf.as_ptr().cast::<Response<T>>().read_unaligned() })
+        .unwrap();
+        server.unwrap().join().unwrap();
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,227 @@
+//! Nonblocking versions of the client requests for use from async code running
+//! on a [tokio](https://tokio.rs) runtime.
+//!
+//! Requests and responses are encoded exactly like the blocking API's, so both
+//! report the same [`ClientError`]s. A connection handles one request at a
+//! time: if a request's future is dropped before it completes, its response
+//! may still be waiting on the socket and the connection should be discarded.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::io::{Seek, Write};
+//!
+//! use clipboard_history_client_sdk::{
+//!     api::r#async::{connect_to_server, AddRequest},
+//!     core::{
+//!         dirs::{data_dir, socket_file},
+//!         protocol::{AddResponse, MimeType, RingKind},
+//!     },
+//!     DatabaseReader, EntryReader,
+//! };
+//! use rustix::{
+//!     fs::{memfd_create, MemfdFlags},
+//!     net::SocketAddrUnix,
+//! };
+//!
+//! let mut server = connect_to_server(&SocketAddrUnix::new(socket_file())?).await?;
+//!
+//! let mut data = std::fs::File::from(memfd_create(c"entry", MemfdFlags::empty())?);
+//! data.write_all(b"Hello, world!")?;
+//! data.rewind()?;
+//! let AddResponse::Success { id, .. } =
+//!     AddRequest::response(&mut server, RingKind::Main, MimeType::new(), &data).await?
+//! else {
+//!     return Err("entry wasn't added".into());
+//! };
+//!
+//! let mut database = data_dir();
+//! let reader = DatabaseReader::open(&mut database)?;
+//! let entries = EntryReader::open(&mut database)?;
+//! assert_eq!(&**reader.get_raw(id)?.to_slice(&entries)?, b"Hello, world!");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    io::ErrorKind,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+};
+
+use ringboard_core::{
+    protocol::{
+        AddResponse, MimeType, MoveToFrontResponse, RemoveResponse, Response, RingKind,
+        StatsResponse, SwapResponse,
+    },
+    IoErr,
+};
+use rustix::net::{
+    connect_unix, socket_with, AddressFamily, RecvFlags, SendFlags, SocketAddrUnix, SocketFlags,
+    SocketType,
+};
+use tokio::io::{unix::AsyncFd, Interest};
+
+use crate::{api, api::ServerCapabilities, ClientError};
+
+/// Like [`api::connect_to_server`], but the socket is nonblocking and
+/// registered with the current tokio runtime.
+pub async fn connect_to_server(addr: &SocketAddrUnix) -> Result<AsyncFd<OwnedFd>, ClientError> {
+    negotiate_with_server(addr).await.map(|(socket, _)| socket)
+}
+
+/// Like [`api::negotiate_with_server`], but the socket is nonblocking and
+/// registered with the current tokio runtime.
+pub async fn negotiate_with_server(
+    addr: &SocketAddrUnix,
+) -> Result<(AsyncFd<OwnedFd>, ServerCapabilities), ClientError> {
+    let mut hello = api::Hello::Range;
+    loop {
+        let socket = socket_with(
+            AddressFamily::UNIX,
+            SocketType::SEQPACKET,
+            SocketFlags::NONBLOCK | SocketFlags::CLOEXEC,
+            None,
+        )
+        .map_io_err(|| format!("Failed to create socket: {addr:?}"))?;
+        connect_unix(&socket, addr)
+            .map_io_err(|| format!("Failed to connect to server: {addr:?}"))?;
+        let socket =
+            AsyncFd::new(socket).map_io_err(|| "Failed to register socket with the runtime.")?;
+
+        retry(&socket, Interest::WRITABLE, |socket| {
+            api::send_version(socket, addr, hello, SendFlags::DONTWAIT)
+        })
+        .await?;
+        match retry(&socket, Interest::READABLE, |socket| {
+            api::recv_version(socket, hello, RecvFlags::DONTWAIT)
+        })
+        .await?
+        {
+            api::Handshake::Accepted(capabilities) => return Ok((socket, capabilities)),
+            api::Handshake::Retry(version) => hello = api::Hello::Legacy(version),
+        }
+    }
+}
+
+pub struct AddRequest;
+
+impl AddRequest {
+    /// Unlike [`api::AddRequest::response`], `data` isn't copied into a
+    /// temporary file if it isn't a regular file since reading it could block.
+    /// Send entries backed by something else, e.g. a pipe, from a memfd
+    /// instead.
+    pub async fn response<Data: AsFd + Send + Sync>(
+        server: &mut AsyncFd<OwnedFd>,
+        to: RingKind,
+        mime_type: MimeType,
+        data: Data,
+    ) -> Result<AddResponse, ClientError> {
+        retry(server, Interest::WRITABLE, |server| {
+            api::AddRequest::send(server, to, mime_type, &data, SendFlags::DONTWAIT)
+        })
+        .await?;
+        retry(server, Interest::READABLE, |server| unsafe {
+            api::AddRequest::recv(server, RecvFlags::DONTWAIT)
+        })
+        .await
+        .map(|Response { value, .. }| value)
+    }
+}
+
+pub struct MoveToFrontRequest;
+
+impl MoveToFrontRequest {
+    pub async fn response(
+        server: &mut AsyncFd<OwnedFd>,
+        id: u64,
+        to: Option<RingKind>,
+    ) -> Result<MoveToFrontResponse, ClientError> {
+        retry(server, Interest::WRITABLE, |server| {
+            api::MoveToFrontRequest::send(server, id, to, SendFlags::DONTWAIT)
+        })
+        .await?;
+        retry(server, Interest::READABLE, |server| unsafe {
+            api::MoveToFrontRequest::recv(server, RecvFlags::DONTWAIT)
+        })
+        .await
+        .map(|Response { value, .. }| value)
+    }
+}
+
+pub struct SwapRequest;
+
+impl SwapRequest {
+    pub async fn response(
+        server: &mut AsyncFd<OwnedFd>,
+        id1: u64,
+        id2: u64,
+    ) -> Result<SwapResponse, ClientError> {
+        retry(server, Interest::WRITABLE, |server| {
+            api::SwapRequest::send(server, id1, id2, SendFlags::DONTWAIT)
+        })
+        .await?;
+        retry(server, Interest::READABLE, |server| unsafe {
+            api::SwapRequest::recv(server, RecvFlags::DONTWAIT)
+        })
+        .await
+        .map(|Response { value, .. }| value)
+    }
+}
+
+pub struct RemoveRequest;
+
+impl RemoveRequest {
+    pub async fn response(
+        server: &mut AsyncFd<OwnedFd>,
+        id: u64,
+    ) -> Result<RemoveResponse, ClientError> {
+        retry(server, Interest::WRITABLE, |server| {
+            api::RemoveRequest::send(server, id, SendFlags::DONTWAIT)
+        })
+        .await?;
+        retry(server, Interest::READABLE, |server| unsafe {
+            api::RemoveRequest::recv(server, RecvFlags::DONTWAIT)
+        })
+        .await
+        .map(|Response { value, .. }| value)
+    }
+}
+
+pub struct StatsRequest;
+
+impl StatsRequest {
+    pub async fn response(server: &mut AsyncFd<OwnedFd>) -> Result<StatsResponse, ClientError> {
+        retry(server, Interest::WRITABLE, |server| {
+            api::StatsRequest::send(server, SendFlags::DONTWAIT)
+        })
+        .await?;
+        retry(server, Interest::READABLE, |server| unsafe {
+            api::StatsRequest::recv(server, RecvFlags::DONTWAIT)
+        })
+        .await
+        .map(|Response { value, .. }| value)
+    }
+}
+
+/// Runs a nonblocking socket operation until it stops failing with
+/// [`ErrorKind::WouldBlock`], waiting for the socket to become ready in
+/// between.
+async fn retry<T>(
+    socket: &AsyncFd<OwnedFd>,
+    interest: Interest,
+    mut f: impl FnMut(BorrowedFd) -> Result<T, ClientError>,
+) -> Result<T, ClientError> {
+    loop {
+        let mut guard = socket
+            .ready(interest)
+            .await
+            .map_io_err(|| "Failed to wait for the server socket.")?;
+        match f(socket.get_ref().as_fd()) {
+            Err(ClientError::Core(ringboard_core::Error::Io { error, context: _ }))
+                if error.kind() == ErrorKind::WouldBlock =>
+            {
+                guard.clear_ready();
+            }
+            r => return r,
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Puts entries on the system clipboard for clients that don't have a
+//! Ringboard watcher to paste through, by handing them to the display server's
+//! command line clipboard tool.
+//!
+//! On Wayland that's `wl-copy` and on X11 `xclip` or, for text, `xsel`. The
+//! tools keep offering the entry in the background after they return, so
+//! copying doesn't wait for anyone to paste.
+
+use std::{
+    env, io,
+    io::Write,
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+};
+
+use thiserror::Error;
+
+use crate::{Entry, EntryReader, FileOrSlice, LoadedEntry};
+
+#[derive(Error, Debug)]
+pub enum CopyError {
+    #[error("{0}")]
+    Core(#[from] ringboard_core::Error),
+    #[error("no display server")]
+    NoDisplayServer,
+    #[error("no clipboard tool installed")]
+    NoCopier { display_server: DisplayServer },
+    #[error("failed to copy to the clipboard")]
+    Transfer {
+        copier: &'static str,
+        error: io::Error,
+    },
+}
+
+#[cfg(feature = "error-stack")]
+mod error_stack_compat {
+    use error_stack::{Context, Report};
+
+    use super::{CopyError, DisplayServer};
+
+    impl CopyError {
+        pub fn into_report<W: Context>(self, wrapper: W) -> Report<W> {
+            match self {
+                Self::Core(e) => e.into_report(wrapper),
+                Self::NoDisplayServer => Report::new(wrapper).attach_printable(
+                    "Neither WAYLAND_DISPLAY nor DISPLAY is set, so there's no clipboard to copy \
+                     to.",
+                ),
+                Self::NoCopier { display_server } => {
+                    Report::new(wrapper).attach_printable(match display_server {
+                        DisplayServer::Wayland => "Install wl-clipboard to copy entries.",
+                        DisplayServer::X11 => {
+                            "Install xclip to copy entries, or xsel to copy text entries."
+                        }
+                    })
+                }
+                Self::Transfer { copier, error } => Report::new(error)
+                    .attach_printable(format!("Clipboard tool: {copier}"))
+                    .change_context(wrapper),
+            }
+        }
+    }
+}
+
+/// The display servers whose clipboard entries can be copied to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DisplayServer {
+    Wayland,
+    X11,
+}
+
+impl DisplayServer {
+    /// The display server this process would open windows on, preferring
+    /// Wayland if both are around.
+    #[must_use]
+    pub fn detect() -> Option<Self> {
+        let is_set = |var| env::var_os(var).is_some_and(|value| !value.is_empty());
+        if is_set("WAYLAND_DISPLAY") {
+            Some(Self::Wayland)
+        } else if is_set("DISPLAY") {
+            Some(Self::X11)
+        } else {
+            None
+        }
+    }
+
+    /// The tools that can copy to this display server's clipboard, in the
+    /// order they're tried.
+    const fn copiers(self) -> &'static [Copier] {
+        match self {
+            Self::Wayland => &[Copier {
+                program: "wl-copy",
+                args: &[],
+                mime_type_flag: Some("--type"),
+            }],
+            Self::X11 => &[
+                Copier {
+                    program: "xclip",
+                    args: &["-selection", "clipboard"],
+                    mime_type_flag: Some("-t"),
+                },
+                Copier {
+                    program: "xsel",
+                    args: &["--clipboard", "--input"],
+                    mime_type_flag: None,
+                },
+            ],
+        }
+    }
+}
+
+/// A clipboard tool that reads what to copy from its stdin.
+struct Copier {
+    program: &'static str,
+    args: &'static [&'static str],
+    /// How to offer the contents as a particular mime type, if the tool can.
+    /// Tools that can't only copy text.
+    mime_type_flag: Option<&'static str>,
+}
+
+/// Puts an entry on the system clipboard, offered as its mime type.
+///
+/// Entries stored in their own file are streamed to the clipboard tool rather
+/// than read into memory first.
+pub fn copy_entry(entry: &Entry, reader: &EntryReader) -> Result<(), CopyError> {
+    copy(entry.to_reader(reader)?)
+}
+
+/// Like [`copy_entry`] for an entry that was already loaded, e.g. through an
+/// [`EntrySource`](crate::EntrySource).
+pub fn copy(entry: LoadedEntry<FileOrSlice>) -> Result<(), CopyError> {
+    let display_server = DisplayServer::detect().ok_or(CopyError::NoDisplayServer)?;
+    let mime_type = entry.mime_type()?;
+    let is_text = mime_type.is_empty() || mime_type.starts_with("text/plain");
+    let contents = entry.into_inner();
+
+    for copier in display_server.copiers() {
+        let mut command = Command::new(copier.program);
+        command.args(copier.args);
+        match copier.mime_type_flag {
+            // Without a type, the tools offer the usual text targets.
+            Some(flag) if !mime_type.is_empty() => {
+                command.arg(flag).arg(&*mime_type);
+            }
+            Some(_) => {}
+            None if is_text => {}
+            None => continue,
+        }
+        let stdin = match &contents {
+            FileOrSlice::File(file) => file.try_clone().map(Stdio::from),
+            FileOrSlice::Slice(_) => Ok(Stdio::piped()),
+        };
+        let transfer = |error| CopyError::Transfer {
+            copier: copier.program,
+            error,
+        };
+        let mut child = match stdin.and_then(|stdin| {
+            command
+                .stdin(stdin)
+                // The tool's background process inherits these and would keep
+                // them open for as long as it owns the clipboard.
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                // Keep terminal signals like Ctrl+C meant for the client away from the tool.
+                .process_group(0)
+                .spawn()
+        }) {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(transfer(e)),
+        };
+
+        if let (FileOrSlice::Slice(bytes), Some(mut stdin)) = (&contents, child.stdin.take()) {
+            stdin.write_all(bytes.get_ref()).map_err(transfer)?;
+        }
+        let status = child.wait().map_err(transfer)?;
+        if !status.success() {
+            return Err(transfer(io::Error::other(format!(
+                "{} exited with {status}",
+                copier.program
+            ))));
+        }
+        return Ok(());
+    }
+    Err(CopyError::NoCopier { display_server })
+}
@@ -1,22 +1,156 @@
 use std::{
-    collections::BTreeMap,
-    hash::{Hash, Hasher},
+    borrow::Cow,
+    collections::HashMap,
+    hash::{BuildHasherDefault, Hash, Hasher},
+    num::NonZeroUsize,
+    str, thread,
 };
 
-use ringboard_core::{protocol::IdNotFoundError, ring::Mmap, IoErr, RingAndIndex};
+use ringboard_core::{
+    protocol::{decompose_id, IdNotFoundError},
+    ring::Mmap,
+    IoErr, RingAndIndex,
+};
 use rustc_hash::FxHasher;
 use rustix::fs::{statx, AtFlags, StatxFlags};
 use smallvec::SmallVec;
 
 use crate::{DatabaseReader, Entry, EntryReader, Kind};
 
+type Hashes = HashMap<u32, SmallVec<RingAndIndex, 4>, BuildHasherDefault<FxHasher>>;
+
 #[derive(Default)]
 pub struct DuplicateDetector {
-    hashes: BTreeMap<u32, SmallVec<RingAndIndex, 4>>,
+    hashes: Hashes,
 }
 
+/// Entries at least this big are hashed by their length alone so they don't
+/// have to be read unless another entry has the same length.
+const LARGE_ENTRY_LEN: u64 = 4096;
+
 const _: () = assert!(size_of::<SmallVec<RingAndIndex, 4>>() <= size_of::<Vec<RingAndIndex>>());
 
+/// Entries sharing identical content.
+///
+/// Entries are listed in the order they were found, so any favorites come
+/// first and each ring's newest copy precedes older ones.
+#[derive(Debug)]
+pub struct DuplicateGroup {
+    pub entries: Vec<Entry>,
+}
+
+/// What counts as identical content.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Matching {
+    /// The same bytes.
+    #[default]
+    Exact,
+    /// The same text ignoring case, leading and trailing whitespace, and how
+    /// long runs of whitespace are. Entries that aren't UTF-8 text must still
+    /// match byte for byte.
+    Normalized,
+}
+
+impl Matching {
+    fn apply(self, data: &[u8]) -> Cow<'_, [u8]> {
+        match self {
+            Self::Exact => Cow::Borrowed(data),
+            Self::Normalized => normalize(data),
+        }
+    }
+}
+
+/// Finds every group of entries with identical content in the database.
+///
+/// Entries are hashed on every core up front, keeping only their locations
+/// and hashes in memory. Entries with the same hash are then read back and
+/// compared byte for byte as groups are pulled from the iterator, so hash
+/// collisions never pass for duplicates. Entries that can't be read back are
+/// left out.
+pub fn find_duplicates<'a>(
+    reader: &'a EntryReader,
+    database: &DatabaseReader,
+    matching: Matching,
+) -> Result<impl Iterator<Item = DuplicateGroup> + 'a, ringboard_core::Error> {
+    let entries = database
+        .favorites()
+        .rev()
+        .chain(database.main().rev())
+        .collect::<Vec<_>>();
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let hashes = thread::scope(|scope| {
+        #[allow(clippy::needless_collect)] // Every hasher has to start before any is joined.
+        let hashers = entries
+            .chunks(entries.len().div_ceil(threads).max(1))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&entry| match matching {
+                            Matching::Exact => content_hash(entry, reader),
+                            Matching::Normalized => entry
+                                .to_slice(reader)
+                                .map(|data| hash_all(&normalize(&data))),
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        hashers
+            .into_iter()
+            .map(|hasher| hasher.join().unwrap())
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    let mut hashes = hashes.into_iter().flatten().zip(0..).collect::<Vec<_>>();
+    hashes.sort_unstable();
+    let mut candidates = hashes
+        .chunk_by(|(a, _), (b, _)| a == b)
+        .filter(|same_hash| same_hash.len() > 1)
+        .collect::<Vec<_>>();
+    candidates.sort_unstable_by_key(|same_hash| same_hash[0].1);
+    #[allow(clippy::needless_collect)] // The groups borrow locals the iterator can't outlive.
+    let candidates = candidates
+        .into_iter()
+        .map(|same_hash| same_hash.iter().map(|&(_, i)| entries[i]).collect())
+        .collect::<Vec<_>>();
+
+    Ok(candidates
+        .into_iter()
+        .flat_map(move |same_hash| confirm_duplicates(same_hash, reader, matching)))
+}
+
+/// Splits entries with the same hash into groups whose contents really are
+/// identical, keeping the order they were listed in.
+fn confirm_duplicates(
+    mut candidates: Vec<Entry>,
+    reader: &EntryReader,
+    matching: Matching,
+) -> Vec<DuplicateGroup> {
+    let mut groups = Vec::new();
+    while candidates.len() > 1 {
+        let first = candidates.remove(0);
+        let Ok(data) = first.to_slice(reader) else {
+            continue;
+        };
+        let data = matching.apply(&data);
+        let mut entries = vec![first];
+        candidates.retain(|&candidate| {
+            let same = candidate
+                .to_slice(reader)
+                .is_ok_and(|candidate| matching.apply(&candidate) == data);
+            if same {
+                entries.push(candidate);
+            }
+            !same
+        });
+        if entries.len() > 1 {
+            groups.push(DuplicateGroup { entries });
+        }
+    }
+    groups
+}
+
 impl DuplicateDetector {
     pub fn add_entry(
         &mut self,
@@ -24,46 +158,171 @@ impl DuplicateDetector {
         database: &DatabaseReader,
         reader: &mut EntryReader,
     ) -> Result<bool, ringboard_core::Error> {
-        let hash = {
-            let mut data_hasher = FxHasher::default();
-            match entry.kind() {
-                Kind::Bucket(_) => entry.to_slice(reader)?.hash(&mut data_hasher),
-                Kind::File => {
-                    let file = entry.to_file(reader)?;
-                    let len = statx(&*file, c"", AtFlags::EMPTY_PATH, StatxFlags::SIZE)
-                        .map_io_err(|| format!("Failed to statx file: {file:?}"))?
-                        .stx_size;
-
-                    if len >= 4096 {
-                        len.hash(&mut data_hasher);
-                    } else {
-                        Mmap::from(&*file)
-                            .map_io_err(|| format!("Failed to mmap file: {file:?}"))?
-                            .hash(&mut data_hasher);
-                    }
-                }
-            }
-            data_hasher.finish()
-        };
+        self.find_duplicate(entry, database, reader)
+            .map(|original| original.is_some())
+    }
 
-        let entries = self
-            .hashes
-            .entry(u32::try_from(hash & u64::from(u32::MAX)).unwrap())
-            .or_default();
+    /// Returns the previously added entry with the same content as this one,
+    /// or adds this entry if it is unique.
+    pub fn find_duplicate(
+        &mut self,
+        entry: &Entry,
+        database: &DatabaseReader,
+        reader: &mut EntryReader,
+    ) -> Result<Option<RingAndIndex>, ringboard_core::Error> {
+        let hash = content_hash(*entry, reader)?;
+        let entries = self.hashes.entry(hash).or_default();
         if !entries.is_empty() {
             let data = entry.to_slice_raw(reader)?.unwrap();
-            for &entry in &*entries {
-                let entry = database.get_raw(entry.id())?;
+            for &original in &*entries {
+                let entry = database.get_raw(original.id())?;
                 if **data
                     == **entry
                         .to_slice_raw(reader)?
                         .ok_or_else(|| IdNotFoundError::Entry(entry.index()))?
                 {
-                    return Ok(true);
+                    return Ok(Some(original));
                 }
             }
         }
         entries.push(RingAndIndex::new(entry.ring(), entry.index()));
-        Ok(false)
+        Ok(None)
+    }
+
+    /// Starts an import of many entries, indexing the database's current
+    /// entries once up front so each imported entry can be checked in constant
+    /// time.
+    pub fn begin_bulk<'a>(
+        &'a mut self,
+        database: &'a mut DatabaseReader,
+        reader: &'a mut EntryReader,
+    ) -> Result<BulkSession<'a>, ringboard_core::Error> {
+        for entry in database.favorites().rev().chain(database.main().rev()) {
+            self.find_duplicate(&entry, database, reader)?;
+        }
+        Ok(BulkSession {
+            detector: self,
+            added: Hashes::default(),
+            database,
+            reader,
+        })
+    }
+}
+
+/// Checks entries being imported against the database and each other.
+///
+/// Only hashes and entry locations are kept in memory. Entries with the same
+/// hash are told apart by reading them back from the database.
+pub struct BulkSession<'a> {
+    detector: &'a mut DuplicateDetector,
+    added: Hashes,
+    database: &'a mut DatabaseReader,
+    reader: &'a mut EntryReader,
+}
+
+impl BulkSession<'_> {
+    /// Returns the ID of an existing or previously imported entry with this
+    /// content.
+    pub fn check(&mut self, data: &[u8]) -> Result<Option<u64>, ringboard_core::Error> {
+        let hash = hash_bytes(data);
+        let candidates = [&self.detector.hashes, &self.added]
+            .into_iter()
+            .filter_map(|hashes| hashes.get(&hash))
+            .flatten()
+            .copied()
+            .collect::<SmallVec<RingAndIndex, 4>>();
+        for candidate in candidates {
+            // SAFETY: candidates were either read from the database or added to
+            // it by the server.
+            let Ok(entry) = (unsafe { self.database.get(candidate.id()) }) else {
+                continue;
+            };
+            if **entry.to_slice(self.reader)? == *data {
+                return Ok(Some(candidate.id()));
+            }
+        }
+        Ok(None)
     }
+
+    /// Remembers an entry the import added so later checks find it.
+    pub fn record(&mut self, data: &[u8], id: u64) -> Result<(), IdNotFoundError> {
+        let (ring, index) = decompose_id(id)?;
+        self.added
+            .entry(hash_bytes(data))
+            .or_default()
+            .push(RingAndIndex::new(ring, index));
+        Ok(())
+    }
+
+    /// Keeps the imported entries in the detector once the import is done.
+    pub fn commit(self) {
+        for (hash, entries) in self.added {
+            self.detector
+                .hashes
+                .entry(hash)
+                .or_default()
+                .extend(entries);
+        }
+    }
+}
+
+fn content_hash(entry: Entry, reader: &EntryReader) -> Result<u32, ringboard_core::Error> {
+    Ok(match entry.kind() {
+        Kind::Bucket(_) => hash_bytes(&entry.to_slice(reader)?),
+        Kind::File => {
+            let file = entry.to_file(reader)?;
+            let len = statx(&*file, c"", AtFlags::EMPTY_PATH, StatxFlags::SIZE)
+                .map_io_err(|| format!("Failed to statx file: {file:?}"))?
+                .stx_size;
+
+            if len >= LARGE_ENTRY_LEN {
+                hash_len(len)
+            } else {
+                hash_bytes(
+                    &Mmap::from(&*file).map_io_err(|| format!("Failed to mmap file: {file:?}"))?,
+                )
+            }
+        }
+    })
+}
+
+fn hash_bytes(data: &[u8]) -> u32 {
+    let len = u64::try_from(data.len()).unwrap();
+    if len >= LARGE_ENTRY_LEN {
+        return hash_len(len);
+    }
+    hash_all(data)
+}
+
+/// Lowercases text and collapses its whitespace, leaving other data as is.
+fn normalize(data: &[u8]) -> Cow<'_, [u8]> {
+    let Ok(text) = str::from_utf8(data) else {
+        return Cow::Borrowed(data);
+    };
+    let mut normalized = String::with_capacity(text.len());
+    for word in text.split_whitespace() {
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        normalized.extend(word.chars().flat_map(char::to_lowercase));
+    }
+    Cow::Owned(normalized.into_bytes())
+}
+
+/// Hashes every byte, which normalized data needs since normalizing changes
+/// lengths.
+fn hash_all(data: &[u8]) -> u32 {
+    let mut hasher = FxHasher::default();
+    data.hash(&mut hasher);
+    truncate_hash(hasher.finish())
+}
+
+fn hash_len(len: u64) -> u32 {
+    let mut hasher = FxHasher::default();
+    len.hash(&mut hasher);
+    truncate_hash(hasher.finish())
+}
+
+fn truncate_hash(hash: u64) -> u32 {
+    u32::try_from(hash & u64::from(u32::MAX)).unwrap()
 }
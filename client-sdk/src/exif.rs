@@ -0,0 +1,280 @@
+//! Just enough of an EXIF parser to tell where a photo came from.
+//!
+//! Photos come from arbitrary clipboard contents, so anything malformed is
+//! skipped rather than reported.
+
+use std::str;
+
+const MAKE: u16 = 0x010F;
+const MODEL: u16 = 0x0110;
+const DATE_TIME: u16 = 0x0132;
+const EXIF_IFD: u16 = 0x8769;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+const ASCII: u16 = 2;
+const LONG: u16 = 4;
+
+#[derive(Default, Debug, Eq, PartialEq)]
+pub struct Exif {
+    /// When the photo was taken, e.g. `2024-05-06 07:08:09`.
+    pub taken: Option<Box<str>>,
+    /// The camera's make and model.
+    pub camera: Option<Box<str>>,
+}
+
+/// Reads the EXIF data embedded in a JPEG.
+pub fn parse_jpeg(data: &[u8]) -> Exif {
+    exif_segment(data)
+        .and_then(Tiff::new)
+        .and_then(|tiff| tiff.exif())
+        .unwrap_or_default()
+}
+
+fn exif_segment(data: &[u8]) -> Option<&[u8]> {
+    let mut rest = data.strip_prefix(&[0xFF, 0xD8])?;
+    loop {
+        let [0xFF, marker, ref tail @ ..] = *rest else {
+            return None;
+        };
+        match marker {
+            // Fill bytes before the actual marker.
+            0xFF => {
+                rest = &rest[1..];
+                continue;
+            }
+            // Markers without a length.
+            0x01 | 0xD0..=0xD7 => {
+                rest = tail;
+                continue;
+            }
+            // Metadata only comes before the image data.
+            0xD9 | 0xDA => return None,
+            _ => {}
+        }
+
+        // The length includes its own two bytes.
+        let len = usize::from(u16::from_be_bytes(tail.get(..2)?.try_into().unwrap()));
+        let segment = tail.get(2..len)?;
+        if marker == 0xE1 {
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                return Some(tiff);
+            }
+        }
+        rest = &tail[len..];
+    }
+}
+
+/// EXIF data is laid out like a TIFF file: directories of tagged values
+/// pointing at each other by offset from the start of the data.
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(..2)? {
+            b"II" => false,
+            b"MM" => true,
+            _ => return None,
+        };
+        let me = Self { data, big_endian };
+        (me.u16(2)? == 42).then_some(me)
+    }
+
+    fn exif(&self) -> Option<Exif> {
+        let ifd0 = self.offset(4)?;
+        let taken = self
+            .entry(ifd0, EXIF_IFD)
+            .filter(|&entry| self.u16(entry + 2) == Some(LONG))
+            .and_then(|entry| self.offset(entry + 8))
+            .and_then(|ifd| self.ascii(ifd, DATE_TIME_ORIGINAL))
+            .or_else(|| self.ascii(ifd0, DATE_TIME))
+            .map(format_date);
+        let camera = match (self.ascii(ifd0, MAKE), self.ascii(ifd0, MODEL)) {
+            (Some(make), Some(model)) if model.starts_with(&*make) => Some(model),
+            (Some(make), Some(model)) => Some(format!("{make} {model}").into()),
+            (make, model) => make.or(model),
+        };
+        Some(Exif { taken, camera })
+    }
+
+    /// Finds the entry for `tag` in the directory at `ifd`.
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        (0..usize::from(self.u16(ifd)?))
+            .map(|i| ifd + 2 + 12 * i)
+            .find(|&entry| self.u16(entry) == Some(tag))
+    }
+
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<Box<str>> {
+        let entry = self.entry(ifd, tag)?;
+        if self.u16(entry + 2)? != ASCII {
+            return None;
+        }
+        let len = usize::try_from(self.u32(entry + 4)?).ok()?;
+        // Values that fit are stored in place of their offset.
+        let start = if len <= 4 {
+            entry + 8
+        } else {
+            self.offset(entry + 8)?
+        };
+
+        let text = str::from_utf8(self.data.get(start..start.checked_add(len)?)?).ok()?;
+        let text = text.trim_end_matches('\0').trim();
+        (!text.is_empty()).then(|| text.into())
+    }
+
+    fn offset(&self, at: usize) -> Option<usize> {
+        usize::try_from(self.u32(at)?).ok()
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at.checked_add(2)?)?.try_into().unwrap();
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at.checked_add(4)?)?.try_into().unwrap();
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+/// EXIF separates the parts of dates with colons, e.g. `2024:05:06 07:08:09`.
+fn format_date(date: Box<str>) -> Box<str> {
+    let mut date = String::from(date);
+    for i in [4, 7] {
+        if date.get(i..=i) == Some(":") {
+            date.replace_range(i..=i, "-");
+        }
+    }
+    date.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_jpeg, Exif, ASCII, DATE_TIME, DATE_TIME_ORIGINAL, EXIF_IFD, LONG, MAKE, MODEL};
+
+    /// A JPEG with only an EXIF segment, holding the `entries` in its first
+    /// directory and the `exif_entries` in its EXIF directory. The values
+    /// must be too long to be stored in place of their offset.
+    fn jpeg(entries: &[(u16, &str)], exif_entries: &[(u16, &str)]) -> Vec<u8> {
+        const fn directory_len(entries: usize) -> usize {
+            2 + 12 * entries + 4
+        }
+
+        let ifd0 = 8;
+        let exif_ifd = ifd0 + directory_len(entries.len() + 1);
+        let mut strings = Vec::new();
+        let mut directory = |entries: &[(u16, &str)], end: usize| {
+            let mut directory = entries
+                .iter()
+                .map(|&(tag, value)| {
+                    let offset = end + strings.len();
+                    strings.extend_from_slice(value.as_bytes());
+                    strings.push(0);
+                    (tag, ASCII, value.len() + 1, offset)
+                })
+                .collect::<Vec<_>>();
+            directory.sort_unstable();
+            directory
+        };
+        let strings_start = exif_ifd + directory_len(exif_entries.len());
+        let mut ifd0_entries = directory(entries, strings_start);
+        ifd0_entries.push((EXIF_IFD, LONG, 1, exif_ifd));
+        let exif_entries = directory(exif_entries, strings_start);
+
+        let mut tiff = b"MM\0\x2A".to_vec();
+        let push = |tiff: &mut Vec<u8>, value: usize| {
+            tiff.extend_from_slice(&u32::try_from(value).unwrap().to_be_bytes());
+        };
+        push(&mut tiff, ifd0);
+        for directory in [ifd0_entries, exif_entries] {
+            tiff.extend_from_slice(&u16::try_from(directory.len()).unwrap().to_be_bytes());
+            for (tag, kind, count, value) in directory {
+                tiff.extend_from_slice(&tag.to_be_bytes());
+                tiff.extend_from_slice(&kind.to_be_bytes());
+                push(&mut tiff, count);
+                push(&mut tiff, value);
+            }
+            push(&mut tiff, 0);
+        }
+        assert_eq!(tiff.len(), strings_start);
+        tiff.extend_from_slice(&strings);
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&u16::try_from(2 + 6 + tiff.len()).unwrap().to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0, 2]);
+        jpeg
+    }
+
+    fn exif(taken: &str, camera: &str) -> Exif {
+        Exif {
+            taken: Some(taken.into()),
+            camera: Some(camera.into()),
+        }
+    }
+
+    #[test]
+    fn photos_say_when_and_with_what_they_were_taken() {
+        let jpeg = jpeg(
+            &[
+                (MAKE, "Canon"),
+                (MODEL, "PowerShot G7"),
+                (DATE_TIME, "2024:05:07 00:00:00"),
+            ],
+            &[(DATE_TIME_ORIGINAL, "2024:05:06 07:08:09")],
+        );
+
+        assert_eq!(
+            parse_jpeg(&jpeg),
+            exif("2024-05-06 07:08:09", "Canon PowerShot G7")
+        );
+    }
+
+    #[test]
+    fn make_is_not_repeated() {
+        let jpeg = jpeg(
+            &[
+                (MAKE, "Canon"),
+                (MODEL, "Canon EOS 5D"),
+                (DATE_TIME, "2024:05:07 10:11:12"),
+            ],
+            &[],
+        );
+
+        assert_eq!(
+            parse_jpeg(&jpeg),
+            exif("2024-05-07 10:11:12", "Canon EOS 5D")
+        );
+    }
+
+    #[test]
+    fn malformed_data_is_ignored() {
+        let jpeg = jpeg(
+            &[(MAKE, "Canon"), (MODEL, "PowerShot G7")],
+            &[(DATE_TIME_ORIGINAL, "2024:05:06 07:08:09")],
+        );
+
+        for len in 0..jpeg.len() {
+            parse_jpeg(&jpeg[..len]);
+        }
+        for i in 0..jpeg.len() {
+            for flip in [0x01, 0x80, 0xFF] {
+                let mut jpeg = jpeg.clone();
+                jpeg[i] ^= flip;
+                parse_jpeg(&jpeg);
+            }
+        }
+        assert_eq!(parse_jpeg(b"\x89PNG"), Exif::default());
+    }
+}
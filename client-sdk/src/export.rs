@@ -0,0 +1,194 @@
+//! Exports the database as JSON lines and reads such exports back.
+//!
+//! An export starts with a header line naming the format and its version,
+//! followed by one line per entry:
+//!
+//! ```json
+//! {"format":"ringboard-export","version":1}
+//! {"id":4294967296,"ring":"main","mime_type":"text/plain","text":"Hello"}
+//! {"id":4294967297,"ring":"main","mime_type":"image/png","base64":"iVBORw0KGgo="}
+//! ```
+//!
+//! Entries are listed favorites first and each ring from oldest to newest, so
+//! adding them back in order preserves their relative order. Text is written
+//! as is and anything else is base64 encoded.
+
+use std::{
+    borrow::Cow,
+    io,
+    io::{BufRead, Write},
+    str,
+};
+
+use base64::{engine::general_purpose::STANDARD, write::EncoderWriter, Engine};
+use ringboard_core::{
+    protocol::{MimeType, RingKind},
+    IoErr,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{ClientError, DatabaseReader, Entry, EntryReader};
+
+/// Identifies exports in their header.
+pub const FORMAT: &str = "ringboard-export";
+
+/// The newest export format version. Readers accept every version up to this
+/// one.
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Header<'a> {
+    format: Cow<'a, str>,
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Ring {
+    Favorites,
+    Main,
+}
+
+#[derive(Deserialize)]
+struct Record {
+    id: u64,
+    ring: Ring,
+    #[serde(default)]
+    mime_type: String,
+    text: Option<String>,
+    base64: Option<String>,
+}
+
+/// An entry read back from an export.
+#[derive(Debug)]
+pub struct ExportedEntry {
+    /// The entry's id in the exported database. Imported entries get new ids.
+    pub id: u64,
+    pub ring: RingKind,
+    pub mime_type: MimeType,
+    pub data: Vec<u8>,
+}
+
+/// Writes every entry in the database to `out`.
+///
+/// Entries are written straight from the database files, so large ones aren't
+/// buffered in memory.
+pub fn export(
+    database: &DatabaseReader,
+    reader: &EntryReader,
+    mut out: impl Write,
+) -> Result<(), ClientError> {
+    serde_json::to_writer(
+        &mut out,
+        &Header {
+            format: FORMAT.into(),
+            version: VERSION,
+        },
+    )
+    .map_err(io::Error::from)
+    .and_then(|()| out.write_all(b"\n"))
+    .map_io_err(|| "Failed to write export header.")?;
+
+    for entry in database.favorites().chain(database.main()) {
+        write_entry(&mut out, entry, reader)?;
+    }
+    out.flush().map_io_err(|| "Failed to write export.")?;
+    Ok(())
+}
+
+fn write_entry(
+    out: &mut impl Write,
+    entry: Entry,
+    reader: &EntryReader,
+) -> Result<(), ClientError> {
+    let loaded = entry.to_slice(reader)?;
+    let mime_type = loaded.mime_type()?;
+    let ring = match entry.ring() {
+        RingKind::Favorites => Ring::Favorites,
+        RingKind::Main => Ring::Main,
+    };
+
+    let mut write = || -> io::Result<()> {
+        write!(out, r#"{{"id":{},"ring":"#, entry.id())?;
+        serde_json::to_writer(&mut *out, &ring)?;
+        out.write_all(br#","mime_type":"#)?;
+        serde_json::to_writer(&mut *out, &*mime_type)?;
+        if let Ok(text) = str::from_utf8(&loaded) {
+            out.write_all(br#","text":"#)?;
+            serde_json::to_writer(&mut *out, text)?;
+        } else {
+            out.write_all(br#","base64":""#)?;
+            {
+                let mut encoder = EncoderWriter::new(&mut *out, &STANDARD);
+                encoder.write_all(&loaded)?;
+                encoder.finish()?;
+            }
+            out.write_all(b"\"")?;
+        }
+        out.write_all(b"}\n")
+    };
+    write().map_io_err(|| format!("Failed to export entry {}.", entry.id()))?;
+    Ok(())
+}
+
+/// Reads an export written by [`export`], checking its header up front.
+///
+/// Each entry is decoded as it's reached, so only one is held in memory at a
+/// time.
+pub fn read(
+    input: impl BufRead,
+) -> Result<impl Iterator<Item = Result<ExportedEntry, ClientError>>, ClientError> {
+    let mut lines = input.lines().zip(1..);
+    let (header, _) = lines.next().ok_or_else(|| invalid(1, "Missing header."))?;
+    let header = header.map_io_err(|| "Failed to read export header.")?;
+    let Header { format, version } =
+        serde_json::from_str(&header).map_err(|e| invalid(1, format!("Bad header: {e}")))?;
+    if format != FORMAT {
+        return Err(invalid(1, format!("Unknown format {format:?}.")));
+    }
+    if version > VERSION {
+        return Err(ClientError::UnsupportedExportVersion { version });
+    }
+
+    Ok(lines
+        .filter(|(line, _)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(line, number)| {
+            let line = line.map_io_err(|| "Failed to read export.")?;
+            parse_entry(&line, number)
+        }))
+}
+
+fn parse_entry(line: &str, number: usize) -> Result<ExportedEntry, ClientError> {
+    let Record {
+        id,
+        ring,
+        mime_type,
+        text,
+        base64,
+    } = serde_json::from_str(line).map_err(|e| invalid(number, e.to_string()))?;
+    let data = match (text, base64) {
+        (Some(text), None) => text.into_bytes(),
+        (None, Some(base64)) => STANDARD
+            .decode(base64)
+            .map_err(|e| invalid(number, format!("Bad base64 data: {e}")))?,
+        _ => return Err(invalid(number, "Expected either text or base64 data.")),
+    };
+    let mime_type = MimeType::from(&mime_type)
+        .map_err(|_| invalid(number, format!("Mime type too long: {mime_type:?}")))?;
+    Ok(ExportedEntry {
+        id,
+        ring: match ring {
+            Ring::Favorites => RingKind::Favorites,
+            Ring::Main => RingKind::Main,
+        },
+        mime_type,
+        data,
+    })
+}
+
+fn invalid(line: usize, context: impl Into<Cow<'static, str>>) -> ClientError {
+    ClientError::InvalidExport {
+        line,
+        context: context.into(),
+    }
+}
@@ -1,20 +1,43 @@
 use std::borrow::Cow;
 
-pub use ring_reader::{DatabaseReader, Entry, EntryReader, Kind, LoadedEntry, RingReader};
+pub use preview::{line_count, lossy_one_liner, one_liner, sniff_mime, text_kind, TextKind};
+pub use remote_reader::{EntrySource, RemoteReader};
+pub use ring_reader::{
+    decode_direct_file, group_by_bucket, DatabaseReader, Entry, EntryReader, FileOrSlice, Kind,
+    LoadedContents, LoadedEntry, MergedReader, MmapOrSlice, ResumeError, RingReader,
+};
+pub use watch::DatabaseWatcher;
 pub use ringboard_core as core;
-use ringboard_core::protocol::IdNotFoundError;
+pub use ringboard_core::protocol::{format_id, parse_id};
+use ringboard_core::protocol::{ErrorCode, IdNotFoundError, TraceId};
 #[cfg(feature = "search")]
 pub use search::search;
 use thiserror::Error;
+#[cfg(feature = "zeroize")]
+pub use zeroize::Zeroizing;
 
 pub mod api;
+pub mod clipboard;
 #[cfg(feature = "deduplication")]
 pub mod duplicate_detection;
+#[cfg(feature = "exif")]
+mod exif;
+#[cfg(feature = "export")]
+pub mod export;
+pub mod preview;
+mod remote_reader;
 mod ring_reader;
 #[cfg(feature = "search")]
 pub mod search;
+#[cfg(feature = "testing")]
+pub mod testing;
 #[cfg(feature = "ui")]
 pub mod ui_actor;
+mod watch;
+mod wipe;
+
+/// The version of this SDK.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Error, Debug)]
 pub enum ClientError {
@@ -22,8 +45,59 @@ pub enum ClientError {
     Core(#[from] ringboard_core::Error),
     #[error("protocol version mismatch")]
     VersionMismatch { actual: u8 },
-    #[error("invalid server response")]
-    InvalidResponse { context: Cow<'static, str> },
+    #[error(
+        "invalid server response{}",
+        .trace.map_or_else(String::new, |trace| format!(" (trace {trace})"))
+    )]
+    InvalidResponse {
+        context: Cow<'static, str>,
+        /// The request's id in the server logs if the server got far enough to
+        /// send one.
+        trace: Option<TraceId>,
+    },
+    #[error("database full")]
+    DatabaseFull,
+    #[error("favorites full")]
+    FavoritesFull,
+    #[error("unsupported entry data")]
+    UnsupportedData,
+    #[error("empty entry")]
+    EmptyEntry,
+    #[error("entry larger than {max} bytes")]
+    EntryTooLarge { max: u64 },
+    #[error("server too old for this request")]
+    UnsupportedByServer {
+        tag: u32,
+        /// Missing if the request was never sent because the server said it
+        /// didn't know it while connecting.
+        trace: Option<TraceId>,
+    },
+    #[error("server is rate limiting connections")]
+    RateLimited,
+    #[error("server refused a client running as another user")]
+    PermissionDenied,
+    #[error("invalid export on line {line}")]
+    InvalidExport {
+        line: usize,
+        context: Cow<'static, str>,
+    },
+    #[error("unsupported export version {version}")]
+    UnsupportedExportVersion { version: u32 },
+    #[error("invalid search query")]
+    InvalidQuery { context: Cow<'static, str> },
+    #[error(
+        "server failed to search the database{}",
+        .trace.map_or_else(String::new, |trace| format!(" (trace {trace})"))
+    )]
+    SearchFailed { trace: Option<TraceId> },
+    /// The server couldn't carry out the request but carries on otherwise.
+    #[error("{code}")]
+    Server {
+        code: ErrorCode,
+        context: Cow<'static, str>,
+        /// The request's id in the server logs.
+        trace: TraceId,
+    },
 }
 
 impl From<IdNotFoundError> for ClientError {
@@ -43,9 +117,75 @@ mod error_stack_compat {
         pub fn into_report<W: Context>(self, wrapper: W) -> Report<W> {
             match self {
                 Self::Core(e) => e.into_report(wrapper),
-                Self::InvalidResponse { context } => Report::new(wrapper).attach_printable(context),
+                Self::InvalidResponse { context, trace } => {
+                    let report = Report::new(wrapper).attach_printable(context);
+                    if let Some(trace) = trace {
+                        report.attach_printable(format!("Server trace: {trace}"))
+                    } else {
+                        report
+                    }
+                }
+                Self::DatabaseFull => Report::new(wrapper)
+                    .attach_printable("The server ran out of disk space while adding the entry."),
+                Self::FavoritesFull => Report::new(wrapper).attach_printable(
+                    "Favoriting another entry would overwrite the oldest favorite, so remove it \
+                     first.",
+                ),
+                Self::UnsupportedData => Report::new(wrapper).attach_printable(
+                    "The server won't read the entry's data from this kind of file.",
+                ),
+                Self::EmptyEntry => Report::new(wrapper)
+                    .attach_printable("The server won't store entries without data."),
+                Self::EntryTooLarge { max } => Report::new(wrapper).attach_printable(format!(
+                    "The server won't store entries larger than {max} bytes. Raise its \
+                     max_entry_size setting to allow them."
+                )),
+                Self::UnsupportedByServer { tag, trace } => {
+                    let report = Report::new(wrapper).attach_printable(format!(
+                        "The server doesn't know request {tag}, so it's probably older than this \
+                         client."
+                    ));
+                    if let Some(trace) = trace {
+                        report.attach_printable(format!("Server trace: {trace}"))
+                    } else {
+                        report
+                    }
+                }
+                Self::RateLimited => Report::new(wrapper).attach_printable(
+                    "Too many connections were opened recently. Try again in a moment.",
+                ),
+                Self::PermissionDenied => Report::new(wrapper).attach_printable(
+                    "Each user runs their own server: connect as the user that started it.",
+                ),
+                Self::InvalidExport { line, context } => {
+                    Report::new(wrapper).attach_printable(format!("Line {line}: {context}"))
+                }
+                Self::UnsupportedExportVersion { version } => Report::new(wrapper)
+                    .attach_printable(format!(
+                        "Export version {version} is newer than this version of Ringboard can \
+                         read. Upgrade Ringboard to import it."
+                    )),
+                Self::InvalidQuery { context } => Report::new(wrapper).attach_printable(context),
+                Self::SearchFailed { trace } => {
+                    let report = Report::new(wrapper).attach_printable(
+                        "The server couldn't read part of its database, so matches may be missing.",
+                    );
+                    if let Some(trace) = trace {
+                        report.attach_printable(format!("Server trace: {trace}"))
+                    } else {
+                        report
+                    }
+                }
+                Self::Server {
+                    code: _,
+                    context,
+                    trace,
+                } => Report::new(wrapper)
+                    .attach_printable(context)
+                    .attach_printable(format!("Server trace: {trace}")),
                 Self::VersionMismatch { actual } => Report::new(wrapper).attach_printable(format!(
-                    "Expected v{} but got v{actual}.",
+                    "Expected v{} through v{} but got v{actual}.",
+                    protocol::MIN_VERSION,
                     protocol::VERSION
                 )),
             }
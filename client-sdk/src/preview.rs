@@ -0,0 +1,541 @@
+//! Turns entries into something displayable.
+
+#[cfg(feature = "preview")]
+use std::io::Cursor;
+use std::{cmp::min, env, ops::Range, path::Path, str};
+
+#[cfg(feature = "preview")]
+use image::{ImageFormat, ImageReader};
+
+use crate::core::protocol::MimeType;
+#[cfg(feature = "preview")]
+use crate::{
+    core::{Error as CoreError, IoErr},
+    Entry, EntryReader, Kind, LoadedEntry, MmapOrSlice,
+};
+
+/// Enough text to fill a row of the widest terminals, which UIs cut down to
+/// the width they have.
+const MAX_SOURCE_LEN: usize = 512;
+/// Lines past this point aren't counted so huge entries don't slow down list
+/// loading.
+const MAX_COUNTED_LEN: usize = 1 << 20;
+/// How much of an entry [`sniff_mime`] looks at, so classifying a page of
+/// entries costs the same however large they are.
+const MAX_SNIFFED_LEN: usize = 512;
+
+/// Builds the single line shown for a text entry in lists.
+///
+/// That's its first 512 bytes with surrounding whitespace dropped and runs of
+/// whitespace collapsed into one space, followed by `…` if the entry is longer.
+/// Returns [`None`] if `data` isn't UTF-8. Otherwise, `matched` (a byte range
+/// into `data`) is moved to where it ended up in the one-liner.
+#[must_use]
+pub fn one_liner(
+    data: &[u8],
+    matched: Option<Range<usize>>,
+) -> Option<(String, Option<Range<usize>>)> {
+    let s = match str::from_utf8(&data[..min(data.len(), MAX_SOURCE_LEN)]) {
+        Ok(s) => s,
+        Err(e) if e.error_len().is_none() => unsafe {
+            str::from_utf8_unchecked(&data[..e.valid_up_to()])
+        },
+        Err(_) => return None,
+    };
+    Some(collapse(
+        s.char_indices().map(|(i, c)| (i..i + c.len_utf8(), c)),
+        s.len(),
+        s.len() != data.len(),
+        matched,
+    ))
+}
+
+/// Like [`one_liner`], but shows invalid UTF-8 as `�` instead of giving up,
+/// for entries that claim to be text.
+#[must_use]
+pub fn lossy_one_liner(
+    data: &[u8],
+    matched: Option<Range<usize>>,
+) -> (String, Option<Range<usize>>) {
+    let source = &data[..min(data.len(), MAX_SOURCE_LEN)];
+    let mut offset = 0;
+    let chars = source.utf8_chunks().flat_map(|chunk| {
+        let start = offset;
+        offset += chunk.valid().len() + chunk.invalid().len();
+        chunk
+            .valid()
+            .char_indices()
+            .map(move |(i, c)| (start + i..start + i + c.len_utf8(), c))
+            .chain((!chunk.invalid().is_empty()).then(|| {
+                let invalid = start + chunk.valid().len();
+                (invalid..offset, char::REPLACEMENT_CHARACTER)
+            }))
+    });
+    // Every invalid byte may become a replacement character.
+    let max_len = source.len() * char::REPLACEMENT_CHARACTER.len_utf8();
+    collapse(chars, max_len, source.len() != data.len(), matched)
+}
+
+/// How many lines the text in `data` spans, not counting trailing line breaks.
+#[must_use]
+pub fn line_count(data: &[u8]) -> usize {
+    let data = data[..min(data.len(), MAX_COUNTED_LEN)].trim_ascii_end();
+    if data.is_empty() {
+        return 0;
+    }
+    data.split(|&b| b == b'\n').count()
+}
+
+/// Guesses the mime type of an entry stored without one, like every entry in
+/// the buckets, from its first 512 bytes.
+///
+/// Images and documents are recognized by their magic numbers. Anything else
+/// is `text/plain` unless it has NUL bytes or more than 30% of it is invalid
+/// UTF-8 or control characters, in which case it's
+/// `application/octet-stream`. Text that got mangled somewhere along the way
+/// stays text.
+#[must_use]
+pub fn sniff_mime(data: &[u8]) -> MimeType {
+    const MAGIC_NUMBERS: [(&[u8], &str); 7] = [
+        (b"\x89PNG\r\n\x1A\n", "image/png"),
+        (b"\xFF\xD8\xFF", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"\x1F\x8B", "application/gzip"),
+        (b"PK\x03\x04", "application/zip"),
+    ];
+
+    let prefix = &data[..min(data.len(), MAX_SNIFFED_LEN)];
+    let is_webp = prefix.starts_with(b"RIFF") && prefix.get(8..12) == Some(b"WEBP");
+    let mime_type = MAGIC_NUMBERS
+        .iter()
+        .find(|(magic, _)| prefix.starts_with(magic))
+        .map(|&(_, mime_type)| mime_type)
+        .or_else(|| is_webp.then_some("image/webp"))
+        .unwrap_or_else(|| {
+            if looks_like_text(prefix) {
+                "text/plain"
+            } else {
+                "application/octet-stream"
+            }
+        });
+    MimeType::from(mime_type).unwrap()
+}
+
+fn looks_like_text(data: &[u8]) -> bool {
+    let mut suspicious = 0;
+    for chunk in data.utf8_chunks() {
+        suspicious += chunk.invalid().len();
+        for c in chunk.valid().chars() {
+            if c == '\0' {
+                return false;
+            }
+            // Escape sequences show up in text copied from terminals.
+            if c.is_control() && !c.is_whitespace() && c != '\x1B' {
+                suspicious += 1;
+            }
+        }
+    }
+    suspicious * 10 <= data.len() * 3
+}
+
+/// What kind of value a text entry holds, for UIs that render some of them
+/// specially.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TextKind {
+    Url,
+    /// A `#rgb` or `#rrggbb` color.
+    Color([u8; 3]),
+    /// An absolute or home-relative path.
+    Path {
+        /// Whether something was at the path when the entry was loaded.
+        exists: bool,
+    },
+    Json,
+}
+
+/// Guesses what `one_liner` (as built by [`one_liner`]) holds from its text
+/// alone, so classifying entries costs no more than building their one-liners.
+///
+/// Entries spanning several `lines` can only be JSON.
+#[must_use]
+pub fn text_kind(one_liner: &str, lines: usize) -> Option<TextKind> {
+    let truncated = one_liner.ends_with('…');
+    match (one_liner.chars().next(), one_liner.chars().next_back()) {
+        (Some('{'), Some('}' | '…')) | (Some('['), Some(']' | '…')) => {
+            return Some(TextKind::Json);
+        }
+        _ => {}
+    }
+    if lines != 1 || truncated || one_liner.contains(' ') {
+        return None;
+    }
+
+    if one_liner.starts_with("https://") || one_liner.starts_with("http://") {
+        return Some(TextKind::Url);
+    }
+    if let Some(hex) = one_liner.strip_prefix('#') {
+        return parse_color(hex).map(TextKind::Color);
+    }
+    let path = if let Some(relative) = one_liner.strip_prefix("~/") {
+        Path::new(&env::var_os("HOME")?).join(relative)
+    } else if one_liner.starts_with('/') {
+        Path::new(one_liner).to_path_buf()
+    } else {
+        return None;
+    };
+    Some(TextKind::Path {
+        exists: path.try_exists().unwrap_or(false),
+    })
+}
+
+/// Configures [`preview`].
+#[cfg(feature = "preview")]
+#[derive(Copy, Clone, Debug)]
+pub struct PreviewOptions {
+    /// The most characters a one-liner may hold, including the `…` it's cut
+    /// off with.
+    pub width: usize,
+    /// Whether to read image headers for their dimensions, which costs a
+    /// little I/O per image.
+    pub image_dimensions: bool,
+}
+
+#[cfg(feature = "preview")]
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        Self {
+            width: MAX_SOURCE_LEN,
+            image_dimensions: false,
+        }
+    }
+}
+
+/// How an entry is shown in a list.
+#[cfg(feature = "preview")]
+#[derive(Debug)]
+pub enum Preview {
+    Text {
+        one_liner: String,
+        /// Whether the entry holds more than its one-liner shows because it
+        /// was cut off or spans several lines.
+        full_available: bool,
+    },
+    Image {
+        /// The image's width and height, if they were asked for and its
+        /// header could be read.
+        dimensions: Option<(u32, u32)>,
+        format: Option<ImageFormat>,
+    },
+    Binary {
+        /// Empty if the entry didn't record one.
+        mime_type: MimeType,
+        len: u64,
+    },
+    Error(CoreError),
+}
+
+/// Loads `entry` and describes it the same way the [`ui_actor`] does.
+///
+/// [`ui_actor`]: crate::ui_actor
+#[cfg(feature = "preview")]
+#[must_use]
+pub fn preview(entry: Entry, reader: &EntryReader, options: PreviewOptions) -> Preview {
+    entry
+        .to_slice(reader)
+        .and_then(|loaded| preview_loaded(entry, &loaded, options))
+        .unwrap_or_else(Preview::Error)
+}
+
+/// Like [`preview`], but for contents that were already loaded, e.g. by a
+/// [`RemoteReader`](crate::RemoteReader).
+#[cfg(feature = "preview")]
+pub fn preview_loaded(
+    entry: Entry,
+    loaded: &LoadedEntry<MmapOrSlice>,
+    PreviewOptions {
+        width,
+        image_dimensions,
+    }: PreviewOptions,
+) -> Result<Preview, CoreError> {
+    let mime_type = loaded.mime_type()?;
+    Ok(match Contents::classify(entry, loaded, mime_type, None) {
+        Contents::Text { mut one_liner, .. } => {
+            let cut_off = one_liner.chars().nth(width).is_some();
+            if cut_off {
+                let end = one_liner
+                    .char_indices()
+                    .nth(width.saturating_sub(1))
+                    .map_or(0, |(i, _)| i);
+                one_liner.truncate(end);
+                one_liner.push('…');
+            }
+            Preview::Text {
+                one_liner,
+                full_available: cut_off || loaded.len() > MAX_SOURCE_LEN || line_count(loaded) > 1,
+            }
+        }
+        Contents::Image { format } => {
+            let dimensions = if image_dimensions {
+                ImageReader::new(Cursor::new(&***loaded))
+                    .with_guessed_format()
+                    .map_io_err(|| "Failed to guess image format.")?
+                    .into_dimensions()
+                    .ok()
+            } else {
+                None
+            };
+            Preview::Image { dimensions, format }
+        }
+        Contents::Binary { mime_type } => Preview::Binary {
+            mime_type,
+            len: u64::try_from(loaded.len()).unwrap(),
+        },
+    })
+}
+
+/// What an entry's contents are shown as, shared by [`preview`] and the
+/// [`ui_actor`](crate::ui_actor) so that they can't disagree.
+#[cfg(feature = "preview")]
+pub(crate) enum Contents {
+    Text {
+        one_liner: String,
+        matched: Option<Range<usize>>,
+    },
+    Image {
+        format: Option<ImageFormat>,
+    },
+    Binary {
+        mime_type: MimeType,
+    },
+}
+
+#[cfg(feature = "preview")]
+impl Contents {
+    /// Sorts `loaded` into text, images, and everything else by its
+    /// `mime_type`, pointing out where `matched` ended up in the one-liner of
+    /// text.
+    ///
+    /// Entries without a mime type are sniffed with [`sniff_mime`], so blobs
+    /// and small images in the buckets aren't mistaken for text. Direct files
+    /// only lose their mime type if something stripped their extended
+    /// attributes, so those aren't trusted to be text even if they look like
+    /// it: they must be valid UTF-8.
+    pub(crate) fn classify(
+        entry: Entry,
+        loaded: &[u8],
+        mime_type: MimeType,
+        matched: Option<Range<usize>>,
+    ) -> Self {
+        if mime_type.starts_with("image/") {
+            return Self::Image {
+                format: ImageFormat::from_mime_type(mime_type),
+            };
+        }
+
+        let unknown = mime_type.is_empty() && matches!(entry.kind(), Kind::File);
+        if unknown {
+            if let Ok(format) = image::guess_format(loaded) {
+                return Self::Image {
+                    format: Some(format),
+                };
+            }
+        }
+        if mime_type.is_empty() {
+            let sniffed = sniff_mime(loaded);
+            if sniffed.starts_with("image/") {
+                return Self::Image {
+                    format: ImageFormat::from_mime_type(sniffed),
+                };
+            }
+            if !sniffed.starts_with("text/") {
+                return Self::Binary { mime_type: sniffed };
+            }
+        }
+        // Text that got mangled somewhere along the way is still mostly readable.
+        let is_text = !unknown && (mime_type.is_empty() || mime_type.starts_with("text/"));
+        one_liner(loaded, matched.clone())
+            .or_else(|| is_text.then(|| lossy_one_liner(loaded, matched)))
+            .map_or(Self::Binary { mime_type }, |(one_liner, matched)| {
+                Self::Text { one_liner, matched }
+            })
+    }
+}
+
+fn parse_color(hex: &str) -> Option<[u8; 3]> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let width = match hex.len() {
+        3 => 1,
+        6 => 2,
+        _ => return None,
+    };
+    let channel = |i: usize| {
+        let c = u8::from_str_radix(&hex[i * width..(i + 1) * width], 16).unwrap();
+        if width == 1 { c * 0x11 } else { c }
+    };
+    Some([channel(0), channel(1), channel(2)])
+}
+
+fn collapse(
+    chars: impl Iterator<Item = (Range<usize>, char)>,
+    max_len: usize,
+    truncated: bool,
+    matched: Option<Range<usize>>,
+) -> (String, Option<Range<usize>>) {
+    // Sized up front so growing it doesn't leave copies of the text behind.
+    let mut one_liner = String::with_capacity(max_len + '…'.len_utf8());
+    // An empty range past every character matches none of them.
+    let matched = matched.unwrap_or(usize::MAX..usize::MAX);
+    let (mut matched_start, mut matched_end) = (None, None);
+    let mut prev_char_is_whitespace = false;
+    for (source, c) in chars {
+        // Snap outwards so partially matched characters are shown in full.
+        if matched_start.is_none() && source.end > matched.start {
+            matched_start = Some(one_liner.len());
+        }
+        if matched_end.is_none() && source.start >= matched.end {
+            matched_end = Some(one_liner.len());
+        }
+        if (prev_char_is_whitespace || one_liner.is_empty()) && c.is_whitespace() {
+            continue;
+        }
+
+        one_liner.push(if c.is_whitespace() { ' ' } else { c });
+        prev_char_is_whitespace = c.is_whitespace();
+    }
+    if one_liner.ends_with(' ') {
+        one_liner.pop();
+        let len = one_liner.len();
+        matched_start = matched_start.map(|start| min(start, len));
+        matched_end = matched_end.map(|end| min(end, len));
+    }
+    if truncated {
+        one_liner.push('…');
+    }
+
+    let matched = matched_start
+        .map(|start| start..matched_end.unwrap_or(one_liner.len()))
+        .filter(|matched| !matched.is_empty());
+    (one_liner, matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{line_count, lossy_one_liner, one_liner, sniff_mime, text_kind, TextKind};
+    #[cfg(feature = "preview")]
+    use crate::{
+        core::{
+            protocol::{MimeType, RingKind},
+            ring::InitializedEntry,
+        },
+        preview::Contents,
+        Entry,
+    };
+
+    #[test]
+    fn whitespace_is_collapsed() {
+        let (text, matched) = one_liner(b"\n    fn main() {\n\t\tok\n}\n", Some(22..24)).unwrap();
+        assert_eq!(text, "fn main() { ok }");
+        assert_eq!(&text[matched.unwrap()], "}");
+    }
+
+    #[test]
+    fn invalid_text_is_replaced() {
+        assert!(one_liner(b"caf\xE9 au lait", None).is_none());
+        let (text, matched) = lossy_one_liner(b"caf\xE9 au  lait", Some(5..7));
+        assert_eq!(text, "caf\u{FFFD} au lait");
+        assert_eq!(&text[matched.unwrap()], "au");
+    }
+
+    #[test]
+    fn lines_are_counted() {
+        assert_eq!(line_count(b""), 0);
+        assert_eq!(line_count(b"one"), 1);
+        assert_eq!(line_count(b"one\n"), 1);
+        assert_eq!(line_count(b"one\ntwo\n\nfour\n\n"), 4);
+    }
+
+    #[test]
+    fn text_kinds_are_detected() {
+        assert_eq!(text_kind("https://example.com/a?b", 1), Some(TextKind::Url));
+        assert_eq!(text_kind("https://example.com and more", 1), None);
+        assert_eq!(
+            text_kind("#ff8000", 1),
+            Some(TextKind::Color([0xFF, 0x80, 0x00]))
+        );
+        assert_eq!(
+            text_kind("#fa0", 1),
+            Some(TextKind::Color([0xFF, 0xAA, 0x00]))
+        );
+        assert_eq!(text_kind("#ff80", 1), None);
+        assert_eq!(text_kind("/", 1), Some(TextKind::Path { exists: true }));
+        assert_eq!(
+            text_kind("/ringboard/does/not/exist", 1),
+            Some(TextKind::Path { exists: false })
+        );
+        assert_eq!(text_kind("/etc/hosts", 2), None);
+        assert_eq!(text_kind(r#"{ "a": [1, 2] }"#, 3), Some(TextKind::Json));
+        assert_eq!(text_kind("[1, 2…", 1), Some(TextKind::Json));
+        assert_eq!(text_kind("plain text", 1), None);
+    }
+
+    #[test]
+    fn mime_types_are_sniffed() {
+        let sniff = |data: &[u8]| sniff_mime(data).to_string();
+        assert_eq!(sniff(b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR"), "image/png");
+        assert_eq!(sniff(b"\xFF\xD8\xFF\xE0\0\x10JFIF"), "image/jpeg");
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniff(b"%PDF-1.7\n"), "application/pdf");
+        assert_eq!(sniff(b""), "text/plain");
+        assert_eq!(sniff("naïve café\n\tdone".as_bytes()), "text/plain");
+        assert_eq!(sniff(b"\x1B[1;31merror\x1B[0m"), "text/plain");
+        assert_eq!(sniff(b"caf\xE9 au lait"), "text/plain");
+        // A protobuf message.
+        assert_eq!(
+            sniff(b"\x08\x96\x01\x12\x07testing\x1A\x03\x8E\x02\x9E\xA7\x05"),
+            "application/octet-stream"
+        );
+        assert_eq!(sniff(b"text\0"), "application/octet-stream");
+        // Only the start is looked at.
+        let mut data = vec![b'a'; 512];
+        data.extend_from_slice(&[0; 512]);
+        assert_eq!(sniff(&data), "text/plain");
+    }
+
+    #[cfg(feature = "preview")]
+    #[test]
+    fn entries_without_a_mime_type_are_sniffed() {
+        let bucketed = Entry::new(RingKind::Main, 0, InitializedEntry::bucket(4, 0));
+        let direct = Entry::new(RingKind::Main, 1, InitializedEntry::file());
+        let classify = |entry, data: &[u8], mime_type| {
+            let mime_type = MimeType::from(mime_type).unwrap();
+            match Contents::classify(entry, data, mime_type, None) {
+                Contents::Text { one_liner, .. } => one_liner,
+                Contents::Image { format } => format!("{format:?}"),
+                Contents::Binary { mime_type } => format!("binary {mime_type:?}"),
+            }
+        };
+
+        assert_eq!(classify(bucketed, b"caf\xFF", ""), "caf\u{FFFD}");
+        assert_eq!(
+            classify(bucketed, b"\x08\x96\x01\x12\0", ""),
+            "binary \"application/octet-stream\""
+        );
+        assert_eq!(classify(bucketed, b"\xFF\xD8\xFF\xDB", ""), "Some(Jpeg)");
+        // Unlike direct files, text in buckets isn't sniffed for images.
+        assert_eq!(classify(bucketed, b"BM text", ""), "BM text");
+        assert_eq!(classify(direct, b"BM text", ""), "Some(Bmp)");
+        assert_eq!(classify(direct, b"caf\xFF", "text/plain"), "caf\u{FFFD}");
+        assert_eq!(
+            classify(direct, b"caf\xFF", "font/ttf"),
+            "binary \"font/ttf\""
+        );
+        assert_eq!(classify(direct, b"caf\xFF", ""), "binary \"\"");
+        assert_eq!(classify(direct, b"caf\xC3\xA9", ""), "caf\u{E9}");
+        assert_eq!(classify(direct, b"GIF89a", ""), "Some(Gif)");
+        assert_eq!(classify(direct, b"GIF89a", "image/webp"), "Some(WebP)");
+    }
+}
@@ -0,0 +1,295 @@
+use std::{fs::File, io, io::ErrorKind, os::fd::OwnedFd, time::SystemTime};
+
+use ringboard_core::{
+    origins::Origin,
+    protocol::{
+        decompose_id, IdNotFoundError, ListTagsResponse, ListedEntry, MimeType, ReadOutcome,
+        ReadResponse, RingKind, MAX_LIST_ENTRIES,
+    },
+    ring::{InitializedEntry, Mmap, MAX_ENTRIES},
+    tags::TagSet,
+    Context, IoErr,
+};
+use rustix::net::SocketAddrUnix;
+
+use crate::{
+    api::{connect_to_server, GenerationRequest, ListRequest, ListTagsRequest, ReadRequest},
+    decode_direct_file,
+    ring_reader::{bytes_to_file, xattr_mime_type, FileOrSlice, MmapOrSlice},
+    wipe::ContentBuf,
+    ClientError, Entry, Kind, LoadedEntry,
+};
+
+/// Read access to a Ringboard database.
+///
+/// Local clients should prefer mmapping the database directly, but clients
+/// without access to the data directory (e.g. sandboxed apps) can go through
+/// the server with a [`RemoteReader`] instead.
+pub trait EntrySource {
+    /// Picks up entries that were added since the source was opened.
+    fn refresh(&mut self) -> Result<(), ringboard_core::Error>;
+
+    /// Reads the write head and length of the favorites and main rings (in
+    /// that order) without loading any entries.
+    fn ring_heads(&mut self) -> Result<[(u32, u32); 2], ringboard_core::Error>;
+
+    /// The database generation if the source can read it cheaply. Refreshes
+    /// compare it to tell whether anything changed since the last load.
+    fn generation(&self) -> Option<u64>;
+
+    /// Iterates through the favorites ring from oldest to newest entry.
+    fn favorites(&self) -> impl DoubleEndedIterator<Item = Entry> + '_;
+
+    /// Iterates through the main ring from oldest to newest entry.
+    fn main(&self) -> impl DoubleEndedIterator<Item = Entry> + '_;
+
+    fn get(&mut self, id: u64) -> Result<Entry, ringboard_core::Error>;
+
+    /// Hints that `entries` are about to be loaded, so the source can start
+    /// reading them in all at once. See
+    /// [`EntryReader::prefetch`](crate::EntryReader::prefetch).
+    fn prefetch(&mut self, _: &[Entry]) {}
+
+    fn to_slice(&mut self, entry: Entry)
+    -> Result<LoadedEntry<MmapOrSlice>, ringboard_core::Error>;
+
+    fn to_file(&mut self, entry: Entry) -> Result<LoadedEntry<File>, ringboard_core::Error>;
+
+    /// Loads the entry for reading as a stream, see [`Entry::to_reader`].
+    fn to_reader(
+        &mut self,
+        entry: Entry,
+    ) -> Result<LoadedEntry<FileOrSlice>, ringboard_core::Error>;
+
+    fn mime_type(&mut self, entry: Entry) -> Result<MimeType, ringboard_core::Error>;
+
+    /// The tags the user gave the entry, in the order they were added.
+    fn tags(&mut self, entry: Entry) -> Result<TagSet, ringboard_core::Error>;
+
+    /// The client that added the entry, if the source can tell. Only the
+    /// database records origins, so remote sources can't.
+    fn added_by(&mut self, _: Entry) -> Option<Origin> {
+        None
+    }
+
+    /// When the entry was copied, if the source can tell. Like origins,
+    /// timestamps are only recorded in the database.
+    fn timestamp(&mut self, _: Entry) -> Option<SystemTime> {
+        None
+    }
+}
+
+#[derive(Debug)]
+pub struct RemoteReader {
+    server: OwnedFd,
+    favorites: Vec<Entry>,
+    main: Vec<Entry>,
+    buf: ContentBuf,
+}
+
+impl RemoteReader {
+    pub fn connect(addr: &SocketAddrUnix) -> Result<Self, ClientError> {
+        let mut me = Self {
+            server: connect_to_server(addr)?,
+            favorites: Vec::new(),
+            main: Vec::new(),
+            buf: ContentBuf::default(),
+        };
+        me.refresh()?;
+        Ok(me)
+    }
+
+    fn list(&self, ring: RingKind) -> Result<Vec<Entry>, ClientError> {
+        let page_size = u32::try_from(MAX_LIST_ENTRIES).unwrap();
+
+        let mut entries = Vec::new();
+        let mut start = 0;
+        let write_head = loop {
+            let response = ListRequest::response(&self.server, ring, start, page_size)?;
+            entries.extend(response.entries().iter().map(|e| listed_entry(ring, e)));
+
+            start += page_size;
+            if start >= response.len {
+                break response.write_head;
+            }
+        };
+
+        // Match the local ring reader which starts iterating at the write head.
+        let wrapped = entries.partition_point(|e| e.index() < write_head);
+        entries.rotate_left(wrapped);
+        Ok(entries)
+    }
+
+    fn read(&self, entry: Entry) -> Result<(ReadResponse, Option<OwnedFd>), ClientError> {
+        let (response, fd) = ReadRequest::response(&self.server, entry.id(), 0, u32::MAX)?;
+        match response.outcome {
+            ReadOutcome::Error(e) => Err(e.into()),
+            ReadOutcome::Inline | ReadOutcome::File => Ok((response, fd)),
+        }
+    }
+}
+
+impl EntrySource for RemoteReader {
+    fn refresh(&mut self) -> Result<(), ringboard_core::Error> {
+        self.favorites = self.list(RingKind::Favorites).map_err(into_core)?;
+        self.main = self.list(RingKind::Main).map_err(into_core)?;
+        Ok(())
+    }
+
+    /// Asks the server, which older servers can't answer.
+    fn generation(&self) -> Option<u64> {
+        GenerationRequest::response(&self.server)
+            .ok()
+            .map(|response| response.generation)
+    }
+
+    fn ring_heads(&mut self) -> Result<[(u32, u32); 2], ringboard_core::Error> {
+        let mut heads = [(0, 0); 2];
+        for ring in [RingKind::Favorites, RingKind::Main] {
+            let response = ListRequest::response(&self.server, ring, 0, 0).map_err(into_core)?;
+            heads[ring as usize] = (response.write_head, response.len);
+        }
+        Ok(heads)
+    }
+
+    fn favorites(&self) -> impl DoubleEndedIterator<Item = Entry> + '_ {
+        self.favorites.iter().copied()
+    }
+
+    fn main(&self) -> impl DoubleEndedIterator<Item = Entry> + '_ {
+        self.main.iter().copied()
+    }
+
+    fn get(&mut self, id: u64) -> Result<Entry, ringboard_core::Error> {
+        let (ring, index) = decompose_id(id)?;
+        let response = ListRequest::response(&self.server, ring, index, 1).map_err(into_core)?;
+        response
+            .entries()
+            .iter()
+            .find(|e| e.index == index)
+            .map(|e| listed_entry(ring, e))
+            .ok_or_else(|| IdNotFoundError::Entry(index).into())
+    }
+
+    fn to_slice(
+        &mut self,
+        entry: Entry,
+    ) -> Result<LoadedEntry<MmapOrSlice>, ringboard_core::Error> {
+        match self.read(entry).map_err(into_core)? {
+            (response, None) => {
+                self.buf.clear();
+                self.buf.extend_from_slice(response.data());
+                Ok(LoadedEntry::new(MmapOrSlice::Slice(&self.buf), None))
+            }
+            (_, Some(fd)) => {
+                let (file, original) = decode_direct_file(File::from(fd))?;
+                Ok(LoadedEntry::new(
+                    Mmap::from(&file)
+                        .map_io_err(|| Context::Entry {
+                            message: "Failed to mmap entry",
+                            id: entry.id(),
+                        })?
+                        .into(),
+                    Some(original.unwrap_or_else(|| file.into())),
+                ))
+            }
+        }
+    }
+
+    fn to_file(&mut self, entry: Entry) -> Result<LoadedEntry<File>, ringboard_core::Error> {
+        match self.read(entry).map_err(into_core)? {
+            (response, None) => Ok(LoadedEntry::new(bytes_to_file(response.data())?, None)),
+            (_, Some(fd)) => {
+                let (file, original) = decode_direct_file(File::from(fd))?;
+                Ok(LoadedEntry::new(file, original))
+            }
+        }
+    }
+
+    fn to_reader(
+        &mut self,
+        entry: Entry,
+    ) -> Result<LoadedEntry<FileOrSlice>, ringboard_core::Error> {
+        match self.read(entry).map_err(into_core)? {
+            (response, None) => {
+                self.buf.clear();
+                self.buf.extend_from_slice(response.data());
+                Ok(LoadedEntry::new(self.buf.as_slice().into(), None))
+            }
+            (_, Some(fd)) => {
+                let (file, original) = decode_direct_file(File::from(fd))?;
+                Ok(LoadedEntry::new(file.into(), original))
+            }
+        }
+    }
+
+    fn mime_type(&mut self, entry: Entry) -> Result<MimeType, ringboard_core::Error> {
+        match entry.kind() {
+            Kind::Bucket(_) => Ok(MimeType::new()),
+            Kind::File => match self.read(entry).map_err(into_core)? {
+                (_, Some(fd)) => xattr_mime_type(&fd),
+                (_, None) => Ok(MimeType::new()),
+            },
+        }
+    }
+
+    /// Asks the server, which older servers can't answer: their entries can't
+    /// have tags anyway.
+    fn tags(&mut self, entry: Entry) -> Result<TagSet, ringboard_core::Error> {
+        match ListTagsRequest::response(&self.server, entry.id()) {
+            Ok(ListTagsResponse { error: None, tags }) => Ok(tags),
+            Ok(ListTagsResponse { error: Some(e), .. }) => Err(e.into()),
+            Err(ClientError::UnsupportedByServer { .. }) => Ok(TagSet::default()),
+            Err(e) => Err(into_core(e)),
+        }
+    }
+}
+
+fn listed_entry(
+    ring: RingKind,
+    &ListedEntry {
+        index,
+        size,
+        bucket_index,
+    }: &ListedEntry,
+) -> Entry {
+    Entry::new(
+        ring,
+        index,
+        if size == 0 {
+            InitializedEntry::file()
+        } else if bucket_index == MAX_ENTRIES {
+            InitializedEntry::packed(size)
+        } else {
+            InitializedEntry::bucket(size, bucket_index)
+        },
+    )
+}
+
+/// Keeps what kind of failure the server reported so callers can still tell,
+/// say, a full database apart from a bad response.
+fn into_core(e: ClientError) -> ringboard_core::Error {
+    let kind = match e {
+        ClientError::Core(e) => return e,
+        ClientError::VersionMismatch { .. } | ClientError::UnsupportedByServer { .. } => {
+            ErrorKind::Unsupported
+        }
+        ClientError::InvalidResponse { .. }
+        | ClientError::InvalidExport { .. }
+        | ClientError::UnsupportedExportVersion { .. } => ErrorKind::InvalidData,
+        ClientError::DatabaseFull => ErrorKind::StorageFull,
+        ClientError::EntryTooLarge { .. } => ErrorKind::FileTooLarge,
+        ClientError::UnsupportedData
+        | ClientError::EmptyEntry
+        | ClientError::InvalidQuery { .. } => ErrorKind::InvalidInput,
+        ClientError::RateLimited => ErrorKind::ResourceBusy,
+        ClientError::PermissionDenied => ErrorKind::PermissionDenied,
+        ClientError::FavoritesFull
+        | ClientError::SearchFailed { .. }
+        | ClientError::Server { .. } => ErrorKind::Other,
+    };
+    ringboard_core::Error::Io {
+        error: io::Error::new(kind, e),
+        context: "Failed to read from the Ringboard server.".into(),
+    }
+}
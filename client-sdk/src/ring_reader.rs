@@ -2,36 +2,69 @@ use std::{
     fmt::{Debug, Formatter},
     fs::File,
     io,
-    io::ErrorKind,
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
     ops::{Deref, DerefMut},
     os::{
-        fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+        fd::{AsFd, BorrowedFd, OwnedFd},
         unix::fs::FileExt,
     },
-    path::PathBuf,
-    slice, str,
+    path::{Path, PathBuf},
+    str,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use arrayvec::ArrayVec;
 use ringboard_core::{
-    bucket_to_length, direct_file_name, open_buckets,
-    protocol::{composite_id, decompose_id, IdNotFoundError, MimeType, RingKind},
+    bucket_to_length, checksums,
+    checksums::Verification,
+    direct_file_name, encoding,
+    encoding::Encoding,
+    generation,
+    generation::Generation,
+    open_buckets, origins,
+    origins::{Origin, Origins},
+    packed,
+    packed::{PackedEntries, PackedIndex},
+    protocol::{composite_id, decompose_id, IdNotFoundError, Label, MimeType, RingKind},
     ring::{InitializedEntry, Mmap, Ring},
-    size_to_bucket, IoErr, PathView, RingAndIndex, NUM_BUCKETS,
+    sensitive,
+    settings::RingCapacities,
+    size_to_bucket, slot_generations,
+    slot_generations::SlotGenerations,
+    tags,
+    tags::{TagSet, Tags, MAX_TAGS_LEN},
+    timestamps,
+    timestamps::Timestamps,
+    Context, IoErr, PathView, RingAndIndex, NUM_BUCKETS,
 };
 use rustix::{
-    fs::{fgetxattr, memfd_create, openat, MemfdFlags, Mode, OFlags, CWD},
+    fs::{
+        fgetxattr, memfd_create, openat, statx, AtFlags, MemfdFlags, Mode, OFlags, StatxFlags, CWD,
+    },
     io::Errno,
 };
+use thiserror::Error;
+
+use crate::DatabaseWatcher;
 
 #[derive(Debug)]
 struct RingIter {
     kind: RingKind,
 
     write_head: u32,
+    /// The write head once the ring has wrapped around and the first slot
+    /// until then.
+    oldest: u32,
     front: u32,
     back: u32,
     done: bool,
+    /// Nothing comes before the starting point, so iterating backwards yields
+    /// nothing while iterating forwards still does.
+    back_done: bool,
 }
 
 impl RingIter {
@@ -39,45 +72,54 @@ impl RingIter {
         self.next_dir(ring, |me| {
             let id = me.front;
             me.front = ring.next_entry(id);
-            id
+            (id, ring.next_head(id) == me.write_head)
         })
     }
 
     fn next_back(&mut self, ring: &Ring) -> Option<Entry> {
+        if self.back_done {
+            return None;
+        }
         self.next_dir(ring, |me| {
             let id = me.back;
             me.back = ring.prev_entry(id);
-            id
+            (id, id == me.oldest)
         })
     }
 
+    /// `advance` returns the slot to read and whether it's the last one in
+    /// that direction.
     fn next_dir(
         &mut self,
         ring: &Ring,
-        mut advance: impl FnMut(&mut Self) -> u32,
+        mut advance: impl FnMut(&mut Self) -> (u32, bool),
     ) -> Option<Entry> {
         loop {
             if self.done {
                 return None;
             }
-            self.done = self.front == self.back
-                || ring.next_head(self.front) == self.write_head
-                || self.back == self.write_head;
+            let met = self.front == self.back;
+            let (id, last) = advance(self);
+            self.done = met || last;
 
-            if let Some(entry) = Entry::from(ring, self.kind, advance(self)) {
+            if let Some(entry) = Entry::from(ring, self.kind, id) {
                 break Some(entry);
             }
         }
     }
 
+    /// Any of the remaining slots may be empty, so only the upper bound is
+    /// known.
     fn size_hint(&self, ring: &Ring) -> (usize, Option<usize>) {
-        let len = if self.front > self.back {
+        if self.done {
+            return (0, Some(0));
+        }
+        let slots = if self.front > self.back {
             ring.len() - self.front + self.back
         } else {
             self.back - self.front
-        };
-        let len = usize::try_from(len).unwrap();
-        (len, Some(len))
+        } + 1;
+        (0, Some(usize::try_from(slots).unwrap()))
     }
 }
 
@@ -85,16 +127,47 @@ impl RingIter {
 pub struct DatabaseReader {
     main: Ring,
     favorites: Ring,
+    generation: Option<Generation>,
 }
 
 impl DatabaseReader {
+    /// Opens the rings with the capacities configured in the database's
+    /// settings file.
     pub fn open(database: &mut PathBuf) -> Result<Self, ringboard_core::Error> {
+        let capacities = RingCapacities::load(database)?;
+        let main = RingReader::prepare_ring(database, RingKind::Main, capacities.main)?;
+        let favorites =
+            RingReader::prepare_ring(database, RingKind::Favorites, capacities.favorites)?;
+        // Every client starts by reading the newest entries, so have the kernel
+        // fetch the rings while the rest of the database is opened. Failing to
+        // only costs speed.
+        let _ = main.prefetch();
+        let _ = favorites.prefetch();
         Ok(Self {
-            main: RingReader::prepare_ring(database, RingKind::Main)?,
-            favorites: RingReader::prepare_ring(database, RingKind::Favorites)?,
+            main,
+            favorites,
+            generation: {
+                let file = PathView::new(database, generation::FILE_NAME);
+                match Generation::open(&*file) {
+                    // Databases that haven't been opened by a recent server yet won't have one.
+                    Err(ringboard_core::Error::Io { error, .. })
+                        if error.kind() == ErrorKind::NotFound =>
+                    {
+                        None
+                    }
+                    r => Some(r?),
+                }
+            },
         })
     }
 
+    /// The database's current generation, which changes whenever the server
+    /// mutates the database.
+    #[must_use]
+    pub fn generation(&self) -> Option<u64> {
+        self.generation.as_ref().map(Generation::get)
+    }
+
     pub fn get_raw(&self, id: u64) -> Result<Entry, IdNotFoundError> {
         let (kind, id) = decompose_id(id)?;
         Entry::from(
@@ -126,6 +199,12 @@ impl DatabaseReader {
         self.get_raw(id)
     }
 
+    /// Watches the database in `database_dir` for changes, e.g. to pick up
+    /// entries as they are copied instead of polling for them.
+    pub fn watch(database_dir: &Path) -> Result<DatabaseWatcher, ringboard_core::Error> {
+        DatabaseWatcher::new(database_dir)
+    }
+
     pub fn main_ring_mut(&mut self) -> &mut Ring {
         &mut self.main
     }
@@ -134,6 +213,18 @@ impl DatabaseReader {
         &mut self.favorites
     }
 
+    /// Resumes reading the ring a [`Cursor`] was saved from.
+    pub fn resume(&self, cursor: Cursor) -> Result<RingReader, ResumeError> {
+        RingReader::resume(
+            match cursor.kind() {
+                RingKind::Favorites => &self.favorites,
+                RingKind::Main => &self.main,
+            },
+            cursor,
+            self.generation(),
+        )
+    }
+
     #[must_use]
     pub fn main(&self) -> RingReader {
         RingReader::from_ring(&self.main, RingKind::Main)
@@ -143,6 +234,46 @@ impl DatabaseReader {
     pub fn favorites(&self) -> RingReader {
         RingReader::from_ring(&self.favorites, RingKind::Favorites)
     }
+
+    /// Reads every entry in both rings, oldest first or newest first when
+    /// reversed. See [`MergedReader`] for how the rings are interleaved.
+    #[must_use]
+    pub fn all(&self) -> MergedReader {
+        MergedReader::new(self.main(), self.favorites())
+    }
+}
+
+/// Why a [`RingReader`] couldn't pick up where a previous one left off.
+#[derive(Error, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ResumeError {
+    #[error("position {index} is out of bounds for a ring of length {len}")]
+    OutOfBounds { index: u32, len: u32 },
+    /// New entries overwrote the resume point, so reading has to start over
+    /// from the write head.
+    #[error("the ring wrapped past the resume point")]
+    ResumePointLost,
+    /// The starting entry is past the end of the ring or was never written.
+    #[error(transparent)]
+    NotFound(#[from] IdNotFoundError),
+}
+
+/// A reader's position in a ring which can be saved, e.g. across restarts,
+/// and picked back up later with [`RingReader::resume`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cursor {
+    kind: RingKind,
+    write_head: u32,
+    position: u32,
+    exhausted: bool,
+    generation: Option<u64>,
+}
+
+impl Cursor {
+    #[must_use]
+    pub const fn kind(&self) -> RingKind {
+        self.kind
+    }
 }
 
 #[derive(Debug)]
@@ -152,17 +283,188 @@ pub struct RingReader<'a> {
 }
 
 impl<'a> RingReader<'a> {
+    /// Reads every entry, oldest first or newest first when reversed.
     #[must_use]
     pub fn from_ring(ring: &'a Ring, kind: RingKind) -> Self {
-        let tail = ring.write_head();
-        Self::from_id(ring, kind, tail, tail)
+        let mut me = Self::from_uninit(ring, kind);
+        // Past the end if the ring grew since it was opened, or if it's corrupted.
+        let write_head = ring.write_head().min(ring.len());
+        me.reset_to(write_head, write_head);
+        me.iter.front = me.iter.oldest;
+        me.iter.back = ring.prev_entry(write_head);
+        me.iter.back_done = false;
+        me
     }
 
-    #[must_use]
-    pub fn from_id(ring: &'a Ring, kind: RingKind, write_head: u32, id: u32) -> Self {
+    /// Resumes reading at `id` from a `write_head` observed earlier, e.g. to
+    /// load the next page of entries.
+    ///
+    /// Iterating forwards yields `id` and the entries newer than it while
+    /// iterating backwards yields the entries older than `id`, newest first.
+    /// Entries written since `write_head` are accounted for: they show up when
+    /// iterating forwards and the older entries they replaced are skipped.
+    ///
+    /// Fails with [`ResumeError::ResumePointLost`] if new entries overwrote
+    /// `id`, in which case reading should restart with
+    /// [`from_ring`](Self::from_ring). A ring that wrapped all the way around
+    /// since `write_head` can't be told apart from one that didn't change, so
+    /// callers that may fall that far behind should also compare database
+    /// generations. The ring's length must be up to date with its write head.
+    ///
+    /// Fails with [`IdNotFoundError::Entry`] if `id` is past the end of the
+    /// ring or its slot is empty.
+    pub fn from_id(
+        ring: &'a Ring,
+        kind: RingKind,
+        write_head: u32,
+        id: u32,
+    ) -> Result<Self, ResumeError> {
+        Entry::from(ring, kind, id).ok_or(IdNotFoundError::Entry(id))?;
+        Self::from_slot(ring, kind, write_head, id)
+    }
+
+    /// Reads the entries around `id`: at most `before` entries older than it,
+    /// then `id` itself, then at most `after` entries newer than it.
+    ///
+    /// The window stops at the oldest and newest entries instead of wrapping
+    /// around the write head. Both ends can be iterated, so walking outwards
+    /// from `id` is a matter of reversing a window with nothing `after` it and
+    /// reading one with nothing `before` it forwards.
+    pub fn window(
+        ring: &'a Ring,
+        kind: RingKind,
+        id: u32,
+        before: u32,
+        after: u32,
+    ) -> Result<Self, IdNotFoundError> {
+        Entry::from(ring, kind, id).ok_or(IdNotFoundError::Entry(id))?;
+
+        let mut me = Self::from_ring(ring, kind);
+        let RingIter {
+            write_head, oldest, ..
+        } = me.iter;
+        me.iter.front = window_edge(ring, kind, id, before, |slot| {
+            (slot != oldest).then(|| ring.prev_entry(slot))
+        });
+        me.iter.back = window_edge(ring, kind, id, after, |slot| {
+            (ring.next_head(slot) != write_head).then(|| ring.next_entry(slot))
+        });
+        me.iter.done = false;
+        me.iter.back_done = false;
+        Ok(me)
+    }
+
+    /// Like [`from_id`](Self::from_id), but `id` may have been removed since,
+    /// in which case reading picks up around its slot.
+    fn from_slot(
+        ring: &'a Ring,
+        kind: RingKind,
+        write_head: u32,
+        id: u32,
+    ) -> Result<Self, ResumeError> {
+        let len = ring.len();
+        let current_head = ring.write_head();
+        for index in [write_head, current_head] {
+            if index > len {
+                return Err(ResumeError::OutOfBounds { index, len });
+            }
+        }
+        if id >= len {
+            return Err(ResumeError::OutOfBounds { index: id, len });
+        }
+
+        // Writes since the snapshot went to the slots from its write head up
+        // to the current one.
+        let overwritten = if write_head <= current_head {
+            (write_head..current_head).contains(&id)
+        } else {
+            id >= write_head || id < current_head
+        };
+        if overwritten {
+            return Err(ResumeError::ResumePointLost);
+        }
+
         let mut me = Self::from_uninit(ring, kind);
-        me.reset_to(write_head, id);
-        me
+        me.reset_to(current_head, id);
+        Ok(me)
+    }
+
+    /// Picks up where the reader that made `cursor` left off, as
+    /// [`from_id`](Self::from_id) does.
+    ///
+    /// `generation` is the database's current generation. Since every write
+    /// bumps it, it tells apart a ring that wrapped all the way around from one
+    /// that didn't change: cursors from before that many changes are refused
+    /// with [`ResumeError::ResumePointLost`] even if their entry might still be
+    /// there.
+    pub fn resume(
+        ring: &'a Ring,
+        cursor: Cursor,
+        generation: Option<u64>,
+    ) -> Result<Self, ResumeError> {
+        let Cursor {
+            kind,
+            write_head,
+            position,
+            exhausted,
+            generation: then,
+        } = cursor;
+        if let (Some(then), Some(now)) = (then, generation) {
+            if now
+                .checked_sub(then)
+                .is_none_or(|changes| changes >= u64::from(ring.len()))
+            {
+                return Err(ResumeError::ResumePointLost);
+            }
+        }
+        if exhausted {
+            return Ok(Self::from_uninit(ring, kind));
+        }
+        // Nothing was read yet from a ring that hasn't filled up or changed since.
+        if position == ring.len() && position == ring.write_head() {
+            return Ok(Self::from_ring(ring, kind));
+        }
+        if position == write_head {
+            // Nothing was read yet, so only the newest entry as of the snapshot
+            // has to have survived.
+            let mut me = Self::from_slot(ring, kind, write_head, ring.prev_entry(position))?;
+            me.reset_to(ring.write_head(), position);
+            return Ok(me);
+        }
+        Self::from_slot(ring, kind, write_head, position)
+    }
+
+    /// Saves the reader's position for reading newest first: a reader resumed
+    /// from the cursor yields the entries this reader has yet to yield when
+    /// iterating backwards.
+    ///
+    /// `generation` should be the database's generation as of when the reader
+    /// was created.
+    #[must_use]
+    pub const fn cursor(&self, generation: Option<u64>) -> Cursor {
+        let RingIter {
+            kind,
+            write_head,
+            oldest,
+            back,
+            front: _,
+            done,
+            back_done,
+        } = self.iter;
+        let exhausted = done || back_done;
+        Cursor {
+            kind,
+            write_head,
+            position: if exhausted {
+                oldest
+            } else if back == self.ring.prev_entry(write_head) {
+                write_head
+            } else {
+                self.ring.next_entry(back)
+            },
+            exhausted,
+            generation,
+        }
     }
 
     #[must_use]
@@ -172,9 +474,11 @@ impl<'a> RingReader<'a> {
                 kind,
 
                 write_head: 0,
+                oldest: 0,
                 back: 0,
                 front: 0,
                 done: true,
+                back_done: true,
             },
             ring,
         }
@@ -183,9 +487,10 @@ impl<'a> RingReader<'a> {
     pub fn prepare_ring(
         database_dir: &mut PathBuf,
         kind: RingKind,
+        capacity: u32,
     ) -> Result<Ring, ringboard_core::Error> {
         let ring = PathView::new(database_dir, kind.file_name());
-        Ring::open(kind.default_max_entries(), &*ring)
+        Ring::open(capacity, &*ring)
     }
 
     #[must_use]
@@ -198,22 +503,59 @@ impl<'a> RingReader<'a> {
         self.iter.kind
     }
 
+    /// Like [`from_id`](Self::from_id), but trusts that the ring didn't change
+    /// since `write_head` and that `start` is in bounds.
     pub fn reset_to(&mut self, write_head: u32, start: u32) {
         let RingIter {
             kind: _,
             write_head: old_write_head,
+            oldest,
             back,
             front,
             done,
+            back_done,
         } = &mut self.iter;
 
         *old_write_head = write_head;
+        *oldest = if write_head < self.ring.len() {
+            write_head
+        } else {
+            0
+        };
         *back = self.ring.prev_entry(start);
         *front = self.ring.next_entry(*back);
-        *done = false;
+        *done = self.ring.is_empty();
+        *back_done = start == *oldest;
     }
 }
 
+/// The slot of the `count`th entry away from `id` in the direction `step`
+/// goes, or of the last one before `step` runs out of slots.
+fn window_edge(
+    ring: &Ring,
+    kind: RingKind,
+    id: u32,
+    count: u32,
+    step: impl Fn(u32) -> Option<u32>,
+) -> u32 {
+    let (mut edge, mut slot, mut remaining) = (id, id, count);
+    // Bounded by the ring's length in case its write head is corrupted.
+    for _ in 0..ring.len() {
+        if remaining == 0 {
+            break;
+        }
+        let Some(next) = step(slot) else {
+            break;
+        };
+        slot = next;
+        if Entry::from(ring, kind, slot).is_some() {
+            edge = slot;
+            remaining -= 1;
+        }
+    }
+    edge
+}
+
 impl Iterator for RingReader<'_> {
     type Item = Entry;
 
@@ -232,6 +574,145 @@ impl DoubleEndedIterator for RingReader<'_> {
     }
 }
 
+/// Reads the main and favorites rings as one, oldest first or newest first
+/// when reversed.
+///
+/// Each ring's entries come out in the ring's own order. Across rings, entries
+/// are ordered by how far behind their ring's write head they are, so the
+/// newest entries of both rings come first when reversed. Pass an
+/// [`EntryReader`] to [`by_timestamp`](Self::by_timestamp) to order them by
+/// when they were copied instead.
+#[derive(Debug)]
+pub struct MergedReader<'a> {
+    main: MergeSide<'a>,
+    favorites: MergeSide<'a>,
+    timestamps: Option<&'a EntryReader>,
+}
+
+/// A ring's reader along with the entries looked at but not yet yielded from
+/// either end.
+#[derive(Debug)]
+struct MergeSide<'a> {
+    reader: RingReader<'a>,
+    front: Option<Entry>,
+    back: Option<Entry>,
+}
+
+impl<'a> MergeSide<'a> {
+    const fn new(reader: RingReader<'a>) -> Self {
+        Self {
+            reader,
+            front: None,
+            back: None,
+        }
+    }
+
+    fn peek_front(&mut self) -> Option<Entry> {
+        if self.front.is_none() {
+            self.front = self.reader.next().or_else(|| self.back.take());
+        }
+        self.front
+    }
+
+    fn peek_back(&mut self) -> Option<Entry> {
+        if self.back.is_none() {
+            self.back = self.reader.next_back().or_else(|| self.front.take());
+        }
+        self.back
+    }
+
+    /// How many slots behind the write head `entry` is, zero being the
+    /// newest.
+    const fn age(&self, entry: Entry) -> u32 {
+        let len = self.reader.ring.len();
+        (self.reader.iter.write_head + len - 1 - entry.index()) % len
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let peeked = usize::from(self.front.is_some()) + usize::from(self.back.is_some());
+        let (min, max) = self.reader.size_hint();
+        (min + peeked, max.map(|max| max + peeked))
+    }
+}
+
+impl<'a> MergedReader<'a> {
+    #[must_use]
+    pub const fn new(main: RingReader<'a>, favorites: RingReader<'a>) -> Self {
+        Self {
+            main: MergeSide::new(main),
+            favorites: MergeSide::new(favorites),
+            timestamps: None,
+        }
+    }
+
+    /// Orders entries from different rings by when they were copied, falling
+    /// back to their position in the rings when either has no timestamp or
+    /// they were copied in the same minute.
+    ///
+    /// Favorites keep the order they were arranged in, so they can still come
+    /// out of timestamp order.
+    #[must_use]
+    pub const fn by_timestamp(mut self, reader: &'a EntryReader) -> Self {
+        self.timestamps = Some(reader);
+        self
+    }
+
+    /// Whether `main` comes after `favorite` when reading oldest first.
+    fn main_is_newer(&self, main: Entry, favorite: Entry) -> bool {
+        if let Some(reader) = self.timestamps {
+            if let (Some(main), Some(favorite)) =
+                (main.timestamp(reader), favorite.timestamp(reader))
+            {
+                if main != favorite {
+                    return main > favorite;
+                }
+            }
+        }
+        self.main.age(main) <= self.favorites.age(favorite)
+    }
+}
+
+impl Iterator for MergedReader<'_> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let take_main = match (self.main.peek_front(), self.favorites.peek_front()) {
+            (Some(main), Some(favorite)) => !self.main_is_newer(main, favorite),
+            (main, _) => main.is_some(),
+        };
+        if take_main {
+            self.main.front.take()
+        } else {
+            self.favorites.front.take()
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (main_min, main_max) = self.main.size_hint();
+        let (favorites_min, favorites_max) = self.favorites.size_hint();
+        (
+            main_min + favorites_min,
+            main_max
+                .zip(favorites_max)
+                .map(|(main, favorites)| main + favorites),
+        )
+    }
+}
+
+impl DoubleEndedIterator for MergedReader<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let take_main = match (self.main.peek_back(), self.favorites.peek_back()) {
+            (Some(main), Some(favorite)) => self.main_is_newer(main, favorite),
+            (main, _) => main.is_some(),
+        };
+        if take_main {
+            self.main.back.take()
+        } else {
+            self.favorites.back.take()
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Entry {
     rai: RingAndIndex,
@@ -239,28 +720,46 @@ pub struct Entry {
 }
 
 impl Entry {
+    pub(crate) fn new(kind: RingKind, id: u32, metadata: InitializedEntry) -> Self {
+        Self {
+            rai: RingAndIndex::new(kind, id),
+            metadata,
+        }
+    }
+
     fn from(ring: &Ring, kind: RingKind, id: u32) -> Option<Self> {
-        use ringboard_core::ring::Entry::{Bucketed, File, Uninitialized};
+        use ringboard_core::ring::Entry::{Bucketed, File, Packed, Uninitialized};
+        // Only ids within the ring fit in a RingAndIndex.
+        let metadata = match ring.get(id)? {
+            Uninitialized => return None,
+            Bucketed(e) | Packed(e) => e,
+            File => InitializedEntry::file(),
+        };
         Some(Self {
             rai: RingAndIndex::new(kind, id),
-            metadata: match ring.get(id)? {
-                Uninitialized => return None,
-                Bucketed(e) => e,
-                File => InitializedEntry::file(),
-            },
+            metadata,
         })
     }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum Kind {
+    /// Read in place from its bucket or, for
+    /// [packed](InitializedEntry::is_packed) entries, the packed log.
     Bucket(InitializedEntry),
     File,
 }
 
+/// An entry's contents along with the file backing them, if any.
+///
+/// Loaded entries own everything they refer to (or borrow it from the
+/// [`EntryReader`] they were loaded from), so they can be sent to and shared
+/// with other threads whenever their contents can.
 pub struct LoadedEntry<T> {
     loaded: T,
-    fd: Option<LoadedEntryFd>,
+    /// The backing file when it isn't the loaded contents themselves.
+    fd: Option<OwnedFd>,
+    added_by: Option<Origin>,
 }
 
 impl<T: Debug> Debug for LoadedEntry<T> {
@@ -269,9 +768,31 @@ impl<T: Debug> Debug for LoadedEntry<T> {
     }
 }
 
-enum LoadedEntryFd {
-    Owned(OwnedFd),
-    HackySelfReference(BorrowedFd<'static>),
+/// Loaded entry contents, which may be the entry's backing file themselves.
+pub trait LoadedContents {
+    /// The file these contents were read from if they are that file.
+    fn as_backing_file(&self) -> Option<BorrowedFd>;
+}
+
+impl LoadedContents for File {
+    fn as_backing_file(&self) -> Option<BorrowedFd> {
+        Some(self.as_fd())
+    }
+}
+
+impl LoadedContents for MmapOrSlice<'_> {
+    fn as_backing_file(&self) -> Option<BorrowedFd> {
+        None
+    }
+}
+
+impl LoadedContents for FileOrSlice<'_> {
+    fn as_backing_file(&self) -> Option<BorrowedFd> {
+        match self {
+            Self::File(file) => Some(file.as_fd()),
+            Self::Slice(_) => None,
+        }
+    }
 }
 
 pub fn xattr_mime_type<Fd: AsFd>(fd: Fd) -> Result<MimeType, ringboard_core::Error> {
@@ -290,11 +811,70 @@ pub fn xattr_mime_type<Fd: AsFd>(fd: Fd) -> Result<MimeType, ringboard_core::Err
     Ok(MimeType::from(mime_type).unwrap())
 }
 
+pub fn xattr_label<Fd: AsFd>(fd: Fd) -> Result<Label, ringboard_core::Error> {
+    let mut label = [0u8; Label::new_const().capacity()];
+    let len = match fgetxattr(fd, c"user.label", &mut label) {
+        Err(Errno::NODATA) => {
+            return Ok(Label::new());
+        }
+        r => r.map_io_err(|| "Failed to read extended attributes.")?,
+    };
+    let label = str::from_utf8(&label[..len]).map_err(|e| ringboard_core::Error::Io {
+        error: io::Error::new(ErrorKind::InvalidInput, e),
+        context: "Database corruption detected: invalid label detected".into(),
+    })?;
+
+    Ok(Label::from(label).unwrap())
+}
+
+pub fn xattr_tags<Fd: AsFd>(fd: Fd) -> Result<TagSet, ringboard_core::Error> {
+    let mut tags = [0; MAX_TAGS_LEN];
+    let len = match fgetxattr(fd, tags::XATTR_NAME, &mut tags) {
+        Err(Errno::NODATA) => {
+            return Ok(TagSet::default());
+        }
+        r => r.map_io_err(|| "Failed to read extended attributes.")?,
+    };
+    Ok(TagSet::from_bytes(&tags[..len]))
+}
+
+/// Reads the text the server recognized in an image entry into `buf`.
+///
+/// Returns an empty slice if no text was recognized.
+pub fn xattr_image_text<Fd: AsFd>(
+    fd: Fd,
+    buf: &mut Vec<u8>,
+) -> Result<&[u8], ringboard_core::Error> {
+    // The kernel caps extended attribute values at 64 KiB.
+    buf.resize(1 << 16, 0);
+    let len = match fgetxattr(fd, c"user.image_text", buf) {
+        Err(Errno::NODATA) => 0,
+        r => r.map_io_err(|| "Failed to read extended attributes.")?,
+    };
+    Ok(&buf[..len])
+}
+
 impl<T> LoadedEntry<T> {
+    pub(crate) const fn new(loaded: T, fd: Option<OwnedFd>) -> Self {
+        Self {
+            loaded,
+            fd,
+            added_by: None,
+        }
+    }
+
     pub fn into_inner(self) -> T {
         self.loaded
     }
 
+    /// The client that added this entry, if the server recorded it.
+    #[must_use]
+    pub const fn added_by(&self) -> Option<Origin> {
+        self.added_by
+    }
+}
+
+impl<T: LoadedContents> LoadedEntry<T> {
     pub fn mime_type(&self) -> Result<MimeType, ringboard_core::Error> {
         let Some(fd) = self.backing_file() else {
             return Ok(MimeType::new());
@@ -303,14 +883,116 @@ impl<T> LoadedEntry<T> {
         xattr_mime_type(fd)
     }
 
+    /// The text the server recognized in this entry if it is an image.
+    pub fn image_text(&self) -> Result<Vec<u8>, ringboard_core::Error> {
+        let Some(fd) = self.backing_file() else {
+            return Ok(Vec::new());
+        };
+
+        let mut buf = Vec::new();
+        let len = xattr_image_text(fd, &mut buf)?.len();
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// The label the user gave this entry, empty if it has none.
+    pub fn label(&self) -> Result<Label, ringboard_core::Error> {
+        let Some(fd) = self.backing_file() else {
+            return Ok(Label::new());
+        };
+        xattr_label(fd)
+    }
+
+    /// Whether the server only kept the start of this entry because it was
+    /// larger than the configured maximum entry size.
+    pub fn truncated(&self) -> Result<bool, ringboard_core::Error> {
+        let Some(fd) = self.backing_file() else {
+            return Ok(false);
+        };
+        match fgetxattr(fd, c"user.truncated", &mut [0; 1]) {
+            Err(Errno::NODATA) => Ok(false),
+            r => r
+                .map(|_| true)
+                .map_io_err(|| "Failed to read extended attributes."),
+        }
+    }
+
+    /// When the server deletes this entry if it holds sensitive data, e.g. a
+    /// password copied from a password manager.
+    pub fn expires_at(&self) -> Result<Option<SystemTime>, ringboard_core::Error> {
+        let Some(fd) = self.backing_file() else {
+            return Ok(None);
+        };
+        Ok(sensitive::read(fd)?.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)))
+    }
+
+    /// Checks the entry's contents against the checksum the server recorded
+    /// when it was added.
+    ///
+    /// Only direct files have checksums, so bucketed entries and those added
+    /// before checksums were recorded verify as [`Verification::Unknown`].
+    pub fn verify(&self) -> Result<Verification, ringboard_core::Error> {
+        let Some(fd) = self.backing_file() else {
+            return Ok(Verification::Unknown);
+        };
+        checksums::verify(fd)
+    }
+
+    /// Borrowed from the entry rather than stored alongside the contents so
+    /// it can't outlive them, even if they're replaced through [`DerefMut`].
     pub fn backing_file(&self) -> Option<BorrowedFd> {
-        self.fd.as_ref().map(|fd| match fd {
-            LoadedEntryFd::Owned(o) => o.as_fd(),
-            LoadedEntryFd::HackySelfReference(b) => *b,
-        })
+        self.fd
+            .as_ref()
+            .map(AsFd::as_fd)
+            .or_else(|| self.loaded.as_backing_file())
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl<T: Deref<Target = [u8]>> LoadedEntry<T> {
+    /// Copies the entry's contents into a buffer that's wiped when dropped.
+    #[must_use]
+    pub fn into_zeroizing_vec(self) -> zeroize::Zeroizing<Vec<u8>> {
+        zeroize::Zeroizing::new(self.loaded.to_vec())
+    }
+}
+
+pub fn bytes_to_file(bytes: &[u8]) -> Result<File, ringboard_core::Error> {
+    let file = File::from(
+        memfd_create(c"ringboard_bucket_reader", MemfdFlags::empty())
+            .map_io_err(|| "Failed to create data entry file.")?,
+    );
+
+    file.write_all_at(bytes, 0)
+        .map_io_err(|| "Failed to write bytes to entry file.")?;
+    Ok(file)
+}
+
+/// Undoes any encoding the server stored a direct file's contents with.
+///
+/// Returns the entry's contents and, if they had to be decoded, the original
+/// file which still carries the entry's extended attributes.
+pub fn decode_direct_file(file: File) -> Result<(File, Option<OwnedFd>), ringboard_core::Error> {
+    let Some(encoding) = encoding::read(&file)? else {
+        return Ok((file, None));
+    };
+    let decoded = File::from(
+        memfd_create(c"ringboard_decoded_reader", MemfdFlags::empty())
+            .map_io_err(|| "Failed to create decoded entry file.")?,
+    );
+    match encoding {
+        Encoding::Zstd => {
+            let mut source = &file;
+            source
+                .rewind()
+                .and_then(|()| zstd::stream::copy_decode(source, &decoded))
+                .and_then(|()| (&decoded).rewind())
+                .map_io_err(|| "Failed to decompress entry.")?;
+        }
+    }
+    Ok((decoded, Some(file.into())))
+}
+
 impl<T> Deref for LoadedEntry<T> {
     type Target = T;
 
@@ -353,6 +1035,44 @@ impl<'a> Deref for MmapOrSlice<'a> {
     }
 }
 
+/// Entry contents read as a stream: direct files are read as they are and
+/// bucketed entries straight out of their bucket, so neither is copied or
+/// mapped up front.
+pub enum FileOrSlice<'a> {
+    File(File),
+    Slice(io::Cursor<&'a [u8]>),
+}
+
+impl<'a> From<&'a [u8]> for FileOrSlice<'a> {
+    fn from(value: &'a [u8]) -> Self {
+        Self::Slice(io::Cursor::new(value))
+    }
+}
+
+impl From<File> for FileOrSlice<'_> {
+    fn from(value: File) -> Self {
+        Self::File(value)
+    }
+}
+
+impl Read for FileOrSlice<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::File(f) => f.read(buf),
+            Self::Slice(s) => s.read(buf),
+        }
+    }
+}
+
+impl Seek for FileOrSlice<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(f) => f.seek(pos),
+            Self::Slice(s) => s.seek(pos),
+        }
+    }
+}
+
 impl Entry {
     #[must_use]
     pub fn kind(&self) -> Kind {
@@ -378,16 +1098,18 @@ impl Entry {
         composite_id(self.ring(), self.index())
     }
 
-    pub fn mime_type(&self, reader: &mut EntryReader) -> Result<MimeType, ringboard_core::Error> {
+    pub fn mime_type(&self, reader: &EntryReader) -> Result<MimeType, ringboard_core::Error> {
         match self.kind() {
             Kind::Bucket(_) => Ok(MimeType::new()),
             Kind::File => self.to_file(reader)?.mime_type(),
         }
     }
 
+    /// Loads the entry, mapping more of its bucket if the server grew it since
+    /// the reader last looked.
     pub fn to_slice<'a>(
         &self,
-        reader: &'a mut EntryReader,
+        reader: &'a EntryReader,
     ) -> Result<LoadedEntry<MmapOrSlice<'a>>, ringboard_core::Error> {
         self.grow_bucket_if_needed(reader)?;
         Ok(self.to_slice_raw(reader)?.unwrap())
@@ -395,40 +1117,147 @@ impl Entry {
 
     pub fn to_file(
         &self,
-        reader: &mut EntryReader,
+        reader: &EntryReader,
     ) -> Result<LoadedEntry<File>, ringboard_core::Error> {
         self.grow_bucket_if_needed(reader)?;
         Ok(self.to_file_raw(reader)?.unwrap())
     }
 
-    fn grow_bucket_if_needed(self, reader: &mut EntryReader) -> Result<(), ringboard_core::Error> {
+    /// Loads the entry for reading as a stream, e.g. by a decoder that only
+    /// needs part of it or can work through it a bit at a time.
+    ///
+    /// Unlike [`Entry::to_slice`], direct files aren't mapped and unlike
+    /// [`Entry::to_file`], bucketed entries aren't copied into a file.
+    pub fn to_reader<'a>(
+        &self,
+        reader: &'a EntryReader,
+    ) -> Result<LoadedEntry<FileOrSlice<'a>>, ringboard_core::Error> {
+        self.grow_bucket_if_needed(reader)?;
+        match self.kind() {
+            Kind::Bucket(_) => {
+                let Ok(bytes) = load_bucket_entry(reader, *self) else {
+                    unreachable!("the bucket was grown to fit the entry");
+                };
+                let bytes = bytes.ok_or_else(|| IdNotFoundError::Entry(self.index()))?;
+                Ok(LoadedEntry {
+                    loaded: bytes.into(),
+                    fd: None,
+                    added_by: self.added_by(reader),
+                })
+            }
+            Kind::File => {
+                let LoadedEntry {
+                    loaded,
+                    fd,
+                    added_by,
+                } = self.to_file_raw(reader)?.unwrap();
+                Ok(LoadedEntry {
+                    loaded: loaded.into(),
+                    fd,
+                    added_by,
+                })
+            }
+        }
+    }
+
+    /// Copies the entry's bytes into `out`, returning the number of bytes
+    /// written.
+    ///
+    /// Direct entries are streamed with [`io::copy`] which uses
+    /// `copy_file_range`, `sendfile`, or `splice` when `out` is backed by a
+    /// file, pipe, or socket and a small buffer otherwise.
+    pub fn write_to(
+        &self,
+        reader: &EntryReader,
+        out: &mut impl Write,
+    ) -> Result<u64, ringboard_core::Error> {
+        match self.kind() {
+            Kind::Bucket(_) => {
+                let bytes = self.to_slice(reader)?;
+                out.write_all(&bytes).map_io_err(|| Context::Entry {
+                    message: "Failed to write entry",
+                    id: self.id(),
+                })?;
+                Ok(u64::try_from(bytes.len()).unwrap())
+            }
+            Kind::File => {
+                io::copy(&mut *self.to_file(reader)?, out).map_io_err(|| Context::Entry {
+                    message: "Failed to copy entry",
+                    id: self.id(),
+                })
+            }
+        }
+    }
+
+    fn grow_bucket_if_needed(self, reader: &EntryReader) -> Result<(), ringboard_core::Error> {
         match self.kind() {
-            Kind::Bucket(entry) => {
+            Kind::Bucket(entry) if !entry.is_packed() => {
                 if let Err(BucketTooShort { bucket, needed_len }) =
                     bucket_entry_to_slice(reader, entry)
                 {
-                    let data = &mut reader.buckets[bucket];
-                    data.remap(needed_len)
-                        .map_io_err(|| format!("Failed to remap bucket {bucket:?}."))?;
+                    // Only as much of the bucket as exists gets mapped, so entries
+                    // pointing past it must come from a corrupted ring.
+                    if reader.refresh_bucket(bucket)?.len() < needed_len {
+                        return Err(ringboard_core::Error::Io {
+                            error: io::Error::new(
+                                ErrorKind::InvalidData,
+                                "Entry points past the end of its bucket.",
+                            ),
+                            context: Context::Entry {
+                                message: "Database corruption detected in entry",
+                                id: self.id(),
+                            },
+                        });
+                    }
                 }
             }
-            Kind::File => {}
+            Kind::Bucket(_) | Kind::File => {}
         }
         Ok(())
     }
 
+    fn added_by(self, reader: &EntryReader) -> Option<Origin> {
+        reader.added_by(self.ring(), self.index())
+    }
+
+    /// When the entry was copied, to the minute. Entries added by servers that
+    /// predate timestamps have none.
+    #[must_use]
+    pub fn timestamp(&self, reader: &EntryReader) -> Option<SystemTime> {
+        reader.timestamp(self.ring(), self.index())
+    }
+
+    /// The tags the user gave this entry, in the order they were added.
+    pub fn tags(&self, reader: &EntryReader) -> Result<TagSet, ringboard_core::Error> {
+        match self.kind() {
+            Kind::Bucket(_) => Ok(reader.tags(self.ring(), self.index())),
+            Kind::File => {
+                let mut buf = Default::default();
+                let buf = direct_file_name(&mut buf, self.ring(), self.index());
+
+                let file = openat(&reader.direct, &*buf, OFlags::RDONLY, Mode::empty())
+                    .map_io_err(|| format!("Failed to open direct file: {buf:?}"))?;
+                xattr_tags(file)
+            }
+        }
+    }
+
+    /// Like [`Entry::to_slice`], but returns [`None`] instead of mapping more
+    /// of a bucket.
     pub fn to_slice_raw<'a>(
         &self,
         reader: &'a EntryReader,
     ) -> Result<Option<LoadedEntry<MmapOrSlice<'a>>>, ringboard_core::Error> {
         match self.kind() {
-            Kind::Bucket(entry) => {
-                let Ok(bytes) = bucket_entry_to_slice(reader, entry) else {
+            Kind::Bucket(_) => {
+                let Ok(bytes) = load_bucket_entry(reader, *self) else {
                     return Ok(None);
                 };
+                let bytes = bytes.ok_or_else(|| IdNotFoundError::Entry(self.index()))?;
                 Ok(Some(LoadedEntry {
                     loaded: bytes.into(),
                     fd: None,
+                    added_by: self.added_by(reader),
                 }))
             }
             Kind::File => {
@@ -439,7 +1268,8 @@ impl Entry {
                     loaded: Mmap::from(&*file)
                         .map_io_err(|| format!("Failed to mmap data file: {file:?}"))?
                         .into(),
-                    fd: Some(LoadedEntryFd::Owned(file.loaded.into())),
+                    added_by: file.added_by,
+                    fd: Some(file.fd.unwrap_or_else(|| file.loaded.into())),
                 }))
             }
         }
@@ -450,21 +1280,15 @@ impl Entry {
         reader: &EntryReader,
     ) -> Result<Option<LoadedEntry<File>>, ringboard_core::Error> {
         match self.kind() {
-            Kind::Bucket(entry) => {
-                let Ok(bytes) = bucket_entry_to_slice(reader, entry) else {
+            Kind::Bucket(_) => {
+                let Ok(bytes) = load_bucket_entry(reader, *self) else {
                     return Ok(None);
                 };
-                let file = File::from(
-                    memfd_create(c"ringboard_bucket_reader", MemfdFlags::empty())
-                        .map_io_err(|| "Failed to create data entry file.")?,
-                );
-
-                file.write_all_at(bytes, 0)
-                    .map_io_err(|| "Failed to write bytes to entry file.")?;
-
+                let bytes = bytes.ok_or_else(|| IdNotFoundError::Entry(self.index()))?;
                 Ok(Some(LoadedEntry {
-                    loaded: file,
+                    loaded: bytes_to_file(bytes)?,
                     fd: None,
+                    added_by: self.added_by(reader),
                 }))
             }
             Kind::File => {
@@ -474,68 +1298,314 @@ impl Entry {
                 let file = openat(&reader.direct, &*buf, OFlags::RDONLY, Mode::empty())
                     .map_io_err(|| format!("Failed to open direct file: {buf:?}"))
                     .map(File::from)?;
+                let (loaded, fd) = decode_direct_file(file)?;
                 Ok(Some(LoadedEntry {
-                    fd: Some(LoadedEntryFd::HackySelfReference(unsafe {
-                        BorrowedFd::borrow_raw(file.as_raw_fd())
-                    })),
-                    loaded: file,
+                    loaded,
+                    fd,
+                    added_by: self.added_by(reader),
                 }))
             }
         }
     }
 }
 
+/// Reads the contents of entries out of the database's buckets and direct
+/// files.
+///
+/// Buckets are mapped read-only and shared, so any number of threads can read
+/// entries through a shared reference at once (searches share one through an
+/// [`Arc`](std::sync::Arc)). Entries the server added since a bucket was
+/// mapped are picked up by mapping the bucket again, also through a shared
+/// reference: reading an entry only takes a lock if it lies past the current
+/// mapping.
+///
+/// The server only ever grows buckets, so their mappings stay valid as long as
+/// the reader is alive.
 #[derive(Debug)]
 pub struct EntryReader {
-    buckets: [Mmap; NUM_BUCKETS],
+    buckets: [BucketMap; NUM_BUCKETS],
+    /// Kept around to check that a bucket grew before mapping more of it.
+    bucket_files: [OwnedFd; NUM_BUCKETS],
     direct: OwnedFd,
+    /// Missing when the server doesn't record who added entries.
+    origins: Option<Origins>,
+    /// Missing in databases written by servers that predate timestamps.
+    timestamps: Option<Timestamps>,
+    /// Missing in databases written by servers that predate tags.
+    tags: Option<Tags>,
+    /// Missing in databases written by servers that predate slot generations.
+    slot_generations: Option<SlotGenerations>,
+    /// Only present in databases created with short entries packed.
+    packed: Option<PackedEntries>,
 }
 
 impl EntryReader {
     pub fn open(database_dir: &mut PathBuf) -> Result<Self, ringboard_core::Error> {
         let direct_dir = {
             let file = PathView::new(database_dir, "direct");
-            openat(CWD, &*file, OFlags::DIRECTORY | OFlags::PATH, Mode::empty())
-                .map_io_err(|| format!("Failed to open directory: {file:?}"))
+            openat(CWD, &*file, OFlags::DIRECTORY | OFlags::PATH, Mode::empty()).map_io_err(|| {
+                Context::File {
+                    message: "Failed to open directory",
+                    path: file.to_path_buf(),
+                }
+            })
         }?;
 
-        let buckets = {
+        let (buckets, bucket_files) = {
             let mut buckets = PathView::new(database_dir, "buckets");
             let (buckets, lengths) = open_buckets(|name| {
                 let file = PathView::new(&mut buckets, name);
-                openat(CWD, &*file, OFlags::RDONLY, Mode::empty())
-                    .map_io_err(|| format!("Failed to open bucket: {file:?}"))
+                openat(CWD, &*file, OFlags::RDONLY, Mode::empty()).map_io_err(|| Context::File {
+                    message: "Failed to open bucket",
+                    path: file.to_path_buf(),
+                })
             })?;
 
             let mut maps = ArrayVec::new_const();
-            for (i, fd) in buckets.into_iter().enumerate() {
-                maps.push(
-                    Mmap::new(fd, usize::try_from(lengths[i]).unwrap())
-                        .map_io_err(|| format!("Failed to mmap bucket {i:?}."))?,
-                );
+            // Unlike the rings, buckets aren't prefetched: asking for all of them
+            // delays the first page on a cold cache (see the startup benchmark)
+            // since the few entries it shows are scattered across them. Pages
+            // prefetch just their own entries instead, see `prefetch`.
+            for (i, fd) in buckets.iter().enumerate() {
+                maps.push(BucketMap::new(
+                    Mmap::new(fd, usize::try_from(lengths[i]).unwrap()).map_io_err(|| {
+                        Context::Bucket {
+                            message: "Failed to mmap bucket",
+                            bucket: i,
+                        }
+                    })?,
+                ));
             }
-            maps.into_inner().unwrap()
+            (maps.into_inner().unwrap(), buckets)
         };
 
+        let origins = {
+            let file = PathView::new(database_dir, origins::FILE_NAME);
+            match Origins::open(&*file) {
+                Err(ringboard_core::Error::Io { error, .. })
+                    if error.kind() == ErrorKind::NotFound =>
+                {
+                    None
+                }
+                r => Some(r?),
+            }
+        };
+        let timestamps = {
+            let file = PathView::new(database_dir, timestamps::FILE_NAME);
+            match Timestamps::open(&*file) {
+                Err(ringboard_core::Error::Io { error, .. })
+                    if error.kind() == ErrorKind::NotFound =>
+                {
+                    None
+                }
+                r => Some(r?),
+            }
+        };
+        let tags = {
+            let file = PathView::new(database_dir, tags::FILE_NAME);
+            match Tags::open(&*file) {
+                Err(ringboard_core::Error::Io { error, .. })
+                    if error.kind() == ErrorKind::NotFound =>
+                {
+                    None
+                }
+                r => Some(r?),
+            }
+        };
+        let slot_generations = {
+            let file = PathView::new(database_dir, slot_generations::FILE_NAME);
+            match SlotGenerations::open(&*file) {
+                Err(ringboard_core::Error::Io { error, .. })
+                    if error.kind() == ErrorKind::NotFound =>
+                {
+                    None
+                }
+                r => Some(r?),
+            }
+        };
+        let packed = {
+            let file = PathView::new(database_dir, packed::INDEX_FILE_NAME);
+            match PackedIndex::open(&*file) {
+                Err(ringboard_core::Error::Io { error, .. })
+                    if error.kind() == ErrorKind::NotFound =>
+                {
+                    None
+                }
+                r => Some(r?),
+            }
+        };
+        let packed = packed
+            .map(|index| {
+                let file = PathView::new(database_dir, packed::LOG_FILE_NAME);
+                PackedEntries::open(index, &*file)
+            })
+            .transpose()?;
+
         Ok(Self {
             buckets,
+            bucket_files,
             direct: direct_dir,
+            origins,
+            timestamps,
+            tags,
+            slot_generations,
+            packed,
         })
     }
 
+    /// The buckets as currently mapped, which may miss entries the server
+    /// added since. See [`EntryReader::refresh`].
     #[must_use]
     pub fn buckets(&self) -> [&Mmap; NUM_BUCKETS] {
         let mut buckets = ArrayVec::new_const();
         for bucket in &self.buckets {
-            buckets.push(bucket);
+            buckets.push(bucket.get());
         }
         buckets.into_inner().unwrap()
     }
 
+    /// Maps whatever the server added to the buckets since they were last
+    /// mapped.
+    pub fn refresh(&self) -> Result<(), ringboard_core::Error> {
+        for bucket in 0..NUM_BUCKETS {
+            self.refresh_bucket(bucket)?;
+        }
+        Ok(())
+    }
+
+    /// Maps whatever the server added to one bucket since it was last mapped,
+    /// returning the up-to-date mapping.
+    pub fn refresh_bucket(&self, bucket: usize) -> Result<&Mmap, ringboard_core::Error> {
+        let fd = &self.bucket_files[bucket];
+        let len = statx(fd, c"", AtFlags::EMPTY_PATH, StatxFlags::SIZE)
+            .map_io_err(|| Context::Bucket {
+                message: "Failed to statx bucket",
+                bucket,
+            })?
+            .stx_size;
+        self.buckets[bucket]
+            .grow(fd, usize::try_from(len).unwrap())
+            .map_io_err(|| Context::Bucket {
+                message: "Failed to remap bucket",
+                bucket,
+            })
+    }
+
     #[must_use]
     pub fn direct(&self) -> BorrowedFd {
         self.direct.as_fd()
     }
+
+    /// The client that added the entry at `index` in `ring`, if the server
+    /// recorded it.
+    #[must_use]
+    pub fn added_by(&self, ring: RingKind, index: u32) -> Option<Origin> {
+        self.origins.as_ref()?.get(ring, index)
+    }
+
+    /// When the entry at `index` in `ring` was copied, if the server recorded
+    /// it.
+    #[must_use]
+    pub fn timestamp(&self, ring: RingKind, index: u32) -> Option<SystemTime> {
+        self.timestamps.as_ref()?.get(ring, index)
+    }
+
+    /// The tags of the bucketed entry at `index` in `ring`. Entries stored in
+    /// files of their own keep theirs in the file, see [`Entry::tags`].
+    #[must_use]
+    pub fn tags(&self, ring: RingKind, index: u32) -> TagSet {
+        self.tags
+            .as_ref()
+            .map(|tags| tags.get(ring, index))
+            .unwrap_or_default()
+    }
+
+    /// Asks the kernel to start reading in the parts of the buckets holding
+    /// `entries`, so loading them one by one afterwards doesn't wait on the
+    /// disk for each.
+    ///
+    /// Entries are scattered across the buckets in the order they're shown,
+    /// so on a cold cache every load would otherwise fault in its own page.
+    /// Like [`Mmap::prefetch`], this is only a hint: entries stored in their
+    /// own file, packed, or past the current mappings are skipped.
+    pub fn prefetch(&self, entries: impl IntoIterator<Item = Entry>) {
+        for (bucket, group) in group_by_bucket(entries).iter().enumerate() {
+            let size_class = usize::from(bucket_to_length(bucket));
+            let _ = self.buckets[bucket]
+                .get()
+                .prefetch_ranges(group.iter().map(|entry| {
+                    let Kind::Bucket(entry) = entry.kind() else {
+                        unreachable!()
+                    };
+                    let start = size_class * usize::try_from(entry.index()).unwrap();
+                    start..start + usize::from(entry.size())
+                }));
+        }
+    }
+}
+
+/// Groups bucketed entries by the bucket holding them, each group sorted by
+/// position in its bucket so it can be read front to back. Entries stored in
+/// their own file or packed are left out.
+///
+/// Batches of entries, such as a page of them or a set of search results,
+/// are cheaper to read in this order than in the order they're shown.
+pub fn group_by_bucket(entries: impl IntoIterator<Item = Entry>) -> [Vec<Entry>; NUM_BUCKETS] {
+    let mut groups = [const { Vec::new() }; NUM_BUCKETS];
+    for entry in entries {
+        match entry.kind() {
+            Kind::Bucket(metadata) if !metadata.is_packed() => {
+                groups[usize::from(size_to_bucket(metadata.size()))].push(entry);
+            }
+            Kind::Bucket(_) | Kind::File => {}
+        }
+    }
+    for group in &mut groups {
+        group.sort_unstable_by_key(|entry| entry.metadata.index());
+    }
+    groups
+}
+
+/// A bucket's mapping, which can be extended through a shared reference.
+///
+/// Reads only load the current mapping. Growing maps the bucket again and swaps
+/// the new mapping in, but keeps the old ones alive since slices borrowed from
+/// the reader may still point into them.
+#[derive(Debug)]
+struct BucketMap {
+    current: AtomicPtr<Mmap>,
+    /// Every mapping handed out so far, the current one last. Also keeps
+    /// threads from growing the bucket at the same time.
+    #[allow(clippy::vec_box)] // `current` points at one of them, so they can't move.
+    maps: Mutex<Vec<Box<Mmap>>>,
+}
+
+impl BucketMap {
+    fn new(map: Mmap) -> Self {
+        let mut map = Box::new(map);
+        Self {
+            current: AtomicPtr::new(&mut *map),
+            maps: Mutex::new(vec![map]),
+        }
+    }
+
+    fn get(&self) -> &Mmap {
+        // SAFETY: the pointer comes from one of the boxes in `maps`, which are only
+        // dropped along with self.
+        unsafe { &*self.current.load(Ordering::Acquire) }
+    }
+
+    /// Maps `len` bytes of the bucket unless that much is already mapped.
+    fn grow(&self, fd: &OwnedFd, len: usize) -> rustix::io::Result<&Mmap> {
+        let mut maps = self.maps.lock().unwrap_or_else(PoisonError::into_inner);
+        if maps.last().unwrap().len() < len {
+            let mut map = Box::new(Mmap::new(fd, len)?);
+            self.current.store(&mut *map, Ordering::Release);
+            maps.push(map);
+        }
+        drop(maps);
+        Ok(self.get())
+    }
 }
 
 struct BucketTooShort {
@@ -543,6 +1613,31 @@ struct BucketTooShort {
     needed_len: usize,
 }
 
+/// Like [`bucket_entry_to_slice`], but returns [`None`] if the entry was
+/// overwritten. Packed entries are read from the packed log instead, which is
+/// mapped as far as it can grow.
+///
+/// The server may reuse the slot of an entry while readers still hold it, see
+/// [`ringboard_core::ring`].
+fn load_bucket_entry(reader: &EntryReader, entry: Entry) -> Result<Option<&[u8]>, BucketTooShort> {
+    let Entry {
+        rai,
+        metadata: entry,
+    } = entry;
+    if entry.is_packed() {
+        return Ok(reader
+            .packed
+            .as_ref()
+            .and_then(|packed| packed.get(rai.ring(), rai.index(), entry)));
+    }
+    let Some(generations) = &reader.slot_generations else {
+        return bucket_entry_to_slice(reader, entry).map(Some);
+    };
+    generations
+        .read(entry, || bucket_entry_to_slice(reader, entry))
+        .transpose()
+}
+
 fn bucket_entry_to_slice(
     reader: &EntryReader,
     entry: InitializedEntry,
@@ -553,7 +1648,7 @@ fn bucket_entry_to_slice(
 
     let size_class = usize::from(bucket_to_length(bucket));
     let start = size_class * index;
-    let mem = &reader.buckets[bucket];
+    let mem = reader.buckets[bucket].get();
     if start + size > mem.len() {
         return Err(BucketTooShort {
             bucket,
@@ -561,6 +1656,618 @@ fn bucket_entry_to_slice(
         });
     }
 
-    let ptr = mem.ptr().as_ptr();
-    Ok(unsafe { slice::from_raw_parts(ptr.add(start), size) })
+    // The slice borrows the reader, so the bucket can't be remapped under it.
+    Ok(&mem[start..start + size])
+}
+
+// GUIs share readers across threads, so losing any of these would break them.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<DatabaseReader>();
+    assert_send_sync::<RingReader>();
+    assert_send_sync::<EntryReader>();
+    assert_send_sync::<Entry>();
+    assert_send_sync::<LoadedEntry<File>>();
+    assert_send_sync::<LoadedEntry<MmapOrSlice>>();
+    assert_send_sync::<LoadedEntry<FileOrSlice>>();
+};
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        fs::{File, OpenOptions},
+        io::{pipe, Read, Seek, SeekFrom},
+        mem::offset_of,
+        os::unix::fs::FileExt,
+        path::PathBuf,
+        process,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use ringboard_core::{
+        direct_file_name, open_buckets,
+        protocol::{decompose_id, IdNotFoundError, RingKind},
+        ring,
+        ring::{entries_to_offset, Header, InitializedEntry, RawEntry, Ring},
+        size_to_bucket, timestamps,
+        timestamps::TimestampsWriter,
+        IoErr, PathView,
+    };
+    use rustix::fs::{openat, Mode, OFlags, CWD};
+
+    use super::{
+        group_by_bucket, Cursor, Entry, EntryReader, FileOrSlice, Kind, MergedReader, ResumeError,
+        RingReader,
+    };
+
+    struct TestDatabase {
+        dir: PathBuf,
+        reader: EntryReader,
+    }
+
+    impl TestDatabase {
+        fn new(name: &str, bucketed: &[u8], direct: &[u8]) -> Self {
+            let mut dir = std::env::temp_dir().join(format!("ringboard-{name}-{}", process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("buckets")).unwrap();
+            fs::create_dir_all(dir.join("direct")).unwrap();
+
+            {
+                let mut buckets = PathView::new(&mut dir, "buckets");
+                let (buckets, _) = open_buckets(|name| {
+                    let file = PathView::new(&mut buckets, name);
+                    openat(
+                        CWD,
+                        &*file,
+                        OFlags::RDWR | OFlags::CREATE,
+                        Mode::RUSR | Mode::WUSR,
+                    )
+                    .map_io_err(|| format!("Failed to create bucket: {file:?}"))
+                })
+                .unwrap();
+                let [first, ..] = buckets;
+                File::from(first).write_all_at(bucketed, 0).unwrap();
+            }
+            {
+                let mut buf = Default::default();
+                let name = direct_file_name(&mut buf, RingKind::Main, 1);
+                fs::write(dir.join("direct").join(name.to_str().unwrap()), direct).unwrap();
+            }
+
+            let reader = EntryReader::open(&mut dir).unwrap();
+            Self { dir, reader }
+        }
+    }
+
+    impl Drop for TestDatabase {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn bucketed_entry() -> Entry {
+        Entry::new(RingKind::Main, 0, InitializedEntry::bucket(4, 0))
+    }
+
+    fn direct_entry() -> Entry {
+        Entry::new(RingKind::Main, 1, InitializedEntry::file())
+    }
+
+    #[test]
+    fn bucketed_to_vec() {
+        let db = TestDatabase::new("bucketed-to-vec", b"abcd", b"");
+
+        let mut out = Vec::new();
+        let written = bucketed_entry().write_to(&db.reader, &mut out).unwrap();
+
+        assert_eq!(written, 4);
+        assert_eq!(out, b"abcd");
+    }
+
+    #[test]
+    fn batches_are_grouped_in_bucket_order() {
+        let db = TestDatabase::new("bucket-order", b"abcdefgh", b"");
+        let entries = [
+            Entry::new(RingKind::Main, 0, InitializedEntry::bucket(4, 1)),
+            direct_entry(),
+            Entry::new(RingKind::Main, 2, InitializedEntry::bucket(100, 3)),
+            Entry::new(RingKind::Favorites, 3, InitializedEntry::bucket(3, 0)),
+        ];
+
+        let groups = group_by_bucket(entries);
+        let ids = |size| {
+            groups[usize::from(size_to_bucket(size))]
+                .iter()
+                .map(Entry::id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(4), [entries[3].id(), entries[0].id()]);
+        assert_eq!(ids(100), [entries[2].id()]);
+        assert_eq!(groups.iter().map(Vec::len).sum::<usize>(), 3);
+
+        // Entries past the end of their bucket are left for loading to report.
+        db.reader.prefetch(entries);
+    }
+
+    #[test]
+    fn direct_to_file() {
+        let data = vec![7; 3 * 4096 + 1];
+        let db = TestDatabase::new("direct-to-file", b"", &data);
+
+        let mut out = File::from(
+            openat(
+                CWD,
+                std::env::temp_dir(),
+                OFlags::RDWR | OFlags::TMPFILE,
+                Mode::RUSR | Mode::WUSR,
+            )
+            .unwrap(),
+        );
+        let written = direct_entry().write_to(&db.reader, &mut out).unwrap();
+
+        let mut copied = Vec::new();
+        out.rewind().unwrap();
+        out.read_to_end(&mut copied).unwrap();
+        assert_eq!(written, u64::try_from(data.len()).unwrap());
+        assert_eq!(copied, data);
+    }
+
+    #[test]
+    fn entries_are_streamed_in_place() {
+        let data = vec![7; 3 * 4096 + 1];
+        let db = TestDatabase::new("streamed", b"abcd", &data);
+
+        let mut bucketed = bucketed_entry().to_reader(&db.reader).unwrap();
+        assert!(matches!(*bucketed, FileOrSlice::Slice(_)));
+        assert!(bucketed.backing_file().is_none());
+        let mut copied = Vec::new();
+        bucketed.read_to_end(&mut copied).unwrap();
+        assert_eq!(copied, b"abcd");
+
+        let mut direct = direct_entry().to_reader(&db.reader).unwrap();
+        assert!(matches!(*direct, FileOrSlice::File(_)));
+        assert!(direct.backing_file().is_some());
+        let mut tail = Vec::new();
+        direct.seek(SeekFrom::End(-2)).unwrap();
+        direct.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, [7, 7]);
+    }
+
+    /// A ring the tests write to directly, playing the part of the server.
+    struct TestRing {
+        path: PathBuf,
+        file: File,
+        ring: Ring,
+        /// The sequence number of the entry in each slot.
+        slots: Vec<u32>,
+        next: u32,
+    }
+
+    impl TestRing {
+        fn new(name: &str, capacity: u32) -> Self {
+            let path = std::env::temp_dir().join(format!("ringboard-{name}-{}", process::id()));
+            let Header {
+                magic,
+                version,
+                write_head,
+            } = Header::default();
+            let mut header = magic.to_vec();
+            header.push(version);
+            header.extend_from_slice(&write_head.to_le_bytes());
+            fs::write(&path, header).unwrap();
+
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            let ring = Ring::open(capacity, &path).unwrap();
+            Self {
+                path,
+                file,
+                ring,
+                slots: Vec::new(),
+                next: 0,
+            }
+        }
+
+        fn add(&mut self) {
+            let head = self.ring.write_head();
+            let entry = ring::Entry::Bucketed(InitializedEntry::bucket(1, self.next));
+            self.file
+                .write_all_at(
+                    &RawEntry::from(entry).to_le_bytes(),
+                    entries_to_offset(head),
+                )
+                .unwrap();
+            self.file
+                .write_all_at(
+                    &self.ring.next_head(head).to_le_bytes(),
+                    u64::try_from(offset_of!(Header, write_head)).unwrap(),
+                )
+                .unwrap();
+
+            let index = usize::try_from(head).unwrap();
+            if index == self.slots.len() {
+                self.slots.push(self.next);
+                unsafe {
+                    self.ring.set_len(head + 1);
+                }
+            } else {
+                self.slots[index] = self.next;
+            }
+            self.next += 1;
+        }
+
+        fn remove(&self, index: u32) {
+            self.file
+                .write_all_at(
+                    &RawEntry::from(ring::Entry::Uninitialized).to_le_bytes(),
+                    entries_to_offset(index),
+                )
+                .unwrap();
+        }
+
+        /// The sequence numbers in the ring, oldest first.
+        fn sequence(&self) -> Vec<u32> {
+            let mut sequence = self.slots.clone();
+            sequence.sort_unstable();
+            sequence
+        }
+    }
+
+    impl Drop for TestRing {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn sequence_number(entry: Entry) -> u32 {
+        let Kind::Bucket(entry) = entry.kind() else {
+            unreachable!()
+        };
+        entry.index()
+    }
+
+    /// `SplitMix64`, which is plenty for picking how far to read and write.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u32) -> u32 {
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            u32::try_from((z ^ (z >> 31)) % u64::from(n)).unwrap()
+        }
+    }
+
+    #[test]
+    #[allow(clippy::similar_names)]
+    fn resumed_readers_skip_and_repeat_nothing() {
+        for seed in 0..50 {
+            let mut rng = Rng(seed);
+            let capacity = 2 + rng.below(8);
+            let mut ring = TestRing::new(&format!("resume-{seed}"), capacity);
+
+            for _ in 0..20 {
+                for _ in 0..rng.below(2 * capacity) {
+                    ring.add();
+                }
+                if ring.slots.is_empty() {
+                    continue;
+                }
+
+                // Read a page newest first.
+                let write_head = ring.ring.write_head();
+                let page = 1 + rng.below(ring.ring.len());
+                let last = RingReader::from_ring(&ring.ring, RingKind::Main)
+                    .rev()
+                    .take(usize::try_from(page).unwrap())
+                    .last()
+                    .unwrap();
+                let (_, index) = decompose_id(last.id()).unwrap();
+                let last = sequence_number(last);
+
+                // Wrapping all the way around can't be detected.
+                for _ in 0..rng.below(capacity) {
+                    ring.add();
+                }
+
+                let resumed = RingReader::from_id(&ring.ring, RingKind::Main, write_head, index);
+                let still_there = ring.slots[usize::try_from(index).unwrap()] == last;
+                let reader = match resumed {
+                    Ok(reader) => reader,
+                    Err(ResumeError::ResumePointLost) if !still_there => continue,
+                    Err(e) => panic!("Failed to resume at {index} (seed {seed}): {e}"),
+                };
+                assert!(still_there, "Resumed at overwritten {index} (seed {seed})");
+
+                let (older, newer) = ring
+                    .sequence()
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|&s| s < last);
+                assert_eq!(
+                    reader.rev().map(sequence_number).collect::<Vec<_>>(),
+                    older.into_iter().rev().collect::<Vec<_>>(),
+                    "Older entries (seed {seed})"
+                );
+                let reader =
+                    RingReader::from_id(&ring.ring, RingKind::Main, write_head, index).unwrap();
+                assert_eq!(
+                    reader.map(sequence_number).collect::<Vec<_>>(),
+                    newer,
+                    "Newer entries (seed {seed})"
+                );
+            }
+        }
+    }
+
+    /// The sequence numbers in both rings, newest first: the entries the same
+    /// distance behind their write heads are next to each other, main first.
+    fn merged_sequence(main: &TestRing, favorites: &TestRing) -> Vec<u32> {
+        let mut entries = Vec::new();
+        for (rank, ring) in [main, favorites].into_iter().enumerate() {
+            let len = ring.ring.len();
+            let reader = RingReader::from_ring(&ring.ring, RingKind::Main);
+            for entry in reader {
+                let age = (ring.ring.write_head() + len - 1 - entry.index()) % len;
+                entries.push((age, rank, sequence_number(entry)));
+            }
+        }
+        entries.sort_unstable();
+        entries.into_iter().map(|(_, _, s)| s).collect()
+    }
+
+    fn merged_reader<'a>(main: &'a TestRing, favorites: &'a TestRing) -> MergedReader<'a> {
+        MergedReader::new(
+            RingReader::from_ring(&main.ring, RingKind::Main),
+            RingReader::from_ring(&favorites.ring, RingKind::Favorites),
+        )
+    }
+
+    #[test]
+    fn merged_readers_interleave_by_position() {
+        for seed in 0..100 {
+            let mut rng = Rng(seed);
+            let mut main = TestRing::new(&format!("merged-main-{seed}"), 2 + rng.below(8));
+            let mut favorites =
+                TestRing::new(&format!("merged-favorites-{seed}"), 1 + rng.below(4));
+            // Tell the rings apart by sequence number.
+            favorites.next = 1000;
+            let mut holes = false;
+            for ring in [&mut main, &mut favorites] {
+                // Empty, partly filled, full, and wrapped rings.
+                let adds = if rng.below(5) == 0 {
+                    0
+                } else {
+                    rng.below(3 * ring.ring.capacity())
+                };
+                for _ in 0..adds {
+                    ring.add();
+                }
+                if !ring.slots.is_empty() && rng.below(3) == 0 {
+                    ring.remove(rng.below(ring.ring.len()));
+                    holes = true;
+                }
+            }
+
+            let newest_first = merged_sequence(&main, &favorites);
+            assert_eq!(
+                merged_reader(&main, &favorites)
+                    .rev()
+                    .map(sequence_number)
+                    .collect::<Vec<_>>(),
+                newest_first,
+                "Newest first (seed {seed})"
+            );
+
+            // Reading from both ends meets in the middle.
+            let mut reader = merged_reader(&main, &favorites);
+            let (mut oldest, mut newest) = (Vec::new(), Vec::new());
+            loop {
+                let remaining = newest_first.len() - oldest.len() - newest.len();
+                let (lower, upper) = reader.size_hint();
+                assert!(lower <= remaining, "Lower bound (seed {seed})");
+                assert!(upper.unwrap() >= remaining, "Upper bound (seed {seed})");
+                if !holes {
+                    assert_eq!(upper, Some(remaining), "Exact bound (seed {seed})");
+                }
+
+                let entry = if rng.below(2) == 0 {
+                    reader.next().map(|e| oldest.push(sequence_number(e)))
+                } else {
+                    reader.next_back().map(|e| newest.push(sequence_number(e)))
+                };
+                if entry.is_none() {
+                    break;
+                }
+            }
+            assert_eq!(reader.size_hint(), (0, Some(0)));
+            newest.extend(oldest.iter().rev());
+            assert_eq!(newest, newest_first, "Both ends (seed {seed})");
+        }
+    }
+
+    #[test]
+    fn merged_readers_order_by_timestamp() {
+        let db = TestDatabase::new("merged-timestamps", b"", b"");
+        let mut main = TestRing::new("merged-timestamps-main", 8);
+        let mut favorites = TestRing::new("merged-timestamps-favorites", 8);
+        favorites.next = 1000;
+        for _ in 0..3 {
+            main.add();
+        }
+        for _ in 0..2 {
+            favorites.add();
+        }
+        let minute = |m: u64| Some(UNIX_EPOCH + Duration::from_secs(60 * m));
+        {
+            let mut writer = TimestampsWriter::open(&db.dir.join(timestamps::FILE_NAME)).unwrap();
+            for (index, time) in [10, 20, 30].into_iter().enumerate() {
+                let index = u32::try_from(index).unwrap();
+                writer.set(RingKind::Main, index, minute(time)).unwrap();
+            }
+            // The newest favorite has no timestamp.
+            writer.set(RingKind::Favorites, 0, minute(25)).unwrap();
+        }
+        let reader = EntryReader::open(&mut db.dir.clone()).unwrap();
+        let newest_first =
+            |merged: MergedReader| merged.rev().map(sequence_number).collect::<Vec<_>>();
+
+        assert_eq!(
+            newest_first(merged_reader(&main, &favorites)),
+            [2, 1001, 1, 1000, 0]
+        );
+        assert_eq!(
+            newest_first(merged_reader(&main, &favorites).by_timestamp(&reader)),
+            [2, 1001, 1000, 1, 0]
+        );
+        assert_eq!(
+            merged_reader(&main, &favorites)
+                .by_timestamp(&reader)
+                .map(sequence_number)
+                .collect::<Vec<_>>(),
+            [0, 1, 1000, 1001, 2]
+        );
+    }
+
+    #[test]
+    fn resuming_out_of_bounds_is_refused() {
+        let mut ring = TestRing::new("resume-out-of-bounds", 4);
+        ring.add();
+
+        assert_eq!(
+            RingReader::from_id(&ring.ring, RingKind::Main, 1, 1).unwrap_err(),
+            ResumeError::NotFound(IdNotFoundError::Entry(1))
+        );
+        assert_eq!(
+            RingReader::from_id(&ring.ring, RingKind::Main, 3, 0).unwrap_err(),
+            ResumeError::OutOfBounds { index: 3, len: 1 }
+        );
+    }
+
+    #[test]
+    fn windows_stop_at_the_write_head() {
+        let mut ring = TestRing::new("window", 8);
+        for _ in 0..10 {
+            ring.add();
+        }
+        let window = |ring: &TestRing, id, before, after| {
+            RingReader::window(&ring.ring, RingKind::Main, id, before, after)
+                .unwrap()
+                .map(sequence_number)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(window(&ring, 0, 3, 3), [5, 6, 7, 8, 9]);
+        assert_eq!(window(&ring, 3, 3, 1), [2, 3, 4]);
+        assert_eq!(window(&ring, 4, 0, 0), [4]);
+        let reader = RingReader::window(&ring.ring, RingKind::Main, 0, 2, 0).unwrap();
+        assert_eq!(
+            reader.rev().map(sequence_number).collect::<Vec<_>>(),
+            [8, 7, 6]
+        );
+
+        ring.remove(6);
+        assert_eq!(window(&ring, 0, 2, 0), [5, 7, 8]);
+        assert_eq!(
+            RingReader::window(&ring.ring, RingKind::Main, 6, 1, 1).unwrap_err(),
+            IdNotFoundError::Entry(6)
+        );
+        assert_eq!(
+            RingReader::window(&ring.ring, RingKind::Main, 8, 1, 1).unwrap_err(),
+            IdNotFoundError::Entry(8)
+        );
+        assert_eq!(
+            RingReader::from_id(&ring.ring, RingKind::Main, 2, 6).unwrap_err(),
+            ResumeError::NotFound(IdNotFoundError::Entry(6))
+        );
+    }
+
+    /// Reads a page of `page` entries newest first, returning where it ended.
+    fn read_page(ring: &TestRing, page: usize, generation: u64) -> (Vec<u32>, Cursor) {
+        let mut reader = RingReader::from_ring(&ring.ring, RingKind::Main);
+        let entries = reader
+            .by_ref()
+            .rev()
+            .take(page)
+            .map(sequence_number)
+            .collect();
+        (entries, reader.cursor(Some(generation)))
+    }
+
+    #[test]
+    fn cursors_resume_after_appends() {
+        let mut ring = TestRing::new("cursor-append", 8);
+        for _ in 0..3 {
+            ring.add();
+        }
+        let (_, top) = read_page(&ring, 0, 3);
+        let (page, cursor) = read_page(&ring, 2, 3);
+        assert_eq!(page, [2, 1]);
+        let (_, end) = read_page(&ring, 3, 3);
+
+        ring.add();
+        ring.add();
+
+        let resumed = RingReader::resume(&ring.ring, cursor, Some(5)).unwrap();
+        assert_eq!(resumed.rev().map(sequence_number).collect::<Vec<_>>(), [0]);
+        let resumed = RingReader::resume(&ring.ring, top, Some(5)).unwrap();
+        assert_eq!(
+            resumed.rev().map(sequence_number).collect::<Vec<_>>(),
+            [2, 1, 0]
+        );
+        let resumed = RingReader::resume(&ring.ring, end, Some(5)).unwrap();
+        assert_eq!(resumed.rev().count(), 0);
+    }
+
+    #[test]
+    fn cursors_expire_after_wrapping() {
+        let mut ring = TestRing::new("cursor-wrap", 4);
+        for _ in 0..4 {
+            ring.add();
+        }
+        let (page, cursor) = read_page(&ring, 1, 4);
+        assert_eq!(page, [3]);
+
+        // Overwrites the entries older than the cursor but not its own slot.
+        ring.add();
+        let resumed = RingReader::resume(&ring.ring, cursor, Some(5)).unwrap();
+        assert_eq!(
+            resumed.rev().map(sequence_number).collect::<Vec<_>>(),
+            [2, 1]
+        );
+
+        // Lands back on the same write head, which only the generation reveals.
+        for _ in 0..3 {
+            ring.add();
+        }
+        assert!(RingReader::from_id(&ring.ring, RingKind::Main, 0, 3).is_ok());
+        assert_eq!(
+            RingReader::resume(&ring.ring, cursor, Some(8)).unwrap_err(),
+            ResumeError::ResumePointLost
+        );
+        assert_eq!(
+            RingReader::resume(&ring.ring, cursor, Some(3)).unwrap_err(),
+            ResumeError::ResumePointLost
+        );
+    }
+
+    #[test]
+    fn direct_to_pipe() {
+        let data = b"Hello, pipe!";
+        let db = TestDatabase::new("direct-to-pipe", b"", data);
+
+        let (mut rx, mut tx) = pipe().unwrap();
+        let written = direct_entry().write_to(&db.reader, &mut tx).unwrap();
+        drop(tx);
+
+        let mut copied = Vec::new();
+        rx.read_to_end(&mut copied).unwrap();
+        assert_eq!(written, u64::try_from(data.len()).unwrap());
+        assert_eq!(copied, data);
+    }
 }
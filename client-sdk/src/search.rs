@@ -1,7 +1,11 @@
 use std::{
+    collections::{HashMap, HashSet},
     ffi::CStr,
+    fs::File,
+    hash::BuildHasherDefault,
     io,
     io::ErrorKind,
+    iter,
     mem::MaybeUninit,
     os::fd::OwnedFd,
     str,
@@ -9,28 +13,45 @@ use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc,
-        mpsc::{SendError, SyncSender},
+        mpsc::{RecvTimeoutError, SendError, SyncSender},
         Arc,
     },
     thread,
     thread::JoinHandle,
+    time::Instant,
 };
 
 use arrayvec::ArrayVec;
 use memchr::memmem::Finder;
 use regex::bytes::Regex;
+use regex_automata::{meta, util::syntax, Input};
+use regex_syntax::{
+    hir::{
+        literal::{ExtractKind, Extractor},
+        Capture, Class, Hir, HirKind, Literal, Repetition,
+    },
+    ParserBuilder,
+};
 use ringboard_core::{
-    bucket_to_length, ring::Mmap, size_to_bucket, Error as CoreError, IoErr, DIRECT_FILE_NAME_LEN,
-    TEXT_MIMES,
+    bucket_to_length, packed,
+    protocol::{decompose_id, MimeType, RingKind},
+    ring::Mmap,
+    size_to_bucket, BucketAndIndex, Error as CoreError, IoErr, DIRECT_FILE_NAME_LEN, TEXT_MIMES,
 };
+use rustc_hash::FxHasher;
 use rustix::{
     fs::{openat, Mode, OFlags, RawDir},
     thread::{unshare, UnshareFlags},
 };
 use thiserror::Error;
 
-use crate::{ring_reader::xattr_mime_type, EntryReader};
+use crate::{
+    ring_reader::{decode_direct_file, xattr_image_text, xattr_label, xattr_mime_type},
+    wipe::ContentBuf,
+    DatabaseReader, Entry, EntryReader, Kind,
+};
 
+/// A plain query matched regardless of case, including outside ASCII.
 #[derive(Clone, Debug)]
 pub struct CaselessQuery {
     query: Vec<u8>,
@@ -60,10 +81,72 @@ pub enum Query<'a> {
     Mimes(Regex),
 }
 
+impl Query<'_> {
+    /// Finds every match in text that doesn't live in a database, e.g. to
+    /// highlight an entry's contents. Empty matches are skipped since there's
+    /// nothing to show for them, and mime queries never match text.
+    #[must_use]
+    pub fn find_all(&self, haystack: &[u8]) -> Vec<(usize, usize)> {
+        fn find_all_impl(mut query: impl QueryImpl, haystack: &[u8]) -> Vec<(usize, usize)> {
+            let mut matches = Vec::new();
+            let mut pos = 0;
+            while let Some((start, end)) = query.find(&haystack[pos..]) {
+                if start == end {
+                    pos += start + 1;
+                } else {
+                    matches.push((pos + start, pos + end));
+                    pos += end;
+                }
+                if pos >= haystack.len() {
+                    break;
+                }
+            }
+            matches
+        }
+
+        match self {
+            Self::Plain(query) => find_all_impl(
+                PlainQuery(Arc::new(Finder::new(query).into_owned())),
+                haystack,
+            ),
+            Self::PlainIgnoreCase(query) => {
+                find_all_impl(PlainIgnoreCaseQuery::new(query.clone()), haystack)
+            }
+            // Restarting the search partway through would break anchors.
+            Self::Regex(regex) => regex
+                .find_iter(haystack)
+                .filter(|m| !m.is_empty())
+                .map(|m| (m.start(), m.end()))
+                .collect(),
+            Self::Mimes(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "testing")]
+impl Query<'_> {
+    /// Matches entries that don't live in a database. Mime types are always
+    /// empty.
+    pub(crate) fn is_match(&self, haystack: &[u8]) -> bool {
+        match self {
+            Self::Plain(query) => memchr::memmem::find(haystack, query).is_some(),
+            Self::PlainIgnoreCase(query) => PlainIgnoreCaseQuery::new(query.clone())
+                .find(haystack)
+                .is_some(),
+            Self::Regex(regex) => regex.is_match(haystack),
+            Self::Mimes(regex) => regex.is_match(b""),
+        }
+    }
+}
+
 trait QueryImpl {
     fn find(&mut self, haystack: &[u8]) -> Option<(usize, usize)>;
 
     fn needle_len(&self) -> Option<usize>;
+
+    /// Lets a search through a single long haystack give up once `token` is
+    /// cancelled, for queries slow enough that it matters.
+    fn stop_on(&mut self, _: &CancellationToken) {}
 }
 
 #[derive(Clone)]
@@ -81,50 +164,328 @@ impl QueryImpl for PlainQuery {
     }
 }
 
+/// Looks for an already lowercased needle in lowercased haystacks.
+///
+/// Characters can change length when lowercased, so non-ASCII haystacks keep
+/// track of where each lowercased byte came from to report matches in terms
+/// of the original haystack.
 #[derive(Clone)]
 struct PlainIgnoreCaseQuery {
     inner: PlainQuery,
-    cache: Vec<u8>,
+    ascii: bool,
+    cache: ContentBuf,
+    offsets: Vec<usize>,
+}
+
+impl PlainIgnoreCaseQuery {
+    fn new(CaselessQuery { query, trim }: CaselessQuery) -> Self {
+        let mut needle = Vec::with_capacity(query.len());
+        to_lowercase(&query, &mut needle, &mut Vec::new());
+        let needle = if trim { needle.trim_ascii() } else { &needle };
+        Self {
+            inner: PlainQuery(Arc::new(Finder::new(needle).into_owned())),
+            ascii: needle.is_ascii(),
+            cache: ContentBuf::default(),
+            offsets: Vec::new(),
+        }
+    }
 }
 
 impl QueryImpl for PlainIgnoreCaseQuery {
     fn find(&mut self, haystack: &[u8]) -> Option<(usize, usize)> {
         self.cache.clear();
-        self.cache
-            .extend(haystack.iter().map(u8::to_ascii_lowercase));
+        if haystack.is_ascii() {
+            self.cache
+                .extend(haystack.iter().map(u8::to_ascii_lowercase));
+            return self.inner.find(&self.cache);
+        }
 
-        self.inner.find(&self.cache)
+        to_lowercase(haystack, &mut self.cache, &mut self.offsets);
+        let (start, end) = self.inner.find(&self.cache)?;
+        let original = |i: usize| self.offsets.get(i).copied().unwrap_or(haystack.len());
+        // A match ending partway through a lowercased character covers all of it.
+        let end = if end == start {
+            original(start)
+        } else {
+            let last = self.offsets[end - 1];
+            self.offsets[end..]
+                .iter()
+                .copied()
+                .find(|&offset| offset != last)
+                .unwrap_or(haystack.len())
+        };
+        Some((original(start), end))
     }
 
     fn needle_len(&self) -> Option<usize> {
-        self.inner.needle_len()
+        // Some characters grow when lowercased (e.g. İ), so a non-ASCII needle
+        // can match fewer haystack bytes than it has.
+        if self.ascii {
+            self.inner.needle_len()
+        } else {
+            None
+        }
+    }
+}
+
+/// Lowercases text one character at a time, recording the offset in `text`
+/// each output byte came from. Invalid UTF-8 is passed through as is.
+fn to_lowercase(text: &[u8], out: &mut Vec<u8>, offsets: &mut Vec<usize>) {
+    offsets.clear();
+    let mut offset = 0;
+    for chunk in text.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            for lower in c.to_lowercase() {
+                let len = lower.len_utf8();
+                out.extend_from_slice(lower.encode_utf8(&mut [0; 4]).as_bytes());
+                offsets.extend(iter::repeat_n(offset, len));
+            }
+            offset += c.len_utf8();
+        }
+        for &b in chunk.invalid() {
+            out.push(b);
+            offsets.push(offset);
+            offset += 1;
+        }
     }
 }
 
+/// How many bytes of a haystack a regex that can't match across lines is run
+/// on at a time, rounded up to the next line.
+const REGEX_WINDOW: usize = 64 * 1024;
+
+/// Runs a regex with its matches ruled out up front where possible.
+///
+/// Haystacks shorter than the shortest possible match or missing a literal
+/// every match contains are skipped without running the regex engine. Long
+/// haystacks are searched a few lines at a time if no match can span lines,
+/// so a slow pattern can be cancelled partway through an entry.
 #[derive(Clone)]
-struct RegexQuery(Regex);
+struct RegexQuery {
+    regex: meta::Regex,
+    min_len: Option<usize>,
+    required: Option<Arc<Finder<'static>>>,
+    single_line: bool,
+    token: Option<CancellationToken>,
+}
+
+impl RegexQuery {
+    fn new(regex: &Regex) -> Self {
+        // Search regexes are compiled from their pattern alone, so it parses and
+        // compiles just the same with the settings `regex::bytes` uses.
+        let hir = ParserBuilder::new()
+            .utf8(false)
+            .build()
+            .parse(regex.as_str())
+            .unwrap();
+        let prefixes = Extractor::new().kind(ExtractKind::Prefix).extract(&hir);
+        let suffixes = Extractor::new().kind(ExtractKind::Suffix).extract(&hir);
+        let required = [
+            prefixes.longest_common_prefix(),
+            suffixes.longest_common_suffix(),
+        ]
+        .into_iter()
+        .flatten()
+        .max_by_key(|literal| literal.len())
+        .filter(|literal| !literal.is_empty());
+        Self {
+            regex: meta::Builder::new()
+                .syntax(syntax::Config::new().utf8(false))
+                .configure(meta::Config::new().utf8_empty(false))
+                .build_from_hir(&hir)
+                .unwrap(),
+            min_len: hir.properties().minimum_len(),
+            required: required.map(|literal| Arc::new(Finder::new(literal).into_owned())),
+            single_line: !can_match_newline(&hir),
+            token: None,
+        }
+    }
+}
 
 impl QueryImpl for RegexQuery {
     fn find(&mut self, haystack: &[u8]) -> Option<(usize, usize)> {
-        self.0.find(haystack).map(|m| (m.start(), m.end()))
+        if self.min_len.is_some_and(|min_len| haystack.len() < min_len)
+            || self
+                .required
+                .as_ref()
+                .is_some_and(|required| required.find(haystack).is_none())
+        {
+            return None;
+        }
+        if !self.single_line {
+            return self.regex.find(haystack).map(|m| (m.start(), m.end()));
+        }
+
+        // Windows end after a newline, so every match lies within one of them.
+        // Look-arounds still see past the window.
+        let mut start = 0;
+        loop {
+            if self
+                .token
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+            {
+                return None;
+            }
+            let end = start + REGEX_WINDOW;
+            let end = if end < haystack.len() {
+                memchr::memchr(b'\n', &haystack[end..]).map_or(haystack.len(), |i| end + i + 1)
+            } else {
+                haystack.len()
+            };
+            if let Some(m) = self.regex.find(Input::new(haystack).span(start..end)) {
+                return Some((m.start(), m.end()));
+            }
+            if end == haystack.len() {
+                return None;
+            }
+            start = end;
+        }
     }
 
     fn needle_len(&self) -> Option<usize> {
-        None
+        self.min_len
+    }
+
+    fn stop_on(&mut self, token: &CancellationToken) {
+        self.token = Some(token.clone());
+    }
+}
+
+/// Whether any match of the regex could contain a newline.
+fn can_match_newline(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => false,
+        HirKind::Literal(Literal(bytes)) => bytes.contains(&b'\n'),
+        HirKind::Class(Class::Unicode(class)) => class
+            .ranges()
+            .iter()
+            .any(|range| (range.start()..=range.end()).contains(&'\n')),
+        HirKind::Class(Class::Bytes(class)) => class
+            .ranges()
+            .iter()
+            .any(|range| (range.start()..=range.end()).contains(&b'\n')),
+        HirKind::Repetition(Repetition { sub, .. }) | HirKind::Capture(Capture { sub, .. }) => {
+            can_match_newline(sub)
+        }
+        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => hirs.iter().any(can_match_newline),
     }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct QueryResult {
+    /// The composite id of the matching entry.
+    pub id: u64,
+    #[deprecated(note = "Use `id` to identify the entry or `location()` for diagnostics.")]
     pub location: EntryLocation,
     pub start: usize,
     pub end: usize,
 }
 
+impl QueryResult {
+    #[allow(deprecated)]
+    const fn new(id: u64, location: EntryLocation, start: usize, end: usize) -> Self {
+        Self {
+            id,
+            location,
+            start,
+            end,
+        }
+    }
+
+    #[must_use]
+    pub fn ring(&self) -> RingKind {
+        decompose_id(self.id).unwrap().0
+    }
+
+    /// Where the match was found in the database files.
+    #[must_use]
+    #[allow(deprecated)]
+    pub const fn location(&self) -> EntryLocation {
+        self.location
+    }
+}
+
+/// Where a match was found.
+///
+/// [`ImageText`](Self::ImageText) matches come from the text the server
+/// recognized in an image entry, so their range refers to
+/// [`LoadedEntry::image_text`](crate::LoadedEntry::image_text). Likewise,
+/// [`Label`](Self::Label) ranges refer to
+/// [`LoadedEntry::label`](crate::LoadedEntry::label).
 #[derive(Copy, Clone, Debug)]
 pub enum EntryLocation {
     Bucketed { bucket: u8, index: u32 },
+    Packed { entry_id: u64 },
     File { entry_id: u64 },
+    ImageText { entry_id: u64 },
+    Label { entry_id: u64 },
+}
+
+/// Maps bucket slots back to the entries that own them so bucketed matches
+/// can be resolved to entry ids, and lists the packed entries to search.
+#[derive(Debug, Default)]
+pub struct EntryIndex {
+    /// The write heads and generation the index was built at.
+    built_at: Option<(u32, u32, Option<u64>)>,
+    slots: HashMap<BucketAndIndex, u64, BuildHasherDefault<FxHasher>>,
+    /// Packed entries move around the log as it's compacted, so they're
+    /// searched one by one instead of by scanning the log.
+    packed: Vec<Entry>,
+}
+
+impl EntryIndex {
+    #[must_use]
+    pub fn new(database: &DatabaseReader) -> Self {
+        let mut index = Self::default();
+        index.refresh(database);
+        index
+    }
+
+    /// Rebuilds the index if entries were added or removed since it was last
+    /// built. Removals don't move the write heads, so without a generation
+    /// to go on removed entries keep their slots until the next add.
+    pub fn refresh(&mut self, database: &DatabaseReader) {
+        let built_at = Some((
+            database.favorites().ring().write_head(),
+            database.main().ring().write_head(),
+            database.generation(),
+        ));
+        if self.built_at == built_at {
+            return;
+        }
+        self.built_at = built_at;
+
+        self.slots.clear();
+        self.packed.clear();
+        for entry in database.favorites().chain(database.main()) {
+            match entry.kind() {
+                Kind::Bucket(packed) if packed.is_packed() => self.packed.push(entry),
+                Kind::Bucket(bucket) => {
+                    self.slots.insert(
+                        BucketAndIndex::new(size_to_bucket(bucket.size()), bucket.index()),
+                        entry.id(),
+                    );
+                }
+                Kind::File => {}
+            }
+        }
+    }
+
+    /// Returns the id of the entry stored at `location`, or `None` if the
+    /// location is a free bucket slot.
+    #[must_use]
+    pub fn resolve(&self, location: EntryLocation) -> Option<u64> {
+        match location {
+            EntryLocation::Bucketed { bucket, index } => {
+                self.slots.get(&BucketAndIndex::new(bucket, index)).copied()
+            }
+            EntryLocation::Packed { entry_id }
+            | EntryLocation::File { entry_id }
+            | EntryLocation::ImageText { entry_id }
+            | EntryLocation::Label { entry_id } => Some(entry_id),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -133,7 +494,7 @@ pub struct CancellationToken {
 }
 
 impl CancellationToken {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             stop: Arc::new(AtomicBool::new(false)),
         }
@@ -149,23 +510,75 @@ impl CancellationToken {
     }
 }
 
+/// Streams search results, reporting each entry only once by default.
+///
+/// An entry can match in several places, for example while its contents are
+/// being moved between a bucket slot and a direct allocation. Only the first
+/// match found for an entry is returned unless
+/// [`keep_duplicates`](Self::keep_duplicates) is used.
 pub struct QueryIter {
-    stream: mpsc::IntoIter<Result<QueryResult, CoreError>>,
+    stream: mpsc::Receiver<Result<QueryResult, CoreError>>,
     token: CancellationToken,
+    seen: Option<HashSet<u64, BuildHasherDefault<FxHasher>>>,
 }
 
 impl QueryIter {
+    fn new(
+        stream: mpsc::Receiver<Result<QueryResult, CoreError>>,
+        token: CancellationToken,
+    ) -> Self {
+        Self {
+            stream,
+            token,
+            seen: Some(HashSet::default()),
+        }
+    }
+
     #[must_use]
     pub const fn cancellation_token(&self) -> &CancellationToken {
         &self.token
     }
+
+    /// Returns every match, even if an entry was already reported.
+    #[must_use]
+    pub fn keep_duplicates(mut self) -> Self {
+        self.seen = None;
+        self
+    }
+
+    /// Waits for the next result until `deadline`, so callers can do
+    /// something else while matches are slow to come in.
+    pub fn next_until(
+        &mut self,
+        deadline: Instant,
+    ) -> Result<Result<QueryResult, CoreError>, RecvTimeoutError> {
+        loop {
+            let result = self
+                .stream
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))?;
+            if self.is_new(&result) {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn is_new(&mut self, result: &Result<QueryResult, CoreError>) -> bool {
+        self.seen
+            .as_mut()
+            .is_none_or(|seen| result.as_ref().map_or(true, |r| seen.insert(r.id)))
+    }
 }
 
 impl Iterator for QueryIter {
     type Item = Result<QueryResult, CoreError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.stream.next()
+        loop {
+            let result = self.stream.recv().ok()?;
+            if self.is_new(&result) {
+                return Some(result);
+            }
+        }
     }
 }
 
@@ -175,48 +588,88 @@ impl Drop for QueryIter {
     }
 }
 
+/// Searches the database, only looking at entries in `ring` and whose mime
+/// type passes `mime_filter` (see [`mime_filter_matches`]) if they're given.
 pub fn search(
     query: Query,
+    ring: Option<RingKind>,
+    mime_filter: Option<MimeType>,
     reader: Arc<EntryReader>,
+    index: Arc<EntryIndex>,
 ) -> (
     QueryIter,
     impl Iterator<Item = JoinHandle<()>> + Send + Sync + 'static,
 ) {
     let (results, threads) = match query {
-        Query::Plain(p) => search_impl(PlainQuery(Arc::new(Finder::new(p).into_owned())), reader),
-        Query::PlainIgnoreCase(CaselessQuery { mut query, trim }) => {
-            query.make_ascii_lowercase();
-            let query = if trim { query.trim_ascii() } else { &query };
-            search_impl(
-                PlainIgnoreCaseQuery {
-                    inner: PlainQuery(Arc::new(Finder::new(query).into_owned())),
-                    cache: Vec::new(),
-                },
-                reader,
-            )
-        }
-        Query::Regex(r) => search_impl(RegexQuery(r), reader),
-        Query::Mimes(r) => mime_search_impl(RegexQuery(r), reader),
+        Query::Plain(p) => search_impl(
+            PlainQuery(Arc::new(Finder::new(p).into_owned())),
+            ring,
+            mime_filter,
+            reader,
+            index,
+        ),
+        Query::PlainIgnoreCase(query) => search_impl(
+            PlainIgnoreCaseQuery::new(query),
+            ring,
+            mime_filter,
+            reader,
+            index,
+        ),
+        Query::Regex(r) => search_impl(RegexQuery::new(&r), ring, mime_filter, reader, index),
+        Query::Mimes(r) => mime_search_impl(RegexQuery::new(&r), ring, mime_filter, reader),
     };
     (results, threads.into_iter())
 }
 
+/// Whether an entry of the given mime type passes a search's mime filter.
+///
+/// Parameters like `;charset=utf-8` are ignored and filters without a subtype,
+/// like `image/`, match every subtype. Entries without a mime type are
+/// `text/plain`, as are all bucketed entries.
+#[must_use]
+pub fn mime_filter_matches(filter: &str, mime_type: &str) -> bool {
+    let mime_type = if mime_type.is_empty() {
+        "text/plain"
+    } else {
+        mime_type
+    };
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    if filter.ends_with('/') {
+        essence
+            .get(..filter.len())
+            .is_some_and(|kind| kind.eq_ignore_ascii_case(filter))
+    } else {
+        essence.eq_ignore_ascii_case(filter)
+    }
+}
+
 fn search_impl(
     mut query: impl QueryImpl + Clone + Send + 'static,
+    ring: Option<RingKind>,
+    mime_filter: Option<MimeType>,
     reader: Arc<EntryReader>,
-) -> (QueryIter, arrayvec::IntoIter<JoinHandle<()>, 13>) {
+    entries: Arc<EntryIndex>,
+) -> (QueryIter, arrayvec::IntoIter<JoinHandle<()>, 14>) {
     let (sender, receiver) = mpsc::sync_channel(0);
     let token = CancellationToken::new();
-    let mut threads = ArrayVec::<_, 13>::new_const();
+    let mut threads = ArrayVec::<_, 14>::new_const();
+    query.stop_on(&token);
 
     let mut extra_direct_threads = 1;
     let (direct_file_sender, direct_file_receiver) = crossbeam_channel::bounded(8);
-    for bucket in usize::from(size_to_bucket(
-        u16::try_from(query.needle_len().unwrap_or(0)).unwrap_or(u16::MAX),
-    ))..reader.buckets().len()
-    {
+    // Bucketed and packed entries are always plain text, so they can be skipped
+    // wholesale.
+    let plain_text = mime_filter.is_none_or(|filter| mime_filter_matches(&filter, ""));
+    let needle_len = u16::try_from(query.needle_len().unwrap_or(0)).unwrap_or(u16::MAX);
+    let first_bucket = if plain_text {
+        usize::from(size_to_bucket(needle_len))
+    } else {
+        reader.buckets().len()
+    };
+    for bucket in first_bucket..reader.buckets().len() {
         let mut query = query.clone();
         let reader = reader.clone();
+        let entries = entries.clone();
         let sender = sender.clone();
         let token = token.clone();
         let direct_file_receiver = if extra_direct_threads > 0 {
@@ -233,10 +686,12 @@ fn search_impl(
                 } else {
                     bucket_size / 2 + 1
                 };
-                for (index, entry) in reader.buckets()[bucket]
-                    .chunks_exact(bucket_size)
-                    .enumerate()
-                {
+                // Entries added since the bucket was mapped would otherwise be missed.
+                let data = reader.refresh_bucket(bucket).unwrap_or_else(|e| {
+                    let _ = sender.send(Err(e));
+                    reader.buckets()[bucket]
+                });
+                for (index, entry) in data.chunks_exact(bucket_size).enumerate() {
                     if token.is_cancelled() {
                         break;
                     }
@@ -246,15 +701,19 @@ fn search_impl(
                     let Some((start, end)) = query.find(entry) else {
                         continue;
                     };
+                    let location = EntryLocation::Bucketed {
+                        bucket: u8::try_from(bucket).unwrap(),
+                        index: u32::try_from(index).unwrap(),
+                    };
+                    // Freed slots keep their old contents around.
+                    let Some(id) = entries.resolve(location) else {
+                        continue;
+                    };
+                    if !in_ring(id, ring) {
+                        continue;
+                    }
                     if sender
-                        .send(Ok(QueryResult {
-                            location: EntryLocation::Bucketed {
-                                bucket: u8::try_from(bucket).unwrap(),
-                                index: u32::try_from(index).unwrap(),
-                            },
-                            start,
-                            end,
-                        }))
+                        .send(Ok(QueryResult::new(id, location, start, end)))
                         .is_err()
                     {
                         break;
@@ -266,20 +725,91 @@ fn search_impl(
             }
         }));
     }
+    if plain_text && needle_len <= packed::MAX_LEN && !entries.packed.is_empty() {
+        let mut query = query.clone();
+        let reader = reader.clone();
+        let sender = sender.clone();
+        let token = token.clone();
+        threads.push(thread::spawn(move || {
+            for entry in &entries.packed {
+                if token.is_cancelled() {
+                    break;
+                }
+                if !in_ring(entry.id(), ring) {
+                    continue;
+                }
+
+                // Entries removed or moved since the index was built are skipped.
+                let Ok(Some(bytes)) = entry.to_slice_raw(&reader) else {
+                    continue;
+                };
+                let Some((start, end)) = query.find(&bytes) else {
+                    continue;
+                };
+                let location = EntryLocation::Packed {
+                    entry_id: entry.id(),
+                };
+                if sender
+                    .send(Ok(QueryResult::new(entry.id(), location, start, end)))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }));
+    }
     threads.push(thread::spawn({
         let token = token.clone();
         let sender = sender.clone();
+        let mut query = query.clone();
+        let mut image_text = Vec::new();
         move || {
             stream_through_direct_allocations(
                 &reader,
+                ring,
                 &token,
                 &sender,
                 |file_name, fd, mime_type| {
+                    if mime_filter.is_some_and(|filter| !mime_filter_matches(&filter, mime_type)) {
+                        return Ok(());
+                    }
+
+                    // Only direct allocations can be labeled. A labeled entry is reported once,
+                    // even if its contents match as well.
+                    let label = xattr_label(&fd)?;
+                    if let Some((start, end)) = Some(label.as_bytes())
+                        .filter(|label| !label.is_empty())
+                        .and_then(|label| query.find(label))
+                    {
+                        let id = entry_id_from_direct_file_name(file_name.to_bytes())?;
+                        sender.send(Ok(QueryResult::new(
+                            id,
+                            EntryLocation::Label { entry_id: id },
+                            start,
+                            end,
+                        )))?;
+                        return Ok(());
+                    }
+                    if mime_type.starts_with("image/") {
+                        let text = xattr_image_text(&fd, &mut image_text)?;
+                        if let Some((start, end)) = query.find(text) {
+                            let id = entry_id_from_direct_file_name(file_name.to_bytes())?;
+                            sender.send(Ok(QueryResult::new(
+                                id,
+                                EntryLocation::ImageText { entry_id: id },
+                                start,
+                                end,
+                            )))?;
+                        }
+                        return Ok(());
+                    }
                     if !is_searchable_mime(mime_type) {
                         return Ok(());
                     }
 
-                    let data = Mmap::from(&fd).map_io_err(|| {
+                    // Compressed entries have to be searched decompressed.
+                    let (data, _) = decode_direct_file(File::from(fd))?;
+                    let data = Mmap::from(&data).map_io_err(|| {
                         format!("Failed to mmap direct allocation: {file_name:?}")
                     })?;
                     let file_name = <[u8; DIRECT_FILE_NAME_LEN]>::try_from(file_name.to_bytes())
@@ -308,13 +838,7 @@ fn search_impl(
         }
     }));
 
-    (
-        QueryIter {
-            stream: receiver.into_iter(),
-            token,
-        },
-        threads.into_iter(),
-    )
+    (QueryIter::new(receiver, token), threads.into_iter())
 }
 
 fn direct_alloc_search_stream<U>(
@@ -334,11 +858,12 @@ fn direct_alloc_search_stream<U>(
             };
 
             let id = entry_id_from_direct_file_name(&file_name)?;
-            Ok(Some(QueryResult {
-                location: EntryLocation::File { entry_id: id },
+            Ok(Some(QueryResult::new(
+                id,
+                EntryLocation::File { entry_id: id },
                 start,
                 end,
-            }))
+            )))
         };
 
         if match run() {
@@ -375,6 +900,7 @@ impl<T> From<crossbeam_channel::SendError<T>> for DirectIterError {
 
 fn stream_through_direct_allocations<T>(
     reader: &EntryReader,
+    ring: Option<RingKind>,
     token: &CancellationToken,
     sender: &SyncSender<Result<T, CoreError>>,
     mut f: impl FnMut(&CStr, OwnedFd, &str) -> Result<(), DirectIterError>,
@@ -406,6 +932,12 @@ fn stream_through_direct_allocations<T>(
             if file_name == c"." || file_name == c".." {
                 return Ok(());
             }
+            // File names are entry ids, so the other ring's files needn't be opened.
+            if ring.is_some()
+                && !in_ring(entry_id_from_direct_file_name(file_name.to_bytes())?, ring)
+            {
+                return Ok(());
+            }
 
             let fd = openat(&direct_dir, file_name, OFlags::RDONLY, Mode::empty())
                 .map_io_err(|| format!("Failed to open direct allocation: {file_name:?}"))?;
@@ -425,10 +957,15 @@ fn stream_through_direct_allocations<T>(
     }
 }
 
+fn in_ring(id: u64, ring: Option<RingKind>) -> bool {
+    ring.is_none_or(|ring| decompose_id(id).is_ok_and(|(kind, _)| kind == ring))
+}
+
 fn entry_id_from_direct_file_name(file_name: &[u8]) -> Result<u64, CoreError> {
     str::from_utf8(file_name)
         .ok()
         .and_then(|id| u64::from_str(id).ok())
+        .filter(|&id| decompose_id(id).is_ok())
         .ok_or_else(|| CoreError::Io {
             error: io::Error::new(ErrorKind::InvalidData, "Not a Ringboard database."),
             context: format!(
@@ -445,31 +982,38 @@ fn is_searchable_mime(mime: &str) -> bool {
 
 fn mime_search_impl(
     mut query: impl QueryImpl + Clone + Send + 'static,
+    ring: Option<RingKind>,
+    mime_filter: Option<MimeType>,
     reader: Arc<EntryReader>,
-) -> (QueryIter, arrayvec::IntoIter<JoinHandle<()>, 13>) {
+) -> (QueryIter, arrayvec::IntoIter<JoinHandle<()>, 14>) {
     let (sender, receiver) = mpsc::sync_channel(0);
     let token = CancellationToken::new();
-    let mut threads = ArrayVec::<_, 13>::new_const();
+    let mut threads = ArrayVec::<_, 14>::new_const();
 
     threads.push(thread::spawn({
         let token = token.clone();
         move || {
             stream_through_direct_allocations(
                 &reader,
+                ring,
                 &token,
                 &sender,
                 |file_name, _fd, mime_type| {
-                    if mime_type.is_empty() {
+                    if mime_type.is_empty()
+                        || mime_filter
+                            .is_some_and(|filter| !mime_filter_matches(&filter, mime_type))
+                    {
                         return Ok(());
                     }
 
                     if query.find(mime_type.as_bytes()).is_some() {
                         let id = entry_id_from_direct_file_name(file_name.to_bytes())?;
-                        sender.send(Ok(QueryResult {
-                            location: EntryLocation::File { entry_id: id },
-                            start: 0,
-                            end: 0,
-                        }))?;
+                        sender.send(Ok(QueryResult::new(
+                            id,
+                            EntryLocation::File { entry_id: id },
+                            0,
+                            0,
+                        )))?;
                     }
                     Ok(())
                 },
@@ -477,11 +1021,184 @@ fn mime_search_impl(
         }
     }));
 
-    (
-        QueryIter {
-            stream: receiver.into_iter(),
-            token,
-        },
-        threads.into_iter(),
-    )
+    (QueryIter::new(receiver, token), threads.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use regex::bytes::Regex;
+    use regex_syntax::ParserBuilder;
+    use ringboard_core::protocol::{composite_id, RingKind};
+
+    use super::{
+        can_match_newline, mime_filter_matches, CancellationToken, CaselessQuery, EntryLocation,
+        PlainIgnoreCaseQuery, Query, QueryImpl, QueryIter, QueryResult, RegexQuery, REGEX_WINDOW,
+    };
+
+    fn repeated_matches() -> QueryIter {
+        let id = composite_id(RingKind::Main, 3);
+        let other = composite_id(RingKind::Main, 4);
+        let (sender, receiver) = mpsc::sync_channel(4);
+        for result in [
+            QueryResult::new(
+                id,
+                EntryLocation::Bucketed {
+                    bucket: 0,
+                    index: 1,
+                },
+                2,
+                5,
+            ),
+            QueryResult::new(other, EntryLocation::File { entry_id: other }, 0, 1),
+            QueryResult::new(id, EntryLocation::File { entry_id: id }, 7, 9),
+        ] {
+            sender.send(Ok(result)).unwrap();
+        }
+        QueryIter::new(receiver, CancellationToken::new())
+    }
+
+    #[test]
+    fn entries_are_reported_once_with_their_first_match() {
+        let results = repeated_matches()
+            .map(|r| r.map(|r| (r.id, r.start, r.end)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            results,
+            [
+                (composite_id(RingKind::Main, 3), 2, 5),
+                (composite_id(RingKind::Main, 4), 0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_can_be_kept() {
+        assert_eq!(repeated_matches().keep_duplicates().count(), 3);
+    }
+
+    #[test]
+    fn mime_filters_match_whole_types_or_families() {
+        assert!(mime_filter_matches("image/", "image/png"));
+        assert!(mime_filter_matches("image/png", "IMAGE/PNG"));
+        assert!(!mime_filter_matches("image/png", "image/jpeg"));
+        assert!(!mime_filter_matches("image/", "images/png"));
+        assert!(!mime_filter_matches("image/", "image"));
+        assert!(mime_filter_matches(
+            "text/plain",
+            "text/plain;charset=utf-8"
+        ));
+        // Entries saved before mime types were recorded are text.
+        assert!(mime_filter_matches("text/plain", ""));
+        assert!(mime_filter_matches("text/", ""));
+        assert!(!mime_filter_matches("image/", ""));
+    }
+
+    #[test]
+    fn caseless_matches_point_into_the_original_text() {
+        fn find<'a>(query: &str, haystack: &'a str) -> Option<&'a str> {
+            PlainIgnoreCaseQuery::new(CaselessQuery::new(query))
+                .find(haystack.as_bytes())
+                .map(|(start, end)| &haystack[start..end])
+        }
+
+        assert_eq!(find("github", "see GitHub.com"), Some("GitHub"));
+        assert_eq!(find("GITHUB", "see github.com"), Some("github"));
+        assert_eq!(find("straße", "Die STRAßE"), Some("STRAßE"));
+        assert_eq!(find("ÉTÉ", "un bel été"), Some("été"));
+        // İ lowercases to two characters, so matching part of it covers all of it.
+        assert_eq!(find("i", "İstanbul"), Some("İ"));
+        assert_eq!(find("istanbul", "İstanbul"), None);
+        // The Kelvin sign shrinks to an ASCII k.
+        assert_eq!(find("kelvin", "300 \u{212A}elvin"), Some("\u{212A}elvin"));
+        assert_eq!(find("ü", "ü"), Some("ü"));
+        assert_eq!(
+            PlainIgnoreCaseQuery::new(CaselessQuery::new("bc")).find(b"\xffA\xfeBC"),
+            Some((3, 5))
+        );
+    }
+
+    #[test]
+    fn find_all_skips_empty_matches() {
+        let caseless = Query::PlainIgnoreCase(CaselessQuery::new("ab"));
+        assert_eq!(caseless.find_all(b"abAbxAB"), [(0, 2), (2, 4), (5, 7)]);
+        assert_eq!(Query::Plain(b"aa").find_all(b"aaaaa"), [(0, 2), (2, 4)]);
+        assert_eq!(Query::Plain(b"").find_all(b"abc"), []);
+
+        let regex = Query::Regex(Regex::new("^a|b*").unwrap());
+        assert_eq!(regex.find_all(b"aab"), [(0, 1), (2, 3)]);
+        assert_eq!(
+            Query::Mimes(Regex::new("a.c").unwrap()).find_all(b"abc"),
+            []
+        );
+    }
+    #[test]
+    fn regexes_agree_with_the_regex_crate() {
+        fn find(pattern: &str, haystack: &[u8]) -> Option<(usize, usize)> {
+            let regex = Regex::new(pattern).unwrap();
+            let expected = regex.find(haystack).map(|m| (m.start(), m.end()));
+            let found = RegexQuery::new(&regex).find(haystack);
+            assert_eq!(found, expected, "{pattern}");
+            found
+        }
+
+        // Required literals and minimum lengths rule entries out.
+        assert_eq!(find(r"foo\d+bar", b"xxfoo12bar"), Some((2, 10)));
+        assert_eq!(find(r"foo\d+bar", b"foo bar"), None);
+        assert_eq!(find(r"\w{5}", b"abcd"), None);
+        assert_eq!(find(r"(?i)hello|HELLO", b"say HeLLo"), Some((4, 9)));
+
+        // Long entries are searched in windows that still see around them.
+        let mut long = b"line\n".repeat(40_000);
+        long.extend_from_slice(b"needle");
+        let needle = long.len() - 6;
+        assert_eq!(find(r"(?m)^needle$", &long), Some((needle, long.len())));
+        assert_eq!(find(r"\bneedle\z", &long), Some((needle, long.len())));
+        assert_eq!(find(r"e\nneedle", &long), Some((needle - 2, long.len())));
+        assert_eq!(find(r"(?m)^$", &long), None);
+        assert_eq!(find(r"(?m)^$", b"a\n\nb"), Some((2, 2)));
+        let mut one_line = vec![b'a'; 3 * REGEX_WINDOW];
+        one_line.push(b'b');
+        assert_eq!(
+            find(r"a{100}b", &one_line),
+            Some((one_line.len() - 101, one_line.len()))
+        );
+    }
+
+    #[test]
+    fn regexes_stop_partway_through_long_entries() {
+        let token = CancellationToken::new();
+        let mut query = RegexQuery::new(&Regex::new("needles?").unwrap());
+        query.stop_on(&token);
+        let mut long = b"line\n".repeat(40_000);
+        long.extend_from_slice(b"needle");
+
+        assert!(query.find(&long).is_some());
+        token.cancel();
+        assert_eq!(query.find(&long), None);
+    }
+
+    #[test]
+    fn newlines_are_found_in_patterns() {
+        for (pattern, newline) in [
+            (r"a.b", false),
+            (r"(?m)^\w+$", false),
+            (r"[a-z]{3}", false),
+            (r"(?s)a.b", true),
+            (r"a\sb", true),
+            (r"[^a]", true),
+            (r"a|\n", true),
+            (r"(?x) a \n", true),
+        ] {
+            let hir = ParserBuilder::new()
+                .utf8(false)
+                .build()
+                .parse(pattern)
+                .unwrap();
+            assert_eq!(can_match_newline(&hir), newline, "{pattern}");
+        }
+    }
 }
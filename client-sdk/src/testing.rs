@@ -0,0 +1,866 @@
+//! Fakes for testing clients without a Ringboard database or server.
+
+use std::{
+    collections::HashMap,
+    ffi::{CStr, OsStr},
+    fs,
+    fs::File,
+    io::Read,
+    iter, mem,
+    os::unix::{ffi::OsStrExt, fs::FileExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use arrayvec::ArrayString;
+use ringboard_core::{
+    bucket_to_length, direct_file_name, open_buckets,
+    protocol::{
+        composite_id, decompose_id, AddResponse, BackupStats, BucketUsage, DirectUsage,
+        IdNotFoundError, Label, MimeType, MoveToFrontResponse, RemoveResponse, RestoreResponse,
+        RingKind, ServerInfoResponse, SetClipboardResponse, SetLabelResponse, StartupState,
+        StatsResponse, SwapResponse, Tag, TagResponse,
+    },
+    ring,
+    ring::{Header, InitializedEntry, RawEntry, MAGIC, VERSION},
+    size_to_bucket, tags,
+    tags::{TagSet, TooManyTags},
+    Error as CoreError, IoErr, NUM_BUCKETS,
+};
+use rustix::{
+    fs::{getxattr, openat, setxattr, Mode, OFlags, XattrFlags, CWD},
+    io::Errno,
+};
+
+use crate::{
+    duplicate_detection::DuplicateGroup,
+    ring_reader::bytes_to_file,
+    search::{mime_filter_matches, CancellationToken, Query},
+    ui_actor::{
+        ui_entry, CommandError, Controller, Message, SearchSource, ServerConnection, UiEntry,
+    },
+    ClientError, Entry, EntrySource, FileOrSlice, LoadedEntry, MmapOrSlice,
+};
+
+/// An in-memory database that also plays the part of the server.
+///
+/// Clones share their entries, so a [`Controller`] can use one clone as its
+/// entry source and another as its server connection: favorites and deletions
+/// then show up in the pages it loads next, just like with a real server.
+/// Entries have no mime type and rings never wrap around. Labels are checked
+/// like the server would but aren't stored since entries have no backing file
+/// to hold them. Entries only have a timestamp once one is set for them. Tags
+/// follow entries around like the server's do.
+#[derive(Clone, Default, Debug)]
+pub struct MockDatabase {
+    shared: Arc<Mutex<Shared>>,
+    buf: Vec<u8>,
+}
+
+#[derive(Default, Debug)]
+struct Shared {
+    rings: [Vec<Option<Box<[u8]>>>; 2],
+    generation: u64,
+    pasted: Vec<Vec<u8>>,
+    clipboard: Option<u64>,
+    timestamps: HashMap<u64, SystemTime>,
+    tags: HashMap<u64, TagSet>,
+    /// Removed entries by their old id, without the server's size limit.
+    trash: Vec<(u64, Box<[u8]>)>,
+}
+
+impl MockDatabase {
+    /// Adds an entry to the front of a ring and returns its id.
+    pub fn add(&self, to: RingKind, data: impl Into<Box<[u8]>>) -> u64 {
+        self.shared().add(to, data.into())
+    }
+
+    /// Records when an entry was copied. The timestamp is dropped when the
+    /// entry is moved or removed.
+    pub fn set_timestamp(&self, id: u64, time: SystemTime) {
+        self.shared().timestamps.insert(id, time);
+    }
+
+    /// The contents of the entries that were pasted, oldest first.
+    #[must_use]
+    pub fn pasted(&self) -> Vec<Vec<u8>> {
+        self.shared().pasted.clone()
+    }
+
+    /// A controller serving this database.
+    #[must_use]
+    pub fn controller(&self) -> Controller<Self, Self> {
+        Controller::new(self.clone(), self.clone())
+    }
+
+    fn shared(&self) -> MutexGuard<Shared> {
+        self.shared.lock().unwrap()
+    }
+
+    /// Copies an entry's data into the buffer that loaded entries borrow.
+    fn load(&mut self, id: u64) -> Result<&[u8], CoreError> {
+        let mut buf = mem::take(&mut self.buf);
+        buf.clear();
+        let loaded = self
+            .shared()
+            .get(id)
+            .map(|(_, data)| buf.extend_from_slice(data));
+        self.buf = buf;
+        loaded?;
+        Ok(&self.buf)
+    }
+}
+
+impl Shared {
+    fn add(&mut self, to: RingKind, data: Box<[u8]>) -> u64 {
+        let ring = &mut self.rings[to as usize];
+        ring.push(Some(data));
+        self.generation += 1;
+        composite_id(to, u32::try_from(ring.len() - 1).unwrap())
+    }
+
+    fn take(&mut self, id: u64) -> Result<Box<[u8]>, IdNotFoundError> {
+        let (ring, index) = decompose_id(id)?;
+        let data = self.rings[ring as usize]
+            .get_mut(usize::try_from(index).unwrap())
+            .and_then(Option::take)
+            .ok_or(IdNotFoundError::Entry(index))?;
+        if self.clipboard == Some(id) {
+            self.clipboard = None;
+        }
+        self.timestamps.remove(&id);
+        self.tags.remove(&id);
+        self.generation += 1;
+        Ok(data)
+    }
+
+    /// Like the server, swapping with an empty slot moves the entry there.
+    fn swap(&mut self, id1: u64, id2: u64) -> SwapResponse {
+        let (empty1, empty2) = match (
+            self.slot(id1).map(|data| data.is_none()),
+            self.slot(id2).map(|data| data.is_none()),
+        ) {
+            (Ok(empty1), Ok(empty2)) => (empty1, empty2),
+            (result1, result2) => {
+                return SwapResponse {
+                    error1: result1.err(),
+                    error2: result2.err(),
+                };
+            }
+        };
+        if empty1 && empty2 {
+            let not_found = |id| IdNotFoundError::Entry(decompose_id(id).unwrap().1);
+            return SwapResponse {
+                error1: Some(not_found(id1)),
+                error2: Some(not_found(id2)),
+            };
+        }
+
+        let data1 = self.slot(id1).unwrap().take();
+        let data2 = mem::replace(self.slot(id2).unwrap(), data1);
+        *self.slot(id1).unwrap() = data2;
+        if self.clipboard == Some(id1) {
+            self.clipboard = Some(id2);
+        } else if self.clipboard == Some(id2) {
+            self.clipboard = Some(id1);
+        }
+        let (time1, time2) = (self.timestamps.remove(&id1), self.timestamps.remove(&id2));
+        self.timestamps.extend(time2.map(|time| (id1, time)));
+        self.timestamps.extend(time1.map(|time| (id2, time)));
+        let (tags1, tags2) = (self.tags.remove(&id1), self.tags.remove(&id2));
+        self.tags.extend(tags2.map(|tags| (id1, tags)));
+        self.tags.extend(tags1.map(|tags| (id2, tags)));
+        self.generation += 1;
+        SwapResponse {
+            error1: None,
+            error2: None,
+        }
+    }
+
+    /// Checks tags like the server would.
+    fn update_tags(
+        &mut self,
+        id: u64,
+        tag: &str,
+        update: impl FnOnce(&mut TagSet) -> Result<bool, TooManyTags>,
+    ) -> TagResponse {
+        if !tags::is_valid(tag) {
+            return TagResponse::InvalidTag;
+        }
+        if let Err(e) = self.get(id) {
+            return TagResponse::Error(e);
+        }
+        let mut tags = self.tags.get(&id).copied().unwrap_or_default();
+        let Ok(changed) = update(&mut tags) else {
+            return TagResponse::TooManyTags;
+        };
+        if changed {
+            self.tags.insert(id, tags);
+            self.generation += 1;
+        }
+        TagResponse::Success { changed }
+    }
+
+    fn slot(&mut self, id: u64) -> Result<&mut Option<Box<[u8]>>, IdNotFoundError> {
+        let (ring, index) = decompose_id(id)?;
+        self.rings[ring as usize]
+            .get_mut(usize::try_from(index).unwrap())
+            .ok_or(IdNotFoundError::Entry(index))
+    }
+
+    fn get(&self, id: u64) -> Result<(Entry, &[u8]), IdNotFoundError> {
+        let (ring, index) = decompose_id(id)?;
+        let data = self.rings[ring as usize]
+            .get(usize::try_from(index).unwrap())
+            .and_then(Option::as_deref)
+            .ok_or(IdNotFoundError::Entry(index))?;
+        Ok((entry(ring, index, data), data))
+    }
+
+    fn entries(&self, ring: RingKind) -> impl DoubleEndedIterator<Item = (Entry, &[u8])> {
+        self.rings[ring as usize]
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, data)| {
+                let data = data.as_deref()?;
+                Some((entry(ring, u32::try_from(index).unwrap(), data), data))
+            })
+    }
+}
+
+fn entry(ring: RingKind, index: u32, data: &[u8]) -> Entry {
+    Entry::new(
+        ring,
+        index,
+        match u16::try_from(data.len()) {
+            Ok(len @ 1..4096) => InitializedEntry::bucket(len, index),
+            _ => InitializedEntry::file(),
+        },
+    )
+}
+
+impl EntrySource for MockDatabase {
+    fn refresh(&mut self) -> Result<(), CoreError> {
+        Ok(())
+    }
+
+    fn ring_heads(&mut self) -> Result<[(u32, u32); 2], CoreError> {
+        Ok(self.shared().rings.each_ref().map(|ring| {
+            let len = u32::try_from(ring.len()).unwrap();
+            (len, len)
+        }))
+    }
+
+    fn generation(&self) -> Option<u64> {
+        Some(self.shared().generation)
+    }
+
+    fn favorites(&self) -> impl DoubleEndedIterator<Item = Entry> + '_ {
+        self.shared()
+            .entries(RingKind::Favorites)
+            .map(|(entry, _)| entry)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn main(&self) -> impl DoubleEndedIterator<Item = Entry> + '_ {
+        self.shared()
+            .entries(RingKind::Main)
+            .map(|(entry, _)| entry)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn get(&mut self, id: u64) -> Result<Entry, CoreError> {
+        Ok(self.shared().get(id)?.0)
+    }
+
+    fn to_slice(&mut self, entry: Entry) -> Result<LoadedEntry<MmapOrSlice>, CoreError> {
+        let data = self.load(entry.id())?;
+        Ok(LoadedEntry::new(MmapOrSlice::from(data), None))
+    }
+
+    fn to_file(&mut self, entry: Entry) -> Result<LoadedEntry<File>, CoreError> {
+        let file = bytes_to_file(self.shared().get(entry.id())?.1)?;
+        Ok(LoadedEntry::new(file, None))
+    }
+
+    fn to_reader(&mut self, entry: Entry) -> Result<LoadedEntry<FileOrSlice>, CoreError> {
+        let data = self.load(entry.id())?;
+        Ok(LoadedEntry::new(data.into(), None))
+    }
+
+    fn mime_type(&mut self, _: Entry) -> Result<MimeType, CoreError> {
+        Ok(MimeType::new())
+    }
+
+    fn tags(&mut self, entry: Entry) -> Result<TagSet, CoreError> {
+        let shared = self.shared();
+        shared.get(entry.id())?;
+        Ok(shared.tags.get(&entry.id()).copied().unwrap_or_default())
+    }
+
+    fn timestamp(&mut self, entry: Entry) -> Option<SystemTime> {
+        self.shared().timestamps.get(&entry.id()).copied()
+    }
+}
+
+impl SearchSource for MockDatabase {
+    /// Finds matching entries in a single batch, newest first across both
+    /// rings with favorites winning ties. Mock entries have no recorded origin
+    /// or mime type, so they're all plain text.
+    fn search<E>(
+        &mut self,
+        query: Query,
+        ring: Option<RingKind>,
+        mime_filter: Option<MimeType>,
+        from: Option<&str>,
+        favorites_first: bool,
+        generation: u64,
+        mut send: impl FnMut(Message) -> Result<(), E>,
+    ) -> Result<Vec<UiEntry>, CommandError> {
+        let _ = send(Message::PendingSearch {
+            generation,
+            token: CancellationToken::new(),
+        });
+
+        let shared = self.shared();
+        let mut matches = [RingKind::Favorites, RingKind::Main]
+            .into_iter()
+            .filter(|&kind| ring.is_none_or(|ring| kind == ring) && from.is_none())
+            .filter(|_| mime_filter.is_none_or(|filter| mime_filter_matches(&filter, "")))
+            .flat_map(|ring| shared.entries(ring))
+            .filter(|(_, data)| query.is_match(data))
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|(entry, _)| {
+            let age = shared.rings[entry.ring() as usize].len() - entry.index() as usize;
+            let favorite = entry.ring() == RingKind::Favorites;
+            (!(favorites_first && favorite), age, !favorite)
+        });
+        Ok(matches
+            .into_iter()
+            .map(|(entry, data)| {
+                let first_match = query.find_all(data).first().map(|&(start, end)| start..end);
+                let timestamp = shared.timestamps.get(&entry.id()).copied();
+                ui_entry(
+                    entry,
+                    &LoadedEntry::new(data.into(), None),
+                    first_match,
+                    timestamp,
+                )
+            })
+            .collect::<Result<_, _>>()?)
+    }
+    /// Groups entries with the same contents, favorites first and then newest
+    /// first like the database would.
+    fn duplicates(&mut self) -> Result<Vec<DuplicateGroup>, CommandError> {
+        let shared = self.shared();
+        let mut groups = Vec::<(&[u8], DuplicateGroup)>::new();
+        for (entry, data) in [RingKind::Favorites, RingKind::Main]
+            .into_iter()
+            .flat_map(|ring| shared.entries(ring).rev())
+        {
+            match groups.iter_mut().find(|(contents, _)| *contents == data) {
+                Some((_, group)) => group.entries.push(entry),
+                None => groups.push((
+                    data,
+                    DuplicateGroup {
+                        entries: vec![entry],
+                    },
+                )),
+            }
+        }
+        Ok(groups
+            .into_iter()
+            .map(|(_, group)| group)
+            .filter(|group| group.entries.len() > 1)
+            .collect())
+    }
+}
+
+impl ServerConnection for MockDatabase {
+    fn move_to_front(
+        &mut self,
+        id: u64,
+        to: Option<RingKind>,
+    ) -> Result<MoveToFrontResponse, ClientError> {
+        let mut shared = self.shared();
+        let on_clipboard = shared.clipboard == Some(id);
+        let tags = shared.tags.get(&id).copied();
+        let response = match shared.take(id) {
+            Ok(data) => {
+                let (from, _) = decompose_id(id)?;
+                let id = shared.add(to.unwrap_or(from), data);
+                if on_clipboard {
+                    shared.clipboard = Some(id);
+                }
+                shared.tags.extend(tags.map(|tags| (id, tags)));
+                MoveToFrontResponse::Success { id }
+            }
+            Err(e) => MoveToFrontResponse::Error(e),
+        };
+        drop(shared);
+        Ok(response)
+    }
+
+    fn add(&mut self, to: RingKind, _: MimeType, data: File) -> Result<AddResponse, ClientError> {
+        let mut buf = Vec::new();
+        (&data)
+            .read_to_end(&mut buf)
+            .map_io_err(|| "Failed to read added entry.")?;
+        if buf.is_empty() {
+            return Ok(AddResponse::Empty);
+        }
+        Ok(AddResponse::Success {
+            id: self.shared().add(to, buf.into()),
+            normalized: false,
+            coalesced: false,
+            deduplicated: false,
+            truncated: false,
+        })
+    }
+
+    fn remove(&mut self, id: u64) -> Result<RemoveResponse, ClientError> {
+        let mut shared = self.shared();
+        Ok(RemoveResponse {
+            error: match shared.take(id) {
+                Ok(data) => {
+                    shared.trash.push((id, data));
+                    None
+                }
+                Err(e) => Some(e),
+            },
+        })
+    }
+
+    fn restore(&mut self, tombstone_id: u64) -> Result<RestoreResponse, ClientError> {
+        let mut shared = self.shared();
+        let Some(index) = shared.trash.iter().rposition(|&(id, _)| id == tombstone_id) else {
+            return Ok(RestoreResponse::NotInTrash);
+        };
+        let (_, data) = shared.trash.remove(index);
+        let (ring, _) = decompose_id(tombstone_id)?;
+        Ok(RestoreResponse::Added(AddResponse::Success {
+            id: shared.add(ring, data),
+            normalized: false,
+            coalesced: false,
+            deduplicated: false,
+            truncated: false,
+        }))
+    }
+
+    fn swap(&mut self, id1: u64, id2: u64) -> Result<SwapResponse, ClientError> {
+        Ok(self.shared().swap(id1, id2))
+    }
+
+    fn set_clipboard(&mut self, id: Option<u64>) -> Result<SetClipboardResponse, ClientError> {
+        let mut shared = self.shared();
+        let error = id.and_then(|id| shared.get(id).err());
+        if error.is_none() {
+            shared.clipboard = id;
+            shared.generation += 1;
+        }
+        drop(shared);
+        Ok(SetClipboardResponse { error })
+    }
+
+    fn current_clipboard(&mut self) -> Result<Option<u64>, ClientError> {
+        Ok(self.shared().clipboard)
+    }
+
+    fn set_label(&mut self, id: u64, _: Label) -> Result<SetLabelResponse, ClientError> {
+        let mut shared = self.shared();
+        let error = shared.get(id).err();
+        if error.is_none() {
+            shared.generation += 1;
+        }
+        drop(shared);
+        Ok(SetLabelResponse { error })
+    }
+
+    fn add_tag(&mut self, id: u64, tag: Tag) -> Result<TagResponse, ClientError> {
+        Ok(self
+            .shared()
+            .update_tags(id, &tag, |tags| tags.insert(&tag)))
+    }
+
+    fn remove_tag(&mut self, id: u64, tag: Tag) -> Result<TagResponse, ClientError> {
+        Ok(self
+            .shared()
+            .update_tags(id, &tag, |tags| Ok(tags.remove(&tag))))
+    }
+
+    fn server_info(&mut self) -> Result<ServerInfoResponse, ClientError> {
+        Ok(ServerInfoResponse {
+            version: ArrayString::from(crate::VERSION).unwrap(),
+            git_hash: ArrayString::from("mock").unwrap(),
+        })
+    }
+
+    /// Only the rings and clipboard are filled in: entries aren't stored in
+    /// buckets or files.
+    fn stats(&mut self) -> Result<StatsResponse, ClientError> {
+        let shared = self.shared();
+        let [favorites, main] = &shared.rings;
+        Ok(StatsResponse {
+            last_startup: StartupState::Clean,
+            buckets: [BucketUsage::default(); NUM_BUCKETS],
+            rejected_requests: 0,
+            memory_pressure_events: 0,
+            dropped_completions: 0,
+            rejected_connections: 0,
+            coalesced_adds: 0,
+            deduplicated_adds: 0,
+            aged_out_entries: 0,
+            favorites: u32::try_from(favorites.iter().flatten().count()).unwrap(),
+            max_favorites: RingKind::Favorites.default_max_entries(),
+            main_len: u32::try_from(main.len()).unwrap(),
+            main_capacity: RingKind::Main.default_max_entries(),
+            direct: DirectUsage::default(),
+            packed: None,
+            started_at: 0,
+            connected_clients: 1,
+            total_connections: 1,
+            current_clipboard: shared.clipboard,
+            backups: BackupStats::default(),
+        })
+    }
+
+    fn paste(&mut self, entry: LoadedEntry<File>) -> Result<(), ClientError> {
+        let mut data = Vec::new();
+        (&*entry)
+            .read_to_end(&mut data)
+            .map_io_err(|| "Failed to read pasted entry.")?;
+        self.shared().pasted.push(data);
+        Ok(())
+    }
+}
+
+/// A small database on disk holding every kind of entry, for checking that
+/// readers cope with corrupted databases.
+///
+/// Each ring has a hole, bucketed entries of different sizes, and direct
+/// entries carrying mime type, label, and recognized text attributes. The
+/// main ring has wrapped around. The database is deleted on drop.
+#[derive(Debug)]
+pub struct FixtureDatabase {
+    dir: PathBuf,
+}
+
+impl FixtureDatabase {
+    /// Creates the database in `dir`, replacing whatever was there.
+    ///
+    /// # Panics
+    ///
+    /// If the database can't be written.
+    #[must_use]
+    pub fn create(dir: PathBuf) -> Self {
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("buckets")).unwrap();
+        fs::create_dir_all(dir.join("direct")).unwrap();
+        let me = Self { dir };
+
+        let mut buckets = Vec::from(
+            open_buckets(|name| {
+                openat(
+                    CWD,
+                    me.dir.join("buckets").join(name),
+                    OFlags::RDWR | OFlags::CREATE,
+                    Mode::RUSR | Mode::WUSR,
+                )
+                .map_io_err(|| format!("Failed to create bucket: {name}"))
+            })
+            .unwrap()
+            .0
+            .map(|fd| (File::from(fd), 0)),
+        );
+        let mut add = |ring: RingKind, index: u32, data: FixtureEntry| -> ring::Entry {
+            match data {
+                FixtureEntry::Hole => ring::Entry::Uninitialized,
+                FixtureEntry::Bucketed(data) => {
+                    let size = u16::try_from(data.len()).unwrap();
+                    let (bucket, slots) = &mut buckets[usize::from(size_to_bucket(size))];
+                    let slot = *slots;
+                    *slots += 1;
+                    let len = bucket_to_length(usize::from(size_to_bucket(size)));
+                    // Slots are always whole, like the server leaves them.
+                    let mut padded = data.to_vec();
+                    padded.resize(usize::from(len), 0);
+                    bucket
+                        .write_all_at(&padded, u64::from(len) * u64::from(slot))
+                        .unwrap();
+                    ring::Entry::Bucketed(InitializedEntry::bucket(size, slot))
+                }
+                FixtureEntry::Direct {
+                    data,
+                    mime_type,
+                    attributes,
+                } => {
+                    let mut buf = Default::default();
+                    let name = direct_file_name(&mut buf, ring, index);
+                    let path = me.dir.join("direct").join(name.to_str().unwrap());
+                    fs::write(&path, data).unwrap();
+                    for (name, value) in
+                        iter::once((c"user.mime_type", mime_type)).chain(attributes.iter().copied())
+                    {
+                        setxattr(&path, name, value.as_bytes(), XattrFlags::CREATE).unwrap();
+                    }
+                    ring::Entry::File
+                }
+            }
+        };
+
+        let favorites = [
+            FixtureEntry::Bucketed(b"favorite"),
+            FixtureEntry::Hole,
+            FixtureEntry::Direct {
+                data: &[b'x'; 5000],
+                mime_type: "text/plain",
+                attributes: &[(c"user.label", "lots of x")],
+            },
+        ];
+        let main = [
+            FixtureEntry::Bucketed(b"abc"),
+            FixtureEntry::Bucketed(b"hello world"),
+            FixtureEntry::Direct {
+                data: b"\x89PNG",
+                mime_type: "image/png",
+                attributes: &[(c"user.image_text", "a cat")],
+            },
+            FixtureEntry::Hole,
+            FixtureEntry::Bucketed(&[b'y'; 3000]),
+            FixtureEntry::Direct {
+                data: b"<html>hello</html>",
+                mime_type: "text/html",
+                attributes: &[],
+            },
+            FixtureEntry::Bucketed(b"hello there"),
+        ];
+        for (kind, entries, write_head) in [
+            (RingKind::Favorites, &favorites[..], 3),
+            (RingKind::Main, &main[..], 2),
+        ] {
+            let mut bytes = MAGIC.to_vec();
+            bytes.push(VERSION);
+            bytes.extend_from_slice(&u32::to_le_bytes(write_head));
+            for (index, &entry) in (0..).zip(entries) {
+                bytes.extend_from_slice(&RawEntry::from(add(kind, index, entry)).to_le_bytes());
+            }
+            fs::write(me.dir.join(kind.file_name()), bytes).unwrap();
+        }
+
+        me
+    }
+
+    /// Replaces the main ring with `entries` short text entries, e.g. to time
+    /// readers against a database that has seen some use.
+    ///
+    /// The same number of entries always fills the ring the same way.
+    ///
+    /// # Panics
+    ///
+    /// If the database can't be written.
+    pub fn fill_main(&self, entries: u32) {
+        let (buckets, lengths) = open_buckets(|name| {
+            openat(
+                CWD,
+                self.dir.join("buckets").join(name),
+                OFlags::WRONLY,
+                Mode::empty(),
+            )
+            .map_io_err(|| format!("Failed to open bucket: {name}"))
+        })
+        .unwrap();
+        let mut appended: [Vec<u8>; NUM_BUCKETS] = Default::default();
+        let mut random = SplitMix64(u64::from(entries));
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&u32::to_le_bytes(entries));
+        for _ in 0..entries {
+            let size = 1 + random.below(200);
+            let mut data = (0..size)
+                .map(|_| b'a' + u8::try_from(random.below(26)).unwrap())
+                .collect::<Vec<_>>();
+            let size = u16::try_from(size).unwrap();
+            let bucket = usize::from(size_to_bucket(size));
+            let len = usize::from(bucket_to_length(bucket));
+            let slot = lengths[bucket] / u64::try_from(len).unwrap()
+                + u64::try_from(appended[bucket].len() / len).unwrap();
+            data.resize(len, 0);
+            appended[bucket].extend_from_slice(&data);
+            let entry = InitializedEntry::bucket(size, u32::try_from(slot).unwrap());
+            bytes.extend_from_slice(&RawEntry::from(ring::Entry::Bucketed(entry)).to_le_bytes());
+        }
+
+        for ((bucket, data), len) in buckets.into_iter().zip(&appended).zip(lengths) {
+            File::from(bucket).write_all_at(data, len).unwrap();
+        }
+        fs::write(self.dir.join(RingKind::Main.file_name()), bytes).unwrap();
+    }
+
+    /// Stores `data` as an entry in its own file in one of the rings' holes,
+    /// e.g. to give another entry a duplicate. An empty `mime_type` leaves the
+    /// file without one.
+    ///
+    /// # Panics
+    ///
+    /// If the slot isn't a hole or the database can't be written.
+    pub fn fill_hole(&self, ring: RingKind, index: u32, data: &[u8], mime_type: &str) {
+        let path = self.dir.join(ring.file_name());
+        let mut bytes = fs::read(&path).unwrap();
+        let offset = size_of::<Header>() + usize::try_from(index).unwrap() * size_of::<RawEntry>();
+        let slot = &mut bytes[offset..offset + size_of::<RawEntry>()];
+        assert_eq!(
+            slot,
+            RawEntry::from(ring::Entry::Uninitialized).to_le_bytes()
+        );
+        slot.copy_from_slice(&RawEntry::from(ring::Entry::File).to_le_bytes());
+        fs::write(path, bytes).unwrap();
+
+        let mut buf = Default::default();
+        let name = direct_file_name(&mut buf, ring, index);
+        let path = self.dir.join("direct").join(name.to_str().unwrap());
+        fs::write(&path, data).unwrap();
+        if !mime_type.is_empty() {
+            setxattr(
+                &path,
+                c"user.mime_type",
+                mime_type.as_bytes(),
+                XattrFlags::CREATE,
+            )
+            .unwrap();
+        }
+    }
+
+    /// The database directory.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Flips random bytes in the rings, buckets, direct file names, and
+    /// extended attributes.
+    ///
+    /// The same seed always corrupts a freshly created database the same way.
+    ///
+    /// # Panics
+    ///
+    /// If the database can't be modified.
+    pub fn corrupt(&self, seed: u64) {
+        let mut random = SplitMix64(seed);
+        for _ in 0..=random.below(3) {
+            match random.below(4) {
+                0 => {
+                    let ring = [RingKind::Main, RingKind::Favorites][random.below(2)];
+                    flip_byte(&mut random, &self.dir.join(ring.file_name()));
+                }
+                1 => {
+                    let buckets = self.files("buckets").into_iter();
+                    let buckets = buckets.filter(|bucket| fs::metadata(bucket).unwrap().len() > 0);
+                    if let Some(bucket) = random.pick(buckets.collect()) {
+                        flip_byte(&mut random, &bucket);
+                    }
+                }
+                2 => {
+                    let Some(file) = random.pick(self.files("direct")) else {
+                        continue;
+                    };
+                    let mut name = file.file_name().unwrap().as_bytes().to_vec();
+                    let index = random.below(name.len());
+                    name[index] ^= random.nonzero_byte();
+                    if !name.contains(&b'/') && !name.contains(&0) {
+                        fs::rename(&file, file.with_file_name(OsStr::from_bytes(&name))).unwrap();
+                    }
+                }
+                _ => {
+                    let Some(file) = random.pick(self.files("direct")) else {
+                        continue;
+                    };
+                    let name =
+                        [c"user.mime_type", c"user.label", c"user.image_text"][random.below(3)];
+                    let mut value = vec![0; 1 << 16];
+                    let mut value = match getxattr(&file, name, &mut value) {
+                        Ok(len) => value[..len].to_vec(),
+                        Err(Errno::NODATA) => Vec::new(),
+                        Err(e) => panic!("Failed to read {name:?} of {file:?}: {e}"),
+                    };
+                    if value.is_empty() || random.below(4) == 0 {
+                        value.resize(random.below(300), 0);
+                        value.fill_with(|| random.byte());
+                    } else {
+                        let index = random.below(value.len());
+                        value[index] ^= random.nonzero_byte();
+                    }
+                    setxattr(&file, name, &value, XattrFlags::empty()).unwrap();
+                }
+            }
+        }
+    }
+
+    fn files(&self, dir: &str) -> Vec<PathBuf> {
+        let mut files = fs::read_dir(self.dir.join(dir))
+            .unwrap()
+            .map(|file| file.unwrap().path())
+            .collect::<Vec<_>>();
+        // Directory order isn't stable.
+        files.sort();
+        files
+    }
+}
+
+impl Drop for FixtureDatabase {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn flip_byte(random: &mut SplitMix64, file: &Path) {
+    let mut bytes = fs::read(file).unwrap();
+    let index = random.below(bytes.len());
+    bytes[index] ^= random.nonzero_byte();
+    fs::write(file, bytes).unwrap();
+}
+
+#[derive(Copy, Clone)]
+enum FixtureEntry {
+    Hole,
+    Bucketed(&'static [u8]),
+    Direct {
+        data: &'static [u8],
+        mime_type: &'static str,
+        attributes: &'static [(&'static CStr, &'static str)],
+    },
+}
+
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    const fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let z = (self.0 ^ (self.0 >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        let z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        usize::try_from(self.next() % u64::try_from(n).unwrap()).unwrap()
+    }
+
+    const fn byte(&mut self) -> u8 {
+        self.next().to_le_bytes()[0]
+    }
+
+    fn nonzero_byte(&mut self) -> u8 {
+        u8::try_from(self.below(255) + 1).unwrap()
+    }
+
+    fn pick<T>(&mut self, mut items: Vec<T>) -> Option<T> {
+        if items.is_empty() {
+            return None;
+        }
+        let index = self.below(items.len());
+        Some(items.swap_remove(index))
+    }
+}
@@ -1,17 +1,35 @@
 use std::{
     array,
-    cmp::min,
-    collections::{BinaryHeap, HashMap},
-    hash::BuildHasherDefault,
-    io::{BufReader, IoSlice},
-    iter::once,
-    mem,
-    os::fd::{AsFd, OwnedFd},
+    cmp::{min, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    env,
+    ffi::OsString,
+    fs,
+    fs::{DirBuilder, File, OpenOptions},
+    hash::{BuildHasherDefault, Hash, Hasher},
+    io,
+    io::{BufReader, Cursor, ErrorKind, IoSlice, Read, Seek, SeekFrom},
+    ops::Range,
+    os::{
+        fd::{AsFd, AsRawFd, OwnedFd},
+        unix::{
+            fs::{DirBuilderExt, OpenOptionsExt},
+            process::CommandExt,
+        },
+    },
+    path::PathBuf,
+    process,
+    process::Stdio,
     str,
-    sync::Arc,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use image::{DynamicImage, ImageError, ImageReader};
+use image::{imageops::FilterType, DynamicImage, ImageError, ImageFormat, ImageReader};
 use regex::bytes::Regex;
 use ringboard_core::dirs::paste_socket_file;
 use rustc_hash::FxHasher;
@@ -21,18 +39,42 @@ use rustix::net::{
 };
 use thiserror::Error;
 
+#[cfg(feature = "zeroize")]
+use crate::wipe::{wipe_bytes, wipe_str};
 use crate::{
-    api::{connect_to_server, MoveToFrontRequest, RemoveRequest},
+    api::{
+        connect_to_server_with_startup, AddRequest, AddTagRequest, MoveToFrontRequest,
+        RemoveRequest, RemoveTagRequest, RestoreRequest, ServerCapabilities, ServerInfoRequest,
+        SetClipboardRequest, SetLabelRequest, StatsRequest, SwapRequest,
+    },
+    clipboard,
+    clipboard::CopyError,
     core::{
+        checksums::Verification,
         dirs::{data_dir, socket_file},
-        protocol::{composite_id, IdNotFoundError, MoveToFrontResponse, RemoveResponse, RingKind},
+        origins::Origin,
+        protocol::{
+            composite_id, decompose_id, AddResponse, IdNotFoundError, Label, MimeType,
+            MoveToFrontResponse, RemoveResponse, Request, RestoreResponse, RingKind,
+            ServerInfoResponse, SetClipboardResponse, SetLabelResponse, StatsResponse,
+            SwapResponse, Tag, TagResponse,
+        },
         ring::{Ring, MAX_ENTRIES},
-        size_to_bucket, BucketAndIndex, Error as CoreError, IoErr, RingAndIndex,
+        tags,
+        tags::TagSet,
+        Context, Error as CoreError, IoErr, RingAndIndex,
     },
+    duplicate_detection::{find_duplicates, DuplicateGroup, Matching},
+    line_count,
+    preview::Contents,
+    ring_reader::bytes_to_file,
     search,
-    search::{CancellationToken, CaselessQuery, EntryLocation, Query},
-    ClientError, DatabaseReader, Entry, EntryReader, Kind,
+    search::{CancellationToken, CaselessQuery, EntryIndex, EntryLocation, Query},
+    text_kind, ClientError, DatabaseReader, Entry, EntryReader, EntrySource, FileOrSlice, Kind,
+    LoadedEntry, MmapOrSlice, RemoteReader, TextKind,
 };
+#[cfg(feature = "exif")]
+use crate::{core::ring::Mmap, exif, exif::Exif};
 
 #[derive(Error, Debug)]
 pub enum CommandError {
@@ -44,6 +86,20 @@ pub enum CommandError {
     Regex(#[from] regex::Error),
     #[error("failed to load image")]
     Image(#[from] ImageError),
+    #[error("search requires direct access to the database")]
+    SearchUnavailable,
+    #[error("deduplication requires direct access to the database")]
+    DeduplicationUnavailable,
+    #[error("nothing to undo")]
+    NothingToUndo,
+    #[error("nothing to match on")]
+    NothingToMatch,
+    #[error("tags are made of lowercase letters, digits, '-' and '_'")]
+    InvalidTag,
+    #[error("the entry has too many tags")]
+    TooManyTags,
+    #[error("{0}")]
+    Clipboard(#[from] CopyError),
 }
 
 impl From<IdNotFoundError> for CommandError {
@@ -63,8 +119,15 @@ mod error_stack_compat {
             match self {
                 Self::Core(e) => e.into_report(wrapper),
                 Self::Sdk(e) => e.into_report(wrapper),
+                Self::Clipboard(e) => e.into_report(wrapper),
                 Self::Regex(e) => Report::new(e).change_context(wrapper),
                 Self::Image(e) => Report::new(e).change_context(wrapper),
+                Self::SearchUnavailable
+                | Self::DeduplicationUnavailable
+                | Self::NothingToUndo
+                | Self::NothingToMatch
+                | Self::InvalidTag
+                | Self::TooManyTags => Report::new(wrapper),
             }
         }
     }
@@ -72,14 +135,156 @@ mod error_stack_compat {
 
 #[derive(Debug)]
 pub enum Command {
+    /// Loads every favorite and the newest main ring entries, including those
+    /// paged in since with [`Command::LoadPage`].
     LoadFirstPage,
-    GetDetails { id: u64, with_text: bool },
+    /// Loads up to `count` main ring entries older than `after_id`, newest
+    /// first. An empty page means the oldest entry was reached.
+    LoadPage {
+        after_id: u64,
+        count: usize,
+    },
+    /// Loads every main ring entry older than `after_id` down to and including
+    /// `id`, so an entry that hasn't been paged in yet can be selected.
+    LoadThrough {
+        after_id: u64,
+        id: u64,
+    },
+    RefreshDb,
+    /// Loads an entry's metadata, verifies its checksum, and, `with_text`,
+    /// its contents: the full text if it's valid UTF-8 or a
+    /// [`DETAILS_PREVIEW_SIZE`] byte preview otherwise.
+    GetDetails {
+        id: u64,
+        with_text: bool,
+    },
     Favorite(u64),
     Unfavorite(u64),
     Delete(u64),
-    Search { query: Box<str>, kind: SearchKind },
-    LoadImage(u64),
+    /// Deletes every entry it can, replying once with those that were deleted
+    /// so a single refresh covers all of them.
+    ///
+    /// The first failure is reported after the reply.
+    DeleteMany(Box<[u64]>),
+    /// Restores the entry deleted last by [`Command::Delete`] or
+    /// [`Command::DeleteMany`] from the server's trash, replying with
+    /// [`Message::Restored`].
+    ///
+    /// Only the last deletion can be undone, and only until the server purges
+    /// it from the trash.
+    UndoDelete,
+    /// Deletes every entry whose contents another entry already holds,
+    /// keeping the newest copy or, if any copy is a favorite, that favorite.
+    /// Favorites are never deleted.
+    ///
+    /// Like [`Command::DeleteMany`], the first failure is reported after the
+    /// reply.
+    Deduplicate,
+    /// Moves the first entry into the last one's slot by swapping it through
+    /// every slot in between, which shifts those entries over by one.
+    Reorder(Box<[u64]>),
+    /// Swaps a favorite with its neighbour in the list of favorites, which is
+    /// ordered newest first.
+    MoveFavorite {
+        id: u64,
+        direction: MoveDirection,
+    },
+    Search {
+        query: Box<str>,
+        kind: SearchKind,
+        /// Plain searches ignore case, including outside ASCII, unless set.
+        case_sensitive: bool,
+        /// Only entries in this ring are searched if set.
+        ring: Option<RingKind>,
+        /// Only entries of this mime type are searched if set, see
+        /// [`mime_filter_matches`](crate::search::mime_filter_matches).
+        mime_filter: Option<MimeType>,
+        /// Results are ordered newest first across both rings, unless this is
+        /// set to list favorites before everything else. Either order only
+        /// holds within each [`Message::SearchResults`] batch.
+        favorites_first: bool,
+    },
+    /// Searches for entries like the given one with a plain case-insensitive
+    /// search for its [`similar_entries_query`], replying like
+    /// [`Command::Search`] but without the entry itself. The query is sent
+    /// first in [`Message::FindingSimilar`].
+    ///
+    /// Images and binary entries fail with [`CommandError::NothingToMatch`].
+    FindSimilar(u64),
+    /// Decodes an image entry for showing it in full. Images bigger than
+    /// `max_size` are shrunk to fit in it here, keeping their aspect ratio, so
+    /// UIs don't have to resize huge images as they draw. The
+    /// [`ImageMetadata`] still describes the original.
+    LoadImage {
+        id: u64,
+        max_size: Option<(u32, u32)>,
+    },
+    /// Decodes an image entry and shrinks it to fit in [`THUMBNAIL_SIZE`]
+    /// pixels for showing it in a list.
+    LoadThumbnail(u64),
+    /// Pastes an entry and moves it to the front of the main ring since
+    /// pasting counts as copying it again. Favorites keep their place.
     Paste(u64),
+    /// Puts text on the clipboard through the same route as pastes, without
+    /// adding it to the database first.
+    Copy(Box<str>),
+    /// Puts an entry on the system clipboard with [`clipboard::copy`], for
+    /// clients that can't count on a watcher to paste through.
+    Yank(u64),
+    GetServerInfo,
+    /// Asks the server how its database and connections are doing.
+    GetStats,
+    /// Labels an entry, or removes its label if `label` is empty.
+    SetLabel {
+        id: u64,
+        label: Label,
+    },
+    /// Tags an entry, replying with [`Message::Tagged`]. Invalid tags fail
+    /// with [`CommandError::InvalidTag`], see [`tags::parse`].
+    Tag {
+        id: u64,
+        tag: Tag,
+    },
+    /// Removes a tag from an entry, replying with [`Message::Tagged`].
+    Untag {
+        id: u64,
+        tag: Tag,
+    },
+    /// Lists the entries tagged with `tag` like a [`Command::Search`] with
+    /// [`SearchKind::Tag`] across both rings.
+    FilterByTag(Tag),
+    /// Replaces an entry's contents with `text`, keeping its id, mime type
+    /// and label.
+    Replace {
+        id: u64,
+        text: Box<str>,
+    },
+    /// Replaces an entry with `data`, added as a new entry at the front of the
+    /// same ring with the old one's mime type and label. Replies with
+    /// [`Message::EntryReplaced`].
+    ReplaceEntry {
+        id: u64,
+        data: Box<[u8]>,
+    },
+    /// Gathers statistics about every entry, sending [`Message::Dashboard`]
+    /// as they come in. Results are reused for [`DASHBOARD_CACHE_TIME`].
+    ComputeDashboard,
+    /// Opens an entry with `$RINGBOARD_OPENER`, or `xdg-open` if unset, in
+    /// the background. Text entries holding a URL are opened as that URL.
+    ///
+    /// Entries stored in their own file are opened in place while others are
+    /// copied to a temporary file named after their mime type, see
+    /// [`remove_opened_files`].
+    OpenExternal(u64),
+}
+
+/// Which way [`Command::MoveFavorite`] moves a favorite.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MoveDirection {
+    /// Towards the newest favorite.
+    Up,
+    /// Towards the oldest favorite.
+    Down,
 }
 
 #[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -88,42 +293,259 @@ pub enum SearchKind {
     Plain,
     Regex,
     Mime,
+    /// Lists the entries tagged with the query, favorites first and then the
+    /// rest newest first. Tags are checked entry by entry rather than
+    /// searched for, so this works with any entry source.
+    Tag,
 }
 
 #[derive(Debug)]
 pub enum Message {
     FatalDbOpen(CoreError),
+    /// Sent as soon as the database is open, before the first page is read,
+    /// so clients can tell that entries are on their way.
+    Connected,
     Error(CommandError),
     LoadedFirstPage {
         entries: Box<[UiEntry]>,
         default_focused_id: Option<u64>,
+        /// The entry that owns the system clipboard if the server knows of
+        /// one.
+        on_clipboard: Option<u64>,
+    },
+    /// The reply to [`Command::LoadPage`].
+    LoadedPage {
+        after_id: u64,
+        entries: Box<[UiEntry]>,
+    },
+    /// The reply to [`Command::LoadThrough`], without entries if there's no
+    /// main ring entry with that id.
+    LoadedThrough {
+        after_id: u64,
+        id: u64,
+        entries: Option<Box<[UiEntry]>>,
+    },
+    DbRefreshed {
+        changed: bool,
+        new_entries: usize,
+        removed_entries: usize,
     },
     EntryDetails {
         id: u64,
         result: Result<DetailedEntry, CoreError>,
     },
-    PendingSearch(CancellationToken),
-    SearchResults(Box<[UiEntry]>),
+    /// Sent once a search can be cancelled. Results tagged with an older
+    /// generation belong to a search that was since replaced.
+    PendingSearch {
+        generation: u64,
+        token: CancellationToken,
+    },
+    /// The query [`Command::FindSimilar`] searches for, sent before
+    /// [`Message::PendingSearch`].
+    FindingSimilar {
+        id: u64,
+        query: Box<str>,
+    },
+    /// Matches found since the previous batch, newest first. The last batch
+    /// of every search is `done`, and empty if the search was cancelled.
+    SearchResults {
+        generation: u64,
+        batch: Box<[UiEntry]>,
+        done: bool,
+    },
     FavoriteChange(u64),
     Deleted(u64),
+    /// The reply to [`Command::DeleteMany`].
+    DeletedMany(Box<[u64]>),
+    /// The reply to [`Command::UndoDelete`] with the restored entry's new id.
+    Restored {
+        id: u64,
+        new_id: u64,
+    },
+    /// The reply to [`Command::Deduplicate`] with how many sets of identical
+    /// entries were found and the duplicates that were deleted.
+    Deduplicated {
+        groups: usize,
+        deleted: Box<[u64]>,
+    },
+    /// The reply to [`Command::Reorder`] and [`Command::MoveFavorite`] with
+    /// the moved entry's new id.
+    ///
+    /// The move is abandoned if any of the entries were removed meanwhile.
+    Reordered {
+        id: u64,
+        moved: bool,
+    },
+    /// Sent before an image starts loading so it can be abandoned, e.g. once
+    /// a different entry is selected.
+    PendingImage {
+        id: u64,
+        token: CancellationToken,
+    },
+    /// How much of an image's data has been decoded so far.
+    ImageProgress {
+        id: u64,
+        pct: u8,
+    },
     LoadedImage {
         id: u64,
         image: DynamicImage,
+        metadata: ImageMetadata,
+    },
+    ImageFailed {
+        id: u64,
+        error: CommandError,
     },
-    Pasted,
+    /// The reply to [`Command::LoadThumbnail`], without an image if the entry
+    /// couldn't be decoded.
+    LoadedThumbnail {
+        id: u64,
+        image: Option<DynamicImage>,
+    },
+    /// The reply to [`Command::Paste`] with the id the entry was moved to.
+    Pasted {
+        id: u64,
+        new_id: u64,
+    },
+    Copied,
+    Yanked(u64),
+    ServerInfo(ServerInfoResponse),
+    Stats(Box<StatsResponse>),
+    Labeled(u64),
+    /// The reply to [`Command::Tag`] and [`Command::Untag`].
+    Tagged(u64),
+    Replaced(u64),
+    /// The reply to [`Command::ReplaceEntry`] with the id the new contents were
+    /// added under.
+    EntryReplaced {
+        id: u64,
+        new_id: u64,
+    },
+    /// Sent before the dashboard starts being computed so it can be
+    /// abandoned, e.g. once the dashboard is closed.
+    PendingDashboard(CancellationToken),
+    /// The dashboard so far, complete once every entry was
+    /// [scanned](Dashboard::scanned).
+    Dashboard(Box<Dashboard>),
+}
+
+/// What's known about a loaded image besides its pixels.
+#[derive(Clone, Default, Debug)]
+pub struct ImageMetadata {
+    pub width: u32,
+    pub height: u32,
+    /// The format the image was decoded from.
+    pub format: Option<ImageFormat>,
+    /// The size of the image's data in bytes.
+    pub len: u64,
+    /// When the photo was taken, as recorded in a JPEG's EXIF data.
+    pub taken: Option<Box<str>>,
+    /// The camera's make and model, as recorded in a JPEG's EXIF data.
+    pub camera: Option<Box<str>>,
+}
+
+/// The largest width and height of images sent in
+/// [`Message::LoadedThumbnail`].
+pub const THUMBNAIL_SIZE: u32 = 64;
+
+/// How many leading bytes of a binary entry [`DetailedEntry::preview`] holds.
+pub const DETAILS_PREVIEW_SIZE: usize = 16 * 1024;
+
+/// How many days [`Dashboard::adds_per_day`] covers.
+pub const DASHBOARD_DAYS: usize = 30;
+
+/// How long a computed dashboard is shown again instead of being recomputed.
+pub const DASHBOARD_CACHE_TIME: Duration = Duration::from_secs(5 * 60);
+
+/// Statistics about the whole clipboard history.
+#[derive(Clone, Default, Debug)]
+pub struct Dashboard {
+    /// How many of the entries were added on each of the last
+    /// [`DASHBOARD_DAYS`] days, today first. Entries without a timestamp
+    /// aren't counted.
+    pub adds_per_day: [u32; DASHBOARD_DAYS],
+    /// The most common mime types, most entries first.
+    pub mime_types: Vec<MimeTypeStats>,
+    /// The biggest entries, biggest first.
+    pub largest: Vec<LargeEntry>,
+    /// Entries with the same contents as one scanned before them.
+    pub duplicates: u32,
+    pub scanned: u32,
+    pub total: u32,
+}
+
+impl Dashboard {
+    #[must_use]
+    pub const fn is_complete(&self) -> bool {
+        self.scanned == self.total
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MimeTypeStats {
+    /// Empty for entries stored without one, which are treated as text.
+    pub mime_type: Box<str>,
+    pub count: u32,
+    pub bytes: u64,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LargeEntry {
+    pub id: u64,
+    pub len: u64,
 }
 
 #[derive(Debug)]
 pub struct UiEntry {
     pub entry: Entry,
     pub cache: UiEntryCache,
+    pub badge: EntryBadge,
+    /// Shown in place of the entry's contents when the user labeled it.
+    pub label: Option<Box<str>>,
+    /// The size of the entry's contents in bytes, or zero if they couldn't be
+    /// loaded.
+    pub len: u64,
+    /// Changes whenever the entry's contents do, so details shown for the
+    /// entry can be kept across reloads.
+    ///
+    /// Only a prefix of the contents is hashed along with where and how big
+    /// they are, so this is cheap even for huge entries.
+    pub fingerprint: u64,
+    /// When the entry was copied, if the database recorded it.
+    pub timestamp: Option<SystemTime>,
+}
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum EntryBadge {
+    #[default]
+    None,
+    Image,
+    Document,
+    Code,
+    Url,
+    /// A search result that matched text recognized in an image.
+    ImageText,
 }
 
 #[derive(Debug)]
 pub enum UiEntryCache {
-    Text { one_liner: Box<str> },
+    Text {
+        one_liner: Box<str>,
+        /// The part of the one-liner that a search matched, if it's visible.
+        matched: Option<Range<usize>>,
+        /// How many lines the entry spans, so lists can point out the ones
+        /// that don't fit their one-liner.
+        lines: usize,
+        /// What the text looks like, guessed from the one-liner alone.
+        highlight: Option<TextKind>,
+    },
     Image,
-    Binary { mime_type: Box<str> },
+    Binary {
+        mime_type: Box<str>,
+    },
+    /// Data like a password that the server deletes once it expires, which
+    /// UIs keep hidden until asked to reveal it.
+    Sensitive,
     Error(CoreError),
 }
 
@@ -131,397 +553,1976 @@ pub enum UiEntryCache {
 pub struct DetailedEntry {
     pub mime_type: Box<str>,
     pub full_text: Option<Box<str>>,
+    /// The start of an entry that isn't text, so huge direct entries aren't
+    /// copied whole.
+    pub preview: Option<Box<[u8]>>,
+    pub added_by: Option<Origin>,
+    pub timestamp: Option<SystemTime>,
+    pub tags: TagSet,
+    /// Whether the entry's contents still match their checksum, so UIs can
+    /// warn about corruption instead of showing broken data as is.
+    pub verification: Verification,
+    /// Whether the server cut the entry down to its maximum entry size, in
+    /// which case the entry's length is the size it was truncated to.
+    pub truncated: bool,
+    /// What an image entry's header says about it, read without decoding the
+    /// image. Missing for other entries and images with unreadable headers.
+    pub image: Option<ImageMetadata>,
+    /// When the server deletes the entry if it holds sensitive data.
+    pub expires_at: Option<SystemTime>,
 }
 
-pub fn controller<E>(
-    commands: impl IntoIterator<Item = Command>,
-    mut send: impl FnMut(Message) -> Result<(), E>,
-) {
-    fn maybe_init_server(cache: &mut Option<OwnedFd>) -> Result<impl AsFd + '_, ClientError> {
-        if cache.is_some() {
-            return Ok(cache.as_ref().unwrap());
+#[cfg(feature = "zeroize")]
+impl Drop for UiEntryCache {
+    fn drop(&mut self) {
+        if let Self::Text { one_liner, .. } = self {
+            wipe_str(one_liner);
         }
-
-        let server = {
-            let socket_file = socket_file();
-            let addr = SocketAddrUnix::new(&socket_file)
-                .map_io_err(|| format!("Failed to make socket address: {socket_file:?}"))?;
-            connect_to_server(&addr)?
-        };
-
-        Ok(cache.insert(server))
     }
+}
 
-    fn maybe_init_paste_server(
-        cache: &mut Option<(OwnedFd, SocketAddrUnix)>,
-    ) -> Result<(impl AsFd + '_, &SocketAddrUnix), ClientError> {
-        if cache.is_some() {
-            let (sock, addr) = cache.as_ref().unwrap();
-            return Ok((sock, addr));
+#[cfg(feature = "zeroize")]
+impl Drop for DetailedEntry {
+    fn drop(&mut self) {
+        if let Some(full_text) = &mut self.full_text {
+            wipe_str(full_text);
+        }
+        if let Some(preview) = &mut self.preview {
+            wipe_bytes(preview);
         }
-
-        let addr = {
-            let socket_file = paste_socket_file();
-            SocketAddrUnix::new(&socket_file)
-                .map_io_err(|| format!("Failed to make socket address: {socket_file:?}"))?
-        };
-        let sock = socket_with(
-            AddressFamily::UNIX,
-            SocketType::DGRAM,
-            SocketFlags::empty(),
-            None,
-        )
-        .map_io_err(|| format!("Failed to create socket: {addr:?}"))?;
-
-        let (sock, addr) = cache.insert((sock, addr));
-        Ok((sock, addr))
     }
+}
 
-    let mut server = None;
-    let mut paste_server = None;
-    let (mut database, reader) = {
-        let run = || {
-            let mut dir = data_dir();
-
-            let database = DatabaseReader::open(&mut dir)?;
-            let reader = EntryReader::open(&mut dir)?;
-
-            Ok((database, reader))
-        };
+/// Runs a [`Controller`] over the local database and real server connections.
+///
+/// The server is started if the database can't be opened, since fresh
+/// sessions have no database until the server first runs. Clients that still
+/// can't see the database, e.g. because they are sandboxed, read entries
+/// through the server instead.
+pub fn controller<E>(commands: &Receiver<Command>, mut send: impl FnMut(Message) -> Result<(), E>) {
+    let e = match LocalSource::open() {
+        Ok(source) => {
+            Controller::new(source, SocketConnection::default()).run(commands, send);
+            return;
+        }
+        Err(e) => e,
+    };
 
-        match run() {
-            Ok(db) => db,
-            Err(e) => {
-                let _ = send(Message::FatalDbOpen(e));
-                return;
+    let started = server_addr().and_then(|addr| {
+        let server = connect_to_server_with_startup(&addr, SocketFlags::empty(), None)?;
+        Ok((addr, server))
+    });
+    let (e, remote) = match started {
+        Ok((addr, server)) => {
+            let connection = SocketConnection {
+                server: Some(server),
+                paste_server: None,
+            };
+            let e = match LocalSource::open() {
+                Ok(source) => {
+                    Controller::new(source, connection).run(commands, send);
+                    return;
+                }
+                Err(e) => e,
+            };
+            match RemoteReader::connect(&addr) {
+                Ok(source) => {
+                    Controller::new(source, connection).run(commands, send);
+                    return;
+                }
+                Err(remote) => (e, remote),
             }
         }
+        Err(remote) => (e, remote),
     };
-    let mut reader = Some(reader);
-    let mut cache = Default::default();
+    let _ = send(Message::FatalDbOpen(no_entry_source(e, remote)));
+}
 
-    for command in once(Command::LoadFirstPage).chain(commands) {
-        let result = handle_command(
-            command,
-            || maybe_init_server(&mut server),
-            || maybe_init_paste_server(&mut paste_server),
-            &mut send,
-            &mut database,
-            &mut reader,
-            &mut cache,
-        )
-        .unwrap_or_else(|e| Some(Message::Error(e)));
+/// Neither the database nor the server could supply entries.
+#[derive(Error, Debug)]
+#[error("{local}, and reading through the server failed too: {remote}")]
+struct NoEntrySource {
+    local: io::Error,
+    #[source]
+    remote: ClientError,
+}
 
-        let Some(response) = result else {
-            continue;
-        };
-        if send(response).is_err() {
-            break;
-        }
+/// Keeps the database's error in front since it's usually the one to fix, but
+/// chains why the server couldn't stand in for it.
+fn no_entry_source(local: CoreError, remote: ClientError) -> CoreError {
+    let (local, context) = match local {
+        CoreError::Io { error, context } => (error, context),
+        e => (
+            io::Error::other(e),
+            Context::Message("Failed to open the database.".into()),
+        ),
+    };
+    CoreError::Io {
+        error: io::Error::new(local.kind(), NoEntrySource { local, remote }),
+        context,
     }
 }
 
-fn handle_command<'a, Server: AsFd, PasteServer: AsFd, E>(
-    command: Command,
-    server: impl FnOnce() -> Result<Server, ClientError>,
-    paste_server: impl FnOnce() -> Result<(PasteServer, &'a SocketAddrUnix), ClientError>,
-    send: impl FnMut(Message) -> Result<(), E>,
-    database: &mut DatabaseReader,
-    reader_: &mut Option<EntryReader>,
-    cache: &mut (
-        Option<(u32, u32)>,
-        HashMap<BucketAndIndex, RingAndIndex, BuildHasherDefault<FxHasher>>,
-        Vec<RingAndIndex>,
-    ),
-) -> Result<Option<Message>, CommandError> {
-    let reader = reader_.as_mut().unwrap();
-    match command {
-        Command::LoadFirstPage => {
-            // This will trigger every time once the ring has reached capacity and doesn't
-            // work if the ring fully wrapped around while we weren't looking.
-            let shitty_refresh = |ring: &mut Ring| {
-                let head = ring.write_head();
-                #[allow(clippy::comparison_chain)]
-                if head < ring.len() {
-                    unsafe {
-                        ring.set_len(ring.capacity());
-                    }
-                } else if head > ring.len() {
-                    unsafe {
-                        ring.set_len(head);
-                    }
-                }
-            };
-            shitty_refresh(database.favorites_ring_mut());
-            shitty_refresh(database.main_ring_mut());
+fn server_addr() -> Result<SocketAddrUnix, ClientError> {
+    let socket_file = socket_file();
+    Ok(
+        SocketAddrUnix::new(&socket_file).map_io_err(|| Context::File {
+            message: "Failed to make socket address",
+            path: socket_file.clone(),
+        })?,
+    )
+}
 
-            let mut entries = Vec::with_capacity(100);
-            for entry in database
-                .favorites()
-                .rev()
-                .chain(database.main().rev().take(100))
-            {
-                entries.push(ui_entry(entry, reader).unwrap_or_else(|e| UiEntry {
-                    cache: UiEntryCache::Error(e),
-                    entry,
-                }));
+/// Sends [`Command::RefreshDb`] whenever the local database changes so
+/// clients show new entries as they're copied.
+///
+/// Clients that can't see the database, e.g. because they are sandboxed,
+/// aren't notified and have to keep refreshing on their own.
+pub fn watch_database<E>(mut send: impl FnMut(Command) -> Result<(), E> + Send + 'static) {
+    let Ok(mut watcher) = DatabaseReader::watch(&data_dir()) else {
+        return;
+    };
+    thread::spawn(move || {
+        while watcher.wait().is_ok() {
+            if send(Command::RefreshDb).is_err() {
+                break;
             }
-            Ok(Some(Message::LoadedFirstPage {
-                entries: entries.into(),
-                default_focused_id: {
-                    let mut main = database.main().rev();
-                    let first = main.next();
-                    main.next()
-                        .or(first)
-                        .or_else(|| database.favorites().next_back())
-                        .as_ref()
-                        .map(Entry::id)
-                },
-            }))
         }
-        Command::GetDetails { id, with_text } => {
-            let mut run = || {
-                let entry = unsafe { database.get(id)? };
-                if with_text {
-                    let loaded = entry.to_slice(reader)?;
-                    Ok(DetailedEntry {
-                        mime_type: (&*loaded.mime_type()?).into(),
-                        full_text: str::from_utf8(&loaded).map(Box::from).ok(),
-                    })
-                } else {
-                    Ok(DetailedEntry {
-                        mime_type: (&*entry.mime_type(reader)?).into(),
-                        full_text: None,
-                    })
-                }
-            };
-            Ok(Some(Message::EntryDetails { id, result: run() }))
+    });
+}
+
+/// Executes [`Command`]s, replying with [`Message`]s.
+///
+/// Entries are read from `Source` while changes and pastes go through
+/// `Server`, so tests can swap either out for fakes such as the ones in
+/// [`testing`](crate::testing).
+#[derive(Debug)]
+pub struct Controller<Source = LocalSource, Server = SocketConnection> {
+    source: Source,
+    server: Server,
+    version: DbVersion,
+    /// How many main ring entries were loaded past the first page.
+    paged_in: usize,
+    /// The last complete dashboard and when it was computed.
+    dashboard: Option<(Instant, Dashboard)>,
+    /// How many searches were started, which tags their results.
+    searches: u64,
+    /// The id of the last entry deleted, for [`Command::UndoDelete`].
+    last_deleted: Option<u64>,
+}
+
+impl<Source: EntrySource + SearchSource, Server: ServerConnection> Controller<Source, Server> {
+    pub fn new(source: Source, server: Server) -> Self {
+        Self {
+            source,
+            server,
+            version: Default::default(),
+            paged_in: 0,
+            dashboard: None,
+            searches: 0,
+            last_deleted: None,
         }
-        ref c @ (Command::Favorite(id) | Command::Unfavorite(id)) => {
-            match MoveToFrontRequest::response(
-                server()?,
-                id,
-                Some(match c {
-                    Command::Favorite(_) => RingKind::Favorites,
-                    Command::Unfavorite(_) => RingKind::Main,
-                    _ => unreachable!(),
-                }),
-            )? {
-                MoveToFrontResponse::Success { id } => Ok(Some(Message::FavoriteChange(id))),
-                MoveToFrontResponse::Error(e) => Err(e.into()),
-            }
+    }
+
+    /// Handles commands until they run out or a message can't be sent. The
+    /// first page of entries is always loaded up front, right after
+    /// [`Message::Connected`].
+    ///
+    /// [`Command::GetDetails`] and [`Command::LoadImage`] are skipped without a
+    /// reply if another one of the same kind is already queued behind them,
+    /// so scrubbing through entries only loads the one the UI stopped at.
+    pub fn run<E>(
+        mut self,
+        commands: &Receiver<Command>,
+        mut send: impl FnMut(Message) -> Result<(), E>,
+    ) {
+        if send(Message::Connected).is_err() {
+            return;
         }
-        Command::Delete(id) => match RemoveRequest::response(server()?, id)? {
-            RemoveResponse { error: None } => Ok(Some(Message::Deleted(id))),
-            RemoveResponse { error: Some(e) } => Err(e.into()),
-        },
-        Command::Search { query, kind } => {
-            let query = match kind {
-                SearchKind::Plain => {
-                    if query
-                        .chars()
-                        .all(|c| !char::is_alphabetic(c) || char::is_lowercase(c))
-                    {
-                        Query::PlainIgnoreCase(CaselessQuery::new(query.into_boxed_bytes()).trim())
-                    } else {
-                        Query::Plain(query.trim().as_bytes())
-                    }
-                }
-                SearchKind::Regex => Query::Regex(Regex::new(&query)?),
-                SearchKind::Mime => Query::Mimes(Regex::new(&query)?),
+        let mut queued = VecDeque::from([Command::LoadFirstPage]);
+        loop {
+            queued.extend(commands.try_iter());
+            let Some(command) = queued.pop_front().or_else(|| commands.recv().ok()) else {
+                break;
             };
-            Ok(Some(Message::SearchResults(
-                do_search(query, reader_, database, send, cache).into(),
-            )))
-        }
-        Command::LoadImage(id) => {
-            let entry = unsafe { database.get(id)? };
-            Ok(Some(Message::LoadedImage {
-                id,
-                image: ImageReader::new(BufReader::new(&*entry.to_file(reader)?))
-                    .with_guessed_format()
-                    .map_io_err(|| format!("Failed to guess image format for entry {id}."))?
-                    .decode()?,
-            }))
-        }
-        Command::Paste(id) => {
-            let entry = unsafe { database.get(id)? };
-            let (paste_server, addr) = paste_server()?;
-            send_paste_buffer(paste_server, addr, entry, reader)?;
-            Ok(Some(Message::Pasted))
+            if superseded(&command, &queued) {
+                continue;
+            }
+            let Some(response) = self.handle(command, &mut send) else {
+                continue;
+            };
+            if send(response).is_err() {
+                break;
+            }
         }
     }
+
+    /// Handles a single command, returning its reply if it has one.
+    ///
+    /// Progress messages such as [`Message::PendingSearch`] are sent before
+    /// the reply.
+    pub fn handle<E>(
+        &mut self,
+        command: Command,
+        send: impl FnMut(Message) -> Result<(), E>,
+    ) -> Option<Message> {
+        handle_command(
+            command,
+            &mut self.server,
+            send,
+            &mut self.source,
+            &mut self.version,
+            &mut self.paged_in,
+            &mut self.dashboard,
+            &mut self.searches,
+            &mut self.last_deleted,
+        )
+        .unwrap_or_else(|e| Some(Message::Error(e)))
+    }
 }
 
-fn ui_entry(entry: Entry, reader: &mut EntryReader) -> Result<UiEntry, CoreError> {
-    let loaded = entry.to_slice(reader)?;
-    let mime_type = &*loaded.mime_type()?;
-    if mime_type.starts_with("image/") {
-        return Ok(UiEntry {
-            entry,
-            cache: UiEntryCache::Image,
-        });
+/// The server side of commands that change the database or paste entries.
+pub trait ServerConnection {
+    fn move_to_front(
+        &mut self,
+        id: u64,
+        to: Option<RingKind>,
+    ) -> Result<MoveToFrontResponse, ClientError>;
+
+    fn add(
+        &mut self,
+        to: RingKind,
+        mime_type: MimeType,
+        data: File,
+    ) -> Result<AddResponse, ClientError>;
+
+    fn remove(&mut self, id: u64) -> Result<RemoveResponse, ClientError>;
+
+    fn restore(&mut self, tombstone_id: u64) -> Result<RestoreResponse, ClientError>;
+
+    fn swap(&mut self, id1: u64, id2: u64) -> Result<SwapResponse, ClientError>;
+
+    fn set_clipboard(&mut self, id: Option<u64>) -> Result<SetClipboardResponse, ClientError>;
+
+    fn current_clipboard(&mut self) -> Result<Option<u64>, ClientError>;
+
+    fn set_label(&mut self, id: u64, label: Label) -> Result<SetLabelResponse, ClientError>;
+
+    fn add_tag(&mut self, id: u64, tag: Tag) -> Result<TagResponse, ClientError>;
+
+    fn remove_tag(&mut self, id: u64, tag: Tag) -> Result<TagResponse, ClientError>;
+
+    fn server_info(&mut self) -> Result<ServerInfoResponse, ClientError>;
+
+    fn stats(&mut self) -> Result<StatsResponse, ClientError>;
+
+    fn paste(&mut self, entry: LoadedEntry<File>) -> Result<(), ClientError>;
+}
+
+/// Connections to the Ringboard server and paste server, opened on first use.
+#[derive(Default, Debug)]
+pub struct SocketConnection {
+    server: Option<(OwnedFd, ServerCapabilities)>,
+    paste_server: Option<(OwnedFd, SocketAddrUnix)>,
+}
+
+impl SocketConnection {
+    fn server(&mut self) -> Result<impl AsFd + '_, ClientError> {
+        self.server_for(None)
     }
 
-    Ok(match str::from_utf8(&loaded[..min(loaded.len(), 250)]) {
-        Ok(s) => Some(s),
-        Err(e) if e.error_len().is_none() => {
-            Some(unsafe { str::from_utf8_unchecked(&loaded[..e.valid_up_to()]) })
+    /// Connects to the server like [`Self::server`], failing without sending
+    /// anything if the server is too old for `request`.
+    fn server_for(&mut self, request: Option<&Request>) -> Result<impl AsFd + '_, ClientError> {
+        if self.server.is_none() {
+            self.server = Some(connect_to_server_with_startup(
+                &server_addr()?,
+                SocketFlags::empty(),
+                None,
+            )?);
         }
-        Err(_) => None,
-    }
-    .map_or_else(
-        || UiEntry {
-            entry,
-            cache: UiEntryCache::Binary {
-                mime_type: mime_type.into(),
-            },
-        },
-        |s| {
-            let mut one_liner = String::new();
-            let mut prev_char_is_whitespace = false;
-            for c in s.chars() {
-                if (prev_char_is_whitespace || one_liner.is_empty()) && c.is_whitespace() {
-                    continue;
-                }
 
-                one_liner.push(if c.is_whitespace() { ' ' } else { c });
-                prev_char_is_whitespace = c.is_whitespace();
-            }
-            if s.len() != loaded.len() {
-                one_liner.push('…');
-            }
+        let (server, capabilities) = self.server.as_ref().unwrap();
+        if let Some(request) = request.filter(|request| !capabilities.supports(request)) {
+            return Err(ClientError::UnsupportedByServer {
+                tag: request.tag(),
+                trace: None,
+            });
+        }
+        Ok(server)
+    }
 
-            UiEntry {
-                entry,
-                cache: UiEntryCache::Text {
-                    one_liner: one_liner.into(),
-                },
+    fn paste_server(&mut self) -> Result<(impl AsFd + '_, &SocketAddrUnix), ClientError> {
+        let (sock, addr) = match &mut self.paste_server {
+            Some(server) => server,
+            slot @ None => {
+                let addr = {
+                    let socket_file = paste_socket_file();
+                    SocketAddrUnix::new(&socket_file).map_io_err(|| Context::File {
+                        message: "Failed to make socket address",
+                        path: socket_file.clone(),
+                    })?
+                };
+                let sock = socket_with(
+                    AddressFamily::UNIX,
+                    SocketType::DGRAM,
+                    SocketFlags::empty(),
+                    None,
+                )
+                .map_io_err(|| format!("Failed to create socket: {addr:?}"))?;
+                slot.insert((sock, addr))
             }
-        },
-    ))
+        };
+        Ok((&*sock, &*addr))
+    }
 }
 
-fn do_search<E>(
-    query: Query,
-    reader_: &mut Option<EntryReader>,
-    database: &mut DatabaseReader,
-    mut send: impl FnMut(Message) -> Result<(), E>,
-    (cached_write_heads, reverse_index_cache, search_result_buf): &mut (
-        Option<(u32, u32)>,
-        HashMap<BucketAndIndex, RingAndIndex, BuildHasherDefault<FxHasher>>,
-        Vec<RingAndIndex>,
-    ),
-) -> Vec<UiEntry> {
-    const MAX_SEARCH_ENTRIES: usize = 256;
+impl ServerConnection for SocketConnection {
+    fn move_to_front(
+        &mut self,
+        id: u64,
+        to: Option<RingKind>,
+    ) -> Result<MoveToFrontResponse, ClientError> {
+        MoveToFrontRequest::response(self.server()?, id, to)
+    }
 
-    let reader = Arc::new(reader_.take().unwrap());
+    fn add(
+        &mut self,
+        to: RingKind,
+        mime_type: MimeType,
+        data: File,
+    ) -> Result<AddResponse, ClientError> {
+        AddRequest::response(self.server()?, to, mime_type, data)
+    }
 
-    let (result_stream, threads) = search(query, reader.clone());
-    let _ = send(Message::PendingSearch(
-        result_stream.cancellation_token().clone(),
-    ));
+    fn remove(&mut self, id: u64) -> Result<RemoveResponse, ClientError> {
+        RemoveRequest::response(self.server()?, id)
+    }
 
-    if *cached_write_heads
-        != Some((
-            database.favorites().ring().write_head(),
-            database.main().ring().write_head(),
-        ))
-    {
-        reverse_index_cache.clear();
+    fn restore(&mut self, tombstone_id: u64) -> Result<RestoreResponse, ClientError> {
+        RestoreRequest::response(
+            self.server_for(Some(&Request::Restore { tombstone_id }))?,
+            tombstone_id,
+        )
     }
-    if reverse_index_cache.is_empty() {
-        for entry in database.favorites().chain(database.main()) {
-            let Kind::Bucket(bucket) = entry.kind() else {
-                continue;
-            };
-            reverse_index_cache.insert(
-                BucketAndIndex::new(size_to_bucket(bucket.size()), bucket.index()),
-                RingAndIndex::new(entry.ring(), entry.index()),
-            );
-        }
+
+    fn swap(&mut self, id1: u64, id2: u64) -> Result<SwapResponse, ClientError> {
+        SwapRequest::response(self.server()?, id1, id2)
     }
 
-    let mut results = BinaryHeap::from(mem::take(search_result_buf));
-    let write_heads: [_; 2] = array::from_fn(|i| {
-        let ring = if i == RingKind::Main as usize {
-            database.main()
-        } else if i == RingKind::Favorites as usize {
-            database.favorites()
-        } else {
-            unreachable!()
-        };
-        let ring = ring.ring();
-        ring.prev_entry(ring.write_head())
-    });
-    for entry in result_stream
-        .flatten()
-        .flat_map(|q| match q.location {
-            EntryLocation::Bucketed { bucket, index } => reverse_index_cache
-                .get(&BucketAndIndex::new(bucket, index))
-                .copied()
-                .ok_or_else(|| {
-                    CoreError::IdNotFound(IdNotFoundError::Entry(
-                        index << u8::BITS | u32::from(bucket),
-                    ))
-                }),
-            EntryLocation::File { entry_id } => {
-                RingAndIndex::from_id(entry_id).map_err(CoreError::IdNotFound)
-            }
-        })
-        .map(|entry| {
-            RingAndIndex::new(
-                entry.ring(),
-                write_heads[entry.ring() as usize].wrapping_sub(entry.index()) & MAX_ENTRIES,
-            )
-        })
-    {
-        if results.len() == MAX_SEARCH_ENTRIES {
-            if entry < *results.peek().unwrap() {
-                results.pop();
-                results.push(entry);
-            }
-        } else {
-            results.push(entry);
-        }
+    fn set_clipboard(&mut self, id: Option<u64>) -> Result<SetClipboardResponse, ClientError> {
+        SetClipboardRequest::response(self.server()?, id)
     }
 
-    for thread in threads {
-        let _ = thread.join();
+    fn current_clipboard(&mut self) -> Result<Option<u64>, ClientError> {
+        Ok(StatsRequest::response(self.server()?)?.current_clipboard)
     }
-    let reader = reader_.insert(Arc::into_inner(reader).unwrap());
 
-    let mut results = results.into_vec();
-    results.sort_unstable();
-    #[allow(clippy::iter_with_drain)] // https://github.com/rust-lang/rust-clippy/issues/8539
-    let entries = results
-        .drain(..)
-        .flat_map(|entry| {
-            let ring = entry.ring();
-            let index = write_heads[ring as usize].wrapping_sub(entry.index()) & MAX_ENTRIES;
+    fn set_label(&mut self, id: u64, label: Label) -> Result<SetLabelResponse, ClientError> {
+        SetLabelRequest::response(
+            self.server_for(Some(&Request::SetLabel { id, label }))?,
+            id,
+            label,
+        )
+    }
+
+    fn add_tag(&mut self, id: u64, tag: Tag) -> Result<TagResponse, ClientError> {
+        AddTagRequest::response(
+            self.server_for(Some(&Request::AddTag { id, tag }))?,
+            id,
+            tag,
+        )
+    }
+
+    fn remove_tag(&mut self, id: u64, tag: Tag) -> Result<TagResponse, ClientError> {
+        RemoveTagRequest::response(
+            self.server_for(Some(&Request::RemoveTag { id, tag }))?,
+            id,
+            tag,
+        )
+    }
+
+    fn server_info(&mut self) -> Result<ServerInfoResponse, ClientError> {
+        ServerInfoRequest::response(self.server()?)
+    }
+
+    fn stats(&mut self) -> Result<StatsResponse, ClientError> {
+        StatsRequest::response(self.server()?)
+    }
+
+    fn paste(&mut self, entry: LoadedEntry<File>) -> Result<(), ClientError> {
+        let (server, addr) = self.paste_server()?;
+        send_paste_buffer(server, addr, &entry)?;
+        Ok(())
+    }
+}
+
+type SearchCache = (Arc<EntryIndex>, Vec<RingAndIndex>);
+
+/// Entry sources that can be searched.
+pub trait SearchSource {
+    /// Searches the source's entries, sending [`Message::PendingSearch`] once
+    /// the search can be cancelled and [`Message::SearchResults`] batches
+    /// tagged with `generation` as matches come in. Returns the matches found
+    /// after the last batch. Results can be narrowed down to a ring, a mime
+    /// type, and entries added by the client with the given name, and list
+    /// favorites before other matches if `favorites_first` is set.
+    ///
+    /// Sources can't be searched by default.
+    fn search<E>(
+        &mut self,
+        _: Query,
+        _: Option<RingKind>,
+        _: Option<MimeType>,
+        _: Option<&str>,
+        _: bool,
+        _: u64,
+        _: impl FnMut(Message) -> Result<(), E>,
+    ) -> Result<Vec<UiEntry>, CommandError> {
+        Err(CommandError::SearchUnavailable)
+    }
+
+    /// Finds every group of entries with identical contents, see
+    /// [`find_duplicates`].
+    ///
+    /// Sources can't be deduplicated by default.
+    fn duplicates(&mut self) -> Result<Vec<DuplicateGroup>, CommandError> {
+        Err(CommandError::DeduplicationUnavailable)
+    }
+}
+
+/// Reads entries straight from the database files.
+#[derive(Debug)]
+pub struct LocalSource {
+    database: DatabaseReader,
+    reader: Option<EntryReader>,
+    cache: SearchCache,
+}
+
+impl LocalSource {
+    pub fn open() -> Result<Self, CoreError> {
+        let mut dir = data_dir();
+
+        let database = DatabaseReader::open(&mut dir)?;
+        let reader = EntryReader::open(&mut dir)?;
+
+        Ok(Self {
+            database,
+            reader: Some(reader),
+            cache: Default::default(),
+        })
+    }
+}
+
+impl EntrySource for LocalSource {
+    fn generation(&self) -> Option<u64> {
+        self.database.generation()
+    }
+
+    fn ring_heads(&mut self) -> Result<[(u32, u32); 2], CoreError> {
+        self.refresh()?;
+        let favorites = self.database.favorites_ring_mut();
+        let favorites = (favorites.write_head(), favorites.len());
+        let main = self.database.main_ring_mut();
+        let main = (main.write_head(), main.len());
+        Ok([favorites, main])
+    }
+
+    fn refresh(&mut self) -> Result<(), CoreError> {
+        // This will trigger every time once the ring has reached capacity and doesn't
+        // work if the ring fully wrapped around while we weren't looking.
+        let shitty_refresh = |ring: &mut Ring| {
+            let head = ring.write_head();
+            #[allow(clippy::comparison_chain)]
+            if head < ring.len() {
+                unsafe {
+                    ring.set_len(ring.capacity());
+                }
+            } else if head > ring.len() {
+                unsafe {
+                    ring.set_len(head);
+                }
+            }
+        };
+        shitty_refresh(self.database.favorites_ring_mut());
+        shitty_refresh(self.database.main_ring_mut());
+        Ok(())
+    }
+
+    fn favorites(&self) -> impl DoubleEndedIterator<Item = Entry> + '_ {
+        self.database.favorites()
+    }
+
+    fn main(&self) -> impl DoubleEndedIterator<Item = Entry> + '_ {
+        self.database.main()
+    }
+
+    fn get(&mut self, id: u64) -> Result<Entry, CoreError> {
+        Ok(unsafe { self.database.get(id)? })
+    }
+
+    fn prefetch(&mut self, entries: &[Entry]) {
+        self.reader
+            .as_ref()
+            .unwrap()
+            .prefetch(entries.iter().copied());
+    }
+
+    fn to_slice(&mut self, entry: Entry) -> Result<LoadedEntry<MmapOrSlice>, CoreError> {
+        entry.to_slice(self.reader.as_ref().unwrap())
+    }
+
+    fn to_file(&mut self, entry: Entry) -> Result<LoadedEntry<File>, CoreError> {
+        entry.to_file(self.reader.as_ref().unwrap())
+    }
+
+    fn to_reader(&mut self, entry: Entry) -> Result<LoadedEntry<FileOrSlice>, CoreError> {
+        entry.to_reader(self.reader.as_ref().unwrap())
+    }
+
+    fn mime_type(&mut self, entry: Entry) -> Result<MimeType, CoreError> {
+        entry.mime_type(self.reader.as_ref().unwrap())
+    }
+
+    fn added_by(&mut self, entry: Entry) -> Option<Origin> {
+        self.reader
+            .as_ref()
+            .unwrap()
+            .added_by(entry.ring(), entry.index())
+    }
+
+    fn tags(&mut self, entry: Entry) -> Result<TagSet, CoreError> {
+        entry.tags(self.reader.as_ref().unwrap())
+    }
+
+    fn timestamp(&mut self, entry: Entry) -> Option<SystemTime> {
+        entry.timestamp(self.reader.as_ref().unwrap())
+    }
+}
+
+impl SearchSource for LocalSource {
+    fn search<E>(
+        &mut self,
+        query: Query,
+        ring: Option<RingKind>,
+        mime_filter: Option<MimeType>,
+        from: Option<&str>,
+        favorites_first: bool,
+        generation: u64,
+        send: impl FnMut(Message) -> Result<(), E>,
+    ) -> Result<Vec<UiEntry>, CommandError> {
+        Ok(do_search(
+            query,
+            ring,
+            mime_filter,
+            from,
+            favorites_first,
+            generation,
+            &mut self.reader,
+            &mut self.database,
+            send,
+            &mut self.cache,
+        ))
+    }
+
+    fn duplicates(&mut self) -> Result<Vec<DuplicateGroup>, CommandError> {
+        self.refresh()?;
+        Ok(find_duplicates(
+            self.reader.as_ref().unwrap(),
+            &self.database,
+            Matching::Exact,
+        )?
+        .collect())
+    }
+}
+
+impl SearchSource for RemoteReader {}
+
+fn handle_command<E>(
+    command: Command,
+    server: &mut impl ServerConnection,
+    mut send: impl FnMut(Message) -> Result<(), E>,
+    source: &mut (impl EntrySource + SearchSource),
+    version: &mut DbVersion,
+    paged_in: &mut usize,
+    dashboard: &mut Option<(Instant, Dashboard)>,
+    searches: &mut u64,
+    last_deleted: &mut Option<u64>,
+) -> Result<Option<Message>, CommandError> {
+    match command {
+        Command::LoadFirstPage => {
+            *version = db_version(source)?;
+            load_first_page(source, server, *paged_in).map(Some)
+        }
+        Command::LoadPage { after_id, count } => {
+            let entries = load_page(source, after_id, count, None)?;
+            *paged_in += entries.len();
+            Ok(Some(Message::LoadedPage {
+                after_id,
+                entries: entries.into(),
+            }))
+        }
+        Command::LoadThrough { after_id, id } => {
+            let [_, (_, len)] = source.ring_heads()?;
+            // Ids are typed in by hand, so check they're in the ring before
+            // looking them up.
+            let entry = match decompose_id(id) {
+                Ok((RingKind::Main, index)) if index < len => match source.get(id) {
+                    Ok(entry) => Some(entry),
+                    Err(CoreError::IdNotFound(_)) => None,
+                    Err(e) => return Err(e.into()),
+                },
+                _ => None,
+            };
+            let entries = entry
+                .map(|entry| load_page(source, after_id, usize::MAX, Some(entry.index())))
+                .transpose()?;
+            if let Some(entries) = &entries {
+                *paged_in += entries.len();
+            }
+            Ok(Some(Message::LoadedThrough {
+                after_id,
+                id,
+                entries: entries.map(Vec::into_boxed_slice),
+            }))
+        }
+        Command::RefreshDb => {
+            let (old_heads, old_generation) = *version;
+            *version = db_version(source)?;
+            let (ring_heads, generation) = *version;
+
+            let (mut new_entries, mut removed_entries) = (0, 0);
+            for (old, new) in old_heads.into_iter().zip(ring_heads) {
+                let (added, removed) = ring_changes(old, new);
+                new_entries += added;
+                removed_entries += removed;
+            }
+            // Deletions and swaps leave the write heads alone but do bump the generation.
+            // Without a generation to go by, e.g. from older servers, they can't be told
+            // apart from nothing happening, so always reload.
+            let changed =
+                old_heads != ring_heads || old_generation != generation || generation.is_none();
+
+            let _ = send(Message::DbRefreshed {
+                changed,
+                new_entries,
+                removed_entries,
+            });
+            if changed {
+                load_first_page(source, server, *paged_in).map(Some)
+            } else {
+                Ok(None)
+            }
+        }
+        Command::GetDetails { id, with_text } => {
+            let mut run = || {
+                let entry = source.get(id)?;
+                let added_by = source.added_by(entry);
+                let timestamp = source.timestamp(entry);
+                let tags = source.tags(entry)?;
+                let loaded = source.to_slice(entry)?;
+                let mime_type = loaded.mime_type()?;
+                let (full_text, preview) = if with_text {
+                    let full_text = str::from_utf8(&loaded).map(Box::from).ok();
+                    let preview = full_text
+                        .is_none()
+                        .then(|| Box::from(&loaded[..min(loaded.len(), DETAILS_PREVIEW_SIZE)]));
+                    (full_text, preview)
+                } else {
+                    (None, None)
+                };
+                let (mime_type, image) = match full_text {
+                    Some(_) => (mime_type, None),
+                    // Entries without a mime type get the one they were sniffed as.
+                    None => match Contents::classify(entry, &loaded, mime_type, None) {
+                        Contents::Image { format } => (
+                            format
+                                .filter(|_| mime_type.is_empty())
+                                .and_then(|format| MimeType::from(format.to_mime_type()).ok())
+                                .unwrap_or(mime_type),
+                            image_header(&loaded),
+                        ),
+                        Contents::Binary { mime_type } => (mime_type, None),
+                        Contents::Text { .. } => (mime_type, None),
+                    },
+                };
+                Ok(DetailedEntry {
+                    mime_type: (&*mime_type).into(),
+                    full_text,
+                    preview,
+                    added_by,
+                    timestamp,
+                    tags,
+                    verification: loaded.verify()?,
+                    truncated: loaded.truncated()?,
+                    image,
+                    expires_at: loaded.expires_at()?,
+                })
+            };
+            Ok(Some(Message::EntryDetails { id, result: run() }))
+        }
+        ref c @ (Command::Favorite(id) | Command::Unfavorite(id)) => {
+            match server.move_to_front(
+                id,
+                Some(match c {
+                    Command::Favorite(_) => RingKind::Favorites,
+                    Command::Unfavorite(_) => RingKind::Main,
+                    _ => unreachable!(),
+                }),
+            )? {
+                MoveToFrontResponse::Success { id } => Ok(Some(Message::FavoriteChange(id))),
+                MoveToFrontResponse::Error(e) => Err(e.into()),
+                MoveToFrontResponse::FavoritesFull => Err(ClientError::FavoritesFull.into()),
+            }
+        }
+        Command::Delete(id) => match server.remove(id)? {
+            RemoveResponse { error: None } => {
+                *last_deleted = Some(id);
+                Ok(Some(Message::Deleted(id)))
+            }
+            RemoveResponse { error: Some(e) } => Err(e.into()),
+        },
+        Command::DeleteMany(ids) => {
+            let mut deleted = Vec::with_capacity(ids.len());
+            let mut error = None;
+            for &id in &ids {
+                match server.remove(id)? {
+                    RemoveResponse { error: None } => deleted.push(id),
+                    RemoveResponse { error: Some(e) } => {
+                        error.get_or_insert(e);
+                    }
+                }
+            }
+            if let Some(&id) = deleted.last() {
+                *last_deleted = Some(id);
+            }
+            let reply = Message::DeletedMany(deleted.into());
+            if let Some(e) = error {
+                let _ = send(reply);
+                Err(e.into())
+            } else {
+                Ok(Some(reply))
+            }
+        }
+        Command::UndoDelete => {
+            let id = last_deleted.ok_or(CommandError::NothingToUndo)?;
+            match server.restore(id)? {
+                RestoreResponse::Added(response) => {
+                    let new_id = added_id(response)?;
+                    *last_deleted = None;
+                    Ok(Some(Message::Restored { id, new_id }))
+                }
+                // Purged, most likely because the server restarted or enough
+                // was deleted since.
+                RestoreResponse::NotInTrash => {
+                    *last_deleted = None;
+                    Err(CommandError::NothingToUndo)
+                }
+            }
+        }
+        Command::Deduplicate => {
+            let groups = source.duplicates()?;
+            let mut deleted = Vec::new();
+            let mut error = None;
+            for entry in groups.iter().flat_map(|group| &group.entries[1..]) {
+                if entry.ring() == RingKind::Favorites {
+                    continue;
+                }
+                match server.remove(entry.id())? {
+                    RemoveResponse { error: None } => deleted.push(entry.id()),
+                    RemoveResponse { error: Some(e) } => {
+                        error.get_or_insert(e);
+                    }
+                }
+            }
+            let reply = Message::Deduplicated {
+                groups: groups.len(),
+                deleted: deleted.into(),
+            };
+            if let Some(e) = error {
+                let _ = send(reply);
+                Err(e.into())
+            } else {
+                Ok(Some(reply))
+            }
+        }
+        Command::Reorder(ids) => {
+            let Some(&id) = ids.last() else {
+                return Ok(None);
+            };
+            source.refresh()?;
+            // The server happily swaps an entry into an empty slot, so check
+            // everything is still there up front.
+            let mut moved = ids.iter().all(|&id| source.get(id).is_ok());
+            for pair in ids.windows(2) {
+                if !moved {
+                    break;
+                }
+                let SwapResponse { error1, error2 } = server.swap(pair[0], pair[1])?;
+                moved = error1.is_none() && error2.is_none();
+            }
+            Ok(Some(Message::Reordered { id, moved }))
+        }
+        Command::MoveFavorite { id, direction } => {
+            source.refresh()?;
+            let favorites = source
+                .favorites()
+                .map(|entry| entry.id())
+                .collect::<Vec<_>>();
+            // Favorites are read oldest first, so the one above is the next one.
+            let neighbor = favorites
+                .iter()
+                .position(|&f| f == id)
+                .and_then(|i| match direction {
+                    MoveDirection::Up => favorites.get(i + 1).copied(),
+                    MoveDirection::Down => i.checked_sub(1).map(|i| favorites[i]),
+                });
+            let Some(neighbor) = neighbor else {
+                return Ok(Some(Message::Reordered { id, moved: false }));
+            };
+
+            let SwapResponse { error1, error2 } = server.swap(id, neighbor)?;
+            Ok(Some(Message::Reordered {
+                id: neighbor,
+                moved: error1.is_none() && error2.is_none(),
+            }))
+        }
+        Command::Search {
+            query,
+            kind: SearchKind::Tag,
+            ring,
+            ..
+        } => {
+            let tag = tags::parse(&query).ok_or(CommandError::InvalidTag)?;
+            filter_by_tag(source, &tag, ring, searches, send).map(Some)
+        }
+        Command::FilterByTag(tag) => filter_by_tag(source, &tag, None, searches, send).map(Some),
+        Command::Search {
+            query,
+            kind,
+            case_sensitive,
+            ring,
+            mime_filter,
+            favorites_first,
+        } => {
+            let (query, from) = match kind {
+                SearchKind::Plain => take_origin_filter(query),
+                SearchKind::Regex | SearchKind::Mime | SearchKind::Tag => (query, None),
+            };
+            let query = search_query(&query, kind, case_sensitive)?;
+            *searches += 1;
+            let generation = *searches;
+            let batch = source.search(
+                query,
+                ring,
+                mime_filter,
+                from.as_deref(),
+                favorites_first,
+                generation,
+                send,
+            )?;
+            Ok(Some(Message::SearchResults {
+                generation,
+                batch: batch.into(),
+                done: true,
+            }))
+        }
+        Command::FindSimilar(id) => {
+            let loaded = source.get(id).and_then(|entry| source.to_slice(entry))?;
+            let query = if loaded.mime_type()?.starts_with("image/") {
+                None
+            } else {
+                str::from_utf8(&loaded).ok().and_then(similar_entries_query)
+            }
+            .ok_or(CommandError::NothingToMatch)?;
+            let _ = send(Message::FindingSimilar {
+                id,
+                query: query.as_str().into(),
+            });
+
+            let without_source = |batch: Box<[UiEntry]>| {
+                let mut batch = batch.into_vec();
+                batch.retain(|e| e.entry.id() != id);
+                batch.into_boxed_slice()
+            };
+            *searches += 1;
+            let generation = *searches;
+            let batch = source.search(
+                search_query(&query, SearchKind::Plain, false)?,
+                None,
+                None,
+                None,
+                false,
+                generation,
+                |message| {
+                    send(match message {
+                        Message::SearchResults {
+                            generation,
+                            batch,
+                            done,
+                        } => Message::SearchResults {
+                            generation,
+                            batch: without_source(batch),
+                            done,
+                        },
+                        message => message,
+                    })
+                },
+            )?;
+            Ok(Some(Message::SearchResults {
+                generation,
+                batch: without_source(batch.into()),
+                done: true,
+            }))
+        }
+        Command::LoadImage { id, max_size } => {
+            let token = CancellationToken::new();
+            let _ = send(Message::PendingImage {
+                id,
+                token: token.clone(),
+            });
+            let mut run = || -> Result<_, CommandError> {
+                let entry = source.get(id)?;
+                let mut contents = source.to_reader(entry)?;
+                let len = match &*contents {
+                    FileOrSlice::File(file) => file.metadata().map_or(0, |m| m.len()),
+                    FileOrSlice::Slice(slice) => u64::try_from(slice.get_ref().len()).unwrap(),
+                };
+                let reader = ProgressReader {
+                    inner: &mut *contents,
+                    id,
+                    pos: 0,
+                    len,
+                    reported: 0,
+                    token: &token,
+                    send: &mut send,
+                };
+                let image = ImageReader::new(BufReader::new(reader))
+                    .with_guessed_format()
+                    .map_io_err(|| Context::Entry {
+                        message: "Failed to guess image format for entry",
+                        id,
+                    })?;
+                let format = image.format();
+                let mut image = image.decode()?;
+
+                #[cfg(feature = "exif")]
+                let Exif { taken, camera } = if format == Some(ImageFormat::Jpeg) {
+                    // The metadata is nice to have, so don't fail the load over it.
+                    match &*contents {
+                        FileOrSlice::File(file) => Mmap::from(file)
+                            .map_or_else(|_| Exif::default(), |data| exif::parse_jpeg(&data)),
+                        FileOrSlice::Slice(slice) => exif::parse_jpeg(slice.get_ref()),
+                    }
+                } else {
+                    Exif::default()
+                };
+                #[cfg(not(feature = "exif"))]
+                let (taken, camera) = (None, None);
+
+                let metadata = ImageMetadata {
+                    width: image.width(),
+                    height: image.height(),
+                    format,
+                    len,
+                    taken,
+                    camera,
+                };
+                if let Some((width, height)) = max_size {
+                    let oversized = image.width() > width || image.height() > height;
+                    if oversized && !token.is_cancelled() {
+                        image = image.resize(width, height, FilterType::Triangle);
+                    }
+                }
+                Ok((image, metadata))
+            };
+            Ok(match run() {
+                _ if token.is_cancelled() => None,
+                Ok((image, metadata)) => Some(Message::LoadedImage {
+                    id,
+                    image,
+                    metadata,
+                }),
+                Err(error) => Some(Message::ImageFailed { id, error }),
+            })
+        }
+        Command::LoadThumbnail(id) => {
+            let mut run = || -> Result<_, CommandError> {
+                let entry = source.get(id)?;
+                let mut contents = source.to_reader(entry)?;
+                let image = ImageReader::new(BufReader::new(&mut *contents))
+                    .with_guessed_format()
+                    .map_io_err(|| Context::Entry {
+                        message: "Failed to guess image format for entry",
+                        id,
+                    })?
+                    .decode()?;
+                Ok(image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE))
+            };
+            Ok(Some(Message::LoadedThumbnail {
+                id,
+                image: run().ok(),
+            }))
+        }
+        Command::Paste(id) => {
+            let entry = source.get(id)?;
+            server.paste(source.to_file(entry)?)?;
+            // Only recency and the marker depend on these, so the paste still counts
+            // if the server can't be reached.
+            let mut new_id = id;
+            if entry.ring() == RingKind::Main {
+                if let Ok(MoveToFrontResponse::Success { id }) = server.move_to_front(id, None) {
+                    new_id = id;
+                }
+            }
+            let _ = server.set_clipboard(Some(new_id));
+            Ok(Some(Message::Pasted { id, new_id }))
+        }
+        Command::Copy(text) => {
+            server.paste(LoadedEntry::new(bytes_to_file(text.as_bytes())?, None))?;
+            Ok(Some(Message::Copied))
+        }
+        Command::Yank(id) => {
+            let entry = source.get(id)?;
+            clipboard::copy(source.to_reader(entry)?)?;
+            Ok(Some(Message::Yanked(id)))
+        }
+        Command::GetServerInfo => Ok(Some(Message::ServerInfo(server.server_info()?))),
+        Command::GetStats => Ok(Some(Message::Stats(Box::new(server.stats()?)))),
+        Command::SetLabel { id, label } => match server.set_label(id, label)? {
+            SetLabelResponse { error: None } => Ok(Some(Message::Labeled(id))),
+            SetLabelResponse { error: Some(e) } => Err(e.into()),
+        },
+        Command::Tag { id, tag } => tagged(id, server.add_tag(id, tag)?).map(Some),
+        Command::Untag { id, tag } => tagged(id, server.remove_tag(id, tag)?).map(Some),
+        Command::Replace { id, text } => {
+            let entry = source.get(id)?;
+            let mime_type = source.mime_type(entry)?;
+            let label = source.to_slice(entry)?.label()?;
+
+            // The new contents are added as a fresh entry and swapped into the old
+            // entry's slot so it keeps its id and position.
+            let file = bytes_to_file(text.as_bytes())?;
+            let added = added_id(server.add(RingKind::Main, mime_type, file)?)?;
+            let SwapResponse { error1, error2 } = server.swap(id, added)?;
+            let swapped = error1.or(error2);
+            // Whether or not the swap went through, the entry in the added slot is
+            // no longer wanted.
+            if let RemoveResponse { error: Some(e) } = server.remove(added)? {
+                return Err(e.into());
+            }
+            if let Some(e) = swapped {
+                return Err(e.into());
+            }
+
+            // Labels live with an entry's data, so they don't survive the swap.
+            if !label.is_empty() {
+                if let SetLabelResponse { error: Some(e) } = server.set_label(id, label)? {
+                    return Err(e.into());
+                }
+            }
+            Ok(Some(Message::Replaced(id)))
+        }
+        Command::ReplaceEntry { id, data } => {
+            let entry = source.get(id)?;
+            let mime_type = source.mime_type(entry)?;
+            let label = source.to_slice(entry)?.label()?;
+
+            // Add before removing so a failed add leaves the original in place.
+            let file = bytes_to_file(&data)?;
+            let new_id = added_id(server.add(entry.ring(), mime_type, file)?)?;
+            // Unchanged contents are deduplicated back into the original entry.
+            if new_id != id {
+                if let RemoveResponse { error: Some(e) } = server.remove(id)? {
+                    return Err(e.into());
+                }
+            }
+            if !label.is_empty() {
+                if let SetLabelResponse { error: Some(e) } = server.set_label(new_id, label)? {
+                    return Err(e.into());
+                }
+            }
+            Ok(Some(Message::EntryReplaced { id, new_id }))
+        }
+        Command::ComputeDashboard => {
+            if let Some((_, cached)) = dashboard
+                .as_ref()
+                .filter(|(computed, _)| computed.elapsed() < DASHBOARD_CACHE_TIME)
+            {
+                return Ok(Some(Message::Dashboard(Box::new(cached.clone()))));
+            }
+
+            let token = CancellationToken::new();
+            let _ = send(Message::PendingDashboard(token.clone()));
+            let Some(computed) = compute_dashboard(source, &token, send)? else {
+                return Ok(None);
+            };
+            *dashboard = Some((Instant::now(), computed.clone()));
+            Ok(Some(Message::Dashboard(Box::new(computed))))
+        }
+        Command::OpenExternal(id) => {
+            let entry = source.get(id)?;
+            let mime_type = source.mime_type(entry)?;
+            open_external(id, &mime_type, source.to_file(entry)?)?;
+            Ok(None)
+        }
+    }
+}
+
+/// The directory [`Command::OpenExternal`] copies entries into for this
+/// process.
+fn opened_files_dir() -> PathBuf {
+    env::temp_dir().join(format!("ringboard-open-{}", process::id()))
+}
+
+/// Removes the temporary files written by [`Command::OpenExternal`].
+///
+/// Openers may read their file any time after starting, so clients should
+/// only call this on exit.
+pub fn remove_opened_files() {
+    let _ = fs::remove_dir_all(opened_files_dir());
+}
+
+fn open_external(id: u64, mime_type: &str, mut file: LoadedEntry<File>) -> Result<(), CoreError> {
+    // Anything longer is unlikely to be a URL anyone wants opened.
+    const MAX_URL_LEN: u64 = 4096;
+
+    let is_text = mime_type.is_empty() || mime_type.starts_with("text/plain");
+    let mut target = None;
+    if is_text {
+        let mut text = Vec::new();
+        (&*file)
+            .take(MAX_URL_LEN + 1)
+            .read_to_end(&mut text)
+            .map_io_err(|| Context::Entry {
+                message: "Failed to read entry",
+                id,
+            })?;
+        target = str::from_utf8(text.trim_ascii())
+            .ok()
+            .filter(|text| text_kind(text, line_count(text.as_bytes())) == Some(TextKind::Url))
+            .map(OsString::from);
+        file.rewind().map_io_err(|| Context::Entry {
+            message: "Failed to rewind entry",
+            id,
+        })?;
+    }
+
+    // Entries stored in their own file can be opened in place, unlike the
+    // in-memory copies of entries in buckets.
+    let target = target.or_else(|| {
+        fs::read_link(format!("/proc/self/fd/{}", file.as_raw_fd()))
+            .ok()
+            .filter(|path| path.is_absolute() && path.exists())
+            .map(OsString::from)
+    });
+    let target = if let Some(target) = target {
+        target
+    } else {
+        let dir = opened_files_dir();
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Err(e) if e.kind() != ErrorKind::AlreadyExists => {
+                return Err(e).map_io_err(|| Context::File {
+                    message: "Failed to create directory for opened entries",
+                    path: dir,
+                });
+            }
+            _ => {}
+        }
+        let path = dir.join(format!("{id}.{}", file_extension(mime_type)));
+        let mut copy = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)
+            .map_io_err(|| Context::File {
+                message: "Failed to create file for opened entry",
+                path: path.clone(),
+            })?;
+        io::copy(&mut *file, &mut copy).map_io_err(|| Context::File {
+            message: "Failed to copy entry to file",
+            path: path.clone(),
+        })?;
+        path.into()
+    };
+
+    let opener = env::var("RINGBOARD_OPENER")
+        .ok()
+        .filter(|opener| !opener.trim().is_empty())
+        .unwrap_or_else(|| "xdg-open".into());
+    let mut args = opener.split_whitespace();
+    let program = args.next().unwrap();
+    let mut child = process::Command::new(program)
+        .args(args)
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Keep terminal signals like Ctrl+C meant for the client away from the opener.
+        .process_group(0)
+        .spawn()
+        .map_io_err(|| format!("Failed to run opener {program:?}."))?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Guesses the file extension openers expect for a mime type.
+fn file_extension(mime_type: &str) -> &str {
+    let subtype = mime_type
+        .split_once('/')
+        .map_or("", |(_, subtype)| subtype)
+        .split(['+', ';'])
+        .next()
+        .unwrap_or_default();
+    let subtype = subtype.strip_prefix("x-").unwrap_or(subtype);
+    match subtype {
+        "" | "plain" => "txt",
+        "jpeg" => "jpg",
+        "javascript" => "js",
+        "markdown" => "md",
+        _ if subtype.bytes().all(|b| b.is_ascii_alphanumeric()) => subtype,
+        _ => "bin",
+    }
+}
+
+/// Scans every entry for the dashboard, sending what was found so far every
+/// so often. Returns [`None`] if cancelled.
+fn compute_dashboard<E>(
+    source: &mut impl EntrySource,
+    token: &CancellationToken,
+    mut send: impl FnMut(Message) -> Result<(), E>,
+) -> Result<Option<Dashboard>, CommandError> {
+    const PROGRESS_INTERVAL: u32 = 1024;
+    const TOP_MIME_TYPES: usize = 8;
+    const LARGEST_ENTRIES: usize = 10;
+    const DAY: u64 = 24 * 60 * 60;
+
+    source.refresh()?;
+    // The entries as of now, so ones added while scanning aren't half counted.
+    let entries = source.favorites().chain(source.main()).collect::<Vec<_>>();
+    let now = SystemTime::now();
+
+    let mut dashboard = Dashboard {
+        total: u32::try_from(entries.len()).unwrap(),
+        ..Dashboard::default()
+    };
+    let mut mime_types = HashMap::<Box<str>, (u32, u64)>::new();
+    let mut contents = HashSet::<u64, BuildHasherDefault<FxHasher>>::default();
+    let mut largest = BinaryHeap::<Reverse<(u64, u64)>>::new();
+    let summarize = |dashboard: &mut Dashboard,
+                     mime_types: &HashMap<Box<str>, (u32, u64)>,
+                     largest: &BinaryHeap<Reverse<(u64, u64)>>| {
+        let mut top = mime_types
+            .iter()
+            .map(|(mime_type, &(count, bytes))| MimeTypeStats {
+                mime_type: mime_type.clone(),
+                count,
+                bytes,
+            })
+            .collect::<Vec<_>>();
+        top.sort_unstable_by(|a, b| {
+            (b.count, b.bytes, &a.mime_type).cmp(&(a.count, a.bytes, &b.mime_type))
+        });
+        top.truncate(TOP_MIME_TYPES);
+        dashboard.mime_types = top;
+        let mut biggest = largest
+            .iter()
+            .map(|&Reverse(entry)| entry)
+            .collect::<Vec<_>>();
+        biggest.sort_unstable_by(|a, b| b.cmp(a));
+        dashboard.largest = biggest
+            .into_iter()
+            .map(|(len, id)| LargeEntry { id, len })
+            .collect();
+    };
+
+    for entry in entries {
+        if token.is_cancelled() {
+            return Ok(None);
+        }
+
+        if let Some(time) = source.timestamp(entry) {
+            // Clocks can go backwards, so entries from the future were added today.
+            let age = now.duration_since(time).unwrap_or_default().as_secs() / DAY;
+            if let Some(day) = usize::try_from(age)
+                .ok()
+                .and_then(|age| dashboard.adds_per_day.get_mut(age))
+            {
+                *day += 1;
+            }
+        }
+        // Entries that can't be read are still counted as scanned.
+        if let Ok(loaded) = source.to_slice(entry) {
+            let mime_type = loaded.mime_type()?;
+            let len = u64::try_from(loaded.len()).unwrap();
+
+            let (count, bytes) = mime_types.entry((&*mime_type).into()).or_default();
+            *count += 1;
+            *bytes += len;
+
+            let mut hasher = FxHasher::default();
+            mime_type.hash(&mut hasher);
+            loaded[..].hash(&mut hasher);
+            if !contents.insert(hasher.finish()) {
+                dashboard.duplicates += 1;
+            }
+
+            largest.push(Reverse((len, entry.id())));
+            if largest.len() > LARGEST_ENTRIES {
+                largest.pop();
+            }
+        }
+
+        dashboard.scanned += 1;
+        if dashboard.scanned.is_multiple_of(PROGRESS_INTERVAL) && !dashboard.is_complete() {
+            summarize(&mut dashboard, &mime_types, &largest);
+            let _ = send(Message::Dashboard(Box::new(dashboard.clone())));
+        }
+    }
+    summarize(&mut dashboard, &mime_types, &largest);
+    Ok(Some(dashboard))
+}
+
+/// Reports how far into an image's data the decoder has read and stops it
+/// once the load is cancelled.
+struct ProgressReader<'a, R, F> {
+    inner: R,
+    id: u64,
+    pos: u64,
+    len: u64,
+    reported: u8,
+    token: &'a CancellationToken,
+    send: F,
+}
+
+impl<R: Read, E, F: FnMut(Message) -> Result<(), E>> Read for ProgressReader<'_, R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.token.is_cancelled() {
+            return Err(io::Error::other("Image load cancelled."));
+        }
+
+        let n = self.inner.read(buf)?;
+        self.pos += u64::try_from(n).unwrap();
+        if let Some(pct) = (self.pos.min(self.len) * 100).checked_div(self.len) {
+            let pct = u8::try_from(pct).unwrap();
+            if pct > self.reported {
+                self.reported = pct;
+                let _ = (self.send)(Message::ImageProgress { id: self.id, pct });
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Seek, F> Seek for ProgressReader<'_, R, F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+/// Whether a command queued behind `command` makes its reply moot: UIs only
+/// show the details and image of the entry they have selected.
+fn superseded(command: &Command, queued: &VecDeque<Command>) -> bool {
+    match command {
+        Command::GetDetails { .. } => queued
+            .iter()
+            .any(|c| matches!(c, Command::GetDetails { .. })),
+        Command::LoadImage { .. } => queued
+            .iter()
+            .any(|c| matches!(c, Command::LoadImage { .. })),
+        _ => false,
+    }
+}
+
+/// The ring write heads and lengths along with the database generation.
+type DbVersion = ([(u32, u32); 2], Option<u64>);
+
+fn db_version(source: &mut impl EntrySource) -> Result<DbVersion, CoreError> {
+    // Read the generation first so a concurrent mutation is caught next time.
+    let generation = source.generation();
+    Ok((source.ring_heads()?, generation))
+}
+
+fn load_first_page(
+    source: &mut impl EntrySource,
+    server: &mut impl ServerConnection,
+    paged_in: usize,
+) -> Result<Message, CommandError> {
+    source.refresh()?;
+
+    let first_page = source
+        .favorites()
+        .rev()
+        .chain(source.main().rev().take(100 + paged_in))
+        .collect::<Vec<_>>();
+    source.prefetch(&first_page);
+    let entries = first_page
+        .into_iter()
+        .map(|entry| load_ui_entry(source, entry))
+        .collect::<Vec<_>>();
+    Ok(Message::LoadedFirstPage {
+        entries: entries.into(),
+        default_focused_id: {
+            let mut main = source.main().rev();
+            let first = main.next();
+            main.next()
+                .or(first)
+                .or_else(|| source.favorites().next_back())
+                .as_ref()
+                .map(Entry::id)
+        },
+        on_clipboard: server.current_clipboard().ok().flatten(),
+    })
+}
+
+/// Loads up to `count` main ring entries older than `after_id`, stopping early
+/// once the entry at index `through` is included.
+fn load_page(
+    source: &mut impl EntrySource,
+    after_id: u64,
+    count: usize,
+    through: Option<u32>,
+) -> Result<Vec<UiEntry>, CommandError> {
+    source.refresh()?;
+
+    let (ring, after) = decompose_id(after_id)?;
+    if ring != RingKind::Main {
+        return Err(IdNotFoundError::Ring(ring as u32).into());
+    }
+    let [_, (head, len)] = source.ring_heads()?;
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    // How many writes ago an entry was added, which still orders entries if
+    // `after_id` has been deleted since. Iteration stops at the oldest entry
+    // rather than wrapping back around to the write head.
+    let age = |index: u32| (head + len - 1 - index % len) % len;
+    let page = source
+        .main()
+        .rev()
+        .skip_while(|entry| age(entry.index()) <= age(after))
+        .take_while(|entry| through.is_none_or(|through| age(entry.index()) <= age(through)))
+        .take(count)
+        .collect::<Vec<_>>();
+    source.prefetch(&page);
+    Ok(page
+        .into_iter()
+        .map(|entry| load_ui_entry(source, entry))
+        .collect())
+}
+
+fn tagged(id: u64, response: TagResponse) -> Result<Message, CommandError> {
+    match response {
+        TagResponse::Success { .. } => Ok(Message::Tagged(id)),
+        TagResponse::Error(e) => Err(e.into()),
+        TagResponse::InvalidTag => Err(CommandError::InvalidTag),
+        TagResponse::TooManyTags => Err(CommandError::TooManyTags),
+    }
+}
+
+/// Replies to a tag filter like a search that finds everything at once.
+fn filter_by_tag<E>(
+    source: &mut impl EntrySource,
+    tag: &str,
+    ring: Option<RingKind>,
+    searches: &mut u64,
+    mut send: impl FnMut(Message) -> Result<(), E>,
+) -> Result<Message, CommandError> {
+    *searches += 1;
+    let generation = *searches;
+    let _ = send(Message::PendingSearch {
+        generation,
+        token: CancellationToken::new(),
+    });
+
+    source.refresh()?;
+    let candidates = source
+        .favorites()
+        .rev()
+        .chain(source.main().rev())
+        .filter(|entry| ring.is_none_or(|ring| entry.ring() == ring))
+        .collect::<Vec<_>>();
+    let mut batch = Vec::new();
+    for entry in candidates {
+        // Entries that can't be read are left out like unreadable search matches.
+        if source.tags(entry).is_ok_and(|tags| tags.contains(tag)) {
+            batch.push(load_ui_entry(source, entry));
+        }
+    }
+    Ok(Message::SearchResults {
+        generation,
+        batch: batch.into(),
+        done: true,
+    })
+}
+
+fn added_id(response: AddResponse) -> Result<u64, CommandError> {
+    match response {
+        AddResponse::Success { id, .. } => Ok(id),
+        AddResponse::DatabaseFull => Err(ClientError::DatabaseFull.into()),
+        AddResponse::UnsupportedData => Err(ClientError::UnsupportedData.into()),
+        AddResponse::Empty => Err(ClientError::EmptyEntry.into()),
+        AddResponse::TooLarge { max } => Err(ClientError::EntryTooLarge { max }.into()),
+    }
+}
+
+/// Reads an image's dimensions and format from its header, along with a JPEG's
+/// EXIF data.
+fn image_header(data: &[u8]) -> Option<ImageMetadata> {
+    let reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?;
+    let format = reader.format();
+    let (width, height) = reader.into_dimensions().ok()?;
+
+    #[cfg(feature = "exif")]
+    let Exif { taken, camera } = if format == Some(ImageFormat::Jpeg) {
+        exif::parse_jpeg(data)
+    } else {
+        Exif::default()
+    };
+    #[cfg(not(feature = "exif"))]
+    let (taken, camera) = (None, None);
+
+    Some(ImageMetadata {
+        width,
+        height,
+        format,
+        len: u64::try_from(data.len()).unwrap(),
+        taken,
+        camera,
+    })
+}
+
+fn load_ui_entry(source: &mut impl EntrySource, entry: Entry) -> UiEntry {
+    let timestamp = source.timestamp(entry);
+    source
+        .to_slice(entry)
+        .and_then(|loaded| ui_entry(entry, &loaded, None, timestamp))
+        .unwrap_or_else(|e| UiEntry {
+            cache: UiEntryCache::Error(e),
+            entry,
+            badge: EntryBadge::None,
+            label: None,
+            len: 0,
+            fingerprint: 0,
+            timestamp,
+        })
+}
+
+/// Counts the entries written to and dropped from a ring between two
+/// `(write_head, len)` snapshots.
+fn ring_changes((head, len): (u32, u32), (new_head, new_len): (u32, u32)) -> (usize, usize) {
+    let grown = new_len.saturating_sub(len);
+    let written = if new_head >= head {
+        new_head - head
+    } else {
+        new_len.saturating_sub(head) + new_head
+    };
+    // Writes that didn't extend the ring overwrote existing entries.
+    let removed = written.saturating_sub(grown);
+    (
+        usize::try_from(written).unwrap(),
+        usize::try_from(removed).unwrap(),
+    )
+}
+
+/// Builds the list view of an entry, pointing out where `matched` ended up in
+/// its one-liner.
+pub(crate) fn ui_entry(
+    entry: Entry,
+    loaded: &LoadedEntry<MmapOrSlice>,
+    matched: Option<Range<usize>>,
+    timestamp: Option<SystemTime>,
+) -> Result<UiEntry, CoreError> {
+    let mime_type = loaded.mime_type()?;
+    let sensitive = loaded.expires_at()?.is_some();
+    let len = u64::try_from(loaded.len()).unwrap();
+    let fingerprint = fingerprint(entry, &mime_type, loaded);
+    let label = {
+        let label = loaded.label()?;
+        (!label.is_empty()).then(|| label.as_str().into())
+    };
+    let cache = if sensitive {
+        UiEntryCache::Sensitive
+    } else {
+        match Contents::classify(entry, loaded, mime_type, matched) {
+            Contents::Text { one_liner, matched } => {
+                let lines = line_count(loaded);
+                UiEntryCache::Text {
+                    highlight: text_kind(&one_liner, lines),
+                    one_liner: one_liner.into(),
+                    matched,
+                    lines,
+                }
+            }
+            Contents::Image { .. } => UiEntryCache::Image,
+            Contents::Binary { mime_type } => UiEntryCache::Binary {
+                mime_type: mime_type.as_str().into(),
+            },
+        }
+    };
+    let badge = match &cache {
+        // Badges are sniffed from the data, which would give sensitive entries away.
+        UiEntryCache::Sensitive => EntryBadge::None,
+        // Including images sniffed from entries without a mime type.
+        UiEntryCache::Image => EntryBadge::Image,
+        UiEntryCache::Binary { mime_type } => entry_badge(mime_type, loaded),
+        UiEntryCache::Text { .. } | UiEntryCache::Error(_) => entry_badge(&mime_type, loaded),
+    };
+    Ok(UiEntry {
+        entry,
+        cache,
+        badge,
+        label,
+        len,
+        fingerprint,
+        timestamp,
+    })
+}
+
+fn fingerprint(entry: Entry, mime_type: &str, data: &[u8]) -> u64 {
+    const MAX_HASHED_LEN: usize = 4096;
+
+    let mut hasher = FxHasher::default();
+    if let Kind::Bucket(slot) = entry.kind() {
+        slot.hash(&mut hasher);
+    }
+    mime_type.hash(&mut hasher);
+    data.len().hash(&mut hasher);
+    data[..min(data.len(), MAX_HASHED_LEN)].hash(&mut hasher);
+    hasher.finish()
+}
+
+fn entry_badge(mime_type: &str, data: &[u8]) -> EntryBadge {
+    // Only sniff a bounded prefix so huge entries don't slow down list loading.
+    const MAX_SNIFF_LEN: usize = 256;
+
+    if mime_type.starts_with("image/") {
+        return EntryBadge::Image;
+    }
+    if mime_type == "application/pdf"
+        || mime_type == "application/msword"
+        || mime_type.ends_with("/rtf")
+        || mime_type.starts_with("application/vnd.oasis.opendocument")
+        || mime_type.starts_with("application/vnd.openxmlformats-officedocument")
+    {
+        return EntryBadge::Document;
+    }
+    if mime_type == "application/json"
+        || mime_type == "application/javascript"
+        || mime_type == "application/xml"
+        || mime_type.starts_with("text/x-")
+    {
+        return EntryBadge::Code;
+    }
+
+    let prefix = data[..min(data.len(), MAX_SNIFF_LEN)].trim_ascii();
+    if (prefix.starts_with(b"https://") || prefix.starts_with(b"http://"))
+        && !prefix.iter().any(u8::is_ascii_whitespace)
+    {
+        return EntryBadge::Url;
+    }
+    match (prefix.first(), data.trim_ascii_end().last()) {
+        (Some(b'{'), Some(b'}')) | (Some(b'['), Some(b']')) => EntryBadge::Code,
+        _ => EntryBadge::None,
+    }
+}
+
+/// Pulls a `from:name` filter out of a plain search query, leaving the rest
+/// of the query to match entries against.
+fn take_origin_filter(query: Box<str>) -> (Box<str>, Option<Box<str>>) {
+    let is_filter = |word: &str| {
+        word.strip_prefix("from:")
+            .is_some_and(|name| !name.is_empty())
+    };
+    let Some(filter) = query.split(' ').find(|word| is_filter(word)) else {
+        return (query, None);
+    };
+    let from = filter.strip_prefix("from:").map(Box::from);
+    let rest = query
+        .split(' ')
+        .filter(|word| !is_filter(word))
+        .collect::<Vec<_>>()
+        .join(" ");
+    (rest.into(), from)
+}
+
+fn search_query(
+    query: &str,
+    kind: SearchKind,
+    case_sensitive: bool,
+) -> Result<Query<'_>, regex::Error> {
+    Ok(match kind {
+        SearchKind::Plain if case_sensitive => Query::Plain(query.trim().as_bytes()),
+        SearchKind::Plain => Query::PlainIgnoreCase(CaselessQuery::new(query).trim()),
+        SearchKind::Regex => Query::Regex(Regex::new(query)?),
+        SearchKind::Mime => Query::Mimes(Regex::new(query)?),
+        SearchKind::Tag => unreachable!("tag filters aren't text searches"),
+    })
+}
+
+/// Finds everything a [`Command::Search`] with the same parameters would match
+/// in an entry's text, e.g. to highlight it in the entry's details.
+///
+/// Matches are snapped outwards to character boundaries. Invalid queries, mime
+/// searches and tag filters match nothing.
+#[must_use]
+pub fn search_matches(
+    query: &str,
+    kind: SearchKind,
+    case_sensitive: bool,
+    text: &str,
+) -> Vec<Range<usize>> {
+    let (query, _) = match kind {
+        SearchKind::Plain => take_origin_filter(query.into()),
+        SearchKind::Regex | SearchKind::Mime => (query.into(), None),
+        SearchKind::Tag => return Vec::new(),
+    };
+    let Ok(query) = search_query(&query, kind, case_sensitive) else {
+        return Vec::new();
+    };
+    query
+        .find_all(text.as_bytes())
+        .into_iter()
+        .map(|(mut start, mut end)| {
+            while !text.is_char_boundary(start) {
+                start -= 1;
+            }
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            start..end
+        })
+        .collect()
+}
+
+/// The most characters [`similar_entries_query`] keeps.
+pub const SIMILAR_QUERY_LEN: usize = 64;
+
+/// What [`Command::FindSimilar`] searches for in an entry's text.
+///
+/// That's the text's longest line with runs of whitespace collapsed, cut down
+/// to its first [`SIMILAR_QUERY_LEN`] characters. Blank text has nothing to
+/// match on.
+#[must_use]
+pub fn similar_entries_query(text: &str) -> Option<String> {
+    // Reversed so the first of equally long lines wins.
+    let line = text.lines().rev().max_by_key(|line| line.trim().len())?;
+    let mut query = line
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(SIMILAR_QUERY_LEN)
+        .collect::<String>();
+    query.truncate(query.trim_end().len());
+    (!query.is_empty()).then_some(query)
+}
+
+fn do_search<E>(
+    query: Query,
+    ring: Option<RingKind>,
+    mime_filter: Option<MimeType>,
+    from: Option<&str>,
+    favorites_first: bool,
+    generation: u64,
+    reader_: &mut Option<EntryReader>,
+    database: &mut DatabaseReader,
+    mut send: impl FnMut(Message) -> Result<(), E>,
+    (entry_index, pending): &mut SearchCache,
+) -> Vec<UiEntry> {
+    const MAX_SEARCH_ENTRIES: usize = 256;
+    const BATCH_SIZE: usize = 50;
+    const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+    let reader = Arc::new(reader_.take().unwrap());
+
+    Arc::get_mut(entry_index).unwrap().refresh(database);
+    let (mut result_stream, threads) = search(
+        query,
+        ring,
+        mime_filter,
+        reader.clone(),
+        entry_index.clone(),
+    );
+    let token = result_stream.cancellation_token().clone();
+    let _ = send(Message::PendingSearch {
+        generation,
+        token: token.clone(),
+    });
+
+    let mut matches = SearchMatches::default();
+    let write_heads: [_; 2] = array::from_fn(|i| {
+        let ring = if i == RingKind::Main as usize {
+            database.main()
+        } else if i == RingKind::Favorites as usize {
+            database.favorites()
+        } else {
+            unreachable!()
+        };
+        let ring = ring.ring();
+        ring.prev_entry(ring.write_head())
+    });
+    let mut found = 0;
+    let mut batch_started = Instant::now();
+    macro_rules! send_batch {
+        () => {
+            // Batches of a cancelled search would mix with the next search's results.
+            if !pending.is_empty() && !token.is_cancelled() {
+                let batch = load_search_results(
+                    pending,
+                    favorites_first,
+                    write_heads,
+                    database,
+                    &reader,
+                    &matches,
+                );
+                let _ = send(Message::SearchResults {
+                    generation,
+                    batch: batch.into(),
+                    done: false,
+                });
+            }
+            batch_started = Instant::now();
+        };
+    }
+    loop {
+        let q = match result_stream.next_until(batch_started + BATCH_INTERVAL) {
+            Ok(Ok(q)) => q,
+            Ok(Err(_)) => continue,
+            Err(RecvTimeoutError::Timeout) => {
+                send_batch!();
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match q.location() {
+            EntryLocation::ImageText { .. } => matches.image_text.push(q.id),
+            EntryLocation::Bucketed { .. }
+            | EntryLocation::Packed { .. }
+            | EntryLocation::File { .. } => {
+                matches.content.insert(q.id, q.start..q.end);
+            }
+            EntryLocation::Label { .. } => {}
+        }
+        let Ok(entry) = RingAndIndex::from_id(q.id) else {
+            continue;
+        };
+        if from.is_some_and(|from| {
+            reader
+                .added_by(entry.ring(), entry.index())
+                .is_none_or(|origin| !origin.name().eq_ignore_ascii_case(from))
+        }) {
+            continue;
+        }
+        pending.push(RingAndIndex::new(
+            entry.ring(),
+            write_heads[entry.ring() as usize].wrapping_sub(entry.index()) & MAX_ENTRIES,
+        ));
+
+        found += 1;
+        if found == MAX_SEARCH_ENTRIES {
+            break;
+        }
+        if pending.len() == BATCH_SIZE {
+            send_batch!();
+        }
+    }
+    let cancelled = token.is_cancelled();
+    // Stops the search threads if there were more matches than we're showing.
+    drop(result_stream);
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+    let reader = reader_.insert(Arc::into_inner(reader).unwrap());
+
+    if cancelled {
+        pending.clear();
+        return Vec::new();
+    }
+    load_search_results(
+        pending,
+        favorites_first,
+        write_heads,
+        database,
+        reader,
+        &matches,
+    )
+}
+
+/// Where search results matched, for highlighting and badges.
+#[derive(Default)]
+struct SearchMatches {
+    image_text: Vec<u64>,
+    content: HashMap<u64, Range<usize>, BuildHasherDefault<FxHasher>>,
+}
+
+/// Loads the entries found since the last batch, newest first.
+fn load_search_results(
+    pending: &mut Vec<RingAndIndex>,
+    favorites_first: bool,
+    write_heads: [u32; 2],
+    database: &mut DatabaseReader,
+    reader: &EntryReader,
+    matches: &SearchMatches,
+) -> Vec<UiEntry> {
+    if favorites_first {
+        pending.sort_unstable_by_key(|&entry| (entry.ring() != RingKind::Favorites, entry));
+    } else {
+        pending.sort_unstable();
+    }
+    #[allow(clippy::iter_with_drain)] // https://github.com/rust-lang/rust-clippy/issues/8539
+    pending
+        .drain(..)
+        .flat_map(|entry| {
+            let ring = entry.ring();
+            let index = write_heads[ring as usize].wrapping_sub(entry.index()) & MAX_ENTRIES;
 
             let id = composite_id(ring, index);
             unsafe { database.get(id) }
         })
         .map(|entry| {
-            // TODO add support for bold highlighting the selection range
-            ui_entry(entry, reader).unwrap_or_else(|e| UiEntry {
-                cache: UiEntryCache::Error(e),
-                entry,
-            })
+            let timestamp = entry.timestamp(reader);
+            entry
+                .to_slice(reader)
+                .and_then(|loaded| {
+                    ui_entry(
+                        entry,
+                        &loaded,
+                        matches.content.get(&entry.id()).cloned(),
+                        timestamp,
+                    )
+                })
+                .map_or_else(
+                    |e| UiEntry {
+                        cache: UiEntryCache::Error(e),
+                        entry,
+                        badge: EntryBadge::None,
+                        label: None,
+                        len: 0,
+                        fingerprint: 0,
+                        timestamp,
+                    },
+                    |mut ui_entry| {
+                        if matches.image_text.contains(&entry.id()) {
+                            ui_entry.badge = EntryBadge::ImageText;
+                        }
+                        ui_entry
+                    },
+                )
         })
-        .collect();
-    *search_result_buf = results;
-    entries
+        .collect()
 }
 
 fn send_paste_buffer(
     server: impl AsFd,
     addr: &SocketAddrUnix,
-    entry: Entry,
-    reader: &mut EntryReader,
+    file: &LoadedEntry<File>,
 ) -> ringboard_core::Result<()> {
-    let file = entry.to_file(reader)?;
     let mime = file.mime_type()?;
 
     let mut space = [0; rustix::cmsg_space!(ScmRights(1))];
@@ -541,3 +2542,55 @@ fn send_paste_buffer(
     .map_io_err(|| format!("Failed to send paste entry to paste server at {addr:?}."))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{entry_badge, EntryBadge};
+
+    #[test]
+    fn typed_entries_are_badged_by_mime_type() {
+        assert_eq!(entry_badge("image/png", b""), EntryBadge::Image);
+        assert_eq!(
+            entry_badge("application/pdf", b"%PDF-1.7"),
+            EntryBadge::Document
+        );
+        assert_eq!(
+            entry_badge(
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                b""
+            ),
+            EntryBadge::Document
+        );
+        // The mime type wins over contents that look like code.
+        assert_eq!(
+            entry_badge("text/rtf", br"{\rtf1 hi}"),
+            EntryBadge::Document
+        );
+        assert_eq!(entry_badge("application/json", b"1"), EntryBadge::Code);
+        assert_eq!(
+            entry_badge("text/x-rust", b"fn main() {}"),
+            EntryBadge::Code
+        );
+        assert_eq!(entry_badge("text/html", b"<p>hi</p>"), EntryBadge::None);
+    }
+
+    #[test]
+    fn untyped_entries_are_sniffed() {
+        assert_eq!(
+            entry_badge("", b"  https://example.com/a?b=c\n"),
+            EntryBadge::Url
+        );
+        assert_eq!(
+            entry_badge("", b"http://example.com and more"),
+            EntryBadge::None
+        );
+        assert_eq!(entry_badge("", b"ftp://example.com"), EntryBadge::None);
+        assert_eq!(entry_badge("", b"{\"a\": [1, 2]}\n"), EntryBadge::Code);
+        // Only the start is sniffed, but the end is always looked at.
+        let long = format!("[{}0]", "1, ".repeat(200));
+        assert_eq!(entry_badge("", long.as_bytes()), EntryBadge::Code);
+        assert_eq!(entry_badge("", b"[1, 2"), EntryBadge::None);
+        assert_eq!(entry_badge("", b"plain text"), EntryBadge::None);
+        assert_eq!(entry_badge("", b""), EntryBadge::None);
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    io::ErrorKind,
+    os::fd::{AsFd, OwnedFd},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, TrySendError};
+use ringboard_core::{Context, IoErr};
+use rustix::{
+    event::{poll, PollFd, PollFlags},
+    fs::inotify::{inotify_add_watch, inotify_init, CreateFlags, WatchFlags},
+    io::{read, Errno},
+};
+
+/// Set on events for watches the kernel dropped, e.g. because the watched
+/// directory was deleted.
+const IN_IGNORED: u32 = 0x8000;
+
+/// How long to wait for more changes once the database starts changing. The
+/// server makes several writes per request, so this turns each request into a
+/// single notification.
+const SETTLE_TIME: Duration = Duration::from_millis(50);
+
+/// Watches a database for changes made by the server.
+///
+/// Directories are watched rather than individual files, so rings and buckets
+/// growing or being replaced doesn't lose the watch. Watches on the bucket and
+/// direct directories are re-armed if those directories are recreated.
+#[derive(Debug)]
+pub struct DatabaseWatcher {
+    inotify: OwnedFd,
+    /// The database directory followed by its `buckets` and `direct`
+    /// directories.
+    dirs: [PathBuf; 3],
+    /// The watch on each directory, missing while it doesn't exist.
+    watches: [Option<i32>; 3],
+    buf: Box<[u8]>,
+}
+
+impl DatabaseWatcher {
+    pub(crate) fn new(database_dir: &Path) -> Result<Self, ringboard_core::Error> {
+        let inotify = inotify_init(CreateFlags::CLOEXEC)
+            .map_io_err(|| "Failed to create inotify instance.")?;
+        let dirs = [
+            database_dir.to_path_buf(),
+            database_dir.join("buckets"),
+            database_dir.join("direct"),
+        ];
+
+        let mut me = Self {
+            inotify,
+            dirs,
+            watches: [None; 3],
+            buf: vec![0; 4096].into(),
+        };
+        for i in 0..me.dirs.len() {
+            me.watches[i] = Some(me.watch(i)?);
+        }
+        Ok(me)
+    }
+
+    fn watch(&self, index: usize) -> Result<i32, ringboard_core::Error> {
+        let path = &self.dirs[index];
+        inotify_add_watch(
+            self.inotify.as_fd(),
+            path,
+            WatchFlags::MODIFY
+                | WatchFlags::ATTRIB
+                | WatchFlags::CREATE
+                | WatchFlags::DELETE
+                | WatchFlags::MOVED_FROM
+                | WatchFlags::MOVED_TO
+                | WatchFlags::ONLYDIR,
+        )
+        .map_io_err(|| Context::File {
+            message: "Failed to watch database directory",
+            path: path.clone(),
+        })
+    }
+
+    /// Blocks until the database changes and the burst of changes settles.
+    ///
+    /// Fails if the database directory itself goes away.
+    pub fn wait(&mut self) -> Result<(), ringboard_core::Error> {
+        let mut changed = false;
+        loop {
+            let timeout = if changed {
+                i32::try_from(SETTLE_TIME.as_millis()).unwrap()
+            } else {
+                -1
+            };
+            let ready = match poll(&mut [PollFd::new(&self.inotify, PollFlags::IN)], timeout) {
+                Err(Errno::INTR) => continue,
+                r => r.map_io_err(|| "Failed to wait for database changes.")?,
+            };
+            if ready == 0 {
+                return Ok(());
+            }
+
+            let len = match read(&self.inotify, &mut self.buf) {
+                Err(Errno::INTR) => continue,
+                r => r.map_io_err(|| "Failed to read database changes.")?,
+            };
+            changed = true;
+            self.forget_dropped_watches(len);
+            self.rearm()?;
+        }
+    }
+
+    /// Returns a channel that receives a message whenever the database
+    /// changes, watching from a background thread.
+    ///
+    /// Changes made while a message is waiting to be received are folded into
+    /// it. The thread stops once the receiver is dropped and the database next
+    /// changes, or if watching fails.
+    #[must_use]
+    pub fn spawn(mut self) -> Receiver<()> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        thread::spawn(move || {
+            while self.wait().is_ok() {
+                if sender.try_send(()) == Err(TrySendError::Disconnected(())) {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+
+    fn forget_dropped_watches(&mut self, len: usize) {
+        // Each event is a wd, mask, cookie, and name length followed by the name.
+        const HEADER_LEN: usize = 4 * size_of::<u32>();
+
+        let mut events = &self.buf[..len];
+        while events.len() >= HEADER_LEN {
+            let field = |i: usize| {
+                let field = &events[i * size_of::<u32>()..][..size_of::<u32>()];
+                field.try_into().unwrap()
+            };
+            let (wd, mask, name_len) = (
+                i32::from_ne_bytes(field(0)),
+                u32::from_ne_bytes(field(1)),
+                u32::from_ne_bytes(field(3)),
+            );
+            if mask & IN_IGNORED != 0 {
+                for watch in &mut self.watches {
+                    if *watch == Some(wd) {
+                        *watch = None;
+                    }
+                }
+            }
+            events = events
+                .get(HEADER_LEN + usize::try_from(name_len).unwrap()..)
+                .unwrap_or_default();
+        }
+    }
+
+    /// Watches directories that were recreated. Subdirectories that don't
+    /// exist yet are picked up once they're created in the database directory.
+    fn rearm(&mut self) -> Result<(), ringboard_core::Error> {
+        for i in 0..self.dirs.len() {
+            if self.watches[i].is_some() {
+                continue;
+            }
+            match self.watch(i) {
+                Ok(watch) => self.watches[i] = Some(watch),
+                Err(ringboard_core::Error::Io { error, .. })
+                    if i > 0 && error.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+//! Wiping copies of clipboard contents from memory.
+//!
+//! With the `zeroize` feature, buffers the SDK copies entry contents into are
+//! overwritten before they're reused and once they're dropped:
+//! - [`RemoteReader`](crate::RemoteReader)'s read buffer,
+//! - the lowercased copies made by case-insensitive searches,
+//! - [`UiEntryCache::Text`](crate::ui_actor::UiEntryCache::Text) one-liners and
+//!   [`DetailedEntry`](crate::ui_actor::DetailedEntry) texts and previews,
+//! - [`LoadedEntry::into_zeroizing_vec`](crate::LoadedEntry::into_zeroizing_vec)
+//!   copies.
+//!
+//! Plaintext still lingers elsewhere:
+//! - Slices from [`EntryReader`](crate::EntryReader) point straight into the
+//!   mmapped database, which can't be wiped without destroying the entry.
+//! - Inline read responses pass through stack buffers and the socket.
+//! - Copies made by callers, e.g. text handed to widgets, are their own
+//!   responsibility.
+
+use std::ops::{Deref, DerefMut};
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// A reusable buffer for entry contents.
+#[derive(Clone, Default, Debug)]
+pub struct ContentBuf(Vec<u8>);
+
+impl ContentBuf {
+    /// Empties the buffer, wiping its whole allocation so growing it later
+    /// doesn't leave old contents behind either.
+    pub fn clear(&mut self) {
+        #[cfg(feature = "zeroize")]
+        self.0.zeroize();
+        #[cfg(not(feature = "zeroize"))]
+        self.0.clear();
+    }
+}
+
+impl Deref for ContentBuf {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for ContentBuf {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for ContentBuf {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Overwrites text that's about to be dropped.
+#[cfg(all(feature = "zeroize", feature = "ui"))]
+pub fn wipe_str(text: &mut str) {
+    // Zeroes are valid UTF-8.
+    unsafe { text.as_bytes_mut() }.zeroize();
+}
+
+/// Overwrites bytes that are about to be dropped.
+#[cfg(all(feature = "zeroize", feature = "ui"))]
+pub fn wipe_bytes(bytes: &mut [u8]) {
+    bytes.zeroize();
+}
+
+#[cfg(all(test, feature = "zeroize"))]
+mod tests {
+    use std::{
+        slice,
+        sync::atomic::{compiler_fence, Ordering},
+    };
+
+    use super::ContentBuf;
+
+    const CANARY: &[u8] = b"hunter2 hunter2 hunter2 hunter2";
+
+    #[test]
+    fn cleared_buffers_leave_no_canary() {
+        let mut buf = ContentBuf::default();
+        buf.extend_from_slice(CANARY);
+        buf.extend_from_slice(CANARY);
+        let (ptr, capacity) = (buf.as_ptr(), buf.capacity());
+
+        // Dropping runs the same wipe, but then the memory couldn't be looked at.
+        buf.clear();
+        compiler_fence(Ordering::SeqCst);
+
+        let allocation = unsafe { slice::from_raw_parts(ptr, capacity) };
+        assert!(allocation.iter().all(|&b| b == 0));
+        assert_eq!(buf.capacity(), capacity);
+    }
+}
@@ -0,0 +1,116 @@
+//! Copies entries through stand-ins for the display servers' clipboard tools.
+//!
+//! Everything happens in one test since the display server and tools are
+//! picked through environment variables.
+
+use std::{env, fs, os::unix::fs::PermissionsExt, path::Path, process};
+
+use clipboard_history_client_sdk::{
+    clipboard::{copy_entry, CopyError, DisplayServer},
+    core::protocol::{composite_id, RingKind},
+    testing::FixtureDatabase,
+    DatabaseReader, EntryReader,
+};
+
+/// Installs a clipboard tool that records its arguments and what it was given
+/// to copy in `out`, exiting with `status`.
+fn install_copier(bin: &Path, name: &str, out: &Path, status: u8) {
+    let script = bin.join(name);
+    fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$@\" > '{out}.args'\n/bin/cat > '{out}.data'\nexit \
+             {status}\n",
+            out = out.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn entries_are_piped_to_the_clipboard_tool() {
+    let dir = env::temp_dir().join(format!("ringboard-clipboard-{}", process::id()));
+    let database = FixtureDatabase::create(dir.join("clipboard-history"));
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    let out = dir.join("copied");
+    let copied = || {
+        (
+            fs::read_to_string(out.with_extension("args")).unwrap(),
+            fs::read(out.with_extension("data")).unwrap(),
+        )
+    };
+
+    let mut path = database.path().to_path_buf();
+    let rings = DatabaseReader::open(&mut path).unwrap();
+    let reader = EntryReader::open(&mut path).unwrap();
+    let text = rings.get_raw(composite_id(RingKind::Main, 1)).unwrap();
+    let image = rings.get_raw(composite_id(RingKind::Main, 2)).unwrap();
+
+    env::set_var("PATH", &bin);
+    env::remove_var("WAYLAND_DISPLAY");
+    env::remove_var("DISPLAY");
+    assert!(matches!(
+        copy_entry(&text, &reader),
+        Err(CopyError::NoDisplayServer)
+    ));
+
+    env::set_var("WAYLAND_DISPLAY", "wayland-0");
+    env::set_var("DISPLAY", ":0");
+    assert!(matches!(
+        copy_entry(&text, &reader),
+        Err(CopyError::NoCopier {
+            display_server: DisplayServer::Wayland
+        })
+    ));
+
+    install_copier(&bin, "wl-copy", &out, 0);
+    copy_entry(&text, &reader).unwrap();
+    assert_eq!(copied(), (String::from("\n"), b"hello world".to_vec()));
+    // Direct files are offered as their mime type.
+    copy_entry(&image, &reader).unwrap();
+    assert_eq!(
+        copied(),
+        (String::from("--type\nimage/png\n"), b"\x89PNG".to_vec())
+    );
+
+    install_copier(&bin, "wl-copy", &out, 1);
+    assert!(matches!(
+        copy_entry(&text, &reader),
+        Err(CopyError::Transfer {
+            copier: "wl-copy",
+            ..
+        })
+    ));
+
+    // xsel can't say what it's copying, so it's only used for text.
+    env::remove_var("WAYLAND_DISPLAY");
+    install_copier(&bin, "xsel", &out, 0);
+    copy_entry(&text, &reader).unwrap();
+    assert_eq!(
+        copied(),
+        (
+            String::from("--clipboard\n--input\n"),
+            b"hello world".to_vec()
+        )
+    );
+    assert!(matches!(
+        copy_entry(&image, &reader),
+        Err(CopyError::NoCopier {
+            display_server: DisplayServer::X11
+        })
+    ));
+
+    install_copier(&bin, "xclip", &out, 0);
+    copy_entry(&image, &reader).unwrap();
+    assert_eq!(
+        copied(),
+        (
+            String::from("-selection\nclipboard\n-t\nimage/png\n"),
+            b"\x89PNG".to_vec()
+        )
+    );
+
+    let _ = fs::remove_dir_all(dir);
+}
@@ -0,0 +1,108 @@
+//! Reads databases corrupted in random ways: readers must report errors
+//! instead of panicking or crashing.
+//!
+//! Set `RINGBOARD_FUZZ_ITERATIONS` to try more corruptions than the default
+//! and `RINGBOARD_FUZZ_SEED` to replay a single one.
+
+use std::{env, io, panic, panic::AssertUnwindSafe, process, sync::Arc};
+
+use clipboard_history_client_sdk::{
+    core::{
+        protocol::{composite_id, RingKind},
+        ring::Ring,
+    },
+    search,
+    search::{EntryIndex, Query},
+    testing::FixtureDatabase,
+    DatabaseReader, Entry, EntryReader, RingReader,
+};
+use regex::bytes::Regex;
+
+fn read_entry(entry: Entry, reader: &EntryReader) {
+    let _ = entry.mime_type(reader);
+    if let Ok(loaded) = entry.to_slice(reader) {
+        let _ = loaded.mime_type();
+        let _ = loaded.label();
+    }
+    let _ = entry.to_file(reader);
+    let _ = entry.write_to(reader, &mut io::sink());
+}
+
+fn read_ring(ring: &Ring, kind: RingKind, reader: &EntryReader) {
+    let len = usize::try_from(ring.len()).unwrap();
+    let oldest_first = RingReader::from_ring(ring, kind).collect::<Vec<_>>();
+    let newest_first = RingReader::from_ring(ring, kind).rev().count();
+    assert!(oldest_first.len() <= len);
+    assert!(newest_first <= len);
+    for entry in oldest_first {
+        read_entry(entry, reader);
+    }
+}
+
+fn read_everything(database: &FixtureDatabase) {
+    let mut dir = database.path().to_path_buf();
+    let (Ok(rings), Ok(reader)) = (DatabaseReader::open(&mut dir), EntryReader::open(&mut dir))
+    else {
+        return;
+    };
+
+    for kind in [RingKind::Main, RingKind::Favorites] {
+        let ring = match kind {
+            RingKind::Main => rings.main(),
+            RingKind::Favorites => rings.favorites(),
+        };
+        read_ring(ring.ring(), kind, &reader);
+        for index in 0..16 {
+            if let Ok(entry) = rings.get_raw(composite_id(kind, index)) {
+                read_entry(entry, &reader);
+            }
+        }
+    }
+
+    let index = Arc::new(EntryIndex::new(&rings));
+    let reader = Arc::new(reader);
+    for query in [
+        Query::Plain(b"hello"),
+        Query::Regex(Regex::new("c.t").unwrap()),
+        Query::Mimes(Regex::new("^(text|image)/").unwrap()),
+    ] {
+        let (results, threads) = search(query, None, None, reader.clone(), index.clone());
+        for result in results.flatten() {
+            let _ = rings.get_raw(result.id);
+        }
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
+
+#[test]
+fn corrupted_databases_never_panic() {
+    let mut database = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-corruption-{}", process::id())),
+    );
+    read_everything(&database);
+
+    let seeds = env::var("RINGBOARD_FUZZ_SEED").map_or_else(
+        |_| {
+            let iterations =
+                env::var("RINGBOARD_FUZZ_ITERATIONS").map_or(500, |n| n.parse().unwrap());
+            0..=iterations - 1
+        },
+        |seed| {
+            let seed = seed.parse().unwrap();
+            seed..=seed
+        },
+    );
+    for seed in seeds {
+        let path = database.path().to_path_buf();
+        drop(database);
+        database = FixtureDatabase::create(path);
+        database.corrupt(seed);
+        let read = panic::catch_unwind(AssertUnwindSafe(|| read_everything(&database)));
+        assert!(
+            read.is_ok(),
+            "Reading the database corrupted with seed {seed} panicked."
+        );
+    }
+}
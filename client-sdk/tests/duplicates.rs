@@ -0,0 +1,102 @@
+use std::{env, process};
+
+use clipboard_history_client_sdk::{
+    core::protocol::{composite_id, RingKind},
+    duplicate_detection::{find_duplicates, DuplicateDetector, Matching},
+    testing::FixtureDatabase,
+    DatabaseReader, Entry, EntryReader,
+};
+
+#[test]
+fn bulk_imports_find_existing_and_imported_entries() {
+    let fixture =
+        FixtureDatabase::create(env::temp_dir().join(format!("ringboard-bulk-{}", process::id())));
+    let mut dir = fixture.path().to_path_buf();
+    let mut database = DatabaseReader::open(&mut dir).unwrap();
+    let mut reader = EntryReader::open(&mut dir).unwrap();
+    let mut duplicates = DuplicateDetector::default();
+    let mut session = duplicates.begin_bulk(&mut database, &mut reader).unwrap();
+
+    assert_eq!(
+        session.check(b"hello world").unwrap(),
+        Some(composite_id(RingKind::Main, 1))
+    );
+    assert_eq!(
+        session.check(b"<html>hello</html>").unwrap(),
+        Some(composite_id(RingKind::Main, 5))
+    );
+    // Big entries are hashed by length, so the contents must be compared.
+    assert_eq!(
+        session.check(&[b'x'; 5000]).unwrap(),
+        Some(composite_id(RingKind::Favorites, 2))
+    );
+    assert_eq!(session.check(&[b'z'; 5000]).unwrap(), None);
+    assert_eq!(session.check(b"hello").unwrap(), None);
+
+    // Recorded entries only count as duplicates if what's in the database
+    // really matches.
+    session
+        .record(b"not abc", composite_id(RingKind::Main, 0))
+        .unwrap();
+    assert_eq!(session.check(b"not abc").unwrap(), None);
+    session.commit();
+}
+
+#[test]
+fn duplicates_are_confirmed_byte_for_byte() {
+    let fixture = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-duplicates-{}", process::id())),
+    );
+    // A favorite duplicating a bucketed entry and a big entry whose length
+    // collides with the other favorite's.
+    fixture.fill_hole(RingKind::Favorites, 1, b"hello world", "text/plain");
+    fixture.fill_hole(RingKind::Main, 3, &[b'z'; 5000], "text/plain");
+    let mut dir = fixture.path().to_path_buf();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+
+    let groups = find_duplicates(&reader, &database, Matching::Exact)
+        .unwrap()
+        .map(|group| group.entries.iter().map(Entry::id).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        groups,
+        [[
+            composite_id(RingKind::Favorites, 1),
+            composite_id(RingKind::Main, 1)
+        ]]
+    );
+}
+
+#[test]
+fn normalized_duplicates_ignore_case_and_whitespace() {
+    let fixture = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-normalized-duplicates-{}", process::id())),
+    );
+    fixture.fill_hole(RingKind::Favorites, 1, b"  Hello\n\tWORLD ", "text/plain");
+    fixture.fill_hole(RingKind::Main, 3, b"hello  there", "text/plain");
+    let mut dir = fixture.path().to_path_buf();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+    let groups = |matching| {
+        find_duplicates(&reader, &database, matching)
+            .unwrap()
+            .map(|group| group.entries.iter().map(Entry::id).collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+
+    assert!(groups(Matching::Exact).is_empty());
+    assert_eq!(
+        groups(Matching::Normalized),
+        [
+            [
+                composite_id(RingKind::Favorites, 1),
+                composite_id(RingKind::Main, 1)
+            ],
+            [
+                composite_id(RingKind::Main, 6),
+                composite_id(RingKind::Main, 3)
+            ]
+        ]
+    );
+}
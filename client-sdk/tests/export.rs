@@ -0,0 +1,59 @@
+use std::{env, process};
+
+use clipboard_history_client_sdk::{
+    core::protocol::RingKind,
+    export::{export, read, ExportedEntry},
+    testing::FixtureDatabase,
+    ClientError, DatabaseReader, EntryReader,
+};
+
+#[test]
+fn exports_read_back_every_entry() {
+    let fixture = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-export-{}", process::id())),
+    );
+    let mut dir = fixture.path().to_path_buf();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+
+    let mut out = Vec::new();
+    export(&database, &reader, &mut out).unwrap();
+    let text = String::from_utf8(out.clone()).unwrap();
+    assert!(text.starts_with("{\"format\":\"ringboard-export\",\"version\":1}\n"));
+    // The PNG isn't text, so it's encoded.
+    assert!(text.contains(r#""mime_type":"image/png","base64":"iVBORw==""#));
+
+    let imported = read(&*out)
+        .unwrap()
+        .collect::<Result<Vec<ExportedEntry>, _>>()
+        .unwrap();
+    let expected = database
+        .favorites()
+        .chain(database.main())
+        .collect::<Vec<_>>();
+    assert_eq!(imported.len(), expected.len());
+    assert_eq!(imported[0].ring, RingKind::Favorites);
+    for (imported, entry) in imported.iter().zip(expected) {
+        let loaded = entry.to_slice(&reader).unwrap();
+        assert_eq!(imported.id, entry.id());
+        assert_eq!(imported.ring, entry.ring());
+        assert_eq!(imported.mime_type, loaded.mime_type().unwrap());
+        assert_eq!(imported.data, &**loaded);
+    }
+}
+
+#[test]
+fn invalid_exports_are_rejected() {
+    let export = b"{\"format\":\"ringboard-export\",\"version\":2}\n";
+    assert!(matches!(
+        read(&export[..]).map(|_| ()),
+        Err(ClientError::UnsupportedExportVersion { version: 2 })
+    ));
+
+    let export = b"{\"format\":\"ringboard-export\",\"version\":1}\n{\"id\":1,\"ring\":\"main\"}\n";
+    let mut entries = read(&export[..]).unwrap();
+    assert!(matches!(
+        entries.next(),
+        Some(Err(ClientError::InvalidExport { line: 2, .. }))
+    ));
+}
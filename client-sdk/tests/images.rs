@@ -0,0 +1,101 @@
+//! Loads image entries the way UIs show them in full.
+
+use std::{env, io::Cursor, process};
+
+use clipboard_history_client_sdk::{
+    core::protocol::{composite_id, RingKind},
+    testing::{FixtureDatabase, MockDatabase},
+    ui_actor::{Command, Controller, LocalSource, Message},
+};
+use image::{codecs::gif::GifEncoder, Delay, Frame, GenericImageView, ImageFormat, Rgba, RgbaImage};
+
+fn run(controller: &mut Controller<LocalSource, MockDatabase>, command: Command) -> Vec<Message> {
+    let mut messages = Vec::new();
+    let reply = controller.handle(command, |message| {
+        messages.push(message);
+        Ok::<_, ()>(())
+    });
+    messages.extend(reply);
+    messages
+}
+
+#[test]
+fn images_are_described_and_shrunk_to_fit() {
+    let home = env::temp_dir().join(format!("ringboard-images-{}", process::id()));
+    let database = FixtureDatabase::create(home.join("clipboard-history"));
+    let mut png = Cursor::new(Vec::new());
+    RgbaImage::new(400, 300)
+        .write_to(&mut png, ImageFormat::Png)
+        .unwrap();
+    database.fill_hole(RingKind::Main, 3, png.get_ref(), "image/png");
+    let mut gif = Vec::new();
+    GifEncoder::new(&mut gif)
+        .encode_frames([[255, 0, 0, 255], [0, 0, 255, 255]].map(|color| {
+            Frame::from_parts(
+                RgbaImage::from_pixel(4, 2, Rgba(color)),
+                0,
+                0,
+                Delay::from_numer_denom_ms(100, 1),
+            )
+        }))
+        .unwrap();
+    database.fill_hole(RingKind::Favorites, 1, &gif, "image/gif");
+    env::set_var("XDG_DATA_HOME", &home);
+    let mut controller = Controller::new(LocalSource::open().unwrap(), MockDatabase::default());
+    let png_id = composite_id(RingKind::Main, 3);
+
+    let messages = run(
+        &mut controller,
+        Command::GetDetails {
+            id: png_id,
+            with_text: false,
+        },
+    );
+    let [
+        Message::EntryDetails {
+            result: Ok(details),
+            ..
+        },
+    ] = &messages[..]
+    else {
+        panic!("Failed to load details: {messages:?}");
+    };
+    let image = details.image.as_ref().unwrap();
+    assert_eq!(
+        (image.width, image.height, image.format),
+        (400, 300, Some(ImageFormat::Png))
+    );
+    assert_eq!(image.len, u64::try_from(png.get_ref().len()).unwrap());
+
+    for (max_size, size) in [(Some((100, 100)), (100, 75)), (None, (400, 300))] {
+        let messages = run(
+            &mut controller,
+            Command::LoadImage {
+                id: png_id,
+                max_size,
+            },
+        );
+        let Some(Message::LoadedImage {
+            image, metadata, ..
+        }) = messages.last()
+        else {
+            panic!("Failed to load image: {messages:?}");
+        };
+        assert_eq!(image.dimensions(), size);
+        assert_eq!((metadata.width, metadata.height), (400, 300));
+    }
+
+    // Animations show their first frame.
+    let messages = run(
+        &mut controller,
+        Command::LoadImage {
+            id: composite_id(RingKind::Favorites, 1),
+            max_size: None,
+        },
+    );
+    let Some(Message::LoadedImage { image, .. }) = messages.last() else {
+        panic!("Failed to load GIF: {messages:?}");
+    };
+    assert_eq!(image.dimensions(), (4, 2));
+    assert_eq!(image.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+}
@@ -0,0 +1,79 @@
+use std::{env, io::Cursor, process};
+
+use clipboard_history_client_sdk::{
+    core::protocol::{composite_id, RingKind},
+    preview::{preview, Preview, PreviewOptions},
+    testing::FixtureDatabase,
+    DatabaseReader, EntryReader,
+};
+use image::{ImageFormat, RgbImage};
+
+#[test]
+fn entries_are_previewed() {
+    let fixture = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-preview-{}", process::id())),
+    );
+    let mut png = Cursor::new(Vec::new());
+    RgbImage::new(3, 2)
+        .write_to(&mut png, ImageFormat::Png)
+        .unwrap();
+    fixture.fill_hole(RingKind::Main, 3, png.get_ref(), "");
+    fixture.fill_hole(RingKind::Favorites, 1, b"\xFF\xFE\x00", "");
+    let mut dir = fixture.path().to_path_buf();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+    let preview = |ring, index, options| {
+        let entry = database.get_raw(composite_id(ring, index)).unwrap();
+        preview(entry, &reader, options)
+    };
+    let options = PreviewOptions {
+        width: 10,
+        image_dimensions: true,
+    };
+
+    assert!(matches!(
+        preview(RingKind::Main, 1, PreviewOptions::default()),
+        Preview::Text { one_liner, full_available: false } if one_liner == "hello world"
+    ));
+    assert!(matches!(
+        preview(RingKind::Main, 5, PreviewOptions::default()),
+        Preview::Text { one_liner, full_available: false } if one_liner == "<html>hello</html>"
+    ));
+    assert!(matches!(
+        preview(RingKind::Main, 5, options),
+        Preview::Text { one_liner, full_available: true } if one_liner == "<html>hel…"
+    ));
+    assert!(matches!(
+        preview(RingKind::Favorites, 2, PreviewOptions::default()),
+        Preview::Text {
+            full_available: true,
+            ..
+        }
+    ));
+    // Only the PNG signature is stored, so there's no header to read.
+    assert!(matches!(
+        preview(RingKind::Main, 2, options),
+        Preview::Image {
+            dimensions: None,
+            format: Some(ImageFormat::Png),
+        }
+    ));
+    assert!(matches!(
+        preview(RingKind::Main, 3, options),
+        Preview::Image {
+            dimensions: Some((3, 2)),
+            format: Some(ImageFormat::Png),
+        }
+    ));
+    assert!(matches!(
+        preview(RingKind::Main, 3, PreviewOptions::default()),
+        Preview::Image {
+            dimensions: None,
+            ..
+        }
+    ));
+    assert!(matches!(
+        preview(RingKind::Favorites, 1, options),
+        Preview::Binary { mime_type, len: 3 } if mime_type.as_str() == "application/octet-stream"
+    ));
+}
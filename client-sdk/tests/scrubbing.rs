@@ -0,0 +1,57 @@
+//! Floods a controller with loads the way UIs do while scrolling through
+//! entries.
+
+use std::sync::mpsc;
+
+use clipboard_history_client_sdk::{
+    core::protocol::RingKind,
+    testing::MockDatabase,
+    ui_actor::{Command, Message},
+};
+
+#[test]
+fn only_the_last_queued_details_are_loaded() {
+    let database = MockDatabase::default();
+    let ids = (0..100)
+        .map(|i| database.add(RingKind::Main, format!("entry {i}").into_bytes()))
+        .collect::<Vec<_>>();
+
+    let (sender, commands) = mpsc::channel();
+    for &id in &ids {
+        sender
+            .send(Command::GetDetails {
+                id,
+                with_text: true,
+            })
+            .unwrap();
+        if id == ids[50] {
+            // Other commands are still answered.
+            sender.send(Command::Favorite(id)).unwrap();
+        }
+    }
+    drop(sender);
+    let mut messages = Vec::new();
+    database.controller().run(&commands, |message| {
+        messages.push(message);
+        Ok::<_, ()>(())
+    });
+
+    assert!(
+        messages
+            .iter()
+            .any(|m| matches!(m, Message::FavoriteChange(_))),
+        "{messages:?}"
+    );
+    let details = messages
+        .iter()
+        .filter_map(|m| match m {
+            Message::EntryDetails { id, result } => Some((*id, result)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let [(id, Ok(details))] = &details[..] else {
+        panic!("Expected only the last entry's details: {details:?}");
+    };
+    assert_eq!(*id, ids[99]);
+    assert_eq!(details.full_text.as_deref(), Some("entry 99"));
+}
@@ -0,0 +1,234 @@
+//! Searches a small database on disk.
+
+use std::{env, fs, process, sync::Arc};
+
+use clipboard_history_client_sdk::{
+    core::protocol::{composite_id, MimeType, RingKind},
+    search,
+    search::{EntryIndex, Query},
+    testing::{FixtureDatabase, MockDatabase},
+    ui_actor::{
+        similar_entries_query, Command, Controller, LocalSource, Message, SearchKind,
+        SIMILAR_QUERY_LEN,
+    },
+    DatabaseReader, EntryReader,
+};
+use regex::bytes::Regex;
+
+fn search_ids(database: &FixtureDatabase, mime_filter: Option<&str>) -> Vec<u64> {
+    let reader = EntryReader::open(&mut database.path().to_path_buf()).unwrap();
+    search_ids_with(database, reader, None, mime_filter)
+}
+
+fn search_ids_with(
+    database: &FixtureDatabase,
+    reader: EntryReader,
+    ring: Option<RingKind>,
+    mime_filter: Option<&str>,
+) -> Vec<u64> {
+    let rings = DatabaseReader::open(&mut database.path().to_path_buf()).unwrap();
+    let (results, threads) = search(
+        Query::Regex(Regex::new(".").unwrap()),
+        ring,
+        mime_filter.map(|filter| MimeType::from(filter).unwrap()),
+        Arc::new(reader),
+        Arc::new(EntryIndex::new(&rings)),
+    );
+    let mut ids = results.map(|r| r.unwrap().id).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn searches_can_be_limited_to_a_mime_type() {
+    let database = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-search-mime-{}", process::id())),
+    );
+    let main = |index| composite_id(RingKind::Main, index);
+    let favorite = |index| composite_id(RingKind::Favorites, index);
+    let mut text = vec![
+        main(0),
+        main(1),
+        main(4),
+        main(5),
+        main(6),
+        favorite(0),
+        favorite(2),
+    ];
+    text.sort_unstable();
+
+    let mut everything = search_ids(&database, None);
+    assert_eq!(everything.len(), text.len() + 1);
+    everything.retain(|&id| id != main(2));
+    assert_eq!(everything, text);
+
+    // The image matches through the text recognized in it.
+    assert_eq!(search_ids(&database, Some("image/")), [main(2)]);
+    assert_eq!(search_ids(&database, Some("text/html")), [main(5)]);
+    // Bucketed entries have no mime type and count as plain text.
+    assert_eq!(search_ids(&database, Some("text/")), text);
+    let mut plain = text.clone();
+    plain.retain(|&id| id != main(5));
+    assert_eq!(search_ids(&database, Some("text/plain")), plain);
+}
+
+#[test]
+fn searches_see_entries_added_after_the_reader_was_opened() {
+    let database = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-search-grown-{}", process::id())),
+    );
+    let expected = search_ids(&database, None);
+
+    // A reader opened while the buckets were empty has to map them again to find
+    // anything in them.
+    let buckets = fs::read_dir(database.path().join("buckets"))
+        .unwrap()
+        .map(|file| {
+            let path = file.unwrap().path();
+            let data = fs::read(&path).unwrap();
+            fs::write(&path, []).unwrap();
+            (path, data)
+        })
+        .collect::<Vec<_>>();
+    let reader = EntryReader::open(&mut database.path().to_path_buf()).unwrap();
+    for (path, data) in buckets {
+        fs::write(path, data).unwrap();
+    }
+
+    assert_eq!(search_ids_with(&database, reader, None, None), expected);
+}
+
+#[test]
+fn searches_can_be_limited_to_a_ring() {
+    let database = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-search-ring-{}", process::id())),
+    );
+    let search_ids = |ring| {
+        let reader = EntryReader::open(&mut database.path().to_path_buf()).unwrap();
+        search_ids_with(&database, reader, ring, None)
+    };
+    let main = |index| composite_id(RingKind::Main, index);
+    let favorite = |index| composite_id(RingKind::Favorites, index);
+    // Slots no entry points to, like the ones removed entries leave behind,
+    // are never matched.
+    for file in fs::read_dir(database.path().join("buckets")).unwrap() {
+        let path = file.unwrap().path();
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, data.repeat(2)).unwrap();
+    }
+
+    assert_eq!(
+        search_ids(Some(RingKind::Favorites)),
+        [favorite(0), favorite(2)]
+    );
+    assert_eq!(
+        search_ids(Some(RingKind::Main)),
+        [main(0), main(1), main(2), main(4), main(5), main(6)]
+    );
+    assert_eq!(search_ids(None).len(), 8);
+}
+
+#[test]
+fn search_results_arrive_newest_first_tagged_with_their_search() {
+    let home = env::temp_dir().join(format!("ringboard-search-batches-{}", process::id()));
+    let _database = FixtureDatabase::create(home.join("clipboard-history"));
+    env::set_var("XDG_DATA_HOME", &home);
+    let mut controller = Controller::new(LocalSource::open().unwrap(), MockDatabase::default());
+    let mut search = |favorites_first| {
+        let mut messages = Vec::new();
+        let reply = controller.handle(
+            Command::Search {
+                query: ".".into(),
+                kind: SearchKind::Regex,
+                case_sensitive: false,
+                ring: None,
+                mime_filter: None,
+                favorites_first,
+            },
+            |message| {
+                messages.push(message);
+                Ok::<_, ()>(())
+            },
+        );
+        messages.extend(reply);
+
+        let Some(Message::PendingSearch { generation, .. }) = messages.first() else {
+            panic!("Search wasn't announced: {messages:?}");
+        };
+        let generation = *generation;
+        let mut ids = Vec::new();
+        for (i, message) in messages.iter().enumerate().skip(1) {
+            let Message::SearchResults {
+                generation: batch_generation,
+                batch,
+                done,
+            } = message
+            else {
+                panic!("Unexpected message: {message:?}");
+            };
+            assert_eq!(*batch_generation, generation);
+            assert_eq!(*done, i == messages.len() - 1);
+            ids.extend(batch.iter().map(|e| e.entry.id()));
+        }
+        (generation, ids)
+    };
+    let main = |index| composite_id(RingKind::Main, index);
+    let favorite = |index| composite_id(RingKind::Favorites, index);
+
+    // Distances from the write heads interleave the rings, favorites first on
+    // ties.
+    let (first, ids) = search(false);
+    assert_eq!(
+        ids,
+        [
+            favorite(2),
+            main(1),
+            main(0),
+            favorite(0),
+            main(6),
+            main(5),
+            main(4),
+            main(2)
+        ]
+    );
+    let (second, ids) = search(true);
+    assert_eq!(second, first + 1);
+    assert_eq!(
+        ids,
+        [
+            favorite(2),
+            favorite(0),
+            main(1),
+            main(0),
+            main(6),
+            main(5),
+            main(4),
+            main(2)
+        ]
+    );
+    let _ = fs::remove_dir_all(home);
+}
+
+#[test]
+fn similar_entries_are_found_by_their_longest_line() {
+    assert_eq!(
+        similar_entries_query("ls\ncargo  build\t--release\ncd").as_deref(),
+        Some("cargo build --release")
+    );
+    // Ties go to the first line.
+    assert_eq!(similar_entries_query("abc\ndef").as_deref(), Some("abc"));
+    let url = format!("https://example.com/?q={}", "é".repeat(100));
+    let query = similar_entries_query(&url).unwrap();
+    assert_eq!(query.chars().count(), SIMILAR_QUERY_LEN);
+    assert!(url.starts_with(&query));
+    // Cutting the query short doesn't leave it ending in a space.
+    let words = format!("{} tail", "x".repeat(SIMILAR_QUERY_LEN - 1));
+    assert_eq!(
+        similar_entries_query(&words).unwrap(),
+        "x".repeat(SIMILAR_QUERY_LEN - 1)
+    );
+    assert_eq!(similar_entries_query(" \n\t"), None);
+}
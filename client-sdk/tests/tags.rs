@@ -0,0 +1,152 @@
+//! Tags entries and filters by them through a controller.
+
+use clipboard_history_client_sdk::{
+    core::{protocol::RingKind, tags},
+    testing::MockDatabase,
+    ui_actor::{Command, CommandError, Controller, Message, SearchKind},
+};
+
+fn run(controller: &mut Controller<MockDatabase, MockDatabase>, command: Command) -> Vec<Message> {
+    let mut messages = Vec::new();
+    let reply = controller.handle(command, |message| {
+        messages.push(message);
+        Ok::<_, ()>(())
+    });
+    messages.extend(reply);
+    messages
+}
+
+fn results(messages: &[Message]) -> Vec<u64> {
+    let [
+        Message::PendingSearch { generation, .. },
+        Message::SearchResults {
+            generation: results_generation,
+            batch,
+            done: true,
+        },
+    ] = messages
+    else {
+        panic!("Expected a pending search and its results: {messages:?}");
+    };
+    assert_eq!(generation, results_generation);
+    batch.iter().map(|e| e.entry.id()).collect()
+}
+
+#[test]
+fn entries_are_filtered_by_tag() {
+    let database = MockDatabase::default();
+    let mut controller = database.controller();
+    let [work, home, report] = ["work", "home", "report"].map(|tag| tags::parse(tag).unwrap());
+    let old = database.add(RingKind::Main, &b"standup notes"[..]);
+    let other = database.add(RingKind::Main, &b"groceries"[..]);
+    let new = database.add(RingKind::Main, &b"quarterly report"[..]);
+    let favorite = database.add(RingKind::Favorites, &b"vpn config"[..]);
+
+    for (id, tag) in [
+        (old, work),
+        (new, work),
+        (new, report),
+        (other, home),
+        (favorite, work),
+    ] {
+        assert!(
+            matches!(
+                run(&mut controller, Command::Tag { id, tag })[..],
+                [Message::Tagged(tagged)] if tagged == id
+            ),
+            "{id} {tag}"
+        );
+    }
+    assert_eq!(
+        results(&run(&mut controller, Command::FilterByTag(work))),
+        [favorite, new, old]
+    );
+    assert_eq!(
+        results(&run(
+            &mut controller,
+            Command::Search {
+                query: " Work ".into(),
+                kind: SearchKind::Tag,
+                case_sensitive: false,
+                ring: Some(RingKind::Main),
+                mime_filter: None,
+                favorites_first: false,
+            }
+        )),
+        [new, old]
+    );
+
+    // Tags move along with their entry.
+    let [Message::FavoriteChange(_)] = &run(&mut controller, Command::Favorite(new))[..] else {
+        panic!("Expected the entry to be favorited.");
+    };
+    let moved = results(&run(&mut controller, Command::FilterByTag(report)));
+    assert_eq!(moved.len(), 1);
+    let [
+        Message::EntryDetails {
+            result: Ok(details),
+            ..
+        },
+    ] = &run(
+        &mut controller,
+        Command::GetDetails {
+            id: moved[0],
+            with_text: false,
+        },
+    )[..]
+    else {
+        panic!("Expected the moved entry's details.");
+    };
+    assert_eq!(details.tags.iter().collect::<Vec<_>>(), ["work", "report"]);
+
+    run(&mut controller, Command::Untag { id: old, tag: work });
+    assert_eq!(
+        results(&run(&mut controller, Command::FilterByTag(work))),
+        [moved[0], favorite]
+    );
+}
+
+#[test]
+fn invalid_tags_are_rejected() {
+    let database = MockDatabase::default();
+    let mut controller = database.controller();
+    let id = database.add(RingKind::Main, &b"data"[..]);
+
+    let [Message::Error(CommandError::InvalidTag)] = &run(
+        &mut controller,
+        Command::Search {
+            query: "two words".into(),
+            kind: SearchKind::Tag,
+            case_sensitive: false,
+            ring: None,
+            mime_filter: None,
+            favorites_first: false,
+        },
+    )[..] else {
+        panic!("Expected the filter to be rejected.");
+    };
+    let [Message::Error(CommandError::InvalidTag)] = &run(
+        &mut controller,
+        Command::Tag {
+            id,
+            tag: "Not Valid".try_into().unwrap(),
+        },
+    )[..] else {
+        panic!("Expected the tag to be rejected.");
+    };
+
+    let long = "x".repeat(tags::MAX_TAG_LEN - 1);
+    let mut full = false;
+    for c in 'a'..='z' {
+        let tag = tags::parse(&format!("{c}{long}")).unwrap();
+        match &run(&mut controller, Command::Tag { id, tag })[..] {
+            [Message::Tagged(_)] => {}
+            [Message::Error(CommandError::TooManyTags)] => {
+                full = true;
+                break;
+            }
+            messages => panic!("Unexpected reply: {messages:?}"),
+        }
+    }
+    assert!(full);
+}
@@ -0,0 +1,89 @@
+//! Shares readers across threads while they and others grow their view of the
+//! same buckets.
+
+use std::{
+    env, fs, process,
+    sync::{Arc, Barrier},
+    thread,
+};
+
+use clipboard_history_client_sdk::{testing::FixtureDatabase, DatabaseReader, Entry, EntryReader};
+
+const READERS: usize = 4;
+const GROWERS: usize = 4;
+const ROUNDS: usize = 100;
+
+fn entries(database: &DatabaseReader) -> Vec<Entry> {
+    database.favorites().chain(database.main()).collect()
+}
+
+fn contents(entries: &[Entry], reader: &EntryReader) -> Vec<(u64, Vec<u8>)> {
+    entries
+        .iter()
+        .map(|entry| (entry.id(), entry.to_slice(reader).unwrap().to_vec()))
+        .collect()
+}
+
+#[test]
+fn concurrent_reads_while_buckets_are_remapped() {
+    let fixture = FixtureDatabase::create(
+        env::temp_dir().join(format!("ringboard-threads-{}", process::id())),
+    );
+    let mut dir = fixture.path().to_path_buf();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let entries = entries(&database);
+    let expected = contents(&entries, &EntryReader::open(&mut dir).unwrap());
+
+    // Readers opened while the buckets are empty have to remap them to read
+    // anything, just like readers opened before the server added entries.
+    let buckets = fs::read_dir(dir.join("buckets"))
+        .unwrap()
+        .map(|file| {
+            let path = file.unwrap().path();
+            let data = fs::read(&path).unwrap();
+            fs::write(&path, []).unwrap();
+            (path, data)
+        })
+        .collect::<Vec<_>>();
+    let growers = (0..GROWERS)
+        .map(|_| EntryReader::open(&mut dir).unwrap())
+        .collect::<Vec<_>>();
+    let shared = Arc::new(EntryReader::open(&mut dir).unwrap());
+    for (path, data) in buckets {
+        fs::write(path, data).unwrap();
+    }
+    let start = Arc::new(Barrier::new(READERS + GROWERS));
+    let entries = Arc::new(entries);
+    let expected = Arc::new(expected);
+    let mut threads = Vec::new();
+    for _ in 0..READERS {
+        let (shared, start, entries, expected) = (
+            shared.clone(),
+            start.clone(),
+            entries.clone(),
+            expected.clone(),
+        );
+        threads.push(thread::spawn(move || {
+            start.wait();
+            for _ in 0..ROUNDS {
+                for (entry, (id, data)) in entries.iter().zip(&*expected) {
+                    let loaded = entry.to_slice(&shared).unwrap();
+                    assert_eq!(entry.id(), *id);
+                    assert_eq!(&**loaded, &data[..]);
+                }
+            }
+        }));
+    }
+    for reader in growers {
+        let (start, entries, expected) = (start.clone(), entries.clone(), expected.clone());
+        threads.push(thread::spawn(move || {
+            start.wait();
+            for _ in 0..ROUNDS {
+                assert_eq!(contents(&entries, &reader), *expected);
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+//! Watches a database on disk for changes.
+
+use std::{env, fs, fs::OpenOptions, os::unix::fs::FileExt, process, time::Duration};
+
+use clipboard_history_client_sdk::{core::protocol::RingKind, testing::FixtureDatabase, DatabaseReader};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Comfortably longer than changes take to settle.
+const QUIET: Duration = Duration::from_millis(300);
+
+#[test]
+fn changes_are_coalesced_and_followed_across_recreated_directories() {
+    let fixture =
+        FixtureDatabase::create(env::temp_dir().join(format!("ringboard-watch-{}", process::id())));
+    let dir = fixture.path();
+    let changes = DatabaseReader::watch(dir).unwrap().spawn();
+    assert!(changes.recv_timeout(QUIET).is_err());
+
+    // A burst of writes, like the server makes for a single request.
+    let ring = OpenOptions::new()
+        .write(true)
+        .open(dir.join(RingKind::Main.file_name()))
+        .unwrap();
+    for i in 0..10 {
+        ring.write_all_at(&[0], 4 + i).unwrap();
+    }
+    // Open files keep their directory alive.
+    drop(ring);
+    changes.recv_timeout(TIMEOUT).unwrap();
+    assert!(changes.recv_timeout(QUIET).is_err());
+
+    // Entries are added to the direct directory even after it was replaced.
+    let direct = dir.join("direct");
+    fs::remove_dir_all(&direct).unwrap();
+    fs::create_dir(&direct).unwrap();
+    changes.recv_timeout(TIMEOUT).unwrap();
+    fs::write(direct.join("new"), "hello").unwrap();
+    changes.recv_timeout(TIMEOUT).unwrap();
+
+    // Watching stops once the database goes away.
+    fs::remove_dir_all(dir).unwrap();
+    while changes.recv_timeout(TIMEOUT).is_ok() {}
+    assert!(changes.is_empty());
+    assert!(
+        changes.recv_timeout(QUIET).unwrap_err().is_disconnected(),
+        "Still watching a deleted database."
+    );
+}
@@ -0,0 +1,80 @@
+use std::{ffi::CStr, fmt::Write, io, io::ErrorKind, os::fd::AsFd, str};
+
+use arrayvec::ArrayString;
+use rustix::{
+    fs::{fgetxattr, fsetxattr, XattrFlags},
+    io::{pread, Errno},
+};
+
+use crate::{Error, IoErr, Result};
+
+/// The extended attribute holding the CRC-32 of a direct file's contents as
+/// eight hex digits.
+pub const XATTR_NAME: &CStr = c"user.checksum";
+
+/// The result of checking an entry's contents against its stored checksum.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Verification {
+    Ok,
+    Mismatch,
+    /// The entry has no checksum: it's stored in a bucket or was added before
+    /// checksums were recorded.
+    Unknown,
+}
+
+/// Checksums the contents of a file, reading it from the start.
+pub fn checksum_file(fd: impl AsFd) -> Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = [0; 1 << 14];
+    let mut offset = 0;
+    loop {
+        match pread(&fd, &mut buf, offset) {
+            Ok(0) => break,
+            Ok(n) => {
+                hasher.update(&buf[..n]);
+                offset += u64::try_from(n).unwrap();
+            }
+            Err(Errno::INTR) => {}
+            Err(e) => return Err(e).map_io_err(|| "Failed to read file to checksum."),
+        }
+    }
+    Ok(hasher.finalize())
+}
+
+/// Records the checksum of a file's current contents.
+pub fn write(fd: impl AsFd) -> Result<()> {
+    let checksum = checksum_file(&fd)?;
+    let mut value = ArrayString::<8>::new_const();
+    write!(value, "{checksum:08x}").unwrap();
+    fsetxattr(fd, XATTR_NAME, value.as_bytes(), XattrFlags::empty())
+        .map_io_err(|| "Failed to create checksum attribute.")
+}
+
+/// Reads a file's recorded checksum, if it has one.
+pub fn read(fd: impl AsFd) -> Result<Option<u32>> {
+    let mut value = [0; 8];
+    let len = match fgetxattr(fd, XATTR_NAME, &mut value) {
+        Err(Errno::NODATA) => return Ok(None),
+        r => r.map_io_err(|| "Failed to read extended attributes.")?,
+    };
+    str::from_utf8(&value[..len])
+        .ok()
+        .and_then(|value| u32::from_str_radix(value, 16).ok())
+        .map(Some)
+        .ok_or_else(|| Error::Io {
+            error: io::Error::from(ErrorKind::InvalidData),
+            context: "Database corruption detected: invalid checksum detected".into(),
+        })
+}
+
+/// Recomputes a file's checksum and compares it against the recorded one.
+pub fn verify(fd: impl AsFd) -> Result<Verification> {
+    let Some(expected) = read(&fd)? else {
+        return Ok(Verification::Unknown);
+    };
+    Ok(if checksum_file(fd)? == expected {
+        Verification::Ok
+    } else {
+        Verification::Mismatch
+    })
+}
@@ -5,6 +5,12 @@ use std::{
 
 #[must_use]
 pub fn data_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("RINGBOARD_DATA_DIR") {
+        let mut dir = PathBuf::from(dir);
+        dir.reserve("buckets/(1024, 2048]".len());
+        return dir;
+    }
+
     let mut dir = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("/tmp/data"));
     dir.reserve("clipboard-history/buckets/(1024, 2048]".len());
     dir.push("clipboard-history");
@@ -0,0 +1,61 @@
+use std::{ffi::CStr, io, io::ErrorKind, os::fd::AsFd};
+
+use rustix::{
+    fs::{fgetxattr, fsetxattr, XattrFlags},
+    io::Errno,
+};
+
+use crate::{Error, IoErr, Result};
+
+/// The extended attribute naming how a direct file's contents are encoded.
+/// Files without it hold the entry's data as is.
+pub const XATTR_NAME: &CStr = c"user.encoding";
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Encoding {
+    Zstd,
+}
+
+impl Encoding {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// Records how a file's contents are encoded.
+pub fn write(fd: impl AsFd, encoding: Encoding) -> Result<()> {
+    fsetxattr(
+        fd,
+        XATTR_NAME,
+        encoding.name().as_bytes(),
+        XattrFlags::empty(),
+    )
+    .map_io_err(|| "Failed to create encoding attribute.")
+}
+
+/// Reads how a file's contents are encoded, if they are. Encodings this
+/// version doesn't know are errors so their contents aren't mistaken for the
+/// entry's data.
+pub fn read(fd: impl AsFd) -> Result<Option<Encoding>> {
+    let mut value = [0; 16];
+    let len = match fgetxattr(fd, XATTR_NAME, &mut value) {
+        Err(Errno::NODATA) => return Ok(None),
+        Err(Errno::RANGE) => value.len(),
+        r => r.map_io_err(|| "Failed to read extended attributes.")?,
+    };
+    let value = &value[..len];
+    if value == Encoding::Zstd.name().as_bytes() {
+        return Ok(Some(Encoding::Zstd));
+    }
+    let value = value.escape_ascii();
+    Err(Error::Io {
+        error: io::Error::new(
+            ErrorKind::Unsupported,
+            format!("unsupported encoding {value}"),
+        ),
+        context: "Entry is stored in an encoding this version can't read.".into(),
+    })
+}
@@ -0,0 +1,151 @@
+use std::{
+    fmt::Debug,
+    io,
+    io::ErrorKind,
+    ops::Deref,
+    os::fd::AsFd,
+    ptr,
+    ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use rustix::{
+    fs::{ftruncate, openat, statx, AtFlags, Mode, OFlags, StatxFlags, CWD},
+    mm::{mmap, munmap, MapFlags, ProtFlags},
+    path::Arg,
+};
+
+use crate::{Error, IoErr, Result};
+
+pub const FILE_NAME: &str = "generation";
+
+/// A counter bumped by the server on every database mutation.
+///
+/// Comparing generations is a cheap way to detect changes since reads are a
+/// plain atomic load from shared memory.
+#[derive(Debug)]
+pub struct Generation {
+    counter: NonNull<AtomicU64>,
+}
+
+// SAFETY: the counter is owned and only accessed atomically.
+unsafe impl Send for Generation {}
+unsafe impl Sync for Generation {}
+
+impl Generation {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        let fd = openat(CWD, path, OFlags::RDONLY, Mode::empty())
+            .map_io_err(|| format!("Failed to open generation file: {path:?}"))?;
+        let len = statx(&fd, c"", AtFlags::EMPTY_PATH, StatxFlags::SIZE)
+            .map_io_err(|| format!("Failed to statx generation file: {path:?}"))?
+            .stx_size;
+        if len < u64::try_from(size_of::<AtomicU64>()).unwrap() {
+            return Err(Error::Io {
+                error: io::Error::new(ErrorKind::InvalidData, "Generation file too short."),
+                context: format!("Generation file has not been initialized: {path:?}").into(),
+            });
+        }
+        Self::map(fd, ProtFlags::READ)
+    }
+
+    fn map(fd: impl AsFd, prot: ProtFlags) -> Result<Self> {
+        let counter = unsafe {
+            mmap(
+                ptr::null_mut(),
+                size_of::<AtomicU64>(),
+                prot,
+                MapFlags::SHARED_VALIDATE,
+                fd,
+                0,
+            )
+        }
+        .map_io_err(|| "Failed to mmap generation file.")?;
+        Ok(Self {
+            counter: unsafe { NonNull::new_unchecked(counter.cast()) },
+        })
+    }
+
+    #[must_use]
+    pub fn get(&self) -> u64 {
+        unsafe { self.counter.as_ref() }.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Generation {
+    fn drop(&mut self) {
+        let _ = unsafe { munmap(self.counter.as_ptr().cast(), size_of::<AtomicU64>()) };
+    }
+}
+
+/// The server's writable view of the [`Generation`] counter.
+#[derive(Debug)]
+pub struct GenerationWriter(Generation);
+
+impl GenerationWriter {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        let fd = openat(
+            CWD,
+            path,
+            OFlags::RDWR | OFlags::CREATE,
+            Mode::RUSR | Mode::WUSR,
+        )
+        .map_io_err(|| format!("Failed to open generation file: {path:?}"))?;
+        ftruncate(&fd, u64::try_from(size_of::<AtomicU64>()).unwrap())
+            .map_io_err(|| format!("Failed to size generation file: {path:?}"))?;
+        Generation::map(fd, ProtFlags::READ | ProtFlags::WRITE).map(Self)
+    }
+
+    pub fn bump(&mut self) {
+        let counter = unsafe { self.0.counter.as_ref() };
+        // There is only ever one writer, so a plain store is enough.
+        counter.store(
+            counter.load(Ordering::Relaxed).wrapping_add(1),
+            Ordering::Release,
+        );
+    }
+}
+
+impl Deref for GenerationWriter {
+    type Target = Generation;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, thread};
+
+    use super::{Generation, GenerationWriter};
+
+    #[test]
+    fn concurrent_reads_are_monotonic() {
+        const BUMPS: u64 = 100_000;
+
+        let path = env::temp_dir().join(format!("ringboard-generation-{}", process::id()));
+        let mut writer = GenerationWriter::open(&path).unwrap();
+        let start = writer.get();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let reader = Generation::open(&path).unwrap();
+                s.spawn(move || {
+                    let mut last = reader.get();
+                    while last < start + BUMPS {
+                        let current = reader.get();
+                        assert!(current >= last, "{current} < {last}");
+                        last = current;
+                    }
+                });
+            }
+
+            for _ in 0..BUMPS {
+                writer.bump();
+            }
+        });
+
+        assert_eq!(Generation::open(&path).unwrap().get(), start + BUMPS);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,6 +1,12 @@
 #![feature(core_io_borrowed_buf, read_buf)]
 
-use std::{borrow::Cow, io, num::ParseIntError};
+use std::{
+    borrow::Cow,
+    fmt::{Display, Formatter},
+    io,
+    num::ParseIntError,
+    path::PathBuf,
+};
 
 use thiserror::Error;
 pub use utils::*;
@@ -8,9 +14,19 @@ pub use views::{BucketAndIndex, PathView, RingAndIndex, StringView};
 
 use crate::protocol::IdNotFoundError;
 
+pub mod checksums;
 pub mod dirs;
+pub mod encoding;
+pub mod generation;
+pub mod origins;
+pub mod packed;
 pub mod protocol;
 pub mod ring;
+pub mod sensitive;
+pub mod settings;
+pub mod slot_generations;
+pub mod tags;
+pub mod timestamps;
 mod utils;
 mod views;
 
@@ -19,10 +35,7 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("an I/O error occurred")]
-    Io {
-        error: io::Error,
-        context: Cow<'static, str>,
-    },
+    Io { error: io::Error, context: Context },
     #[error("invalid PID")]
     InvalidPidError {
         error: ParseIntError,
@@ -32,12 +45,69 @@ pub enum Error {
     IdNotFound(#[from] IdNotFoundError),
 }
 
+/// What was being done when an I/O error occurred.
+///
+/// Prefer the structured variants over [`Context::Message`] so clients can
+/// tell which file, bucket, entry, or client an error is about without
+/// parsing its message.
+#[derive(Debug)]
+pub enum Context {
+    Message(Cow<'static, str>),
+    File {
+        message: &'static str,
+        path: PathBuf,
+    },
+    Bucket {
+        message: &'static str,
+        bucket: usize,
+    },
+    Entry {
+        message: &'static str,
+        id: u64,
+    },
+    /// A server connection, identified by the server's client slot.
+    Client {
+        message: &'static str,
+        client: u8,
+    },
+}
+
+impl Display for Context {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Message(message) => f.write_str(message),
+            Self::File { message, path } => write!(f, "{message}: {path:?}"),
+            Self::Bucket { message, bucket } => write!(f, "{message} {bucket}."),
+            Self::Entry { message, id } => write!(f, "{message} {id}."),
+            Self::Client { message, client } => write!(f, "{message} {client}."),
+        }
+    }
+}
+
+impl From<&'static str> for Context {
+    fn from(value: &'static str) -> Self {
+        Self::Message(value.into())
+    }
+}
+
+impl From<String> for Context {
+    fn from(value: String) -> Self {
+        Self::Message(value.into())
+    }
+}
+
+impl From<Cow<'static, str>> for Context {
+    fn from(value: Cow<'static, str>) -> Self {
+        Self::Message(value)
+    }
+}
+
 pub trait IoErr<Out> {
-    fn map_io_err<I: Into<Cow<'static, str>>>(self, f: impl FnOnce() -> I) -> Out;
+    fn map_io_err<I: Into<Context>>(self, f: impl FnOnce() -> I) -> Out;
 }
 
 impl<T> IoErr<Result<T>> for std::result::Result<T, io::Error> {
-    fn map_io_err<I: Into<Cow<'static, str>>>(self, context: impl FnOnce() -> I) -> Result<T> {
+    fn map_io_err<I: Into<Context>>(self, context: impl FnOnce() -> I) -> Result<T> {
         self.map_err(|error| Error::Io {
             error,
             context: context().into(),
@@ -46,7 +116,7 @@ impl<T> IoErr<Result<T>> for std::result::Result<T, io::Error> {
 }
 
 impl<T> IoErr<Result<T>> for rustix::io::Result<T> {
-    fn map_io_err<I: Into<Cow<'static, str>>>(self, context: impl FnOnce() -> I) -> Result<T> {
+    fn map_io_err<I: Into<Context>>(self, context: impl FnOnce() -> I) -> Result<T> {
         self.map_err(io::Error::from).map_io_err(context)
     }
 }
@@ -72,6 +142,8 @@ mod error_stack_compat {
                 Self::IdNotFound(IdNotFoundError::Entry(id)) => {
                     Report::new(wrapper).attach_printable(format!("Unknown entry: {id}"))
                 }
+                Self::IdNotFound(IdNotFoundError::Malformed) => Report::new(wrapper)
+                    .attach_printable("Expected an entry ID like main-42, fav-1 or 4294967297."),
             }
         }
     }
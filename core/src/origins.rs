@@ -0,0 +1,211 @@
+use std::{
+    fmt::{Debug, Display, Formatter},
+    fs::File,
+    os::unix::fs::FileExt,
+    str,
+};
+
+use arrayvec::ArrayString;
+use rustix::{
+    fs::{openat, Mode, OFlags, CWD},
+    path::Arg,
+};
+
+use crate::{protocol::RingKind, ring::MAX_ENTRIES, IoErr, Result};
+
+pub const FILE_NAME: &str = "origins";
+
+/// The longest client name kept, enough for a process's comm.
+pub const MAX_NAME_LEN: usize = 28;
+
+const SLOT_SIZE: usize = size_of::<u32>() + MAX_NAME_LEN;
+
+/// The client an entry was added by: the name it gave when connecting, or
+/// its process's command name otherwise.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Origin {
+    name: ArrayString<MAX_NAME_LEN>,
+    uid: u32,
+}
+
+impl Origin {
+    /// Keeps names to a short run of `[A-Za-z0-9._-]` so they can be shown
+    /// and searched for as is. Returns [`None`] if nothing is left.
+    #[must_use]
+    pub fn new(name: &str, uid: u32) -> Option<Self> {
+        let mut sanitized = ArrayString::new();
+        for c in name.trim().chars() {
+            let c = if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            };
+            if sanitized.try_push(c).is_err() {
+                break;
+            }
+        }
+        (!sanitized.is_empty()).then_some(Self {
+            name: sanitized,
+            uid,
+        })
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub const fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    fn to_slot(self) -> [u8; SLOT_SIZE] {
+        let mut slot = [0; SLOT_SIZE];
+        slot[..size_of::<u32>()].copy_from_slice(&self.uid.to_le_bytes());
+        slot[size_of::<u32>()..][..self.name.len()].copy_from_slice(self.name.as_bytes());
+        slot
+    }
+
+    fn from_slot(slot: &[u8; SLOT_SIZE]) -> Option<Self> {
+        let (uid, name) = slot.split_at(size_of::<u32>());
+        let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        let name = str::from_utf8(&name[..len]).ok()?;
+        Self::new(name, u32::from_le_bytes(uid.try_into().unwrap()))
+            .filter(|origin| origin.name() == name)
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (uid {})", self.name, self.uid)
+    }
+}
+
+/// Each entry has a fixed slot, so the file is sparse: entries added before
+/// origins were recorded simply read as zeros.
+fn slot_offset(ring: RingKind, index: u32) -> u64 {
+    (ring as u64 * (u64::from(MAX_ENTRIES) + 1) + u64::from(index))
+        * u64::try_from(SLOT_SIZE).unwrap()
+}
+
+/// Which client added each entry, as recorded by the server.
+///
+/// The file only exists while the server is configured to record origins.
+#[derive(Debug)]
+pub struct Origins {
+    file: File,
+}
+
+impl Origins {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        openat(CWD, path, OFlags::RDONLY, Mode::empty())
+            .map_io_err(|| format!("Failed to open origins file: {path:?}"))
+            .map(|fd| Self {
+                file: File::from(fd),
+            })
+    }
+
+    /// Missing and unreadable slots are treated as unknown since origins are
+    /// purely informational.
+    #[must_use]
+    pub fn get(&self, ring: RingKind, index: u32) -> Option<Origin> {
+        let mut slot = [0; SLOT_SIZE];
+        self.file
+            .read_exact_at(&mut slot, slot_offset(ring, index))
+            .ok()?;
+        Origin::from_slot(&slot)
+    }
+}
+
+/// The server's writable view of the [`Origins`] file.
+#[derive(Debug)]
+pub struct OriginsWriter(Origins);
+
+impl OriginsWriter {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        openat(
+            CWD,
+            path,
+            OFlags::RDWR | OFlags::CREATE,
+            Mode::RUSR | Mode::WUSR,
+        )
+        .map_io_err(|| format!("Failed to open origins file: {path:?}"))
+        .map(|fd| {
+            Self(Origins {
+                file: File::from(fd),
+            })
+        })
+    }
+
+    pub fn set(&mut self, ring: RingKind, index: u32, origin: Option<Origin>) -> Result<()> {
+        self.0
+            .file
+            .write_all_at(
+                &origin.map_or([0; SLOT_SIZE], Origin::to_slot),
+                slot_offset(ring, index),
+            )
+            .map_io_err(|| format!("Failed to write origin of entry {index} in {ring:?} ring."))
+    }
+
+    #[must_use]
+    pub fn get(&self, ring: RingKind, index: u32) -> Option<Origin> {
+        self.0.get(ring, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{Origin, Origins, OriginsWriter, MAX_NAME_LEN};
+    use crate::{protocol::RingKind, ring::MAX_ENTRIES};
+
+    #[test]
+    fn names_are_sanitized() {
+        let cases = [
+            ("firefox-watcher", Some("firefox-watcher")),
+            ("  Web Content\n", Some("Web_Content")),
+            ("kworker/0:1", Some("kworker_0_1")),
+            ("héllo", Some("h_llo")),
+            ("", None),
+            (" \t", None),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(
+                Origin::new(name, 1000).as_ref().map(Origin::name),
+                expected,
+                "{name:?}"
+            );
+        }
+        assert_eq!(
+            Origin::new(&"a".repeat(100), 0).unwrap().name().len(),
+            MAX_NAME_LEN
+        );
+    }
+
+    #[test]
+    fn origins_are_kept_per_entry() {
+        let path = env::temp_dir().join(format!("ringboard-origins-{}", process::id()));
+        let mut writer = OriginsWriter::open(&path).unwrap();
+        let cli = Origin::new("ringboard-cli", 1000);
+        let x11 = Origin::new("ringboard-x11", 0);
+
+        writer.set(RingKind::Main, 0, cli).unwrap();
+        writer.set(RingKind::Favorites, 0, x11).unwrap();
+        writer.set(RingKind::Main, MAX_ENTRIES - 1, x11).unwrap();
+
+        let reader = Origins::open(&path).unwrap();
+        assert_eq!(reader.get(RingKind::Main, 0), cli);
+        assert_eq!(reader.get(RingKind::Favorites, 0), x11);
+        assert_eq!(reader.get(RingKind::Main, MAX_ENTRIES - 1), x11);
+        assert_eq!(reader.get(RingKind::Main, 1), None);
+        assert_eq!(reader.get(RingKind::Favorites, MAX_ENTRIES - 1), None);
+
+        writer.set(RingKind::Main, 0, None).unwrap();
+        assert_eq!(reader.get(RingKind::Main, 0), None);
+        assert_eq!(cli.unwrap().to_string(), "ringboard-cli (uid 1000)");
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,352 @@
+//! Short text entries packed back to back into a log, for databases whose
+//! main ring has the [`PACKED`](crate::ring::PACKED) flag.
+//!
+//! Bucket slots round entries up to a power of two, so a 130 byte entry takes
+//! 256 bytes. Packed entries take exactly their length in the log instead,
+//! plus a record in the index file. Records are kept per ring slot like the
+//! other sidecar files, so packed entries don't need a free list.
+//!
+//! A record starts with a word like those of [`crate::slot_generations`]: the
+//! entry's length along with a generation bumped on every write to the
+//! record. The entry's offset in the log follows. The server writes an
+//! entry's bytes to the log before pointing a record at them and publishes the
+//! record without a length until its offset is stored. Readers load the word
+//! before and after reading the bytes the record points at: what they read is
+//! their entry only if both loads returned the same word with their entry's
+//! length.
+//!
+//! Removed entries leave garbage behind in the log, which the server compacts
+//! by moving the remaining entries towards its front. Bytes are only ever
+//! overwritten once no record points at them anymore.
+
+use std::{
+    fmt::Debug,
+    io,
+    io::ErrorKind,
+    ops::Range,
+    sync::atomic::{fence, Ordering},
+};
+
+use rustix::{
+    fs::{ftruncate, openat, Mode, OFlags, CWD},
+    path::Arg,
+};
+
+use crate::{
+    protocol::RingKind,
+    ring::{InitializedEntry, Mmap, MAX_ENTRIES},
+    Error, IoErr, Result,
+};
+
+pub const INDEX_FILE_NAME: &str = "packed_index";
+pub const LOG_FILE_NAME: &str = "packed_log";
+
+/// The longest entry that gets packed. Past this, the bucket slots' rounding
+/// wastes less than a record costs often enough not to bother.
+pub const MAX_LEN: u16 = 255;
+
+const RECORD_LEN: usize = 2 * size_of::<u32>();
+
+/// Every slot of both rings gets a record, so the file is sparse.
+const FILE_LEN: usize = 2 * (MAX_ENTRIES as usize + 1) * RECORD_LEN;
+
+/// As far as records can point into the log.
+const MAX_LOG_LEN: usize = u32::MAX as usize + MAX_LEN as usize;
+
+const LEN_BITS: u32 = 12;
+
+fn record_offset(ring: RingKind, index: u32) -> usize {
+    (ring as usize * (MAX_ENTRIES as usize + 1) + usize::try_from(index).unwrap()) * RECORD_LEN
+}
+
+fn len(word: u32) -> u16 {
+    u16::try_from(word & ((1 << LEN_BITS) - 1)).unwrap()
+}
+
+/// The records of every packed entry, as published by the server.
+#[derive(Debug)]
+pub struct PackedIndex {
+    mem: Mmap,
+}
+
+impl PackedIndex {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        let fd = openat(CWD, path, OFlags::RDONLY, Mode::empty())
+            .map_io_err(|| format!("Failed to open packed index file: {path:?}"))?;
+        let mem =
+            Mmap::from(&fd).map_io_err(|| format!("Failed to mmap packed index file: {path:?}"))?;
+        if mem.len() < FILE_LEN {
+            return Err(Error::Io {
+                error: io::Error::new(ErrorKind::InvalidData, "Packed index file too short."),
+                context: format!("Packed index file has not been initialized: {path:?}").into(),
+            });
+        }
+        Ok(Self { mem })
+    }
+
+    /// Runs `read` on the range of the log holding the entry in the given ring
+    /// slot, returning what it read unless the server moved or removed the
+    /// entry in the meantime.
+    ///
+    /// Returns [`None`] if the record is being written to, holds an entry of a
+    /// different length, or got a new generation while `read` ran.
+    pub fn read<T>(
+        &self,
+        ring: RingKind,
+        index: u32,
+        entry: InitializedEntry,
+        read: impl FnOnce(Range<usize>) -> T,
+    ) -> Option<T> {
+        let offset = record_offset(ring, index);
+        let before = self.mem.load_word(offset);
+        if len(before) != entry.size() {
+            return None;
+        }
+        let start = usize::try_from(self.mem.load_word(offset + size_of::<u32>())).unwrap();
+        let read = read(start..start + usize::from(entry.size()));
+        // Keeps the log's loads from sinking past the second check.
+        fence(Ordering::Acquire);
+        (self.mem.load_word(offset) == before).then_some(read)
+    }
+}
+
+/// A reader's view of the log along with its [`PackedIndex`].
+///
+/// The log is mapped as far as records can point into it, which costs nothing
+/// but address space: the server never shrinks the log, so every byte a
+/// record points at stays backed by the file and the log never needs mapping
+/// again.
+#[derive(Debug)]
+pub struct PackedEntries {
+    index: PackedIndex,
+    log: Mmap,
+}
+
+impl PackedEntries {
+    pub fn open<P: Arg + Copy + Debug>(index: PackedIndex, log_path: P) -> Result<Self> {
+        let fd = openat(CWD, log_path, OFlags::RDONLY, Mode::empty())
+            .map_io_err(|| format!("Failed to open packed log: {log_path:?}"))?;
+        let log = Mmap::new(&fd, MAX_LOG_LEN)
+            .map_io_err(|| format!("Failed to mmap packed log: {log_path:?}"))?;
+        Ok(Self { index, log })
+    }
+
+    /// The bytes of the entry in the given ring slot, unless the server moved
+    /// or removed it while they were being located.
+    ///
+    /// The bytes are read in place, so they may change once the entry is
+    /// removed.
+    #[must_use]
+    pub fn get(&self, ring: RingKind, index: u32, entry: InitializedEntry) -> Option<&[u8]> {
+        self.index
+            .read(ring, index, entry, |range| &self.log[range])
+    }
+}
+
+/// The server's writable view of the [`PackedIndex`].
+#[derive(Debug)]
+pub struct PackedIndexWriter(PackedIndex);
+
+impl PackedIndexWriter {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        let fd = openat(
+            CWD,
+            path,
+            OFlags::RDWR | OFlags::CREATE,
+            Mode::RUSR | Mode::WUSR,
+        )
+        .map_io_err(|| format!("Failed to open packed index file: {path:?}"))?;
+        ftruncate(&fd, u64::try_from(FILE_LEN).unwrap())
+            .map_io_err(|| format!("Failed to size packed index file: {path:?}"))?;
+        let mem = Mmap::new_writable(&fd, FILE_LEN)
+            .map_io_err(|| format!("Failed to mmap packed index file: {path:?}"))?;
+        Ok(Self(PackedIndex { mem }))
+    }
+
+    /// Where the entry recorded for the ring slot lives in the log, if any.
+    #[must_use]
+    pub fn get(&self, ring: RingKind, index: u32) -> Option<(u32, u16)> {
+        let offset = record_offset(ring, index);
+        match len(self.0.mem.load_word(offset)) {
+            0 => None,
+            len => Some((self.0.mem.load_word(offset + size_of::<u32>()), len)),
+        }
+    }
+
+    /// Points the ring slot's record at `len` bytes of the log starting at
+    /// `start`, which must already hold the entry.
+    pub fn set(&mut self, ring: RingKind, index: u32, start: u32, len: u16) {
+        debug_assert!(len > 0 && len <= MAX_LEN);
+        let offset = record_offset(ring, index);
+        let generation = self.next_generation(offset);
+        self.store(offset, generation);
+        // Keeps the new offset from rising above the emptied word.
+        fence(Ordering::Release);
+        self.store(offset + size_of::<u32>(), start);
+        self.store(offset, generation | u32::from(len));
+    }
+
+    /// Empties the ring slot's record so readers give up on its entry.
+    pub fn clear(&mut self, ring: RingKind, index: u32) {
+        let offset = record_offset(ring, index);
+        if len(self.0.mem.load_word(offset)) != 0 {
+            let generation = self.next_generation(offset);
+            self.store(offset, generation);
+        }
+    }
+
+    fn next_generation(&self, offset: usize) -> u32 {
+        // There is only ever one writer, so plain stores are enough.
+        let word = self.0.mem.load_word(offset);
+        ((word >> LEN_BITS).wrapping_add(1)) << LEN_BITS
+    }
+
+    fn store(&mut self, offset: usize, word: u32) {
+        // SAFETY: the mapping is writable and the file was sized to fit every record.
+        unsafe { self.0.mem.store_word(offset, word) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::{self, File},
+        os::unix::fs::FileExt,
+        process,
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+    };
+
+    use super::{PackedEntries, PackedIndex, PackedIndexWriter};
+    use crate::{
+        protocol::RingKind,
+        ring::{InitializedEntry, Mmap, MAX_ENTRIES},
+    };
+
+    #[test]
+    fn records_are_kept_per_slot() {
+        let path = env::temp_dir().join(format!("ringboard-packed-index-{}", process::id()));
+        let mut writer = PackedIndexWriter::open(&path).unwrap();
+        let reader = PackedIndex::open(&path).unwrap();
+        let entry = InitializedEntry::packed(5);
+
+        writer.set(RingKind::Main, 3, 40, 5);
+        writer.set(RingKind::Favorites, 3, 100, 7);
+        writer.set(RingKind::Main, MAX_ENTRIES - 1, 7, 5);
+        assert_eq!(writer.get(RingKind::Main, 3), Some((40, 5)));
+        assert_eq!(writer.get(RingKind::Favorites, 3), Some((100, 7)));
+        assert_eq!(writer.get(RingKind::Main, 4), None);
+        assert_eq!(reader.read(RingKind::Main, 3, entry, |r| r), Some(40..45));
+        assert_eq!(
+            reader.read(RingKind::Main, MAX_ENTRIES - 1, entry, |r| r),
+            Some(7..12)
+        );
+        // Lengths are checked against the entry the reader found in the ring.
+        assert_eq!(reader.read(RingKind::Favorites, 3, entry, |r| r), None);
+
+        writer.clear(RingKind::Main, 3);
+        assert_eq!(writer.get(RingKind::Main, 3), None);
+        assert_eq!(reader.read(RingKind::Main, 3, entry, |r| r), None);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn entries_appended_later_are_readable() {
+        let dir = env::temp_dir().join(format!("ringboard-packed-entries-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = File::create_new(dir.join("log")).unwrap();
+        let mut writer = PackedIndexWriter::open(&dir.join("index")).unwrap();
+        let reader = PackedEntries::open(
+            PackedIndex::open(&dir.join("index")).unwrap(),
+            &dir.join("log"),
+        )
+        .unwrap();
+        let entry = InitializedEntry::packed(5);
+        assert_eq!(reader.get(RingKind::Main, 0, entry), None);
+
+        log.write_all_at(b"hello", 0).unwrap();
+        writer.set(RingKind::Main, 0, 0, 5);
+        log.write_all_at(b"world", 1 << 20).unwrap();
+        writer.set(RingKind::Favorites, 0, 1 << 20, 5);
+        assert_eq!(reader.get(RingKind::Main, 0, entry), Some(&b"hello"[..]));
+        assert_eq!(
+            reader.get(RingKind::Favorites, 0, entry),
+            Some(&b"world"[..])
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn readers_give_up_on_moved_entries() {
+        let path = env::temp_dir().join(format!("ringboard-packed-index-moved-{}", process::id()));
+        let mut writer = PackedIndexWriter::open(&path).unwrap();
+        let reader = PackedIndex::open(&path).unwrap();
+        let entry = InitializedEntry::packed(3);
+        writer.set(RingKind::Main, 0, 10, 3);
+
+        let moved = reader.read(RingKind::Main, 0, entry, |r| {
+            writer.set(RingKind::Main, 0, 0, 3);
+            r
+        });
+        assert_eq!(moved, None);
+        assert_eq!(reader.read(RingKind::Main, 0, entry, |r| r), Some(0..3));
+
+        let removed = reader.read(RingKind::Main, 0, entry, |r| {
+            writer.clear(RingKind::Main, 0);
+            r
+        });
+        assert_eq!(removed, None);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn copies_never_overlap_moves() {
+        const LEN: u16 = 64;
+        const MOVES: u8 = 200;
+
+        let dir = env::temp_dir().join(format!("ringboard-packed-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index");
+        let log = File::create_new(dir.join("log")).unwrap();
+        log.write_all_at(&[0; 2 * LEN as usize], 0).unwrap();
+
+        let entry = InitializedEntry::packed(LEN);
+        let mut writer = PackedIndexWriter::open(&path).unwrap();
+        writer.set(RingKind::Main, 0, 0, LEN);
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let reader = PackedIndex::open(&path).unwrap();
+                let mem = Mmap::from(&log).unwrap();
+                let done = &done;
+                s.spawn(move || {
+                    while !done.load(Ordering::Acquire) {
+                        let Some(copy) = reader.read(RingKind::Main, 0, entry, |r| mem[r].to_vec())
+                        else {
+                            continue;
+                        };
+                        assert!(copy.iter().all(|&b| b == copy[0]), "Torn copy: {copy:?}");
+                    }
+                });
+            }
+
+            // Like compaction, only overwrite the half no record points at.
+            for n in 1..=MOVES {
+                let (from, _) = writer.get(RingKind::Main, 0).unwrap();
+                let to = u32::from(LEN) - from;
+                for i in 0..LEN {
+                    log.write_all_at(&[n], u64::from(to + u32::from(i)))
+                        .unwrap();
+                }
+                writer.set(RingKind::Main, 0, to, LEN);
+            }
+            done.store(true, Ordering::Release);
+        });
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,13 +1,36 @@
-use std::ffi::CStr;
+use std::{
+    ffi::CStr,
+    fmt::{Debug, Display, Formatter},
+    mem::offset_of,
+    ptr,
+};
 
 use arrayvec::ArrayString;
 
-use crate::AsBytes;
+use crate::{tags::TagSet, AsBytes, NUM_BUCKETS};
 
-pub const VERSION: u8 = 0;
+/// The newest protocol version this build speaks.
+pub const VERSION: u8 = 3;
+
+/// The oldest protocol version this build still speaks. Clients and servers
+/// settle on the newest version they both speak while connecting.
+pub const MIN_VERSION: u8 = 1;
+
+/// The first protocol version whose clients say which versions they speak
+/// instead of only the newest one.
+pub const RANGED_VERSION: u8 = 2;
+
+/// Sent in place of the version to clients that connect more often than the
+/// server allows, right before they're disconnected.
+pub const RATE_LIMITED: u8 = u8::MAX;
+
+/// Sent in place of the version to clients that don't run as the server's
+/// user, right before they're disconnected.
+pub const PERMISSION_DENIED: u8 = u8::MAX - 1;
 
 #[repr(u8)]
 #[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RingKind {
     Favorites,
     #[default]
@@ -31,6 +54,16 @@ impl RingKind {
         }
     }
 
+    /// What ids of the ring's entries start with in their
+    /// [friendly form](format_id).
+    #[must_use]
+    pub const fn id_prefix(&self) -> &'static str {
+        match self {
+            Self::Main => "main",
+            Self::Favorites => "fav",
+        }
+    }
+
     #[must_use]
     pub const fn default_max_entries(&self) -> u32 {
         match self {
@@ -45,38 +78,277 @@ impl RingKind {
 // enough while still letting the Request fit in two cache lines.
 pub type MimeType = ArrayString<96>;
 
+/// A short user-provided description of an entry, sized like the mime type so
+/// the Request still fits in two cache lines.
+pub type Label = ArrayString<96>;
+
+/// What a [`Request::Search`] looks for, sized like the mime type so the
+/// Request still fits in two cache lines.
+pub type SearchQuery = ArrayString<96>;
+
+/// A short name entries can be grouped under, see [`tags`](crate::tags).
+pub type Tag = ArrayString<{ crate::tags::MAX_TAG_LEN }>;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub enum Request {
-    Add { to: RingKind, mime_type: MimeType },
-    MoveToFront { id: u64, to: Option<RingKind> },
-    Swap { id1: u64, id2: u64 },
-    Remove { id: u64 },
-    GarbageCollect { max_wasted_bytes: u64 },
+    Add {
+        to: RingKind,
+        /// Whether the data is sensitive, e.g. a password a password manager
+        /// copied, which the server deletes once it expires.
+        ///
+        /// Added in version 3: adds from older clients are never sensitive.
+        sensitive: bool,
+        mime_type: MimeType,
+        /// When the data was originally copied in seconds since the Unix
+        /// epoch, e.g. for imported entries, or zero for now.
+        ///
+        /// Added in version 2: adds from older clients are copied now.
+        copied_at: u64,
+    },
+    MoveToFront {
+        id: u64,
+        to: Option<RingKind>,
+    },
+    Swap {
+        id1: u64,
+        id2: u64,
+    },
+    Remove {
+        id: u64,
+    },
+    GarbageCollect {
+        max_wasted_bytes: u64,
+    },
+    /// Reads `len` bytes of an entry starting at `offset`, or everything
+    /// after `offset` if `len` is [`u32::MAX`].
+    Read {
+        id: u64,
+        offset: u64,
+        len: u32,
+    },
+    List {
+        ring: RingKind,
+        start: u32,
+        count: u32,
+    },
+    Stats,
+    ServerInfo,
+    TrimMemory,
+    /// Records which entry owns the system clipboard, or that none does once
+    /// something that isn't in the database was copied.
+    SetClipboard {
+        id: Option<u64>,
+    },
+    /// Labels an entry, or removes its label if the label is empty.
+    SetLabel {
+        id: u64,
+        label: Label,
+    },
+    /// Backs up the database into the configured backup directory in the
+    /// background.
+    BackupNow,
+    /// Announces that the next `len` requests in the same message, at most
+    /// [`MAX_BATCH_LEN`], are handled in order and answered in a single message
+    /// with their responses back to back. Batches can't carry reads, searches
+    /// or other batches, and their file descriptors are attached in request
+    /// order.
+    Batch {
+        len: u8,
+    },
+    /// Searches the database on the client's behalf for clients that can't
+    /// read it themselves. `query` is matched exactly as written unless it's a
+    /// regex.
+    ///
+    /// Up to `limit` matches are streamed back in as many [`SearchResponse`]s
+    /// as needed, all carrying this request's sequence number. Only the last
+    /// one has a status other than [`SearchStatus::More`]. Searches can't be
+    /// batched.
+    Search {
+        query: SearchQuery,
+        regex: bool,
+        limit: u32,
+    },
+    /// Adds a removed entry back at the front of the ring it was removed from.
+    ///
+    /// The server keeps the last few removed entries in a trash, each under a
+    /// tombstone named after the id the entry had when it was removed. Only the
+    /// most recent removal under an id can be restored.
+    Restore {
+        tombstone_id: u64,
+    },
+    /// Permanently deletes the entries in the trash.
+    EmptyTrash,
+    /// Reads the database generation, which the server bumps whenever it
+    /// changes the database.
+    ///
+    /// Clients that can read the database use the generation file instead
+    /// (see [`Generation`](crate::generation::Generation)), so this is for
+    /// those that only have the socket.
+    Generation,
+    /// Tags an entry. Entries can have several tags as long as they fit in
+    /// [`MAX_TAGS_LEN`](crate::tags::MAX_TAGS_LEN) bytes.
+    AddTag {
+        id: u64,
+        tag: Tag,
+    },
+    RemoveTag {
+        id: u64,
+        tag: Tag,
+    },
+    ListTags {
+        id: u64,
+    },
 }
 
 const _: () = assert!(size_of::<Request>() <= 128);
+// Servers reject requests shorter than their own, so growing requests would cut
+// off older clients.
+const _: () = assert!(size_of::<Request>() == 120);
+
+/// The most requests a [`Request::Batch`] carries.
+pub const MAX_BATCH_LEN: usize = 4;
+
+impl Request {
+    /// How many kinds of requests this protocol version defines, which must be
+    /// bumped whenever a request is added.
+    pub const KINDS: u32 = 21;
+
+    /// Identifies the kind of request on the wire.
+    #[must_use]
+    pub fn tag(&self) -> u32 {
+        u32::from_ne_bytes(self.as_bytes()[..size_of::<u32>()].try_into().unwrap())
+    }
+
+    /// Reads a request sent by a client speaking protocol `version`, rejecting
+    /// kinds of requests added after this build instead of misinterpreting
+    /// them.
+    ///
+    /// Fields that didn't exist yet in `version` are reset to what their
+    /// absence means since older clients leave them as padding.
+    ///
+    /// # Panics
+    ///
+    /// If `bytes` is shorter than a request.
+    pub fn decode(bytes: &[u8], version: u8) -> Result<Self, UnknownRequest> {
+        assert!(bytes.len() >= size_of::<Self>());
+        let tag = u32::from_ne_bytes(bytes[..size_of::<u32>()].try_into().unwrap());
+        if tag >= Self::KINDS {
+            return Err(UnknownRequest { tag });
+        }
+        let mut bytes = <[u8; size_of::<Self>()]>::try_from(&bytes[..size_of::<Self>()]).unwrap();
+        let (add_tag, sensitive_offset) = Self::sensitive_flag();
+        // Unlike other fields, padding isn't necessarily a valid flag.
+        if version < 3 && tag == add_tag {
+            bytes[sensitive_offset] = 0;
+        }
+        let mut request = unsafe { bytes.as_ptr().cast::<Self>().read_unaligned() };
+        if version < 2 {
+            if let Self::Add { copied_at, .. } = &mut request {
+                *copied_at = 0;
+            }
+        }
+        Ok(request)
+    }
+
+    /// The tag of [`Self::Add`] and where its `sensitive` flag is in its bytes.
+    fn sensitive_flag() -> (u32, usize) {
+        let add = Self::Add {
+            to: RingKind::Main,
+            sensitive: false,
+            mime_type: MimeType::new(),
+            copied_at: 0,
+        };
+        let Self::Add { sensitive, .. } = &add else {
+            unreachable!()
+        };
+        (
+            add.tag(),
+            ptr::from_ref(sensitive) as usize - ptr::from_ref(&add) as usize,
+        )
+    }
+}
 
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct Response<T> {
     pub sequence_number: u64,
+    pub trace: TraceId,
     pub value: T,
 }
 
+/// Identifies a handled request in the server's logs so client errors can be
+/// matched up with what the server did.
+#[repr(transparent)]
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct TraceId(u32);
+
+impl TraceId {
+    #[must_use]
+    pub const fn new(sequence_number: u64, client: u8) -> Self {
+        // Scramble the bits so consecutive requests look different in logs. The
+        // multiplier is odd, so a client's ids only repeat every 2^19 requests.
+        #[allow(clippy::cast_possible_truncation)]
+        let id = ((sequence_number as u32) << 5) | (client as u32 & 0x1F);
+        Self(id.wrapping_mul(0x9E37_79B1) & 0xFF_FFFF)
+    }
+}
+
+impl Display for TraceId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:06x}", self.0)
+    }
+}
+
+impl Debug for TraceId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 #[must_use]
 pub enum AddResponse {
-    Success { id: u64 },
+    Success {
+        id: u64,
+        /// The server cleaned up the entry's text before storing it, e.g. by
+        /// removing tracking parameters from a URL.
+        normalized: bool,
+        /// The same client added identical data moments ago, so that entry's
+        /// id is returned instead of storing a copy.
+        coalesced: bool,
+        /// Identical data was added recently, so that entry was moved to the
+        /// front instead of storing a copy. Its previous id is no longer valid.
+        deduplicated: bool,
+        /// The entry was larger than the server is configured to store, so only
+        /// its start was kept.
+        truncated: bool,
+    },
+    /// The server ran out of disk space while storing the entry.
+    DatabaseFull,
+    /// The entry's data was sent in a file the server won't read from, e.g. a
+    /// pipe or socket that might never be written to, or its data didn't
+    /// arrive in time.
+    UnsupportedData,
+    /// The entry had no data.
+    Empty,
+    /// The entry's data was larger than the server is configured to store
+    /// and the server isn't configured to truncate it.
+    TooLarge { max: u64 },
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 #[must_use]
 pub enum MoveToFrontResponse {
-    Success { id: u64 },
+    Success {
+        id: u64,
+    },
     Error(IdNotFoundError),
+    /// Moving the entry into the favorites would have overwritten the oldest
+    /// favorite, which has to be removed first.
+    FavoritesFull,
 }
 
 #[repr(C)]
@@ -99,15 +371,470 @@ pub struct RemoveResponse {
 #[must_use]
 pub struct GarbageCollectResponse {
     pub bytes_freed: u64,
+    /// The part of `bytes_freed` that came from truncating each bucket file.
+    pub bucket_bytes_freed: [u64; NUM_BUCKETS],
+    /// The part of `bytes_freed` that came from compacting the packed log.
+    pub packed_bytes_freed: u64,
 }
 
+/// Bucketed entries are always smaller than this, so they can be returned
+/// inline while larger entries are sent back as a file descriptor.
+pub const MAX_INLINE_READ_LEN: usize = 4096;
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ReadOutcome {
+    /// The requested bytes are in [`ReadResponse::data`]. Ranges that are too
+    /// long to fit get sent as a file instead.
+    Inline,
+    /// The requested bytes are in a file attached to the response via
+    /// `SCM_RIGHTS`: the entry's own file if the whole entry was asked for, or
+    /// a copy of the range otherwise.
+    File,
+    Error(IdNotFoundError),
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[must_use]
+pub struct ReadResponse {
+    pub outcome: ReadOutcome,
+    pub len: u16,
+    pub data: [u8; MAX_INLINE_READ_LEN],
+}
+
+impl ReadResponse {
+    /// # Panics
+    ///
+    /// If `data` is longer than [`MAX_INLINE_READ_LEN`].
+    pub fn inline(data: &[u8]) -> Self {
+        let mut response = Self::with_outcome(ReadOutcome::Inline);
+        response.len = u16::try_from(data.len()).unwrap();
+        response.data[..data.len()].copy_from_slice(data);
+        response
+    }
+
+    pub const fn file() -> Self {
+        Self::with_outcome(ReadOutcome::File)
+    }
+
+    pub const fn error(e: IdNotFoundError) -> Self {
+        Self::with_outcome(ReadOutcome::Error(e))
+    }
+
+    const fn with_outcome(outcome: ReadOutcome) -> Self {
+        Self {
+            outcome,
+            len: 0,
+            data: [0; MAX_INLINE_READ_LEN],
+        }
+    }
+
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data[..usize::from(self.len).min(MAX_INLINE_READ_LEN)]
+    }
+}
+
+impl Debug for ReadResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadResponse")
+            .field("outcome", &self.outcome)
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+pub const MAX_LIST_ENTRIES: usize = 256;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[must_use]
+pub struct ListResponse {
+    pub write_head: u32,
+    pub len: u32,
+    pub count: u32,
+    pub entries: [ListedEntry; MAX_LIST_ENTRIES],
+}
+
+impl ListResponse {
+    #[must_use]
+    pub fn entries(&self) -> &[ListedEntry] {
+        &self.entries[..usize::try_from(self.count).unwrap().min(MAX_LIST_ENTRIES)]
+    }
+}
+
+impl Debug for ListResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ListResponse")
+            .field("write_head", &self.write_head)
+            .field("len", &self.len)
+            .field("entries", &self.entries())
+            .finish_non_exhaustive()
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct ListedEntry {
+    pub index: u32,
+    /// Zero for entries stored in their own file.
+    pub size: u16,
+    /// [`MAX_ENTRIES`](crate::ring::MAX_ENTRIES) for packed entries.
+    pub bucket_index: u32,
+}
+
+/// The most matches a single [`SearchResponse`] carries.
+pub const MAX_SEARCH_MATCHES: usize = 128;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[must_use]
+pub struct SearchResponse {
+    pub status: SearchStatus,
+    pub count: u32,
+    pub matches: [SearchMatch; MAX_SEARCH_MATCHES],
+}
+
+impl SearchResponse {
+    #[must_use]
+    pub fn matches(&self) -> &[SearchMatch] {
+        &self.matches[..usize::try_from(self.count).unwrap().min(MAX_SEARCH_MATCHES)]
+    }
+}
+
+impl Debug for SearchResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SearchResponse")
+            .field("status", &self.status)
+            .field("matches", &self.matches())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where a search matched, with the same meaning as the local search's
+/// results.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct SearchMatch {
+    /// The composite id of the matching entry.
+    pub id: u64,
+    pub start: u64,
+    pub end: u64,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SearchStatus {
+    /// More matches follow in later responses.
+    More,
+    /// The search finished or reached its limit.
+    Done,
+    /// The query wasn't a valid regex, so nothing was searched.
+    InvalidRegex,
+    /// The server couldn't read part of the database, so matches may be
+    /// missing.
+    Failed,
+}
+
+/// The database checks performed when the server last started.
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum StartupState {
+    /// The previous server shut down cleanly, so no checks were needed.
+    Clean,
+    /// The previous server crashed, but the most recent entries were intact.
+    FastChecked,
+    /// The database had to be scanned and repaired.
+    Recovered,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub struct StatsResponse {
+    pub last_startup: StartupState,
+    pub buckets: [BucketUsage; NUM_BUCKETS],
+    /// Requests rejected for breaking the protocol, e.g. by carrying an
+    /// unexpected number of file descriptors.
+    pub rejected_requests: u64,
+    /// How often the server trimmed its memory because the system was low on
+    /// it.
+    pub memory_pressure_events: u64,
+    /// Completions the kernel discarded because the server fell behind on
+    /// processing them.
+    pub dropped_completions: u64,
+    /// Connections turned away for arriving faster than the configured rate.
+    pub rejected_connections: u64,
+    /// Adds answered with an identical entry the same client added moments
+    /// before.
+    pub coalesced_adds: u64,
+    /// Adds that moved a recent identical entry to the front instead of
+    /// storing a copy.
+    pub deduplicated_adds: u64,
+    /// Main ring entries deleted for being older than the configured maximum
+    /// age.
+    pub aged_out_entries: u64,
+    pub favorites: u32,
+    /// How many favorites fit before new ones are refused.
+    pub max_favorites: u32,
+    /// How far the main ring has been written. Removed entries leave holes
+    /// that are still counted.
+    pub main_len: u32,
+    /// How many entries the main ring holds before the oldest are overwritten.
+    pub main_capacity: u32,
+    pub direct: DirectUsage,
+    /// When the server started in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// Clients connected when the stats were taken, including the one asking.
+    pub connected_clients: u32,
+    /// Clients that connected since the server started.
+    pub total_connections: u64,
+    /// The entry last copied or pasted through Ringboard if it still owns the
+    /// system clipboard as far as the server knows.
+    pub current_clipboard: Option<u64>,
+    pub backups: BackupStats,
+    /// Missing for databases created without packing short entries.
+    pub packed: Option<PackedUsage>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct BackupStats {
+    /// When the last backup was taken in seconds since the Unix epoch, or zero
+    /// if there are none.
+    pub last_backup: u64,
+    /// Backups that failed since the server started.
+    pub failures: u64,
+    /// Files copied so far by the backup being taken, or by the last one.
+    pub copied_files: u32,
+    /// Files the backup being taken will copy, or that the last one did.
+    pub total_files: u32,
+    pub in_progress: bool,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct BucketUsage {
+    /// Bytes covered by slots, including free ones.
+    pub used_bytes: u64,
+    /// Bytes reserved on disk, including preallocated space past the end of
+    /// the bucket.
+    pub allocated_bytes: u64,
+    pub slots: u32,
+    /// Slots waiting to be reused by new entries.
+    pub free_slots: u32,
+}
+
+/// Short text entries packed into a log rather than bucket slots.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct PackedUsage {
+    pub entries: u32,
+    /// The combined length of the entries.
+    pub live_bytes: u64,
+    /// How far the log has been written, including what removed entries left
+    /// behind until the next compaction.
+    pub log_bytes: u64,
+    /// Bytes reserved on disk by the log.
+    pub allocated_bytes: u64,
+}
+
+/// Entries stored in files of their own rather than in a bucket: anything that
+/// isn't text, and text too large for a bucket.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct DirectUsage {
+    pub files: u64,
+    /// The combined size of the files' contents.
+    pub bytes: u64,
+}
+
+/// The server's build information.
+///
+/// Clients and servers that share a protocol version are compatible
+/// regardless of their release versions.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub struct ServerInfoResponse {
+    pub version: ArrayString<32>,
+    /// The commit the server was built from or `unknown` outside a git
+    /// checkout.
+    pub git_hash: ArrayString<16>,
+}
+
+/// The server's resident set size around a memory trim, or zero if it
+/// couldn't be determined.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub struct TrimMemoryResponse {
+    pub resident_bytes_before: u64,
+    pub resident_bytes_after: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub struct SetClipboardResponse {
+    pub error: Option<IdNotFoundError>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub struct SetLabelResponse {
+    pub error: Option<IdNotFoundError>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub enum RestoreResponse {
+    /// The entry was added back like any other entry, and is only taken out of
+    /// the trash if that succeeded.
+    Added(AddResponse),
+    /// Nothing removed under that id is left in the trash, e.g. because later
+    /// removals pushed it out.
+    NotInTrash,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub struct EmptyTrashResponse {
+    pub purged: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub enum TagResponse {
+    /// Whether the entry's tags changed: adding a tag it already has or
+    /// removing one it doesn't is a no-op.
+    Success {
+        changed: bool,
+    },
+    Error(IdNotFoundError),
+    /// The tag isn't a run of `[a-z0-9_-]`, see
+    /// [`tags::is_valid`](crate::tags::is_valid).
+    InvalidTag,
+    /// The entry's tags would no longer fit.
+    TooManyTags,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub struct ListTagsResponse {
+    pub error: Option<IdNotFoundError>,
+    pub tags: TagSet,
+}
+
+/// Any difference from an earlier generation means the database changed.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub struct GenerationResponse {
+    pub generation: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[must_use]
+pub struct BackupResponse {
+    pub outcome: BackupOutcome,
+    /// When the last backup was taken in seconds since the Unix epoch, or zero
+    /// if there are none.
+    pub last_backup: u64,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum BackupOutcome {
+    /// The backup was started. Its progress and outcome are reported in
+    /// [`StatsResponse::backups`].
+    Started,
+    /// A backup is already being taken.
+    InProgress,
+    /// The database hasn't changed since the last backup.
+    Unchanged,
+    /// No backup directory is configured.
+    NotConfigured,
+}
+
+/// Sent in place of the expected response when the server doesn't know the
+/// kind of request it received, typically because the client is newer.
 #[repr(C)]
 #[derive(Copy, Clone, thiserror::Error, Debug)]
+#[error("unknown request: {tag}")]
+#[must_use]
+pub struct UnknownRequest {
+    pub tag: u32,
+}
+
+/// Sent in place of the expected response when the server couldn't carry out
+/// a request, e.g. because the entry's data couldn't be read.
+///
+/// Batches are answered with this alone, stopping at the failed request: the
+/// ones before it took effect.
+///
+/// Unlike other responses, this one is sent without its trailing padding so
+/// that its length, [`RequestFailed::LEN`], can't be mistaken for that of any
+/// response or batch of responses.
+#[repr(C)]
+#[derive(Copy, Clone, thiserror::Error, Debug)]
+#[error("{code}: {context}")]
+#[must_use]
+pub struct RequestFailed {
+    pub code: ErrorCode,
+    /// What went wrong in the server's words, cut short if needed.
+    pub context: ArrayString<120>,
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, thiserror::Error, Debug)]
+pub enum ErrorCode {
+    #[error("the server couldn't read the entry's data")]
+    UnreadableData,
+    #[error("the server couldn't update its database")]
+    Io,
+    #[error("the request broke the protocol")]
+    ProtocolViolation,
+}
+
+impl RequestFailed {
+    /// How many bytes a [`Response`] carrying this error takes up on the wire.
+    pub const LEN: usize = offset_of!(Response<Self>, value) + size_of::<Self>();
+
+    pub fn new(code: ErrorCode, context: &str) -> Self {
+        let mut truncated = ArrayString::new();
+        for c in context.chars() {
+            if truncated.try_push(c).is_err() {
+                break;
+            }
+        }
+        Self {
+            code,
+            context: truncated,
+        }
+    }
+}
+
+// Every other response is padded to a multiple of its alignment.
+const _: () = assert!(!RequestFailed::LEN.is_multiple_of(align_of::<Response<UnknownRequest>>()));
+
+#[repr(C)]
+#[derive(Copy, Clone, Eq, PartialEq, thiserror::Error, Debug)]
 pub enum IdNotFoundError {
     #[error("invalid ring ID: {0}")]
     Ring(u32),
     #[error("invalid entry ID: {0}")]
     Entry(u32),
+    /// Never sent by the server: only ids typed by people can be malformed.
+    #[error("malformed ID: expected a number or one like main-42")]
+    Malformed,
 }
 
 #[must_use]
@@ -124,10 +851,152 @@ pub fn decompose_id(id: u64) -> Result<(RingKind, u32), IdNotFoundError> {
     .map(|ring| (ring, u32::try_from(id & u64::from(u32::MAX)).unwrap()))
 }
 
+/// Formats an id the way people read and type it, e.g. `main-42` or `fav-1`.
+/// Ids of unknown rings are left as numbers.
+#[must_use]
+pub fn format_id(id: u64) -> String {
+    match decompose_id(id) {
+        Ok((ring, index)) => format!("{}-{index}", ring.id_prefix()),
+        Err(_) => id.to_string(),
+    }
+}
+
+/// Parses an id written by [`format_id`] or as the raw number.
+pub fn parse_id(text: &str) -> Result<u64, IdNotFoundError> {
+    let text = text.trim();
+    let Some((prefix, index)) = text.split_once('-') else {
+        let id = text.parse().map_err(|_| IdNotFoundError::Malformed)?;
+        return decompose_id(id).map(|_| id);
+    };
+    let ring = [RingKind::Favorites, RingKind::Main]
+        .into_iter()
+        .find(|ring| prefix.eq_ignore_ascii_case(ring.id_prefix()))
+        .ok_or(IdNotFoundError::Malformed)?;
+    let index = index.parse().map_err(|_| IdNotFoundError::Malformed)?;
+    Ok(composite_id(ring, index))
+}
+
 impl AsBytes for Request {}
+impl AsBytes for TraceId {}
 
 impl AsBytes for AddResponse {}
 impl AsBytes for MoveToFrontResponse {}
 impl AsBytes for SwapResponse {}
 impl AsBytes for RemoveResponse {}
 impl AsBytes for GarbageCollectResponse {}
+impl AsBytes for ReadResponse {}
+impl AsBytes for ListResponse {}
+impl AsBytes for StatsResponse {}
+impl AsBytes for ServerInfoResponse {}
+impl AsBytes for TrimMemoryResponse {}
+impl AsBytes for SetClipboardResponse {}
+impl AsBytes for SetLabelResponse {}
+impl AsBytes for RestoreResponse {}
+impl AsBytes for EmptyTrashResponse {}
+impl AsBytes for GenerationResponse {}
+impl AsBytes for TagResponse {}
+impl AsBytes for ListTagsResponse {}
+impl AsBytes for BackupResponse {}
+impl AsBytes for SearchResponse {}
+impl AsBytes for UnknownRequest {}
+impl AsBytes for RequestFailed {}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        composite_id, decompose_id, format_id, parse_id, IdNotFoundError, MimeType, Request,
+        RingKind, UnknownRequest, VERSION,
+    };
+    use crate::AsBytes;
+
+    #[test]
+    fn kinds_covers_every_request() {
+        let last = Request::ListTags { id: 0 };
+        assert_eq!(last.tag(), Request::KINDS - 1);
+    }
+
+    #[test]
+    fn known_requests_are_decoded() {
+        let request = Request::decode(Request::Remove { id: 42 }.as_bytes(), VERSION).unwrap();
+        assert!(matches!(request, Request::Remove { id: 42 }));
+    }
+
+    #[test]
+    fn fields_older_clients_dont_know_are_reset() {
+        let add = Request::Add {
+            to: RingKind::Main,
+            sensitive: true,
+            mime_type: MimeType::new(),
+            copied_at: 1_700_000_000,
+        };
+        let request = Request::decode(add.as_bytes(), VERSION).unwrap();
+        assert!(matches!(
+            request,
+            Request::Add {
+                sensitive: true,
+                copied_at: 1_700_000_000,
+                ..
+            }
+        ));
+        // Version 2 clients send whatever was in the padding where the flag is now.
+        let mut bytes = add.as_bytes().to_vec();
+        bytes[Request::sensitive_flag().1] = 0xAA;
+        let request = Request::decode(&bytes, 2).unwrap();
+        assert!(matches!(
+            request,
+            Request::Add {
+                sensitive: false,
+                copied_at: 1_700_000_000,
+                ..
+            }
+        ));
+        // Version 1 clients send whatever was in the padding where the time is now.
+        let request = Request::decode(add.as_bytes(), 1).unwrap();
+        assert!(matches!(request, Request::Add { copied_at: 0, .. }));
+    }
+
+    #[test]
+    fn requests_from_newer_clients_are_rejected() {
+        // What a client with one more kind of request than this build would send.
+        let mut fixture = [0xAA; size_of::<Request>()];
+        fixture[..size_of::<u32>()].copy_from_slice(&Request::KINDS.to_ne_bytes());
+
+        let UnknownRequest { tag } = Request::decode(&fixture, VERSION).unwrap_err();
+        assert_eq!(tag, Request::KINDS);
+
+        fixture[..size_of::<u32>()].copy_from_slice(&u32::MAX.to_ne_bytes());
+        assert_eq!(
+            Request::decode(&fixture, VERSION).unwrap_err().tag,
+            u32::MAX
+        );
+    }
+
+    #[test]
+    fn ids_round_trip_through_both_forms() {
+        for ring in [RingKind::Favorites, RingKind::Main] {
+            for index in [0, 1, u32::MAX / 2, u32::MAX - 1, u32::MAX] {
+                let id = composite_id(ring, index);
+                assert_eq!(decompose_id(id), Ok((ring, index)));
+                assert_eq!(parse_id(&format_id(id)), Ok(id), "{}", format_id(id));
+                assert_eq!(parse_id(&id.to_string()), Ok(id));
+            }
+        }
+        assert_eq!(format_id(composite_id(RingKind::Favorites, 1)), "fav-1");
+        assert_eq!(format_id(composite_id(RingKind::Main, 42)), "main-42");
+        assert_eq!(parse_id(" MAIN-42 "), Ok(composite_id(RingKind::Main, 42)));
+    }
+
+    #[test]
+    fn bad_ids_are_rejected() {
+        let unknown_ring = 2 << 32;
+        assert_eq!(format_id(unknown_ring), "8589934592");
+        assert_eq!(
+            parse_id(&unknown_ring.to_string()),
+            Err(IdNotFoundError::Ring(2))
+        );
+        let past_the_end = format!("main-{}", u64::from(u32::MAX) + 1);
+        for text in ["", "main", "main-", "-1", "trash-1", "1e3", &past_the_end] {
+            assert_eq!(parse_id(text), Err(IdNotFoundError::Malformed), "{text}");
+        }
+    }
+}
@@ -1,22 +1,51 @@
+//! The rings listing a database's entries, which clients map and read while
+//! the server writes them.
+//!
+//! Readers take no locks, so the server and readers agree on the following
+//! instead:
+//!
+//! - The server writes everything an entry is made of first: its bucket slot or
+//!   its direct file along with the file's extended attributes. Direct files
+//!   are written while unnamed and only then linked into place, so they never
+//!   appear partially written.
+//! - It then publishes the entry by storing it into its ring slot with release
+//!   ordering, through a shared mapping so the 4 byte store can't be torn. New
+//!   slots are created uninitialized before anything is stored in them.
+//! - Entries are unpublished by storing [`Entry::Uninitialized`] before their
+//!   storage is freed, and the write head is stored with release ordering after
+//!   the entry it points past.
+//! - Readers load slots and the write head with acquire ordering, so whatever a
+//!   loaded entry points at is fully written.
+//!
+//! A reader holding on to an entry can still race the server reusing its
+//! storage after the entry is overwritten or removed. Readers therefore check
+//! the word of a bucketed entry's slot before and after reading it as described
+//! in [`crate::slot_generations`], treating entries whose slot was written to
+//! in the meantime as gone. The slot is read in place, so bytes held past that
+//! check may change once the entry is removed. Packed entries are checked the
+//! same way through their record, see [`crate::packed`].
+
 use std::{
     fmt::{Debug, Formatter},
     fs, io,
     io::ErrorKind,
-    ops::Deref,
+    ops::{Deref, Range},
     os::fd::{AsFd, AsRawFd},
     path::PathBuf,
     ptr,
     ptr::NonNull,
     slice,
+    sync::atomic::{AtomicU32, Ordering},
 };
 
 use rustix::{
     fs::{openat, statx, AtFlags, Mode, OFlags, StatxFlags, CWD},
-    mm::{mmap, mremap, munmap, MapFlags, MremapFlags, ProtFlags},
+    mm::{madvise, mmap, mremap, munmap, Advice, MapFlags, MremapFlags, ProtFlags},
+    param::page_size,
     path::Arg,
 };
 
-use crate::{Error, IoErr, Result};
+use crate::{Context, Error, IoErr, Result};
 
 pub const MAX_ENTRIES: u32 = (1 << 20) - 1;
 
@@ -25,12 +54,24 @@ pub struct Ring {
     mem: Mmap,
     len: u32,
     capacity: u32,
+    packed: bool,
     #[cfg(debug_assertions)]
     fd: std::os::fd::OwnedFd,
 }
 
 pub const MAGIC: [u8; 3] = [0x4D, 0x18, 0x32];
 pub const VERSION: u8 = 0;
+/// Where [`Header::write_head`] lives in the ring file.
+pub const WRITE_HEAD_OFFSET: usize = MAGIC.len() + size_of::<u8>();
+/// Set in a ring's version byte when its database is encrypted at rest.
+///
+/// This build can't read encrypted buckets and direct files. Refusing such
+/// rings up front keeps plaintext from being mixed into an encrypted database.
+pub const ENCRYPTED: u8 = 1 << 7;
+/// Set in the main ring's version byte when its database was created with
+/// short text entries packed into a log instead of bucket slots. See
+/// [`crate::packed`].
+pub const PACKED: u8 = 1 << 6;
 
 #[repr(C)]
 pub struct Header {
@@ -66,7 +107,9 @@ impl From<Entry> for RawEntry {
     fn from(value: Entry) -> Self {
         match value {
             Entry::Uninitialized => Self(0),
-            Entry::Bucketed(InitializedEntry(data)) => Self(data),
+            Entry::Bucketed(InitializedEntry(data)) | Entry::Packed(InitializedEntry(data)) => {
+                Self(data)
+            }
             Entry::File => Self(InitializedEntry::file().0),
         }
     }
@@ -82,6 +125,8 @@ impl From<RawEntry> for Entry {
         let entry = InitializedEntry(value);
         if entry.is_file() {
             Self::File
+        } else if entry.is_packed() {
+            Self::Packed(entry)
         } else {
             Self::Bucketed(entry)
         }
@@ -93,6 +138,20 @@ pub enum Entry {
     Uninitialized,
     Bucketed(InitializedEntry),
     File,
+    /// A short text entry kept in the packed log, only found in databases
+    /// created with packing turned on.
+    Packed(InitializedEntry),
+}
+
+/// The number of ring slots in each state, as returned by
+/// [`Ring::count_by_state`].
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+pub struct SlotCounts {
+    pub initialized: u32,
+    pub uninitialized: u32,
+    pub bucketed: u32,
+    pub file: u32,
+    pub packed: u32,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash)]
@@ -107,6 +166,15 @@ impl InitializedEntry {
         Self((index << 12) | u32::from(size))
     }
 
+    /// Packed entries are located through their ring slot, so their index is
+    /// left at a value no bucket slot can have.
+    #[must_use]
+    pub fn packed(size: u16) -> Self {
+        debug_assert!(size > 0);
+        debug_assert!(size < (1 << 12));
+        Self((MAX_ENTRIES << 12) | u32::from(size))
+    }
+
     #[must_use]
     pub const fn file() -> Self {
         Self(1 << (u32::BITS - 1))
@@ -126,12 +194,21 @@ impl InitializedEntry {
     pub fn is_file(&self) -> bool {
         self.size() == 0
     }
+
+    #[must_use]
+    pub fn is_packed(&self) -> bool {
+        self.index() == MAX_ENTRIES && !self.is_file()
+    }
 }
 
 impl Debug for InitializedEntry {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         if self.is_file() {
             f.write_str("File")
+        } else if self.is_packed() {
+            f.debug_struct("Packed")
+                .field("size", &self.size())
+                .finish()
         } else {
             f.debug_struct("Bucketed")
                 .field("size", &self.size())
@@ -148,6 +225,8 @@ pub struct Mmap {
     backing_len: usize,
 }
 
+// SAFETY: the mapping is owned and only ever written through atomics.
+// Remapping needs a mutable reference, so it can't race with readers.
 unsafe impl Send for Mmap {}
 unsafe impl Sync for Mmap {}
 
@@ -158,13 +237,22 @@ impl Mmap {
     }
 
     pub fn new<Fd: AsFd>(fd: Fd, len: usize) -> rustix::io::Result<Self> {
+        Self::map(fd, len, ProtFlags::READ)
+    }
+
+    /// Maps the file for [`Mmap::store_word`] to write to.
+    pub fn new_writable<Fd: AsFd>(fd: Fd, len: usize) -> rustix::io::Result<Self> {
+        Self::map(fd, len, ProtFlags::READ | ProtFlags::WRITE)
+    }
+
+    fn map<Fd: AsFd>(fd: Fd, len: usize, prot: ProtFlags) -> rustix::io::Result<Self> {
         let backing_len = len.max(4096);
         Ok(Self {
             ptr: unsafe {
                 NonNull::new_unchecked(mmap(
                     ptr::null_mut(),
                     backing_len,
-                    ProtFlags::READ,
+                    prot,
                     MapFlags::SHARED_VALIDATE,
                     fd,
                     0,
@@ -209,6 +297,41 @@ impl Mmap {
         self.ptr
     }
 
+    /// Loads the little-endian word at `offset` with acquire ordering.
+    ///
+    /// # Panics
+    ///
+    /// If the word is misaligned or lies past the mapping.
+    #[must_use]
+    pub fn load_word(&self, offset: usize) -> u32 {
+        u32::from_le(self.word(offset).load(Ordering::Acquire))
+    }
+
+    /// Stores a little-endian word at `offset` with release ordering, see the
+    /// [module docs](self).
+    ///
+    /// # Safety
+    ///
+    /// The mapping must be [writable](Mmap::new_writable) and the file must
+    /// extend past the word.
+    ///
+    /// # Panics
+    ///
+    /// If the word is misaligned or lies past the mapping.
+    pub unsafe fn store_word(&self, offset: usize, value: u32) {
+        self.word(offset).store(value.to_le(), Ordering::Release);
+    }
+
+    fn word(&self, offset: usize) -> &AtomicU32 {
+        assert!(
+            offset.is_multiple_of(align_of::<AtomicU32>())
+                && offset + size_of::<u32>() <= self.len()
+        );
+        // SAFETY: the word is aligned and mapped for as long as self lives. Mappings
+        // start on page boundaries, so offsets are aligned like addresses.
+        unsafe { AtomicU32::from_ptr(self.ptr.as_ptr().add(offset).cast()) }
+    }
+
     #[must_use]
     pub const fn len(&self) -> usize {
         self.requested_len
@@ -218,6 +341,70 @@ impl Mmap {
     pub const fn is_empty(&self) -> bool {
         self.requested_len == 0
     }
+
+    /// Asks the kernel to reclaim the mapped pages.
+    ///
+    /// The mapping is read-only and shared, so its pages are always clean
+    /// copies of the file: they are read back in on the next access and never
+    /// lost. The kernel leaves pages mapped by other processes alone.
+    pub fn page_out(&self) -> rustix::io::Result<()> {
+        unsafe {
+            madvise(
+                self.ptr.as_ptr().cast(),
+                self.backing_len,
+                Advice::LinuxPageOut,
+            )
+        }
+    }
+
+    /// Asks the kernel to start reading the mapped pages in so that first
+    /// accesses don't each wait on the disk. Like [`Mmap::page_out`], this is
+    /// only a hint.
+    pub fn prefetch(&self) -> rustix::io::Result<()> {
+        unsafe {
+            madvise(
+                self.ptr.as_ptr().cast(),
+                self.requested_len,
+                Advice::WillNeed,
+            )
+        }
+    }
+
+    /// Like [`Mmap::prefetch`], but only for the pages holding `ranges`,
+    /// which must be sorted. Ranges less than a page apart are requested
+    /// together so scattered small reads turn into a few larger ones.
+    pub fn prefetch_ranges(
+        &self,
+        ranges: impl IntoIterator<Item = Range<usize>>,
+    ) -> rustix::io::Result<()> {
+        let page = page_size();
+        let mut pending = None::<Range<usize>>;
+        for Range { start, end } in ranges {
+            let (start, end) = (start / page * page, end.min(self.requested_len));
+            if start >= end {
+                continue;
+            }
+            match &mut pending {
+                Some(run) if start <= run.end + page => run.end = run.end.max(end),
+                _ => {
+                    if let Some(run) = pending.replace(start..end) {
+                        self.will_need(run)?;
+                    }
+                }
+            }
+        }
+        pending.map_or(Ok(()), |run| self.will_need(run))
+    }
+
+    fn will_need(&self, Range { start, end }: Range<usize>) -> rustix::io::Result<()> {
+        unsafe {
+            madvise(
+                self.ptr.as_ptr().add(start).cast(),
+                end - start,
+                Advice::WillNeed,
+            )
+        }
+    }
 }
 
 impl Deref for Mmap {
@@ -252,7 +439,23 @@ impl Ring {
         let len = statx(&fd, c"", AtFlags::EMPTY_PATH, StatxFlags::SIZE)
             .map_io_err(|| "Failed to statx Ringboard database file.")?
             .stx_size;
-        let len = usize::try_from(len).unwrap();
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        let path = || {
+            fs::read_link(PathBuf::from(format!(
+                "/proc/self/fd/{}",
+                fd.as_fd().as_raw_fd()
+            )))
+            .unwrap_or_else(|_| PathBuf::from("unknown"))
+        };
+        if offset_to_entries(len) > MAX_ENTRIES {
+            return Err(Error::Io {
+                error: io::Error::new(ErrorKind::InvalidData, "Not a Ringboard database."),
+                context: Context::File {
+                    message: "Ring file holds more entries than a ring can",
+                    path: path(),
+                },
+            });
+        }
         let max_entries = max_entries.clamp(offset_to_entries(len), MAX_ENTRIES);
         let mem = Mmap::new(
             &fd,
@@ -263,21 +466,32 @@ impl Ring {
         if len < MAGIC.len()
             || unsafe { slice::from_raw_parts(mem.ptr().as_ptr(), MAGIC.len()) } != MAGIC
         {
-            let path = fs::read_link(PathBuf::from(format!(
-                "/proc/self/fd/{}",
-                fd.as_fd().as_raw_fd()
-            )))
-            .unwrap_or_else(|_| PathBuf::from("unknown"));
             return Err(Error::Io {
                 error: io::Error::new(ErrorKind::InvalidData, "Not a Ringboard database."),
-                context: format!("Ring file has invalid magic header: {path:?}").into(),
+                context: format!("Ring file has invalid magic header: {:?}", path()).into(),
             });
         }
+        if len > MAGIC.len() && unsafe { *mem.ptr().as_ptr().add(MAGIC.len()) } & ENCRYPTED != 0 {
+            return Err(Error::Io {
+                error: io::Error::new(
+                    ErrorKind::Unsupported,
+                    "Encrypted Ringboard databases aren't supported by this build.",
+                ),
+                context: Context::File {
+                    message: "Ring file is encrypted at rest",
+                    path: path(),
+                },
+            });
+        }
+
+        let packed =
+            len > MAGIC.len() && unsafe { *mem.ptr().as_ptr().add(MAGIC.len()) } & PACKED != 0;
 
         Ok(Self {
             mem,
             len: offset_to_entries(len),
             capacity: max_entries,
+            packed,
             #[cfg(debug_assertions)]
             fd: fd
                 .as_fd()
@@ -322,18 +536,25 @@ impl Ring {
         self.capacity
     }
 
+    /// Whether the ring's version byte has the [`PACKED`] flag.
+    #[must_use]
+    pub const fn is_packed(&self) -> bool {
+        self.packed
+    }
+
+    /// See [`Mmap::page_out`].
+    pub fn page_out(&self) -> rustix::io::Result<()> {
+        self.mem.page_out()
+    }
+
+    /// See [`Mmap::prefetch`].
+    pub fn prefetch(&self) -> rustix::io::Result<()> {
+        self.mem.prefetch()
+    }
+
     #[must_use]
     pub fn write_head(&self) -> u32 {
-        let bytes = unsafe {
-            slice::from_raw_parts(
-                self.mem
-                    .ptr()
-                    .as_ptr()
-                    .add(MAGIC.len() + size_of_val(&VERSION)),
-                size_of::<u32>(),
-            )
-        };
-        u32::from_le_bytes(bytes.try_into().unwrap())
+        self.mem.load_word(WRITE_HEAD_OFFSET)
     }
 
     #[must_use]
@@ -377,18 +598,34 @@ impl Ring {
             return None;
         }
 
-        let bytes = unsafe {
-            slice::from_raw_parts(
-                self.mem
-                    .ptr()
-                    .as_ptr()
-                    .add(usize::try_from(entries_to_offset(index)).unwrap()),
-                size_of::<u32>(),
-            )
-        };
-        let raw = RawEntry(u32::from_le_bytes(bytes.try_into().unwrap()));
+        let raw = RawEntry(
+            self.mem
+                .load_word(usize::try_from(entries_to_offset(index)).unwrap()),
+        );
         Some(Entry::from(raw))
     }
+
+    /// Iterates through every slot in storage order, including uninitialized
+    /// holes that ring readers skip.
+    #[must_use]
+    pub fn slots(&self) -> impl ExactSizeIterator<Item = (u32, Entry)> + '_ {
+        (0..self.len()).map(|index| (index, self.get(index).unwrap()))
+    }
+
+    #[must_use]
+    pub fn count_by_state(&self) -> SlotCounts {
+        let mut counts = SlotCounts::default();
+        for (_, entry) in self.slots() {
+            match entry {
+                Entry::Uninitialized => counts.uninitialized += 1,
+                Entry::Bucketed(_) => counts.bucketed += 1,
+                Entry::File => counts.file += 1,
+                Entry::Packed(_) => counts.packed += 1,
+            }
+        }
+        counts.initialized = counts.bucketed + counts.file + counts.packed;
+        counts
+    }
 }
 
 #[must_use]
@@ -399,5 +636,157 @@ pub fn entries_to_offset(entries: u32) -> u64 {
 
 #[must_use]
 pub fn offset_to_entries(offset: usize) -> u32 {
-    u32::try_from(offset.saturating_sub(size_of::<Header>()) / size_of::<RawEntry>()).unwrap()
+    u32::try_from(offset.saturating_sub(size_of::<Header>()) / size_of::<RawEntry>())
+        .unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io, process};
+
+    use super::{
+        entries_to_offset, Entry, Header, InitializedEntry, Mmap, RawEntry, Ring, SlotCounts,
+        ENCRYPTED, MAX_ENTRIES, PACKED, WRITE_HEAD_OFFSET,
+    };
+    use crate::{Context, Error, Result};
+
+    fn open_fixture(name: &str, version: u8, entries: &[Entry]) -> Result<Ring> {
+        let Header {
+            magic,
+            version: _,
+            write_head,
+        } = Header::default();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&magic);
+        bytes.push(version);
+        bytes.extend_from_slice(&write_head.to_le_bytes());
+        for &entry in entries {
+            bytes.extend_from_slice(&RawEntry::from(entry).to_le_bytes());
+        }
+
+        let path = env::temp_dir().join(format!("ringboard-ring-{name}-{}", process::id()));
+        fs::write(&path, bytes).unwrap();
+        let ring = Ring::open(0, &path);
+        fs::remove_file(path).unwrap();
+        ring
+    }
+
+    fn fixture(name: &str, entries: &[Entry]) -> Ring {
+        open_fixture(name, Header::default().version, entries).unwrap()
+    }
+
+    #[test]
+    fn encrypted_rings_are_refused() {
+        let result = open_fixture("encrypted", Header::default().version | ENCRYPTED, &[]);
+
+        let Err(Error::Io {
+            error,
+            context: Context::File { .. },
+        }) = result
+        else {
+            panic!("Opened an encrypted ring.");
+        };
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn oversized_rings_are_refused() {
+        let entries = vec![Entry::File; usize::try_from(MAX_ENTRIES).unwrap() + 1];
+        let result = open_fixture("oversized", Header::default().version, &entries);
+
+        let Err(Error::Io {
+            error,
+            context: Context::File { .. },
+        }) = result
+        else {
+            panic!("Opened a ring with more entries than it can hold.");
+        };
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn slots_include_holes() {
+        let entries = [
+            Entry::Bucketed(InitializedEntry::bucket(3, 0)),
+            Entry::Uninitialized,
+            Entry::File,
+            Entry::Uninitialized,
+            Entry::Uninitialized,
+            Entry::Bucketed(InitializedEntry::bucket(42, 7)),
+            Entry::File,
+            Entry::Uninitialized,
+            Entry::Packed(InitializedEntry::packed(42)),
+        ];
+        let ring = fixture("holes", &entries);
+
+        assert_eq!(
+            ring.slots().collect::<Vec<_>>(),
+            (0..).zip(entries).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            ring.count_by_state(),
+            SlotCounts {
+                initialized: 5,
+                uninitialized: 4,
+                bucketed: 2,
+                file: 2,
+                packed: 1,
+            }
+        );
+        assert!(!ring.is_packed());
+    }
+
+    #[test]
+    fn packed_rings_are_flagged() {
+        let entries = [
+            Entry::Packed(InitializedEntry::packed(1)),
+            Entry::Packed(InitializedEntry::packed((1 << 12) - 1)),
+            Entry::Bucketed(InitializedEntry::bucket(1, MAX_ENTRIES - 1)),
+        ];
+        let ring = open_fixture("packed", Header::default().version | PACKED, &entries).unwrap();
+
+        assert!(ring.is_packed());
+        assert_eq!(
+            ring.slots().map(|(_, entry)| entry).collect::<Vec<_>>(),
+            entries
+        );
+    }
+
+    #[test]
+    fn stores_are_visible_to_readers() {
+        let path = env::temp_dir().join(format!("ringboard-ring-stores-{}", process::id()));
+        let mut bytes = Header::default().magic.to_vec();
+        bytes.push(Header::default().version);
+        bytes.extend_from_slice(&[0; 4 + 2 * size_of::<RawEntry>()]);
+        fs::write(&path, bytes).unwrap();
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        fs::remove_file(path).unwrap();
+
+        let ring = Ring::open_fd(2, &file).unwrap();
+        let writer = Mmap::new_writable(&file, ring.mem.len()).unwrap();
+        assert_eq!(ring.get(1), Some(Entry::Uninitialized));
+
+        let entry = Entry::Bucketed(InitializedEntry::bucket(42, 7));
+        unsafe {
+            writer.store_word(
+                usize::try_from(entries_to_offset(1)).unwrap(),
+                *RawEntry::from(entry),
+            );
+            writer.store_word(WRITE_HEAD_OFFSET, 2);
+        }
+        assert_eq!(ring.get(1), Some(entry));
+        assert_eq!(ring.write_head(), 2);
+    }
+
+    #[test]
+    fn empty_ring_has_no_slots() {
+        let ring = fixture("empty", &[]);
+
+        assert_eq!(ring.slots().len(), 0);
+        assert_eq!(ring.count_by_state(), SlotCounts::default());
+    }
 }
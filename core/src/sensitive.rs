@@ -0,0 +1,44 @@
+use std::{ffi::CStr, os::fd::AsFd, str};
+
+use rustix::{
+    fs::{fgetxattr, fsetxattr, XattrFlags},
+    io::Errno,
+};
+
+use crate::{IoErr, Result};
+
+/// The extended attribute marking a direct file as holding sensitive data,
+/// e.g. a password.
+///
+/// Its value is when the server deletes the entry in seconds since the Unix
+/// epoch. Only direct files have somewhere to record this, so sensitive
+/// entries are never bucketed.
+pub const XATTR_NAME: &CStr = c"user.sensitive";
+
+/// Marks a file as sensitive until `expires_at`.
+pub fn write(fd: impl AsFd, expires_at: u64) -> Result<()> {
+    fsetxattr(
+        fd,
+        XATTR_NAME,
+        expires_at.to_string().as_bytes(),
+        XattrFlags::empty(),
+    )
+    .map_io_err(|| "Failed to create sensitivity attribute.")
+}
+
+/// Reads when a sensitive file expires, if it's sensitive. Unreadable expiry
+/// times read as already expired so the data doesn't outstay its welcome.
+pub fn read(fd: impl AsFd) -> Result<Option<u64>> {
+    let mut value = [0; 20];
+    let len = match fgetxattr(fd, XATTR_NAME, &mut value) {
+        Err(Errno::NODATA) => return Ok(None),
+        Err(Errno::RANGE) => return Ok(Some(0)),
+        r => r.map_io_err(|| "Failed to read extended attributes.")?,
+    };
+    Ok(Some(
+        str::from_utf8(&value[..len])
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0),
+    ))
+}
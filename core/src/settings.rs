@@ -0,0 +1,341 @@
+//! The parts of the server's settings file that clients need too.
+//!
+//! The file lives in the database directory and contains `key = value` lines;
+//! blank lines and lines starting with `#` are ignored.
+
+use std::{fs, io, io::ErrorKind, path::PathBuf};
+
+use crate::{protocol::RingKind, ring::MAX_ENTRIES, Error, IoErr, PathView, Result};
+
+pub const FILE_NAME: &str = "settings";
+
+/// How many entries each ring holds before the oldest ones are overwritten.
+///
+/// Readers map rings up to their capacity, so they must agree with the server
+/// on it.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct RingCapacities {
+    pub main: u32,
+    pub favorites: u32,
+}
+
+impl Default for RingCapacities {
+    fn default() -> Self {
+        Self {
+            main: RingKind::Main.default_max_entries(),
+            favorites: RingKind::Favorites.default_max_entries(),
+        }
+    }
+}
+
+impl RingCapacities {
+    /// Reads the capacities from the settings file in `database_dir`, falling
+    /// back to the defaults if there is none.
+    pub fn load(database_dir: &mut PathBuf) -> Result<Self> {
+        load(database_dir, Self::parse)
+    }
+
+    /// Parses the capacity settings, ignoring every other line.
+    pub fn parse(contents: &str) -> std::result::Result<Self, String> {
+        parse(contents, Self::parse_setting)
+    }
+
+    /// Applies `key = value` if it's a capacity setting. Returns whether the
+    /// value was valid, or [`None`] for other settings.
+    pub fn parse_setting(&mut self, key: &str, value: &str) -> Option<bool> {
+        let kind = [RingKind::Main, RingKind::Favorites]
+            .into_iter()
+            .find(|&kind| Self::key(kind) == key)?;
+        let capacity = match kind {
+            RingKind::Main => &mut self.main,
+            RingKind::Favorites => &mut self.favorites,
+        };
+        let Some(parsed) = value
+            .parse::<u32>()
+            .ok()
+            .filter(|entries| (1..=MAX_ENTRIES).contains(entries))
+        else {
+            return Some(false);
+        };
+        *capacity = parsed;
+        Some(true)
+    }
+
+    #[must_use]
+    pub const fn get(&self, kind: RingKind) -> u32 {
+        match kind {
+            RingKind::Main => self.main,
+            RingKind::Favorites => self.favorites,
+        }
+    }
+
+    /// The settings key for a ring's capacity.
+    #[must_use]
+    pub const fn key(kind: RingKind) -> &'static str {
+        match kind {
+            RingKind::Main => "main_max_entries",
+            RingKind::Favorites => "favorites_max_entries",
+        }
+    }
+}
+
+/// How large entries may be and what happens to those that are larger.
+///
+/// Clients that fetch data before adding it, like the clipboard watchers, read
+/// these too so they don't transfer data the server won't store.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct EntryLimits {
+    /// The largest entry in bytes that will be stored.
+    pub max_entry_size: u64,
+    /// Store the first `max_entry_size` bytes of larger entries and mark them
+    /// as truncated instead of rejecting them.
+    pub truncate_oversized_entries: bool,
+}
+
+impl Default for EntryLimits {
+    fn default() -> Self {
+        Self {
+            max_entry_size: 1 << 30,
+            truncate_oversized_entries: false,
+        }
+    }
+}
+
+impl EntryLimits {
+    /// Reads the limits from the settings file in `database_dir`, falling back
+    /// to the defaults if there is none.
+    pub fn load(database_dir: &mut PathBuf) -> Result<Self> {
+        load(database_dir, Self::parse)
+    }
+
+    /// Parses the limit settings, ignoring every other line.
+    pub fn parse(contents: &str) -> std::result::Result<Self, String> {
+        parse(contents, Self::parse_setting)
+    }
+
+    /// Applies `key = value` if it's a limit setting. Returns whether the value
+    /// was valid, or [`None`] for other settings.
+    pub fn parse_setting(&mut self, key: &str, value: &str) -> Option<bool> {
+        match key {
+            "max_entry_size" => {
+                let Some(size) = value.parse::<u64>().ok().filter(|&size| size > 0) else {
+                    return Some(false);
+                };
+                self.max_entry_size = size;
+            }
+            "truncate_oversized_entries" => {
+                let Ok(truncate) = value.parse() else {
+                    return Some(false);
+                };
+                self.truncate_oversized_entries = truncate;
+            }
+            _ => return None,
+        }
+        Some(true)
+    }
+
+    /// What to do with an entry of `size` bytes.
+    #[must_use]
+    pub const fn check(&self, size: u64) -> SizeCheck {
+        if size <= self.max_entry_size {
+            SizeCheck::Fits
+        } else if self.truncate_oversized_entries {
+            SizeCheck::Truncate(self.max_entry_size)
+        } else {
+            SizeCheck::Reject
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SizeCheck {
+    Fits,
+    /// Store only this many bytes.
+    Truncate(u64),
+    Reject,
+}
+
+/// What happens to entries that password managers mark as sensitive.
+///
+/// The clipboard watchers read these too since they're the ones that notice
+/// the marks.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct SensitiveEntries {
+    /// Don't add sensitive entries at all instead of adding them to expire.
+    pub skip_sensitive_entries: bool,
+    /// How many seconds sensitive entries are kept before they're deleted.
+    pub sensitive_entry_ttl: u64,
+}
+
+impl Default for SensitiveEntries {
+    fn default() -> Self {
+        Self {
+            skip_sensitive_entries: false,
+            sensitive_entry_ttl: 60,
+        }
+    }
+}
+
+impl SensitiveEntries {
+    /// Reads the sensitivity settings from the settings file in
+    /// `database_dir`, falling back to the defaults if there is none.
+    pub fn load(database_dir: &mut PathBuf) -> Result<Self> {
+        load(database_dir, Self::parse)
+    }
+
+    /// Parses the sensitivity settings, ignoring every other line.
+    pub fn parse(contents: &str) -> std::result::Result<Self, String> {
+        parse(contents, Self::parse_setting)
+    }
+
+    /// Applies `key = value` if it's a sensitivity setting. Returns whether the
+    /// value was valid, or [`None`] for other settings.
+    pub fn parse_setting(&mut self, key: &str, value: &str) -> Option<bool> {
+        match key {
+            "skip_sensitive_entries" => {
+                let Ok(skip) = value.parse() else {
+                    return Some(false);
+                };
+                self.skip_sensitive_entries = skip;
+            }
+            "sensitive_entry_ttl" => {
+                let Some(ttl) = value.parse::<u64>().ok().filter(|&ttl| ttl > 0) else {
+                    return Some(false);
+                };
+                self.sensitive_entry_ttl = ttl;
+            }
+            _ => return None,
+        }
+        Some(true)
+    }
+}
+
+fn load<T: Default>(
+    database_dir: &mut PathBuf,
+    parse: impl FnOnce(&str) -> std::result::Result<T, String>,
+) -> Result<T> {
+    let file = PathView::new(database_dir, FILE_NAME);
+    let contents = match fs::read_to_string(&*file) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(T::default()),
+        r => r.map_io_err(|| format!("Failed to read settings file: {file:?}"))?,
+    };
+    parse(&contents).map_err(|context| Error::Io {
+        error: io::Error::new(ErrorKind::InvalidData, "Invalid settings file."),
+        context: context.into(),
+    })
+}
+
+fn parse<T: Default>(
+    contents: &str,
+    mut parse_setting: impl FnMut(&mut T, &str, &str) -> Option<bool>,
+) -> std::result::Result<T, String> {
+    let mut settings = T::default();
+    for (number, line) in (1..).zip(contents.lines()) {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if parse_setting(&mut settings, key, value) == Some(false) {
+            return Err(format!(
+                "Invalid value for {key:?} on line {number}: {value:?}"
+            ));
+        }
+    }
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EntryLimits, RingCapacities, SensitiveEntries, SizeCheck};
+
+    #[test]
+    fn capacities_are_read_from_settings() {
+        let capacities = RingCapacities::parse(
+            "\
+# Other settings are left to the server.
+max_entry_size = 1000
+main_max_entries = 5000
+favorites_max_entries = 10
+",
+        )
+        .unwrap();
+        assert_eq!(
+            capacities,
+            RingCapacities {
+                main: 5000,
+                favorites: 10
+            }
+        );
+        assert_eq!(
+            RingCapacities::parse("").unwrap(),
+            RingCapacities::default()
+        );
+        assert!(RingCapacities::parse("main_max_entries = 0").is_err());
+        assert!(RingCapacities::parse("favorites_max_entries = 1048576").is_err());
+    }
+
+    #[test]
+    fn entry_limits_are_read_from_settings() {
+        let limits = EntryLimits::parse(
+            "\
+main_max_entries = 5000
+max_entry_size = 1000
+truncate_oversized_entries = true
+",
+        )
+        .unwrap();
+        assert_eq!(
+            limits,
+            EntryLimits {
+                max_entry_size: 1000,
+                truncate_oversized_entries: true
+            }
+        );
+        assert_eq!(EntryLimits::parse("").unwrap(), EntryLimits::default());
+        assert!(EntryLimits::parse("max_entry_size = 0").is_err());
+        assert!(EntryLimits::parse("truncate_oversized_entries = yes").is_err());
+    }
+
+    #[test]
+    fn oversized_entries_are_rejected_or_truncated() {
+        let mut limits = EntryLimits {
+            max_entry_size: 1000,
+            truncate_oversized_entries: false,
+        };
+        assert_eq!(limits.check(1000), SizeCheck::Fits);
+        assert_eq!(limits.check(1001), SizeCheck::Reject);
+
+        limits.truncate_oversized_entries = true;
+        assert_eq!(limits.check(1000), SizeCheck::Fits);
+        assert_eq!(limits.check(1001), SizeCheck::Truncate(1000));
+    }
+
+    #[test]
+    fn sensitivity_is_read_from_settings() {
+        let sensitive = SensitiveEntries::parse(
+            "\
+max_entry_size = 1000
+skip_sensitive_entries = true
+sensitive_entry_ttl = 30
+",
+        )
+        .unwrap();
+        assert_eq!(
+            sensitive,
+            SensitiveEntries {
+                skip_sensitive_entries: true,
+                sensitive_entry_ttl: 30
+            }
+        );
+        assert_eq!(
+            SensitiveEntries::parse("").unwrap(),
+            SensitiveEntries::default()
+        );
+        assert!(SensitiveEntries::parse("sensitive_entry_ttl = 0").is_err());
+        assert!(SensitiveEntries::parse("skip_sensitive_entries = 1").is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! A word per bucket slot, which lets readers check that a bucketed entry is
+//! still in its slot seqlock style.
+//!
+//! Each word holds the length of the entry in its slot along with a
+//! generation bumped on every write to the slot. While writing, the server
+//! publishes the new generation with a length of zero and only stores the
+//! entry's length once done, both with [`Mmap::store_word`]. Readers load the
+//! word before and after reading the slot: what they read is their entry only
+//! if both loads returned the same word with their entry's length.
+//!
+//! A crash in the middle of a write leaves the word without a length, so the
+//! server records the slots of the entries in its rings again on startup.
+
+use std::{
+    fmt::Debug,
+    io,
+    io::ErrorKind,
+    sync::atomic::{fence, Ordering},
+};
+
+use rustix::{
+    fs::{ftruncate, openat, Mode, OFlags, CWD},
+    path::Arg,
+};
+
+use crate::{
+    ring::{InitializedEntry, Mmap, MAX_ENTRIES},
+    size_to_bucket, Error, IoErr, Result, NUM_BUCKETS,
+};
+
+pub const FILE_NAME: &str = "slot_generations";
+
+/// Every slot a bucket could have gets a word, so the file is sparse.
+const FILE_LEN: usize = NUM_BUCKETS * (MAX_ENTRIES as usize + 1) * size_of::<u32>();
+
+/// Bucketed entries are shorter than 4 KiB, leaving the rest of the word to
+/// the generation.
+const LEN_BITS: u32 = 12;
+
+fn word_offset(entry: InitializedEntry) -> usize {
+    let bucket = usize::from(size_to_bucket(entry.size()));
+    (bucket * (MAX_ENTRIES as usize + 1) + usize::try_from(entry.index()).unwrap())
+        * size_of::<u32>()
+}
+
+fn len(word: u32) -> u16 {
+    u16::try_from(word & ((1 << LEN_BITS) - 1)).unwrap()
+}
+
+/// The words of every bucket slot, as published by the server.
+///
+/// Databases written by older servers don't have this file.
+#[derive(Debug)]
+pub struct SlotGenerations {
+    mem: Mmap,
+}
+
+impl SlotGenerations {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        let fd = openat(CWD, path, OFlags::RDONLY, Mode::empty())
+            .map_io_err(|| format!("Failed to open slot generations file: {path:?}"))?;
+        let mem = Mmap::from(&fd)
+            .map_io_err(|| format!("Failed to mmap slot generations file: {path:?}"))?;
+        if mem.len() < FILE_LEN {
+            return Err(Error::Io {
+                error: io::Error::new(ErrorKind::InvalidData, "Slot generations file too short."),
+                context: format!("Slot generations file has not been initialized: {path:?}").into(),
+            });
+        }
+        Ok(Self { mem })
+    }
+
+    /// Runs `read` on the entry's slot, returning what it read unless the
+    /// server wrote to the slot in the meantime.
+    ///
+    /// Returns [`None`] if the slot is being written to, holds an entry of a
+    /// different length, or got a new generation while `read` ran, since the
+    /// entry must have been removed for the server to reuse its slot.
+    pub fn read<T>(&self, entry: InitializedEntry, read: impl FnOnce() -> T) -> Option<T> {
+        let offset = word_offset(entry);
+        let before = self.mem.load_word(offset);
+        if len(before) != entry.size() {
+            return None;
+        }
+        let read = read();
+        // Keeps the slot's loads from sinking past the second check.
+        fence(Ordering::Acquire);
+        (self.mem.load_word(offset) == before).then_some(read)
+    }
+}
+
+/// The server's writable view of the [`SlotGenerations`].
+#[derive(Debug)]
+pub struct SlotGenerationsWriter(SlotGenerations);
+
+impl SlotGenerationsWriter {
+    /// Opens the file, recording the slots of `entries` as holding them where
+    /// an older server or a crash in the middle of a write left them
+    /// unrecorded.
+    pub fn open<P: Arg + Copy + Debug>(
+        path: P,
+        entries: impl IntoIterator<Item = InitializedEntry>,
+    ) -> Result<Self> {
+        let fd = openat(
+            CWD,
+            path,
+            OFlags::RDWR | OFlags::CREATE,
+            Mode::RUSR | Mode::WUSR,
+        )
+        .map_io_err(|| format!("Failed to open slot generations file: {path:?}"))?;
+        ftruncate(&fd, u64::try_from(FILE_LEN).unwrap())
+            .map_io_err(|| format!("Failed to size slot generations file: {path:?}"))?;
+        let mem = Mmap::new_writable(&fd, FILE_LEN)
+            .map_io_err(|| format!("Failed to mmap slot generations file: {path:?}"))?;
+
+        let mut writer = Self(SlotGenerations { mem });
+        for entry in entries {
+            let offset = word_offset(entry);
+            if len(writer.0.mem.load_word(offset)) != entry.size() {
+                let generation = writer.next_generation(offset);
+                writer.store(offset, generation | u32::from(entry.size()));
+            }
+        }
+        Ok(writer)
+    }
+
+    /// Runs `write` to fill the slot `entry` is about to occupy, so that
+    /// readers retry any copies overlapping it and give up on the slot's
+    /// previous entry.
+    ///
+    /// The slot is left without an entry if `write` fails.
+    pub fn write<T, E>(
+        &mut self,
+        entry: InitializedEntry,
+        write: impl FnOnce() -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        let offset = word_offset(entry);
+        let generation = self.next_generation(offset);
+        self.store(offset, generation);
+        // Keeps the slot's writes from rising above the emptied word.
+        fence(Ordering::Release);
+        let written = write()?;
+        self.store(offset, generation | u32::from(entry.size()));
+        Ok(written)
+    }
+
+    fn next_generation(&self, offset: usize) -> u32 {
+        // There is only ever one writer, so plain stores are enough.
+        let word = self.0.mem.load_word(offset);
+        ((word >> LEN_BITS).wrapping_add(1)) << LEN_BITS
+    }
+
+    fn store(&mut self, offset: usize, word: u32) {
+        // SAFETY: the mapping is writable and the file was sized to fit every slot.
+        unsafe { self.0.mem.store_word(offset, word) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::{self, File},
+        io,
+        os::unix::fs::FileExt,
+        process,
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+    };
+
+    use super::{SlotGenerations, SlotGenerationsWriter};
+    use crate::ring::{InitializedEntry, Mmap};
+
+    #[test]
+    fn copies_never_overlap_writes() {
+        const LEN: u16 = 256;
+        const WRITES: u8 = 200;
+
+        let dir = env::temp_dir().join(format!("ringboard-slot-generations-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("generations");
+        let slot = File::create_new(dir.join("slot")).unwrap();
+        slot.write_all_at(&[0; LEN as usize], 0).unwrap();
+
+        let entry = InitializedEntry::bucket(LEN, 7);
+        let mut writer = SlotGenerationsWriter::open(&path, [entry]).unwrap();
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let reader = SlotGenerations::open(&path).unwrap();
+                let mem = Mmap::from(&slot).unwrap();
+                let done = &done;
+                s.spawn(move || {
+                    while !done.load(Ordering::Acquire) {
+                        let Some(copy) = reader.read(entry, || mem.to_vec()) else {
+                            continue;
+                        };
+                        assert!(copy.iter().all(|&b| b == copy[0]), "Torn copy: {copy:?}");
+                    }
+                });
+            }
+
+            for n in 1..=WRITES {
+                writer
+                    .write(entry, || {
+                        (0..LEN).try_for_each(|i| slot.write_all_at(&[n], u64::from(i)))
+                    })
+                    .unwrap();
+            }
+            done.store(true, Ordering::Release);
+        });
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn readers_give_up_on_reused_slots() {
+        let path = env::temp_dir().join(format!(
+            "ringboard-slot-generations-reuse-{}",
+            process::id()
+        ));
+        let old = InitializedEntry::bucket(20, 5);
+        let mut writer = SlotGenerationsWriter::open(&path, [old]).unwrap();
+        let reader = SlotGenerations::open(&path).unwrap();
+        assert_eq!(reader.read(old, || 1), Some(1));
+
+        let new = InitializedEntry::bucket(17, 5);
+        writer.write(new, || Ok::<_, io::Error>(())).unwrap();
+        assert_eq!(reader.read(old, || 1), None);
+        assert_eq!(reader.read(new, || 2), Some(2));
+
+        // An entry of the same length taking over the slot mid-read only shows
+        // in the generation.
+        let reused = reader.read(new, || {
+            writer.write(new, || Ok::<_, io::Error>(())).unwrap();
+            2
+        });
+        assert_eq!(reused, None);
+        assert_eq!(reader.read(new, || 2), Some(2));
+
+        writer
+            .write(old, || Err::<(), _>(io::Error::other("failed")))
+            .unwrap_err();
+        assert_eq!(reader.read(old, || 1), None);
+        assert_eq!(reader.read(new, || 2), None);
+
+        // Entries written without recording their slot, e.g. by older servers.
+        drop(writer);
+        SlotGenerationsWriter::open(&path, [old]).unwrap();
+        assert_eq!(reader.read(old, || 1), Some(1));
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    ffi::CStr,
+    fmt::{Debug, Display, Formatter},
+    fs::File,
+    os::unix::fs::FileExt,
+    str,
+};
+
+use arrayvec::ArrayString;
+use rustix::{
+    fs::{openat, Mode, OFlags, CWD},
+    path::Arg,
+};
+
+use crate::{
+    protocol::{RingKind, Tag},
+    ring::MAX_ENTRIES,
+    IoErr, Result,
+};
+
+pub const FILE_NAME: &str = "tags";
+
+/// The extended attribute holding the tags of entries stored in files of
+/// their own. Bucketed entries keep theirs in the [`Tags`] file instead.
+pub const XATTR_NAME: &CStr = c"user.tags";
+
+/// The longest tag accepted.
+pub const MAX_TAG_LEN: usize = 24;
+
+/// How many bytes an entry's tags take up at most, separators included.
+pub const MAX_TAGS_LEN: usize = 64;
+
+const SEPARATOR: char = ',';
+
+/// Whether `tag` is a run of `[a-z0-9_-]` short enough to be stored, which is
+/// what the server accepts.
+#[must_use]
+pub fn is_valid(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= MAX_TAG_LEN
+        && tag
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || matches!(b, b'_' | b'-'))
+}
+
+/// Turns what someone typed into a tag, ignoring case and surrounding
+/// whitespace. Returns [`None`] if what's left isn't [valid](is_valid).
+#[must_use]
+pub fn parse(input: &str) -> Option<Tag> {
+    let tag = input.trim().to_ascii_lowercase();
+    is_valid(&tag).then(|| Tag::from(&tag).unwrap())
+}
+
+/// Adding the tag would have left no room for the entry's other tags.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TooManyTags;
+
+/// An entry's tags in the order they were added.
+#[repr(transparent)]
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct TagSet(ArrayString<MAX_TAGS_LEN>);
+
+impl TagSet {
+    /// Reads tags as stored, dropping anything that isn't a valid tag.
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        let mut tags = Self::default();
+        for tag in str::from_utf8(&bytes[..len])
+            .unwrap_or_default()
+            .split(SEPARATOR)
+            .filter(|tag| is_valid(tag))
+        {
+            if tags.insert(tag).is_err() {
+                break;
+            }
+        }
+        tags
+    }
+
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.split(SEPARATOR).filter(|tag| !tag.is_empty())
+    }
+
+    #[must_use]
+    pub fn contains(&self, tag: &str) -> bool {
+        self.iter().any(|t| t == tag)
+    }
+
+    /// Returns whether the tag is new.
+    ///
+    /// # Panics
+    ///
+    /// If the tag isn't [valid](is_valid).
+    pub fn insert(&mut self, tag: &str) -> std::result::Result<bool, TooManyTags> {
+        assert!(is_valid(tag), "invalid tag: {tag:?}");
+        if self.contains(tag) {
+            return Ok(false);
+        }
+        let separator = usize::from(!self.is_empty());
+        if self.0.len() + separator + tag.len() > MAX_TAGS_LEN {
+            return Err(TooManyTags);
+        }
+        if separator > 0 {
+            self.0.push(SEPARATOR);
+        }
+        self.0.push_str(tag);
+        Ok(true)
+    }
+
+    /// Returns whether the entry had the tag.
+    pub fn remove(&mut self, tag: &str) -> bool {
+        if !self.contains(tag) {
+            return false;
+        }
+        let old = self.0;
+        self.0.clear();
+        for t in old.split(SEPARATOR).filter(|&t| t != tag) {
+            let _ = self.insert(t);
+        }
+        true
+    }
+}
+
+impl Debug for TagSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Display for TagSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+/// Like origins, each entry has a fixed slot so the file is sparse and
+/// untagged entries read as zeros.
+fn slot_offset(ring: RingKind, index: u32) -> u64 {
+    (ring as u64 * (u64::from(MAX_ENTRIES) + 1) + u64::from(index))
+        * u64::try_from(MAX_TAGS_LEN).unwrap()
+}
+
+/// The tags of bucketed entries, which have no file to hold them.
+#[derive(Debug)]
+pub struct Tags {
+    file: File,
+}
+
+impl Tags {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        openat(CWD, path, OFlags::RDONLY, Mode::empty())
+            .map_io_err(|| format!("Failed to open tags file: {path:?}"))
+            .map(|fd| Self {
+                file: File::from(fd),
+            })
+    }
+
+    /// Missing and unreadable slots are treated as untagged.
+    #[must_use]
+    pub fn get(&self, ring: RingKind, index: u32) -> TagSet {
+        let mut slot = [0; MAX_TAGS_LEN];
+        if self
+            .file
+            .read_exact_at(&mut slot, slot_offset(ring, index))
+            .is_err()
+        {
+            return TagSet::default();
+        }
+        TagSet::from_bytes(&slot)
+    }
+}
+
+/// The server's writable view of the [`Tags`] file.
+#[derive(Debug)]
+pub struct TagsWriter(Tags);
+
+impl TagsWriter {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        openat(
+            CWD,
+            path,
+            OFlags::RDWR | OFlags::CREATE,
+            Mode::RUSR | Mode::WUSR,
+        )
+        .map_io_err(|| format!("Failed to open tags file: {path:?}"))
+        .map(|fd| {
+            Self(Tags {
+                file: File::from(fd),
+            })
+        })
+    }
+
+    pub fn set(&mut self, ring: RingKind, index: u32, tags: &TagSet) -> Result<()> {
+        let mut slot = [0; MAX_TAGS_LEN];
+        slot[..tags.as_bytes().len()].copy_from_slice(tags.as_bytes());
+        self.0
+            .file
+            .write_all_at(&slot, slot_offset(ring, index))
+            .map_io_err(|| format!("Failed to write tags of entry {index} in {ring:?} ring."))
+    }
+
+    #[must_use]
+    pub fn get(&self, ring: RingKind, index: u32) -> TagSet {
+        self.0.get(ring, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{is_valid, parse, TagSet, Tags, TagsWriter, TooManyTags, MAX_TAG_LEN};
+    use crate::{protocol::RingKind, ring::MAX_ENTRIES};
+
+    #[test]
+    fn tags_are_validated() {
+        let cases = [
+            ("work", Some("work")),
+            ("  To-Do_2\n", Some("to-do_2")),
+            ("two words", None),
+            ("a,b", None),
+            ("héllo", None),
+            ("", None),
+            (" \t", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse(input).as_deref(), expected, "{input:?}");
+        }
+        assert!(is_valid(&"a".repeat(MAX_TAG_LEN)));
+        assert!(!is_valid(&"a".repeat(MAX_TAG_LEN + 1)));
+        assert!(!is_valid("Work"));
+    }
+
+    #[test]
+    fn tag_sets_are_bounded() {
+        let mut tags = TagSet::default();
+        assert_eq!(tags.insert("work"), Ok(true));
+        assert_eq!(tags.insert("urgent"), Ok(true));
+        assert_eq!(tags.insert("work"), Ok(false));
+        assert_eq!(tags.iter().collect::<Vec<_>>(), ["work", "urgent"]);
+
+        assert!(tags.remove("work"));
+        assert!(!tags.remove("work"));
+        assert_eq!(tags.to_string(), "urgent");
+
+        let long = "x".repeat(MAX_TAG_LEN);
+        let mut full = TagSet::default();
+        for c in ['a', 'b'] {
+            full.insert(&format!("{c}{}", &long[1..])).unwrap();
+        }
+        assert_eq!(full.insert(&long), Err(TooManyTags));
+        assert_eq!(TagSet::from_bytes(full.as_bytes()), full);
+        assert_eq!(
+            TagSet::from_bytes(b"ok,Not Ok,,fine\0junk").to_string(),
+            "ok,fine"
+        );
+    }
+
+    #[test]
+    fn tags_are_kept_per_entry() {
+        let path = env::temp_dir().join(format!("ringboard-tags-{}", process::id()));
+        let mut writer = TagsWriter::open(&path).unwrap();
+        let mut work = TagSet::default();
+        work.insert("work").unwrap();
+
+        writer.set(RingKind::Main, 0, &work).unwrap();
+        writer
+            .set(RingKind::Favorites, MAX_ENTRIES - 1, &work)
+            .unwrap();
+
+        let reader = Tags::open(&path).unwrap();
+        assert_eq!(reader.get(RingKind::Main, 0), work);
+        assert_eq!(reader.get(RingKind::Favorites, MAX_ENTRIES - 1), work);
+        assert!(reader.get(RingKind::Main, 1).is_empty());
+
+        writer.set(RingKind::Main, 0, &TagSet::default()).unwrap();
+        assert!(reader.get(RingKind::Main, 0).is_empty());
+
+        fs::remove_file(path).unwrap();
+    }
+}
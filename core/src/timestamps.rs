@@ -0,0 +1,147 @@
+use std::{
+    fmt::Debug,
+    fs::File,
+    os::unix::fs::FileExt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rustix::{
+    fs::{openat, Mode, OFlags, CWD},
+    path::Arg,
+};
+
+use crate::{protocol::RingKind, ring::MAX_ENTRIES, IoErr, Result};
+
+pub const FILE_NAME: &str = "timestamps";
+
+const SLOT_SIZE: usize = size_of::<u32>();
+
+/// Timestamps are stored as minutes since the epoch which is plenty precise
+/// for showing ages and lasts well past the year 10000.
+fn to_slot(time: SystemTime) -> [u8; SLOT_SIZE] {
+    let minutes = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 60)
+        // Zero means unknown, so clamp anything before the epoch's first minute.
+        .max(1);
+    u32::try_from(minutes).unwrap_or(u32::MAX).to_le_bytes()
+}
+
+fn from_slot(slot: [u8; SLOT_SIZE]) -> Option<SystemTime> {
+    match u32::from_le_bytes(slot) {
+        0 => None,
+        minutes => Some(UNIX_EPOCH + Duration::from_secs(u64::from(minutes) * 60)),
+    }
+}
+
+/// Each entry has a fixed slot, so the file is sparse: entries added before
+/// timestamps were recorded simply read as zeros.
+fn slot_offset(ring: RingKind, index: u32) -> u64 {
+    (ring as u64 * (u64::from(MAX_ENTRIES) + 1) + u64::from(index))
+        * u64::try_from(SLOT_SIZE).unwrap()
+}
+
+/// When each entry was created, as recorded by the server.
+///
+/// Databases written by older servers don't have this file.
+#[derive(Debug)]
+pub struct Timestamps {
+    file: File,
+}
+
+impl Timestamps {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        openat(CWD, path, OFlags::RDONLY, Mode::empty())
+            .map_io_err(|| format!("Failed to open timestamps file: {path:?}"))
+            .map(|fd| Self {
+                file: File::from(fd),
+            })
+    }
+
+    /// Missing and unreadable slots are treated as unknown since timestamps
+    /// are purely informational.
+    #[must_use]
+    pub fn get(&self, ring: RingKind, index: u32) -> Option<SystemTime> {
+        let mut slot = [0; SLOT_SIZE];
+        self.file
+            .read_exact_at(&mut slot, slot_offset(ring, index))
+            .ok()?;
+        from_slot(slot)
+    }
+}
+
+/// The server's writable view of the [`Timestamps`] file.
+#[derive(Debug)]
+pub struct TimestampsWriter(Timestamps);
+
+impl TimestampsWriter {
+    pub fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self> {
+        openat(
+            CWD,
+            path,
+            OFlags::RDWR | OFlags::CREATE,
+            Mode::RUSR | Mode::WUSR,
+        )
+        .map_io_err(|| format!("Failed to open timestamps file: {path:?}"))
+        .map(|fd| {
+            Self(Timestamps {
+                file: File::from(fd),
+            })
+        })
+    }
+
+    pub fn set(&mut self, ring: RingKind, index: u32, time: Option<SystemTime>) -> Result<()> {
+        self.0
+            .file
+            .write_all_at(
+                &time.map_or([0; SLOT_SIZE], to_slot),
+                slot_offset(ring, index),
+            )
+            .map_io_err(|| format!("Failed to write timestamp of entry {index} in {ring:?} ring."))
+    }
+
+    #[must_use]
+    pub fn get(&self, ring: RingKind, index: u32) -> Option<SystemTime> {
+        self.0.get(ring, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs, process,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{Timestamps, TimestampsWriter};
+    use crate::{protocol::RingKind, ring::MAX_ENTRIES};
+
+    #[test]
+    fn timestamps_are_kept_per_entry() {
+        let path = env::temp_dir().join(format!("ringboard-timestamps-{}", process::id()));
+        let mut writer = TimestampsWriter::open(&path).unwrap();
+        let minute = UNIX_EPOCH + Duration::from_secs(1_700_000_040);
+        let later = minute + Duration::from_secs(59);
+
+        writer.set(RingKind::Main, 0, Some(later)).unwrap();
+        writer.set(RingKind::Favorites, 0, Some(minute)).unwrap();
+        writer
+            .set(RingKind::Main, MAX_ENTRIES - 1, Some(UNIX_EPOCH))
+            .unwrap();
+
+        let reader = Timestamps::open(&path).unwrap();
+        assert_eq!(reader.get(RingKind::Main, 0), Some(minute));
+        assert_eq!(reader.get(RingKind::Favorites, 0), Some(minute));
+        assert_eq!(
+            reader.get(RingKind::Main, MAX_ENTRIES - 1),
+            Some(UNIX_EPOCH + Duration::from_secs(60))
+        );
+        assert_eq!(reader.get(RingKind::Main, 1), None);
+        assert_eq!(reader.get(RingKind::Favorites, MAX_ENTRIES - 1), None);
+
+        writer.set(RingKind::Main, 0, None).unwrap();
+        assert_eq!(reader.get(RingKind::Main, 0), None);
+
+        fs::remove_file(path).unwrap();
+    }
+}
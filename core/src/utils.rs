@@ -18,12 +18,12 @@ use rustix::{
     fs::{copy_file_range, linkat, openat, statx, AtFlags, Mode, OFlags, StatxFlags, CWD},
     net::{bind_unix, listen, socket, AddressFamily, SocketAddrUnix, SocketType},
     path::Arg,
-    process::Pid,
+    process::{umask, Pid},
 };
 
 use crate::{
     protocol::{composite_id, RingKind},
-    Error, IoErr, Result,
+    Context, Error, IoErr, Result,
 };
 
 pub const TEXT_MIMES: &[&str] = &[
@@ -210,22 +210,41 @@ pub fn init_unix_server<P: AsRef<Path>>(socket_file: P, kind: SocketType) -> Res
             Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
             r => r,
         }
-        .map_io_err(|| format!("Failed to remove old socket: {socket_file:?}"))?;
+        .map_io_err(|| Context::File {
+            message: "Failed to remove old socket",
+            path: socket_file.to_path_buf(),
+        })?;
 
         if let Some(parent) = socket_file.parent() {
-            fs::create_dir_all(parent)
-                .map_io_err(|| format!("Failed to create socket directory: {parent:?}"))?;
+            fs::create_dir_all(parent).map_io_err(|| Context::File {
+                message: "Failed to create socket directory",
+                path: parent.to_path_buf(),
+            })?;
         }
-        SocketAddrUnix::new(socket_file)
-            .map_io_err(|| format!("Failed to make socket address: {socket_file:?}"))?
+        SocketAddrUnix::new(socket_file).map_io_err(|| Context::File {
+            message: "Failed to make socket address",
+            path: socket_file.to_path_buf(),
+        })?
     };
 
-    let socket = socket(AddressFamily::UNIX, kind, None)
-        .map_io_err(|| format!("Failed to create socket: {socket_file:?}"))?;
-    bind_unix(&socket, &addr).map_io_err(|| format!("Failed to bind socket: {socket_file:?}"))?;
+    let socket = socket(AddressFamily::UNIX, kind, None).map_io_err(|| Context::File {
+        message: "Failed to create socket",
+        path: socket_file.to_path_buf(),
+    })?;
+    // The sockets directory is shared by every user, so the socket itself has to
+    // keep the others out from the moment it exists.
+    let mask = umask(Mode::XUSR | Mode::RWXG | Mode::RWXO);
+    let bound = bind_unix(&socket, &addr);
+    umask(mask);
+    bound.map_io_err(|| Context::File {
+        message: "Failed to bind socket",
+        path: socket_file.to_path_buf(),
+    })?;
     if kind != SocketType::DGRAM {
-        listen(&socket, -1)
-            .map_io_err(|| format!("Failed to listen for clients: {socket_file:?}"))?;
+        listen(&socket, -1).map_io_err(|| Context::File {
+            message: "Failed to listen for clients",
+            path: socket_file.to_path_buf(),
+        })?;
     }
     Ok(socket)
 }
@@ -5,26 +5,29 @@ use std::{
     env,
     error::Error,
     ffi::OsStr,
+    mem,
     sync::{
         mpsc,
         mpsc::{Receiver, Sender},
         Arc,
     },
     thread,
+    time::SystemTime,
 };
 
 use eframe::{
     egui,
     egui::{
-        text::LayoutJob, Align, CentralPanel, Event, FontId, Image, InputState, Key, Label, Layout,
-        Modifiers, PopupCloseBehavior, Pos2, Response, RichText, ScrollArea, Sense, TextEdit,
-        TextFormat, TopBottomPanel, Ui, Vec2, ViewportBuilder, ViewportCommand, Widget,
+        text::LayoutJob, Align, CentralPanel, Color32, Event, FontId, Image, InputState, Key,
+        Label, Layout, Modifiers, PopupCloseBehavior, Pos2, Response, RichText, ScrollArea, Sense,
+        TextEdit, TextFormat, TopBottomPanel, Ui, Vec2, ViewportBuilder, ViewportCommand, Widget,
     },
     epaint::FontFamily,
     Theme,
 };
 use ringboard_sdk::{
-    core::{protocol::RingKind, Error as CoreError},
+    api::set_client_name,
+    core::{checksums::Verification, protocol::RingKind, Error as CoreError},
     search::CancellationToken,
     ui_actor::{
         controller, Command, CommandError, DetailedEntry, Message, SearchKind, UiEntry,
@@ -43,6 +46,7 @@ static GLOBAL: tracy_client::ProfiledAllocator<std::alloc::System> =
     tracy_client::ProfiledAllocator::new(std::alloc::System, 100);
 
 fn main() -> Result<(), eframe::Error> {
+    set_client_name(env!("CARGO_BIN_NAME"));
     eframe::run_native(
         concat!("Ringboard v", env!("CARGO_PKG_VERSION")),
         eframe::NativeOptions {
@@ -132,11 +136,24 @@ fn main() -> Result<(), eframe::Error> {
                     ctx.add_image_loader(ringboard_loader.clone());
 
                     controller(&command_receiver, |m| {
-                        let r = if let Message::LoadedImage { id, image } = m {
-                            ringboard_loader.add(id, image);
-                            Ok(())
-                        } else {
-                            response_sender.send(m)
+                        let r = match m {
+                            Message::LoadedImage {
+                                id,
+                                image,
+                                metadata: _,
+                            } => {
+                                ringboard_loader.add(id, image);
+                                Ok(())
+                            }
+                            Message::ImageFailed { id, error } => {
+                                ringboard_loader.fail(id, &error);
+                                Ok(())
+                            }
+                            // egui has no use for partially loaded images.
+                            Message::PendingImage { .. } | Message::ImageProgress { .. } => {
+                                return Ok(());
+                            }
+                            m => response_sender.send(m),
                         };
                         if r.is_ok() {
                             ctx.request_repaint();
@@ -205,13 +222,21 @@ struct UiState {
     query: String,
     search_highlighted_id: Option<u64>,
     search_kind: SearchKind,
-    pending_search_token: Option<CancellationToken>,
+    pending_search: Option<PendingSearch>,
     queued_searches: u32,
 
     was_focused: bool,
     skip_first_focus: bool,
 }
 
+/// The latest search, whose results are still coming in.
+struct PendingSearch {
+    generation: u64,
+    token: CancellationToken,
+    /// Whether its results replaced the previous search's yet.
+    shown: bool,
+}
+
 impl App {
     fn start(
         row_font: FontFamily,
@@ -247,7 +272,7 @@ fn handle_message(
                 query: _,
                 search_highlighted_id,
                 search_kind: _,
-                pending_search_token,
+                pending_search,
                 queued_searches,
                 was_focused: _,
                 skip_first_focus: _,
@@ -265,6 +290,7 @@ fn handle_message(
         Message::LoadedFirstPage {
             entries,
             default_focused_id,
+            on_clipboard: _,
         } => {
             *loaded_entries = entries;
             if highlighted_id.is_none() {
@@ -276,22 +302,69 @@ fn handle_message(
                 *detailed_entry = Some(result);
             }
         }
-        Message::SearchResults(entries) => {
-            *queued_searches = queued_searches.saturating_sub(1);
-            if pending_search_token.take().is_some() {
-                *search_highlighted_id = entries.first().map(|e| e.entry.id());
-                *search_results = entries;
+        Message::SearchResults {
+            generation,
+            batch,
+            done,
+        } => {
+            if done {
+                *queued_searches = queued_searches.saturating_sub(1);
+            }
+            if let Some(pending) = pending_search
+                .as_mut()
+                .filter(|p| p.generation == generation && !p.token.is_cancelled())
+            {
+                if pending.shown {
+                    let mut results = mem::take(search_results).into_vec();
+                    results.extend(batch);
+                    *search_results = results.into();
+                } else {
+                    *search_highlighted_id = batch.first().map(|e| e.entry.id());
+                    *search_results = batch;
+                    pending.shown = true;
+                }
+            }
+            if done {
+                pending_search.take_if(|p| p.generation == generation);
             }
         }
-        Message::FavoriteChange(_) | Message::Deleted(_) => {}
-        Message::LoadedImage { .. } => unreachable!(),
-        Message::PendingSearch(token) => {
+        Message::Connected
+        | Message::FavoriteChange(_)
+        | Message::Deleted(_)
+        | Message::DeletedMany(_)
+        | Message::Restored { .. }
+        | Message::Deduplicated { .. }
+        | Message::DbRefreshed { .. }
+        | Message::ServerInfo(_) => {}
+        Message::PendingImage { .. }
+        | Message::ImageProgress { .. }
+        | Message::LoadedImage { .. }
+        | Message::ImageFailed { .. }
+        | Message::Reordered { .. }
+        | Message::Labeled(_)
+        | Message::Tagged(_)
+        | Message::LoadedPage { .. }
+        | Message::LoadedThrough { .. }
+        | Message::Replaced(_)
+        | Message::EntryReplaced { .. }
+        | Message::Copied
+        | Message::Yanked(_)
+        | Message::PendingDashboard(_)
+        | Message::Dashboard(_)
+        | Message::Stats(_)
+        | Message::LoadedThumbnail { .. }
+        | Message::FindingSimilar { .. } => unreachable!(),
+        Message::PendingSearch { generation, token } => {
             if *queued_searches > 1 {
                 token.cancel();
             }
-            *pending_search_token = Some(token);
+            *pending_search = Some(PendingSearch {
+                generation,
+                token,
+                shown: false,
+            });
         }
-        Message::Pasted => ctx.send_viewport_cmd(ViewportCommand::Close),
+        Message::Pasted { .. } => ctx.send_viewport_cmd(ViewportCommand::Close),
     }
 }
 
@@ -331,7 +404,7 @@ fn search_ui(
                 query,
                 search_kind,
                 search_highlighted_id,
-                pending_search_token,
+                pending_search,
                 queued_searches,
                 ref was_focused,
                 ..
@@ -341,12 +414,16 @@ fn search_ui(
 ) {
     macro_rules! search {
         () => {
-            if let Some(token) = pending_search_token {
+            if let Some(PendingSearch { token, .. }) = pending_search {
                 token.cancel();
             }
             let _ = requests.send(Command::Search {
                 query: query.clone().into(),
                 kind: *search_kind,
+                case_sensitive: smart_case(query),
+                ring: None,
+                mime_filter: None,
+                favorites_first: false,
             });
             *queued_searches += 1;
         };
@@ -355,7 +432,7 @@ fn search_ui(
     if ui.input_mut(|i| i.consume_key(Modifiers::ALT, Key::X)) {
         *search_kind = match search_kind {
             SearchKind::Regex => SearchKind::Plain,
-            SearchKind::Plain | SearchKind::Mime => SearchKind::Regex,
+            SearchKind::Plain | SearchKind::Mime | SearchKind::Tag => SearchKind::Regex,
         };
         ui.input_mut(|i| i.events.retain(|e| !matches!(e, Event::Text(_))));
         search!();
@@ -363,7 +440,7 @@ fn search_ui(
     if ui.input_mut(|i| i.consume_key(Modifiers::ALT, Key::M)) {
         *search_kind = match search_kind {
             SearchKind::Mime => SearchKind::Plain,
-            SearchKind::Plain | SearchKind::Regex => SearchKind::Mime,
+            SearchKind::Plain | SearchKind::Regex | SearchKind::Tag => SearchKind::Mime,
         };
         ui.input_mut(|i| i.events.retain(|e| !matches!(e, Event::Text(_))));
         search!();
@@ -375,10 +452,11 @@ fn search_ui(
                 SearchKind::Plain => "Search",
                 SearchKind::Regex => "RegEx search",
                 SearchKind::Mime => "Mime type search",
+                SearchKind::Tag => "Tag filter",
             })
             .font(match search_kind {
                 SearchKind::Plain => FontId::proportional(16.5),
-                SearchKind::Regex | SearchKind::Mime => FontId::monospace(16.),
+                SearchKind::Regex | SearchKind::Mime | SearchKind::Tag => FontId::monospace(16.),
             })
             .desired_width(f32::INFINITY)
             .cursor_at_end(true)
@@ -430,6 +508,11 @@ macro_rules! active_highlighted_id {
     }};
 }
 
+/// Queries with uppercase letters in them are matched case-sensitively.
+fn smart_case(query: &str) -> bool {
+    query.chars().any(char::is_uppercase)
+}
+
 fn show_error(ui: &mut Ui, e: &dyn Error) {
     ui.label(format!("Error: {e}"));
     ui.label(format!("Details: {e:#?}"));
@@ -445,12 +528,16 @@ fn main_ui(
     let refresh = |state: &mut UiState| {
         let _ = requests.send(Command::LoadFirstPage);
         if !state.query.is_empty() {
-            if let Some(token) = &state.pending_search_token {
+            if let Some(PendingSearch { token, .. }) = &state.pending_search {
                 token.cancel();
             }
             let _ = requests.send(Command::Search {
                 query: state.query.clone().into(),
                 kind: state.search_kind,
+                case_sensitive: smart_case(&state.query),
+                ring: None,
+                mime_filter: None,
+                favorites_first: false,
             });
             state.queued_searches += 1;
         }
@@ -499,7 +586,7 @@ fn main_ui(
     {
         let _ = requests.send(Command::Paste(id));
     }
-    if let Some(UiEntry { entry, cache: _ }) = ui
+    if let Some(UiEntry { entry, .. }) = ui
         .input_mut(|input| {
             (0..10).find(|i| {
                 input.consume_key(
@@ -590,7 +677,7 @@ fn entry_ui(
     index: usize,
 ) {
     let response = match &entry.cache {
-        UiEntryCache::Text { one_liner } => {
+        UiEntryCache::Text { one_liner, .. } => {
             let mut job = LayoutJob::single_section(
                 one_liner.to_string(),
                 TextFormat {
@@ -645,6 +732,18 @@ fn entry_ui(
             max_popup_height,
             index,
         ),
+        UiEntryCache::Sensitive => row_ui(
+            ui,
+            Label::new(RichText::new("••••• sensitive entry").italics()).selectable(false),
+            state,
+            requests,
+            refresh,
+            entry,
+            try_scroll,
+            try_popup,
+            max_popup_height,
+            index,
+        ),
         UiEntryCache::Error(e) => {
             show_error(ui, e);
             return;
@@ -661,7 +760,9 @@ fn row_ui(
     state: &mut UiState,
     requests: &Sender<Command>,
     mut refresh: impl FnMut(&mut UiState),
-    &UiEntry { entry, ref cache }: &UiEntry,
+    &UiEntry {
+        entry, ref cache, ..
+    }: &UiEntry,
     try_scroll: bool,
     try_popup: bool,
     max_popup_height: f32,
@@ -761,10 +862,43 @@ fn row_ui(
                     Some(Ok(DetailedEntry {
                         mime_type,
                         full_text,
+                        preview: _,
+                        added_by,
+                        timestamp: _,
+                        verification,
+                        truncated,
+                        image,
+                        expires_at,
+                        tags,
                     })) => {
                         if !mime_type.is_empty() {
                             ui.label(format!("Mime type: {mime_type}"));
                         }
+                        if let Some(image) = image {
+                            ui.label(format!("Dimensions: {}×{}", image.width, image.height));
+                        }
+                        if let Some(added_by) = added_by {
+                            ui.label(format!("Added by: {added_by}"));
+                        }
+                        if !tags.is_empty() {
+                            let tags = tags.iter().collect::<Vec<_>>().join(", ");
+                            ui.label(format!("Tags: {tags}"));
+                        }
+                        if *truncated {
+                            ui.label("Truncated to the server's maximum entry size.");
+                        }
+                        if let Some(expires_at) = expires_at {
+                            let left = expires_at
+                                .duration_since(SystemTime::now())
+                                .unwrap_or_default();
+                            ui.label(format!("Sensitive: deleted in {}s", left.as_secs()));
+                        }
+                        if *verification == Verification::Mismatch {
+                            ui.colored_label(
+                                Color32::RED,
+                                "Checksum mismatch: the data may be corrupted.",
+                            );
+                        }
                         ui.separator();
                         if let Some(full) = full_text {
                             ScrollArea::both()
@@ -782,6 +916,14 @@ fn row_ui(
                                             .fit_to_original_size(1.),
                                     );
                                 });
+                        } else if matches!(cache, UiEntryCache::Sensitive) {
+                            ui.label("Sensitive entry hidden.");
+                            if ui.button("Reveal").clicked() {
+                                let _ = requests.send(Command::GetDetails {
+                                    id: entry_id,
+                                    with_text: true,
+                                });
+                            }
                         } else {
                             ui.label("Binary data.");
                         }
@@ -850,12 +992,16 @@ mod loader {
         },
     };
     use image::DynamicImage;
-    use ringboard_sdk::{core::RingAndIndex, ui_actor::Command};
+    use ringboard_sdk::{
+        core::RingAndIndex,
+        ui_actor::{Command, CommandError},
+    };
     use rustc_hash::FxHasher;
 
     enum CachedImage {
         Queued,
         Computed(Arc<ColorImage>),
+        Failed(String),
     }
 
     pub struct RingboardLoader {
@@ -887,6 +1033,16 @@ mod loader {
                 ),
             );
         }
+
+        pub fn fail(&self, id: u64, error: &CommandError) {
+            let Ok(mut cache) = self.cache.lock() else {
+                return;
+            };
+            cache.insert(
+                RingAndIndex::from_id(id).unwrap(),
+                CachedImage::Failed(error.to_string()),
+            );
+        }
     }
 
     fn uri_to_id(uri: &str) -> Option<RingAndIndex> {
@@ -916,9 +1072,13 @@ mod loader {
                     CachedImage::Computed(image) => Ok(ImagePoll::Ready {
                         image: image.clone(),
                     }),
+                    CachedImage::Failed(error) => Err(LoadError::Loading(error.clone())),
                 },
                 Entry::Vacant(v) => {
-                    let _ = self.requests.send(Command::LoadImage(id.id()));
+                    let _ = self.requests.send(Command::LoadImage {
+                        id: id.id(),
+                        max_size: None,
+                    });
                     v.insert(CachedImage::Queued);
                     Ok(ImagePoll::Pending { size: None })
                 }
@@ -952,6 +1112,7 @@ mod loader {
                         CachedImage::Computed(image) => {
                             image.pixels.capacity() * size_of::<egui::Color32>()
                         }
+                        CachedImage::Failed(error) => error.capacity(),
                     })
                     .sum::<usize>()
         }
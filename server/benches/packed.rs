@@ -0,0 +1,90 @@
+//! Compares databases created with and without `pack_small_entries` after
+//! adding the same short strings to each: how long the adds took and how much
+//! disk space the database ended up with.
+//!
+//! Run from this crate with `cargo bench --bench packed`.
+
+use std::{
+    env, fs,
+    os::fd::OwnedFd,
+    path::Path,
+    process,
+    time::{Duration, Instant},
+};
+
+use ringboard_core::protocol::{AddResponse, MimeType, RingKind};
+use ringboard_sdk::api::{connect_to_server, AddRequest};
+use rustix::{
+    fs::{memfd_create, statx, AtFlags, MemfdFlags, StatxFlags, CWD},
+    net::SocketAddrUnix,
+};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+const ENTRIES: u32 = 100_000;
+
+fn main() {
+    if !common::io_uring_available() {
+        return;
+    }
+    for packed in [false, true] {
+        let server = common::Server::start_with_settings(
+            env::temp_dir().join(format!("ringboard-packed-bench-{packed}-{}", process::id())),
+            &format!("pack_small_entries = {packed}"),
+        );
+        server.assert_responsive();
+        let client =
+            connect_to_server(&SocketAddrUnix::new(server.socket_file()).unwrap()).unwrap();
+
+        let mut random = 0x2545_f491_4f6c_dd1d_u64;
+        let mut elapsed = Duration::ZERO;
+        for i in 0..ENTRIES {
+            random ^= random << 13;
+            random ^= random >> 7;
+            random ^= random << 17;
+            // Words, urls, and the like: mostly a few dozen bytes.
+            let len = 8 + usize::try_from(random % 120).unwrap();
+            let text = format!("{i:x}-{random:x}").repeat(len / 16 + 1);
+            let data = memfd(&text.as_bytes()[..len]);
+
+            let start = Instant::now();
+            let response = AddRequest::response(&client, RingKind::Main, MimeType::new(), &data);
+            elapsed += start.elapsed();
+            assert!(matches!(response, Ok(AddResponse::Success { .. })));
+        }
+
+        println!(
+            "{}: {ENTRIES} adds in {elapsed:?} ({:.0} per second), {} KiB on disk",
+            if packed { "packed" } else { "bucketed" },
+            f64::from(ENTRIES) / elapsed.as_secs_f64(),
+            allocated_bytes(&server.data_dir()) / 1024,
+        );
+    }
+}
+
+fn memfd(data: &[u8]) -> OwnedFd {
+    let fd = memfd_create(c"ringboard-packed-bench", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&fd, data).unwrap();
+    rustix::fs::seek(&fd, rustix::fs::SeekFrom::Start(0)).unwrap();
+    fd
+}
+
+/// The disk space taken by every file in `dir`, which leaves out the holes in
+/// sparse files.
+fn allocated_bytes(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                allocated_bytes(&path)
+            } else {
+                statx(CWD, &path, AtFlags::empty(), StatxFlags::BLOCKS)
+                    .unwrap()
+                    .stx_blocks
+                    * 512
+            }
+        })
+        .sum()
+}
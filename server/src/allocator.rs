@@ -6,11 +6,12 @@ use std::{
     fmt::Debug,
     fs::File,
     io,
-    io::{ErrorKind, ErrorKind::AlreadyExists, IoSlice, Read, Write},
-    mem::ManuallyDrop,
+    io::{ErrorKind, ErrorKind::AlreadyExists, IoSlice, Read, Seek, SeekFrom, Write},
+    mem::{ManuallyDrop, MaybeUninit},
     ops::{Index, IndexMut},
     os::{fd::OwnedFd, unix::fs::FileExt},
-    slice,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use arrayvec::{ArrayString, ArrayVec};
@@ -18,32 +19,79 @@ use bitcode::{Decode, Encode};
 use bitvec::{order::Lsb0, vec::BitVec};
 use log::{debug, error, info, trace, warn};
 use ringboard_core::{
-    bucket_to_length, copy_file_range_all, direct_file_name, link_tmp_file, open_buckets,
+    bucket_to_length, checksums,
+    checksums::Verification,
+    copy_file_range_all, direct_file_name, encoding,
+    encoding::Encoding,
+    generation,
+    generation::GenerationWriter,
+    link_tmp_file, open_buckets, origins,
+    origins::{Origin, OriginsWriter},
+    packed,
     protocol::{
-        composite_id, decompose_id, AddResponse, GarbageCollectResponse, IdNotFoundError, MimeType,
-        MoveToFrontResponse, RemoveResponse, RingKind, SwapResponse,
+        composite_id, decompose_id, AddResponse, BackupStats, BucketUsage, DirectUsage,
+        EmptyTrashResponse, ErrorCode, GarbageCollectResponse, IdNotFoundError, Label,
+        ListResponse, ListedEntry, MimeType, MoveToFrontResponse, ReadResponse, RemoveResponse,
+        ListTagsResponse, RequestFailed, RestoreResponse, RingKind, SetClipboardResponse,
+        SetLabelResponse, StartupState, StatsResponse, SwapResponse, Tag, TagResponse,
+        MAX_INLINE_READ_LEN, MAX_LIST_ENTRIES,
     },
     ring,
-    ring::{entries_to_offset, Entry, Header, InitializedEntry, RawEntry, Ring},
-    size_to_bucket, IoErr, RingAndIndex, NUM_BUCKETS, TEXT_MIMES,
+    ring::{entries_to_offset, Entry, Header, InitializedEntry, Mmap, RawEntry, Ring},
+    sensitive,
+    settings::{EntryLimits, SizeCheck},
+    size_to_bucket, slot_generations,
+    slot_generations::SlotGenerationsWriter,
+    tags,
+    tags::{TagSet, TagsWriter, TooManyTags},
+    timestamps,
+    timestamps::TimestampsWriter,
+    Context, IoErr, RingAndIndex, DIRECT_FILE_NAME_LEN, NUM_BUCKETS, TEXT_MIMES,
 };
+use ringboard_sdk::decode_direct_file;
 use rustix::{
+    event::{poll, PollFd, PollFlags},
     fs::{
-        fsetxattr, ftruncate, mkdir, openat, renameat, renameat_with, unlinkat, AtFlags, Mode,
-        OFlags, RenameFlags, XattrFlags, CWD,
+        fadvise, fallocate, fremovexattr, fsetxattr, fstat, ftruncate, memfd_create, mkdirat,
+        openat, renameat, renameat_with, statx, unlinkat, Advice, AtFlags, FallocateFlags,
+        FileType, MemfdFlags, Mode, OFlags, RawDir, RenameFlags, StatxFlags, XattrFlags, CWD,
     },
+    io::Errno,
     path::Arg,
 };
 
-use crate::CliError;
+#[cfg(feature = "ocr")]
+use crate::ocr::Ocr;
+use crate::{
+    coalesce::AddCoalescer,
+    dedupe::{hash_contents, RecentEntries},
+    normalize::Normalizer,
+    packed::{missing_record, PackedLog},
+    requests::recoverable,
+    settings::{Preallocation, Settings},
+    startup::{check_database, PreviousShutdown},
+    trash::{xattr_string, xattr_tags, Tombstone, Trash},
+    CliError,
+};
+
+/// The number of most recent entries per ring whose storage is verified after
+/// a crash.
+const FAST_CHECK_ENTRIES: u32 = 64;
+/// How long the server waits for an entry's data to arrive through a pipe or
+/// socket. The server can't handle other requests in the meantime.
+const STREAM_TIMEOUT: Duration = Duration::from_millis(100);
 
 #[derive(Debug)]
 struct RingWriter {
     ring: File,
+    mem: Mmap,
+    len: u64,
 }
 
 impl RingWriter {
-    fn open<P: Arg + Copy + Debug>(path: P) -> Result<Self, CliError> {
+    /// Creates the ring if it doesn't exist yet, with `flags` set in its
+    /// version byte.
+    fn open<P: Arg + Copy + Debug>(path: P, flags: u8) -> Result<Self, CliError> {
         let ring = match openat(CWD, path, OFlags::RDWR, Mode::empty()) {
             Err(e) if e.kind() == ErrorKind::NotFound => {
                 let fd = openat(
@@ -63,7 +111,7 @@ impl RingWriter {
                     } = &Header::default();
                     f.write_all_vectored(&mut [
                         IoSlice::new(magic),
-                        IoSlice::new(slice::from_ref(version)),
+                        IoSlice::new(&[version | flags]),
                         IoSlice::new(&write_head.to_le_bytes()),
                     ])
                     .map_io_err(|| {
@@ -78,26 +126,47 @@ impl RingWriter {
             })?),
         };
 
-        Ok(Self { ring })
+        let len = statx(&ring, c"", AtFlags::EMPTY_PATH, StatxFlags::SIZE)
+            .map_io_err(|| format!("Failed to stat Ringboard database: {path:?}"))?
+            .stx_size;
+        let mem = Mmap::new_writable(
+            &ring,
+            usize::try_from(entries_to_offset(ring::MAX_ENTRIES)).unwrap(),
+        )
+        .map_io_err(|| format!("Failed to map Ringboard database for writing: {path:?}"))?;
+
+        Ok(Self { ring, mem, len })
     }
 
-    #[allow(clippy::needless_pass_by_ref_mut)]
+    /// Publishes the entry with a release store, so its data must already be
+    /// written. See [`ringboard_core::ring`].
     fn write(&mut self, entry: Entry, at: u32) -> ringboard_core::Result<()> {
         debug!("Writing entry to position {at}: {entry:?}");
-        self.ring
-            .write_all_at(&RawEntry::from(entry).to_le_bytes(), entries_to_offset(at))
-            .map_io_err(|| format!("Failed to write entry to Ringboard database: {entry:?}"))
+        let offset = entries_to_offset(at);
+        let end = offset + u64::try_from(size_of::<RawEntry>()).unwrap();
+        if end > self.len {
+            // Stores past the end of the file would fault, so create the slot first.
+            // Readers may see it at this point, but only as uninitialized.
+            self.ring
+                .write_all_at(&RawEntry::from(Entry::Uninitialized).to_le_bytes(), offset)
+                .map_io_err(|| format!("Failed to extend Ringboard database to entry {at}."))?;
+            self.len = end;
+        }
+
+        unsafe {
+            self.mem
+                .store_word(usize::try_from(offset).unwrap(), *RawEntry::from(entry));
+        }
+        Ok(())
     }
 
-    #[allow(clippy::needless_pass_by_ref_mut)]
+    #[allow(clippy::unnecessary_wraps)]
     fn set_write_head(&mut self, head: u32) -> ringboard_core::Result<()> {
         debug!("Setting write head to {head}.");
-        self.ring
-            .write_all_at(
-                &head.to_le_bytes(),
-                u64::try_from(ring::MAGIC.len() + size_of_val(&ring::VERSION)).unwrap(),
-            )
-            .map_io_err(|| format!("Failed to update Ringboard write head: {head}"))
+        unsafe {
+            self.mem.store_word(ring::WRITE_HEAD_OFFSET, head);
+        }
+        Ok(())
     }
 }
 
@@ -126,21 +195,144 @@ impl IndexMut<RingKind> for Rings {
 
 #[derive(Debug)]
 pub struct Allocator {
+    /// Every database file is opened relative to this directory.
+    data_dir: OwnedFd,
     rings: Rings,
     data: AllocatorData,
+    generation: GenerationWriter,
+    /// Missing when the settings turn off recording who added each entry.
+    origins: Option<OriginsWriter>,
+    timestamps: TimestampsWriter,
+    /// Only holds the tags of bucketed and packed entries: direct entries keep
+    /// theirs in their file.
+    tags: TagsWriter,
+    last_startup: StartupState,
+    /// In seconds since the Unix epoch.
+    started_at: u64,
+    accept_pipes: bool,
+    entry_limits: EntryLimits,
+    normalizer: Normalizer,
+    coalescer: AddCoalescer,
+    recent: RecentEntries,
+    /// The entry that owns the system clipboard, followed as it moves around.
+    /// Forgotten on restart since the clipboard may have changed meanwhile.
+    current_clipboard: Option<u64>,
+    /// Whether clients could see changes that haven't been published through
+    /// the generation yet.
+    changed: bool,
+    trash: Trash,
+    sensitive_entry_ttl: u64,
+    /// When the next sensitive entry expires in seconds since the Unix epoch,
+    /// if any are left.
+    next_expiry: Option<u64>,
+    main_max_age: Option<u64>,
+    /// When main ring entries should next be checked for exceeding
+    /// `main_max_age` in seconds since the Unix epoch.
+    next_age_check: Option<u64>,
+    aged_out_entries: u64,
+    #[cfg(feature = "ocr")]
+    ocr: Option<Ocr>,
 }
 
 #[derive(Debug)]
 struct AllocatorData {
     buckets: Buckets,
+    /// Only present in databases created with short entries packed.
+    packed: Option<PackedLog>,
     direct_dir: OwnedFd,
+    /// Kept up to date as files come and go so stats don't have to walk the
+    /// directory.
+    direct_usage: DirectUsage,
+    compress_text_above: Option<u64>,
 }
 
 #[derive(Debug)]
 struct Buckets {
     files: [File; NUM_BUCKETS],
     slot_counts: [u32; NUM_BUCKETS],
+    generations: SlotGenerationsWriter,
     free_lists: FreeLists,
+    preallocator: Preallocator,
+}
+
+/// Reserves bucket file space in chunks ahead of need.
+///
+/// Growing buckets one slot at a time fragments them badly on copy-on-write
+/// file systems. Space is reserved with `FALLOC_FL_KEEP_SIZE` since slot counts
+/// are derived from the logical length of bucket files.
+#[derive(Debug)]
+struct Preallocator {
+    chunk_size: Option<u64>,
+    reserved: [u64; NUM_BUCKETS],
+}
+
+impl Preallocator {
+    fn new(
+        &Settings {
+            preallocation:
+                Preallocation {
+                    preallocate,
+                    preallocation_chunk_size,
+                },
+            ..
+        }: &Settings,
+        slot_counts: &[u32; NUM_BUCKETS],
+    ) -> Self {
+        let mut me = Self {
+            chunk_size: preallocate.then_some(preallocation_chunk_size),
+            reserved: [0; NUM_BUCKETS],
+        };
+        me.reset(slot_counts);
+        me
+    }
+
+    /// Forgets about reserved space, e.g. because bucket files were truncated.
+    fn reset(&mut self, slot_counts: &[u32; NUM_BUCKETS]) {
+        for (bucket, (reserved, &slots)) in self.reserved.iter_mut().zip(slot_counts).enumerate() {
+            *reserved = u64::from(slots) * u64::from(bucket_to_length(bucket));
+        }
+    }
+
+    /// Forgets about space past `len` in a bucket file that was truncated to
+    /// it, since truncation also releases space reserved past the end.
+    fn truncated(&mut self, bucket: usize, len: u64) {
+        let reserved = &mut self.reserved[bucket];
+        *reserved = (*reserved).min(len);
+    }
+
+    fn reserve(
+        &mut self,
+        bucket: usize,
+        end: u64,
+        mut fallocate: impl FnMut(u64, u64) -> rustix::io::Result<()>,
+    ) -> rustix::io::Result<()> {
+        let Some(chunk_size) = self.chunk_size else {
+            return Ok(());
+        };
+        let reserved = &mut self.reserved[bucket];
+        if end <= *reserved {
+            return Ok(());
+        }
+
+        let chunk_end = end.next_multiple_of(chunk_size);
+        let r = match fallocate(*reserved, chunk_end - *reserved) {
+            // Make room for just this entry if a whole chunk doesn't fit anymore.
+            Err(e) if e == Errno::NOSPC => fallocate(*reserved, end - *reserved).map(|()| end),
+            r => r.map(|()| chunk_end),
+        };
+        match r {
+            Ok(new_end) => {
+                *reserved = new_end;
+                Ok(())
+            }
+            Err(e) if e == Errno::OPNOTSUPP => {
+                warn!("File system does not support preallocation, disabling it.");
+                self.chunk_size = None;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -170,17 +362,28 @@ impl Drop for BucketSlotGuard<'_> {
 }
 
 impl FreeLists {
-    fn load(rings: &Rings) -> Result<Self, CliError> {
-        let mut file = match openat(CWD, c"free-lists", OFlags::RDWR, Mode::empty()) {
+    fn load(
+        data_dir: &OwnedFd,
+        rings: &Rings,
+        slot_counts: &[u32; NUM_BUCKETS],
+    ) -> Result<Self, CliError> {
+        let file = match openat(data_dir, c"free-lists", OFlags::RDWR, Mode::empty()) {
             Err(e) if e.kind() == ErrorKind::NotFound => {
-                return Ok(Self {
-                    lists: RawFreeLists::default(),
-                });
+                if slot_counts.iter().all(|&slots| slots == 0) {
+                    return Ok(Self {
+                        lists: RawFreeLists::default(),
+                    });
+                }
+                // Free lists are only saved on clean shutdown, so a server that crashed
+                // before its first shutdown has none.
+                None
             }
-            r => File::from(r.map_io_err(|| "Failed to open free lists file.")?),
+            r => Some(File::from(
+                r.map_io_err(|| "Failed to open free lists file.")?,
+            )),
         };
 
-        {
+        if let Some(mut file) = file {
             let mut bytes = Vec::new();
             file.read_to_end(&mut bytes)
                 .map_io_err(|| "Failed to read free lists file.")?;
@@ -197,11 +400,12 @@ impl FreeLists {
             }
         }
         warn!("Reconstructing allocator free lists.");
+        let start = Instant::now();
 
         let mut allocations = [BitVec::<usize, Lsb0>::EMPTY; NUM_BUCKETS];
         for ring in [RingKind::Favorites, RingKind::Main] {
             let ring = &rings[ring].ring;
-            for entry in (0..ring.len()).filter_map(|i| ring.get(i)) {
+            for (_, entry) in ring.slots() {
                 match entry {
                     Entry::Bucketed(entry) => {
                         let slots = &mut allocations[usize::from(size_to_bucket(entry.size()))];
@@ -213,25 +417,35 @@ impl FreeLists {
                             slots.set(index, true);
                         }
                     }
-                    Entry::Uninitialized | Entry::File => continue,
+                    Entry::Uninitialized | Entry::File | Entry::Packed(_) => continue,
                 }
             }
         }
+        // Slots past the last used one are free too.
+        for (slots, &count) in allocations.iter_mut().zip(slot_counts) {
+            let count = usize::try_from(count).unwrap();
+            if slots.len() < count {
+                slots.resize(count, false);
+            }
+        }
 
-        Ok(Self {
-            lists: RawFreeLists(allocations.map(|slots| {
-                slots
-                    .iter_zeros()
-                    .map(|i| u32::try_from(i).unwrap())
-                    .collect()
-            })),
-        })
+        let lists = RawFreeLists(allocations.map(|slots| {
+            slots
+                .iter_zeros()
+                .map(|i| u32::try_from(i).unwrap())
+                .collect()
+        }));
+        info!(
+            "Reconstructed allocator free lists in {:?}.",
+            start.elapsed()
+        );
+        Ok(Self { lists })
     }
 
-    fn save(&self) -> Result<(), CliError> {
+    fn save(&self, data_dir: &OwnedFd) -> Result<(), CliError> {
         info!("Saving allocator free list to disk.");
         let file = openat(
-            CWD,
+            data_dir,
             c"free-lists",
             OFlags::WRONLY | OFlags::CREATE,
             Mode::RUSR | Mode::WUSR,
@@ -256,19 +470,431 @@ impl FreeLists {
     }
 }
 
-impl Allocator {
-    pub fn open() -> Result<Self, CliError> {
-        let open_ring = |kind: RingKind| -> Result<_, CliError> {
-            let writer = RingWriter::open(kind.file_name_cstr())?;
-            Ok(WritableRing {
-                ring: Ring::open_fd(kind.default_max_entries(), &writer.ring)?,
-                writer,
+/// Bucket slots the saved free lists claim are unused. Entries pointing at one
+/// lost their data to whatever was allocated there next.
+#[derive(Default, Debug)]
+struct FreeSlots([BitVec<usize, Lsb0>; NUM_BUCKETS]);
+
+impl FreeSlots {
+    /// Reads the free lists file without consuming it, treating a missing or
+    /// corrupted file as having no free slots since it'll be rebuilt anyway.
+    fn read(data_dir: &OwnedFd) -> Result<Self, CliError> {
+        let file = match openat(data_dir, c"free-lists", OFlags::RDONLY, Mode::empty()) {
+            Err(Errno::NOENT) => return Ok(Self::default()),
+            r => r.map_io_err(|| "Failed to open free lists file.")?,
+        };
+        let mut bytes = Vec::new();
+        File::from(file)
+            .read_to_end(&mut bytes)
+            .map_io_err(|| "Failed to read free lists file.")?;
+        let Ok(RawFreeLists(lists)) = bitcode::decode(&bytes) else {
+            return Ok(Self::default());
+        };
+
+        let mut free = Self::default();
+        for (slots, list) in free.0.iter_mut().zip(lists) {
+            for index in list {
+                let index = usize::try_from(index).unwrap();
+                if slots.len() <= index {
+                    slots.resize(index + 1, false);
+                }
+                slots.set(index, true);
+            }
+        }
+        Ok(free)
+    }
+
+    fn contains(&self, bucket: u8, index: u32) -> bool {
+        self.0[usize::from(bucket)]
+            .get(usize::try_from(index).unwrap())
+            .is_some_and(|free| *free)
+    }
+}
+
+impl Rings {
+    fn fast_check(
+        &self,
+        slot_counts: &[u32; NUM_BUCKETS],
+        free_slots: &FreeSlots,
+        packed: Option<&PackedLog>,
+        direct_dir: &OwnedFd,
+    ) -> bool {
+        for kind in [RingKind::Favorites, RingKind::Main] {
+            let ring = &self[kind].ring;
+            let write_head = ring.write_head();
+            if !write_head_in_range(ring) {
+                warn!("Write head {write_head} out of range for {kind:?} ring.");
+                return false;
+            }
+
+            let mut index = write_head;
+            for _ in 0..min(FAST_CHECK_ENTRIES, ring.len()) {
+                index = ring.prev_entry(index);
+                let Some(entry) = ring.get(index) else {
+                    continue;
+                };
+                if !storage_exists(
+                    kind,
+                    index,
+                    entry,
+                    slot_counts,
+                    free_slots,
+                    packed,
+                    direct_dir,
+                ) {
+                    warn!("Missing storage for {kind:?} entry {index}: {entry:?}");
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn recover(
+        &mut self,
+        slot_counts: &[u32; NUM_BUCKETS],
+        free_slots: &FreeSlots,
+        packed: Option<&PackedLog>,
+        data_dir: &OwnedFd,
+        direct_dir: &OwnedFd,
+    ) -> Result<(), CliError> {
+        let (mut reset_heads, mut dropped_entries, mut corrupted_entries) = (0, 0, 0);
+        for kind in [RingKind::Favorites, RingKind::Main] {
+            let WritableRing { writer, ring } = &mut self[kind];
+            if !write_head_in_range(ring) {
+                let head = if ring.len() < ring.capacity() {
+                    ring.len()
+                } else {
+                    0
+                };
+                warn!("Resetting {kind:?} ring write head to {head}.");
+                writer.set_write_head(head)?;
+                reset_heads += 1;
+            }
+
+            for (index, entry) in ring.slots() {
+                if !storage_exists(
+                    kind,
+                    index,
+                    entry,
+                    slot_counts,
+                    free_slots,
+                    packed,
+                    direct_dir,
+                ) {
+                    warn!("Dropping {kind:?} entry {index} with missing storage: {entry:?}");
+                    writer.write(Entry::Uninitialized, index)?;
+                    dropped_entries += 1;
+                } else if entry == Entry::File && !direct_file_intact(kind, index, direct_dir) {
+                    corrupted_entries += 1;
+                }
+            }
+        }
+        let orphans = self.remove_orphaned_files(direct_dir)?;
+        info!(
+            "Recovery reset {reset_heads} write head(s), dropped {dropped_entries} entries with \
+             missing storage, found {corrupted_entries} corrupted direct file(s), and removed \
+             {orphans} orphaned direct file(s)."
+        );
+
+        // An empty free lists file forces them to be rebuilt from the repaired rings.
+        openat(
+            data_dir,
+            c"free-lists",
+            OFlags::WRONLY | OFlags::CREATE | OFlags::TRUNC,
+            Mode::RUSR | Mode::WUSR,
+        )
+        .map_io_err(|| "Failed to truncate free lists file.")?;
+        Ok(())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Totals up the direct allocation files named after an entry.
+fn measure_direct_files(direct_dir: &OwnedFd) -> Result<DirectUsage, CliError> {
+    let direct_dir = openat(direct_dir, c".", OFlags::DIRECTORY, Mode::empty())
+        .map_io_err(|| "Failed to open direct directory.")?;
+    let mut usage = DirectUsage::default();
+    let mut buf = [MaybeUninit::uninit(); 8192];
+    let mut iter = RawDir::new(&direct_dir, &mut buf);
+    while let Some(file) = iter.next() {
+        let file = file.map_io_err(|| "Failed to read direct allocation directory.")?;
+        let name = file.file_name();
+        if name.to_bytes().len() != DIRECT_FILE_NAME_LEN
+            || !name.to_bytes().iter().all(u8::is_ascii_digit)
+        {
+            continue;
+        }
+
+        usage.files += 1;
+        usage.bytes += statx(&direct_dir, name, AtFlags::empty(), StatxFlags::SIZE)
+            .map_io_err(|| format!("Failed to stat direct allocation file: {name:?}"))?
+            .stx_size;
+    }
+    Ok(usage)
+}
+
+impl Rings {
+    /// Deletes direct allocation files that no entry points to, returning how
+    /// many there were. Files that aren't named after an entry are left alone.
+    fn remove_orphaned_files(&self, direct_dir: &OwnedFd) -> Result<u32, CliError> {
+        let direct_dir = openat(direct_dir, c".", OFlags::DIRECTORY, Mode::empty())
+            .map_io_err(|| "Failed to open direct directory.")?;
+        let mut orphans = Vec::new();
+        {
+            let mut buf = [MaybeUninit::uninit(); 8192];
+            let mut iter = RawDir::new(&direct_dir, &mut buf);
+            while let Some(file) = iter.next() {
+                let file = file.map_io_err(|| "Failed to read direct allocation directory.")?;
+                let name = file.file_name();
+                if name == c"." || name == c".." {
+                    continue;
+                }
+
+                let Some((kind, index)) = name
+                    .to_str()
+                    .ok()
+                    .filter(|name| name.len() == DIRECT_FILE_NAME_LEN)
+                    .and_then(|name| name.parse().ok())
+                    .and_then(|id| decompose_id(id).ok())
+                else {
+                    warn!("Ignoring unknown file in direct directory: {name:?}");
+                    continue;
+                };
+                if self[kind].ring.get(index) != Some(Entry::File) {
+                    orphans.push(name.to_owned());
+                }
+            }
+        }
+
+        for name in &orphans {
+            warn!("Removing orphaned direct allocation file: {name:?}");
+            unlinkat(&direct_dir, name.as_c_str(), AtFlags::empty())
+                .map_io_err(|| format!("Failed to remove orphaned direct file: {name:?}"))?;
+        }
+        Ok(u32::try_from(orphans.len()).unwrap())
+    }
+}
+
+impl Rings {
+    /// The slots of every packed entry, which are all the packed log holds.
+    fn packed_entries(&self) -> impl Iterator<Item = (RingKind, u32)> + '_ {
+        [RingKind::Favorites, RingKind::Main]
+            .into_iter()
+            .flat_map(|kind| {
+                self[kind]
+                    .ring
+                    .slots()
+                    .filter(|(_, entry)| matches!(entry, Entry::Packed(_)))
+                    .map(move |(index, _)| (kind, index))
             })
+    }
+}
+
+fn write_head_in_range(ring: &Ring) -> bool {
+    let write_head = ring.write_head();
+    write_head <= ring.len() && write_head < ring.capacity()
+}
+
+fn storage_exists(
+    kind: RingKind,
+    index: u32,
+    entry: Entry,
+    slot_counts: &[u32; NUM_BUCKETS],
+    free_slots: &FreeSlots,
+    packed: Option<&PackedLog>,
+    direct_dir: &OwnedFd,
+) -> bool {
+    match entry {
+        Entry::Uninitialized => true,
+        Entry::Bucketed(entry) => {
+            let bucket = size_to_bucket(entry.size());
+            entry.index() < slot_counts[usize::from(bucket)]
+                && !free_slots.contains(bucket, entry.index())
+        }
+        Entry::Packed(entry) => packed.is_some_and(|packed| packed.holds(kind, index, entry)),
+        Entry::File => {
+            let mut buf = Default::default();
+            let buf = direct_file_name(&mut buf, kind, index);
+            statx(direct_dir, &*buf, AtFlags::empty(), StatxFlags::empty()).is_ok()
+        }
+    }
+}
+
+/// Checks a direct file against its checksum, logging why it isn't intact.
+///
+/// Corrupted entries are kept so users can still salvage what's left of them.
+fn direct_file_intact(kind: RingKind, index: u32, direct_dir: &OwnedFd) -> bool {
+    let mut buf = Default::default();
+    let buf = direct_file_name(&mut buf, kind, index);
+    match openat(direct_dir, &*buf, OFlags::RDONLY, Mode::empty())
+        .map_io_err(|| format!("Failed to open direct file: {buf:?}"))
+        .and_then(checksums::verify)
+    {
+        Ok(Verification::Ok | Verification::Unknown) => true,
+        Ok(Verification::Mismatch) => {
+            warn!("Checksum mismatch for {kind:?} entry {index}.");
+            false
+        }
+        Err(e) => {
+            warn!("Failed to verify {kind:?} entry {index}: {e:?}");
+            false
+        }
+    }
+}
+
+/// Copies up to `limit` bytes from a pipe or socket, giving up once
+/// [`STREAM_TIMEOUT`] has passed so a client that never finishes writing can't
+/// stall the server.
+fn copy_stream(mut data: &File, to: &mut File, limit: u64) -> io::Result<u64> {
+    let deadline = Instant::now() + STREAM_TIMEOUT;
+    let mut buf = [0; 4096];
+    let mut size = 0;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "Client didn't finish sending data in time.",
+            ));
+        }
+
+        let timeout = i32::try_from(remaining.as_millis())
+            .unwrap_or(i32::MAX)
+            .max(1);
+        if poll(&mut [PollFd::new(&data, PollFlags::IN)], timeout)? == 0 {
+            continue;
+        }
+        let max_read = usize::try_from(limit - size).map_or(buf.len(), |n| n.min(buf.len()));
+        let read = data.read(&mut buf[..max_read])?;
+        if read == 0 {
+            return Ok(size);
+        }
+        to.write_all(&buf[..read])?;
+        size += u64::try_from(read).unwrap();
+        if size == limit {
+            return Ok(size);
+        }
+    }
+}
+
+/// Turns errors caused by the entry's data or a lack of space into responses.
+/// Reads a range of a direct entry, inline if it fits or as a copy of just that
+/// range otherwise.
+fn read_range(
+    file: &File,
+    offset: u64,
+    len: u64,
+) -> Result<(ReadResponse, Option<OwnedFd>), CliError> {
+    let size = file
+        .metadata()
+        .map_io_err(|| "Failed to stat direct allocation file.")?
+        .len();
+    let start = offset.min(size);
+    let len = (size - start).min(len);
+
+    if let Ok(len @ ..=MAX_INLINE_READ_LEN) = usize::try_from(len) {
+        let mut data = [0; MAX_INLINE_READ_LEN];
+        file.read_exact_at(&mut data[..len], start)
+            .map_io_err(|| "Failed to read direct allocation file.")?;
+        return Ok((ReadResponse::inline(&data[..len]), None));
+    }
+
+    let mut range = File::from(
+        memfd_create(c"ringboard_read_range", MemfdFlags::empty())
+            .map_io_err(|| "Failed to create range file.")?,
+    );
+    let mut source = file;
+    // The data directory may not be on the same file system as memfds, so this
+    // can't always be a copy_file_range.
+    source
+        .seek(SeekFrom::Start(start))
+        .and_then(|_| io::copy(&mut source.take(len), &mut range))
+        .and_then(|_| range.rewind())
+        .map_io_err(|| "Failed to copy range of direct allocation file.")?;
+    Ok((ReadResponse::file(), Some(range.into())))
+}
+
+fn reject_add(e: CliError) -> Result<AddResponse, CliError> {
+    match e {
+        CliError::Core(ringboard_core::Error::Io { error, context })
+            if matches!(
+                error.kind(),
+                ErrorKind::StorageFull | ErrorKind::QuotaExceeded
+            ) =>
+        {
+            warn!("Rejecting new entry: {context} {error}");
+            Ok(AddResponse::DatabaseFull)
+        }
+        CliError::Core(ringboard_core::Error::Io { error, context })
+            if error.kind() == ErrorKind::TimedOut =>
+        {
+            warn!("Rejecting new entry: {context} {error}");
+            Ok(AddResponse::UnsupportedData)
+        }
+        e => Err(e),
+    }
+}
+
+impl Allocator {
+    pub fn open(
+        data_dir: &Path,
+        previous_shutdown: PreviousShutdown,
+        settings: &Settings,
+    ) -> Result<Self, CliError> {
+        let path = |name| data_dir.join(name);
+        let dir = openat(
+            CWD,
+            data_dir,
+            OFlags::DIRECTORY | OFlags::PATH,
+            Mode::empty(),
+        )
+        .map_io_err(|| format!("Failed to open data directory: {data_dir:?}"))?;
+
+        let open_ring = |kind: RingKind, flags| -> Result<_, CliError> {
+            let writer = RingWriter::open(&*path(kind.file_name()), flags)?;
+            let capacity = settings.ring_capacities.get(kind);
+            let ring = Ring::open_fd(capacity, &writer.ring)?;
+            // The write head must also fit since it may not have wrapped yet.
+            let entries = ring.len().max(ring.write_head().saturating_add(1));
+            if entries > capacity {
+                return Err(CliError::RingTooSmall {
+                    ring: kind,
+                    entries,
+                    capacity,
+                });
+            }
+            Ok(WritableRing { writer, ring })
+        };
+        // Both rings share the main ring's layout, so only it records the flag.
+        let main_ring = open_ring(
+            RingKind::Main,
+            if settings.text_storage.pack_small_entries {
+                ring::PACKED
+            } else {
+                0
+            },
+        )?;
+        let favorites_ring = open_ring(RingKind::Favorites, 0)?;
+        let mut packed = if main_ring.ring.is_packed() {
+            Some(PackedLog::open(data_dir)?)
+        } else {
+            None
         };
-        let main_ring = open_ring(RingKind::Main)?;
-        let favorites_ring = open_ring(RingKind::Favorites)?;
+        if packed.is_some() != settings.text_storage.pack_small_entries {
+            warn!(
+                "Ignoring pack_small_entries setting: the database was created with it set to {}.",
+                packed.is_some()
+            );
+        }
 
-        let create_dir = |name| match mkdir(name, Mode::RWXU) {
+        let create_dir = |name| match mkdirat(&dir, name, Mode::RWXU) {
             Err(e) if e.kind() == AlreadyExists => Ok(()),
             r => r.map_io_err(|| format!("Failed to create directory: {name:?}")),
         };
@@ -283,7 +909,7 @@ impl Allocator {
                 path.push_str(name);
                 path.push(char::from(0));
                 openat(
-                    CWD,
+                    &dir,
                     unsafe { CStr::from_ptr(path.as_ptr().cast()) },
                     OFlags::RDWR | OFlags::CREATE,
                     Mode::RUSR | Mode::WUSR,
@@ -301,26 +927,109 @@ impl Allocator {
         };
 
         let direct_dir = openat(
-            CWD,
+            &dir,
             c"direct",
             OFlags::DIRECTORY | OFlags::PATH,
             Mode::empty(),
         )
         .map_io_err(|| "Failed to open direct directory.")?;
 
-        let rings = Rings([favorites_ring, main_ring]);
-        let free_lists = FreeLists::load(&rings)?;
+        let mut rings = Rings([favorites_ring, main_ring]);
+        let free_slots = if previous_shutdown == PreviousShutdown::Clean {
+            FreeSlots::default()
+        } else {
+            FreeSlots::read(&dir)?
+        };
+        let last_startup = check_database(
+            previous_shutdown,
+            &mut rings,
+            |rings| rings.fast_check(&slot_counts, &free_slots, packed.as_ref(), &direct_dir),
+            |rings| {
+                rings.recover(
+                    &slot_counts,
+                    &free_slots,
+                    packed.as_ref(),
+                    &dir,
+                    &direct_dir,
+                )
+            },
+        )?;
+        let free_lists = FreeLists::load(&dir, &rings, &slot_counts)?;
+        if let Some(packed) = &mut packed {
+            packed.recount(rings.packed_entries());
+        }
+        let slot_generations = SlotGenerationsWriter::open(
+            &*path(slot_generations::FILE_NAME),
+            [RingKind::Favorites, RingKind::Main]
+                .into_iter()
+                .flat_map(|kind| {
+                    let ring = &rings[kind].ring;
+                    (0..ring.len()).filter_map(|i| match ring.get(i) {
+                        Some(Entry::Bucketed(entry)) => Some(entry),
+                        Some(Entry::Uninitialized | Entry::File | Entry::Packed(_)) | None => None,
+                    })
+                }),
+        )?;
+        let direct_usage = measure_direct_files(&direct_dir)?;
+        let generation = GenerationWriter::open(&*path(generation::FILE_NAME))?;
+        let origins = if settings.record_origins {
+            Some(OriginsWriter::open(&*path(origins::FILE_NAME))?)
+        } else {
+            match unlinkat(&dir, origins::FILE_NAME, AtFlags::empty()) {
+                Err(Errno::NOENT) => Ok(()),
+                r => r,
+            }
+            .map_io_err(|| "Failed to delete origins file.")?;
+            None
+        };
+        let timestamps = TimestampsWriter::open(&*path(timestamps::FILE_NAME))?;
+        let tags = TagsWriter::open(&*path(tags::FILE_NAME))?;
+        #[cfg(feature = "ocr")]
+        let ocr = settings.ocr.then(Ocr::spawn).transpose()?;
+        #[cfg(not(feature = "ocr"))]
+        if settings.ocr {
+            warn!("Ignoring OCR setting: the server was built without the ocr feature.");
+        }
 
         Ok(Self {
+            data_dir: dir,
             rings,
             data: AllocatorData {
                 buckets: Buckets {
                     files: buckets.map(File::from),
+                    preallocator: Preallocator::new(settings, &slot_counts),
                     slot_counts,
+                    generations: slot_generations,
                     free_lists,
                 },
+                packed,
                 direct_dir,
+                direct_usage,
+                compress_text_above: settings.text_storage.compress_text_above,
             },
+            generation,
+            origins,
+            timestamps,
+            tags,
+            last_startup,
+            started_at: unix_now(),
+            accept_pipes: settings.accept_pipes,
+            entry_limits: settings.entry_limits,
+            normalizer: Normalizer::new(settings),
+            coalescer: AddCoalescer::new(settings),
+            recent: RecentEntries::new(settings),
+            current_clipboard: None,
+            changed: false,
+            trash: Trash::default(),
+            sensitive_entry_ttl: settings.sensitive_entries.sensitive_entry_ttl,
+            // Only scanning the direct directory finds the sensitive entries added
+            // before a restart, which the first expiry check does.
+            next_expiry: Some(0),
+            main_max_age: settings.main_max_age,
+            next_age_check: settings.main_max_age.map(|_| 0),
+            aged_out_entries: 0,
+            #[cfg(feature = "ocr")]
+            ocr,
         })
     }
 
@@ -329,11 +1038,185 @@ impl Allocator {
         fd: OwnedFd,
         to: RingKind,
         mime_type: &MimeType,
+        origin: Option<Origin>,
+        copied_at: Option<SystemTime>,
+        expires_at: Option<u64>,
+    ) -> Result<Result<AddResponse, RequestFailed>, CliError> {
+        // Reads from anything but a regular file can block indefinitely.
+        let stream = match FileType::from_raw_mode(
+            fstat(&fd)
+                .map_io_err(|| "Failed to stat received data.")?
+                .st_mode,
+        ) {
+            FileType::RegularFile => false,
+            FileType::Fifo | FileType::Socket if self.accept_pipes => true,
+            file_type => {
+                warn!("Rejecting new entry: unsupported file type {file_type:?}.");
+                return Ok(Ok(AddResponse::UnsupportedData));
+            }
+        };
+
+        // Receive the data before touching the ring so rejected entries don't
+        // overwrite the oldest one.
+        let max = self.entry_limits.max_entry_size;
+        match self.data.receive(fd, stream, max) {
+            Ok((received, size)) => self
+                .store(received, size, to, mime_type, origin, copied_at, expires_at)
+                .map(Ok),
+            // Whatever isn't the database's fault is the client's.
+            Err(e) => match reject_add(e) {
+                Ok(response) => Ok(Ok(response)),
+                Err(e) => recoverable(ErrorCode::UnreadableData, e).map(Err),
+            },
+        }
+    }
+
+    fn store(
+        &mut self,
+        received: File,
+        mut size: u64,
+        to: RingKind,
+        mime_type: &MimeType,
+        origin: Option<Origin>,
+        copied_at: Option<SystemTime>,
+        expires_at: Option<u64>,
     ) -> Result<AddResponse, CliError> {
-        let id = self.add_internal(to, |head, data| data.alloc(fd, mime_type, to, head))?;
-        Ok(AddResponse::Success {
-            id: composite_id(to, id),
-        })
+        if size == 0 {
+            warn!("Rejecting new entry: it has no data.");
+            return Ok(AddResponse::Empty);
+        }
+        let truncated = match self.entry_limits.check(size) {
+            SizeCheck::Fits => false,
+            SizeCheck::Truncate(max) => {
+                info!("Truncating new entry to {max} bytes.");
+                received
+                    .set_len(max)
+                    .map_io_err(|| "Failed to truncate new entry.")?;
+                size = max;
+                true
+            }
+            SizeCheck::Reject => {
+                let max = self.entry_limits.max_entry_size;
+                warn!("Rejecting new entry: it is larger than {max} bytes.");
+                return Ok(AddResponse::TooLarge { max });
+            }
+        };
+        let normalized = if TEXT_MIMES.iter().any(|b| mime_type.eq_ignore_ascii_case(b)) {
+            self.normalizer
+                .normalize_file(&received, size)
+                .map_io_err(|| "Failed to normalize text entry.")?
+        } else {
+            None
+        };
+        if let Some(normalized) = normalized {
+            debug!("Normalized text entry from {size} to {normalized} bytes.");
+        }
+        let size = normalized.unwrap_or(size);
+
+        let now = Instant::now();
+        // Truncated entries only look like whatever their first bytes match, and
+        // sensitive ones aren't remembered past their expiry.
+        let hash = if !truncated
+            && expires_at.is_none()
+            && (self.coalescer.enabled() || self.recent.enabled())
+        {
+            hash_contents(&received, size).map_io_err(|| "Failed to hash new entry.")?
+        } else {
+            None
+        };
+        let fingerprint = self
+            .coalescer
+            .fingerprint(hash, size, to, mime_type, origin);
+        if let Some(id) = fingerprint.and_then(|f| self.coalescer.coalesce(&f, now)) {
+            info!("Coalescing new entry with identical entry {id} added moments ago.");
+            return Ok(AddResponse::Success {
+                id,
+                normalized: normalized.is_some(),
+                coalesced: true,
+                deduplicated: false,
+                truncated: false,
+            });
+        }
+        let recent =
+            hash.and_then(|hash| Some((hash, self.recent.find(to, mime_type, size, hash)?)));
+        if let Some((hash, existing)) = recent {
+            if self.same_contents(existing, &received, size)? {
+                match self.move_to_front(existing, Some(to))? {
+                    MoveToFrontResponse::Success { id } => {
+                        info!("Moved identical entry {existing} to the front as {id}.");
+                        // The entry now belongs to whoever copied it last.
+                        self.set_origin(to, decompose_id(id).unwrap().1, origin);
+                        self.recent.record_deduplicated();
+                        self.recent.remember(id, mime_type, size, hash);
+                        if let Some(fingerprint) = fingerprint {
+                            self.coalescer.remember(fingerprint, id, now);
+                        }
+                        return Ok(AddResponse::Success {
+                            id,
+                            normalized: normalized.is_some(),
+                            coalesced: false,
+                            deduplicated: true,
+                            truncated: false,
+                        });
+                    }
+                    response => {
+                        debug!("Storing a copy of identical entry {existing}: {response:?}");
+                    }
+                }
+            }
+        }
+
+        match self.add_internal(to, |head, data| {
+            data.alloc(received, size, mime_type, to, head, truncated, expires_at)
+        }) {
+            Ok(id) => {
+                self.set_origin(to, id, origin);
+                let added_at = SystemTime::now();
+                let copied_at = copied_at.map_or(added_at, |time| time.min(added_at));
+                self.set_timestamp(to, id, Some(copied_at));
+                self.set_tags_slot(to, id, &TagSet::default());
+                if let Some(expires_at) = expires_at {
+                    info!("Added sensitive entry expiring at {expires_at}.");
+                    self.next_expiry = Some(
+                        self.next_expiry
+                            .map_or(expires_at, |next| next.min(expires_at)),
+                    );
+                }
+                #[cfg(feature = "ocr")]
+                if expires_at.is_none() && mime_type.starts_with("image/") {
+                    self.submit_for_ocr(to, id);
+                }
+                let id = composite_id(to, id);
+                if let Some(fingerprint) = fingerprint {
+                    self.coalescer.remember(fingerprint, id, now);
+                }
+                if let Some(hash) = hash {
+                    self.recent.remember(id, mime_type, size, hash);
+                }
+                Ok(AddResponse::Success {
+                    id,
+                    normalized: normalized.is_some(),
+                    coalesced: false,
+                    deduplicated: false,
+                    truncated,
+                })
+            }
+            Err(e) => reject_add(e),
+        }
+    }
+
+    #[cfg(feature = "ocr")]
+    fn submit_for_ocr(&self, to: RingKind, id: u32) {
+        let Some(ocr) = &self.ocr else {
+            return;
+        };
+
+        let mut buf = Default::default();
+        let buf = direct_file_name(&mut buf, to, id);
+        match openat(&self.data.direct_dir, &*buf, OFlags::RDONLY, Mode::empty()) {
+            Ok(image) => ocr.submit(image),
+            Err(e) => warn!("Failed to open image for text recognition: {buf:?} {e}"),
+        }
     }
 
     fn add_internal(
@@ -343,9 +1226,15 @@ impl Allocator {
     ) -> Result<u32, CliError> {
         let WritableRing { writer, ring } = &mut self.rings[to];
         let head = ring.write_head();
+        if self.current_clipboard == Some(composite_id(to, head)) {
+            self.current_clipboard = None;
+        }
+        self.coalescer.forget(composite_id(to, head));
+        self.recent.forget(composite_id(to, head));
 
-        if let Some(entry) = ring.get(head) {
+        let compact = if let Some(entry) = ring.get(head) {
             writer.write(Entry::Uninitialized, head)?;
+            self.changed = true;
             self.data.free(entry, to, head)?;
 
             // Only GC on allocation instead of in AllocatorData::free to avoid spamming GCs
@@ -353,7 +1242,12 @@ impl Allocator {
             if let Entry::Bucketed(_) = entry {
                 self.gc_(u16::MAX.into())?;
             }
-        }
+            // Compaction only keeps the entries it finds in the rings, so it has to wait
+            // for an entry being moved here to be back in one.
+            matches!(entry, Entry::Packed(_))
+        } else {
+            false
+        };
         let entry = alloc(head, &mut self.data)?;
         let WritableRing { writer, ring } = &mut self.rings[to];
 
@@ -368,6 +1262,7 @@ impl Allocator {
                 }
             })?;
         writer.set_write_head(ring.next_head(head))?;
+        self.changed = true;
         {
             let len = head + 1;
             if len > ring.len() {
@@ -377,10 +1272,116 @@ impl Allocator {
                 }
             }
         }
+        if compact {
+            // Every entry past the first gap gets moved, so let about as much garbage
+            // as there are live bytes pile up first. The entry was added either way.
+            let live = self.data.packed.as_ref().map_or(0, PackedLog::live);
+            if let Err(e) = self.compact_packed(live.max(u16::MAX.into())) {
+                warn!("Failed to compact packed log: {e:?}");
+            }
+        }
 
         Ok(head)
     }
 
+    fn origin(&self, ring: RingKind, id: u32) -> Option<Origin> {
+        self.origins.as_ref()?.get(ring, id)
+    }
+
+    /// Origins are informational, so failing to record one doesn't fail the
+    /// request.
+    fn set_origin(&mut self, ring: RingKind, id: u32, origin: Option<Origin>) {
+        let Some(origins) = &mut self.origins else {
+            return;
+        };
+        match origins.set(ring, id, origin) {
+            Ok(()) => self.changed = true,
+            Err(e) => warn!("{e}"),
+        }
+    }
+
+    /// Like origins, timestamps are informational.
+    fn set_timestamp(&mut self, ring: RingKind, id: u32, time: Option<SystemTime>) {
+        match self.timestamps.set(ring, id, time) {
+            Ok(()) => self.changed = true,
+            Err(e) => warn!("{e}"),
+        }
+    }
+
+    /// Keeps a bucketed entry's tags in step with its position. The entry has
+    /// already moved by then, so failing to move its tags along doesn't fail
+    /// the request. Slots are only written when they change to keep the tags
+    /// file sparse.
+    fn set_tags_slot(&mut self, ring: RingKind, id: u32, tags: &TagSet) {
+        if self.tags.get(ring, id) == *tags {
+            return;
+        }
+        match self.tags.set(ring, id, tags) {
+            Ok(()) => self.changed = true,
+            Err(e) => warn!("{e}"),
+        }
+    }
+
+    /// Whether a stored entry holds exactly the first `size` bytes of `data`.
+    fn same_contents(&self, id: u64, data: &File, size: u64) -> Result<bool, CliError> {
+        let Ok((ring, index, entry)) = self.get_entry(id) else {
+            return Ok(false);
+        };
+        let direct;
+        let (stored, start, stored_size) = match entry {
+            Entry::Uninitialized => return Ok(false),
+            Entry::Bucketed(entry) => {
+                let bucket = usize::from(size_to_bucket(entry.size()));
+                (
+                    &self.data.buckets.files[bucket],
+                    u64::from(entry.index()) * u64::from(bucket_to_length(bucket)),
+                    u64::from(entry.size()),
+                )
+            }
+            Entry::Packed(entry) => {
+                let (log, start) = self.data.locate_packed(ring, index)?;
+                (log, start, u64::from(entry.size()))
+            }
+            Entry::File => {
+                let mut buf = Default::default();
+                let buf = direct_file_name(&mut buf, ring, index);
+                direct = File::from(
+                    openat(&self.data.direct_dir, &*buf, OFlags::RDONLY, Mode::empty())
+                        .map_io_err(|| format!("Failed to open direct allocation file: {buf:?}"))?,
+                );
+                // Compressed sizes aren't comparable, so those never match.
+                if encoding::read(&direct)?.is_some() {
+                    return Ok(false);
+                }
+                let len = direct
+                    .metadata()
+                    .map_io_err(|| format!("Failed to stat direct allocation file: {buf:?}"))?
+                    .len();
+                (&direct, 0, len)
+            }
+        };
+        if stored_size != size {
+            return Ok(false);
+        }
+
+        let mut new = [0; 4096];
+        let mut old = [0; 4096];
+        let mut offset = 0;
+        while offset < size {
+            let len = usize::try_from(size - offset).map_or(new.len(), |left| left.min(new.len()));
+            data.read_exact_at(&mut new[..len], offset)
+                .map_io_err(|| "Failed to read new entry.")?;
+            stored
+                .read_exact_at(&mut old[..len], start + offset)
+                .map_io_err(|| format!("Failed to read entry {id}."))?;
+            if new[..len] != old[..len] {
+                return Ok(false);
+            }
+            offset += u64::try_from(len).unwrap();
+        }
+        Ok(true)
+    }
+
     fn get_entry(&self, id: u64) -> Result<(RingKind, u32, Entry), IdNotFoundError> {
         let (ring, id) = decompose_id(id)?;
         let Some(entry) = self.rings[ring].ring.get(id) else {
@@ -394,6 +1395,8 @@ impl Allocator {
         id: u64,
         to: Option<RingKind>,
     ) -> Result<MoveToFrontResponse, CliError> {
+        self.coalescer.forget(id);
+        self.recent.forget(id);
         let (from, from_id, from_entry) = match self.get_entry(id) {
             Err(e) => return Ok(MoveToFrontResponse::Error(e)),
             Ok((_, from_id, Entry::Uninitialized)) => {
@@ -402,49 +1405,97 @@ impl Allocator {
             Ok(r) => r,
         };
         let to = to.unwrap_or(from);
-        let WritableRing { writer, ring } = &mut self.rings[from];
+        let ring = &self.rings[from].ring;
 
         if from == to && ring.next_head(from_id) == ring.write_head() {
             return Ok(MoveToFrontResponse::Success {
                 id: composite_id(from, from_id),
             });
         }
+        if to == RingKind::Favorites {
+            // Unlike the main ring, favorites are never evicted to make room.
+            let head = self.rings[to].ring.write_head();
+            if self.rings[to]
+                .ring
+                .get(head)
+                .is_some_and(|entry| entry != Entry::Uninitialized)
+                && (from, from_id) != (to, head)
+            {
+                warn!("Refusing to favorite entry {id}: favorites are full.");
+                return Ok(MoveToFrontResponse::FavoritesFull);
+            }
+        }
+        let writer = &mut self.rings[from].writer;
         writer.write(Entry::Uninitialized, from_id)?;
-
-        let to_id = self.add_internal(to, |to_id, AllocatorData { ref direct_dir, .. }| {
-            debug!(
-                "Moving entry {from_entry:?} from {from:?} ring at position {from_id} to {to:?} \
-                 ring at position {to_id}."
-            );
-
-            match from_entry {
-                Entry::Uninitialized => unreachable!(),
-                Entry::Bucketed(_) => {
-                    // Nothing to do, buckets are shared between rings.
+        self.changed = true;
+        let on_clipboard = self.current_clipboard == Some(id);
+        let origin = self.origin(from, from_id);
+        let tags = self.tags.get(from, from_id);
+
+        let to_id = self.add_internal(
+            to,
+            |to_id,
+             AllocatorData {
+                 ref direct_dir,
+                 ref mut packed,
+                 ..
+             }| {
+                debug!(
+                    "Moving entry {from_entry:?} from {from:?} ring at position {from_id} to \
+                     {to:?} ring at position {to_id}."
+                );
+
+                match from_entry {
+                    Entry::Uninitialized => unreachable!(),
+                    Entry::Bucketed(_) => {
+                        // Nothing to do, buckets are shared between rings.
+                    }
+                    Entry::Packed(_) => {
+                        if let Some(packed) = packed {
+                            packed.move_entry((from, from_id), (to, to_id))?;
+                        }
+                    }
+                    Entry::File => {
+                        let mut from_buf = Default::default();
+                        let from_buf = direct_file_name(&mut from_buf, from, from_id);
+                        let mut to_buf = Default::default();
+                        let to_buf = direct_file_name(&mut to_buf, to, to_id);
+
+                        renameat(direct_dir, &*from_buf, direct_dir, &*to_buf).map_io_err(
+                            || {
+                                format!(
+                                    "Failed to rename direct allocation file from {from_buf:?} to \
+                                     {to_buf:?}."
+                                )
+                            },
+                        )?;
+                    }
                 }
-                Entry::File => {
-                    let mut from_buf = Default::default();
-                    let from_buf = direct_file_name(&mut from_buf, from, from_id);
-                    let mut to_buf = Default::default();
-                    let to_buf = direct_file_name(&mut to_buf, to, to_id);
-
-                    renameat(direct_dir, &*from_buf, direct_dir, &*to_buf).map_io_err(|| {
-                        format!(
-                            "Failed to rename direct allocation file from {from_buf:?} to \
-                             {to_buf:?}."
-                        )
-                    })?;
-                }
-            }
-            Ok(from_entry)
-        })?;
-        Ok(MoveToFrontResponse::Success {
-            id: composite_id(to, to_id),
-        })
+                Ok(from_entry)
+            },
+        )?;
+        if (from, from_id) != (to, to_id) {
+            self.set_origin(from, from_id, None);
+            self.set_timestamp(from, from_id, None);
+            self.set_tags_slot(from, from_id, &TagSet::default());
+        }
+        self.set_origin(to, to_id, origin);
+        self.set_tags_slot(to, to_id, &tags);
+        // Moving an entry to the front counts as copying it again.
+        self.set_timestamp(to, to_id, Some(SystemTime::now()));
+        let id = composite_id(to, to_id);
+        if on_clipboard {
+            self.current_clipboard = Some(id);
+        }
+        Ok(MoveToFrontResponse::Success { id })
     }
 
     #[allow(clippy::similar_names)]
     pub fn swap(&mut self, id1: u64, id2: u64) -> Result<SwapResponse, CliError> {
+        self.coalescer.forget(id1);
+        self.coalescer.forget(id2);
+        self.recent.forget(id1);
+        self.recent.forget(id2);
         let (ring1, id1, entry1) = match self.get_entry(id1) {
             Ok(r) => r,
             Err(e) => {
@@ -475,7 +1526,40 @@ impl Allocator {
         );
 
         self.rings[ring1].writer.write(entry2, id1)?;
+        self.changed = true;
         self.rings[ring2].writer.write(entry1, id2)?;
+        if let Some(packed) = &mut self.data.packed {
+            packed.swap(
+                (ring1, id1, matches!(entry2, Entry::Packed(_))),
+                (ring2, id2, matches!(entry1, Entry::Packed(_))),
+            );
+        }
+        {
+            let (origin1, origin2) = (self.origin(ring1, id1), self.origin(ring2, id2));
+            self.set_origin(ring1, id1, origin2);
+            self.set_origin(ring2, id2, origin1);
+        }
+        {
+            let (time1, time2) = (
+                self.timestamps.get(ring1, id1),
+                self.timestamps.get(ring2, id2),
+            );
+            self.set_timestamp(ring1, id1, time2);
+            self.set_timestamp(ring2, id2, time1);
+        }
+        {
+            let (tags1, tags2) = (self.tags.get(ring1, id1), self.tags.get(ring2, id2));
+            self.set_tags_slot(ring1, id1, &tags2);
+            self.set_tags_slot(ring2, id2, &tags1);
+        }
+        {
+            let (id1, id2) = (composite_id(ring1, id1), composite_id(ring2, id2));
+            self.current_clipboard = match self.current_clipboard {
+                Some(id) if id == id1 => Some(id2),
+                Some(id) if id == id2 => Some(id1),
+                current => current,
+            };
+        }
 
         match (entry1, entry2) {
             (Entry::File, _) | (_, Entry::File) => {
@@ -504,9 +1588,13 @@ impl Allocator {
                     },
                 )?;
             }
-            (Entry::Bucketed(_), Entry::Bucketed(_) | Entry::Uninitialized)
-            | (Entry::Uninitialized, Entry::Bucketed(_)) => {
-                // Nothing to do.
+            (
+                Entry::Bucketed(_) | Entry::Packed(_),
+                Entry::Bucketed(_) | Entry::Packed(_) | Entry::Uninitialized,
+            )
+            | (Entry::Uninitialized, Entry::Bucketed(_) | Entry::Packed(_)) => {
+                // Nothing to do, packed records were swapped along with the
+                // ring entries.
             }
             (Entry::Uninitialized, Entry::Uninitialized) => unreachable!(),
         }
@@ -518,6 +1606,12 @@ impl Allocator {
     }
 
     pub fn remove(&mut self, id: u64) -> Result<RemoveResponse, CliError> {
+        if self.current_clipboard == Some(id) {
+            self.current_clipboard = None;
+            self.changed = true;
+        }
+        self.coalescer.forget(id);
+        self.recent.forget(id);
         let (ring, id, entry) = match self.get_entry(id) {
             Err(e) => return Ok(RemoveResponse { error: Some(e) }),
             Ok((_, id, Entry::Uninitialized)) => {
@@ -529,18 +1623,741 @@ impl Allocator {
         };
         debug!("Removing entry {entry:?} in {ring:?} ring at position {id}.");
 
+        let tombstone = self.bury(ring, id, entry)?;
         self.rings[ring].writer.write(Entry::Uninitialized, id)?;
+        self.changed = true;
         self.data.free(entry, ring, id)?;
+        self.set_origin(ring, id, None);
+        self.set_timestamp(ring, id, None);
+        self.set_tags_slot(ring, id, &TagSet::default());
+        self.trash.push(tombstone);
 
         Ok(RemoveResponse { error: None })
     }
 
+    /// Keeps a copy of an entry about to be removed so it can be restored.
+    fn bury(&self, ring: RingKind, id: u32, entry: Entry) -> Result<Tombstone, CliError> {
+        let (data, mime_type, label, tags, expires_at) = match entry {
+            Entry::Uninitialized => unreachable!(),
+            Entry::Bucketed(_) | Entry::Packed(_) => (
+                self.data.copy_to_direct_file(ring, id, entry)?,
+                MimeType::new(),
+                Label::new(),
+                self.tags.get(ring, id),
+                None,
+            ),
+            Entry::File => {
+                // Holding on to the file keeps its data around once it's unlinked.
+                let mut buf = Default::default();
+                let buf = direct_file_name(&mut buf, ring, id);
+                let file = File::from(
+                    openat(&self.data.direct_dir, &*buf, OFlags::RDONLY, Mode::empty())
+                        .map_io_err(|| format!("Failed to open direct allocation file: {buf:?}"))?,
+                );
+                let mime_type = xattr_string(&file, c"user.mime_type")?;
+                let label = xattr_string(&file, c"user.label")?;
+                let tags = xattr_tags(&file)?;
+                let expires_at = sensitive::read(&file)?;
+                (file, mime_type, label, tags, expires_at)
+            }
+        };
+        Ok(Tombstone {
+            id: composite_id(ring, id),
+            ring,
+            data,
+            mime_type,
+            label,
+            tags,
+            origin: self.origin(ring, id),
+            copied_at: self.timestamps.get(ring, id),
+            expires_at,
+        })
+    }
+
+    /// Adds a removed entry back at the front of its ring, keeping it in the
+    /// trash if that fails.
+    pub fn restore(
+        &mut self,
+        tombstone_id: u64,
+    ) -> Result<Result<RestoreResponse, RequestFailed>, CliError> {
+        let Some(tombstone) = self.trash.get(tombstone_id) else {
+            return Ok(Ok(RestoreResponse::NotInTrash));
+        };
+        let &Tombstone {
+            id: _,
+            ring,
+            ref data,
+            mime_type,
+            label,
+            tags,
+            origin,
+            copied_at,
+            expires_at,
+        } = tombstone;
+        // Duplicates share their offset, so the data has to be rewound for every
+        // attempt.
+        let mut data = data
+            .try_clone()
+            .map_io_err(|| "Failed to duplicate removed entry.")?;
+        data.rewind()
+            .map_io_err(|| "Failed to rewind removed entry.")?;
+        // Compressed entries are re-added from their contents so they go through
+        // the same storage decisions as any other entry.
+        let (data, _) = decode_direct_file(data)?;
+
+        let response =
+            match self.add(data.into(), ring, &mime_type, origin, copied_at, expires_at)? {
+                Ok(response) => response,
+                Err(failed) => return Ok(Err(failed)),
+            };
+        if let AddResponse::Success { id, .. } = response {
+            info!("Restored removed entry {tombstone_id} as {id}.");
+            self.trash.take(tombstone_id);
+            if !label.is_empty() {
+                if let SetLabelResponse { error: Some(e) } = self.set_label(id, &label)? {
+                    warn!("Failed to relabel restored entry {id}: {e:?}");
+                }
+            }
+            if !tags.is_empty() {
+                if let TagResponse::Error(e) = self.update_tags(id, |t| {
+                    *t = tags;
+                    Ok(true)
+                })? {
+                    warn!("Failed to retag restored entry {id}: {e:?}");
+                }
+            }
+        }
+        Ok(Ok(RestoreResponse::Added(response)))
+    }
+
+    pub fn empty_trash(&mut self) -> EmptyTrashResponse {
+        let purged = self.trash.empty();
+        info!("Permanently deleted {purged} removed entries.");
+        EmptyTrashResponse {
+            purged: u32::try_from(purged).unwrap(),
+        }
+    }
+
+    /// How long a sensitive entry added now is kept, in seconds since the Unix
+    /// epoch.
+    pub fn sensitive_expiry(&self) -> u64 {
+        unix_now().saturating_add(self.sensitive_entry_ttl)
+    }
+
+    /// How long until the next sensitive entry expires, if any are left.
+    pub fn until_next_expiry(&self) -> Option<Duration> {
+        self.next_expiry
+            .map(|next| Duration::from_secs(next.saturating_sub(unix_now())))
+    }
+
+    /// Deletes the sensitive entries that expired. The direct directory is
+    /// only scanned once the next one is due, so this is cheap enough to run
+    /// before every read.
+    pub fn expire_sensitive(&mut self) -> Result<(), CliError> {
+        let now = unix_now();
+        if self.next_expiry.is_none_or(|next| next > now) {
+            return Ok(());
+        }
+
+        let mut next_expiry = self.trash.purge_expired(now);
+        let mut expired = Vec::new();
+        {
+            let direct_dir = openat(
+                &self.data.direct_dir,
+                c".",
+                OFlags::DIRECTORY,
+                Mode::empty(),
+            )
+            .map_io_err(|| "Failed to open direct directory.")?;
+            let mut buf = [MaybeUninit::uninit(); 8192];
+            let mut iter = RawDir::new(&direct_dir, &mut buf);
+            while let Some(file) = iter.next() {
+                let file = file.map_io_err(|| "Failed to read direct allocation directory.")?;
+                let name = file.file_name();
+                let Some(id) = name
+                    .to_str()
+                    .ok()
+                    .filter(|name| name.len() == DIRECT_FILE_NAME_LEN)
+                    .and_then(|name| name.parse::<u64>().ok())
+                else {
+                    continue;
+                };
+                let file = openat(&direct_dir, name, OFlags::RDONLY, Mode::empty())
+                    .map_io_err(|| format!("Failed to open direct allocation file: {name:?}"))?;
+                match sensitive::read(file)? {
+                    Some(expires_at) if expires_at <= now => expired.push(id),
+                    Some(expires_at) => {
+                        next_expiry =
+                            Some(next_expiry.map_or(expires_at, |next| next.min(expires_at)));
+                    }
+                    None => {}
+                }
+            }
+        }
+        self.next_expiry = next_expiry;
+
+        for &id in &expired {
+            self.remove_expired(id)?;
+        }
+        Ok(())
+    }
+
+    /// How long until main ring entries should be checked for exceeding
+    /// `main_max_age`, if they are limited.
+    pub fn until_next_age_check(&self) -> Option<Duration> {
+        self.next_age_check
+            .map(|next| Duration::from_secs(next.saturating_sub(unix_now())))
+    }
+
+    /// Deletes the main ring entries older than `main_max_age`.
+    ///
+    /// Entries are walked from the oldest one and the walk stops at the first
+    /// entry young enough to keep, so only the entries about to be removed are
+    /// looked at. Entries without a timestamp fall back to the modification
+    /// time of their direct allocation file, and bucketed ones are skipped
+    /// since nothing records when they were added.
+    pub fn expire_old(&mut self) -> Result<(), CliError> {
+        let Some(max_age) = self.main_max_age else {
+            self.next_age_check = None;
+            return Ok(());
+        };
+        let now = unix_now();
+        if self.next_age_check.is_none_or(|next| next > now) {
+            return Ok(());
+        }
+        let cutoff = now.saturating_sub(max_age);
+
+        // Check at least hourly so that lowering the limit takes effect even
+        // when the oldest entry won't be due for a long time.
+        let mut next_check = now.saturating_add(max_age.min(60 * 60));
+        let mut expired = Vec::new();
+        {
+            let ring = &self.rings[RingKind::Main].ring;
+            let head = ring.write_head();
+            let mut index = if head < ring.len() { head } else { 0 };
+            for _ in 0..ring.len() {
+                let current = index;
+                index = ring.next_entry(index);
+
+                let Some(entry) = ring.get(current) else {
+                    continue;
+                };
+                let recorded = self
+                    .timestamps
+                    .get(RingKind::Main, current)
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|time| time.as_secs());
+                let added_at = match entry {
+                    Entry::Uninitialized => continue,
+                    Entry::Bucketed(_) | Entry::Packed(_) => recorded,
+                    Entry::File => {
+                        recorded.or_else(|| self.data.direct_file_mtime(RingKind::Main, current))
+                    }
+                };
+                let Some(added_at) = added_at else {
+                    continue;
+                };
+                if added_at > cutoff {
+                    next_check = next_check.min(added_at.saturating_add(max_age));
+                    break;
+                }
+                expired.push(composite_id(RingKind::Main, current));
+            }
+        }
+        self.next_age_check = Some(next_check);
+
+        let mut removed = 0;
+        for &id in &expired {
+            if let RemoveResponse { error: Some(e) } = self.remove(id)? {
+                warn!("Failed to remove entry {id} past the maximum age: {e:?}");
+                continue;
+            }
+            self.trash.take(id);
+            removed += 1;
+        }
+        if removed > 0 {
+            info!("Deleted {removed} entries older than {max_age} seconds.");
+            self.aged_out_entries += removed;
+        }
+        Ok(())
+    }
+
+    /// Deletes an expired sensitive entry the way any other entry is removed,
+    /// except that nothing is left in the trash to restore it from.
+    fn remove_expired(&mut self, id: u64) -> Result<(), CliError> {
+        if let RemoveResponse { error: Some(e) } = self.remove(id)? {
+            warn!("Failed to remove expired sensitive entry {id}: {e:?}");
+            return Ok(());
+        }
+        self.trash.take(id);
+        info!("Deleted expired sensitive entry {id}.");
+        Ok(())
+    }
+
+    /// Reads `len` bytes of the entry starting at `offset`, with a `len` of
+    /// [`u32::MAX`] reading to the end of the entry.
+    pub fn read(
+        &self,
+        id: u64,
+        offset: u64,
+        len: u32,
+    ) -> Result<(ReadResponse, Option<OwnedFd>), CliError> {
+        let (ring, id, entry) = match self.get_entry(id) {
+            Err(e) => return Ok((ReadResponse::error(e), None)),
+            Ok((_, id, Entry::Uninitialized)) => {
+                return Ok((ReadResponse::error(IdNotFoundError::Entry(id)), None));
+            }
+            Ok(r) => r,
+        };
+        debug!("Reading entry {entry:?} in {ring:?} ring at position {id}.");
+        let len = if len == u32::MAX {
+            u64::MAX
+        } else {
+            u64::from(len)
+        };
+
+        match entry {
+            Entry::Uninitialized => unreachable!(),
+            Entry::Bucketed(entry) => {
+                let bucket = usize::from(size_to_bucket(entry.size()));
+                let size = u64::from(entry.size());
+                let start = offset.min(size);
+                let len = usize::try_from((size - start).min(len)).unwrap();
+
+                let mut data = [0; MAX_INLINE_READ_LEN];
+                self.data.buckets.files[bucket]
+                    .read_exact_at(
+                        &mut data[..len],
+                        u64::from(entry.index()) * u64::from(bucket_to_length(bucket)) + start,
+                    )
+                    .map_io_err(|| Context::Bucket {
+                        message: "Failed to read from bucket",
+                        bucket,
+                    })?;
+                Ok((ReadResponse::inline(&data[..len]), None))
+            }
+            Entry::Packed(entry) => {
+                let size = u64::from(entry.size());
+                let start = offset.min(size);
+                let len = usize::try_from((size - start).min(len)).unwrap();
+
+                let mut data = [0; MAX_INLINE_READ_LEN];
+                let (log, entry_start) = self.data.locate_packed(ring, id)?;
+                log.read_exact_at(&mut data[..len], entry_start + start)
+                    .map_io_err(|| "Failed to read from packed log.")?;
+                Ok((ReadResponse::inline(&data[..len]), None))
+            }
+            Entry::File => {
+                let mut buf = Default::default();
+                let buf = direct_file_name(&mut buf, ring, id);
+                let file = File::from(
+                    openat(&self.data.direct_dir, &*buf, OFlags::RDONLY, Mode::empty())
+                        .map_io_err(|| format!("Failed to open direct allocation file: {buf:?}"))?,
+                );
+                // Whole entries are sent as they're stored for clients to decode.
+                if offset == 0 && len == u64::MAX {
+                    return Ok((ReadResponse::file(), Some(file.into())));
+                }
+
+                // Ranges are of the entry's data, not of how it's stored.
+                let (file, _) = decode_direct_file(file)?;
+                read_range(&file, offset, len)
+            }
+        }
+    }
+
+    /// Uninitialized entries are skipped, so fewer than `count` entries may be
+    /// returned even if the ring has more entries after `start`.
+    pub fn list(&self, kind: RingKind, start: u32, count: u32) -> ListResponse {
+        let ring = &self.rings[kind].ring;
+        let mut response = ListResponse {
+            write_head: ring.write_head(),
+            len: ring.len(),
+            count: 0,
+            entries: [ListedEntry::default(); MAX_LIST_ENTRIES],
+        };
+
+        let end = start
+            .saturating_add(count.min(u32::try_from(MAX_LIST_ENTRIES).unwrap()))
+            .min(ring.len());
+        for index in start..end {
+            let (size, bucket_index) = match ring.get(index) {
+                Some(Entry::Bucketed(entry) | Entry::Packed(entry)) => {
+                    (entry.size(), entry.index())
+                }
+                Some(Entry::File) => (0, 0),
+                Some(Entry::Uninitialized) | None => continue,
+            };
+            response.entries[usize::try_from(response.count).unwrap()] = ListedEntry {
+                index,
+                size,
+                bucket_index,
+            };
+            response.count += 1;
+        }
+        response
+    }
+
+    /// Lets clients know that the database changed.
+    /// Bumps the generation if anything clients can see changed since the
+    /// last call, including by requests that failed partway.
+    pub fn publish_changes(&mut self) {
+        if self.changed {
+            self.generation.bump();
+            self.changed = false;
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+
+    pub fn stats(&self) -> Result<StatsResponse, CliError> {
+        let Buckets {
+            files,
+            slot_counts,
+            generations: _,
+            free_lists,
+            preallocator: _,
+        } = &self.data.buckets;
+
+        let mut buckets = [BucketUsage::default(); NUM_BUCKETS];
+        for (bucket, (usage, (file, &slots))) in buckets
+            .iter_mut()
+            .zip(files.iter().zip(slot_counts))
+            .enumerate()
+        {
+            usage.slots = slots;
+            usage.free_slots = u32::try_from(free_lists.lists.0[bucket].len()).unwrap();
+            usage.used_bytes = u64::from(slots) * u64::from(bucket_to_length(bucket));
+            usage.allocated_bytes = statx(file, c"", AtFlags::EMPTY_PATH, StatxFlags::BLOCKS)
+                .map_io_err(|| Context::Bucket {
+                    message: "Failed to statx bucket",
+                    bucket,
+                })?
+                .stx_blocks
+                * 512;
+        }
+
+        Ok(StatsResponse {
+            last_startup: self.last_startup,
+            buckets,
+            // Tracked by the reactor.
+            rejected_requests: 0,
+            memory_pressure_events: 0,
+            dropped_completions: 0,
+            rejected_connections: 0,
+            coalesced_adds: self.coalescer.coalesced(),
+            deduplicated_adds: self.recent.deduplicated(),
+            aged_out_entries: self.aged_out_entries,
+            favorites: {
+                let ring = &self.rings[RingKind::Favorites].ring;
+                (0..ring.len())
+                    .filter(|&index| ring.get(index) != Some(Entry::Uninitialized))
+                    .count()
+                    .try_into()
+                    .unwrap()
+            },
+            max_favorites: self.rings[RingKind::Favorites].ring.capacity(),
+            main_len: self.rings[RingKind::Main].ring.len(),
+            main_capacity: self.rings[RingKind::Main].ring.capacity(),
+            direct: self.data.direct_usage,
+            started_at: self.started_at,
+            // Tracked by the reactor.
+            connected_clients: 0,
+            total_connections: 0,
+            current_clipboard: self.current_clipboard,
+            // Tracked by the backup thread.
+            backups: BackupStats::default(),
+            packed: self
+                .data
+                .packed
+                .as_ref()
+                .map(PackedLog::usage)
+                .transpose()?,
+        })
+    }
+
+    pub fn set_clipboard(&mut self, id: Option<u64>) -> SetClipboardResponse {
+        let error = id.and_then(|id| match self.get_entry(id) {
+            Err(e) => Some(e),
+            Ok((_, id, Entry::Uninitialized)) => Some(IdNotFoundError::Entry(id)),
+            Ok(_) => None,
+        });
+        if error.is_none() {
+            debug!("Clipboard now owned by entry {id:?}.");
+            self.changed |= self.current_clipboard != id;
+            self.current_clipboard = id;
+        }
+        SetClipboardResponse { error }
+    }
+
+    /// Labels are stored in the `user.label` attribute of the entry's direct
+    /// allocation file. Bucket slots and the packed log have nowhere to store
+    /// attributes, so labeling a bucketed or packed entry moves it to a direct
+    /// allocation first.
+    pub fn set_label(&mut self, id: u64, label: &Label) -> Result<SetLabelResponse, CliError> {
+        let (ring, id, entry) = match self.get_entry(id) {
+            Err(e) => return Ok(SetLabelResponse { error: Some(e) }),
+            Ok((_, id, Entry::Uninitialized)) => {
+                return Ok(SetLabelResponse {
+                    error: Some(IdNotFoundError::Entry(id)),
+                });
+            }
+            Ok(r) => r,
+        };
+        debug!("Labeling entry {entry:?} in {ring:?} ring at position {id}: {label:?}.");
+
+        match entry {
+            Entry::Uninitialized => unreachable!(),
+            Entry::Bucketed(_) | Entry::Packed(_) if label.is_empty() => {
+                // Bucketed and packed entries can't have a label to remove.
+            }
+            Entry::Bucketed(_) | Entry::Packed(_) => {
+                let data = self.data.copy_to_direct_file(ring, id, entry)?;
+                fsetxattr(&data, c"user.label", label.as_bytes(), XattrFlags::CREATE)
+                    .map_io_err(|| "Failed to create label attribute.")?;
+                let tags = self.tags.get(ring, id);
+                if !tags.is_empty() {
+                    fsetxattr(&data, tags::XATTR_NAME, tags.as_bytes(), XattrFlags::CREATE)
+                        .map_io_err(|| "Failed to create tags attribute.")?;
+                }
+                let file = self.data.alloc_direct(data, &MimeType::new(), ring, id)?;
+
+                let writer = &mut self.rings[ring].writer;
+                if let Err(e) = writer.write(file, id) {
+                    let e = CliError::from(e);
+                    return Err(if let Err(e2) = self.data.free(file, ring, id) {
+                        CliError::Multiple(vec![e, e2])
+                    } else {
+                        e
+                    });
+                }
+                self.changed = true;
+                self.data.free(entry, ring, id)?;
+                self.set_tags_slot(ring, id, &TagSet::default());
+            }
+            Entry::File => {
+                let mut buf = Default::default();
+                let buf = direct_file_name(&mut buf, ring, id);
+                let file = openat(&self.data.direct_dir, &*buf, OFlags::RDONLY, Mode::empty())
+                    .map_io_err(|| format!("Failed to open direct allocation file: {buf:?}"))?;
+                if label.is_empty() {
+                    match fremovexattr(&file, c"user.label") {
+                        Err(Errno::NODATA) => Ok(()),
+                        r => r,
+                    }
+                    .map_io_err(|| "Failed to remove label attribute.")?;
+                } else {
+                    fsetxattr(&file, c"user.label", label.as_bytes(), XattrFlags::empty())
+                        .map_io_err(|| "Failed to set label attribute.")?;
+                }
+                self.changed = true;
+            }
+        }
+        Ok(SetLabelResponse { error: None })
+    }
+
+    pub fn add_tag(&mut self, id: u64, tag: &Tag) -> Result<TagResponse, CliError> {
+        if !tags::is_valid(tag) {
+            return Ok(TagResponse::InvalidTag);
+        }
+        self.update_tags(id, |tags| tags.insert(tag))
+    }
+
+    pub fn remove_tag(&mut self, id: u64, tag: &Tag) -> Result<TagResponse, CliError> {
+        if !tags::is_valid(tag) {
+            return Ok(TagResponse::InvalidTag);
+        }
+        self.update_tags(id, |tags| Ok(tags.remove(tag)))
+    }
+
+    pub fn list_tags(&self, id: u64) -> Result<ListTagsResponse, CliError> {
+        let (ring, id, entry) = match self.get_entry(id) {
+            Err(e) => {
+                return Ok(ListTagsResponse {
+                    error: Some(e),
+                    tags: TagSet::default(),
+                });
+            }
+            Ok((_, id, Entry::Uninitialized)) => {
+                return Ok(ListTagsResponse {
+                    error: Some(IdNotFoundError::Entry(id)),
+                    tags: TagSet::default(),
+                });
+            }
+            Ok(r) => r,
+        };
+        Ok(ListTagsResponse {
+            error: None,
+            tags: self.entry_tags(ring, id, entry)?,
+        })
+    }
+
+    /// Direct entries keep their tags in their file's `user.tags` attribute
+    /// so they follow it around like its label. Bucket slots have nowhere to
+    /// store attributes, so bucketed entries keep theirs in the tags file
+    /// instead of being moved out of their bucket like labeled entries are.
+    /// Packed entries do the same.
+    fn update_tags(
+        &mut self,
+        id: u64,
+        update: impl FnOnce(&mut TagSet) -> Result<bool, TooManyTags>,
+    ) -> Result<TagResponse, CliError> {
+        let (ring, id, entry) = match self.get_entry(id) {
+            Err(e) => return Ok(TagResponse::Error(e)),
+            Ok((_, id, Entry::Uninitialized)) => {
+                return Ok(TagResponse::Error(IdNotFoundError::Entry(id)));
+            }
+            Ok(r) => r,
+        };
+        let mut tags = self.entry_tags(ring, id, entry)?;
+        let Ok(changed) = update(&mut tags) else {
+            warn!("Refusing to tag entry {id} in {ring:?} ring: it has too many tags.");
+            return Ok(TagResponse::TooManyTags);
+        };
+        if !changed {
+            return Ok(TagResponse::Success { changed });
+        }
+        debug!("Tagging entry {entry:?} in {ring:?} ring at position {id}: {tags:?}.");
+
+        match entry {
+            Entry::Uninitialized => unreachable!(),
+            Entry::Bucketed(_) | Entry::Packed(_) => self.tags.set(ring, id, &tags)?,
+            Entry::File => {
+                let file = self.open_direct(ring, id)?;
+                if tags.is_empty() {
+                    match fremovexattr(&file, tags::XATTR_NAME) {
+                        Err(Errno::NODATA) => Ok(()),
+                        r => r,
+                    }
+                    .map_io_err(|| "Failed to remove tags attribute.")?;
+                } else {
+                    fsetxattr(
+                        &file,
+                        tags::XATTR_NAME,
+                        tags.as_bytes(),
+                        XattrFlags::empty(),
+                    )
+                    .map_io_err(|| "Failed to set tags attribute.")?;
+                }
+            }
+        }
+        self.changed = true;
+        Ok(TagResponse::Success { changed })
+    }
+
+    fn entry_tags(&self, ring: RingKind, id: u32, entry: Entry) -> Result<TagSet, CliError> {
+        match entry {
+            Entry::Uninitialized => unreachable!(),
+            Entry::Bucketed(_) | Entry::Packed(_) => Ok(self.tags.get(ring, id)),
+            Entry::File => xattr_tags(&File::from(self.open_direct(ring, id)?)),
+        }
+    }
+
+    fn open_direct(&self, ring: RingKind, id: u32) -> Result<OwnedFd, CliError> {
+        let mut buf = Default::default();
+        let buf = direct_file_name(&mut buf, ring, id);
+        openat(&self.data.direct_dir, &*buf, OFlags::RDONLY, Mode::empty())
+            .map_io_err(|| format!("Failed to open direct allocation file: {buf:?}"))
+            .map_err(CliError::from)
+    }
+
+    /// Applies the settings that can change while the server is running. See
+    /// [`Settings::startup_only_changes`] for the rest.
+    pub fn reload(&mut self, settings: &Settings) {
+        let Buckets {
+            files: _,
+            slot_counts,
+            generations: _,
+            free_lists: _,
+            preallocator,
+        } = &mut self.data.buckets;
+        *preallocator = Preallocator::new(settings, slot_counts);
+        self.data.compress_text_above = settings.text_storage.compress_text_above;
+        self.sensitive_entry_ttl = settings.sensitive_entries.sensitive_entry_ttl;
+        if self.main_max_age != settings.main_max_age {
+            self.main_max_age = settings.main_max_age;
+            self.next_age_check = settings.main_max_age.map(|_| 0);
+        }
+        self.accept_pipes = settings.accept_pipes;
+        self.entry_limits = settings.entry_limits;
+        self.normalizer = Normalizer::new(settings);
+        self.coalescer.reload(settings);
+        self.recent.reload(settings);
+    }
+
+    /// Gives back memory that is cheap to recreate.
+    ///
+    /// Only pages that can be read back from disk are dropped: the rings are
+    /// read-only shared mappings and bucket files are accessed through the page
+    /// cache whose dirty pages are written back rather than discarded. Clients
+    /// mapping the same files therefore never observe different data, at worst
+    /// they fault pages back in.
+    pub fn trim_memory(&mut self) {
+        for kind in [RingKind::Favorites, RingKind::Main] {
+            if let Err(e) = self.rings[kind].ring.page_out() {
+                warn!("Failed to page out {kind:?} ring: {e}");
+            }
+        }
+
+        let Buckets {
+            files,
+            slot_counts: _,
+            generations: _,
+            free_lists,
+            preallocator: _,
+        } = &mut self.data.buckets;
+        for (bucket, file) in files.iter().enumerate() {
+            // Pages mapped by clients are left alone by the kernel.
+            if let Err(e) = fadvise(file, 0, 0, Advice::DontNeed) {
+                warn!("Failed to drop cached pages of bucket {bucket}: {e}");
+            }
+        }
+        for free_slots in &mut free_lists.lists.0 {
+            free_slots.shrink_to_fit();
+        }
+    }
+
     pub fn gc(&mut self, max_wasted_bytes: u64) -> Result<GarbageCollectResponse, CliError> {
-        self.gc_(max_wasted_bytes)
-            .map(|bytes_freed| GarbageCollectResponse { bytes_freed })
+        let bucket_bytes_freed = self.gc_(max_wasted_bytes)?;
+        let packed_bytes_freed = self.compact_packed(max_wasted_bytes)?;
+        Ok(GarbageCollectResponse {
+            bytes_freed: bucket_bytes_freed.iter().sum::<u64>() + packed_bytes_freed,
+            bucket_bytes_freed,
+            packed_bytes_freed,
+        })
     }
 
-    fn gc_(&mut self, max_wasted_bytes: u64) -> Result<u64, CliError> {
+    /// Compacts the packed log if removed entries left more than
+    /// `max_wasted_bytes` of garbage in it, returning how many bytes that
+    /// freed.
+    fn compact_packed(&mut self, max_wasted_bytes: u64) -> Result<u64, CliError> {
+        let Some(packed) = &mut self.data.packed else {
+            return Ok(0);
+        };
+        debug!(
+            "Packed log compaction requested with {max_wasted_bytes} bytes of max wasted space; \
+             found {} wasted bytes.",
+            packed.garbage()
+        );
+        if packed.garbage() <= max_wasted_bytes {
+            return Ok(0);
+        }
+        // Nothing in the rings changes, but backups need to notice the records moving.
+        self.changed = true;
+        packed.compact(self.rings.packed_entries())
+    }
+
+    /// Compacts the bucket files by moving the entries in their last slots
+    /// into free slots closer to the front and truncating the slots left
+    /// behind.
+    ///
+    /// An entry's data is copied to its new slot before its ring entry is
+    /// pointed there, so readers following the ring never land on a slot
+    /// without the entry's data. Only slots past the last live one are
+    /// truncated.
+    fn gc_(&mut self, max_wasted_bytes: u64) -> Result<[u64; NUM_BUCKETS], CliError> {
         const MIN_BYTES_TO_FREE: u64 = 1 << 14;
 
         let wasted_bucket_bytes = self
@@ -558,7 +2375,7 @@ impl Allocator {
              {wasted_bucket_bytes} wasted bytes."
         );
         if wasted_bucket_bytes <= max_wasted_bytes {
-            return Ok(0);
+            return Ok([0; NUM_BUCKETS]);
         }
         info!("Running GC.");
 
@@ -623,7 +2440,9 @@ impl Allocator {
         let Buckets {
             files,
             slot_counts,
+            generations,
             free_lists,
+            preallocator: _,
         } = &mut self.data.buckets;
 
         let mut swappable_allocations = [const { BinaryHeap::new() }; NUM_BUCKETS];
@@ -671,7 +2490,9 @@ impl Allocator {
                                 slots.push(item);
                             }
                         }
-                        Some(Entry::Uninitialized | Entry::File) | None => continue,
+                        Some(Entry::Uninitialized | Entry::File | Entry::Packed(_)) | None => {
+                            continue;
+                        }
                     }
                 }
             }
@@ -683,13 +2504,16 @@ impl Allocator {
         });
 
         let mut pending_frees = Vec::with_capacity(usize::try_from(layers_to_remove).unwrap());
-        let mut bytes_freed = 0;
-        for ((((file, slot_count), free_slots), mut swappable_allocations), bucket_size) in files
+        let mut bytes_freed = [0; NUM_BUCKETS];
+        for (
+            (((file, slot_count), free_slots), mut swappable_allocations),
+            (bucket_size, bucket_bytes_freed),
+        ) in files
             .iter_mut()
             .zip(slot_counts)
             .zip(&mut free_lists.lists.0)
             .zip(swappable_allocations)
-            .zip((0..NUM_BUCKETS).map(bucket_to_length))
+            .zip((0..NUM_BUCKETS).map(bucket_to_length).zip(&mut bytes_freed))
         {
             let mut swap = || -> Result<_, CliError> {
                 for _ in 0..layers_to_remove {
@@ -713,24 +2537,29 @@ impl Allocator {
                          length {bucket_size}."
                     );
 
-                    copy_file_range_all(
-                        &*file,
-                        Some(&mut (u64::from(alloc) * u64::from(bucket_size))),
-                        &*file,
-                        Some(&mut (u64::from(free) * u64::from(bucket_size))),
-                        // Copy the NUL byte too
-                        if size < bucket_size { size + 1 } else { size }.into(),
-                    )
-                    .map_io_err(|| {
-                        format!(
-                            "Failed to copy bucket slot {alloc} to {free} in bucket {}.",
-                            size_to_bucket(size)
-                        )
-                    })?;
+                    // Readers may still be copying an entry the free slot used to hold.
+                    generations
+                        .write(InitializedEntry::bucket(size, free), || {
+                            copy_file_range_all(
+                                &*file,
+                                Some(&mut (u64::from(alloc) * u64::from(bucket_size))),
+                                &*file,
+                                Some(&mut (u64::from(free) * u64::from(bucket_size))),
+                                // Copy the NUL byte too
+                                if size < bucket_size { size + 1 } else { size }.into(),
+                            )
+                        })
+                        .map_io_err(|| {
+                            format!(
+                                "Failed to copy bucket slot {alloc} to {free} in bucket {}.",
+                                size_to_bucket(size)
+                            )
+                        })?;
                     writer.write(
                         Entry::Bucketed(InitializedEntry::bucket(size, free)),
                         rai.index(),
                     )?;
+                    self.changed = true;
 
                     free_slots.pop();
                     swappable_allocations.pop();
@@ -773,49 +2602,121 @@ impl Allocator {
             })?;
             *slot_count -= u32::try_from(drop_count).unwrap();
             free_slots.drain(..drop_count);
-            bytes_freed += u64::try_from(drop_count).unwrap() * u64::from(bucket_size);
+            *bucket_bytes_freed = u64::try_from(drop_count).unwrap() * u64::from(bucket_size);
         }
-        info!("GC freed {bytes_freed} bytes.");
+        self.data
+            .buckets
+            .preallocator
+            .reset(&self.data.buckets.slot_counts);
+        info!(
+            "GC freed {} bytes: {bytes_freed:?}.",
+            bytes_freed.iter().sum::<u64>()
+        );
         Ok(bytes_freed)
     }
 
     pub fn shutdown(self) -> Result<(), CliError> {
-        self.data.buckets.free_lists.save()
+        self.data.buckets.free_lists.save(&self.data_dir)
     }
 }
 
 impl AllocatorData {
+    /// Copies the entry's data into an anonymous file in the direct directory,
+    /// stopping once it has more than `max` bytes.
+    fn receive(&self, data: OwnedFd, stream: bool, max: u64) -> Result<(File, u64), CliError> {
+        let mut received = File::from(
+            openat(
+                &self.direct_dir,
+                c".",
+                OFlags::RDWR | OFlags::TMPFILE,
+                Mode::RUSR | Mode::WUSR,
+            )
+            .map_io_err(|| "Failed to create data receiver file.")?,
+        );
+
+        let limit = max.saturating_add(1);
+        let size = if stream {
+            copy_stream(&File::from(data), &mut received, limit)
+        } else {
+            io::copy(&mut File::from(data).take(limit), &mut received)
+        }
+        .map_io_err(|| "Failed to copy data to receiver file.")?;
+        debug!("Received {size} bytes.");
+
+        Ok((received, size))
+    }
+
     fn alloc(
         &mut self,
-        data: OwnedFd,
+        received: File,
+        size: u64,
         mime_type: &MimeType,
         to: RingKind,
         id: u32,
+        truncated: bool,
+        expires_at: Option<u64>,
     ) -> Result<Entry, CliError> {
         debug!("Allocating entry to {to:?} ring at position {id} with mime type {mime_type:?}.");
-        let mut received = File::from(
+        let is_text = TEXT_MIMES.iter().any(|b| mime_type.eq_ignore_ascii_case(b));
+        if !truncated && expires_at.is_none() && is_text && size < 4096 {
+            let size = u16::try_from(size).unwrap();
+            return match &mut self.packed {
+                Some(packed) if size <= packed::MAX_LEN => packed.append(&received, size, to, id),
+                _ => self.alloc_bucket(received, size),
+            };
+        }
+        let data = match self.compress_text_above {
+            Some(threshold) if is_text && size >= threshold => self.compress(received, size)?,
+            _ => received,
+        };
+        if truncated {
+            // Only direct files have somewhere to record this.
+            fsetxattr(&data, c"user.truncated", b"1", XattrFlags::CREATE)
+                .map_io_err(|| "Failed to create truncation attribute.")?;
+        }
+        if let Some(expires_at) = expires_at {
+            sensitive::write(&data, expires_at)?;
+        }
+        if is_text {
+            self.alloc_direct(data, &MimeType::new(), to, id)
+        } else {
+            self.alloc_direct(data, mime_type, to, id)
+        }
+    }
+
+    /// Compresses the data of a direct allocation, returning it as is if it
+    /// doesn't shrink by at least a fifth since reading it back would then
+    /// cost more than the space saved.
+    fn compress(&self, data: File, size: u64) -> Result<File, CliError> {
+        let compressed = File::from(
             openat(
                 &self.direct_dir,
                 c".",
                 OFlags::RDWR | OFlags::TMPFILE,
                 Mode::RUSR | Mode::WUSR,
             )
-            .map_io_err(|| "Failed to create data receiver file.")?,
+            .map_io_err(|| "Failed to create compressed direct allocation file.")?,
         );
-
-        let size = io::copy(&mut File::from(data), &mut received)
-            .map_io_err(|| "Failed to copy data to receiver file.")?;
-        debug!("Received {size} bytes.");
-
-        if TEXT_MIMES.iter().any(|b| mime_type.eq_ignore_ascii_case(b)) {
-            if size > 0 && size < 4096 {
-                self.alloc_bucket(received, u16::try_from(size).unwrap())
-            } else {
-                self.alloc_direct(received, &MimeType::new(), to, id)
-            }
-        } else {
-            self.alloc_direct(received, mime_type, to, id)
+        let mut source = &data;
+        let compressed_size = source
+            .rewind()
+            .and_then(|()| {
+                zstd::stream::copy_encode(
+                    source.take(size),
+                    &compressed,
+                    zstd::DEFAULT_COMPRESSION_LEVEL,
+                )
+            })
+            .and_then(|()| (&compressed).stream_position())
+            .map_io_err(|| "Failed to compress direct allocation.")?;
+        if compressed_size > size / 5 * 4 {
+            debug!("Storing {size} bytes uncompressed as they only shrank to {compressed_size}.");
+            return Ok(data);
         }
+
+        debug!("Compressed {size} bytes to {compressed_size}.");
+        encoding::write(&compressed, Encoding::Zstd)?;
+        Ok(compressed)
     }
 
     fn alloc_bucket(&mut self, data: File, size: u16) -> Result<Entry, CliError> {
@@ -824,7 +2725,9 @@ impl AllocatorData {
         let Buckets {
             files,
             slot_counts: bucket_lengths,
+            generations,
             free_lists,
+            preallocator,
         } = &mut self.buckets;
 
         let free_bucket = free_lists.alloc(bucket);
@@ -836,30 +2739,57 @@ impl AllocatorData {
         debug!("Writing to bucket {bucket} at slot {bucket_index}.");
         {
             let grow = free_bucket.is_none();
+            let start = u64::from(bucket_index) * u64::from(bucket_len);
+            let end = start + u64::from(bucket_len);
             if grow {
-                bucket_lengths[bucket] += 1;
+                preallocator
+                    .reserve(bucket, end, |offset, len| {
+                        fallocate(&files[bucket], FallocateFlags::KEEP_SIZE, offset, len)
+                    })
+                    .map_io_err(|| Context::Bucket {
+                        message: "Failed to preallocate space for bucket",
+                        bucket,
+                    })?;
             }
 
-            let mut offset = u64::from(bucket_index) * u64::from(bucket_len);
-            copy_file_range_all(
-                data,
-                Some(&mut 0),
-                &files[bucket],
-                Some(&mut offset),
-                usize::from(size),
-            )
-            .map_io_err(|| format!("Failed to copy data to bucket {bucket}."))?;
-            if size < bucket_len {
-                files[bucket]
-                    .write_all_at(
-                        &[0],
-                        if grow {
-                            u64::from(bucket_index + 1) * u64::from(bucket_len) - 1
-                        } else {
-                            offset
-                        },
-                    )
-                    .map_io_err(|| format!("Failed to write NUL bytes to bucket {bucket}."))?;
+            let write = || -> Result<(), CliError> {
+                let mut offset = start;
+                copy_file_range_all(
+                    data,
+                    Some(&mut 0),
+                    &files[bucket],
+                    Some(&mut offset),
+                    usize::from(size),
+                )
+                .map_io_err(|| Context::Bucket {
+                    message: "Failed to copy data to bucket",
+                    bucket,
+                })?;
+                if size < bucket_len {
+                    files[bucket]
+                        .write_all_at(&[0], if grow { end - 1 } else { offset })
+                        .map_io_err(|| Context::Bucket {
+                            message: "Failed to write NUL bytes to bucket",
+                            bucket,
+                        })?;
+                }
+                Ok(())
+            };
+            // Readers may still be copying whatever the slot held before.
+            if let Err(e) = generations.write(InitializedEntry::bucket(size, bucket_index), write) {
+                if grow {
+                    // Don't leave a partially written slot behind since slot counts are
+                    // derived from the bucket length.
+                    if ftruncate(&files[bucket], start).is_ok() {
+                        preallocator.truncated(bucket, start);
+                    }
+                }
+                // A reused slot goes back to the free list since nothing points at it.
+                drop(free_bucket);
+                return Err(e);
+            }
+            if grow {
+                bucket_lengths[bucket] += 1;
             }
         }
 
@@ -869,7 +2799,7 @@ impl AllocatorData {
     }
 
     fn alloc_direct(
-        &self,
+        &mut self,
         data: File,
         &mime_type: &MimeType,
         to: RingKind,
@@ -887,15 +2817,71 @@ impl AllocatorData {
             )
             .map_io_err(|| "Failed to create mime type attribute.")?;
         }
+        checksums::write(&data)?;
 
+        let size = fstat(&data)
+            .map_io_err(|| "Failed to stat direct allocation.")?
+            .st_size;
         let mut buf = Default::default();
         let buf = direct_file_name(&mut buf, to, id);
         link_tmp_file(data, &self.direct_dir, &*buf)
             .map_io_err(|| format!("Failed to materialize direct allocation: {buf:?}"))?;
 
+        self.direct_usage.files += 1;
+        self.direct_usage.bytes += u64::try_from(size).unwrap();
         Ok(Entry::File)
     }
 
+    /// Copies a bucketed or packed entry into an anonymous file in the direct
+    /// directory.
+    fn copy_to_direct_file(&self, ring: RingKind, id: u32, entry: Entry) -> Result<File, CliError> {
+        let mut data = [0; MAX_INLINE_READ_LEN];
+        let data = match entry {
+            Entry::Bucketed(entry) => {
+                let bucket = usize::from(size_to_bucket(entry.size()));
+                let data = &mut data[..usize::from(entry.size())];
+                self.buckets.files[bucket]
+                    .read_exact_at(
+                        data,
+                        u64::from(entry.index()) * u64::from(bucket_to_length(bucket)),
+                    )
+                    .map_io_err(|| Context::Bucket {
+                        message: "Failed to read from bucket",
+                        bucket,
+                    })?;
+                data
+            }
+            Entry::Packed(entry) => {
+                let data = &mut data[..usize::from(entry.size())];
+                let (log, start) = self.locate_packed(ring, id)?;
+                log.read_exact_at(data, start)
+                    .map_io_err(|| "Failed to read from packed log.")?;
+                data
+            }
+            Entry::Uninitialized | Entry::File => unreachable!(),
+        };
+
+        let mut file = File::from(
+            openat(
+                &self.direct_dir,
+                c".",
+                OFlags::RDWR | OFlags::TMPFILE,
+                Mode::RUSR | Mode::WUSR,
+            )
+            .map_io_err(|| "Failed to create direct allocation file.")?,
+        );
+        file.write_all(data)
+            .map_io_err(|| "Failed to copy entry to direct allocation file.")?;
+        Ok(file)
+    }
+
+    fn locate_packed(&self, ring: RingKind, id: u32) -> Result<(&File, u64), CliError> {
+        self.packed.as_ref().map_or_else(
+            || Err(missing_record(ring, id)),
+            |packed| packed.locate(ring, id),
+        )
+    }
+
     fn free(&mut self, entry: Entry, to: RingKind, id: u32) -> Result<(), CliError> {
         debug!("Freeing entry in {to:?} ring at position {id}: {entry:?}");
         match entry {
@@ -906,16 +2892,790 @@ impl AllocatorData {
                     .free(size_to_bucket(bucket.size()).into(), bucket.index());
                 Ok(())
             }
+            Entry::Packed(_) => {
+                if let Some(packed) = &mut self.packed {
+                    packed.free(to, id);
+                }
+                Ok(())
+            }
             Entry::File => self.free_direct(to, id),
         }
     }
 
-    fn free_direct(&self, to: RingKind, id: u32) -> Result<(), CliError> {
+    /// When a direct allocation file was last written in seconds since the
+    /// Unix epoch, which is about when its entry was added.
+    fn direct_file_mtime(&self, ring: RingKind, id: u32) -> Option<u64> {
+        let mut buf = Default::default();
+        let buf = direct_file_name(&mut buf, ring, id);
+        statx(&self.direct_dir, &*buf, AtFlags::empty(), StatxFlags::MTIME)
+            .ok()
+            .and_then(|stat| u64::try_from(stat.stx_mtime.tv_sec).ok())
+    }
+
+    fn free_direct(&mut self, to: RingKind, id: u32) -> Result<(), CliError> {
         debug!("Freeing direct allocation.");
         let mut buf = Default::default();
         let buf = direct_file_name(&mut buf, to, id);
+        let size = statx(&self.direct_dir, &*buf, AtFlags::empty(), StatxFlags::SIZE)
+            .map_io_err(|| format!("Failed to stat direct allocation file: {buf:?}"))?
+            .stx_size;
         unlinkat(&self.direct_dir, &*buf, AtFlags::empty())
             .map_io_err(|| format!("Failed to remove direct allocation file: {buf:?}"))?;
+
+        // Stats only, so they shouldn't take the server down if they drift.
+        self.direct_usage.files = self.direct_usage.files.saturating_sub(1);
+        self.direct_usage.bytes = self.direct_usage.bytes.saturating_sub(size);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ringboard_core::NUM_BUCKETS;
+    use rustix::io::Errno;
+
+    use super::Preallocator;
+
+    fn preallocator(chunk_size: u64) -> Preallocator {
+        Preallocator {
+            chunk_size: Some(chunk_size),
+            reserved: [0; NUM_BUCKETS],
+        }
+    }
+
+    #[test]
+    fn reserves_whole_chunks() {
+        let mut preallocator = preallocator(4096);
+        let mut calls = Vec::new();
+
+        for end in [4, 8, 4096, 4100] {
+            preallocator
+                .reserve(0, end, |offset, len| {
+                    calls.push((offset, len));
+                    Ok(())
+                })
+                .unwrap();
+        }
+
+        assert_eq!(calls, [(0, 4096), (4096, 4096)]);
+        assert_eq!(preallocator.reserved[0], 8192);
+    }
+
+    #[test]
+    fn falls_back_to_exact_size_when_out_of_space() {
+        let mut preallocator = preallocator(4096);
+        let mut calls = Vec::new();
+
+        preallocator
+            .reserve(0, 8, |offset, len| {
+                calls.push((offset, len));
+                if len > 8 { Err(Errno::NOSPC) } else { Ok(()) }
+            })
+            .unwrap();
+        assert_eq!(calls, [(0, 4096), (0, 8)]);
+        assert_eq!(preallocator.reserved[0], 8);
+
+        assert_eq!(
+            preallocator.reserve(0, 16, |_, _| Err(Errno::NOSPC)),
+            Err(Errno::NOSPC)
+        );
+        assert_eq!(preallocator.reserved[0], 8);
+    }
+
+    #[test]
+    fn truncation_releases_reserved_space() {
+        let mut preallocator = preallocator(4096);
+        preallocator.reserve(0, 8, |_, _| Ok(())).unwrap();
+        assert_eq!(preallocator.reserved[0], 4096);
+
+        preallocator.truncated(0, 4);
+        let mut calls = Vec::new();
+        preallocator
+            .reserve(0, 8, |offset, len| {
+                calls.push((offset, len));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(calls, [(4, 4092)]);
+    }
+
+    #[test]
+    fn disables_itself_without_file_system_support() {
+        let mut preallocator = preallocator(4096);
+
+        preallocator
+            .reserve(0, 8, |_, _| Err(Errno::OPNOTSUPP))
+            .unwrap();
+        assert_eq!(preallocator.chunk_size, None);
+        preallocator.reserve(0, 16, |_, _| unreachable!()).unwrap();
+    }
+}
+
+/// Model-based tests: random operations are applied to both a real allocator
+/// and an in-memory model of the rings, checking after every step that they
+/// agree and that no storage was leaked.
+///
+/// Set `RINGBOARD_SIM_SEED` to replay a single seed.
+#[cfg(test)]
+mod simulation {
+    use std::{
+        collections::BTreeSet, env, fs, fs::File, io::Read, mem, os::fd::OwnedFd, path::Path,
+        process,
+    };
+
+    use ringboard_core::{
+        bucket_to_length, checksums,
+        checksums::Verification,
+        origins::Origin,
+        packed,
+        protocol::{
+            composite_id, decompose_id, AddResponse, GarbageCollectResponse, Label, MimeType,
+            MoveToFrontResponse, ReadOutcome, ReadResponse, RemoveResponse, RingKind,
+            SetClipboardResponse, SetLabelResponse, SwapResponse,
+        },
+        ring::Entry,
+        settings::EntryLimits,
+        size_to_bucket, NUM_BUCKETS, TEXT_MIMES,
+    };
+    use rustix::{
+        fs::{fgetxattr, memfd_create, MemfdFlags},
+        io::Errno,
+    };
+
+    use super::Allocator;
+    use crate::{
+        settings::{Coalescing, Deduplication, Settings, TextStorage},
+        startup::PreviousShutdown,
+    };
+
+    const SEEDS: u64 = 3;
+    const OPS_PER_SEED: u32 = 3000;
+
+    /// `SplitMix64`, which is plenty for picking operations.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        /// Favorites are preferred so that ring wraps around within a seed.
+        fn ring(&mut self) -> RingKind {
+            if self.below(4) == 0 {
+                RingKind::Main
+            } else {
+                RingKind::Favorites
+            }
+        }
+
+        fn entry(&mut self) -> ModelEntry {
+            let len = match self.below(10) {
+                0 => 0,
+                1..=2 => self.below(u64::from(packed::MAX_LEN) + 1),
+                3..=7 => self.below(4096),
+                _ => 4096 + self.below(8192),
+            };
+            let mut data = vec![0; usize::try_from(len).unwrap()];
+            for chunk in data.chunks_mut(8) {
+                chunk.copy_from_slice(&self.next().to_le_bytes()[..chunk.len()]);
+            }
+            let mime_type = if self.below(4) == 0 { "image/png" } else { "" };
+            let origin =
+                ["", "ringboard-x11", "ringboard-cli"][usize::try_from(self.below(3)).unwrap()];
+            ModelEntry {
+                data,
+                mime_type: MimeType::from(mime_type).unwrap(),
+                label: String::new(),
+                labeled: false,
+                origin: Origin::new(origin, 1000),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct ModelEntry {
+        data: Vec<u8>,
+        mime_type: MimeType,
+        label: String,
+        /// Labeling moves entries out of their bucket for good.
+        labeled: bool,
+        origin: Option<Origin>,
+    }
+
+    impl ModelEntry {
+        fn is_bucketed(&self) -> bool {
+            !self.labeled
+                && TEXT_MIMES
+                    .iter()
+                    .any(|m| self.mime_type.eq_ignore_ascii_case(m))
+                && (1..4096).contains(&self.data.len())
+        }
+
+        fn is_packed(&self, packing: bool) -> bool {
+            packing && self.is_bucketed() && self.data.len() <= usize::from(packed::MAX_LEN)
+        }
+    }
+
+    #[derive(Default)]
+    struct ModelRing {
+        entries: Vec<Option<ModelEntry>>,
+        write_head: u32,
+    }
+
+    #[derive(Default)]
+    struct Model {
+        rings: [ModelRing; 2],
+        clipboard: Option<u64>,
+    }
+
+    impl Model {
+        fn add(&mut self, to: RingKind, entry: ModelEntry) -> u64 {
+            let ring = &mut self.rings[to as usize];
+            let head = ring.write_head;
+            let index = usize::try_from(head).unwrap();
+            if index == ring.entries.len() {
+                ring.entries.push(Some(entry));
+            } else {
+                ring.entries[index] = Some(entry);
+            }
+            ring.write_head = next_head(to, head);
+            let id = composite_id(to, head);
+            if self.clipboard == Some(id) {
+                self.clipboard = None;
+            }
+            id
+        }
+
+        fn slot(&mut self, id: u64) -> Option<&mut Option<ModelEntry>> {
+            let (ring, index) = decompose_id(id).ok()?;
+            self.rings[ring as usize]
+                .entries
+                .get_mut(usize::try_from(index).unwrap())
+        }
+
+        fn is_live(&self, id: u64) -> Option<bool> {
+            let (ring, index) = decompose_id(id).ok()?;
+            self.rings[ring as usize]
+                .entries
+                .get(usize::try_from(index).unwrap())
+                .map(Option::is_some)
+        }
+
+        fn move_to_front(&mut self, id: u64, to: Option<RingKind>) -> Option<u64> {
+            let (from, index) = decompose_id(id).unwrap();
+            let write_head = self.rings[from as usize].write_head;
+            let slot = self.slot(id)?;
+            slot.as_ref()?;
+
+            let to = to.unwrap_or(from);
+            if from == to && next_head(from, index) == write_head {
+                return Some(id);
+            }
+            let favorites_head = self.rings[RingKind::Favorites as usize].write_head;
+            let head = composite_id(RingKind::Favorites, favorites_head);
+            if to == RingKind::Favorites && self.is_live(head) == Some(true) && head != id {
+                return None;
+            }
+            let entry = self.slot(id).unwrap().take().unwrap();
+            let new_id = self.add(to, entry);
+            if self.clipboard == Some(id) {
+                self.clipboard = Some(new_id);
+            }
+            Some(new_id)
+        }
+
+        fn swap(&mut self, id1: u64, id2: u64) -> bool {
+            let (Some(live1), Some(live2)) = (self.is_live(id1), self.is_live(id2)) else {
+                return false;
+            };
+            if !live1 && !live2 {
+                return false;
+            }
+            if id1 != id2 {
+                let entry1 = self.slot(id1).unwrap().take();
+                let entry2 = mem::replace(self.slot(id2).unwrap(), entry1);
+                *self.slot(id1).unwrap() = entry2;
+            }
+            if self.clipboard == Some(id1) {
+                self.clipboard = Some(id2);
+            } else if self.clipboard == Some(id2) {
+                self.clipboard = Some(id1);
+            }
+            true
+        }
+
+        fn remove(&mut self, id: u64) -> Option<ModelEntry> {
+            let entry = self.slot(id).and_then(Option::take);
+            if entry.is_some() && self.clipboard == Some(id) {
+                self.clipboard = None;
+            }
+            entry
+        }
+
+        fn set_clipboard(&mut self, id: Option<u64>) -> bool {
+            let valid = id.is_none_or(|id| self.is_live(id) == Some(true));
+            if valid {
+                self.clipboard = id;
+            }
+            valid
+        }
+
+        fn set_label(&mut self, id: u64, label: &str) -> bool {
+            let Some(Some(entry)) = self.slot(id) else {
+                return false;
+            };
+            entry.labeled |= !label.is_empty();
+            entry.label = label.to_string();
+            true
+        }
+
+        fn ids(&self) -> impl Iterator<Item = (u64, Option<&ModelEntry>)> {
+            [RingKind::Favorites, RingKind::Main]
+                .into_iter()
+                .flat_map(move |kind| {
+                    self.rings[kind as usize].entries.iter().enumerate().map(
+                        move |(index, entry)| {
+                            (
+                                composite_id(kind, u32::try_from(index).unwrap()),
+                                entry.as_ref(),
+                            )
+                        },
+                    )
+                })
+        }
+    }
+
+    fn next_head(kind: RingKind, head: u32) -> u32 {
+        if head >= kind.default_max_entries() - 1 {
+            0
+        } else {
+            head + 1
+        }
+    }
+
+    fn data_fd(data: &[u8]) -> OwnedFd {
+        let fd = memfd_create(c"ringboard-simulation", MemfdFlags::empty()).unwrap();
+        rustix::io::write(&fd, data).unwrap();
+        rustix::fs::seek(&fd, rustix::fs::SeekFrom::Start(0)).unwrap();
+        fd
+    }
+
+    fn read(allocator: &Allocator, id: u64, offset: u64, len: u32) -> Vec<u8> {
+        match allocator.read(id, offset, len).unwrap() {
+            (response, None) if response.outcome == ReadOutcome::Inline => response.data().to_vec(),
+            (response, Some(fd)) if response.outcome == ReadOutcome::File => {
+                let mut data = Vec::new();
+                File::from(fd).read_to_end(&mut data).unwrap();
+                data
+            }
+            (
+                ReadResponse {
+                    outcome: ReadOutcome::Error(e),
+                    ..
+                },
+                _,
+            ) => panic!("Failed to read entry {id}: {e:?}"),
+            _ => unreachable!(),
+        }
+    }
+
+    fn label(allocator: &Allocator, id: u64) -> String {
+        let (
+            ReadResponse {
+                outcome: ReadOutcome::File,
+                ..
+            },
+            Some(fd),
+        ) = allocator.read(id, 0, u32::MAX).unwrap()
+        else {
+            return String::new();
+        };
+        let mut label = [0; Label::new_const().capacity()];
+        match fgetxattr(&fd, c"user.label", &mut label) {
+            Err(Errno::NODATA) => String::new(),
+            r => String::from_utf8(label[..r.unwrap()].to_vec()).unwrap(),
+        }
+    }
+
+    /// Picks mostly live entries, but also holes and ids past the end of rings.
+    fn pick_id(rng: &mut Rng, model: &Model) -> u64 {
+        let kind = rng.ring();
+        let len = model.rings[kind as usize].entries.len();
+        let index = rng.below(u64::try_from(len).unwrap() + 2);
+        composite_id(kind, u32::try_from(index).unwrap())
+    }
+
+    /// Adds the entry to both, returning its id unless the allocator rightly
+    /// rejected it.
+    fn add(
+        allocator: &mut Allocator,
+        model: &mut Model,
+        to: RingKind,
+        entry: ModelEntry,
+    ) -> Option<u64> {
+        let response = allocator
+            .add(
+                data_fd(&entry.data),
+                to,
+                &entry.mime_type,
+                entry.origin,
+                None,
+                None,
+            )
+            .unwrap()
+            .unwrap();
+        if entry.data.is_empty() {
+            assert!(matches!(response, AddResponse::Empty));
+            return None;
+        }
+        let max = allocator.entry_limits.max_entry_size;
+        if u64::try_from(entry.data.len()).unwrap() > max {
+            assert!(matches!(response, AddResponse::TooLarge { max: real } if real == max));
+            return None;
+        }
+        let id = model.add(to, entry);
+        assert!(
+            matches!(response, AddResponse::Success { id: real, normalized: false, coalesced: false, deduplicated: false, truncated: false } if real == id)
+        );
+        Some(id)
+    }
+
+    fn step(rng: &mut Rng, allocator: &mut Allocator, model: &mut Model) {
+        match rng.below(26) {
+            0..=9 => {
+                let to = rng.ring();
+                add(allocator, model, to, rng.entry());
+            }
+            10..=12 => {
+                let id = pick_id(rng, model);
+                let to = [None, Some(RingKind::Favorites), Some(RingKind::Main)]
+                    [usize::try_from(rng.below(3)).unwrap()];
+                match (
+                    allocator.move_to_front(id, to).unwrap(),
+                    model.move_to_front(id, to),
+                ) {
+                    (MoveToFrontResponse::Success { id: real }, Some(id)) => assert_eq!(real, id),
+                    (MoveToFrontResponse::Error(_) | MoveToFrontResponse::FavoritesFull, None) => {}
+                    (real, expected) => panic!("Moved {id}: {real:?} instead of {expected:?}"),
+                }
+            }
+            13..=15 => {
+                let (id1, id2) = (pick_id(rng, model), pick_id(rng, model));
+                let SwapResponse { error1, error2 } = allocator.swap(id1, id2).unwrap();
+                assert_eq!(
+                    error1.is_none() && error2.is_none(),
+                    model.swap(id1, id2),
+                    "Swapped {id1} and {id2}: {error1:?} {error2:?}"
+                );
+            }
+            16..=18 => {
+                let id = pick_id(rng, model);
+                let RemoveResponse { error } = allocator.remove(id).unwrap();
+                let expected = model.remove(id);
+                assert_eq!(
+                    error.is_none(),
+                    expected.is_some(),
+                    "Removed {id}: {error:?}"
+                );
+            }
+            19..=20 => {
+                let id = (rng.below(4) != 0).then(|| pick_id(rng, model));
+                let SetClipboardResponse { error } = allocator.set_clipboard(id);
+                assert_eq!(
+                    error.is_none(),
+                    model.set_clipboard(id),
+                    "Set clipboard to {id:?}: {error:?}"
+                );
+            }
+            21..=22 => {
+                let id = pick_id(rng, model);
+                let label = ["", "AWS prod token", "x"][usize::try_from(rng.below(3)).unwrap()];
+                let SetLabelResponse { error } = allocator
+                    .set_label(id, &Label::from(label).unwrap())
+                    .unwrap();
+                assert_eq!(
+                    error.is_none(),
+                    model.set_label(id, label),
+                    "Labeled {id} {label:?}: {error:?}"
+                );
+            }
+            23..=24 => {
+                // Clients replace an entry's contents by adding the new ones, swapping
+                // them into the entry's slot, and removing what was swapped out.
+                let id = pick_id(rng, model);
+                let Some(added) = add(allocator, model, RingKind::Main, rng.entry()) else {
+                    return;
+                };
+                let SwapResponse { error1, error2 } = allocator.swap(id, added).unwrap();
+                assert_eq!(
+                    error1.is_none() && error2.is_none(),
+                    model.swap(id, added),
+                    "Replaced {id} with {added}: {error1:?} {error2:?}"
+                );
+                let RemoveResponse { error } = allocator.remove(added).unwrap();
+                let expected = model.remove(added);
+                assert_eq!(
+                    error.is_none(),
+                    expected.is_some(),
+                    "Removed replaced {added}: {error:?}"
+                );
+            }
+            _ => {
+                let max_wasted_bytes = [0, 4096, u64::MAX][usize::try_from(rng.below(3)).unwrap()];
+                let GarbageCollectResponse {
+                    bytes_freed,
+                    bucket_bytes_freed,
+                    packed_bytes_freed,
+                } = allocator.gc(max_wasted_bytes).unwrap();
+                assert_eq!(
+                    bytes_freed,
+                    bucket_bytes_freed.iter().sum::<u64>() + packed_bytes_freed
+                );
+            }
+        }
+    }
+
+    fn check(allocator: &Allocator, model: &Model, dir: &Path) {
+        for kind in [RingKind::Favorites, RingKind::Main] {
+            let ring = &allocator.rings[kind].ring;
+            let expected = &model.rings[kind as usize];
+            assert_eq!(
+                ring.write_head(),
+                expected.write_head,
+                "{kind:?} write head"
+            );
+            assert_eq!(
+                usize::try_from(ring.len()).unwrap(),
+                expected.entries.len(),
+                "{kind:?} length"
+            );
+        }
+
+        assert_eq!(
+            allocator.current_clipboard, model.clipboard,
+            "Clipboard owner"
+        );
+
+        let packing = allocator.data.packed.is_some();
+        let mut expected_files = BTreeSet::new();
+        let mut used_slots = [const { BTreeSet::new() }; NUM_BUCKETS];
+        let mut packed_ranges = Vec::new();
+        for (id, expected) in model.ids() {
+            let (kind, index) = decompose_id(id).unwrap();
+            let entry = allocator.rings[kind].ring.get(index).unwrap();
+            let Some(expected) = expected else {
+                assert_eq!(entry, Entry::Uninitialized, "Entry {id} should be empty");
+                assert_eq!(
+                    allocator.timestamps.get(kind, index),
+                    None,
+                    "Entry {id} timestamp"
+                );
+                continue;
+            };
+
+            match entry {
+                Entry::Packed(entry) if expected.is_packed(packing) => {
+                    assert_eq!(usize::from(entry.size()), expected.data.len());
+                    let (_, start) = allocator
+                        .data
+                        .packed
+                        .as_ref()
+                        .unwrap()
+                        .locate(kind, index)
+                        .unwrap();
+                    packed_ranges.push(start..start + u64::from(entry.size()));
+                }
+                Entry::Bucketed(entry)
+                    if expected.is_bucketed() && !expected.is_packed(packing) =>
+                {
+                    assert_eq!(usize::from(entry.size()), expected.data.len());
+                    let bucket = usize::from(size_to_bucket(entry.size()));
+                    assert!(
+                        used_slots[bucket].insert(entry.index()),
+                        "Slot {} of bucket {bucket} is shared",
+                        entry.index()
+                    );
+                }
+                Entry::File if !expected.is_bucketed() => {
+                    expected_files.insert(format!("{id:0>13}"));
+                    let (
+                        ReadResponse {
+                            outcome: ReadOutcome::File,
+                            ..
+                        },
+                        Some(fd),
+                    ) = allocator.read(id, 0, u32::MAX).unwrap()
+                    else {
+                        unreachable!()
+                    };
+                    assert_eq!(
+                        checksums::verify(fd).unwrap(),
+                        Verification::Ok,
+                        "Entry {id} checksum"
+                    );
+                }
+                entry => panic!("Entry {id} is {entry:?} instead of {expected:?}"),
+            }
+            assert!(
+                read(allocator, id, 0, u32::MAX) == expected.data,
+                "Entry {id} has wrong contents"
+            );
+            // Long ranges of direct entries are sent as copies of just that range.
+            let (offset, len) = (expected.data.len() / 3, expected.data.len() / 2);
+            assert!(
+                read(
+                    allocator,
+                    id,
+                    offset.try_into().unwrap(),
+                    len.try_into().unwrap()
+                ) == expected.data[offset..offset + len],
+                "Entry {id} has wrong contents in {offset}..{}",
+                offset + len
+            );
+            assert_eq!(label(allocator, id), expected.label, "Entry {id} label");
+            assert_eq!(
+                allocator.origin(kind, index),
+                expected.origin,
+                "Entry {id} origin"
+            );
+            assert!(
+                allocator.timestamps.get(kind, index).is_some(),
+                "Entry {id} has no timestamp"
+            );
+        }
+
+        let files = fs::read_dir(dir.join("direct"))
+            .unwrap()
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(files, expected_files, "Direct allocations leaked or lost");
+
+        if let Some(packed) = &allocator.data.packed {
+            packed_ranges.sort_unstable_by_key(|range| range.start);
+            assert!(
+                packed_ranges.windows(2).all(|w| w[0].end <= w[1].start),
+                "Packed entries overlap: {packed_ranges:?}"
+            );
+            assert_eq!(
+                packed_ranges
+                    .iter()
+                    .map(|range| range.end - range.start)
+                    .sum::<u64>(),
+                packed.live(),
+                "Packed log miscounted its live bytes"
+            );
+            assert!(
+                packed_ranges
+                    .last()
+                    .is_none_or(|range| range.end <= packed.live() + packed.garbage()),
+                "Packed entries lie past the end of the log"
+            );
+            assert_eq!(
+                usize::try_from(packed.usage().unwrap().entries).unwrap(),
+                packed_ranges.len(),
+                "Packed log miscounted its entries"
+            );
+        }
+
+        let buckets = &allocator.data.buckets;
+        for (bucket, used) in used_slots.iter().enumerate() {
+            let free = &buckets.free_lists.lists.0[bucket];
+            let slots = buckets.slot_counts[bucket];
+            assert!(
+                free.iter().all(|index| !used.contains(index)),
+                "Bucket {bucket} has used slots on its free list"
+            );
+            assert_eq!(
+                free.iter().collect::<BTreeSet<_>>().len(),
+                free.len(),
+                "Bucket {bucket} has duplicate free slots"
+            );
+            assert_eq!(
+                used.len() + free.len(),
+                usize::try_from(slots).unwrap(),
+                "Bucket {bucket} leaked slots"
+            );
+            assert_eq!(
+                buckets.files[bucket].metadata().unwrap().len(),
+                u64::from(slots) * u64::from(bucket_to_length(bucket)),
+                "Bucket {bucket} has the wrong length"
+            );
+        }
+    }
+
+    fn simulate(seed: u64, dir: &Path) {
+        println!("Simulating seed {seed}.");
+        let mut rng = Rng(seed);
+        let settings = Settings {
+            text_storage: TextStorage {
+                pack_small_entries: seed % 2 == 1,
+                ..TextStorage::default()
+            },
+            // Some generated entries are larger than this.
+            entry_limits: EntryLimits {
+                max_entry_size: 10_000,
+                truncate_oversized_entries: false,
+            },
+            // Whether identical adds are coalesced depends on timing, which the model
+            // doesn't follow.
+            coalescing: Coalescing {
+                coalesce_adds: false,
+                ..Coalescing::default()
+            },
+            // The model stores every add.
+            deduplication: Deduplication {
+                dedupe_adds: false,
+                ..Deduplication::default()
+            },
+            ..Settings::default()
+        };
+        let mut allocator = Allocator::open(dir, PreviousShutdown::Clean, &settings).unwrap();
+        assert_eq!(
+            allocator.data.packed.is_some(),
+            settings.text_storage.pack_small_entries
+        );
+        let mut model = Model::default();
+
+        for _ in 0..OPS_PER_SEED {
+            if rng.below(100) == 0 {
+                // Restarts must pick up exactly where the previous server left off.
+                let previous_shutdown = if rng.below(2) == 0 {
+                    allocator.shutdown().unwrap();
+                    PreviousShutdown::Clean
+                } else {
+                    drop(allocator);
+                    PreviousShutdown::Crashed
+                };
+                allocator = Allocator::open(dir, previous_shutdown, &settings).unwrap();
+                model.clipboard = None;
+            } else {
+                step(&mut rng, &mut allocator, &mut model);
+            }
+            check(&allocator, &model, dir);
+        }
+    }
+
+    #[test]
+    fn allocator_matches_model() {
+        let seeds = env::var("RINGBOARD_SIM_SEED").map_or(0..SEEDS, |seed| {
+            let seed = seed.parse().unwrap();
+            seed..seed + 1
+        });
+
+        let root = env::temp_dir().join(format!("ringboard-simulation-{}", process::id()));
+        for seed in seeds {
+            let dir = root.join(seed.to_string());
+            fs::create_dir_all(&dir).unwrap();
+            simulate(seed, &dir);
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    ffi::CStr,
+    fs,
+    fs::{File, OpenOptions},
+    io,
+    io::ErrorKind,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, SyncSender},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info, warn};
+use ringboard_core::{
+    generation,
+    generation::Generation,
+    origins,
+    origins::OriginsWriter,
+    packed,
+    protocol::{decompose_id, BackupOutcome, BackupResponse, BackupStats, RingKind},
+    ring::{entries_to_offset, Entry, RawEntry},
+    sensitive, timestamps,
+    timestamps::TimestampsWriter,
+    Context, IoErr,
+};
+use rustix::fs::{fgetxattr, flistxattr, fsetxattr, XattrFlags};
+
+use crate::{settings::Settings, CliError};
+
+/// Backups are directories named with this prefix followed by the time they
+/// were taken in seconds since the Unix epoch.
+const NAME_PREFIX: &str = "ringboard-";
+/// How many times to start over when the database changes during a backup.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Copies the database into the backup directory on a background thread, both
+/// on a schedule and on demand.
+///
+/// A backup is only kept if the database didn't change while it was being
+/// copied, so it can be restored by copying it back into the data directory
+/// while the server is stopped. Backups are skipped if nothing changed since
+/// the last one, and failures are logged and counted without affecting the
+/// server.
+#[derive(Debug)]
+pub struct Backups {
+    requests: SyncSender<()>,
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    generation: Generation,
+    /// The generation the last backup was taken at, or `u64::MAX` if unknown.
+    backed_up: AtomicU64,
+    in_progress: AtomicBool,
+    last_backup: AtomicU64,
+    failures: AtomicU64,
+    copied_files: AtomicU32,
+    total_files: AtomicU32,
+}
+
+impl Backups {
+    pub fn spawn(
+        &Settings {
+            ref backup_dir,
+            backup_interval,
+            backups_kept,
+            ..
+        }: &Settings,
+    ) -> Result<Option<Self>, CliError> {
+        let Some(dir) = backup_dir.as_deref().map(Path::to_path_buf) else {
+            return Ok(None);
+        };
+
+        let (last_backup, backed_up) = newest_backup(&dir).unwrap_or((0, u64::MAX));
+        let shared = Arc::new(Shared {
+            generation: Generation::open(generation::FILE_NAME)?,
+            backed_up: AtomicU64::new(backed_up),
+            in_progress: AtomicBool::new(false),
+            last_backup: AtomicU64::new(last_backup),
+            failures: AtomicU64::new(0),
+            copied_files: AtomicU32::new(0),
+            total_files: AtomicU32::new(0),
+        });
+        let (requests, receiver) = mpsc::sync_channel(1);
+
+        let interval = Duration::from_secs(backup_interval);
+        let worker = shared.clone();
+        thread::Builder::new()
+            .name("backup".into())
+            .spawn(move || {
+                // The first backup is left for a full interval rather than being taken as the
+                // server starts.
+                let mut next = if last_backup == 0 {
+                    SystemTime::now()
+                } else {
+                    UNIX_EPOCH + Duration::from_secs(last_backup)
+                } + interval;
+                loop {
+                    let wait = next.duration_since(SystemTime::now()).unwrap_or_default();
+                    match receiver.recv_timeout(wait) {
+                        Ok(()) | Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    worker.in_progress.store(true, Ordering::Relaxed);
+                    if let Err(e) = backup(&dir, backups_kept, &worker) {
+                        warn!("Failed to back up database: {e}");
+                        worker.failures.fetch_add(1, Ordering::Relaxed);
+                    }
+                    worker.in_progress.store(false, Ordering::Release);
+                    next = SystemTime::now() + interval;
+                }
+            })
+            .map_io_err(|| "Failed to spawn backup thread.")?;
+        Ok(Some(Self { requests, shared }))
+    }
+
+    /// Starts a backup unless one is already running or there's nothing new to
+    /// back up.
+    pub fn start(&self) -> BackupResponse {
+        let Shared {
+            generation,
+            backed_up,
+            in_progress,
+            last_backup,
+            ..
+        } = &*self.shared;
+        let outcome = if generation.get() == backed_up.load(Ordering::Relaxed) {
+            BackupOutcome::Unchanged
+        } else if in_progress.swap(true, Ordering::Relaxed) {
+            BackupOutcome::InProgress
+        } else {
+            // Only full if a scheduled backup is about to pick this request up anyway.
+            let _ = self.requests.try_send(());
+            BackupOutcome::Started
+        };
+        BackupResponse {
+            outcome,
+            last_backup: last_backup.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn stats(&self) -> BackupStats {
+        let Shared {
+            generation: _,
+            backed_up: _,
+            in_progress,
+            last_backup,
+            failures,
+            copied_files,
+            total_files,
+        } = &*self.shared;
+        BackupStats {
+            last_backup: last_backup.load(Ordering::Relaxed),
+            failures: failures.load(Ordering::Relaxed),
+            copied_files: copied_files.load(Ordering::Relaxed),
+            total_files: total_files.load(Ordering::Relaxed),
+            in_progress: in_progress.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// The time and generation of the newest backup in `dir`.
+fn newest_backup(dir: &Path) -> Option<(u64, u64)> {
+    let (time, path) = list_backups(dir).ok()?.pop()?;
+    let generation = fs::read(path.join(generation::FILE_NAME)).ok()?;
+    let generation = u64::from_ne_bytes(generation.get(..size_of::<u64>())?.try_into().ok()?);
+    Some((time, generation))
+}
+
+/// Finished backups in `dir`, oldest first.
+fn list_backups(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let time = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix(NAME_PREFIX))
+            .and_then(|time| time.parse::<u64>().ok());
+        if let Some(time) = time {
+            backups.push((time, entry.path()));
+        }
+    }
+    backups.sort_unstable();
+    Ok(backups)
+}
+
+fn backup(dir: &Path, kept: u32, shared: &Shared) -> Result<(), CliError> {
+    let mut generation = shared.generation.get();
+    if generation == shared.backed_up.load(Ordering::Relaxed) {
+        info!("Skipping backup: the database hasn't changed since the last one.");
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir).map_io_err(|| Context::File {
+        message: "Failed to create backup directory",
+        path: dir.to_path_buf(),
+    })?;
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let name = format!("{NAME_PREFIX}{time}");
+    let staging = dir.join(format!(".{name}.partial"));
+    let remove_staging = || match fs::remove_dir_all(&staging) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        r => r.map_io_err(|| Context::File {
+            message: "Failed to remove partial backup",
+            path: staging.clone(),
+        }),
+    };
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        remove_staging()?;
+        if let Err(e) = copy_database(&staging, shared) {
+            let _ = remove_staging();
+            return Err(e);
+        }
+
+        let after = shared.generation.get();
+        if after != generation {
+            debug!("Database changed during backup attempt {attempt}, starting over.");
+            generation = after;
+            continue;
+        }
+
+        let backup = dir.join(&name);
+        // Only possible if the previous backup was taken within the same second.
+        let _ = fs::remove_dir_all(&backup);
+        fs::rename(&staging, &backup).map_io_err(|| Context::File {
+            message: "Failed to move finished backup into place",
+            path: backup.clone(),
+        })?;
+        shared.backed_up.store(generation, Ordering::Relaxed);
+        shared.last_backup.store(time, Ordering::Relaxed);
+        info!("Backed up database to {backup:?}.");
+
+        return prune(dir, kept);
+    }
+
+    remove_staging()?;
+    Err(ringboard_core::Error::Io {
+        error: io::Error::new(ErrorKind::ResourceBusy, "Database kept changing."),
+        context: format!("Gave up on backup after {MAX_ATTEMPTS} attempts.").into(),
+    }
+    .into())
+}
+
+/// Copies every file making up the database into `to`, leaving out the free
+/// lists since they're stale while the server is running and get rebuilt
+/// when missing.
+///
+/// Sensitive entries are left out entirely: their data would outlive them in
+/// the backup otherwise.
+fn copy_database(to: &Path, shared: &Shared) -> Result<(), CliError> {
+    let mut files = [
+        RingKind::Main.file_name(),
+        RingKind::Favorites.file_name(),
+        generation::FILE_NAME,
+        origins::FILE_NAME,
+        timestamps::FILE_NAME,
+        packed::INDEX_FILE_NAME,
+        packed::LOG_FILE_NAME,
+    ]
+    .into_iter()
+    .filter(|file| Path::new(file).exists())
+    .map(PathBuf::from)
+    .collect::<Vec<_>>();
+    for dir in ["buckets", "direct"] {
+        fs::create_dir_all(to.join(dir)).map_io_err(|| Context::File {
+            message: "Failed to create backup directory",
+            path: to.join(dir),
+        })?;
+        for entry in fs::read_dir(dir).map_io_err(|| format!("Failed to list {dir:?}."))? {
+            files.push(
+                entry
+                    .map_io_err(|| format!("Failed to list {dir:?}."))?
+                    .path(),
+            );
+        }
+    }
+
+    shared.copied_files.store(0, Ordering::Relaxed);
+    shared.total_files.store(
+        u32::try_from(files.len()).unwrap_or(u32::MAX),
+        Ordering::Relaxed,
+    );
+    let mut sensitive = Vec::new();
+    for file in files {
+        let copied = match copy_file(&file, &to.join(&file)) {
+            // Removed entries show up as a generation change.
+            Err(e) if e.kind() == ErrorKind::NotFound => true,
+            r => r.map_io_err(|| Context::File {
+                message: "Failed to back up file",
+                path: file.clone(),
+            })?,
+        };
+        if !copied {
+            sensitive.extend(
+                file.file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.parse::<u64>().ok()),
+            );
+        }
+        shared.copied_files.fetch_add(1, Ordering::Relaxed);
+    }
+    forget_entries(to, &sensitive)
+}
+
+/// Copies a file along with its extended attributes, which hold entry
+/// metadata like mime types and labels. Sensitive entries aren't copied, which
+/// is reported by returning false.
+fn copy_file(from: &Path, to: &Path) -> io::Result<bool> {
+    let mut source = File::open(from)?;
+    if matches!(sensitive::read(&source), Ok(Some(_))) {
+        return Ok(false);
+    }
+    let mut copy = File::create_new(to)?;
+    io::copy(&mut source, &mut copy)?;
+
+    let mut names = [0; 1024];
+    let len = flistxattr(&source, &mut names)?;
+    let mut value = [0; 4096];
+    for name in names[..len].split_inclusive(|&c| c == 0) {
+        let name = unsafe { CStr::from_ptr(name.as_ptr().cast()) };
+        let len = fgetxattr(&source, name, &mut value)?;
+        fsetxattr(&copy, name, &value[..len], XattrFlags::CREATE)?;
+    }
+    Ok(true)
+}
+
+/// Clears the backed up ring entries whose data was left out, along with
+/// their metadata, so restoring the backup brings back no trace of them.
+fn forget_entries(backup: &Path, ids: &[u64]) -> Result<(), CliError> {
+    let open = |name: &str| {
+        let path = backup.join(name);
+        path.exists().then_some(path)
+    };
+    let mut timestamps = open(timestamps::FILE_NAME)
+        .map(|path| TimestampsWriter::open(&*path))
+        .transpose()?;
+    let mut origins = open(origins::FILE_NAME)
+        .map(|path| OriginsWriter::open(&*path))
+        .transpose()?;
+
+    for &id in ids {
+        let Ok((ring, index)) = decompose_id(id) else {
+            continue;
+        };
+        let ring_file = backup.join(ring.file_name());
+        OpenOptions::new()
+            .write(true)
+            .open(&ring_file)
+            .and_then(|file| {
+                file.write_all_at(
+                    &RawEntry::from(Entry::Uninitialized).to_le_bytes(),
+                    entries_to_offset(index),
+                )
+            })
+            .map_io_err(|| Context::File {
+                message: "Failed to drop sensitive entry from backup",
+                path: ring_file,
+            })?;
+        if let Some(timestamps) = &mut timestamps {
+            timestamps.set(ring, index, None)?;
+        }
+        if let Some(origins) = &mut origins {
+            origins.set(ring, index, None)?;
+        }
+        debug!("Left sensitive entry {id} out of backup.");
+    }
+    Ok(())
+}
+
+/// Deletes all but the newest `kept` backups along with any left behind by
+/// backups that were interrupted.
+fn prune(dir: &Path, kept: u32) -> Result<(), CliError> {
+    let list_err = || Context::File {
+        message: "Failed to list backups",
+        path: dir.to_path_buf(),
+    };
+    for entry in fs::read_dir(dir).map_io_err(list_err)? {
+        let entry = entry.map_io_err(list_err)?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&format!(".{NAME_PREFIX}")) && name.ends_with(".partial") {
+            remove_backup(&entry.path())?;
+        }
+    }
+
+    let backups = list_backups(dir).map_io_err(list_err)?;
+    let stale = backups.len().saturating_sub(usize::try_from(kept).unwrap());
+    for (_, path) in &backups[..stale] {
+        remove_backup(path)?;
+    }
+    Ok(())
+}
+
+fn remove_backup(path: &Path) -> Result<(), CliError> {
+    debug!("Deleting backup {path:?}.");
+    fs::remove_dir_all(path).map_io_err(|| Context::File {
+        message: "Failed to delete old backup",
+        path: path.to_path_buf(),
+    })?;
+    Ok(())
+}
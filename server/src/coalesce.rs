@@ -0,0 +1,219 @@
+use std::time::{Duration, Instant};
+
+use arrayvec::ArrayVec;
+use ringboard_core::{
+    origins::Origin,
+    protocol::{MimeType, RingKind},
+};
+
+use crate::settings::{Coalescing, Settings};
+
+/// How many recent adds are remembered, across all clients.
+const RECENT_ADDS: usize = 8;
+
+/// What an add must match to be coalesced with an earlier one.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Fingerprint {
+    origin: Option<Origin>,
+    to: RingKind,
+    mime_type: MimeType,
+    size: u64,
+    hash: u64,
+}
+
+#[derive(Debug)]
+struct RecentAdd {
+    fingerprint: Fingerprint,
+    id: u64,
+    at: Instant,
+}
+
+/// Answers an add that is identical to one the same client made moments ago
+/// with the earlier entry instead of storing it again.
+///
+/// Some applications, like terminals and Java apps, announce a single copy
+/// several times within a few milliseconds, so clipboard watchers end up
+/// adding the same data two or three times in a row.
+#[derive(Debug)]
+pub struct AddCoalescer {
+    window: Option<Duration>,
+    recent: ArrayVec<RecentAdd, RECENT_ADDS>,
+    coalesced: u64,
+}
+
+impl AddCoalescer {
+    pub fn new(
+        &Settings {
+            coalescing:
+                Coalescing {
+                    coalesce_adds,
+                    coalesce_window,
+                },
+            ..
+        }: &Settings,
+    ) -> Self {
+        Self {
+            window: coalesce_adds.then(|| Duration::from_millis(coalesce_window)),
+            recent: ArrayVec::new(),
+            coalesced: 0,
+        }
+    }
+
+    pub const fn enabled(&self) -> bool {
+        self.window.is_some()
+    }
+
+    /// Applies reloaded settings, forgetting recent adds but keeping stats.
+    pub fn reload(&mut self, settings: &Settings) {
+        *self = Self {
+            coalesced: self.coalesced,
+            ..Self::new(settings)
+        };
+    }
+
+    /// Identifies a received entry from its [`hash_contents`] so it can be
+    /// compared against recent ones. Returns [`None`] if the entry won't be
+    /// coalesced.
+    ///
+    /// [`hash_contents`]: crate::dedupe::hash_contents
+    pub fn fingerprint(
+        &self,
+        hash: Option<u64>,
+        size: u64,
+        to: RingKind,
+        mime_type: &MimeType,
+        origin: Option<Origin>,
+    ) -> Option<Fingerprint> {
+        self.window?;
+        Some(Fingerprint {
+            origin,
+            to,
+            mime_type: *mime_type,
+            size,
+            hash: hash?,
+        })
+    }
+
+    /// The entry an add with this fingerprint should be answered with, if
+    /// the same client added it within the window.
+    ///
+    /// Every coalesced add extends the window, so a burst of any length
+    /// results in a single entry.
+    pub fn coalesce(&mut self, fingerprint: &Fingerprint, now: Instant) -> Option<u64> {
+        let window = self.window?;
+        let recent = self.recent.iter_mut().find(|recent| {
+            recent.fingerprint == *fingerprint && now.saturating_duration_since(recent.at) <= window
+        })?;
+        recent.at = now;
+        self.coalesced += 1;
+        Some(recent.id)
+    }
+
+    /// Remembers a stored entry so identical adds following it are coalesced.
+    pub fn remember(&mut self, fingerprint: Fingerprint, id: u64, now: Instant) {
+        self.forget(id);
+        if self.recent.is_full() {
+            self.recent.remove(0);
+        }
+        self.recent.push(RecentAdd {
+            fingerprint,
+            id,
+            at: now,
+        });
+    }
+
+    /// Stops answering adds with an entry that was moved, removed, or
+    /// overwritten.
+    pub fn forget(&mut self, id: u64) {
+        self.recent.retain(|recent| recent.id != id);
+    }
+
+    /// How many adds were answered with an earlier entry since the server
+    /// started.
+    pub const fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::Write,
+        time::{Duration, Instant},
+    };
+
+    use ringboard_core::{
+        origins::Origin,
+        protocol::{MimeType, RingKind},
+    };
+    use rustix::fs::{memfd_create, MemfdFlags};
+
+    use super::{AddCoalescer, Fingerprint};
+    use crate::{
+        dedupe::hash_contents,
+        settings::{Coalescing, Settings},
+    };
+
+    fn file(data: &[u8]) -> File {
+        let mut file =
+            File::from(memfd_create(c"ringboard-coalesce", MemfdFlags::empty()).unwrap());
+        file.write_all(data).unwrap();
+        file
+    }
+
+    fn fingerprint(data: &[u8], origin: Option<Origin>) -> Fingerprint {
+        let size = u64::try_from(data.len()).unwrap();
+        let hash = hash_contents(&file(data), size).unwrap();
+        AddCoalescer::new(&Settings::default())
+            .fingerprint(hash, size, RingKind::Main, &MimeType::new(), origin)
+            .unwrap()
+    }
+
+    #[test]
+    fn identical_adds_within_the_window_are_coalesced() {
+        let mut coalescer = AddCoalescer::new(&Settings::default());
+        let terminal = Origin::new("terminal", 1000);
+        let start = Instant::now();
+        let window = Duration::from_millis(Coalescing::default().coalesce_window);
+
+        let hello = fingerprint(b"hello", terminal);
+        assert_eq!(coalescer.coalesce(&hello, start), None);
+        coalescer.remember(hello, 1, start);
+        assert_eq!(coalescer.coalesce(&hello, start + window), Some(1));
+        // The burst keeps the window open.
+        assert_eq!(coalescer.coalesce(&hello, start + window * 2), Some(1));
+        assert_eq!(coalescer.coalesce(&hello, start + window * 4), None);
+
+        let later = start + window * 4;
+        coalescer.remember(hello, 2, later);
+        assert_eq!(
+            coalescer.coalesce(&fingerprint(b"hellO", terminal), later),
+            None
+        );
+        let browser = Origin::new("browser", 1000);
+        assert_eq!(
+            coalescer.coalesce(&fingerprint(b"hello", browser), later),
+            None
+        );
+        coalescer.forget(2);
+        assert_eq!(coalescer.coalesce(&hello, later), None);
+        assert_eq!(coalescer.coalesced(), 2);
+    }
+
+    #[test]
+    fn disabled_coalescing_fingerprints_nothing() {
+        let coalescer = AddCoalescer::new(&Settings {
+            coalescing: Coalescing {
+                coalesce_adds: false,
+                ..Coalescing::default()
+            },
+            ..Settings::default()
+        });
+        assert!(
+            coalescer
+                .fingerprint(Some(0xa), 5, RingKind::Main, &MimeType::new(), None)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    hash::{DefaultHasher, Hasher},
+    io,
+    os::unix::fs::FileExt,
+};
+
+use ringboard_core::protocol::{decompose_id, MimeType, RingKind};
+
+use crate::settings::{Deduplication, Settings};
+
+/// Larger entries are never hashed: doing so would slow down every add and
+/// applications rarely copy them more than once.
+const MAX_HASHED_SIZE: u64 = 16 << 20;
+
+/// Hashes the first `size` bytes of a received entry. Returns [`None`] if the
+/// entry is too large to be worth comparing against others.
+pub fn hash_contents(data: &File, size: u64) -> io::Result<Option<u64>> {
+    if size > MAX_HASHED_SIZE {
+        return Ok(None);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0; 8192];
+    let mut offset = 0;
+    while offset < size {
+        let len = usize::try_from(size - offset).map_or(buf.len(), |left| left.min(buf.len()));
+        let chunk = &mut buf[..len];
+        data.read_exact_at(chunk, offset)?;
+        hasher.write(chunk);
+        offset += u64::try_from(len).unwrap();
+    }
+    Ok(Some(hasher.finish()))
+}
+
+/// What two entries must share to possibly be identical.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+struct Contents {
+    ring: RingKind,
+    mime_type: MimeType,
+    size: u64,
+    hash: u64,
+}
+
+/// Remembers the contents of recently added entries so an add repeating one of
+/// them can move that entry to the front instead of storing a copy.
+///
+/// Only entries added since the server started are remembered, and only
+/// against entries in the ring being added to, so favorites are never pulled
+/// into the main ring.
+#[derive(Debug)]
+pub struct RecentEntries {
+    capacity: usize,
+    by_contents: HashMap<Contents, u64>,
+    /// Oldest first, so the oldest entries are forgotten once full.
+    order: VecDeque<(u64, Contents)>,
+    deduplicated: u64,
+}
+
+impl RecentEntries {
+    pub fn new(
+        &Settings {
+            deduplication:
+                Deduplication {
+                    dedupe_adds,
+                    dedupe_recent_entries,
+                },
+            ..
+        }: &Settings,
+    ) -> Self {
+        let capacity = if dedupe_adds {
+            usize::try_from(dedupe_recent_entries).unwrap()
+        } else {
+            0
+        };
+        Self {
+            capacity,
+            by_contents: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            deduplicated: 0,
+        }
+    }
+
+    pub const fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Applies reloaded settings, forgetting recent entries but keeping stats.
+    pub fn reload(&mut self, settings: &Settings) {
+        *self = Self {
+            deduplicated: self.deduplicated,
+            ..Self::new(settings)
+        };
+    }
+
+    /// A recent entry in `ring` whose contents hash the same as the new one.
+    /// The caller must compare their contents since hashes may collide.
+    pub fn find(&self, ring: RingKind, mime_type: &MimeType, size: u64, hash: u64) -> Option<u64> {
+        self.by_contents
+            .get(&Contents {
+                ring,
+                mime_type: *mime_type,
+                size,
+                hash,
+            })
+            .copied()
+    }
+
+    /// Remembers an entry that was just stored or moved to the front.
+    pub fn remember(&mut self, id: u64, mime_type: &MimeType, size: u64, hash: u64) {
+        if !self.enabled() {
+            return;
+        }
+        let Ok((ring, _)) = decompose_id(id) else {
+            return;
+        };
+        self.forget(id);
+
+        let contents = Contents {
+            ring,
+            mime_type: *mime_type,
+            size,
+            hash,
+        };
+        if let Some(previous) = self.by_contents.insert(contents, id) {
+            // A colliding entry with different contents was stored: only the
+            // newest one is remembered.
+            self.order.retain(|&(id, _)| id != previous);
+        }
+        if self.order.len() == self.capacity {
+            let (_, oldest) = self.order.pop_front().unwrap();
+            self.by_contents.remove(&oldest);
+        }
+        self.order.push_back((id, contents));
+    }
+
+    /// Stops matching new entries against one that was moved, removed, or
+    /// overwritten.
+    pub fn forget(&mut self, id: u64) {
+        let Some(index) = self.order.iter().position(|&(recent, _)| recent == id) else {
+            return;
+        };
+        let (_, contents) = self.order.remove(index).unwrap();
+        self.by_contents.remove(&contents);
+    }
+
+    pub const fn record_deduplicated(&mut self) {
+        self.deduplicated += 1;
+    }
+
+    /// How many adds moved an identical entry to the front since the server
+    /// started.
+    pub const fn deduplicated(&self) -> u64 {
+        self.deduplicated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ringboard_core::protocol::{composite_id, MimeType, RingKind};
+
+    use super::RecentEntries;
+    use crate::settings::{Deduplication, Settings};
+
+    #[test]
+    fn only_the_most_recent_entries_are_found() {
+        let mut recent = RecentEntries::new(&Settings {
+            deduplication: Deduplication {
+                dedupe_recent_entries: 2,
+                ..Deduplication::default()
+            },
+            ..Settings::default()
+        });
+        let text = MimeType::new();
+        let main = |index| composite_id(RingKind::Main, index);
+
+        recent.remember(main(0), &text, 5, 0xa);
+        recent.remember(main(1), &text, 5, 0xb);
+        assert_eq!(recent.find(RingKind::Main, &text, 5, 0xa), Some(main(0)));
+        assert_eq!(recent.find(RingKind::Favorites, &text, 5, 0xa), None);
+        assert_eq!(recent.find(RingKind::Main, &text, 6, 0xa), None);
+
+        recent.remember(main(2), &text, 5, 0xc);
+        assert_eq!(recent.find(RingKind::Main, &text, 5, 0xa), None);
+        assert_eq!(recent.find(RingKind::Main, &text, 5, 0xb), Some(main(1)));
+
+        // Moving an entry forgets its old id.
+        recent.forget(main(1));
+        recent.remember(main(3), &text, 5, 0xb);
+        assert_eq!(recent.find(RingKind::Main, &text, 5, 0xb), Some(main(3)));
+        assert_eq!(recent.find(RingKind::Main, &text, 5, 0xc), Some(main(2)));
+        recent.forget(main(2));
+        assert_eq!(recent.find(RingKind::Main, &text, 5, 0xc), None);
+    }
+
+    #[test]
+    fn disabled_deduplication_remembers_nothing() {
+        let mut recent = RecentEntries::new(&Settings {
+            deduplication: Deduplication {
+                dedupe_adds: false,
+                ..Deduplication::default()
+            },
+            ..Settings::default()
+        });
+        recent.remember(composite_id(RingKind::Main, 0), &MimeType::new(), 5, 0xa);
+        assert_eq!(recent.find(RingKind::Main, &MimeType::new(), 5, 0xa), None);
+    }
+}
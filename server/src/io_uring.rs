@@ -262,6 +262,11 @@ pub mod buf_ring {
             }
         }
 
+        /// The bytes of memory backing the ring and its buffers.
+        pub const fn size(&self) -> usize {
+            self.ring.len
+        }
+
         pub fn unregister(self, submitter: &Submitter) -> io::Result<()> {
             submitter.unregister_buf_ring(self.group_id)
         }
@@ -343,6 +348,10 @@ pub mod buf_ring {
     }
 
     impl Buf<'_, '_> {
+        pub const fn index(&self) -> u16 {
+            self.index
+        }
+
         pub fn into_index(self) -> u16 {
             let me = ManuallyDrop::new(self);
             me.index
@@ -0,0 +1,163 @@
+//! Where the server's logs go and what they look like.
+//!
+//! Logs are written to stderr unless a file is given. `RUST_LOG` picks the
+//! level as usual, but `-v` flags take precedence over its default level.
+//! Nothing here can stop the server from starting: a log file that can't be
+//! opened is reported on stderr, which is used instead.
+
+use std::{
+    fs::OpenOptions,
+    io,
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use env_logger::{fmt::Formatter, Builder, Env, Target};
+use log::{warn, Level, LevelFilter, Record};
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Options {
+    /// How many times `-v` was passed: once for info, twice for debug.
+    pub verbosity: u8,
+    pub file: Option<PathBuf>,
+    pub format: Format,
+}
+
+pub fn init(options: &Options) {
+    let default_level = if cfg!(debug_assertions) {
+        "info"
+    } else {
+        "error"
+    };
+    let mut builder = Builder::from_env(Env::default().default_filter_or(default_level));
+    match options.verbosity {
+        0 => {}
+        1 => {
+            builder.filter_level(LevelFilter::Info);
+        }
+        _ => {
+            builder.filter_level(LevelFilter::Debug);
+        }
+    }
+
+    let mut to_file = false;
+    let mut open_error = None;
+    if let Some(path) = &options.file {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => {
+                builder.target(Target::Pipe(Box::new(file)));
+                to_file = true;
+            }
+            Err(e) => open_error = Some((path, e)),
+        }
+    }
+
+    match options.format {
+        Format::Json => {
+            builder.format(write_json);
+        }
+        // The journal timestamps each line and understands syslog priorities.
+        Format::Text if !to_file && stderr_is_journal() => {
+            builder.format(|buf, record| {
+                writeln!(
+                    buf,
+                    "<{}>{}: {}",
+                    syslog_priority(record.level()),
+                    record.target(),
+                    record.args()
+                )
+            });
+        }
+        Format::Text => {}
+    }
+
+    // Only fails if a logger is already installed, which would log for us.
+    let _ = builder.try_init();
+    if let Some((path, e)) = open_error {
+        warn!("Logging to stderr, failed to open log file {path:?}: {e}");
+    }
+}
+
+fn write_json(buf: &mut Formatter, record: &Record<'_>) -> io::Result<()> {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    write!(
+        buf,
+        "{{\"ts\":{millis},\"level\":\"{}\",\"target\":",
+        record.level()
+    )?;
+    write_json_string(buf, record.target())?;
+    write!(buf, ",\"msg\":")?;
+    write_json_string(buf, &record.args().to_string())?;
+    writeln!(buf, "}}")
+}
+
+fn write_json_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", u32::from(c))?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    write!(out, "\"")
+}
+
+const fn syslog_priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Whether systemd connected stderr to the journal, as described in
+/// `systemd.exec(5)` under `$JOURNAL_STREAM`.
+#[cfg(feature = "systemd")]
+fn stderr_is_journal() -> bool {
+    let Some(stream) = std::env::var_os("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Some((dev, ino)) = stream.to_str().and_then(|s| s.split_once(':')) else {
+        return false;
+    };
+    rustix::fs::fstat(io::stderr())
+        .is_ok_and(|stat| dev.parse() == Ok(stat.st_dev) && ino.parse() == Ok(stat.st_ino))
+}
+
+#[cfg(not(feature = "systemd"))]
+const fn stderr_is_journal() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_json_string;
+
+    #[test]
+    fn json_strings_are_escaped() {
+        let mut out = Vec::new();
+        write_json_string(&mut out, "say \"hi\"\\\n\t\u{1}é").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#""say \"hi\"\\\n\t\u0001é""#
+        );
+    }
+}
@@ -1,22 +1,47 @@
 #![feature(write_all_vectored)]
 #![feature(vec_into_raw_parts)]
 
-use std::{borrow::Cow, collections::VecDeque, fs, path::PathBuf};
+use std::{
+    borrow::Cow, collections::VecDeque, env, ffi::OsString, fs::DirBuilder,
+    os::unix::fs::DirBuilderExt, path::PathBuf,
+};
 
 use error_stack::Report;
-use log::info;
-use ringboard_core::{dirs::data_dir, Error, IoErr};
-use rustix::process::{chdir, Pid};
+use log::{info, warn};
+use ringboard_core::{
+    dirs::data_dir, protocol::RingKind, settings::RingCapacities, Context, Error, IoErr,
+};
+use rustix::process::{chdir, getuid, Pid};
 use thiserror::Error;
 
-use crate::{allocator::Allocator, startup::claim_server_ownership};
+use crate::{
+    allocator::Allocator,
+    backup::Backups,
+    settings::Settings,
+    startup::{
+        check_data_dir, claim_server_ownership, clear_recovery_request, DataDirExposure,
+        PreviousShutdown,
+    },
+};
 
 mod allocator;
+mod backup;
+mod coalesce;
+mod dedupe;
 mod io_uring;
+mod logging;
+mod normalize;
+#[cfg(feature = "ocr")]
+mod ocr;
+mod packed;
+mod rate_limit;
 mod reactor;
 mod requests;
+mod search;
 mod send_msg_bufs;
+mod settings;
 mod startup;
+mod trash;
 
 #[cfg(feature = "trace")]
 #[global_allocator]
@@ -29,6 +54,17 @@ enum CliError {
     Core(#[from] Error),
     #[error("server already running at {pid:?}")]
     ServerAlreadyRunning { pid: Pid, lock_file: PathBuf },
+    #[error("ring capacity is too small")]
+    RingTooSmall {
+        ring: RingKind,
+        entries: u32,
+        capacity: u32,
+    },
+    #[error("data directory is accessible by other users")]
+    SharedDataDir {
+        path: PathBuf,
+        exposure: DataDirExposure,
+    },
     #[error("multiple errors occurred")]
     Multiple(Vec<CliError>),
     #[error("internal error")]
@@ -45,13 +81,68 @@ fn main() -> error_stack::Result<(), Wrapper> {
     #[cfg(not(debug_assertions))]
     error_stack::Report::install_debug_hook::<std::panic::Location>(|_, _| {});
 
-    if cfg!(debug_assertions) {
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-    } else {
-        env_logger::init();
+    let (args, problems) = parse_args(env::args_os().skip(1));
+    logging::init(&args.log);
+    for problem in problems {
+        warn!("{problem}");
     }
 
-    run().map_err(into_report)
+    run(&args).map_err(into_report)
+}
+
+/// The server's command line flags.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Args {
+    log: logging::Options,
+    /// Runs the crash recovery pass on demand and exits without serving.
+    fsck: bool,
+    allow_shared_data_dir: bool,
+}
+
+/// Reads `-v`, `--log-file`, `--log-format`, `--fsck` and
+/// `--allow-shared-data-dir`, ignoring every other flag. Bad values are
+/// returned as problems to be logged instead of failing.
+fn parse_args(args: impl IntoIterator<Item = OsString>) -> (Args, Vec<String>) {
+    let mut parsed = Args::default();
+    let options = &mut parsed.log;
+    let mut problems = Vec::new();
+    let mut parse_format = |format: Option<&str>| match format {
+        Some("text") => Some(logging::Format::Text),
+        Some("json") => Some(logging::Format::Json),
+        format => {
+            problems.push(format!(
+                "Using text logs, unknown log format {format:?}: expected json or text."
+            ));
+            None
+        }
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("-v" | "--verbose") => options.verbosity += 1,
+            Some("-vv") => options.verbosity += 2,
+            Some("--log-file") => options.file = args.next().map(PathBuf::from),
+            Some("--log-format") => {
+                let format = args.next();
+                if let Some(format) = parse_format(format.as_ref().and_then(|f| f.to_str())) {
+                    options.format = format;
+                }
+            }
+            Some(arg) if arg.starts_with("--log-file=") => {
+                options.file = Some(PathBuf::from(&arg["--log-file=".len()..]));
+            }
+            Some(arg) if arg.starts_with("--log-format=") => {
+                if let Some(format) = parse_format(Some(&arg["--log-format=".len()..])) {
+                    options.format = format;
+                }
+            }
+            Some("--fsck") => parsed.fsck = true,
+            Some("--allow-shared-data-dir") => parsed.allow_shared_data_dir = true,
+            _ => {}
+        }
+    }
+    (parsed, problems)
 }
 
 fn into_report(cli_err: CliError) -> Report<Wrapper> {
@@ -61,10 +152,37 @@ fn into_report(cli_err: CliError) -> Report<Wrapper> {
         CliError::ServerAlreadyRunning { pid: _, lock_file } => Report::new(wrapper)
             .attach_printable(
                 "Unable to safely start server: please shut down the existing instance. If \
-                 something has gone terribly wrong, please create an empty server lock file to \
-                 initiate the recovery sequence on the next startup.",
+                 something has gone terribly wrong, create an empty `recovery-requested` file \
+                 next to the lock file to run the recovery sequence on the next startup.",
             )
             .attach_printable(format!("Lock file: {lock_file:?}")),
+        CliError::RingTooSmall {
+            ring,
+            entries,
+            capacity,
+        } => Report::new(wrapper).attach_printable(format!(
+            "The {ring:?} ring holds {entries} entries but its capacity is set to {capacity}. \
+             Shrinking a ring would lose entries, so set `{}` to at least {entries} in the \
+             settings file.",
+            RingCapacities::key(ring)
+        )),
+        CliError::SharedDataDir { path, exposure } => Report::new(wrapper)
+            .attach_printable(match exposure {
+                DataDirExposure::ForeignOwner(owner) => format!(
+                    "The data directory belongs to user {} rather than {}, so it can't be trusted.",
+                    owner,
+                    getuid().as_raw()
+                ),
+                DataDirExposure::Shared(mode) => format!(
+                    "The data directory grants other users permissions {mode:03o}, exposing your \
+                     clipboard history. Restrict it with `chmod 700`."
+                ),
+            })
+            .attach_printable(
+                "Pass --allow-shared-data-dir to serve the database anyway, e.g. if access is \
+                 controlled some other way.",
+            )
+            .attach_printable(format!("Data directory: {path:?}")),
         CliError::Multiple(errs) => {
             let mut errs = VecDeque::from(errs);
             let mut report = into_report(errs.pop_front().unwrap_or(CliError::Internal {
@@ -89,25 +207,45 @@ fn into_result(errs: Vec<CliError>) -> Result<(), CliError> {
     }
 }
 
-fn run() -> Result<(), CliError> {
+fn run(args: &Args) -> Result<(), CliError> {
     info!("Starting Ringboard server v{}.", env!("CARGO_PKG_VERSION"));
 
-    {
-        let data_dir = data_dir();
-        info!("Using database in {data_dir:?}.");
+    let data_dir = data_dir();
+    info!("Using database in {data_dir:?}.");
 
-        fs::create_dir_all(&data_dir)
-            .map_io_err(|| format!("Failed to create data directory: {data_dir:?}"))?;
-        chdir(&data_dir)
-            .map_io_err(|| format!("Failed to change working directory: {data_dir:?}"))?;
-    }
-    let server_guard = claim_server_ownership()?;
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&data_dir)
+        .map_io_err(|| Context::File {
+            message: "Failed to create data directory",
+            path: data_dir.clone(),
+        })?;
+    chdir(&data_dir).map_io_err(|| Context::File {
+        message: "Failed to change working directory",
+        path: data_dir.clone(),
+    })?;
+    check_data_dir(&data_dir, args.allow_shared_data_dir)?;
+
+    let (server_guard, previous_shutdown) = claim_server_ownership()?;
     info!("Acquired server lock.");
 
-    let mut allocator = Allocator::open()?;
+    let settings = Settings::load()?;
+    let previous_shutdown = if args.fsck {
+        PreviousShutdown::RecoveryRequested
+    } else {
+        previous_shutdown
+    };
+    let mut allocator = Allocator::open(&data_dir, previous_shutdown, &settings)?;
+    clear_recovery_request()?;
     into_result(
         [
-            reactor::run(&mut allocator),
+            if args.fsck {
+                Ok(())
+            } else {
+                Backups::spawn(&settings)
+                    .and_then(|backups| reactor::run(&mut allocator, &settings, backups.as_ref()))
+            },
             allocator.shutdown(),
             server_guard.shutdown(),
         ]
@@ -116,3 +254,52 @@ fn run() -> Result<(), CliError> {
         .collect::<Vec<_>>(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsString, path::PathBuf};
+
+    use super::{parse_args, Args};
+    use crate::logging::{Format, Options};
+
+    fn parse(args: &[&str]) -> (Args, Vec<String>) {
+        parse_args(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn flags_are_parsed_together() {
+        let (args, problems) = parse(&[
+            "--fsck",
+            "-vv",
+            "--log-file",
+            "/tmp/server.log",
+            "--allow-shared-data-dir",
+            "--log-format=json",
+            "--unknown",
+        ]);
+        assert_eq!(
+            args,
+            Args {
+                log: Options {
+                    verbosity: 2,
+                    file: Some(PathBuf::from("/tmp/server.log")),
+                    format: Format::Json,
+                },
+                fsck: true,
+                allow_shared_data_dir: true,
+            }
+        );
+        assert!(problems.is_empty());
+        assert_eq!(parse(&["-v", "--verbose"]).0.log.verbosity, 2);
+        assert_eq!(parse(&[]).0, Args::default());
+    }
+
+    #[test]
+    fn bad_log_formats_fall_back_to_text() {
+        for args in [&["--log-format", "xml"][..], &["--log-format"]] {
+            let (args, problems) = parse(args);
+            assert_eq!(args.log.format, Format::Text);
+            assert_eq!(problems.len(), 1, "{problems:?}");
+        }
+    }
+}
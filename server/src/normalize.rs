@@ -0,0 +1,240 @@
+use std::{borrow::Cow, fs::File, io, os::unix::fs::FileExt};
+
+use crate::settings::{Normalization, Settings};
+
+/// Larger text is stored as is: it's rarely a URL and reading it into memory
+/// would slow down adds.
+const MAX_NORMALIZED_SIZE: u64 = 4096;
+
+/// A cleanup applied to text entries before they're stored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Rule {
+    /// Trims text that fits on one line and collapses runs of whitespace
+    /// inside it into single spaces.
+    TrimSingleLine,
+    /// Removes query parameters matching one of the patterns from URLs. A
+    /// trailing `*` in a pattern matches any suffix, e.g. `utm_*`.
+    StripQueryParams(Box<[Box<str>]>),
+}
+
+impl Rule {
+    fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::TrimSingleLine => trim_single_line(text),
+            Self::StripQueryParams(patterns) => strip_query_params(text, patterns),
+        }
+    }
+}
+
+/// Cleans up text entries as they're added, as configured in the server
+/// settings. The original text is not kept.
+#[derive(Debug, Default)]
+pub struct Normalizer {
+    rules: Vec<Rule>,
+}
+
+impl Normalizer {
+    pub fn new(
+        &Settings {
+            normalization:
+                Normalization {
+                    trim_single_line_text,
+                    ref strip_query_params,
+                },
+            ..
+        }: &Settings,
+    ) -> Self {
+        let mut rules = Vec::new();
+        // Trim first so URLs with stray whitespace are still recognized.
+        if trim_single_line_text {
+            rules.push(Rule::TrimSingleLine);
+        }
+        if !strip_query_params.is_empty() {
+            rules.push(Rule::StripQueryParams(strip_query_params.clone()));
+        }
+        Self { rules }
+    }
+
+    /// Returns the cleaned up text if any rule changed it.
+    fn normalize(&self, text: &str) -> Option<String> {
+        let mut normalized = Cow::Borrowed(text);
+        for rule in &self.rules {
+            if let Cow::Owned(changed) = rule.apply(&normalized) {
+                normalized = Cow::Owned(changed);
+            }
+        }
+        match normalized {
+            Cow::Owned(normalized) if !normalized.is_empty() => Some(normalized),
+            _ => None,
+        }
+    }
+
+    /// Normalizes the text entry received in `file` in place, returning its
+    /// new size if it changed.
+    pub fn normalize_file(&self, file: &File, size: u64) -> io::Result<Option<u64>> {
+        if self.rules.is_empty() || size >= MAX_NORMALIZED_SIZE {
+            return Ok(None);
+        }
+
+        let mut data = vec![0; usize::try_from(size).unwrap()];
+        file.read_exact_at(&mut data, 0)?;
+        let Some(normalized) = String::from_utf8(data)
+            .ok()
+            .and_then(|text| self.normalize(&text))
+        else {
+            return Ok(None);
+        };
+
+        file.write_all_at(normalized.as_bytes(), 0)?;
+        let size = u64::try_from(normalized.len()).unwrap();
+        file.set_len(size)?;
+        Ok(Some(size))
+    }
+}
+
+fn trim_single_line(text: &str) -> Cow<'_, str> {
+    let trimmed = text.trim();
+    if trimmed.contains(['\n', '\r']) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut words = trimmed.split_whitespace();
+    let mut collapsed = String::with_capacity(trimmed.len());
+    if let Some(first) = words.next() {
+        collapsed.push_str(first);
+    }
+    for word in words {
+        collapsed.push(' ');
+        collapsed.push_str(word);
+    }
+
+    if collapsed == text {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(collapsed)
+    }
+}
+
+fn strip_query_params<'a>(text: &'a str, patterns: &[Box<str>]) -> Cow<'a, str> {
+    // Only text that is nothing but a URL is touched.
+    let url = text.trim();
+    let is_url = ["http://", "https://"].iter().any(|scheme| {
+        url.get(..scheme.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    });
+    if !is_url || url.contains(char::is_whitespace) {
+        return Cow::Borrowed(text);
+    }
+
+    let (url, fragment) = url.find('#').map_or((url, ""), |i| url.split_at(i));
+    let Some((base, query)) = url.split_once('?') else {
+        return Cow::Borrowed(text);
+    };
+    let is_tracking = |param: &str| {
+        let name = param.split_once('=').map_or(param, |(name, _)| name);
+        patterns.iter().any(|pattern| {
+            pattern
+                .strip_suffix('*')
+                .map_or_else(|| name == &**pattern, |prefix| name.starts_with(prefix))
+        })
+    };
+    if !query.split('&').any(is_tracking) {
+        return Cow::Borrowed(text);
+    }
+
+    let start = text.len() - text.trim_start().len();
+    let end = text.trim_end().len();
+    let mut stripped = String::with_capacity(text.len());
+    stripped.push_str(&text[..start]);
+    stripped.push_str(base);
+    for (i, param) in query
+        .split('&')
+        .filter(|&param| !is_tracking(param))
+        .enumerate()
+    {
+        stripped.push(if i == 0 { '?' } else { '&' });
+        stripped.push_str(param);
+    }
+    stripped.push_str(fragment);
+    stripped.push_str(&text[end..]);
+    Cow::Owned(stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Normalizer;
+    use crate::settings::{Normalization, Settings};
+
+    fn normalizer(trim: bool) -> Normalizer {
+        Normalizer::new(&Settings {
+            normalization: Normalization {
+                trim_single_line_text: trim,
+                strip_query_params: ["utm_*", "fbclid", "si"].map(Box::from).into(),
+            },
+            ..Settings::default()
+        })
+    }
+
+    #[test]
+    fn tracking_params_are_stripped_from_urls() {
+        let cases = [
+            ("https://a.com/?utm_source=x", "https://a.com/"),
+            (
+                "https://a.com/p?id=1&utm_source=x&utm_medium=y&b=2",
+                "https://a.com/p?id=1&b=2",
+            ),
+            ("https://a.com/p?fbclid=abc&id=1", "https://a.com/p?id=1"),
+            (
+                "https://a.com/p?utm_campaign=z#section?utm_source=kept",
+                "https://a.com/p#section?utm_source=kept",
+            ),
+            ("HTTP://a.com/?si=1&si=2", "HTTP://a.com/"),
+            ("https://a.com/?fbclid", "https://a.com/"),
+            ("  https://a.com/?si=1\n", "  https://a.com/\n"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(
+                normalizer(false).normalize(text).as_deref(),
+                Some(expected),
+                "{text:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn other_text_is_untouched() {
+        let cases = [
+            "https://a.com/",
+            "https://a.com/p?id=1&sig=2",
+            "https://a.com/p#utm_source=x",
+            "https://a.com/?fbclid_not=1",
+            "ftp://a.com/?utm_source=x",
+            "see https://a.com/?utm_source=x",
+            "utm_source=x",
+            "",
+            "  ",
+        ];
+        for text in cases {
+            assert_eq!(normalizer(false).normalize(text), None, "{text:?}");
+            assert_eq!(Normalizer::default().normalize(text), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn single_lines_are_trimmed() {
+        let cases = [
+            ("  hello \t world\n", Some("hello world")),
+            (" https://a.com/?utm_source=x \n", Some("https://a.com/")),
+            ("hello world", None),
+            ("hello\nworld ", None),
+            (" \n ", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(
+                normalizer(true).normalize(text).as_deref(),
+                expected,
+                "{text:?}"
+            );
+        }
+    }
+}
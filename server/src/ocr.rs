@@ -0,0 +1,90 @@
+use std::{
+    fs::File,
+    io,
+    os::fd::OwnedFd,
+    process::{Command, Stdio},
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread,
+};
+
+use log::{debug, warn};
+use ringboard_core::IoErr;
+use rustix::fs::{fsetxattr, XattrFlags};
+
+use crate::CliError;
+
+/// How many images may wait for recognition before new ones are skipped.
+const QUEUE_LEN: usize = 16;
+/// Recognized text is stored in an extended attribute which most file systems
+/// limit to a single block.
+const MAX_TEXT_LEN: usize = 4000;
+
+/// Recognizes text in image entries on a background thread so they can be
+/// found by text search.
+///
+/// Recognition shells out to `tesseract` and stores the result in the
+/// `user.image_text` attribute of the entry's direct allocation file. Images
+/// are never waited on: if the queue is full or recognition fails, the image
+/// simply isn't text searchable.
+#[derive(Debug)]
+pub struct Ocr {
+    images: SyncSender<OwnedFd>,
+}
+
+impl Ocr {
+    pub fn spawn() -> Result<Self, CliError> {
+        let (images, receiver) = mpsc::sync_channel::<OwnedFd>(QUEUE_LEN);
+        thread::Builder::new()
+            .name("ocr".into())
+            .spawn(move || {
+                for image in receiver {
+                    if let Err(e) = recognize(&image) {
+                        warn!("Failed to recognize text in image: {e}");
+                    }
+                }
+            })
+            .map_io_err(|| "Failed to spawn OCR thread.")?;
+        Ok(Self { images })
+    }
+
+    pub fn submit(&self, image: OwnedFd) {
+        match self.images.try_send(image) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+            Err(TrySendError::Full(_)) => debug!("Skipping text recognition: queue is full."),
+        }
+    }
+}
+
+fn recognize(image: &OwnedFd) -> io::Result<()> {
+    let output = Command::new("tesseract")
+        .args(["stdin", "stdout"])
+        .stdin(File::from(image.try_clone()?))
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "tesseract failed with {}",
+            output.status
+        )));
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let text = text.trim();
+    let mut len = text.len().min(MAX_TEXT_LEN);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    let text = &text[..len];
+    debug!("Recognized {len} bytes of text in image.");
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    fsetxattr(
+        image,
+        c"user.image_text",
+        text.as_bytes(),
+        XattrFlags::empty(),
+    )?;
+    Ok(())
+}
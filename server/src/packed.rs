@@ -0,0 +1,277 @@
+use std::{fs::File, io, io::ErrorKind, os::unix::fs::FileExt, path::Path};
+
+use log::{debug, info, warn};
+use ringboard_core::{
+    packed,
+    packed::PackedIndexWriter,
+    protocol::{PackedUsage, RingKind},
+    ring::{Entry, InitializedEntry},
+    Context, IoErr,
+};
+use rustix::{
+    fs::{fallocate, openat, statx, AtFlags, FallocateFlags, Mode, OFlags, StatxFlags, CWD},
+    io::Errno,
+};
+
+use crate::CliError;
+
+/// The server's side of the packed log, see [`ringboard_core::packed`].
+///
+/// Entries are appended to the end of the log and only move when it's
+/// compacted. The log never shrinks, so readers' mappings of it stay valid:
+/// compaction punches a hole over the space it frees instead.
+#[derive(Debug)]
+pub struct PackedLog {
+    index: PackedIndexWriter,
+    log: File,
+    /// Where the next entry is appended.
+    end: u64,
+    /// The combined length of the entries, with the rest of the log up to
+    /// `end` being garbage left behind by removed entries.
+    live: u64,
+    entries: u32,
+}
+
+pub fn missing_record(ring: RingKind, index: u32) -> CliError {
+    ringboard_core::Error::Io {
+        error: io::Error::new(ErrorKind::InvalidData, "Packed entry has no record."),
+        context: format!("Packed entry {index} in {ring:?} ring is missing from the index.").into(),
+    }
+    .into()
+}
+
+impl PackedLog {
+    /// Opens the log, whose end isn't known until [`PackedLog::recount`] is
+    /// called with the entries in the rings.
+    pub fn open(data_dir: &Path) -> Result<Self, CliError> {
+        let index = PackedIndexWriter::open(&*data_dir.join(packed::INDEX_FILE_NAME))?;
+        let path = data_dir.join(packed::LOG_FILE_NAME);
+        let log = openat(
+            CWD,
+            &*path,
+            OFlags::RDWR | OFlags::CREATE,
+            Mode::RUSR | Mode::WUSR,
+        )
+        .map_io_err(|| Context::File {
+            message: "Failed to open packed log",
+            path,
+        })?;
+        Ok(Self {
+            index,
+            log: File::from(log),
+            end: 0,
+            live: 0,
+            entries: 0,
+        })
+    }
+
+    /// Whether the ring slot's record holds the entry within the log, which
+    /// is all that can be checked without knowing what the entry was.
+    pub fn holds(&self, ring: RingKind, index: u32, entry: InitializedEntry) -> bool {
+        let Some((start, len)) = self.index.get(ring, index) else {
+            return false;
+        };
+        len == entry.size()
+            && self
+                .log
+                .metadata()
+                .is_ok_and(|metadata| u64::from(start) + u64::from(len) <= metadata.len())
+    }
+
+    /// Works out where the log ends and how much of it is live from the
+    /// entries in the rings, treating whatever else is in it as garbage.
+    pub fn recount(&mut self, entries: impl IntoIterator<Item = (RingKind, u32)>) {
+        (self.end, self.live, self.entries) = (0, 0, 0);
+        for (ring, index) in entries {
+            let Some((start, len)) = self.index.get(ring, index) else {
+                continue;
+            };
+            self.end = self.end.max(u64::from(start) + u64::from(len));
+            self.live += u64::from(len);
+            self.entries += 1;
+        }
+        debug!(
+            "Packed log holds {} entries in {} of its {} bytes.",
+            self.entries, self.live, self.end
+        );
+    }
+
+    pub const fn live(&self) -> u64 {
+        self.live
+    }
+
+    pub const fn garbage(&self) -> u64 {
+        self.end - self.live
+    }
+
+    /// Appends the first `size` bytes of `data` to the log as the entry of the
+    /// given ring slot.
+    pub fn append(
+        &mut self,
+        data: &File,
+        size: u16,
+        ring: RingKind,
+        index: u32,
+    ) -> Result<Entry, CliError> {
+        debug!("Appending {size} byte entry to packed log at {}.", self.end);
+        let mut buf = [0; packed::MAX_LEN as usize];
+        let buf = &mut buf[..usize::from(size)];
+        data.read_exact_at(buf, 0)
+            .map_io_err(|| "Failed to read new entry.")?;
+        let start = self.write_at_end(buf)?;
+        self.index.set(ring, index, start, size);
+        self.live += u64::from(size);
+        self.entries += 1;
+        Ok(Entry::Packed(InitializedEntry::packed(size)))
+    }
+
+    /// Records only have room for 32 bit offsets, so running out of them is
+    /// treated like running out of space.
+    fn write_at_end(&mut self, buf: &[u8]) -> Result<u32, CliError> {
+        let end = self.end + u64::try_from(buf.len()).unwrap();
+        let (Ok(start), Ok(_)) = (u32::try_from(self.end), u32::try_from(end)) else {
+            return Err(ringboard_core::Error::Io {
+                error: io::Error::new(ErrorKind::StorageFull, "Packed log is full."),
+                context: "Packed log outgrew the offsets its records can hold.".into(),
+            }
+            .into());
+        };
+        self.log
+            .write_all_at(buf, self.end)
+            .map_io_err(|| "Failed to write to packed log.")?;
+        self.end = end;
+        Ok(start)
+    }
+
+    /// The log along with where the entry of the given ring slot starts in it.
+    pub fn locate(&self, ring: RingKind, index: u32) -> Result<(&File, u64), CliError> {
+        self.index
+            .get(ring, index)
+            .map(|(start, _)| (&self.log, u64::from(start)))
+            .ok_or_else(|| missing_record(ring, index))
+    }
+
+    /// Points the record of `to` at the entry of `from`, which is left without
+    /// one.
+    pub fn move_entry(
+        &mut self,
+        (from, from_index): (RingKind, u32),
+        (to, to_index): (RingKind, u32),
+    ) -> Result<(), CliError> {
+        if (from, from_index) == (to, to_index) {
+            return Ok(());
+        }
+        let (start, len) = self
+            .index
+            .get(from, from_index)
+            .ok_or_else(|| missing_record(from, from_index))?;
+        self.index.set(to, to_index, start, len);
+        self.index.clear(from, from_index);
+        Ok(())
+    }
+
+    /// Exchanges the records of two ring slots whose entries were swapped,
+    /// given whether each slot now holds a packed entry.
+    pub fn swap(
+        &mut self,
+        (ring1, index1, packed1): (RingKind, u32, bool),
+        (ring2, index2, packed2): (RingKind, u32, bool),
+    ) {
+        let (record1, record2) = (self.index.get(ring1, index1), self.index.get(ring2, index2));
+        for (ring, index, packed, record) in [
+            (ring1, index1, packed1, record2),
+            (ring2, index2, packed2, record1),
+        ] {
+            match record {
+                Some((start, len)) if packed => self.index.set(ring, index, start, len),
+                _ => self.index.clear(ring, index),
+            }
+        }
+    }
+
+    pub fn free(&mut self, ring: RingKind, index: u32) {
+        let Some((_, len)) = self.index.get(ring, index) else {
+            warn!("Freeing packed entry {index} in {ring:?} ring without a record.");
+            return;
+        };
+        debug!("Freeing {len} byte packed entry {index} in {ring:?} ring.");
+        self.index.clear(ring, index);
+        self.live -= u64::from(len);
+        self.entries -= 1;
+    }
+
+    /// Moves the given entries, which must be every entry in the log, towards
+    /// the front of the log to get rid of the garbage between them. Returns
+    /// how many bytes the log shrank by.
+    ///
+    /// An entry is copied to its new place before its record is pointed
+    /// there, and only over bytes that no record points at anymore. Entries
+    /// whose new place overlaps their old one are copied to the end of the
+    /// log first so a crash never leaves them half overwritten.
+    pub fn compact(
+        &mut self,
+        entries: impl IntoIterator<Item = (RingKind, u32)>,
+    ) -> Result<u64, CliError> {
+        let mut records = entries
+            .into_iter()
+            .filter_map(|(ring, index)| {
+                let (start, len) = self.index.get(ring, index)?;
+                Some((start, len, ring, index))
+            })
+            .collect::<Vec<_>>();
+        records.sort_unstable_by_key(|&(start, ..)| start);
+        info!("Compacting packed log of {} entries.", records.len());
+
+        let old_end = self.end;
+        let mut cursor: u32 = 0;
+        let mut buf = [0; packed::MAX_LEN as usize];
+        for (start, len, ring, index) in records {
+            let buf = &mut buf[..usize::from(len)];
+            if start == cursor {
+                cursor += u32::from(len);
+                continue;
+            }
+
+            self.log
+                .read_exact_at(buf, start.into())
+                .map_io_err(|| "Failed to read from packed log.")?;
+            if cursor + u32::from(len) > start {
+                let staged = self.write_at_end(buf)?;
+                self.index.set(ring, index, staged, len);
+            }
+            self.log
+                .write_all_at(buf, cursor.into())
+                .map_io_err(|| "Failed to write to packed log.")?;
+            self.index.set(ring, index, cursor, len);
+            cursor += u32::from(len);
+        }
+        debug_assert_eq!(u64::from(cursor), self.live);
+
+        match fallocate(
+            &self.log,
+            FallocateFlags::PUNCH_HOLE | FallocateFlags::KEEP_SIZE,
+            cursor.into(),
+            self.end - u64::from(cursor),
+        ) {
+            Ok(()) => {}
+            Err(Errno::OPNOTSUPP) => debug!("File system can't punch holes in the packed log."),
+            Err(e) => warn!("Failed to release space freed from the packed log: {e}"),
+        }
+        self.end = cursor.into();
+        let freed = old_end - self.end;
+        info!("Packed log compaction freed {freed} bytes.");
+        Ok(freed)
+    }
+
+    pub fn usage(&self) -> Result<PackedUsage, CliError> {
+        Ok(PackedUsage {
+            entries: self.entries,
+            live_bytes: self.live,
+            log_bytes: self.end,
+            allocated_bytes: statx(&self.log, c"", AtFlags::EMPTY_PATH, StatxFlags::BLOCKS)
+                .map_io_err(|| "Failed to statx packed log.")?
+                .stx_blocks
+                * 512,
+        })
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::{Duration, Instant};
+
+use crate::settings::Settings;
+
+/// How often rejected connections are reported while they keep coming.
+const WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A token bucket for new connections so a client stuck in a reconnect loop
+/// can't keep the server busy setting up and tearing down connections.
+///
+/// Accepted sockets are `io_uring` direct descriptors which can't be asked for
+/// their peer's credentials, so all clients share one bucket. The socket lives
+/// in a per-user directory, so in practice they all belong to the same user
+/// anyway.
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    interval: Duration,
+    burst: u32,
+    tokens: u32,
+    refilled_at: Instant,
+
+    unreported: u64,
+    reported_at: Option<Instant>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(
+        &Settings {
+            max_connections_per_second,
+            connection_burst,
+            ..
+        }: &Settings,
+        now: Instant,
+    ) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / max_connections_per_second,
+            burst: connection_burst,
+            tokens: connection_burst,
+            refilled_at: now,
+
+            unreported: 0,
+            reported_at: None,
+        }
+    }
+
+    /// Applies reloaded settings. Clients that were already turned away stay
+    /// counted.
+    pub fn reload(&mut self, settings: &Settings, now: Instant) {
+        *self = Self {
+            unreported: self.unreported,
+            reported_at: self.reported_at,
+            ..Self::new(settings, now)
+        };
+    }
+
+    /// Whether a connection arriving now should be accepted.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        let earned = now.saturating_duration_since(self.refilled_at).as_nanos()
+            / self.interval.as_nanos().max(1);
+        let earned = u32::try_from(earned).unwrap_or(u32::MAX);
+        if self.tokens.saturating_add(earned) >= self.burst {
+            self.tokens = self.burst;
+            self.refilled_at = now;
+        } else if earned > 0 {
+            self.tokens += earned;
+            self.refilled_at += self.interval * earned;
+        }
+
+        if self.tokens == 0 {
+            false
+        } else {
+            self.tokens -= 1;
+            true
+        }
+    }
+
+    /// Records a rejected connection, returning how many went unreported if
+    /// it's time to warn about them again.
+    pub fn reject(&mut self, now: Instant) -> Option<u64> {
+        self.unreported += 1;
+        if self
+            .reported_at
+            .is_some_and(|at| now.saturating_duration_since(at) < WARNING_INTERVAL)
+        {
+            return None;
+        }
+        self.reported_at = Some(now);
+        Some(std::mem::take(&mut self.unreported))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{ConnectionLimiter, WARNING_INTERVAL};
+    use crate::settings::Settings;
+
+    fn limiter(now: Instant) -> ConnectionLimiter {
+        ConnectionLimiter::new(
+            &Settings {
+                max_connections_per_second: 10,
+                connection_burst: 3,
+                ..Settings::default()
+            },
+            now,
+        )
+    }
+
+    #[test]
+    fn bursts_are_allowed_then_refilled_at_the_rate() {
+        let start = Instant::now();
+        let mut limiter = limiter(start);
+
+        assert!((0..3).all(|_| limiter.try_acquire(start)));
+        assert!(!limiter.try_acquire(start));
+
+        let later = start + Duration::from_millis(150);
+        assert!(limiter.try_acquire(later));
+        assert!(!limiter.try_acquire(later));
+        // The leftover 50ms count towards the next connection.
+        assert!(limiter.try_acquire(start + Duration::from_millis(200)));
+
+        let idle = start + Duration::from_secs(60);
+        assert!((0..3).all(|_| limiter.try_acquire(idle)));
+        assert!(!limiter.try_acquire(idle));
+    }
+
+    #[test]
+    fn rejections_are_reported_once_per_interval() {
+        let start = Instant::now();
+        let mut limiter = limiter(start);
+
+        assert_eq!(limiter.reject(start), Some(1));
+        assert_eq!(limiter.reject(start), None);
+        assert_eq!(limiter.reject(start + Duration::from_secs(1)), None);
+        assert_eq!(limiter.reject(start + WARNING_INTERVAL), Some(3));
+    }
+}
@@ -1,114 +1,321 @@
 use std::{
+    fmt,
     fs::File,
     io,
     io::{ErrorKind, Read as StdRead, Write},
-    mem,
-    os::fd::{AsRawFd, OwnedFd},
+    iter, mem,
+    os::fd::{AsRawFd, OwnedFd, RawFd},
     path::PathBuf,
-    ptr,
+    ptr, slice,
+    time::{Duration, Instant},
 };
 
 use arrayvec::ArrayVec;
 use io_uring::{
     cqueue::{buffer_select, more, Entry},
-    opcode::{AcceptMulti, Close, PollAdd, RecvMsgMulti, SendMsg},
+    opcode::{Accept, Close, PollAdd, RecvMsgMulti, Send, SendMsg, Shutdown, Timeout},
     squeue::{Flags, PushError},
-    types::Fixed,
-    IoUring, SubmissionQueue,
+    types::{DestinationSlot, Fixed, Timespec},
+    IoUring, SubmissionQueue, Submitter,
 };
 use log::{debug, info, trace, warn};
-use ringboard_core::{dirs::socket_file, init_unix_server, IoErr};
+use ringboard_core::{dirs::socket_file, init_unix_server, protocol::RATE_LIMITED, Context, IoErr};
 use rustix::{
     io::Errno,
-    net::{RecvFlags, SocketType},
+    net::{sockopt::set_socket_passcred, RecvFlags, SocketType},
 };
 
 use crate::{
     allocator::Allocator,
+    backup::Backups,
     io_uring::{buf_ring::BufRing, register_buf_ring, types::RecvMsgOutMut},
+    rate_limit::ConnectionLimiter,
     requests,
+    requests::{Counters, Refusal},
+    search::Searches,
     send_msg_bufs::SendMsgBufs,
+    settings::Settings,
     CliError,
 };
 
-pub const MAX_NUM_CLIENTS: u8 = 1 << MAX_NUM_CLIENTS_SHIFT;
+/// The most clients [`Settings::max_clients`] can allow, which is every ID
+/// that fits in the client field of a completion's user data.
+pub const MAX_NUM_CLIENTS: u16 = 1 << CLIENT_ID_BITS;
+/// A request's receive buffer is only recycled once its response has been
+/// sent, so this also caps how many responses a client can have outstanding.
+/// Clients that stop reading run out of buffers and aren't received from until
+/// their responses drain.
 pub const MAX_NUM_BUFS_PER_CLIENT: u8 = 8;
 
-const MAX_NUM_CLIENTS_SHIFT: u32 = 5;
+/// Each receive buffer holds the recvmsg header and control data followed by
+/// the message, which is at most a full batch of requests.
+const RECV_BUF_LEN: u32 = 1024;
+const _: () = assert!(requests::MAX_MESSAGE_LEN + 256 <= RECV_BUF_LEN as usize);
 
-#[derive(Default, Debug)]
-struct Clients {
-    connections: u32,
-    pending_closes: u32,
-    pending_recv: u32,
-    pending_sends: u32,
-}
+const CLIENT_ID_BITS: u32 = u8::BITS;
 
-impl Clients {
-    fn is_connected(&self, id: u8) -> bool {
-        debug_assert!(id < MAX_NUM_CLIENTS);
-        (self.connections & (1 << id)) != 0
-    }
+/// How long a client has to go without sending anything for its receive
+/// buffers to be released when memory runs low.
+const IDLE_CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One bit per possible client ID.
+#[derive(Default, Copy, Clone, Debug)]
+struct ClientSet([u64; MAX_NUM_CLIENTS as usize / u64::BITS as usize]);
 
-    fn is_closing(&self, id: u8) -> bool {
-        debug_assert!(id < MAX_NUM_CLIENTS);
-        (self.pending_closes & (1 << id)) != 0
+impl ClientSet {
+    const fn split(id: u8) -> (usize, u64) {
+        (
+            id as usize / u64::BITS as usize,
+            1 << (id as u32 % u64::BITS),
+        )
     }
 
-    fn set_connected(&mut self, id: u8) {
-        debug_assert!(id < MAX_NUM_CLIENTS);
-        self.connections |= 1 << id;
-        self.pending_closes &= !(1 << id);
-        self.pending_recv &= !(1 << id);
+    const fn contains(&self, id: u8) -> bool {
+        let (word, bit) = Self::split(id);
+        (self.0[word] & bit) != 0
     }
 
-    fn set_send_buffered(&mut self, id: u8, value: bool) -> bool {
-        let r = (self.pending_sends & (1 << id)) != 0;
+    /// Adds or removes `id`, returning whether it was in the set before.
+    const fn set(&mut self, id: u8, value: bool) -> bool {
+        let (word, bit) = Self::split(id);
+        let r = (self.0[word] & bit) != 0;
         if value {
-            self.pending_sends |= 1 << id;
+            self.0[word] |= bit;
         } else {
-            self.pending_sends &= !(1 << id);
+            self.0[word] &= !bit;
         }
         r
     }
 
-    fn set_disconnecting(&mut self, id: u8) {
-        debug_assert!(id < MAX_NUM_CLIENTS);
-        self.pending_closes |= 1 << id;
+    fn len(&self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+}
+
+#[derive(Debug)]
+struct Clients {
+    /// Every client holding a slot in the fixed file table, including those
+    /// still handshaking or being closed.
+    accepted: ClientSet,
+    connections: ClientSet,
+    pending_closes: ClientSet,
+    rejected: ClientSet,
+    pending_recv: ClientSet,
+    pending_sends: ClientSet,
+    /// When each client last sent a message.
+    last_active: Box<[Instant]>,
+}
+
+impl Clients {
+    fn new(max_clients: u16, now: Instant) -> Self {
+        Self {
+            accepted: ClientSet::default(),
+            connections: ClientSet::default(),
+            pending_closes: ClientSet::default(),
+            rejected: ClientSet::default(),
+            pending_recv: ClientSet::default(),
+            pending_sends: ClientSet::default(),
+            last_active: vec![now; max_clients.into()].into_boxed_slice(),
+        }
+    }
+
+    fn connected(&self) -> u32 {
+        self.connections.len()
     }
 
-    fn set_disconnected(&mut self, id: u8) {
-        debug_assert!(id < MAX_NUM_CLIENTS);
-        self.connections &= !(1 << id);
-        self.pending_closes |= 1 << id;
+    fn accepted(&self) -> u32 {
+        self.accepted.len()
     }
 
-    fn set_closed(&mut self, id: u8) {
-        debug_assert!(id < MAX_NUM_CLIENTS);
-        self.connections &= !(1 << id);
-        self.pending_closes &= !(1 << id);
-        self.pending_recv &= !(1 << id);
+    const fn is_connected(&self, id: u8) -> bool {
+        self.connections.contains(id)
     }
 
-    fn set_pending_recv(&mut self, id: u8) {
-        debug_assert!(id < MAX_NUM_CLIENTS);
-        self.pending_recv |= 1 << id;
+    const fn is_closing(&self, id: u8) -> bool {
+        self.pending_closes.contains(id)
     }
 
-    fn take_pending_recv(&mut self, id: u8) -> bool {
-        debug_assert!(id < MAX_NUM_CLIENTS);
-        let r = (self.pending_recv & (1 << id)) != 0;
-        self.pending_recv &= !(1 << id);
-        r
+    /// Whether the client failed its handshake and is waiting to hang up.
+    const fn is_rejected(&self, id: u8) -> bool {
+        self.rejected.contains(id)
+    }
+
+    fn set_accepted(&mut self, id: u8, now: Instant) {
+        self.accepted.set(id, true);
+        self.set_active(id, now);
+    }
+
+    fn set_active(&mut self, id: u8, now: Instant) {
+        self.last_active[usize::from(id)] = now;
+    }
+
+    /// Connected clients that haven't sent anything for at least `timeout` and
+    /// have nothing left to be sent to them.
+    fn idle(&self, now: Instant, timeout: Duration) -> impl Iterator<Item = u8> + '_ {
+        self.last_active
+            .iter()
+            .zip(0..=u8::MAX)
+            .filter(move |&(&active, id)| {
+                now.saturating_duration_since(active) >= timeout
+                    && self.is_connected(id)
+                    && !self.is_closing(id)
+                    && !self.pending_recv.contains(id)
+                    && !self.pending_sends.contains(id)
+            })
+            .map(|(_, id)| id)
+    }
+
+    const fn set_connected(&mut self, id: u8) {
+        self.connections.set(id, true);
+        self.pending_closes.set(id, false);
+        self.rejected.set(id, false);
+        self.pending_recv.set(id, false);
+    }
+
+    /// Closing a socket with unread messages resets the connection, which
+    /// would discard the rejection before the client sees it. Rejected clients
+    /// are closed once they hang up instead, with anything they sent in the
+    /// meantime being read and dropped.
+    const fn set_rejected(&mut self, id: u8) {
+        self.rejected.set(id, true);
+    }
+
+    const fn set_send_buffered(&mut self, id: u8, value: bool) -> bool {
+        self.pending_sends.set(id, value)
+    }
+
+    const fn set_disconnecting(&mut self, id: u8) {
+        self.pending_closes.set(id, true);
+    }
+
+    const fn set_disconnected(&mut self, id: u8) {
+        self.connections.set(id, false);
+        self.rejected.set(id, false);
+        self.pending_closes.set(id, true);
+    }
+
+    const fn set_closed(&mut self, id: u8) {
+        self.accepted.set(id, false);
+        self.connections.set(id, false);
+        self.pending_closes.set(id, false);
+        self.rejected.set(id, false);
+        self.pending_recv.set(id, false);
+    }
+
+    const fn set_pending_recv(&mut self, id: u8) {
+        self.pending_recv.set(id, true);
+    }
+
+    const fn take_pending_recv(&mut self, id: u8) -> bool {
+        self.pending_recv.set(id, false)
     }
 }
 
-fn setup_uring() -> Result<IoUring, CliError> {
+/// Why a client's connection ended.
+#[derive(Copy, Clone, Debug)]
+enum Disconnect {
+    Closed,
+    Reset,
+    Unread,
+    ReceiveFailed,
+    SendFailed,
+    ProtocolViolation,
+    VersionMismatch,
+    ForeignUser,
+    RateLimited,
+}
+
+impl fmt::Display for Disconnect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Closed => "closed by the client",
+            Self::Reset => "reset by the client",
+            Self::Unread => "closed before reading every response",
+            Self::ReceiveFailed => "failed to receive",
+            Self::SendFailed => "failed to respond",
+            Self::ProtocolViolation => "protocol violation",
+            Self::VersionMismatch => "no supported protocol version",
+            Self::ForeignUser => "running as another user",
+            Self::RateLimited => "rate limited",
+        })
+    }
+}
+
+/// What a client did while connected, logged once it's closed.
+#[derive(Copy, Clone, Debug, Default)]
+struct Activity {
+    requests: u64,
+    bytes_written: u64,
+    /// The first reason the connection was ended for, since later failures
+    /// are usually a consequence of it.
+    disconnect: Option<Disconnect>,
+}
+
+impl Activity {
+    fn end(&mut self, reason: Disconnect) {
+        self.disconnect.get_or_insert(reason);
+    }
+}
+
+fn register_client_buffers(submitter: &Submitter, client: u8) -> Result<BufRing, CliError> {
+    let bufs = register_buf_ring(
+        submitter,
+        MAX_NUM_BUFS_PER_CLIENT.into(),
+        client.into(),
+        RECV_BUF_LEN,
+    )
+    .map_io_err(|| "Failed to register buffer ring with io_uring.")?;
+    Ok(bufs)
+}
+
+/// Unregisters the receive buffers of clients that have been idle for a while.
+///
+/// An idle client's multishot recv stays armed and fails for lack of buffers
+/// once the client sends something again, at which point its buffers are
+/// registered anew. Buffers still holding a request that hasn't been answered
+/// are never released.
+fn release_idle_buffers(
+    clients: &Clients,
+    client_buffers: &mut [Option<BufRing>],
+    send_bufs: &SendMsgBufs,
+    searches: &Searches,
+    now: Instant,
+    submitter: &Submitter,
+) -> Result<(), CliError> {
+    let mut released = 0;
+    let mut count = 0;
+    for client in clients.idle(now, IDLE_CLIENT_TIMEOUT) {
+        if send_bufs.has_outstanding_sends(client) || searches.is_searching(client) {
+            continue;
+        }
+        let Some(bufs) = client_buffers[usize::from(client)].take() else {
+            continue;
+        };
+
+        debug!("Releasing idle client {client}'s receive buffers.");
+        released += bufs.size();
+        count += 1;
+        bufs.unregister(submitter)
+            .map_io_err(|| "Failed to unregister buffer ring with io_uring.")?;
+    }
+    if count > 0 {
+        info!("Released the receive buffers of {count} idle client(s) ({released} bytes).");
+    }
+    Ok(())
+}
+
+fn setup_uring(max_clients: u16, searches: &Searches) -> Result<(IoUring, OwnedFd), CliError> {
     let uring = IoUring::<io_uring::squeue::Entry>::builder()
         .setup_coop_taskrun()
         .setup_single_issuer()
         .setup_defer_taskrun()
-        .build((MAX_NUM_CLIENTS * 2).into())
+        // Every client can have a completion in flight for each of its receive and send
+        // buffers on top of its shutdown and close, plus one for each built-in listener.
+        .setup_cqsize(
+            (u32::from(max_clients) * (2 * u32::from(MAX_NUM_BUFS_PER_CLIENT) + 2) + 4)
+                .next_power_of_two(),
+        )
+        .build((u32::from(max_clients) * 2).next_power_of_two())
         .map_io_err(|| "Failed to create io_uring.")?;
 
     let signal_handler = unsafe {
@@ -120,9 +327,11 @@ fn setup_uring() -> Result<IoUring, CliError> {
         libc::sigaddset(&mut set, libc::SIGTERM);
         libc::sigaddset(&mut set, libc::SIGQUIT);
         libc::sigaddset(&mut set, libc::SIGINT);
+        libc::sigaddset(&mut set, libc::SIGHUP);
         libc::sigprocmask(libc::SIG_BLOCK, &set, ptr::null_mut());
 
-        let fd = libc::signalfd(-1, &set, 0);
+        // Non-blocking so every pending signal can be drained once it's readable.
+        let fd = libc::signalfd(-1, &set, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC);
         if fd < 0 {
             return Err(CliError::Internal {
                 context: "Could not create signal fd.".into(),
@@ -136,9 +345,15 @@ fn setup_uring() -> Result<IoUring, CliError> {
         cgroup.push_str("/sys/fs/cgroup");
         let start = cgroup.len();
         File::open("/proc/self/cgroup")
-            .map_io_err(|| "Failed to open cgroup file: \"/proc/self/cgroup\"")?
+            .map_io_err(|| Context::File {
+                message: "Failed to open cgroup file",
+                path: "/proc/self/cgroup".into(),
+            })?
             .read_to_string(&mut cgroup)
-            .map_io_err(|| "Failed to read cgroup file: \"/proc/self/cgroup\"")?;
+            .map_io_err(|| Context::File {
+                message: "Failed to read cgroup file",
+                path: "/proc/self/cgroup".into(),
+            })?;
         if let Some((idx, _)) = cgroup.match_indices(':').nth(1) {
             cgroup.replace_range(start..=idx, "");
         }
@@ -150,32 +365,98 @@ fn setup_uring() -> Result<IoUring, CliError> {
             .read(true)
             .write(true)
             .open(&mem_pressure_path)
-            .map_io_err(|| format!("Failed to open pressure file: {mem_pressure_path:?}"))?;
+            .map_io_err(|| Context::File {
+                message: "Failed to open pressure file",
+                path: mem_pressure_path.clone(),
+            })?;
 
         mem_pressure
             .write_all(b"some 50000 2000000")
-            .map_io_err(|| format!("Failed to write to pressure file: {mem_pressure_path:?}"))?;
+            .map_io_err(|| Context::File {
+                message: "Failed to write to pressure file",
+                path: mem_pressure_path.clone(),
+            })?;
 
         OwnedFd::from(mem_pressure)
     };
 
     let socket = init_unix_server(socket_file(), SocketType::SEQPACKET)?;
+    // Clients inherit this, making the kernel tell us who they are so that other
+    // users can be refused.
+    set_socket_passcred(&socket, true)
+        .map_io_err(|| "Failed to enable credential passing on socket.")?;
 
     let built_ins = [
         socket.as_raw_fd(),
         signal_handler.as_raw_fd(),
         low_mem_listener.as_raw_fd(),
+        searches.wakeup().as_raw_fd(),
     ];
+    // Clients get the free slots before the built-ins, so accepting can never
+    // hand out an ID past the limit.
     uring
         .submitter()
-        .register_files_sparse(u32::from(MAX_NUM_CLIENTS) + u32::try_from(built_ins.len()).unwrap())
+        .register_files_sparse(u32::from(max_clients) + u32::try_from(built_ins.len()).unwrap())
         .map_io_err(|| "Failed to set up io_uring fixed file table.")?;
     uring
         .submitter()
-        .register_files_update(MAX_NUM_CLIENTS.into(), &built_ins)
+        .register_files_update(max_clients.into(), &built_ins)
         .map_io_err(|| "Failed to register socket FD with io_uring.")?;
 
-    Ok(uring)
+    Ok((uring, signal_handler))
+}
+
+/// Reads every pending signal in the order they're dequeued, stopping early if
+/// `handle` returns `false`.
+fn drain_signals(
+    signal_handler: &OwnedFd,
+    mut handle: impl FnMut(u32) -> bool,
+) -> Result<(), CliError> {
+    let mut infos = [unsafe { mem::zeroed::<libc::signalfd_siginfo>() }; 4];
+    loop {
+        let buf = unsafe {
+            slice::from_raw_parts_mut(infos.as_mut_ptr().cast::<u8>(), mem::size_of_val(&infos))
+        };
+        let len = match rustix::io::read(signal_handler, buf) {
+            Err(Errno::AGAIN) => return Ok(()),
+            r => r.map_io_err(|| "Failed to read signals.")?,
+        };
+        for info in &infos[..len / size_of::<libc::signalfd_siginfo>()] {
+            if !handle(info.ssi_signo) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Re-reads the settings file and applies whatever can change without a
+/// restart. An invalid file leaves the current settings in place.
+fn reload_settings(
+    settings: &mut Settings,
+    allocator: &mut Allocator,
+    limiter: &mut ConnectionLimiter,
+    send_bufs: &mut SendMsgBufs,
+) {
+    let reloaded = match Settings::load() {
+        Ok(reloaded) => reloaded,
+        Err(e) => {
+            warn!("Keeping current settings, failed to reload them: {e}");
+            return;
+        }
+    };
+
+    let startup_only = settings.startup_only_changes(&reloaded);
+    if !startup_only.is_empty() {
+        warn!(
+            "Changes to {} only take effect once the server restarts.",
+            startup_only.join(", ")
+        );
+    }
+    allocator.reload(&reloaded);
+    limiter.reload(&reloaded, Instant::now());
+    send_bufs.trim();
+    *settings = reloaded;
+    info!("Reloaded settings.");
 }
 
 impl From<PushError> for CliError {
@@ -186,30 +467,60 @@ impl From<PushError> for CliError {
     }
 }
 
-pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
+pub fn run(
+    allocator: &mut Allocator,
+    settings: &Settings,
+    backups: Option<&Backups>,
+) -> Result<(), CliError> {
     const REQ_TYPE_ACCEPT: u64 = 0;
     const REQ_TYPE_RECV: u64 = 1;
     const REQ_TYPE_CLOSE: u64 = 2;
     const REQ_TYPE_READ_SIGNALS: u64 = 3;
     const REQ_TYPE_SENDMSG: u64 = 4;
     const REQ_TYPE_LOW_MEM: u64 = 5;
-    const REQ_TYPE_MASK: u64 = 0b111;
+    const REQ_TYPE_REJECT: u64 = 6;
+    const REQ_TYPE_SEARCH: u64 = 7;
+    const REQ_TYPE_SHUTDOWN: u64 = 8;
+    const REQ_TYPE_EXPIRE: u64 = 9;
+    const REQ_TYPE_AGE_CHECK: u64 = 10;
+    const REQ_TYPE_MASK: u64 = 0b1111;
     const REQ_TYPE_SHIFT: u32 = REQ_TYPE_MASK.count_ones();
 
-    let accept = AcceptMulti::new(Fixed(MAX_NUM_CLIENTS.into()))
-        .allocate_file_index(true)
+    let max_clients = settings.max_clients;
+    // Only one connection is accepted at a time so that none are taken off the
+    // listen backlog while the fixed file table is full, which would drop them.
+    let accept = Accept::new(Fixed(max_clients.into()), ptr::null_mut(), ptr::null_mut())
+        .file_index(Some(DestinationSlot::auto_target()))
         .build()
         .user_data(REQ_TYPE_ACCEPT);
     let poll_low_mem = PollAdd::new(
-        Fixed(u32::from(MAX_NUM_CLIENTS) + 2),
+        Fixed(u32::from(max_clients) + 2),
         u32::try_from(libc::POLLPRI).unwrap(),
     )
     .multi(true)
     .build()
     .user_data(REQ_TYPE_LOW_MEM);
+    let poll_searches = PollAdd::new(
+        Fixed(u32::from(max_clients) + 3),
+        u32::try_from(libc::POLLIN).unwrap(),
+    )
+    .multi(true)
+    .build()
+    .user_data(REQ_TYPE_SEARCH);
     let receive_hdr = {
+        // Only leave room for the file descriptors a request may carry: the kernel
+        // closes any others and flags the message as truncated. The sender's
+        // credentials are attached to every message when origins are recorded.
+        #[allow(clippy::useless_conversion)]
+        let control_len = unsafe {
+            libc::CMSG_SPACE(u32::try_from(size_of::<RawFd>() * requests::MAX_REQUEST_FDS).unwrap())
+                + libc::CMSG_SPACE(u32::try_from(size_of::<libc::ucred>()).unwrap())
+        }
+        .try_into()
+        .unwrap();
+
         let mut hdr = unsafe { mem::zeroed::<libc::msghdr>() };
-        hdr.msg_controllen = 24;
+        hdr.msg_controllen = control_len;
         hdr
     };
     let recvmsg = |fd| {
@@ -218,11 +529,22 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
             .build()
     };
 
-    let store_fd = |fd| u64::from(fd) << (u64::BITS - MAX_NUM_CLIENTS_SHIFT);
-    let restore_fd = |entry: &Entry| {
-        u8::try_from(entry.user_data() >> (u64::BITS - MAX_NUM_CLIENTS_SHIFT)).unwrap()
+    // The most SQEs that handling a completion can push, not counting sends which
+    // are only submitted once all completions have been handled.
+    let max_submissions = |entry: &Entry| match entry.user_data() & REQ_TYPE_MASK {
+        // The next accept plus the client's recv, or the rejection and close of a rate
+        // limited client.
+        REQ_TYPE_ACCEPT => 3,
+        // A client's shutdown and close.
+        REQ_TYPE_RECV | REQ_TYPE_SENDMSG => 2,
+        // Either a recv, accept or poll.
+        _ => 1,
     };
 
+    let store_fd = |fd| u64::from(fd) << (u64::BITS - CLIENT_ID_BITS);
+    let restore_fd =
+        |entry: &Entry| u8::try_from(entry.user_data() >> (u64::BITS - CLIENT_ID_BITS)).unwrap();
+
     let drain_send_bufs = |client: u8,
                            bufs: &mut SendMsgBufs,
                            remaining_submission_slots: usize,
@@ -250,40 +572,53 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
     let try_close = |client: u8,
                      clients: &mut Clients,
                      bufs: &mut SendMsgBufs,
+                     searches: &mut Searches,
                      submissions: &mut SubmissionQueue|
      -> Result<_, PushError> {
+        searches.cancel(client);
         if bufs.has_outstanding_sends(client) {
             clients.set_disconnecting(client);
             return Ok(());
         }
 
+        // Closing the fixed file alone leaves the socket open while its multishot recv
+        // is armed, so shut it down first for the client to see the disconnect.
+        let shutdown = Shutdown::new(Fixed(u32::from(client)), libc::SHUT_RDWR)
+            .build()
+            .flags(Flags::IO_HARDLINK | Flags::SKIP_SUCCESS)
+            .user_data(REQ_TYPE_SHUTDOWN | store_fd(client));
         let close = Close::new(Fixed(u32::from(client)))
             .build()
             .user_data(REQ_TYPE_CLOSE | store_fd(client));
-        unsafe { submissions.push(&close) }?;
+        unsafe { submissions.push_multiple(&[shutdown, close]) }?;
         clients.set_disconnected(client);
 
         Ok(())
     };
 
-    let mut uring = setup_uring()?;
+    let mut searches = Searches::new()?;
+    let (mut uring, signal_handler) = setup_uring(max_clients, &searches)?;
+    let read_signals = PollAdd::new(
+        Fixed(u32::from(max_clients) + 1),
+        u32::try_from(libc::POLLIN).unwrap(),
+    )
+    .build()
+    .user_data(REQ_TYPE_READ_SIGNALS);
 
     #[cfg(feature = "systemd")]
     sd_notify::notify(false, &[sd_notify::NotifyState::Ready])
         .map_io_err(|| "Failed to notify systemd of startup completion.")?;
 
     {
-        let read_signals = PollAdd::new(
-            Fixed(u32::from(MAX_NUM_CLIENTS) + 1),
-            u32::try_from(libc::POLLIN).unwrap(),
-        )
-        .build()
-        .user_data(REQ_TYPE_READ_SIGNALS);
-
         let mut submission = uring.submission();
         unsafe {
             submission
-                .push_multiple(&[accept.clone(), read_signals, poll_low_mem.clone()])
+                .push_multiple(&[
+                    accept.clone(),
+                    read_signals.clone(),
+                    poll_low_mem.clone(),
+                    poll_searches.clone(),
+                ])
                 .unwrap();
         }
     }
@@ -291,14 +626,32 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
     info!("Server event loop started.");
 
     let mut sequence_number = 0;
-    let mut client_buffers = [const { None::<BufRing> }; MAX_NUM_CLIENTS as usize];
-    let mut send_bufs = SendMsgBufs::new();
-    let mut clients = Clients::default();
+    let mut counters = Counters::default();
+    let mut settings = settings.clone();
+    let mut limiter = ConnectionLimiter::new(&settings, Instant::now());
+    let mut seen_overflows = 0;
+    let mut client_buffers = iter::repeat_with(|| None::<BufRing>)
+        .take(max_clients.into())
+        .collect::<Box<[_]>>();
+    let mut send_bufs = SendMsgBufs::new(max_clients);
+    let mut clients = Clients::new(max_clients, Instant::now());
+    let mut origins = vec![None; max_clients.into()];
+    // The protocol version each client settled on while connecting.
+    let mut versions = vec![0; max_clients.into()];
+    let mut activity = vec![Activity::default(); max_clients.into()];
     let mut pending_accept = false;
+    let mut pending_trim = false;
+    // Read by the kernel when the timeout is submitted, so it has to stay put
+    // until then.
+    let mut expiry_timeout;
+    let mut pending_expiry = false;
+    let mut age_check_timeout;
+    let mut pending_age_check = false;
     let mut clients_with_pending_sends = ArrayVec::<u8, { MAX_NUM_CLIENTS as usize }>::new_const();
+    let mut deferred_completion = None::<Entry>;
     'outer: loop {
         {
-            let want = uring.submission().is_empty().into();
+            let want = (uring.submission().is_empty() && deferred_completion.is_none()).into();
             trace!("Waiting for at least {want} events.");
             match uring.submit_and_wait(want) {
                 Err(e) if e.kind() == ErrorKind::Interrupted => continue,
@@ -307,15 +660,26 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
             .map_io_err(|| "Failed to wait for io_uring.")?;
         }
 
+        {
+            let overflows = uring.completion().overflow();
+            if overflows != seen_overflows {
+                counters.dropped_completions += u64::from(overflows.wrapping_sub(seen_overflows));
+                seen_overflows = overflows;
+                warn!(
+                    "io_uring dropped completions ({} so far).",
+                    counters.dropped_completions
+                );
+            }
+        }
+
         let mut completions = unsafe { uring.completion_shared() };
         let mut submissions = unsafe { uring.submission_shared() };
-        loop {
-            if submissions.capacity() - submissions.len() < 2 {
+        while let Some(entry) = deferred_completion.take().or_else(|| completions.next()) {
+            if submissions.capacity() - submissions.len() < max_submissions(&entry) {
+                trace!("Submission queue is full, deferring completion.");
+                deferred_completion = Some(entry);
                 break;
             }
-            let Some(entry) = completions.next() else {
-                break;
-            };
 
             let result = u32::try_from(entry.result())
                 .map_err(|_| io::Error::from_raw_os_error(-entry.result()));
@@ -330,25 +694,49 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
                         }
                         r => r.map_io_err(|| "Failed to accept socket connection.")?,
                     };
-                    debug_assert!(client < u32::from(MAX_NUM_CLIENTS));
+                    debug_assert!(client < u32::from(max_clients));
                     #[allow(clippy::cast_possible_truncation)]
                     let client = client as u8;
+
+                    let now = Instant::now();
+                    clients.set_accepted(client, now);
+                    if clients.accepted() < u32::from(max_clients) {
+                        unsafe { submissions.push(&accept) }?;
+                    } else {
+                        info!("Client table is full, waiting for a client to leave.");
+                        pending_accept = true;
+                    }
+
+                    if !limiter.try_acquire(now) {
+                        static REJECTION: [u8; 1] = [RATE_LIMITED];
+
+                        counters.rejected_connections += 1;
+                        if let Some(count) = limiter.reject(now) {
+                            warn!(
+                                "Rejected {count} connection(s) for arriving too quickly ({} so \
+                                 far).",
+                                counters.rejected_connections
+                            );
+                        }
+
+                        activity[usize::from(client)].end(Disconnect::RateLimited);
+                        let reject = Send::new(Fixed(client.into()), REJECTION.as_ptr(), 1)
+                            .build()
+                            .flags(Flags::IO_HARDLINK | Flags::SKIP_SUCCESS)
+                            .user_data(REQ_TYPE_REJECT | store_fd(client));
+                        let close = Close::new(Fixed(client.into()))
+                            .build()
+                            .user_data(REQ_TYPE_CLOSE | store_fd(client));
+                        unsafe { submissions.push_multiple(&[reject, close]) }?;
+                        clients.set_disconnected(client);
+                        break 'accept;
+                    }
                     debug!("Accepting client {client}.");
 
                     debug_assert!(client_buffers[usize::from(client)].is_none());
-                    client_buffers[usize::from(client)] = Some(
-                        register_buf_ring(
-                            &uring.submitter(),
-                            MAX_NUM_BUFS_PER_CLIENT.into(),
-                            client.into(),
-                            256,
-                        )
-                        .map_io_err(|| "Failed to register buffer ring with io_uring.")?,
-                    );
+                    client_buffers[usize::from(client)] =
+                        Some(register_client_buffers(&uring.submitter(), client)?);
 
-                    if !more(entry.flags()) {
-                        unsafe { submissions.push(&accept) }?;
-                    }
                     let recv = recvmsg(client).user_data(REQ_TYPE_RECV | store_fd(client));
                     unsafe { submissions.push(&recv) }?;
                 }
@@ -361,17 +749,49 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
                                 .iter()
                                 .any(|kind| e.raw_os_error() == Some(kind.raw_os_error())) =>
                         {
+                            let bufs = &mut client_buffers[usize::from(fd)];
+                            if bufs.is_none() && !clients.is_closing(fd) {
+                                debug!("Restoring idle client {fd}'s receive buffers.");
+                                clients.set_active(fd, Instant::now());
+                                *bufs = Some(register_client_buffers(&uring.submitter(), fd)?);
+                                let recv = recvmsg(fd).user_data(entry.user_data());
+                                unsafe { submissions.push(&recv) }?;
+                                break 'recv;
+                            }
                             warn!("No buffers available to receive client {fd}'s message.");
                             clients.set_pending_recv(fd);
                             break 'recv;
                         }
                         Err(e) if e.kind() == ErrorKind::ConnectionReset => {
                             warn!("Client {fd} reset the connection.");
-                            try_close(fd, &mut clients, &mut send_bufs, &mut submissions)?;
+                            activity[usize::from(fd)].end(Disconnect::Reset);
+                            try_close(
+                                fd,
+                                &mut clients,
+                                &mut send_bufs,
+                                &mut searches,
+                                &mut submissions,
+                            )?;
                             break 'recv;
                         }
-                        r => r.map_io_err(|| format!("Failed to recv from client {fd}."))?,
+                        Err(e) => {
+                            // Only this client's connection is broken, so it alone is dropped.
+                            warn!("Disconnecting client {fd} after failing to receive: {e}");
+                            activity[usize::from(fd)].end(Disconnect::ReceiveFailed);
+                            if !clients.is_closing(fd) {
+                                try_close(
+                                    fd,
+                                    &mut clients,
+                                    &mut send_bufs,
+                                    &mut searches,
+                                    &mut submissions,
+                                )?;
+                            }
+                            break 'recv;
+                        }
+                        Ok(_) => {}
                     };
+                    clients.set_active(fd, Instant::now());
 
                     debug_assert!(buffer_select(entry.flags()).is_some());
                     let mut buf_submissions = client_buffers[usize::from(fd)]
@@ -381,15 +801,22 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
                     let mut buf = unsafe {
                         buf_submissions.get(entry.flags(), usize::try_from(entry.result()).unwrap())
                     };
+                    let token = buf.index();
                     let msg = RecvMsgOutMut::parse(&mut buf, &receive_hdr).map_err(|()| {
                         CliError::Internal {
                             context: "Didn't allocate enough large enough buffers.".into(),
                         }
                     })?;
-                    if msg.is_name_data_truncated()
-                        || msg.is_control_data_truncated()
-                        || msg.is_payload_truncated()
-                    {
+                    let received = requests::received_fds(msg.control_data).and_then(|received| {
+                        if msg.is_control_data_truncated() {
+                            Err(requests::ProtocolViolation::TooManyFds)
+                        } else if msg.is_payload_truncated() {
+                            Err(requests::ProtocolViolation::MessageTooLong)
+                        } else {
+                            Ok(received)
+                        }
+                    });
+                    if msg.is_name_data_truncated() {
                         return Err(CliError::Internal {
                             context: "Received data was truncated.".into(),
                         });
@@ -397,48 +824,120 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
 
                     if msg.payload_data.is_empty() {
                         debug!("Client {fd} closed the connection.");
+                        activity[usize::from(fd)].end(Disconnect::Closed);
                         if !clients.is_closing(fd) {
-                            try_close(fd, &mut clients, &mut send_bufs, &mut submissions)?;
+                            try_close(
+                                fd,
+                                &mut clients,
+                                &mut send_bufs,
+                                &mut searches,
+                                &mut submissions,
+                            )?;
                         }
                     } else {
                         if clients.is_closing(fd) {
                             debug!("Dropping spurious message for client {fd}.");
                             break 'recv;
                         }
+                        if clients.is_rejected(fd) {
+                            debug!("Dropping message from rejected client {fd}.");
+                            if !more(entry.flags()) {
+                                let recv = recvmsg(fd).user_data(entry.user_data());
+                                unsafe { submissions.push(&recv) }?;
+                            }
+                            break 'recv;
+                        }
 
                         if !clients.set_send_buffered(fd, true) {
                             clients_with_pending_sends.push(fd);
                         }
-                        let response = if clients.is_connected(fd) {
-                            requests::handle(
+                        let response = match received {
+                            Ok((fds, _)) if clients.is_connected(fd) => {
+                                activity[usize::from(fd)].requests += 1;
+                                requests::handle(
+                                    msg.payload_data,
+                                    fds,
+                                    &mut send_bufs,
+                                    allocator,
+                                    &mut sequence_number,
+                                    Counters {
+                                        connected_clients: clients.connected(),
+                                        ..counters
+                                    },
+                                    backups,
+                                    &mut searches,
+                                    fd,
+                                    token,
+                                    origins[usize::from(fd)],
+                                    versions[usize::from(fd)],
+                                )?
+                            }
+                            Ok((fds, sender)) => requests::connect(
                                 msg.payload_data,
-                                msg.control_data,
+                                &fds,
+                                sender,
+                                settings.record_origins,
                                 &mut send_bufs,
-                                allocator,
-                                &mut sequence_number,
-                            )?
-                        } else {
-                            let (version_valid, resp) =
-                                requests::connect(msg.payload_data, &mut send_bufs);
-                            if version_valid {
-                                info!("Client {fd} connected.");
-                                clients.set_connected(fd);
-                            } else {
-                                clients.set_disconnected(fd);
-                            }
-                            Some(resp)
+                            )
+                            .map(|(version, origin, resp)| {
+                                match version {
+                                    Ok(version) => {
+                                        info!("Client {fd} connected with protocol v{version}.");
+                                        clients.set_connected(fd);
+                                        counters.total_connections += 1;
+                                        origins[usize::from(fd)] = origin;
+                                        versions[usize::from(fd)] = version;
+                                    }
+                                    Err(refusal) => {
+                                        clients.set_rejected(fd);
+                                        activity[usize::from(fd)].end(match refusal {
+                                            Refusal::VersionMismatch => Disconnect::VersionMismatch,
+                                            Refusal::ForeignUser => Disconnect::ForeignUser,
+                                        });
+                                    }
+                                }
+                                Some(resp)
+                            }),
+                            Err(e) => Err(e),
                         };
+                        let response = response.unwrap_or_else(|e| {
+                            counters.rejected_requests += 1;
+                            if clients.is_connected(fd) && e.is_recoverable() {
+                                return Some(requests::reject(
+                                    &e,
+                                    &mut send_bufs,
+                                    &mut sequence_number,
+                                    fd,
+                                ));
+                            }
+                            warn!(
+                                "Disconnecting client {fd} for protocol violation ({} so far): {e}",
+                                counters.rejected_requests
+                            );
+                            clients.set_disconnected(fd);
+                            activity[usize::from(fd)].end(Disconnect::ProtocolViolation);
+                            None
+                        });
                         if let Some(resp) = response {
                             send_bufs.alloc(fd, buf.into_index().into(), resp);
+                        } else if searches.is_running(fd, token) {
+                            // The search answers in this buffer's send slot as it finds matches.
+                            let _ = buf.into_index();
                         }
 
-                        if clients.is_connected(fd) {
+                        if clients.is_connected(fd) || clients.is_rejected(fd) {
                             if !more(entry.flags()) {
                                 let recv = recvmsg(fd).user_data(entry.user_data());
                                 unsafe { submissions.push(&recv) }?;
                             }
                         } else {
-                            try_close(fd, &mut clients, &mut send_bufs, &mut submissions)?;
+                            try_close(
+                                fd,
+                                &mut clients,
+                                &mut send_bufs,
+                                &mut searches,
+                                &mut submissions,
+                            )?;
                         }
                     }
                 }
@@ -446,21 +945,26 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
                     let fd = restore_fd(&entry);
                     debug!("Handling sendmsg completion for client {fd}.");
 
-                    {
+                    let streaming = {
                         let token = entry.user_data() >> REQ_TYPE_SHIFT;
                         unsafe {
                             send_bufs.free(fd, token);
                         }
 
                         let index = u16::try_from(token & u64::from(u16::MAX)).unwrap();
-                        let mut submissions = client_buffers[usize::from(fd)]
-                            .as_mut()
-                            .unwrap()
-                            .submissions();
-                        unsafe {
-                            submissions.recycle_by_index(index);
+                        // Searches keep their buffer until their last response is sent.
+                        let streaming = searches.sent(fd, index);
+                        if !streaming {
+                            let mut submissions = client_buffers[usize::from(fd)]
+                                .as_mut()
+                                .unwrap()
+                                .submissions();
+                            unsafe {
+                                submissions.recycle_by_index(index);
+                            }
                         }
-                    }
+                        streaming
+                    };
 
                     match result {
                         Err(e) if e.kind() == ErrorKind::BrokenPipe => {
@@ -469,52 +973,101 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
                                     "Client {fd} closed the connection before consuming all \
                                      responses."
                                 );
+                                activity[usize::from(fd)].end(Disconnect::Unread);
                                 clients.set_disconnecting(fd);
                             }
                         }
                         Err(e) if e.kind() == ErrorKind::ConnectionReset => {
                             if !clients.is_closing(fd) {
                                 warn!("Client {fd} forcefully disconnected.");
+                                activity[usize::from(fd)].end(Disconnect::Reset);
                                 clients.set_disconnecting(fd);
                             }
                         }
                         Err(e) if e.raw_os_error() == Some(Errno::CANCELED.raw_os_error()) => {
                             debug_assert!(clients.is_closing(fd));
                         }
-                        r => {
-                            r.map_io_err(|| format!("Failed to send response to client {fd}."))?;
+                        Err(e) => {
+                            if !clients.is_closing(fd) {
+                                warn!("Disconnecting client {fd} after failing to respond: {e}");
+                                activity[usize::from(fd)].end(Disconnect::SendFailed);
+                                clients.set_disconnecting(fd);
+                            }
+                        }
+                        Ok(written) => {
+                            activity[usize::from(fd)].bytes_written += u64::from(written);
                         }
                     };
 
-                    if clients.is_closing(fd) && clients.is_connected(fd) {
-                        try_close(fd, &mut clients, &mut send_bufs, &mut submissions)?;
-                    } else if !clients.is_closing(fd)
-                        && clients.is_connected(fd)
-                        && clients.take_pending_recv(fd)
-                    {
+                    let open = clients.is_connected(fd) || clients.is_rejected(fd);
+                    if clients.is_closing(fd) && open {
+                        try_close(
+                            fd,
+                            &mut clients,
+                            &mut send_bufs,
+                            &mut searches,
+                            &mut submissions,
+                        )?;
+                    } else if !clients.is_closing(fd) && open && clients.take_pending_recv(fd) {
                         info!("Restoring client {fd}'s connection.");
                         let recv = recvmsg(fd).user_data(REQ_TYPE_RECV | store_fd(fd));
                         unsafe { submissions.push(&recv) }?;
                     }
+                    // Closing clients had their searches cancelled.
+                    if streaming {
+                        searches.poll(&mut send_bufs, |client| {
+                            if !clients.set_send_buffered(client, true) {
+                                clients_with_pending_sends.push(client);
+                            }
+                        });
+                    }
                 }
                 REQ_TYPE_CLOSE => {
                     let fd = restore_fd(&entry);
                     debug!("Handling close completion for client {fd}.");
-                    result.map_io_err(|| format!("Failed to close client {fd}."))?;
-                    info!("Client {fd} disconnected.");
+                    result.map_io_err(|| Context::Client {
+                        message: "Failed to close client",
+                        client: fd,
+                    })?;
+                    let Activity {
+                        requests,
+                        bytes_written,
+                        disconnect,
+                    } = mem::take(&mut activity[usize::from(fd)]);
+                    info!(
+                        "Client {fd} disconnected ({}) after {requests} request(s) and \
+                         {bytes_written} byte(s) written.",
+                        disconnect.map_or_else(|| "closed by the server".into(), |d| d.to_string())
+                    );
 
                     clients.set_closed(fd);
+                    origins[usize::from(fd)] = None;
                     if let Some(bufs) = mem::take(&mut client_buffers[usize::from(fd)]) {
                         bufs.unregister(&uring.submitter())
                             .map_io_err(|| "Failed to unregister buffer ring with io_uring.")?;
                     }
 
-                    if pending_accept && clients.pending_closes == 0 {
+                    if pending_accept {
                         info!("Restoring ability to accept new clients.");
                         unsafe { submissions.push(&accept) }?;
                         pending_accept = false;
                     }
                 }
+                REQ_TYPE_REJECT => {
+                    let fd = restore_fd(&entry);
+                    // Successful sends are skipped, so this is always a failure. The client is
+                    // being closed regardless, so there's nothing else to do.
+                    if let Err(e) = result {
+                        debug!("Failed to tell client {fd} it was rate limited: {e}");
+                    }
+                }
+                REQ_TYPE_SHUTDOWN => {
+                    let fd = restore_fd(&entry);
+                    // Like rejections, only failures are reported and the close follows anyway.
+                    if let Err(e) = result {
+                        debug!("Failed to shut down client {fd}: {e}");
+                    }
+                }
                 REQ_TYPE_READ_SIGNALS => {
                     debug!("Handling read_signals completion.");
                     let result = result.map_io_err(|| "Failed to poll for signals.")?;
@@ -524,7 +1077,22 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
                         });
                     }
 
-                    break 'outer;
+                    let mut shutdown = false;
+                    drain_signals(&signal_handler, |signal| {
+                        if signal == u32::try_from(libc::SIGHUP).unwrap() {
+                            info!("Reloading settings on SIGHUP.");
+                            reload_settings(&mut settings, allocator, &mut limiter, &mut send_bufs);
+                            true
+                        } else {
+                            info!("Shutting down on signal {signal}.");
+                            shutdown = true;
+                            false
+                        }
+                    })?;
+                    if shutdown {
+                        break 'outer;
+                    }
+                    unsafe { submissions.push(&read_signals) }?;
                 }
                 REQ_TYPE_LOW_MEM => {
                     debug!("Handling low memory completion.");
@@ -539,7 +1107,12 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
                             context: "Error polling for low memory events".into(),
                         });
                     } else if (result & u32::try_from(libc::POLLPRI).unwrap()) != 0 {
-                        send_bufs.trim();
+                        counters.memory_pressure_events += 1;
+                        info!(
+                            "Trimming memory under pressure ({} events so far).",
+                            counters.memory_pressure_events
+                        );
+                        pending_trim = true;
                     } else {
                         return Err(CliError::Internal {
                             context: format!("Unknown low memory poll event received: {result}")
@@ -547,10 +1120,95 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
                         });
                     }
                 }
+                REQ_TYPE_EXPIRE => {
+                    debug!("Handling sensitive entry expiry completion.");
+                    match result {
+                        Err(e) if e.raw_os_error() == Some(Errno::TIME.raw_os_error()) => {}
+                        r => {
+                            r.map_io_err(|| "Failed to wait for sensitive entries to expire.")?;
+                        }
+                    }
+                    pending_expiry = false;
+                    allocator.expire_sensitive()?;
+                    allocator.publish_changes();
+                }
+                REQ_TYPE_AGE_CHECK => {
+                    debug!("Handling maximum entry age check completion.");
+                    match result {
+                        Err(e) if e.raw_os_error() == Some(Errno::TIME.raw_os_error()) => {}
+                        r => {
+                            r.map_io_err(|| "Failed to wait for entries to age out.")?;
+                        }
+                    }
+                    pending_age_check = false;
+                    allocator.expire_old()?;
+                    allocator.publish_changes();
+                }
+                REQ_TYPE_SEARCH => {
+                    debug!("Handling search completion.");
+                    let result = result.map_io_err(|| "Failed to poll for search results.")?;
+                    if (result & u32::try_from(libc::POLLIN).unwrap()) == 0 {
+                        return Err(CliError::Internal {
+                            context: format!("Unknown search poll event received: {result}").into(),
+                        });
+                    }
+
+                    if !more(entry.flags()) {
+                        unsafe { submissions.push(&poll_searches) }?;
+                    }
+                    searches.clear_wakeup()?;
+                    searches.poll(&mut send_bufs, |client| {
+                        if !clients.set_send_buffered(client, true) {
+                            clients_with_pending_sends.push(client);
+                        }
+                    });
+                }
                 _ => unreachable!(),
             }
         }
 
+        // Completions are only posted while waiting, so no receive can be using an idle
+        // client's buffers once every completion has been handled.
+        if pending_trim && deferred_completion.is_none() {
+            pending_trim = false;
+            release_idle_buffers(
+                &clients,
+                &mut client_buffers,
+                &send_bufs,
+                &searches,
+                Instant::now(),
+                &uring.submitter(),
+            )?;
+            let _ = requests::trim_memory(&mut send_bufs, allocator);
+        }
+
+        // Only the next sensitive entry to expire is waited for since the expiry
+        // check finds the one after it.
+        if !pending_expiry && submissions.capacity() > submissions.len() {
+            if let Some(delay) = allocator.until_next_expiry() {
+                trace!("Checking for expired sensitive entries in {delay:?}.");
+                expiry_timeout = Timespec::from(delay);
+                let timeout = Timeout::new(&expiry_timeout)
+                    .build()
+                    .user_data(REQ_TYPE_EXPIRE);
+                unsafe { submissions.push(&timeout) }?;
+                pending_expiry = true;
+            }
+        }
+        // Kept apart from the sensitive entry timer so that a distant age check
+        // doesn't hold up an expiry that comes due sooner.
+        if !pending_age_check && submissions.capacity() > submissions.len() {
+            if let Some(delay) = allocator.until_next_age_check() {
+                trace!("Checking for entries past the maximum age in {delay:?}.");
+                age_check_timeout = Timespec::from(delay);
+                let timeout = Timeout::new(&age_check_timeout)
+                    .build()
+                    .user_data(REQ_TYPE_AGE_CHECK);
+                unsafe { submissions.push(&timeout) }?;
+                pending_age_check = true;
+            }
+        }
+
         let mut remaining_sends = ArrayVec::<u8, { MAX_NUM_CLIENTS as usize }>::new_const();
         for (i, &client) in clients_with_pending_sends.iter().enumerate() {
             if !send_bufs.has_ready_block(client) {
@@ -577,3 +1235,36 @@ pub fn run(allocator: &mut Allocator) -> Result<(), CliError> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Clients, IDLE_CLIENT_TIMEOUT};
+
+    #[test]
+    fn only_quiet_clients_with_nothing_in_flight_are_idle() {
+        let start = Instant::now();
+        let mut clients = Clients::new(5, start);
+        for id in 0..5 {
+            clients.set_accepted(id, start);
+            clients.set_connected(id);
+        }
+        // Still sending.
+        clients.set_active(1, start + IDLE_CLIENT_TIMEOUT);
+        // Waiting on its responses.
+        clients.set_send_buffered(2, true);
+        clients.set_disconnecting(3);
+        let idle =
+            |clients: &Clients, now| clients.idle(now, IDLE_CLIENT_TIMEOUT).collect::<Vec<_>>();
+
+        assert!(idle(&clients, start).is_empty());
+        let later = start + IDLE_CLIENT_TIMEOUT;
+        assert_eq!(idle(&clients, later), [0, 4]);
+        assert_eq!(idle(&clients, later + Duration::from_secs(1)), [0, 4]);
+        assert_eq!(idle(&clients, later + IDLE_CLIENT_TIMEOUT), [0, 1, 4]);
+
+        clients.set_closed(4);
+        assert_eq!(idle(&clients, later), [0]);
+    }
+}
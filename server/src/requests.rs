@@ -1,109 +1,574 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    fs, iter, mem,
+    mem::offset_of,
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    ptr, str,
+    time::{Duration, UNIX_EPOCH},
+};
 
-use arrayvec::ArrayVec;
+use arrayvec::{ArrayString, ArrayVec};
 use log::{debug, info, warn};
 use ringboard_core::{
+    origins::Origin,
     protocol,
-    protocol::{AddResponse, MimeType, Request, RingKind},
+    protocol::{
+        BackupOutcome, BackupResponse, ErrorCode, GenerationResponse, Request, RequestFailed,
+        Response, ServerInfoResponse, StatsResponse, TraceId, TrimMemoryResponse, UnknownRequest,
+        MAX_BATCH_LEN,
+    },
     AsBytes,
 };
-use rustix::net::{AncillaryDrain, RecvAncillaryMessage};
+use rustix::{
+    net::{AncillaryDrain, RecvAncillaryMessage, UCred},
+    process::getuid,
+};
+use thiserror::Error;
 
 use crate::{
     allocator::Allocator,
+    backup::Backups,
+    search::Searches,
     send_msg_bufs::{PendingBufAllocation, SendMsgBufs},
     CliError,
 };
 
-pub fn connect(payload: &[u8], send_bufs: &mut SendMsgBufs) -> (bool, PendingBufAllocation) {
+/// The most file descriptors a single message carries: one per add in a
+/// batch.
+pub const MAX_REQUEST_FDS: usize = MAX_BATCH_LEN;
+
+/// The longest message a client can send, which is a full batch.
+pub const MAX_MESSAGE_LEN: usize = (MAX_BATCH_LEN + 1) * size_of::<Request>();
+
+pub type ReceivedFds = ArrayVec<OwnedFd, MAX_REQUEST_FDS>;
+
+/// A request that breaks the protocol. Clients are disconnected for these
+/// unless the violation [is recoverable](Self::is_recoverable).
+#[derive(Error, Debug)]
+pub enum ProtocolViolation {
+    #[error("sent more file descriptors than any request carries")]
+    TooManyFds,
+    #[error("expected {expected} file descriptors but received {received}")]
+    UnexpectedFds { expected: usize, received: usize },
+    #[error("sent a malformed batch of requests")]
+    InvalidBatch,
+    #[error("sent a message longer than any request")]
+    MessageTooLong,
+}
+
+impl ProtocolViolation {
+    /// Whether the request still arrived in one piece so the client can be
+    /// told what it did wrong instead of being disconnected.
+    pub const fn is_recoverable(&self) -> bool {
+        matches!(self, Self::TooManyFds | Self::UnexpectedFds { .. })
+    }
+}
+
+/// What the reactor keeps track of that is reported in [`StatsResponse`].
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Counters {
+    pub rejected_requests: u64,
+    pub memory_pressure_events: u64,
+    pub dropped_completions: u64,
+    pub rejected_connections: u64,
+    pub total_connections: u64,
+    pub connected_clients: u32,
+}
+
+/// Takes ownership of every file descriptor in the control data so that none
+/// leak into the server, even if the request turns out to be invalid. Also
+/// returns the sender's credentials if the kernel attached them.
+pub fn received_fds(
+    control_data: &mut [u8],
+) -> Result<(ReceivedFds, Option<UCred>), ProtocolViolation> {
+    let mut fds = ReceivedFds::new();
+    let mut sender = None;
+    let mut surplus = false;
+    for message in unsafe { AncillaryDrain::parse(control_data) } {
+        match message {
+            RecvAncillaryMessage::ScmRights(received) => {
+                for fd in received {
+                    // Surplus file descriptors are closed as they're dropped.
+                    surplus |= fds.try_push(fd).is_err();
+                }
+            }
+            RecvAncillaryMessage::ScmCredentials(credentials) => sender = Some(credentials),
+            _ => {}
+        }
+    }
+    if surplus {
+        Err(ProtocolViolation::TooManyFds)
+    } else {
+        Ok((fds, sender))
+    }
+}
+
+const fn expect_fds(fds: &ReceivedFds, expected: usize) -> Result<(), ProtocolViolation> {
+    if fds.len() == expected {
+        Ok(())
+    } else {
+        Err(ProtocolViolation::UnexpectedFds {
+            expected,
+            received: fds.len(),
+        })
+    }
+}
+
+/// Why a client's handshake was refused.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Refusal {
+    /// There's no protocol version both sides speak.
+    VersionMismatch,
+    /// The client runs as another user or its credentials are unknown.
+    ForeignUser,
+}
+
+/// Settles on the newest protocol version both sides speak, refusing clients
+/// that don't run as the server's user.
+///
+/// Clients start with the newest version they speak. Since
+/// [`protocol::RANGED_VERSION`], that's followed by how many kinds of requests
+/// they know and the oldest version they speak. Older clients only speak the
+/// version they start with.
+pub fn connect(
+    payload: &[u8],
+    fds: &ReceivedFds,
+    sender: Option<UCred>,
+    record_origins: bool,
+    send_bufs: &mut SendMsgBufs,
+) -> Result<(Result<u8, Refusal>, Option<Origin>, PendingBufAllocation), ProtocolViolation> {
     debug!("Establishing client/server protocol connection.");
-    let version = payload[0];
-    let valid = version == protocol::VERSION;
-    if !valid {
+    expect_fds(fds, 0)?;
+    // The socket is only reachable by the server's user, but bind mounts and
+    // inherited file descriptors can still hand it to someone else.
+    match sender {
+        Some(UCred { uid, .. }) if uid == getuid() => {}
+        sender => {
+            if let Some(UCred { uid, .. }) = sender {
+                warn!("Refusing client running as user {}.", uid.as_raw());
+            } else {
+                warn!("Refusing client with unknown credentials.");
+            }
+            let response = send_bufs.init_buf(|_| (), |buf| buf.push(protocol::PERMISSION_DENIED));
+            return Ok((Err(Refusal::ForeignUser), None, response));
+        }
+    }
+
+    let max_version = payload[0];
+    let ranged = max_version >= protocol::RANGED_VERSION;
+    let min_version = match payload.get(2) {
+        Some(&min_version) if ranged => min_version,
+        _ => max_version,
+    };
+    let version = max_version.min(protocol::VERSION);
+    let version = (version >= min_version.max(protocol::MIN_VERSION)).then_some(version);
+    if version.is_none() {
         warn!(
-            "Protocol version mismatch: expected {} but got {version}.",
+            "Protocol version mismatch: expected {}..={} but got {min_version}..={max_version}.",
+            protocol::MIN_VERSION,
             protocol::VERSION
         );
     }
 
+    // Clients that list the requests they know about get the same in return,
+    // while older clients only expect the version. Clients that sent a range
+    // also learn the newest version the server speaks.
+    let negotiates = payload.len() > 1;
     let response = send_bufs.init_buf(
         |_| (),
         |buf| {
-            buf.push(protocol::VERSION);
+            buf.push(version.unwrap_or(protocol::VERSION));
+            if negotiates {
+                buf.push(u8::try_from(Request::KINDS).unwrap());
+            }
+            if negotiates && ranged {
+                buf.push(protocol::VERSION);
+            }
         },
     );
 
-    (valid, response)
+    let name = payload.get(if ranged { 3 } else { 2 }..);
+    let origin = sender
+        .filter(|_| record_origins)
+        .and_then(|sender| origin(name, sender));
+    if let Some(origin) = origin {
+        debug!("Client identified as {origin}.");
+    }
+    Ok((version.ok_or(Refusal::VersionMismatch), origin, response))
+}
+
+/// Clients may name themselves at the end of the handshake. Others are named
+/// after their process.
+fn origin(name: Option<&[u8]>, UCred { pid, uid, gid: _ }: UCred) -> Option<Origin> {
+    let uid = uid.as_raw();
+    name.and_then(|name| str::from_utf8(name).ok())
+        .and_then(|name| Origin::new(name, uid))
+        .or_else(|| {
+            let comm = fs::read_to_string(format!("/proc/{}/comm", pid.as_raw_nonzero()));
+            comm.ok().and_then(|comm| Origin::new(&comm, uid))
+        })
 }
 
 pub fn handle(
     request_data: &[u8],
-    control_data: &mut [u8],
+    fds: ReceivedFds,
     send_bufs: &mut SendMsgBufs,
     allocator: &mut Allocator,
     sequence_number: &mut u64,
-) -> Result<Option<PendingBufAllocation>, CliError> {
+    counters: Counters,
+    backups: Option<&Backups>,
+    searches: &mut Searches,
+    client: u8,
+    token: u16,
+    origin: Option<Origin>,
+    version: u8,
+) -> Result<Result<Option<PendingBufAllocation>, ProtocolViolation>, CliError> {
     if request_data.len() < size_of::<Request>() {
         warn!("Dropping invalid request (too short).");
-        return Ok(None);
+        return Ok(Ok(None));
     }
-    let request = unsafe { &request_data.as_ptr().cast::<Request>().read_unaligned() };
+    let requests = match Request::decode(request_data, version).and_then(|request| {
+        if let Request::Batch { len } = request {
+            batch(&request_data[size_of::<Request>()..], len, version)
+        } else {
+            Ok(Ok(iter::once(request).collect()))
+        }
+    }) {
+        Ok(Ok(requests)) => requests,
+        Ok(Err(e)) => return Ok(Err(e)),
+        Err(unknown) => {
+            *sequence_number = sequence_number.wrapping_add(1);
+            let trace = TraceId::new(*sequence_number, client);
+            // Newer clients are told their request isn't supported rather than
+            // being disconnected so they can fall back to something older.
+            warn!("[{trace}] Rejecting {unknown}.");
+            let mut out = send_bufs.scratch();
+            write_reply(&mut out, *sequence_number, trace, &unknown);
+            return Ok(Ok(Some(reply(send_bufs, out, None))));
+        }
+    };
+    let adds = requests
+        .iter()
+        .filter(|request| matches!(request, Request::Add { .. }))
+        .count();
+    if let Err(e) = expect_fds(&fds, adds) {
+        return Ok(Err(e));
+    }
+
+    if let [
+        Request::Search {
+            query,
+            regex,
+            limit,
+        },
+    ] = requests[..]
+    {
+        *sequence_number = sequence_number.wrapping_add(1);
+        let trace = TraceId::new(*sequence_number, client);
+        info!("[{trace}] Processing request: {:?}", requests[0]);
+        // The responses are sent as the search finds matches.
+        searches.start(query, regex, limit, client, token, *sequence_number, trace)?;
+        return Ok(Ok(None));
+    }
+
+    let mut fds = fds.into_iter();
+    let mut out = send_bufs.scratch();
+    let mut reply_fd = None;
+    for request in &requests {
+        *sequence_number = sequence_number.wrapping_add(1);
+        let trace = TraceId::new(*sequence_number, client);
+        info!("[{trace}] Processing request: {request:?}");
+
+        let processed = process(
+            request,
+            &mut fds,
+            &mut out,
+            send_bufs,
+            allocator,
+            *sequence_number,
+            trace,
+            counters,
+            backups,
+            origin,
+        )?;
+        // Failed requests may have gotten partway.
+        allocator.publish_changes();
+        match processed {
+            Ok(fd) => reply_fd = fd,
+            Err(failed) => {
+                // The rest of the batch is skipped, and the responses to the
+                // requests that went through are dropped with it.
+                warn!("[{trace}] Request failed: {failed}");
+                out.clear();
+                write_reply(&mut out, *sequence_number, trace, &failed);
+                out.truncate(RequestFailed::LEN);
+                return Ok(Ok(Some(reply(send_bufs, out, None))));
+            }
+        }
+    }
+    Ok(Ok(Some(reply(send_bufs, out, reply_fd))))
+}
 
+/// Decodes the requests following a [`Request::Batch`] header, rejecting
+/// batches that are malformed or carry requests batches can't.
+fn batch(
+    data: &[u8],
+    len: u8,
+    version: u8,
+) -> Result<Result<ArrayVec<Request, MAX_BATCH_LEN>, ProtocolViolation>, UnknownRequest> {
+    let len = usize::from(len);
+    if !(1..=MAX_BATCH_LEN).contains(&len) || data.len() < len * size_of::<Request>() {
+        return Ok(Err(ProtocolViolation::InvalidBatch));
+    }
+    let mut requests = ArrayVec::new();
+    for request in data.chunks_exact(size_of::<Request>()).take(len) {
+        let request = Request::decode(request, version)?;
+        if matches!(
+            request,
+            Request::Read { .. } | Request::Batch { .. } | Request::Search { .. }
+        ) {
+            return Ok(Err(ProtocolViolation::InvalidBatch));
+        }
+        requests.push(request);
+    }
+    Ok(Ok(requests))
+}
+
+/// Handles a single request, laying out its response in `out`. Returns the
+/// file descriptor to attach to the response, if any, or why the request
+/// failed.
+fn process(
+    request: &Request,
+    fds: &mut impl Iterator<Item = OwnedFd>,
+    out: &mut Vec<u8>,
+    send_bufs: &mut SendMsgBufs,
+    allocator: &mut Allocator,
+    sequence_number: u64,
+    trace: TraceId,
+    counters: Counters,
+    backups: Option<&Backups>,
+    origin: Option<Origin>,
+) -> Result<Result<Option<OwnedFd>, RequestFailed>, CliError> {
     macro_rules! reply {
-        ($response:expr) => {{ Ok(Some(reply(send_bufs, *sequence_number, $response))) }};
+        ($response:expr) => {{
+            write_reply(out, sequence_number, trace, &$response);
+            Ok(Ok(None))
+        }};
+    }
+    // Failing to update the database only fails the request at hand.
+    macro_rules! try_reply {
+        ($response:expr) => {
+            match $response {
+                Ok(Ok(response)) => reply!(response),
+                Ok(Err(failed)) => Ok(Err(failed)),
+                Err(e) => recoverable(ErrorCode::Io, e).map(Err),
+            }
+        };
     }
 
-    info!("Processing request: {request:?}");
-    *sequence_number = sequence_number.wrapping_add(1);
     match *request {
-        Request::Add { to, ref mime_type } => {
-            reply!(add(control_data, allocator, to, mime_type)?)
+        Request::Add {
+            to,
+            sensitive,
+            ref mime_type,
+            copied_at,
+        } => {
+            let copied_at = (copied_at > 0).then(|| UNIX_EPOCH + Duration::from_secs(copied_at));
+            let expires_at = sensitive.then(|| allocator.sensitive_expiry());
+            try_reply!(allocator.add(
+                fds.next().unwrap(),
+                to,
+                mime_type,
+                origin,
+                copied_at,
+                expires_at
+            ))
         }
-        Request::MoveToFront { id, to } => {
-            reply!([allocator.move_to_front(id, to)?])
+        Request::MoveToFront { id, to } => try_reply!(allocator.move_to_front(id, to).map(Ok)),
+        Request::Swap { id1, id2 } => try_reply!(allocator.swap(id1, id2).map(Ok)),
+        Request::Remove { id } => try_reply!(allocator.remove(id).map(Ok)),
+        Request::GarbageCollect { max_wasted_bytes } => reply!(allocator.gc(max_wasted_bytes)?),
+        Request::Read { id, offset, len } => {
+            // Expired entries aren't handed out while waiting for the timer.
+            allocator.expire_sensitive()?;
+            let (response, fd) = allocator.read(id, offset, len)?;
+            write_reply(out, sequence_number, trace, &response);
+            Ok(Ok(fd))
         }
-        Request::Swap { id1, id2 } => reply!([allocator.swap(id1, id2)?]),
-        Request::Remove { id } => reply!([allocator.remove(id)?]),
-        Request::GarbageCollect { max_wasted_bytes } => {
-            reply!([allocator.gc(max_wasted_bytes)?])
+        Request::List { ring, start, count } => reply!(allocator.list(ring, start, count)),
+        Request::Stats => reply!(StatsResponse {
+            rejected_requests: counters.rejected_requests,
+            memory_pressure_events: counters.memory_pressure_events,
+            dropped_completions: counters.dropped_completions,
+            rejected_connections: counters.rejected_connections,
+            total_connections: counters.total_connections,
+            connected_clients: counters.connected_clients,
+            backups: backups.map(Backups::stats).unwrap_or_default(),
+            ..allocator.stats()?
+        }),
+        Request::ServerInfo => reply!(server_info()),
+        Request::TrimMemory => reply!(trim_memory(send_bufs, allocator)),
+        Request::SetClipboard { id } => reply!(allocator.set_clipboard(id)),
+        Request::SetLabel { id, ref label } => reply!(allocator.set_label(id, label)?),
+        Request::AddTag { id, ref tag } => reply!(allocator.add_tag(id, tag)?),
+        Request::RemoveTag { id, ref tag } => reply!(allocator.remove_tag(id, tag)?),
+        Request::ListTags { id } => reply!(allocator.list_tags(id)?),
+        Request::Restore { tombstone_id } => try_reply!(allocator.restore(tombstone_id)),
+        Request::EmptyTrash => reply!(allocator.empty_trash()),
+        Request::Generation => reply!(GenerationResponse {
+            generation: allocator.generation(),
+        }),
+        Request::BackupNow => reply!(backups.map_or(
+            BackupResponse {
+                outcome: BackupOutcome::NotConfigured,
+                last_backup: 0,
+            },
+            Backups::start
+        )),
+        Request::Batch { .. } => unreachable!("batches are unpacked before processing"),
+        Request::Search { .. } => unreachable!("searches are started before processing"),
+    }
+}
+
+/// Turns I/O errors into a reply for the client, leaving everything else to
+/// bring down the server.
+pub fn recoverable(code: ErrorCode, e: CliError) -> Result<RequestFailed, CliError> {
+    match e {
+        CliError::Core(ringboard_core::Error::Io { error, context }) => {
+            Ok(RequestFailed::new(code, &format!("{context} {error}")))
         }
+        e => Err(e),
     }
 }
 
-fn reply<R: AsBytes + Debug>(
+/// Answers a request that broke the protocol in a recoverable way. The file
+/// descriptors it carried have already been closed.
+pub fn reject(
+    violation: &ProtocolViolation,
+    send_bufs: &mut SendMsgBufs,
+    sequence_number: &mut u64,
+    client: u8,
+) -> PendingBufAllocation {
+    *sequence_number = sequence_number.wrapping_add(1);
+    let trace = TraceId::new(*sequence_number, client);
+    warn!("[{trace}] Rejecting request: {violation}");
+    let failed = RequestFailed::new(ErrorCode::ProtocolViolation, &violation.to_string());
+    let mut out = send_bufs.scratch();
+    write_reply(&mut out, *sequence_number, trace, &failed);
+    out.truncate(RequestFailed::LEN);
+    reply(send_bufs, out, None)
+}
+
+/// Releases memory the server can do without, e.g. because the system is
+/// running low on it.
+pub fn trim_memory(send_bufs: &mut SendMsgBufs, allocator: &mut Allocator) -> TrimMemoryResponse {
+    let resident_bytes_before = resident_bytes();
+    send_bufs.trim();
+    allocator.trim_memory();
+    let resident_bytes_after = resident_bytes();
+
+    info!("Trimmed memory: {resident_bytes_before} -> {resident_bytes_after} resident bytes.");
+    TrimMemoryResponse {
+        resident_bytes_before,
+        resident_bytes_after,
+    }
+}
+
+/// Estimates the server's resident set size or returns zero if it's unknown.
+fn resident_bytes() -> u64 {
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rss| rss.trim().strip_suffix(" kB")?.trim().parse::<u64>().ok())
+        .map_or(0, |kib| kib * 1024)
+}
+
+fn server_info() -> ServerInfoResponse {
+    ServerInfoResponse {
+        version: ArrayString::from(env!("CARGO_PKG_VERSION")).unwrap(),
+        git_hash: ArrayString::from(env!("RINGBOARD_GIT_HASH")).unwrap_or_default(),
+    }
+}
+
+/// Lays out one of the responses to a request that's answered over several
+/// messages.
+pub fn streamed_reply<R: AsBytes + Debug>(
     send_bufs: &mut SendMsgBufs,
     sequence_number: u64,
-    responses: impl IntoIterator<Item = R, IntoIter: ExactSizeIterator<Item = R>>,
+    trace: TraceId,
+    response: &R,
 ) -> PendingBufAllocation {
-    send_bufs.init_buf(
-        |_| (),
-        |buf| {
-            let responses = responses.into_iter();
-            debug_assert_eq!(responses.len(), 1);
-            for response in responses {
-                info!("Replying: {sequence_number}@{response:?}");
-                buf.extend_from_slice(&sequence_number.to_ne_bytes());
-                buf.extend_from_slice(response.as_bytes());
-            }
-        },
-    )
+    let mut out = send_bufs.scratch();
+    write_reply(&mut out, sequence_number, trace, response);
+    reply(send_bufs, out, None)
 }
 
-fn add(
-    control_data: &mut [u8],
-    allocator: &mut Allocator,
-    kind: RingKind,
-    mime_type: &MimeType,
-) -> Result<impl ExactSizeIterator<Item = AddResponse>, CliError> {
-    let mut responses = ArrayVec::<_, 1>::new();
+fn write_reply<R: AsBytes + Debug>(
+    out: &mut Vec<u8>,
+    sequence_number: u64,
+    trace: TraceId,
+    response: &R,
+) {
+    info!("[{trace}] Replying: {sequence_number}@{response:?}");
+    write_response(out, sequence_number, trace, response);
+}
 
-    for message in unsafe { AncillaryDrain::parse(control_data) } {
-        if let RecvAncillaryMessage::ScmRights(received_fds) = message {
-            for fd in received_fds {
-                responses.push(allocator.add(fd, kind, mime_type)?);
+/// Lays out a [`Response`] including its padding.
+fn write_response<R: AsBytes>(
+    buf: &mut Vec<u8>,
+    sequence_number: u64,
+    trace: TraceId,
+    response: &R,
+) {
+    let start = buf.len();
+    buf.extend_from_slice(&sequence_number.to_ne_bytes());
+    buf.extend_from_slice(trace.as_bytes());
+    buf.resize(start + offset_of!(Response<R>, value), 0);
+    buf.extend_from_slice(response.as_bytes());
+    buf.resize(start + size_of::<Response<R>>(), 0);
+}
+
+/// Sends the responses laid out in `out` as a single message, attaching `fd`
+/// if there is one.
+fn reply(send_bufs: &mut SendMsgBufs, out: Vec<u8>, fd: Option<OwnedFd>) -> PendingBufAllocation {
+    let (buf, hdr, _) = send_bufs.init_buf(
+        |buf| {
+            let Some(fd) = &fd else {
+                return;
+            };
+            let fd_len = u32::try_from(size_of::<RawFd>()).unwrap();
+            let (len, space) = unsafe { (libc::CMSG_LEN(fd_len), libc::CMSG_SPACE(fd_len)) };
+            buf.resize(usize::try_from(space).unwrap(), 0);
+
+            #[allow(clippy::useless_conversion)]
+            let cmsg = {
+                let mut cmsg = unsafe { mem::zeroed::<libc::cmsghdr>() };
+                cmsg.cmsg_len = len.try_into().unwrap();
+                cmsg.cmsg_level = libc::SOL_SOCKET;
+                cmsg.cmsg_type = libc::SCM_RIGHTS;
+                cmsg
+            };
+            let raw_fd = fd.as_raw_fd();
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    ptr::from_ref(&cmsg).cast(),
+                    buf.as_mut_ptr(),
+                    size_of::<libc::cmsghdr>(),
+                );
+                ptr::copy_nonoverlapping(
+                    ptr::from_ref(&raw_fd).cast(),
+                    buf.as_mut_ptr()
+                        .add(usize::try_from(libc::CMSG_LEN(0)).unwrap()),
+                    size_of::<RawFd>(),
+                );
             }
-        }
+        },
+        |buf| buf.extend_from_slice(&out),
+    );
+    if let Some(fd) = &fd {
+        debug!("Attaching FD {fd:?} to the response.");
     }
-
-    Ok(responses.into_iter())
+    send_bufs.recycle(out);
+    (buf, hdr, fd)
 }
@@ -0,0 +1,268 @@
+use std::{
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    sync::{
+        mpsc,
+        mpsc::{Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use log::{debug, warn};
+use regex::bytes::Regex;
+use ringboard_core::{
+    dirs::data_dir,
+    protocol::{
+        SearchMatch, SearchQuery, SearchResponse, SearchStatus, TraceId, MAX_SEARCH_MATCHES,
+    },
+    IoErr,
+};
+use ringboard_sdk::{
+    search::{EntryIndex, Query},
+    DatabaseReader, EntryReader,
+};
+use rustix::{
+    event::{eventfd, EventfdFlags},
+    io::Errno,
+};
+
+use crate::{requests, send_msg_bufs::SendMsgBufs, CliError};
+
+/// Searches the database on background threads for clients that can't read it
+/// themselves.
+///
+/// A search keeps the receive buffer of the request that started it until its
+/// last response is sent, with each response reusing the buffer's send slot
+/// once the previous one went out. Search threads hand over one response at a
+/// time and signal the reactor through an eventfd, so a search never gets more
+/// than a response ahead of its client.
+pub struct Searches {
+    wakeup: Arc<OwnedFd>,
+    running: Vec<Search>,
+}
+
+struct Search {
+    client: u8,
+    token: u16,
+    sequence_number: u64,
+    trace: TraceId,
+    responses: Receiver<SearchResponse>,
+    /// A response is being sent, so the next one has to wait for its buffer.
+    sending: bool,
+    /// The last response was handed over, so the buffer is freed once it's
+    /// sent.
+    finished: bool,
+}
+
+impl Searches {
+    pub fn new() -> Result<Self, CliError> {
+        let wakeup = eventfd(0, EventfdFlags::NONBLOCK | EventfdFlags::CLOEXEC)
+            .map_io_err(|| "Failed to create search eventfd.")?;
+        Ok(Self {
+            wakeup: Arc::new(wakeup),
+            running: Vec::new(),
+        })
+    }
+
+    /// Becomes readable whenever a search has a response ready.
+    pub fn wakeup(&self) -> BorrowedFd<'_> {
+        self.wakeup.as_fd()
+    }
+
+    /// Starts searching for a client whose request arrived in the receive
+    /// buffer at `token`, which must be kept until [`Self::sent`] says
+    /// otherwise.
+    pub fn start(
+        &mut self,
+        query: SearchQuery,
+        regex: bool,
+        limit: u32,
+        client: u8,
+        token: u16,
+        sequence_number: u64,
+        trace: TraceId,
+    ) -> Result<(), CliError> {
+        // One response waits in the channel while the next is being collected.
+        let (sender, responses) = mpsc::sync_channel(1);
+        let wakeup = self.wakeup.clone();
+        thread::Builder::new()
+            .name("search".into())
+            .spawn(move || {
+                let mut response = SearchResponse {
+                    status: SearchStatus::More,
+                    count: 0,
+                    matches: [SearchMatch::default(); MAX_SEARCH_MATCHES],
+                };
+                let send = |response: SearchResponse| {
+                    let delivered = sender.send(response).is_ok();
+                    if delivered {
+                        let _ = rustix::io::write(&*wakeup, &1u64.to_ne_bytes());
+                    }
+                    delivered
+                };
+
+                let status = search(&query, regex, limit, trace, |m| {
+                    response.matches[usize::try_from(response.count).unwrap()] = m;
+                    response.count += 1;
+                    if usize::try_from(response.count).unwrap() < MAX_SEARCH_MATCHES {
+                        return true;
+                    }
+                    response.count = 0;
+                    send(SearchResponse {
+                        count: u32::try_from(MAX_SEARCH_MATCHES).unwrap(),
+                        ..response
+                    })
+                });
+                send(SearchResponse { status, ..response });
+            })
+            .map_io_err(|| "Failed to spawn search thread.")?;
+
+        self.running.push(Search {
+            client,
+            token,
+            sequence_number,
+            trace,
+            responses,
+            sending: false,
+            finished: false,
+        });
+        Ok(())
+    }
+
+    /// Whether the receive buffer at `token` belongs to a search.
+    pub fn is_running(&self, client: u8, token: u16) -> bool {
+        self.position(client, token).is_some()
+    }
+
+    /// Whether any of the client's receive buffers belong to a search.
+    pub fn is_searching(&self, client: u8) -> bool {
+        self.running.iter().any(|search| search.client == client)
+    }
+
+    /// Resets the eventfd so it only becomes readable again once another
+    /// response is ready.
+    pub fn clear_wakeup(&self) -> Result<(), CliError> {
+        match rustix::io::read(&*self.wakeup, &mut [0; size_of::<u64>()]) {
+            Err(Errno::AGAIN) => {}
+            r => {
+                r.map_io_err(|| "Failed to read search eventfd.")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Queues the ready responses of searches whose send slot is free, calling
+    /// `queued` with each client that got one.
+    pub fn poll(&mut self, send_bufs: &mut SendMsgBufs, mut queued: impl FnMut(u8)) {
+        for search in &mut self.running {
+            if search.sending || search.finished {
+                continue;
+            }
+            let response = match search.responses.try_recv() {
+                Ok(response) => response,
+                Err(TryRecvError::Empty) => continue,
+                Err(TryRecvError::Disconnected) => SearchResponse {
+                    status: SearchStatus::Failed,
+                    count: 0,
+                    matches: [SearchMatch::default(); MAX_SEARCH_MATCHES],
+                },
+            };
+
+            search.sending = true;
+            search.finished = response.status != SearchStatus::More;
+            let reply = requests::streamed_reply(
+                send_bufs,
+                search.sequence_number,
+                search.trace,
+                &response,
+            );
+            send_bufs.alloc(search.client, search.token.into(), reply);
+            queued(search.client);
+        }
+    }
+
+    /// Notes that the response in the send slot at `token` went out, returning
+    /// whether its receive buffer still belongs to a search.
+    pub fn sent(&mut self, client: u8, token: u16) -> bool {
+        let Some(i) = self.position(client, token) else {
+            return false;
+        };
+        if self.running[i].finished {
+            self.running.swap_remove(i);
+            return false;
+        }
+        self.running[i].sending = false;
+        true
+    }
+
+    /// Stops the client's searches, whose threads exit the next time they have
+    /// something to send.
+    pub fn cancel(&mut self, client: u8) {
+        self.running.retain(|search| search.client != client);
+    }
+
+    fn position(&self, client: u8, token: u16) -> Option<usize> {
+        self.running
+            .iter()
+            .position(|search| search.client == client && search.token == token)
+    }
+}
+
+/// Runs a search with a fresh view of the database, stopping early once
+/// `found` returns `false`.
+fn search(
+    query: &SearchQuery,
+    regex: bool,
+    limit: u32,
+    trace: TraceId,
+    mut found: impl FnMut(SearchMatch) -> bool,
+) -> SearchStatus {
+    let query = if regex {
+        match Regex::new(query) {
+            Ok(regex) => Query::Regex(regex),
+            Err(e) => {
+                debug!("[{trace}] Invalid search regex: {e}");
+                return SearchStatus::InvalidRegex;
+            }
+        }
+    } else {
+        Query::Plain(query.as_bytes())
+    };
+
+    let mut dir = data_dir();
+    let (database, reader) = match DatabaseReader::open(&mut dir)
+        .and_then(|database| EntryReader::open(&mut dir).map(|reader| (database, reader)))
+    {
+        Ok(opened) => opened,
+        Err(e) => {
+            warn!("[{trace}] Failed to open database for searching: {e}");
+            return SearchStatus::Failed;
+        }
+    };
+    let index = Arc::new(EntryIndex::new(&database));
+    let (results, threads) = ringboard_sdk::search(query, None, None, Arc::new(reader), index);
+
+    let mut status = SearchStatus::Done;
+    for result in results.take(usize::try_from(limit).unwrap()) {
+        match result {
+            Ok(result) => {
+                let m = SearchMatch {
+                    id: result.id,
+                    start: u64::try_from(result.start).unwrap(),
+                    end: u64::try_from(result.end).unwrap(),
+                };
+                if !found(m) {
+                    break;
+                }
+            }
+            Err(e) => {
+                warn!("[{trace}] Search failed partway through: {e}");
+                status = SearchStatus::Failed;
+            }
+        }
+    }
+    for thread in threads {
+        let _ = thread.join();
+    }
+    status
+}
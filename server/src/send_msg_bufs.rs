@@ -1,31 +1,36 @@
-use std::{mem, mem::ManuallyDrop, ptr, ptr::NonNull};
+use std::{iter, mem, mem::ManuallyDrop, os::fd::OwnedFd, ptr, ptr::NonNull};
 
 use arrayvec::ArrayVec;
 use log::trace;
 use smallvec::SmallVec;
 
-use crate::reactor::{MAX_NUM_BUFS_PER_CLIENT, MAX_NUM_CLIENTS};
+use crate::reactor::MAX_NUM_BUFS_PER_CLIENT;
 
 pub struct SendMsgBufs {
-    bufs: [[Option<LengthlessVec>; MAX_NUM_BUFS_PER_CLIENT as usize]; MAX_NUM_CLIENTS as usize],
-    alloc_counts: [u8; MAX_NUM_CLIENTS as usize],
-    pending_bufs: [ArrayVec<SendBufAllocation, { MAX_NUM_BUFS_PER_CLIENT as usize }>;
-        MAX_NUM_CLIENTS as usize],
+    clients: Box<[ClientBufs]>,
     pool: SmallVec<LengthlessVec, 4>,
 }
 
-pub type PendingBufAllocation = (Vec<u8>, *const libc::msghdr);
+#[derive(Default)]
+struct ClientBufs {
+    bufs: [Option<LengthlessVec>; MAX_NUM_BUFS_PER_CLIENT as usize],
+    // File descriptors sent via SCM_RIGHTS must stay open until the send completes.
+    fds: [Option<OwnedFd>; MAX_NUM_BUFS_PER_CLIENT as usize],
+    alloc_count: u8,
+    pending_bufs: ArrayVec<SendBufAllocation, { MAX_NUM_BUFS_PER_CLIENT as usize }>,
+}
+
+pub type PendingBufAllocation = (Vec<u8>, *const libc::msghdr, Option<OwnedFd>);
 pub type SendBufAllocation = (u8, *const libc::msghdr);
 
 impl SendMsgBufs {
     const TOKEN_MASK: u8 = MAX_NUM_BUFS_PER_CLIENT - 1;
 
-    pub const fn new() -> Self {
+    pub fn new(max_clients: u16) -> Self {
         Self {
-            bufs: [const { [const { None }; MAX_NUM_BUFS_PER_CLIENT as usize] };
-                MAX_NUM_CLIENTS as usize],
-            alloc_counts: [0; MAX_NUM_CLIENTS as usize],
-            pending_bufs: [const { ArrayVec::new_const() }; MAX_NUM_CLIENTS as usize],
+            clients: iter::repeat_with(ClientBufs::default)
+                .take(max_clients.into())
+                .collect(),
             pool: SmallVec::new(),
         }
     }
@@ -35,21 +40,21 @@ impl SendMsgBufs {
         client: u8,
         max: usize,
     ) -> impl ExactSizeIterator<Item = SendBufAllocation> + '_ {
-        let pending = &mut self.pending_bufs[usize::from(client)];
+        let pending = &mut self.clients[usize::from(client)].pending_bufs;
         pending.drain(..max.min(pending.len()))
     }
 
     pub fn has_pending_sends(&self, client: u8) -> bool {
-        !self.pending_bufs[usize::from(client)].is_empty()
+        !self.clients[usize::from(client)].pending_bufs.is_empty()
     }
 
     pub fn has_ready_block(&self, client: u8) -> bool {
-        let client = usize::from(client);
-        self.pending_bufs[client].len() == self.alloc_counts[client].into()
+        let client = &self.clients[usize::from(client)];
+        client.pending_bufs.len() == usize::from(client.alloc_count)
     }
 
     pub fn has_outstanding_sends(&self, client: u8) -> bool {
-        self.alloc_counts[usize::from(client)] > 0
+        self.clients[usize::from(client)].alloc_count > 0
     }
 
     pub fn init_buf<Control: FnOnce(&mut Vec<u8>), Data: FnOnce(&mut Vec<u8>)>(
@@ -57,11 +62,7 @@ impl SendMsgBufs {
         control: Control,
         data: Data,
     ) -> PendingBufAllocation {
-        let mut buf = self
-            .pool
-            .pop()
-            .map(LengthlessVec::into_vec)
-            .unwrap_or_default();
+        let mut buf = self.scratch();
 
         control(&mut buf);
         let control_len = buf.len();
@@ -119,27 +120,45 @@ impl SendMsgBufs {
             ptr
         };
 
-        (buf, ptr.cast())
+        (buf, ptr.cast(), None)
+    }
+
+    /// An empty buffer from the pool for data that has to be laid out before
+    /// its message, to be handed back with [`Self::recycle`].
+    pub fn scratch(&mut self) -> Vec<u8> {
+        self.pool
+            .pop()
+            .map(LengthlessVec::into_vec)
+            .unwrap_or_default()
+    }
+
+    pub fn recycle(&mut self, mut buf: Vec<u8>) {
+        buf.clear();
+        self.pool.push(buf.into());
     }
 
-    pub fn alloc(&mut self, client: u8, token: u64, (buf, ptr): PendingBufAllocation) {
-        let client = usize::from(client);
+    pub fn alloc(&mut self, client: u8, token: u64, (buf, ptr, fd): PendingBufAllocation) {
         let token = usize::try_from(token & u64::from(Self::TOKEN_MASK)).unwrap();
         trace!("Allocating send buffer {token} for client {client}.");
 
-        debug_assert!(self.bufs[client][token].is_none());
-        self.bufs[client][token] = Some(buf.into());
-        self.alloc_counts[client] += 1;
-        self.pending_bufs[client].push((u8::try_from(token).unwrap(), ptr));
+        let client = &mut self.clients[usize::from(client)];
+        debug_assert!(client.bufs[token].is_none());
+        client.bufs[token] = Some(buf.into());
+        client.fds[token] = fd;
+        client.alloc_count += 1;
+        client
+            .pending_bufs
+            .push((u8::try_from(token).unwrap(), ptr));
     }
 
     pub unsafe fn free(&mut self, client: u8, token: u64) {
-        let client = usize::from(client);
         let token = usize::try_from(token & u64::from(Self::TOKEN_MASK)).unwrap();
         trace!("Freeing send buffer {token} for client {client}.");
 
-        self.alloc_counts[client] -= 1;
-        let v = self.bufs[client][token].take().unwrap();
+        let client = &mut self.clients[usize::from(client)];
+        client.alloc_count -= 1;
+        let v = client.bufs[token].take().unwrap();
+        client.fds[token] = None;
         self.pool.push(v);
     }
 
@@ -191,8 +210,8 @@ mod tests {
 
     #[test]
     fn fill() {
-        let mut bufs = SendMsgBufs::new();
-        for client in 0..MAX_NUM_CLIENTS {
+        let mut bufs = SendMsgBufs::new(MAX_NUM_CLIENTS);
+        for client in 0..=u8::MAX {
             for i in 0..MAX_NUM_BUFS_PER_CLIENT {
                 let pending = bufs.init_buf(
                     |control| control.extend(1..=69),
@@ -205,7 +224,7 @@ mod tests {
 
     #[test]
     fn free_random() {
-        let mut bufs = SendMsgBufs::new();
+        let mut bufs = SendMsgBufs::new(1);
 
         for i in 0..3 {
             let pending = bufs.init_buf(
@@ -223,7 +242,7 @@ mod tests {
 
     #[test]
     fn stress() {
-        let mut bufs = SendMsgBufs::new();
+        let mut bufs = SendMsgBufs::new(1);
         for control_len in 0..50 {
             for data_len in 0..50 {
                 let control_data = 0..control_len;
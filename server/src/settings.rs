@@ -0,0 +1,607 @@
+use std::{fs, io, io::ErrorKind, path::Path};
+
+use log::{info, warn};
+use ringboard_core::{
+    protocol::RingKind,
+    settings::{EntryLimits, RingCapacities, SensitiveEntries, FILE_NAME},
+    IoErr,
+};
+
+use crate::{reactor::MAX_NUM_CLIENTS, CliError};
+
+/// Server settings read from the `settings` file in the data directory.
+///
+/// The file contains `key = value` lines; blank lines and lines starting with
+/// `#` are ignored.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Settings {
+    /// Whether bucket files reserve disk space ahead of need, set with
+    /// `preallocate`, and in chunks of how many bytes, set with
+    /// `preallocation_chunk_size`.
+    pub preallocation: Preallocation,
+    /// Recognize text in image entries so they show up in text searches.
+    /// Requires the `ocr` feature and `tesseract` to be installed.
+    pub ocr: bool,
+    /// Accept entry data from pipes and sockets in addition to regular files.
+    /// Their data must arrive promptly or the entry is rejected.
+    pub accept_pipes: bool,
+    /// The largest entry in bytes that will be stored, set with
+    /// `max_entry_size`, and whether larger ones are truncated to fit instead
+    /// of rejected, set with `truncate_oversized_entries`.
+    pub entry_limits: EntryLimits,
+    /// How many new connections per second are accepted once the burst
+    /// allowance is used up, which keeps clients stuck in a reconnect loop
+    /// from monopolizing the server.
+    pub max_connections_per_second: u32,
+    /// How many connections may arrive back to back before rate limiting
+    /// kicks in.
+    pub connection_burst: u32,
+    /// How many clients may be connected at once, up to 256. Further
+    /// connections wait until a client disconnects.
+    pub max_clients: u16,
+    /// How text entries are cleaned up before they're stored, set with
+    /// `trim_single_line_text` and `strip_query_params`.
+    pub normalization: Normalization,
+    /// Which text entries are compressed, set with `compress_text_above`, and
+    /// whether short ones are packed together, set with `pack_small_entries`.
+    pub text_storage: TextStorage,
+    /// How long entries that password managers mark as sensitive are kept,
+    /// set in seconds with `sensitive_entry_ttl`, and whether the clipboard
+    /// watchers add them at all, set with `skip_sensitive_entries`.
+    pub sensitive_entries: SensitiveEntries,
+    /// Remember which client added each entry. When disabled, previously
+    /// recorded origins are deleted.
+    pub record_origins: bool,
+    /// Where to back up the database to, if anywhere. Relative paths are
+    /// resolved against the data directory.
+    pub backup_dir: Option<Box<Path>>,
+    /// How many seconds to wait between backups.
+    pub backup_interval: u64,
+    /// How many backups to keep before the oldest ones are deleted.
+    pub backups_kept: u32,
+    /// Whether a client repeating its previous add is answered with the
+    /// existing entry, set with `coalesce_adds`, and within how many
+    /// milliseconds, set with `coalesce_window`.
+    pub coalescing: Coalescing,
+    /// Whether adding recent data again moves its entry to the front, set
+    /// with `dedupe_adds`, and how many entries are remembered for it, set
+    /// with `dedupe_recent_entries`.
+    pub deduplication: Deduplication,
+    /// How many entries the main and favorites rings hold, set with
+    /// `main_max_entries` and `favorites_max_entries`. Rings can grow but
+    /// never shrink below the entries they already hold.
+    pub ring_capacities: RingCapacities,
+    /// Remove main ring entries once they are this many seconds old, whether
+    /// or not the ring has wrapped around to them. Favorites are exempt.
+    pub main_max_age: Option<u64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            preallocation: Preallocation::default(),
+            ocr: false,
+            accept_pipes: false,
+            entry_limits: EntryLimits::default(),
+            max_connections_per_second: 200,
+            connection_burst: 1000,
+            max_clients: 128,
+            normalization: Normalization::default(),
+            text_storage: TextStorage::default(),
+            sensitive_entries: SensitiveEntries::default(),
+            record_origins: true,
+            backup_dir: None,
+            backup_interval: 24 * 60 * 60,
+            backups_kept: 7,
+            coalescing: Coalescing::default(),
+            deduplication: Deduplication::default(),
+            ring_capacities: RingCapacities::default(),
+            main_max_age: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Result<Self, CliError> {
+        let settings = match fs::read_to_string(FILE_NAME) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            r => {
+                let contents = r.map_io_err(|| "Failed to read settings file.")?;
+                Self::parse(&contents).map_err(|context| ringboard_core::Error::Io {
+                    error: io::Error::new(ErrorKind::InvalidData, "Invalid settings file."),
+                    context: context.into(),
+                })?
+            }
+        };
+        info!("Effective settings: {settings:?}");
+        Ok(settings)
+    }
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        let mut settings = Self::default();
+        for (number, line) in (1..).zip(contents.lines()) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("Expected `key = value` on line {number}: {line:?}"));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = || format!("Invalid value for {key:?} on line {number}: {value:?}");
+            if let Some(valid) = settings
+                .ring_capacities
+                .parse_setting(key, value)
+                .or_else(|| settings.entry_limits.parse_setting(key, value))
+                .or_else(|| settings.sensitive_entries.parse_setting(key, value))
+                .or_else(|| settings.preallocation.parse_setting(key, value))
+                .or_else(|| settings.normalization.parse_setting(key, value))
+                .or_else(|| settings.text_storage.parse_setting(key, value))
+                .or_else(|| settings.coalescing.parse_setting(key, value))
+                .or_else(|| settings.deduplication.parse_setting(key, value))
+            {
+                if !valid {
+                    return Err(invalid());
+                }
+                continue;
+            }
+            match key {
+                "ocr" => settings.ocr = value.parse().map_err(|_| invalid())?,
+                "accept_pipes" => settings.accept_pipes = value.parse().map_err(|_| invalid())?,
+                "record_origins" => {
+                    settings.record_origins = value.parse().map_err(|_| invalid())?;
+                }
+                "main_max_age" => {
+                    settings.main_max_age = if value.is_empty() {
+                        None
+                    } else {
+                        Some(
+                            value
+                                .parse::<u64>()
+                                .ok()
+                                .filter(|&age| age > 0)
+                                .ok_or_else(invalid)?,
+                        )
+                    };
+                }
+                "backup_dir" => {
+                    settings.backup_dir = (!value.is_empty()).then(|| Path::new(value).into());
+                }
+                "backup_interval" => {
+                    settings.backup_interval = value
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&interval| interval > 0)
+                        .ok_or_else(invalid)?;
+                }
+                "backups_kept" => {
+                    settings.backups_kept = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&kept| kept > 0)
+                        .ok_or_else(invalid)?;
+                }
+                "max_connections_per_second" => {
+                    settings.max_connections_per_second = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&rate| rate > 0)
+                        .ok_or_else(invalid)?;
+                }
+                "connection_burst" => {
+                    settings.connection_burst = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&burst| burst > 0)
+                        .ok_or_else(invalid)?;
+                }
+                "max_clients" => {
+                    settings.max_clients = value
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|clients| (1..=MAX_NUM_CLIENTS).contains(clients))
+                        .ok_or_else(invalid)?;
+                }
+                _ => warn!("Ignoring unknown setting {key:?} on line {number}."),
+            }
+        }
+        Ok(settings)
+    }
+
+    /// The keys of settings that differ in `reloaded` but are only read when
+    /// the server starts, so reloading can't apply them.
+    pub fn startup_only_changes(&self, reloaded: &Self) -> Vec<&'static str> {
+        let Self {
+            preallocation: _,
+            ocr,
+            accept_pipes: _,
+            entry_limits: _,
+            max_connections_per_second: _,
+            connection_burst: _,
+            max_clients,
+            normalization: _,
+            text_storage,
+            sensitive_entries: _,
+            record_origins,
+            backup_dir,
+            backup_interval,
+            backups_kept,
+            coalescing: _,
+            deduplication: _,
+            ring_capacities,
+            main_max_age: _,
+        } = self;
+
+        let mut changes = Vec::new();
+        for (key, changed) in [
+            ("ocr", *ocr != reloaded.ocr),
+            ("max_clients", *max_clients != reloaded.max_clients),
+            (
+                "pack_small_entries",
+                text_storage.pack_small_entries != reloaded.text_storage.pack_small_entries,
+            ),
+            ("record_origins", *record_origins != reloaded.record_origins),
+            ("backup_dir", *backup_dir != reloaded.backup_dir),
+            (
+                "backup_interval",
+                *backup_interval != reloaded.backup_interval,
+            ),
+            ("backups_kept", *backups_kept != reloaded.backups_kept),
+        ] {
+            if changed {
+                changes.push(key);
+            }
+        }
+        // Clients map the rings at their configured capacity, so resizing them
+        // underneath running clients isn't safe.
+        for kind in [RingKind::Main, RingKind::Favorites] {
+            if ring_capacities.get(kind) != reloaded.ring_capacities.get(kind) {
+                changes.push(RingCapacities::key(kind));
+            }
+        }
+        changes
+    }
+}
+
+/// How bucket files reserve disk space ahead of need.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Preallocation {
+    /// Reserve disk space for bucket files ahead of need so they stay
+    /// contiguous on copy-on-write file systems.
+    pub preallocate: bool,
+    /// How many bytes to reserve at a time when preallocating.
+    pub preallocation_chunk_size: u64,
+}
+
+impl Default for Preallocation {
+    fn default() -> Self {
+        Self {
+            preallocate: true,
+            preallocation_chunk_size: 4 << 20,
+        }
+    }
+}
+
+impl Preallocation {
+    /// Applies `key = value` if it's a preallocation setting. Returns whether
+    /// the value was valid, or [`None`] for other settings.
+    fn parse_setting(&mut self, key: &str, value: &str) -> Option<bool> {
+        match key {
+            "preallocate" => {
+                let Ok(preallocate) = value.parse() else {
+                    return Some(false);
+                };
+                self.preallocate = preallocate;
+            }
+            "preallocation_chunk_size" => {
+                let Some(size) = value.parse::<u64>().ok().filter(|&size| size > 0) else {
+                    return Some(false);
+                };
+                self.preallocation_chunk_size = size;
+            }
+            _ => return None,
+        }
+        Some(true)
+    }
+}
+
+/// How text entries are cleaned up before they're stored.
+#[derive(Clone, Default, Eq, PartialEq, Debug)]
+pub struct Normalization {
+    /// Trim text entries that fit on one line and collapse whitespace inside
+    /// them before storing them.
+    pub trim_single_line_text: bool,
+    /// Query parameters to remove from URLs before storing them, e.g.
+    /// `utm_*, fbclid`. A trailing `*` matches any suffix.
+    pub strip_query_params: Box<[Box<str>]>,
+}
+
+impl Normalization {
+    /// Applies `key = value` if it's a normalization setting. Returns whether
+    /// the value was valid, or [`None`] for other settings.
+    fn parse_setting(&mut self, key: &str, value: &str) -> Option<bool> {
+        match key {
+            "trim_single_line_text" => {
+                let Ok(trim) = value.parse() else {
+                    return Some(false);
+                };
+                self.trim_single_line_text = trim;
+            }
+            "strip_query_params" => {
+                self.strip_query_params = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|param| !param.is_empty())
+                    .map(Box::from)
+                    .collect();
+            }
+            _ => return None,
+        }
+        Some(true)
+    }
+}
+
+/// How text entries are laid out on disk.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+pub struct TextStorage {
+    /// Compress text entries of at least this many bytes with zstd, unless
+    /// they barely shrink. Off by default because clients built before
+    /// compression was supported read compressed entries as raw zstd data.
+    pub compress_text_above: Option<u64>,
+    /// Pack short text entries back to back into a log instead of rounding
+    /// them up to a bucket slot. Only read when the database is created, since
+    /// existing databases keep the layout they were created with. Off by
+    /// default because clients built before packing was supported can't read
+    /// packed entries.
+    pub pack_small_entries: bool,
+}
+
+impl TextStorage {
+    /// Applies `key = value` if it's a text storage setting. Returns whether
+    /// the value was valid, or [`None`] for other settings.
+    fn parse_setting(&mut self, key: &str, value: &str) -> Option<bool> {
+        match key {
+            "compress_text_above" => {
+                self.compress_text_above = if value.is_empty() {
+                    None
+                } else {
+                    let Some(size) = value.parse::<u64>().ok().filter(|&size| size > 0) else {
+                        return Some(false);
+                    };
+                    Some(size)
+                };
+            }
+            "pack_small_entries" => {
+                let Ok(pack) = value.parse() else {
+                    return Some(false);
+                };
+                self.pack_small_entries = pack;
+            }
+            _ => return None,
+        }
+        Some(true)
+    }
+}
+
+/// How repeated adds from the same client are answered.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Coalescing {
+    /// Answer an add that repeats the same client's previous one within
+    /// `coalesce_window` with the existing entry instead of storing a copy.
+    pub coalesce_adds: bool,
+    /// How many milliseconds apart identical adds are coalesced.
+    pub coalesce_window: u64,
+}
+
+impl Default for Coalescing {
+    fn default() -> Self {
+        Self {
+            coalesce_adds: true,
+            coalesce_window: 200,
+        }
+    }
+}
+
+impl Coalescing {
+    /// Applies `key = value` if it's a coalescing setting. Returns whether the
+    /// value was valid, or [`None`] for other settings.
+    fn parse_setting(&mut self, key: &str, value: &str) -> Option<bool> {
+        match key {
+            "coalesce_adds" => {
+                let Ok(coalesce) = value.parse() else {
+                    return Some(false);
+                };
+                self.coalesce_adds = coalesce;
+            }
+            "coalesce_window" => {
+                let Some(window) = value.parse::<u64>().ok().filter(|&window| window > 0) else {
+                    return Some(false);
+                };
+                self.coalesce_window = window;
+            }
+            _ => return None,
+        }
+        Some(true)
+    }
+}
+
+/// How data that was recently added already is stored again.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Deduplication {
+    /// Move a recently added entry to the front when identical data is added
+    /// again instead of storing a copy.
+    pub dedupe_adds: bool,
+    /// How many of the most recently added entries new ones are compared
+    /// against.
+    pub dedupe_recent_entries: u32,
+}
+
+impl Default for Deduplication {
+    fn default() -> Self {
+        Self {
+            dedupe_adds: true,
+            dedupe_recent_entries: 128,
+        }
+    }
+}
+
+impl Deduplication {
+    /// Applies `key = value` if it's a deduplication setting. Returns whether
+    /// the value was valid, or [`None`] for other settings.
+    fn parse_setting(&mut self, key: &str, value: &str) -> Option<bool> {
+        match key {
+            "dedupe_adds" => {
+                let Ok(dedupe) = value.parse() else {
+                    return Some(false);
+                };
+                self.dedupe_adds = dedupe;
+            }
+            "dedupe_recent_entries" => {
+                let Some(entries) = value.parse::<u32>().ok().filter(|&entries| entries > 0) else {
+                    return Some(false);
+                };
+                self.dedupe_recent_entries = entries;
+            }
+            _ => return None,
+        }
+        Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ringboard_core::settings::{EntryLimits, RingCapacities, SensitiveEntries};
+
+    use super::{Coalescing, Deduplication, Normalization, Preallocation, Settings, TextStorage};
+
+    #[test]
+    fn parse() {
+        assert_eq!(Settings::parse(""), Ok(Settings::default()));
+        let contents = "\
+# Keep bucket files small.
+preallocate = false
+
+preallocation_chunk_size=4096
+ocr = true
+accept_pipes = true
+max_entry_size = 1000000
+truncate_oversized_entries = true
+max_connections_per_second = 10
+connection_burst = 20
+max_clients = 64
+trim_single_line_text = true
+strip_query_params = utm_*, fbclid,
+compress_text_above = 65536
+pack_small_entries = true
+skip_sensitive_entries = true
+sensitive_entry_ttl = 30
+record_origins = false
+backup_dir = /backups/ringboard
+backup_interval = 3600
+backups_kept = 3
+coalesce_adds = false
+coalesce_window = 50
+dedupe_adds = false
+dedupe_recent_entries = 16
+main_max_entries = 5000
+favorites_max_entries = 10
+main_max_age = 2592000
+";
+        assert_eq!(
+            Settings::parse(contents),
+            Ok(Settings {
+                preallocation: Preallocation {
+                    preallocate: false,
+                    preallocation_chunk_size: 4096,
+                },
+                ocr: true,
+                accept_pipes: true,
+                entry_limits: EntryLimits {
+                    max_entry_size: 1_000_000,
+                    truncate_oversized_entries: true,
+                },
+                max_connections_per_second: 10,
+                connection_burst: 20,
+                max_clients: 64,
+                normalization: Normalization {
+                    trim_single_line_text: true,
+                    strip_query_params: ["utm_*", "fbclid"].map(Box::from).into(),
+                },
+                text_storage: TextStorage {
+                    compress_text_above: Some(65536),
+                    pack_small_entries: true,
+                },
+                sensitive_entries: SensitiveEntries {
+                    skip_sensitive_entries: true,
+                    sensitive_entry_ttl: 30,
+                },
+                record_origins: false,
+                backup_dir: Some(Path::new("/backups/ringboard").into()),
+                backup_interval: 3600,
+                backups_kept: 3,
+                coalescing: Coalescing {
+                    coalesce_adds: false,
+                    coalesce_window: 50,
+                },
+                deduplication: Deduplication {
+                    dedupe_adds: false,
+                    dedupe_recent_entries: 16,
+                },
+                ring_capacities: RingCapacities {
+                    main: 5000,
+                    favorites: 10,
+                },
+                main_max_age: Some(2_592_000),
+            })
+        );
+        assert!(Settings::parse("preallocation_chunk_size = 0").is_err());
+        assert!(Settings::parse("max_connections_per_second = 0").is_err());
+        assert!(Settings::parse("max_entry_size = 0").is_err());
+        assert!(Settings::parse("max_clients = 0").is_err());
+        assert!(Settings::parse("max_clients = 257").is_err());
+        assert!(Settings::parse("backups_kept = 0").is_err());
+        assert!(Settings::parse("coalesce_window = 0").is_err());
+        assert!(Settings::parse("dedupe_recent_entries = 0").is_err());
+        assert!(Settings::parse("compress_text_above = 0").is_err());
+        assert!(Settings::parse("sensitive_entry_ttl = 0").is_err());
+        assert_eq!(
+            Settings::parse("compress_text_above =").map(|s| s.text_storage.compress_text_above),
+            Ok(None)
+        );
+        assert!(Settings::parse("main_max_entries = 0").is_err());
+        assert!(Settings::parse("main_max_age = 0").is_err());
+        assert_eq!(
+            Settings::parse("main_max_age =").map(|s| s.main_max_age),
+            Ok(None)
+        );
+        assert!(Settings::parse("preallocate").is_err());
+    }
+
+    #[test]
+    fn startup_only_changes() {
+        let settings = Settings::default();
+        let reloaded = Settings::parse(
+            "\
+dedupe_adds = false
+max_clients = 200
+pack_small_entries = true
+record_origins = false
+main_max_entries = 5000
+",
+        )
+        .unwrap();
+        assert_eq!(
+            settings.startup_only_changes(&reloaded),
+            [
+                "max_clients",
+                "pack_small_entries",
+                "record_origins",
+                "main_max_entries"
+            ]
+        );
+        assert!(settings.startup_only_changes(&settings).is_empty());
+    }
+}
@@ -1,62 +1,96 @@
 use std::{
+    ffi::CStr,
     fs,
     fs::File,
-    io::{ErrorKind::AlreadyExists, Write},
-    marker::PhantomData,
-    path::PathBuf,
+    io::{
+        ErrorKind::{AlreadyExists, NotFound},
+        Write,
+    },
+    os::fd::{AsFd, OwnedFd},
+    path::{Path, PathBuf},
     process,
 };
 
-use log::warn;
-use ringboard_core::{link_tmp_file, read_lock_file_pid, IoErr};
+use log::{info, warn};
+use ringboard_core::{
+    link_tmp_file, protocol::StartupState, read_lock_file_pid, Context, Error as CoreError, IoErr,
+};
 use rustix::{
-    fs::{openat, unlink, unlinkat, AtFlags, Mode, OFlags, CWD},
+    fs::{
+        chmod, flock, fstat, openat, statat, unlink, unlinkat, AtFlags, FlockOperation, Mode,
+        OFlags, RawMode, CWD,
+    },
     io::Errno,
-    process::test_kill_process,
+    process::{getpid, getuid, test_kill_process, Pid, RawUid},
 };
 
 use crate::CliError;
 
+/// Proof that this process owns the database. The server lock file stays
+/// locked for as long as this is alive, so the lock dies with the process.
 #[must_use]
-pub struct OwnedServer(PhantomData<()>);
+pub struct OwnedServer(File);
+
+/// Name of the file users create in the data directory to ask the next server
+/// to run full recovery. Its contents are ignored and it is deleted once the
+/// database has been opened.
+pub const RECOVERY_MARKER: &CStr = c"recovery-requested";
+
+/// How the previous server instance exited, as recorded by its lock file.
+///
+/// The lock file doubles as the database's dirty flag: it exists for as long
+/// as the server might be mutating the database and is only deleted on clean
+/// shutdown.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PreviousShutdown {
+    Clean,
+    /// A lock file was left behind by a dead server.
+    Crashed,
+    /// The user created a [`RECOVERY_MARKER`] file to ask for recovery.
+    RecoveryRequested,
+}
 
 impl OwnedServer {
-    #[allow(clippy::unused_self)]
     pub fn shutdown(self) -> Result<(), CliError> {
-        unlinkat(CWD, c"server.lock", AtFlags::empty())
+        // Unlinking before the lock is released makes anyone waiting on it retry
+        // instead of adopting a lock file that no longer exists.
+        let result = unlinkat(CWD, c"server.lock", AtFlags::empty())
             .map_io_err(|| "Failed to delete server lock file.")
-            .map_err(CliError::from)
+            .map_err(CliError::from);
+        drop(self.0);
+        result
     }
 }
 
-pub fn claim_server_ownership() -> Result<OwnedServer, CliError> {
+pub fn claim_server_ownership() -> Result<(OwnedServer, PreviousShutdown), CliError> {
     let mut lock_file = File::from(
-        openat(CWD, c".", OFlags::WRONLY | OFlags::TMPFILE, Mode::RUSR)
-            .map_io_err(|| "Failed to create server lock temp file.")?,
+        openat(
+            CWD,
+            c".",
+            OFlags::WRONLY | OFlags::TMPFILE | OFlags::CLOEXEC,
+            Mode::RUSR,
+        )
+        .map_io_err(|| "Failed to create server lock temp file.")?,
     );
+    // Locked before it's visible so no one can mistake it for a dead server's.
+    flock(&lock_file, FlockOperation::NonBlockingLockExclusive)
+        .map_io_err(|| "Failed to lock server lock temp file.")?;
 
     write!(lock_file, "{}", process::id()).map_io_err(|| "Failed to write to server lock file.")?;
 
+    let mut previous_shutdown = PreviousShutdown::Clean;
     loop {
         match link_tmp_file(&lock_file, CWD, c"server.lock") {
-            Err(e) if e.kind() == AlreadyExists => 'link: {
-                let pid = read_lock_file_pid(CWD, c"server.lock")?;
-                let Some(pid) = pid else {
-                    break 'link;
+            Err(e) if e.kind() == AlreadyExists => {
+                let Some((stale, shutdown)) = adopt_lock_file()? else {
+                    continue;
                 };
+                previous_shutdown = shutdown;
 
-                match test_kill_process(pid) {
-                    Err(Errno::SRCH) => {
-                        break 'link;
-                    }
-                    r => r.map_io_err(|| format!("Failed to check server status: {pid:?}."))?,
-                }
-
-                return Err(CliError::ServerAlreadyRunning {
-                    pid,
-                    lock_file: fs::canonicalize("server.lock")
-                        .unwrap_or_else(|_| PathBuf::from("server.lock")),
-                });
+                warn!("Unclean shutdown detected, forcibly claiming server lock.");
+                unlink(c"server.lock").map_io_err(|| "Failed to delete server lock.")?;
+                // Only released once the stale lock file is gone, see `adopt_lock_file`.
+                drop(stale);
             }
             r => {
                 r.map_io_err(|| {
@@ -68,11 +102,298 @@ pub fn claim_server_ownership() -> Result<OwnedServer, CliError> {
                 })?;
                 break;
             }
+        }
+    }
+
+    match statat(CWD, RECOVERY_MARKER, AtFlags::empty()) {
+        Err(Errno::NOENT) => {}
+        r => {
+            r.map_io_err(|| "Failed to stat recovery marker.")?;
+            previous_shutdown = PreviousShutdown::RecoveryRequested;
+        }
+    }
+
+    Ok((OwnedServer(lock_file), previous_shutdown))
+}
+
+/// Deletes the [`RECOVERY_MARKER`] so recovery only runs once per request.
+pub fn clear_recovery_request() -> Result<(), CliError> {
+    match unlinkat(CWD, RECOVERY_MARKER, AtFlags::empty()) {
+        Err(Errno::NOENT) => Ok(()),
+        r => r
+            .map_io_err(|| "Failed to delete recovery marker.")
+            .map_err(CliError::from),
+    }
+}
+
+/// Locks the existing server lock file if its owner is gone, returning it
+/// along with how its owner went away. Returns `None` if the lock file changed
+/// while it was being locked, in which case claiming it should start over.
+///
+/// Whoever holds the lock is the only one allowed to delete the file, so of
+/// several servers starting at once only the first to lock it adopts it and the
+/// rest find the new owner's lock file after it's released.
+fn adopt_lock_file() -> Result<Option<(OwnedFd, PreviousShutdown)>, CliError> {
+    let lock_file = match openat(
+        CWD,
+        c"server.lock",
+        OFlags::RDONLY | OFlags::CLOEXEC,
+        Mode::empty(),
+    ) {
+        Err(Errno::NOENT) => return Ok(None),
+        r => r.map_io_err(|| "Failed to open server lock file.")?,
+    };
+    let running = match flock(&lock_file, FlockOperation::NonBlockingLockExclusive) {
+        Err(Errno::WOULDBLOCK) => true,
+        r => {
+            r.map_io_err(|| "Failed to lock server lock file.")?;
+            false
+        }
+    };
+    if !running && !is_same_file(&lock_file)? {
+        return Ok(None);
+    }
+
+    let pid = match read_lock_file_pid(CWD, c"server.lock") {
+        Err(CoreError::Io { error, .. }) if error.kind() == NotFound => return Ok(None),
+        r => r?,
+    };
+    // A lock file without a PID was cut short while being written, which is just
+    // another way for its server to have died.
+    let Some(pid) = pid else {
+        return if running {
+            Ok(None)
+        } else {
+            Ok(Some((lock_file, PreviousShutdown::Crashed)))
         };
+    };
+    // Servers from before lock files were locked can only be recognized by their
+    // PID, which is checked to still be a server in case it was reused.
+    if running || is_other_server(pid)? {
+        return Err(CliError::ServerAlreadyRunning {
+            pid,
+            lock_file: fs::canonicalize("server.lock")
+                .unwrap_or_else(|_| PathBuf::from("server.lock")),
+        });
+    }
+    Ok(Some((lock_file, PreviousShutdown::Crashed)))
+}
+
+/// Whether the lock file is still the one at the lock file's path.
+fn is_same_file(lock_file: impl AsFd) -> Result<bool, CliError> {
+    let locked = fstat(lock_file).map_io_err(|| "Failed to stat server lock file.")?;
+    match statat(CWD, c"server.lock", AtFlags::empty()) {
+        Err(Errno::NOENT) => Ok(false),
+        r => {
+            let current = r.map_io_err(|| "Failed to stat server lock file.")?;
+            Ok((locked.st_dev, locked.st_ino) == (current.st_dev, current.st_ino))
+        }
+    }
+}
+
+/// Whether `pid` is a live process running the same program as this one.
+fn is_other_server(pid: Pid) -> Result<bool, CliError> {
+    if pid == getpid() {
+        return Ok(false);
+    }
+    match test_kill_process(pid) {
+        // Servers run as the same user, so processes that can't be signaled aren't one.
+        Err(Errno::SRCH | Errno::PERM) => return Ok(false),
+        r => r.map_io_err(|| format!("Failed to check server status: {pid:?}."))?,
+    }
+    let comm = |pid: &str| fs::read(format!("/proc/{pid}/comm"));
+    match (comm(&pid.as_raw_nonzero().to_string()), comm("self")) {
+        (Ok(theirs), Ok(ours)) => Ok(theirs == ours),
+        // The process exited after all.
+        (Err(e), _) if e.kind() == NotFound => Ok(false),
+        (Err(e), _) | (_, Err(e)) => Err(e)
+            .map_io_err(|| format!("Failed to check server status: {pid:?}."))
+            .map_err(CliError::from),
+    }
+}
+
+/// How other users could get at the data directory.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DataDirExposure {
+    /// The directory belongs to another user.
+    ForeignOwner(RawUid),
+    /// The directory grants these group or world permission bits.
+    Shared(RawMode),
+}
+
+/// Returns how the data directory is exposed to users other than `uid`, if at
+/// all.
+pub const fn data_dir_exposure(
+    owner: RawUid,
+    mode: RawMode,
+    uid: RawUid,
+) -> Option<DataDirExposure> {
+    let shared = mode & 0o077;
+    if owner != uid {
+        Some(DataDirExposure::ForeignOwner(owner))
+    } else if shared != 0 {
+        Some(DataDirExposure::Shared(shared))
+    } else {
+        None
+    }
+}
+
+/// Refuses to serve a database owned by another user unless `allow_shared` is
+/// set, in which case that's only warned about. Permissions other users were
+/// granted are taken away instead.
+pub fn check_data_dir(path: &Path, allow_shared: bool) -> Result<(), CliError> {
+    let stat = statat(CWD, c".", AtFlags::empty()).map_io_err(|| Context::File {
+        message: "Failed to stat data directory",
+        path: path.to_path_buf(),
+    })?;
+    let mode = stat.st_mode & 0o7777;
+    let Some(exposure) = data_dir_exposure(stat.st_uid, mode, getuid().as_raw()) else {
+        return Ok(());
+    };
+
+    match exposure {
+        _ if allow_shared => {
+            warn!("Serving a data directory other users can access: {exposure:?}.");
+        }
+        DataDirExposure::Shared(shared) => {
+            chmod(c".", Mode::from_raw_mode(mode & !shared)).map_io_err(|| Context::File {
+                message: "Failed to restrict data directory permissions",
+                path: path.to_path_buf(),
+            })?;
+            warn!(
+                "Took permissions {shared:03o} away from other users on the data directory \
+                 {path:?}."
+            );
+        }
+        DataDirExposure::ForeignOwner(_) => {
+            return Err(CliError::SharedDataDir {
+                path: path.to_path_buf(),
+                exposure,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Decides how much checking the database needs before it can be served.
+///
+/// A clean shutdown is trusted as is, a crash gets a bounded fast check, and
+/// full recovery only runs when the fast check fails or was asked for.
+pub fn check_database<Db>(
+    previous_shutdown: PreviousShutdown,
+    database: &mut Db,
+    fast_check: impl FnOnce(&Db) -> bool,
+    recover: impl FnOnce(&mut Db) -> Result<(), CliError>,
+) -> Result<StartupState, CliError> {
+    match previous_shutdown {
+        PreviousShutdown::Clean => return Ok(StartupState::Clean),
+        PreviousShutdown::Crashed => {
+            if fast_check(database) {
+                info!("Database passed fast check.");
+                return Ok(StartupState::FastChecked);
+            }
+            warn!("Database failed fast check, starting full recovery.");
+        }
+        PreviousShutdown::RecoveryRequested => {
+            warn!("Recovery requested, starting full recovery.");
+        }
+    }
+
+    recover(database)?;
+    Ok(StartupState::Recovered)
+}
 
-        warn!("Unclean shutdown detected, forcibly claiming server lock.");
-        unlink(c"server.lock").map_io_err(|| "Failed to delete server lock.")?;
+#[cfg(test)]
+mod tests {
+    use ringboard_core::protocol::StartupState;
+
+    use super::{check_database, data_dir_exposure, DataDirExposure, PreviousShutdown};
+
+    #[test]
+    fn clean_shutdown_skips_checks() {
+        let state = check_database(
+            PreviousShutdown::Clean,
+            &mut (),
+            |()| unreachable!(),
+            |()| unreachable!(),
+        );
+        assert_eq!(state.unwrap(), StartupState::Clean);
+    }
+
+    #[test]
+    fn crash_runs_fast_check() {
+        let state = check_database(
+            PreviousShutdown::Crashed,
+            &mut (),
+            |()| true,
+            |()| unreachable!(),
+        );
+        assert_eq!(state.unwrap(), StartupState::FastChecked);
     }
 
-    Ok(OwnedServer(PhantomData))
+    #[test]
+    fn failed_fast_check_escalates_to_recovery() {
+        let mut recovered = false;
+        let state = check_database(
+            PreviousShutdown::Crashed,
+            &mut recovered,
+            |_| false,
+            |recovered| {
+                *recovered = true;
+                Ok(())
+            },
+        );
+        assert_eq!(state.unwrap(), StartupState::Recovered);
+        assert!(recovered);
+    }
+
+    #[test]
+    fn requested_recovery_skips_fast_check() {
+        let mut recovered = false;
+        let state = check_database(
+            PreviousShutdown::RecoveryRequested,
+            &mut recovered,
+            |_| unreachable!(),
+            |recovered| {
+                *recovered = true;
+                Ok(())
+            },
+        );
+        assert_eq!(state.unwrap(), StartupState::Recovered);
+        assert!(recovered);
+    }
+
+    #[test]
+    fn private_data_dir_is_accepted() {
+        assert_eq!(data_dir_exposure(1000, 0o700, 1000), None);
+        assert_eq!(data_dir_exposure(1000, 0o500, 1000), None);
+    }
+
+    #[test]
+    fn group_or_world_access_is_exposure() {
+        assert_eq!(
+            data_dir_exposure(1000, 0o755, 1000),
+            Some(DataDirExposure::Shared(0o055))
+        );
+        assert_eq!(
+            data_dir_exposure(1000, 0o1702, 1000),
+            Some(DataDirExposure::Shared(0o002))
+        );
+        assert_eq!(
+            data_dir_exposure(1000, 0o770, 1000),
+            Some(DataDirExposure::Shared(0o070))
+        );
+    }
+
+    #[test]
+    fn foreign_owner_is_exposure() {
+        assert_eq!(
+            data_dir_exposure(0, 0o700, 1000),
+            Some(DataDirExposure::ForeignOwner(0))
+        );
+        assert_eq!(
+            data_dir_exposure(1001, 0o777, 1000),
+            Some(DataDirExposure::ForeignOwner(1001))
+        );
+    }
 }
@@ -0,0 +1,176 @@
+use std::{collections::VecDeque, ffi::CStr, fs::File, str, time::SystemTime};
+
+use arrayvec::ArrayString;
+use ringboard_core::{
+    origins::Origin,
+    protocol::{Label, MimeType, RingKind},
+    tags,
+    tags::{TagSet, MAX_TAGS_LEN},
+    IoErr,
+};
+use rustix::{fs::fgetxattr, io::Errno};
+
+use crate::CliError;
+
+/// How many removed entries are kept around to be restored.
+const CAPACITY: usize = 16;
+
+/// A removed entry along with everything needed to add it back.
+#[derive(Debug)]
+pub struct Tombstone {
+    /// The id the entry had when it was removed.
+    pub id: u64,
+    pub ring: RingKind,
+    /// An unlinked copy of the entry's data, freed along with the tombstone.
+    pub data: File,
+    pub mime_type: MimeType,
+    pub label: Label,
+    pub tags: TagSet,
+    pub origin: Option<Origin>,
+    pub copied_at: Option<SystemTime>,
+    /// When the data expires if it's sensitive, in seconds since the Unix
+    /// epoch.
+    pub expires_at: Option<u64>,
+}
+
+/// The most recently removed entries, so removals can be undone.
+///
+/// The trash only lives in memory, so restarting the server empties it. Once
+/// it's full, every removal permanently deletes the oldest entry in it.
+#[derive(Debug, Default)]
+pub struct Trash {
+    /// Oldest first.
+    tombstones: VecDeque<Tombstone>,
+}
+
+impl Trash {
+    pub fn push(&mut self, tombstone: Tombstone) {
+        if self.tombstones.len() == CAPACITY {
+            self.tombstones.pop_front();
+        }
+        self.tombstones.push_back(tombstone);
+    }
+
+    /// The most recent removal under `id`, since ids are reused once the ring
+    /// wraps around.
+    pub fn get(&self, id: u64) -> Option<&Tombstone> {
+        self.tombstones.iter().rev().find(|t| t.id == id)
+    }
+
+    pub fn take(&mut self, id: u64) -> Option<Tombstone> {
+        let index = self.tombstones.iter().rposition(|t| t.id == id)?;
+        self.tombstones.remove(index)
+    }
+
+    /// Permanently deletes sensitive entries that expired by `now`, returning
+    /// when the next one left expires.
+    pub fn purge_expired(&mut self, now: u64) -> Option<u64> {
+        self.tombstones
+            .retain(|t| t.expires_at.is_none_or(|expires_at| expires_at > now));
+        self.tombstones.iter().filter_map(|t| t.expires_at).min()
+    }
+
+    /// Returns how many entries were permanently deleted.
+    pub fn empty(&mut self) -> usize {
+        let purged = self.tombstones.len();
+        self.tombstones.clear();
+        purged
+    }
+}
+
+/// Reads a text attribute like the mime type or label of a direct entry,
+/// which is empty if the entry has none.
+pub fn xattr_string(file: &File, name: &CStr) -> Result<ArrayString<96>, CliError> {
+    let mut buf = [0; 96];
+    let len = match fgetxattr(file, name, &mut buf) {
+        Err(Errno::NODATA) => 0,
+        r => r.map_io_err(|| format!("Failed to read extended attribute {name:?}."))?,
+    };
+    Ok(str::from_utf8(&buf[..len])
+        .ok()
+        .and_then(|s| ArrayString::from(s).ok())
+        .unwrap_or_default())
+}
+
+/// Reads the tags of a direct entry, which has none if the attribute is
+/// missing.
+pub fn xattr_tags(file: &File) -> Result<TagSet, CliError> {
+    let mut buf = [0; MAX_TAGS_LEN];
+    let len = match fgetxattr(file, tags::XATTR_NAME, &mut buf) {
+        Err(Errno::NODATA) => 0,
+        r => r.map_io_err(|| "Failed to read tags attribute.")?,
+    };
+    Ok(TagSet::from_bytes(&buf[..len]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use ringboard_core::{
+        protocol::{composite_id, Label, MimeType, RingKind},
+        tags::TagSet,
+    };
+    use rustix::fs::{memfd_create, MemfdFlags};
+
+    use super::{Tombstone, Trash, CAPACITY};
+
+    fn tombstone(index: u32) -> Tombstone {
+        Tombstone {
+            id: composite_id(RingKind::Main, index),
+            ring: RingKind::Main,
+            data: File::from(memfd_create(c"ringboard-trash", MemfdFlags::empty()).unwrap()),
+            mime_type: MimeType::default(),
+            label: Label::default(),
+            tags: TagSet::default(),
+            origin: None,
+            copied_at: None,
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn oldest_removals_are_purged_once_full() {
+        let mut trash = Trash::default();
+        for index in 0..=u32::try_from(CAPACITY).unwrap() {
+            trash.push(tombstone(index));
+        }
+
+        assert!(trash.get(composite_id(RingKind::Main, 0)).is_none());
+        assert!(trash.take(composite_id(RingKind::Main, 1)).is_some());
+        assert!(trash.take(composite_id(RingKind::Main, 1)).is_none());
+        assert_eq!(trash.empty(), CAPACITY - 1);
+        assert_eq!(trash.empty(), 0);
+    }
+
+    #[test]
+    fn reused_ids_restore_the_latest_removal() {
+        let mut trash = Trash::default();
+        trash.push(tombstone(3));
+        trash.push(Tombstone {
+            copied_at: Some(std::time::UNIX_EPOCH),
+            ..tombstone(3)
+        });
+
+        let latest = trash.take(composite_id(RingKind::Main, 3)).unwrap();
+        assert_eq!(latest.copied_at, Some(std::time::UNIX_EPOCH));
+        assert!(trash.get(composite_id(RingKind::Main, 3)).is_some());
+    }
+
+    #[test]
+    fn expired_sensitive_removals_are_purged() {
+        let mut trash = Trash::default();
+        for (index, expires_at) in [(0, None), (1, Some(100)), (2, Some(200))] {
+            trash.push(Tombstone {
+                expires_at,
+                ..tombstone(index)
+            });
+        }
+
+        assert_eq!(trash.purge_expired(99), Some(100));
+        assert_eq!(trash.purge_expired(100), Some(200));
+        assert!(trash.get(composite_id(RingKind::Main, 1)).is_none());
+        assert_eq!(trash.purge_expired(200), None);
+        assert!(trash.get(composite_id(RingKind::Main, 0)).is_some());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    env,
+    fs::File,
+    io,
+    io::IoSlice,
+    os::{
+        fd::{AsFd, OwnedFd},
+        unix::net::UnixStream,
+    },
+    process,
+};
+
+use common::Server;
+use ringboard_core::{
+    protocol::{AddResponse, ErrorCode, MimeType, Request, RequestFailed, Response, RingKind},
+    AsBytes,
+};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    net::{recv, sendmsg, RecvFlags, SendAncillaryBuffer, SendAncillaryMessage, SendFlags},
+};
+
+mod common;
+
+#[test]
+fn surplus_fds_are_closed() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-fds-{}", process::id())));
+    let client = server.connect();
+    let open_fds = server.open_fds();
+
+    let files = [(); 3].map(|()| File::open("/dev/null").unwrap());
+    let fds = files.each_ref().map(AsFd::as_fd);
+    let mut space = [0; rustix::cmsg_space!(ScmRights(3))];
+    let mut ancillary = SendAncillaryBuffer::new(&mut space);
+    assert!(ancillary.push(SendAncillaryMessage::ScmRights(&fds)));
+    let request = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    sendmsg(
+        &client,
+        &[IoSlice::new(request.as_bytes())],
+        &mut ancillary,
+        SendFlags::empty(),
+    )
+    .unwrap();
+
+    // The client is told what it did wrong and keeps its connection.
+    let mut buf = [0; size_of::<Response<RequestFailed>>()];
+    let len = recv(&client, &mut buf, RecvFlags::empty()).unwrap();
+    assert_eq!(len, RequestFailed::LEN);
+    let failed = unsafe {
+        buf.as_ptr()
+            .cast::<Response<RequestFailed>>()
+            .read_unaligned()
+            .value
+    };
+    assert_eq!(failed.code, ErrorCode::ProtocolViolation);
+    assert_eq!(server.open_fds(), open_fds);
+
+    let data = memfd_create(c"surplus", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, b"still connected").unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let response = add(&client, data);
+    assert!(
+        matches!(response, AddResponse::Success { .. }),
+        "{response:?}"
+    );
+}
+
+fn add(client: &OwnedFd, data: impl AsFd) -> AddResponse {
+    let fds = [data.as_fd()];
+    let mut space = [0; rustix::cmsg_space!(ScmRights(1))];
+    let mut ancillary = SendAncillaryBuffer::new(&mut space);
+    assert!(ancillary.push(SendAncillaryMessage::ScmRights(&fds)));
+    let request = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    sendmsg(
+        client,
+        &[IoSlice::new(request.as_bytes())],
+        &mut ancillary,
+        SendFlags::empty(),
+    )
+    .unwrap();
+
+    let mut buf = [0; 64];
+    let len = recv(client, &mut buf, RecvFlags::empty()).unwrap();
+    assert_eq!(len, size_of::<Response<AddResponse>>());
+    unsafe {
+        buf.as_ptr()
+            .cast::<Response<AddResponse>>()
+            .read_unaligned()
+            .value
+    }
+}
+
+#[test]
+fn never_written_pipes_are_rejected() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-pipe-{}", process::id())));
+    let client = server.connect();
+
+    let (reader, _writer) = io::pipe().unwrap();
+    assert!(matches!(add(&client, reader), AddResponse::UnsupportedData));
+    server.assert_responsive();
+}
+
+#[test]
+fn sockets_are_rejected() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-socket-{}", process::id())));
+    let client = server.connect();
+
+    let (socket, _peer) = UnixStream::pair().unwrap();
+    assert!(matches!(add(&client, socket), AddResponse::UnsupportedData));
+    server.assert_responsive();
+}
+
+#[test]
+fn empty_entries_are_rejected() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-empty-{}", process::id())));
+    let client = server.connect();
+
+    let data = memfd_create(c"empty", MemfdFlags::empty()).unwrap();
+    assert!(matches!(add(&client, data), AddResponse::Empty));
+    server.assert_responsive();
+}
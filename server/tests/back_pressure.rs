@@ -0,0 +1,59 @@
+use std::{env, process};
+
+use common::Server;
+use ringboard_core::{
+    protocol::{Request, StatsResponse},
+    AsBytes,
+};
+use rustix::{
+    io::Errno,
+    net::{recv, send, RecvFlags, SendFlags},
+};
+
+mod common;
+
+#[test]
+fn clients_that_never_read_only_stall_themselves() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-back-pressure-{}", process::id())));
+    let stuck = server.connect();
+
+    // Keep asking until the server stops taking requests from this client.
+    let mut sent = 0;
+    loop {
+        match send(&stuck, Request::Stats.as_bytes(), SendFlags::DONTWAIT) {
+            Ok(_) => sent += 1,
+            Err(Errno::AGAIN) => break,
+            Err(e) => panic!("{e}"),
+        }
+        assert!(sent < 1_000_000, "the server never applied back pressure");
+    }
+    server.wait_for_log("No buffers available to receive client");
+    server.assert_responsive();
+
+    // Reading the backlog lets the client continue where it left off.
+    for _ in 0..sent {
+        assert!(recv(&stuck, &mut [0; 8192], RecvFlags::empty()).unwrap() > 0);
+    }
+    server.assert_responsive();
+}
+
+#[test]
+fn oversized_messages_disconnect_only_their_client() {
+    let server = Server::start(env::temp_dir().join(format!(
+        "ringboard-back-pressure-oversized-{}",
+        process::id()
+    )));
+    let bystander = server.connect();
+    let client = server.connect();
+
+    send(&client, &vec![0; 64 << 10], SendFlags::empty()).unwrap();
+    assert_eq!(
+        recv(&client, &mut [0; 1024], RecvFlags::empty()).unwrap(),
+        0
+    );
+
+    let stats = common::request::<StatsResponse>(&bystander, &Request::Stats, None);
+    assert_eq!(stats.rejected_requests, 1);
+    server.assert_responsive();
+}
@@ -0,0 +1,181 @@
+use std::{
+    env, fs,
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
+
+use common::{request, Server};
+use ringboard_core::{
+    protocol::{
+        decompose_id, AddResponse, BackupOutcome, BackupResponse, BackupStats, MimeType, Request,
+        RingKind, StatsResponse,
+    },
+    ring::{Entry, Ring},
+};
+use rustix::fs::{getxattr, memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, data: &[u8], mime_type: &str) -> u64 {
+    add_with(client, data, mime_type, false)
+}
+
+fn add_with(client: &OwnedFd, data: &[u8], mime_type: &str, sensitive: bool) -> u64 {
+    let file = memfd_create(c"ringboard-backup", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive,
+        mime_type: MimeType::from(mime_type).unwrap(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn backup_now(client: &OwnedFd) -> BackupOutcome {
+    request::<BackupResponse>(client, &Request::BackupNow, None).outcome
+}
+
+/// Waits for the running backup, if any, to finish.
+fn wait_for_backup(client: &OwnedFd) -> BackupStats {
+    for _ in 0..500 {
+        let stats = request::<StatsResponse>(client, &Request::Stats, None).backups;
+        if !stats.in_progress {
+            return stats;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("backup never finished");
+}
+
+fn backups(dir: &Path) -> Vec<PathBuf> {
+    let mut backups = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    backups.sort();
+    backups
+}
+
+#[test]
+fn backups_copy_the_database_and_prune_old_ones() {
+    let dir = env::temp_dir().join(format!("ringboard-backup-{}", process::id()));
+    let target = dir.join("backups");
+    let server = Server::start_with_settings(
+        dir,
+        &format!("backup_dir = {}\nbackups_kept = 1\n", target.display()),
+    );
+    let client = server.connect();
+    add(&client, b"some text", "");
+    add(&client, b"not really a png", "image/png");
+
+    assert_eq!(backup_now(&client), BackupOutcome::Started);
+    let stats = wait_for_backup(&client);
+    assert_eq!(stats.failures, 0, "{}", server.log());
+    assert_ne!(stats.last_backup, 0);
+    assert_eq!(stats.copied_files, stats.total_files);
+    let [first] = &*backups(&target) else {
+        panic!("{:?}", backups(&target));
+    };
+    assert_eq!(
+        first.file_name().unwrap().to_str().unwrap(),
+        format!("ringboard-{}", stats.last_backup)
+    );
+    assert!(first.join("main.ring").exists());
+    assert!(!first.join("free-lists").exists());
+    let [image] = &*backups(&first.join("direct")) else {
+        panic!("{:?}", backups(&first.join("direct")));
+    };
+    let mut mime_type = [0; 16];
+    let len = getxattr(image, c"user.mime_type", &mut mime_type).unwrap();
+    assert_eq!(&mime_type[..len], b"image/png");
+
+    assert_eq!(backup_now(&client), BackupOutcome::Unchanged);
+
+    // Backups are named by the second they were taken in.
+    thread::sleep(Duration::from_secs(1));
+    add(&client, b"more text", "");
+    assert_eq!(backup_now(&client), BackupOutcome::Started);
+    let stats = wait_for_backup(&client);
+    assert_eq!(stats.failures, 0, "{}", server.log());
+    let [second] = &*backups(&target) else {
+        panic!("{:?}", backups(&target));
+    };
+    assert_ne!(second, first);
+}
+
+#[test]
+fn sensitive_entries_are_left_out_of_backups() {
+    let dir = env::temp_dir().join(format!("ringboard-backup-sensitive-{}", process::id()));
+    let target = dir.join("backups");
+    let server = Server::start_with_settings(dir, &format!("backup_dir = {}\n", target.display()));
+    let client = server.connect();
+    let kept = add(&client, b"not really a png", "image/png");
+    let secret = add_with(&client, b"hunter2", "", true);
+
+    assert_eq!(backup_now(&client), BackupOutcome::Started);
+    let stats = wait_for_backup(&client);
+    assert_eq!(stats.failures, 0, "{}", server.log());
+    let [backup] = &*backups(&target) else {
+        panic!("{:?}", backups(&target));
+    };
+    let files = backups(&backup.join("direct"))
+        .into_iter()
+        .map(|file| {
+            file.file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .parse::<u64>()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(files, [kept]);
+
+    let ring = Ring::open(
+        RingKind::Main.default_max_entries(),
+        &*backup.join(RingKind::Main.file_name()),
+    )
+    .unwrap();
+    let (_, kept) = decompose_id(kept).unwrap();
+    let (_, secret) = decompose_id(secret).unwrap();
+    assert_eq!(ring.get(kept), Some(Entry::File));
+    assert_eq!(ring.get(secret), Some(Entry::Uninitialized));
+}
+
+#[test]
+fn backups_are_taken_on_a_schedule() {
+    let dir = env::temp_dir().join(format!("ringboard-backup-schedule-{}", process::id()));
+    let target = dir.join("backups");
+    let server = Server::start_with_settings(
+        dir,
+        &format!("backup_dir = {}\nbackup_interval = 1\n", target.display()),
+    );
+    let client = server.connect();
+    add(&client, b"some text", "");
+
+    for _ in 0..300 {
+        let stats = request::<StatsResponse>(&client, &Request::Stats, None).backups;
+        if stats.last_backup != 0 && !stats.in_progress {
+            assert_eq!(backups(&target).len(), 1);
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("no backup was taken: {}", server.log());
+}
+
+#[test]
+fn backups_must_be_configured() {
+    let server = Server::start(
+        env::temp_dir().join(format!("ringboard-backup-unconfigured-{}", process::id())),
+    );
+    let client = server.connect();
+    assert_eq!(backup_now(&client), BackupOutcome::NotConfigured);
+}
@@ -0,0 +1,158 @@
+use std::{env, io::IoSlice, iter, os::fd::AsFd, process};
+
+use common::Server;
+use ringboard_core::{
+    protocol::{
+        composite_id, AddResponse, ErrorCode, MimeType, Request, RequestFailed, Response, RingKind,
+        StatsResponse, UnknownRequest,
+    },
+    AsBytes,
+};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    net::{recv, sendmsg, RecvFlags, SendAncillaryBuffer, SendAncillaryMessage, SendFlags},
+};
+
+mod common;
+
+fn file(data: &[u8]) -> impl AsFd {
+    let file = memfd_create(c"ringboard-batch", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    file
+}
+
+/// Sends `requests` as a single batch with `fds` attached, returning the reply.
+fn batch(client: impl AsFd, requests: &[&[u8]], fds: &[&dyn AsFd]) -> Vec<u8> {
+    let header = Request::Batch {
+        len: u8::try_from(requests.len()).unwrap(),
+    };
+    let payload = iter::once(header.as_bytes())
+        .chain(requests.iter().copied())
+        .map(IoSlice::new)
+        .collect::<Vec<_>>();
+    let fds = fds.iter().map(|fd| fd.as_fd()).collect::<Vec<_>>();
+    let mut space = [0; rustix::cmsg_space!(ScmRights(4))];
+    let mut ancillary = SendAncillaryBuffer::new(&mut space);
+    if !fds.is_empty() {
+        assert!(ancillary.push(SendAncillaryMessage::ScmRights(&fds)));
+    }
+    sendmsg(&client, &payload, &mut ancillary, SendFlags::empty()).unwrap();
+
+    let mut buf = vec![0; 8192];
+    let len = recv(&client, &mut buf, RecvFlags::empty()).unwrap();
+    buf.truncate(len);
+    buf
+}
+
+fn read<T: Copy>(buf: &[u8], offset: usize) -> T {
+    assert!(buf.len() >= offset + size_of::<Response<T>>());
+    unsafe {
+        buf.as_ptr()
+            .add(offset)
+            .cast::<Response<T>>()
+            .read_unaligned()
+            .value
+    }
+}
+
+#[test]
+fn batched_requests_are_answered_together_in_order() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-batch-{}", process::id())));
+    let client = server.connect();
+    let add = |mime_type| Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::from(mime_type).unwrap(),
+        copied_at: 0,
+    };
+
+    let reply = batch(
+        &client,
+        &[
+            add("text/plain").as_bytes(),
+            add("image/png").as_bytes(),
+            Request::Stats.as_bytes(),
+        ],
+        &[&file(b"hello"), &file(b"\x89PNG")],
+    );
+    let add_len = size_of::<Response<AddResponse>>();
+    assert_eq!(
+        reply.len(),
+        2 * add_len + size_of::<Response<StatsResponse>>()
+    );
+    for (offset, index) in [(0, 0), (add_len, 1)] {
+        let response = read::<AddResponse>(&reply, offset);
+        assert!(
+            matches!(response, AddResponse::Success { id, .. } if id == composite_id(RingKind::Main, index)),
+            "{response:?}"
+        );
+    }
+    let stats = read::<StatsResponse>(&reply, 2 * add_len);
+    assert_eq!(stats.connected_clients, 1);
+
+    // Unbatched requests still get a message of their own.
+    let stats = common::request::<StatsResponse>(&client, &Request::Stats, None);
+    assert_eq!(stats.connected_clients, 1);
+}
+
+#[test]
+fn batches_with_unknown_requests_are_rejected_whole() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-batch-unknown-{}", process::id())));
+    let client = server.connect();
+
+    // What a client with one more kind of request than this server would send.
+    let mut unknown = [0xAA; size_of::<Request>()];
+    unknown[..size_of::<u32>()].copy_from_slice(&Request::KINDS.to_ne_bytes());
+    let reply = batch(&client, &[Request::ServerInfo.as_bytes(), &unknown], &[]);
+    assert_eq!(reply.len(), size_of::<Response<UnknownRequest>>());
+    assert_eq!(read::<UnknownRequest>(&reply, 0).tag, Request::KINDS);
+    server.assert_responsive();
+}
+
+#[test]
+fn malformed_batches_disconnect() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-batch-malformed-{}", process::id())));
+
+    // Reads can't be batched since their responses may carry a file.
+    let client = server.connect();
+    let read = Request::Read {
+        id: 0,
+        offset: 0,
+        len: 0,
+    };
+    assert!(batch(&client, &[Request::Stats.as_bytes(), read.as_bytes()], &[]).is_empty());
+
+    server.assert_responsive();
+}
+
+#[test]
+fn batches_missing_files_are_rejected() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-batch-files-{}", process::id())));
+    let client = server.connect();
+
+    // Every add needs its file.
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    let add = add.as_bytes();
+    let reply = batch(&client, &[add, add], &[&file(b"hello")]);
+    assert_eq!(reply.len(), RequestFailed::LEN);
+    let mut buf = [0; size_of::<Response<RequestFailed>>()];
+    buf[..reply.len()].copy_from_slice(&reply);
+    assert_eq!(
+        read::<RequestFailed>(&buf, 0).code,
+        ErrorCode::ProtocolViolation
+    );
+
+    // Nothing in the batch went through and the client keeps its connection.
+    let stats = read::<StatsResponse>(&batch(&client, &[Request::Stats.as_bytes()], &[]), 0);
+    assert_eq!(stats.main_len, 0);
+    assert_eq!(stats.rejected_requests, 1);
+}
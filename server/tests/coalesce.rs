@@ -0,0 +1,42 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{composite_id, AddResponse, MimeType, Request, RingKind, StatsResponse};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, text: &str) -> (u64, bool) {
+    let data = memfd_create(c"ringboard-coalesce", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text.as_bytes()).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&data)) {
+        AddResponse::Success { id, coalesced, .. } => (id, coalesced),
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+#[test]
+fn rapid_identical_adds_are_coalesced() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-coalesce-{}", process::id())));
+    let client = server.connect();
+
+    let adds = (0..3).map(|_| add(&client, "hello")).collect::<Vec<_>>();
+    let first = composite_id(RingKind::Main, 0);
+    assert_eq!(adds, [(first, false), (first, true), (first, true)]);
+    let stats = request::<StatsResponse>(&client, &Request::Stats, None);
+    assert_eq!(stats.coalesced_adds, 2);
+
+    // Only one entry was stored.
+    assert_eq!(
+        add(&client, "world"),
+        (composite_id(RingKind::Main, 1), false)
+    );
+}
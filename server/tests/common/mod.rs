@@ -0,0 +1,251 @@
+//! A server running in its own data directory for integration tests.
+#![allow(dead_code)]
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    fs::{File, Permissions},
+    io::IoSlice,
+    os::{
+        fd::{AsFd, OwnedFd},
+        unix::fs::PermissionsExt,
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Output},
+    sync::OnceLock,
+    thread,
+    time::Duration,
+};
+
+use ringboard_core::{
+    protocol::{Request, Response, VERSION},
+    AsBytes,
+};
+use rustix::{
+    net::{
+        connect_unix, recv, send, sendmsg, socket, sockopt, sockopt::Timeout, AddressFamily,
+        RecvFlags, SendAncillaryBuffer, SendAncillaryMessage, SendFlags, SocketAddrUnix,
+        SocketType,
+    },
+    process::{Pid, Signal},
+};
+
+pub struct Server {
+    process: Child,
+    dir: PathBuf,
+    args: Vec<OsString>,
+}
+
+impl Server {
+    pub fn start(dir: PathBuf) -> Self {
+        Self::start_with_settings(dir, "")
+    }
+
+    /// Starts a server which reads `settings` as its settings file.
+    pub fn start_with_settings(dir: PathBuf, settings: &str) -> Self {
+        Self::start_with_args(dir, settings, &[])
+    }
+
+    /// Starts a server with extra command line flags.
+    pub fn start_with_args(dir: PathBuf, settings: &str, args: &[&OsStr]) -> Self {
+        let data_dir = dir.join("clipboard-history");
+        create_data_dir(&data_dir);
+        fs::write(data_dir.join("settings"), settings).unwrap();
+        Self {
+            process: spawn(&dir, args),
+            dir,
+            args: args.iter().map(|&arg| arg.to_owned()).collect(),
+        }
+    }
+
+    /// Waits for the server to exit and starts a new one on the same database
+    /// with the same flags, whose log starts over.
+    pub fn restart(&mut self) {
+        self.process.wait().unwrap();
+        // Like after a crash, the old listening socket may still be open.
+        let _ = fs::remove_file(self.dir.join("server.sock"));
+        let args = self
+            .args
+            .iter()
+            .map(OsString::as_os_str)
+            .collect::<Vec<_>>();
+        self.process = spawn(&self.dir, &args);
+    }
+
+    pub fn connect(&self) -> OwnedFd {
+        self.handshake(&[VERSION]).0
+    }
+
+    /// Opens a socket to the server without saying anything on it yet.
+    pub fn dial(&self) -> OwnedFd {
+        let addr = SocketAddrUnix::new(self.socket_file()).unwrap();
+        (0..100)
+            .find_map(|_| {
+                let client = socket(AddressFamily::UNIX, SocketType::SEQPACKET, None).unwrap();
+                if connect_unix(&client, &addr).is_ok() {
+                    Some(client)
+                } else {
+                    thread::sleep(Duration::from_millis(10));
+                    None
+                }
+            })
+            .expect("server never started listening")
+    }
+
+    /// Connects by sending `hello` instead of the usual version, returning the
+    /// server's reply.
+    pub fn handshake(&self, hello: &[u8]) -> (OwnedFd, Vec<u8>) {
+        let client = self.dial();
+        sendmsg(
+            &client,
+            &[IoSlice::new(hello)],
+            &mut SendAncillaryBuffer::default(),
+            SendFlags::empty(),
+        )
+        .unwrap();
+        let mut reply = vec![0; 64];
+        let len = recv(&client, &mut reply, RecvFlags::empty()).unwrap();
+        reply.truncate(len);
+        (client, reply)
+    }
+
+    /// Checks that the server still answers new clients.
+    pub fn assert_responsive(&self) {
+        let client = self.connect();
+        sockopt::set_socket_timeout(&client, Timeout::Recv, Some(Duration::from_secs(1))).unwrap();
+        send(&client, Request::ServerInfo.as_bytes(), SendFlags::empty()).unwrap();
+        assert!(recv(&client, &mut [0; 256], RecvFlags::empty()).unwrap() > 0);
+    }
+
+    /// Everything the server has logged so far.
+    pub fn log(&self) -> String {
+        fs::read_to_string(self.dir.join("server.log")).unwrap()
+    }
+
+    /// Writes a new settings file for the server to pick up on reload.
+    pub fn write_settings(&self, settings: &str) {
+        fs::write(self.dir.join("clipboard-history/settings"), settings).unwrap();
+    }
+
+    pub fn signal(&self, signal: Signal) {
+        let pid = Pid::from_child(&self.process);
+        rustix::process::kill_process(pid, signal).unwrap();
+    }
+
+    /// Waits until the server logs `message`.
+    pub fn wait_for_log(&self, message: &str) {
+        for _ in 0..100 {
+            if self.log().contains(message) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("Server never logged {message:?}:\n{}", self.log());
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.process.id()
+    }
+
+    pub fn wait(&mut self) -> ExitStatus {
+        self.process.wait().unwrap()
+    }
+
+    /// Kills the server without letting it shut down cleanly.
+    pub fn crash(&mut self) {
+        self.process.kill().unwrap();
+        self.process.wait().unwrap();
+        // The dead server's listening socket is closed asynchronously along with
+        // its io_uring, so a restarted server's clients could connect to it.
+        let _ = fs::remove_file(self.dir.join("server.sock"));
+    }
+
+    /// Runs `ringboard-server --fsck` against this server's database.
+    pub fn fsck(&self) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_ringboard-server"))
+            .arg("--fsck")
+            .env("RINGBOARD_DATA_DIR", self.data_dir())
+            .env("RINGBOARD_SOCK", self.socket_file())
+            .env("RUST_LOG", "info")
+            .output()
+            .unwrap()
+    }
+
+    /// Where the server keeps its database.
+    pub fn data_dir(&self) -> PathBuf {
+        self.dir.join("clipboard-history")
+    }
+
+    /// Where the server listens for clients.
+    pub fn socket_file(&self) -> PathBuf {
+        self.dir.join("server.sock")
+    }
+
+    pub fn open_fds(&self) -> usize {
+        fs::read_dir(format!("/proc/{}/fd", self.process.id()))
+            .unwrap()
+            .count()
+    }
+}
+
+/// Whether this kernel lets us set up an `io_uring`, which the server can't run
+/// without. Old kernels, seccomp filters, and many containers refuse it, so
+/// tests check at runtime and skip themselves instead of failing.
+pub fn io_uring_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| match io_uring::IoUring::new(2) {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("Skipping server tests, io_uring is unavailable: {e}");
+            false
+        }
+    })
+}
+
+/// Creates a data directory that only we can access, as the server requires.
+/// Directories left behind by earlier runs are locked down too.
+pub fn create_data_dir(data_dir: &Path) {
+    fs::create_dir_all(data_dir).unwrap();
+    fs::set_permissions(data_dir, Permissions::from_mode(0o700)).unwrap();
+}
+
+fn spawn(dir: &Path, args: &[&OsStr]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_ringboard-server"))
+        .args(args)
+        .env("RINGBOARD_DATA_DIR", dir.join("clipboard-history"))
+        .env("RINGBOARD_SOCK", dir.join("server.sock"))
+        .env("RUST_LOG", "info")
+        .stderr(File::create(dir.join("server.log")).unwrap())
+        .spawn()
+        .unwrap()
+}
+
+/// Sends `request` with `data` attached and waits for its response.
+pub fn request<T: Copy>(client: &OwnedFd, request: &Request, data: Option<&OwnedFd>) -> T {
+    let fds = data.map(AsFd::as_fd);
+    let mut space = [0; rustix::cmsg_space!(ScmRights(1))];
+    let mut ancillary = SendAncillaryBuffer::new(&mut space);
+    if let Some(fd) = &fds {
+        assert!(ancillary.push(SendAncillaryMessage::ScmRights(std::slice::from_ref(fd))));
+    }
+    sendmsg(
+        client,
+        &[IoSlice::new(request.as_bytes())],
+        &mut ancillary,
+        SendFlags::empty(),
+    )
+    .unwrap();
+
+    let mut buf = vec![0u8; size_of::<Response<T>>() + 1];
+    let len = recv(client, &mut buf, RecvFlags::empty()).unwrap();
+    assert_eq!(len, size_of::<Response<T>>());
+    unsafe { buf.as_ptr().cast::<Response<T>>().read_unaligned().value }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{env, fs::File, io::Read, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::{
+    checksums::Verification,
+    encoding,
+    encoding::Encoding,
+    protocol::{AddResponse, MimeType, RemoveResponse, Request, RestoreResponse, RingKind},
+    Error as CoreError,
+};
+use ringboard_sdk::{
+    api::{fetch, remote_search, EntryPayload},
+    ClientError, DatabaseReader, EntryReader,
+};
+use rustix::fs::{fsetxattr, memfd_create, MemfdFlags, XattrFlags};
+
+mod common;
+
+const SETTINGS: &str = "compress_text_above = 8192";
+
+fn add(client: &OwnedFd, data: &[u8]) -> u64 {
+    let file = memfd_create(c"ringboard-compression", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn direct_file(server: &Server, id: u64) -> File {
+    File::open(server.data_dir().join("direct").join(format!("{id:0>13}"))).unwrap()
+}
+
+fn fetched(client: &OwnedFd, id: u64) -> Result<Vec<u8>, ClientError> {
+    let (_, payload) = fetch(client, id)?;
+    let EntryPayload::File(fd) = payload else {
+        panic!("Large entries should be sent as files: {payload:?}");
+    };
+    let mut data = Vec::new();
+    File::from(fd).read_to_end(&mut data).unwrap();
+    Ok(data)
+}
+
+/// Text that compresses well but isn't the same entry every time.
+fn text(seed: &str) -> Vec<u8> {
+    (0..2000)
+        .flat_map(|i| format!("{seed} line {i}\n").into_bytes())
+        .collect()
+}
+
+#[test]
+fn large_text_is_stored_compressed_and_read_back_transparently() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-compression-{}", process::id())),
+        SETTINGS,
+    );
+    let client = server.connect();
+    let data = text("needle");
+    let id = add(&client, &data);
+
+    let file = direct_file(&server, id);
+    assert_eq!(encoding::read(&file).unwrap(), Some(Encoding::Zstd));
+    assert!(file.metadata().unwrap().len() < u64::try_from(data.len()).unwrap() / 5);
+
+    assert_eq!(fetched(&client, id).unwrap(), data);
+    let mut dir = server.data_dir();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+    let entry = database.get_raw(id).unwrap();
+    let loaded = entry.to_slice(&reader).unwrap();
+    assert_eq!(&**loaded, data);
+    // Attributes are still read from the stored file.
+    assert_eq!(loaded.verify().unwrap(), Verification::Ok);
+    let mut contents = Vec::new();
+    entry
+        .to_file(&reader)
+        .unwrap()
+        .read_to_end(&mut contents)
+        .unwrap();
+    assert_eq!(contents, data);
+
+    let matches = remote_search(&client, "needle line 1999", false, u32::MAX)
+        .unwrap()
+        .map(|m| m.unwrap().id)
+        .collect::<Vec<_>>();
+    assert_eq!(matches, [id]);
+    server.assert_responsive();
+}
+
+#[test]
+fn poorly_compressing_and_small_text_is_stored_as_is() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-compression-skip-{}", process::id())),
+        SETTINGS,
+    );
+    let client = server.connect();
+    // Printable noise barely compresses.
+    let mut state = 0x2545_f491_u32;
+    let noise = (0..16 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            b'!' + u8::try_from(state % 94).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let incompressible = add(&client, &noise);
+    let below_threshold = add(&client, &text("small")[..6000]);
+
+    for (id, data) in [
+        (incompressible, &noise[..]),
+        (below_threshold, &text("small")[..6000]),
+    ] {
+        let file = direct_file(&server, id);
+        assert_eq!(encoding::read(&file).unwrap(), None);
+        assert_eq!(fetched(&client, id).unwrap(), data);
+    }
+}
+
+#[test]
+fn restored_entries_are_decompressed_before_being_added_again() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-compression-restore-{}", process::id())),
+        SETTINGS,
+    );
+    let client = server.connect();
+    let data = text("restored");
+    let id = add(&client, &data);
+    let RemoveResponse { error } = request(&client, &Request::Remove { id }, None);
+    assert!(error.is_none(), "{error:?}");
+
+    let RestoreResponse::Added(AddResponse::Success { id, .. }) =
+        request(&client, &Request::Restore { tombstone_id: id }, None)
+    else {
+        panic!("Failed to restore entry {id}.");
+    };
+    assert_eq!(
+        encoding::read(direct_file(&server, id)).unwrap(),
+        Some(Encoding::Zstd)
+    );
+    assert_eq!(fetched(&client, id).unwrap(), data);
+}
+
+#[test]
+fn unknown_encodings_are_reported() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-compression-unknown-{}", process::id())),
+        SETTINGS,
+    );
+    let client = server.connect();
+    let id = add(&client, &text("future"));
+    fsetxattr(
+        direct_file(&server, id),
+        encoding::XATTR_NAME,
+        b"brotli",
+        XattrFlags::REPLACE,
+    )
+    .unwrap();
+
+    let Err(ClientError::Core(CoreError::Io { error, .. })) = fetched(&client, id) else {
+        panic!("Unknown encodings should fail to read.");
+    };
+    assert!(
+        error.to_string().contains("unsupported encoding"),
+        "{error}"
+    );
+    let mut dir = server.data_dir();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+    assert!(database.get_raw(id).unwrap().to_slice(&reader).is_err());
+}
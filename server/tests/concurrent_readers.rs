@@ -0,0 +1,176 @@
+use std::{
+    env, io,
+    io::ErrorKind,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+};
+
+use common::Server;
+use ringboard_core::{
+    protocol::{AddResponse, MimeType, RingKind},
+    slot_generations,
+    slot_generations::{SlotGenerations, SlotGenerationsWriter},
+};
+use ringboard_sdk::{api::AddRequest, DatabaseReader, EntryReader, Kind};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+const ENTRIES: usize = 1500;
+const READERS: usize = 4;
+/// The main ring's capacity, which each pass of a reader looks through in full.
+/// It's small so that the server keeps reusing the slots readers are reading.
+const WINDOW: usize = 32;
+
+/// Entry `n` is its zero-padded number and a dash repeated enough times to
+/// land in a bucket, or every tenth time, a direct file.
+fn entry_text(n: usize) -> String {
+    let repeats = if n % 10 == 9 { 700 } else { 1 + n % 20 };
+    format!("{n:06}-").repeat(repeats)
+}
+
+/// Entries describe themselves, so any mix of two entries or of an entry and
+/// unwritten space won't pass.
+fn is_whole_entry(bytes: &[u8]) -> bool {
+    let Some(chunk) = bytes.get(..7) else {
+        return false;
+    };
+    chunk[..6].iter().all(u8::is_ascii_digit)
+        && chunk[6] == b'-'
+        && bytes.chunks(7).all(|c| c == chunk)
+}
+
+#[test]
+fn readers_never_see_torn_entries_while_the_server_writes() {
+    if !common::io_uring_available() {
+        return;
+    }
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-concurrent-{}", process::id())),
+        &format!("main_max_entries = {WINDOW}\n"),
+    );
+    server.assert_responsive();
+
+    let done = AtomicBool::new(false);
+    let reads = AtomicUsize::new(0);
+    let torn = thread::scope(|s| {
+        s.spawn(|| {
+            let client = server.connect();
+            for n in 0..ENTRIES {
+                let data = memfd_create(c"ringboard-concurrent", MemfdFlags::empty()).unwrap();
+                rustix::io::write(&data, entry_text(n).as_bytes()).unwrap();
+                rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+                let response =
+                    AddRequest::response(&client, RingKind::Main, MimeType::new(), &data).unwrap();
+                assert!(matches!(response, AddResponse::Success { .. }));
+            }
+            done.store(true, Ordering::Release);
+        });
+
+        let readers = (0..READERS)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut dir = server.data_dir();
+                    let reader = EntryReader::open(&mut dir).unwrap();
+                    let generations =
+                        SlotGenerations::open(&dir.join(slot_generations::FILE_NAME)).unwrap();
+                    let mut torn = Vec::new();
+                    while !done.load(Ordering::Acquire) {
+                        // Reopen to pick up the ring's new length.
+                        let database = DatabaseReader::open(&mut dir).unwrap();
+                        for entry in database.main().rev() {
+                            let load = || {
+                                entry
+                                    .to_slice(&reader)
+                                    .map(|bytes| (!is_whole_entry(&bytes)).then(|| bytes.to_vec()))
+                            };
+                            let loaded = match entry.kind() {
+                                // Loaded slots are read in place and may be reused right
+                                // away, so they're checked before the server gets to them.
+                                Kind::Bucket(slot) => {
+                                    let Some(loaded) = generations.read(slot, load) else {
+                                        continue;
+                                    };
+                                    loaded
+                                }
+                                Kind::File => load(),
+                            };
+                            let torn_bytes = match loaded {
+                                Ok(torn_bytes) => torn_bytes,
+                                // Overwritten since the ring was read.
+                                Err(ringboard_core::Error::IdNotFound(_)) => continue,
+                                Err(ringboard_core::Error::Io { error, .. })
+                                    if error.kind() == ErrorKind::NotFound =>
+                                {
+                                    continue;
+                                }
+                                Err(e) => panic!("Failed to load entry {}: {e:?}", entry.id()),
+                            };
+                            if let Some(bytes) = torn_bytes {
+                                torn.push((entry.id(), bytes));
+                            }
+                            reads.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    torn
+                })
+            })
+            .collect::<Vec<_>>();
+        readers
+            .into_iter()
+            .flat_map(|r| r.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    assert!(reads.load(Ordering::Relaxed) > 0);
+    assert!(torn.is_empty(), "Torn reads: {torn:?}");
+    server.assert_responsive();
+}
+
+#[test]
+fn slots_left_mid_write_are_repaired_after_crashes() {
+    let mut server =
+        Server::start(env::temp_dir().join(format!("ringboard-mid-write-{}", process::id())));
+    let client = server.connect();
+    let data = memfd_create(c"ringboard-mid-write", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, b"written").unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let AddResponse::Success { id, .. } =
+        AddRequest::response(&client, RingKind::Main, MimeType::new(), &data).unwrap()
+    else {
+        panic!("Failed to add entry");
+    };
+    drop(client);
+    server.crash();
+
+    let mut dir = server.data_dir();
+    let load = |dir: &mut PathBuf| {
+        let database = DatabaseReader::open(dir).unwrap();
+        let reader = EntryReader::open(dir).unwrap();
+        let entry = database.get_raw(id).unwrap();
+        entry.to_slice(&reader).map(|bytes| bytes.to_vec())
+    };
+    // A write that fails leaves the slot's word as a crash during the write would.
+    let Kind::Bucket(slot) = DatabaseReader::open(&mut dir)
+        .unwrap()
+        .get_raw(id)
+        .unwrap()
+        .kind()
+    else {
+        panic!("Entry isn't bucketed");
+    };
+    SlotGenerationsWriter::open(&dir.join(slot_generations::FILE_NAME), [])
+        .unwrap()
+        .write(slot, || Err::<(), _>(io::Error::other("crashed")))
+        .unwrap_err();
+    assert!(matches!(
+        load(&mut dir),
+        Err(ringboard_core::Error::IdNotFound(_))
+    ));
+
+    server.restart();
+    server.assert_responsive();
+    assert_eq!(load(&mut dir).unwrap(), b"written");
+}
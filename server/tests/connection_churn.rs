@@ -0,0 +1,77 @@
+use std::{env, process, thread, time::Duration};
+
+use common::Server;
+use ringboard_core::{
+    protocol::{Request, StatsResponse},
+    AsBytes,
+};
+use rustix::net::{send, SendFlags};
+
+mod common;
+
+/// Matches the server's default client limit.
+const MAX_NUM_CLIENTS: usize = 128;
+
+#[test]
+fn all_clients_churning_keep_the_server_responsive() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-churn-{}", process::id())));
+    drop(server.connect());
+
+    thread::scope(|s| {
+        for _ in 0..MAX_NUM_CLIENTS {
+            s.spawn(|| {
+                // Stay within the rate limiter's burst allowance.
+                for _ in 0..6 {
+                    let client = server.connect();
+                    for _ in 0..4 {
+                        send(&client, Request::ServerInfo.as_bytes(), SendFlags::empty()).unwrap();
+                    }
+                    // Hang up without reading the responses.
+                }
+            });
+        }
+    });
+    server.assert_responsive();
+}
+
+#[test]
+fn every_client_slot_can_be_used_at_once() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-churn-full-{}", process::id())));
+
+    let clients = (0..MAX_NUM_CLIENTS)
+        .map(|_| server.connect())
+        .collect::<Vec<_>>();
+    for client in &clients {
+        let stats = common::request::<StatsResponse>(client, &Request::Stats, None);
+        assert_eq!(
+            stats.connected_clients,
+            u32::try_from(MAX_NUM_CLIENTS).unwrap()
+        );
+    }
+}
+
+#[test]
+fn connection_storms_wait_for_free_slots() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-churn-storm-{}", process::id())),
+        "max_clients = 4\n",
+    );
+
+    let held = (0..4).map(|_| server.connect()).collect::<Vec<_>>();
+    thread::scope(|s| {
+        for _ in 0..16 {
+            s.spawn(|| {
+                // Blocks until one of the held clients makes room.
+                let client = server.connect();
+                let stats = common::request::<StatsResponse>(&client, &Request::Stats, None);
+                assert!(stats.connected_clients <= 4);
+            });
+        }
+
+        // Let the storm pile up against the limit before any slot frees up.
+        thread::sleep(Duration::from_millis(200));
+        drop(held);
+    });
+    server.assert_responsive();
+}
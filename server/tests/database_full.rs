@@ -0,0 +1,72 @@
+use std::{env, fs, path::PathBuf, process};
+
+use common::{create_data_dir, request, Server};
+use ringboard_core::protocol::{AddResponse, MimeType, Request, RingKind};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    io::Errno,
+    mount::{mount, unmount, MountFlags, UnmountFlags},
+};
+
+mod common;
+
+/// Unmounts the database's file system once its server is gone.
+struct Unmount(PathBuf);
+
+impl Drop for Unmount {
+    fn drop(&mut self) {
+        let _ = unmount(&self.0, UnmountFlags::DETACH);
+        if let Some(dir) = self.0.parent() {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[test]
+fn adds_are_rejected_once_the_database_is_full() {
+    let dir = env::temp_dir().join(format!("ringboard-database-full-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let data_dir = dir.join("clipboard-history");
+    create_data_dir(&data_dir);
+    match mount(
+        c"tmpfs",
+        &data_dir,
+        c"tmpfs",
+        MountFlags::empty(),
+        c"size=1m,mode=0700",
+    ) {
+        Err(e @ (Errno::PERM | Errno::ACCESS)) => {
+            eprintln!("Skipping database full test, tmpfs can't be mounted: {e}");
+            return;
+        }
+        r => r.unwrap(),
+    }
+    let _unmount = Unmount(data_dir);
+
+    let server = Server::start(dir);
+    let client = server.connect();
+    let mut data = vec![0x42; 128 << 10];
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::from("image/png").unwrap(),
+        copied_at: 0,
+    };
+    let mut added = 0;
+    let full = loop {
+        assert!(added < 16, "Database never filled up.");
+        // Distinct data so nothing is deduplicated.
+        data[0] = added;
+        let file = memfd_create(c"ringboard-database-full", MemfdFlags::empty()).unwrap();
+        rustix::io::write(&file, &data).unwrap();
+        rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+        match request(&client, &add, Some(&file)) {
+            AddResponse::Success { .. } => added += 1,
+            response => break response,
+        }
+    };
+    assert!(matches!(full, AddResponse::DatabaseFull), "{full:?}");
+    assert!(added > 0);
+    server.assert_responsive();
+    assert!(server.log().contains("Rejecting new entry"));
+}
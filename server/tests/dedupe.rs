@@ -0,0 +1,64 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{
+    composite_id, AddResponse, MimeType, RemoveResponse, Request, RingKind, StatsResponse,
+};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, to: RingKind, data: &[u8]) -> (u64, bool) {
+    let file = memfd_create(c"ringboard-dedupe", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success {
+            id, deduplicated, ..
+        } => (id, deduplicated),
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+#[test]
+fn identical_adds_move_the_recent_entry_to_the_front() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-dedupe-{}", process::id())),
+        // Identical adds made back to back would otherwise be coalesced.
+        "coalesce_adds = false\n",
+    );
+    let client = server.connect();
+    let main = |index| composite_id(RingKind::Main, index);
+
+    assert_eq!(add(&client, RingKind::Main, b"hello"), (main(0), false));
+    assert_eq!(add(&client, RingKind::Main, b"world"), (main(1), false));
+    assert_eq!(add(&client, RingKind::Main, b"hello"), (main(2), true));
+    let removed = request::<RemoveResponse>(&client, &Request::Remove { id: main(0) }, None);
+    assert!(
+        removed.error.is_some(),
+        "The moved entry's old id still exists."
+    );
+
+    // Large entries are stored in their own files.
+    let large = [b'x'; 5000];
+    assert_eq!(add(&client, RingKind::Main, &large), (main(3), false));
+    // It's already at the front, so it stays put.
+    assert_eq!(add(&client, RingKind::Main, &large), (main(3), true));
+    let mut different = large;
+    different[4999] = b'y';
+    assert_eq!(add(&client, RingKind::Main, &different), (main(4), false));
+
+    // Entries are only deduplicated within the ring they're added to.
+    assert_eq!(
+        add(&client, RingKind::Favorites, b"hello"),
+        (composite_id(RingKind::Favorites, 0), false)
+    );
+    let stats = request::<StatsResponse>(&client, &Request::Stats, None);
+    assert_eq!(stats.deduplicated_adds, 2);
+}
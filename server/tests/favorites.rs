@@ -0,0 +1,109 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{
+    AddResponse, MimeType, MoveToFrontResponse, RemoveResponse, Request, RingKind, StatsResponse,
+    SwapResponse,
+};
+use ringboard_sdk::{DatabaseReader, Entry, EntryReader};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, to: RingKind, text: &str) -> u64 {
+    let data = memfd_create(c"ringboard-favorites", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text.as_bytes()).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&data)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+#[test]
+fn full_favorites_refuse_new_ones() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-favorites-{}", process::id())));
+    let client = server.connect();
+    let max = RingKind::Favorites.default_max_entries();
+    let favorites = (0..max)
+        .map(|i| add(&client, RingKind::Favorites, &format!("snippet {i}")))
+        .collect::<Vec<_>>();
+    let entry = add(&client, RingKind::Main, "entry");
+
+    let favorite = Request::MoveToFront {
+        id: entry,
+        to: Some(RingKind::Favorites),
+    };
+    assert!(matches!(
+        request(&client, &favorite, None),
+        MoveToFrontResponse::FavoritesFull
+    ));
+    let stats = request::<StatsResponse>(&client, &Request::Stats, None);
+    assert_eq!((stats.favorites, stats.max_favorites), (max, max));
+
+    // Removing the oldest favorite makes room without evicting anything.
+    let remove = Request::Remove { id: favorites[0] };
+    let RemoveResponse { error } = request(&client, &remove, None);
+    assert!(error.is_none());
+    assert!(matches!(
+        request(&client, &favorite, None),
+        MoveToFrontResponse::Success { .. }
+    ));
+    let stats = request::<StatsResponse>(&client, &Request::Stats, None);
+    assert_eq!(stats.favorites, max);
+}
+
+fn swap(client: &OwnedFd, id1: u64, id2: u64) {
+    let SwapResponse { error1, error2 } = request(client, &Request::Swap { id1, id2 }, None);
+    assert!(
+        error1.is_none() && error2.is_none(),
+        "{error1:?} {error2:?}"
+    );
+}
+
+/// The favorites' contents, oldest first.
+fn favorites(server: &Server) -> Vec<String> {
+    let mut dir = server.data_dir();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+    let text = |entry: Entry| String::from_utf8(entry.to_slice(&reader).unwrap().to_vec()).unwrap();
+    let favorites = database.favorites().map(text).collect::<Vec<_>>();
+    let newest_first = database.favorites().rev().map(text).collect::<Vec<_>>();
+    assert!(favorites.iter().eq(newest_first.iter().rev()));
+    favorites
+}
+
+#[test]
+fn reordered_favorites_are_read_in_their_new_order() {
+    let server = Server::start(
+        env::temp_dir().join(format!("ringboard-favorites-reorder-{}", process::id())),
+    );
+    let client = server.connect();
+    let [id_a, id_b, id_c, id_d] =
+        ["a", "b", "c", "d"].map(|s| add(&client, RingKind::Favorites, s));
+
+    // The newest favorite sits right behind the write head.
+    swap(&client, id_d, id_c);
+    assert_eq!(favorites(&server), ["a", "b", "d", "c"]);
+    swap(&client, id_a, id_d);
+    assert_eq!(favorites(&server), ["c", "b", "d", "a"]);
+
+    let RemoveResponse { error } = request(&client, &Request::Remove { id: id_b }, None);
+    assert!(error.is_none());
+    assert_eq!(favorites(&server), ["c", "d", "a"]);
+    // Swapping across the removed slot leaves it empty.
+    swap(&client, id_a, id_c);
+    assert_eq!(favorites(&server), ["d", "c", "a"]);
+
+    let id_e = add(&client, RingKind::Favorites, "e");
+    assert_eq!(favorites(&server), ["d", "c", "a", "e"]);
+    swap(&client, id_e, id_d);
+    assert_eq!(favorites(&server), ["d", "c", "e", "a"]);
+}
@@ -0,0 +1,64 @@
+use std::{env, fs::File, io::Read, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::{
+    protocol::{composite_id, AddResponse, IdNotFoundError, MimeType, Request, RingKind},
+    Error as CoreError,
+};
+use ringboard_sdk::{
+    api::{fetch, EntryPayload},
+    ClientError,
+};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, data: &[u8], mime_type: &str) -> u64 {
+    let file = memfd_create(c"ringboard-fetch", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::from(mime_type).unwrap(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+#[test]
+fn entries_are_fetched_through_the_server() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-fetch-{}", process::id())));
+    let client = server.connect();
+    let small = add(&client, b"hello", "");
+    let image = vec![42; 64 * 1024];
+    let large = add(&client, &image, "image/png");
+
+    let (mime_type, payload) = fetch(&client, small).unwrap();
+    assert_eq!(mime_type, MimeType::new());
+    assert!(matches!(payload, EntryPayload::Inline(data) if data == b"hello"));
+
+    let (mime_type, payload) = fetch(&client, large).unwrap();
+    assert_eq!(mime_type, MimeType::from("image/png").unwrap());
+    let EntryPayload::File(fd) = payload else {
+        panic!("Large entries should be sent as files: {payload:?}");
+    };
+    let mut data = Vec::new();
+    File::from(fd).read_to_end(&mut data).unwrap();
+    assert_eq!(data, image);
+
+    assert!(matches!(
+        fetch(&client, composite_id(RingKind::Main, 100)),
+        Err(ClientError::Core(CoreError::IdNotFound(
+            IdNotFoundError::Entry(100)
+        )))
+    ));
+    // The connection is still usable after a failed fetch.
+    assert!(matches!(
+        fetch(&client, small),
+        Ok((_, EntryPayload::Inline(_)))
+    ));
+}
@@ -0,0 +1,152 @@
+use std::{env, fs, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{
+    AddResponse, IdNotFoundError, MimeType, ReadOutcome, ReadResponse, RemoveResponse, Request,
+    RingKind, StatsResponse,
+};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    process::Signal,
+};
+
+mod common;
+
+fn add(client: &OwnedFd, text: &[u8]) -> u64 {
+    let data = memfd_create(c"ringboard-free-lists", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&data)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+/// The number of bucket slots and how many of them are free.
+fn slots(client: &OwnedFd) -> (u32, u32) {
+    let stats = request::<StatsResponse>(client, &Request::Stats, None);
+    stats.buckets.iter().fold((0, 0), |(slots, free), b| {
+        (slots + b.slots, free + b.free_slots)
+    })
+}
+
+fn assert_contents(client: &OwnedFd, id: u64, text: &[u8]) {
+    let read = Request::Read {
+        id,
+        offset: 0,
+        len: u32::MAX,
+    };
+    match request(client, &read, None) {
+        response @ ReadResponse {
+            outcome: ReadOutcome::Inline,
+            ..
+        } => assert_eq!(response.data(), text),
+        response => panic!("Failed to read entry {id}: {response:?}"),
+    }
+}
+
+/// Removes entries, restarts the server with `stop`, and checks that new
+/// entries go into the freed slots instead of growing the buckets.
+fn freed_slots_survive_restarts(name: &str, stop: impl FnOnce(&mut Server)) -> Server {
+    let mut server =
+        Server::start(env::temp_dir().join(format!("ringboard-{name}-{}", process::id())));
+    let client = server.connect();
+    let texts = [b"first", b"secnd", b"third", b"forth"];
+    let ids = texts.map(|text| add(&client, text));
+    for &id in &ids[1..3] {
+        let RemoveResponse { error } = request(&client, &Request::Remove { id }, None);
+        assert!(error.is_none(), "{error:?}");
+    }
+    assert_eq!(slots(&client), (4, 2));
+    drop(client);
+
+    stop(&mut server);
+    server.restart();
+    let client = server.connect();
+    assert_eq!(slots(&client), (4, 2));
+    let added = [b"fifth", b"sixth"].map(|text| add(&client, text));
+    assert_eq!(slots(&client), (4, 0));
+
+    for (id, text) in [ids[0], ids[3]].into_iter().zip([texts[0], texts[3]]) {
+        assert_contents(&client, id, text);
+    }
+    for (id, text) in added.into_iter().zip([b"fifth", b"sixth"]) {
+        assert_contents(&client, id, text);
+    }
+    server
+}
+
+#[test]
+fn freed_slots_are_reused_after_clean_shutdowns() {
+    let server = freed_slots_survive_restarts("free-lists-clean", |server| {
+        server.signal(Signal::Term);
+    });
+    assert!(
+        !server
+            .log()
+            .contains("Reconstructing allocator free lists.")
+    );
+}
+
+#[test]
+fn freed_slots_are_reused_after_crashes() {
+    let server = freed_slots_survive_restarts("free-lists-crash", Server::crash);
+    let log = server.log();
+    assert!(
+        log.contains("Reconstructing allocator free lists."),
+        "{log}"
+    );
+}
+
+#[test]
+fn recovery_drops_entries_in_free_slots() {
+    let mut server =
+        Server::start(env::temp_dir().join(format!("ringboard-free-stale-{}", process::id())));
+    let client = server.connect();
+    let id = add(&client, b"gone");
+    let RemoveResponse { error } = request(&client, &Request::Remove { id }, None);
+    assert!(error.is_none(), "{error:?}");
+    drop(client);
+    server.signal(Signal::Term);
+    server.restart();
+    let stale = fs::read(server.data_dir().join("free-lists")).unwrap();
+
+    // The new entry takes the freed slot, so the old free lists are out of date.
+    let client = server.connect();
+    let reused = add(&client, b"here");
+    drop(client);
+    server.signal(Signal::Term);
+    server.wait();
+    let data_dir = server.data_dir();
+    fs::write(data_dir.join("free-lists"), stale).unwrap();
+    fs::write(data_dir.join("recovery-requested"), "").unwrap();
+
+    server.restart();
+    let client = server.connect();
+    let read = Request::Read {
+        id: reused,
+        offset: 0,
+        len: u32::MAX,
+    };
+    let response = request::<ReadResponse>(&client, &read, None);
+    assert!(
+        matches!(
+            response.outcome,
+            ReadOutcome::Error(IdNotFoundError::Entry(_))
+        ),
+        "{response:?}"
+    );
+    let log = server.log();
+    assert!(log.contains("Recovery requested"), "{log}");
+    assert!(
+        log.contains("dropped 1 entries with missing storage"),
+        "{log}"
+    );
+    assert!(!data_dir.join("recovery-requested").exists());
+}
@@ -0,0 +1,62 @@
+use std::{env, fs, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{composite_id, AddResponse, MimeType, Request, RingKind};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add_image(client: &OwnedFd, data: &[u8]) -> u64 {
+    let file = memfd_create(c"ringboard-fsck", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::from("image/png").unwrap(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+#[test]
+fn fsck_repairs_a_crashed_database() {
+    let dir = env::temp_dir().join(format!("ringboard-fsck-{}", process::id()));
+    let mut server = Server::start(dir.clone());
+    let client = server.connect();
+    let kept = add_image(&client, b"not really a png");
+    let lost = add_image(&client, b"not a png either");
+    let corrupted = add_image(&client, b"a png gone bad");
+    drop(client);
+    server.crash();
+
+    let data_dir = dir.join("clipboard-history");
+    let direct = |id: u64| data_dir.join("direct").join(format!("{id:0>13}"));
+    fs::remove_file(direct(lost)).unwrap();
+    fs::write(direct(corrupted), "a png gone mad").unwrap();
+    let orphan = direct(composite_id(RingKind::Main, 1000));
+    fs::write(&orphan, "orphan").unwrap();
+    let unknown = data_dir.join("direct").join("notes.txt");
+    fs::write(&unknown, "mine").unwrap();
+    assert!(data_dir.join("server.lock").exists());
+
+    let output = server.fsck();
+    let log = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{log}");
+    assert!(
+        log.contains(
+            "dropped 1 entries with missing storage, found 1 corrupted direct file(s), and \
+             removed 1 orphaned"
+        ),
+        "{log}"
+    );
+    assert!(direct(kept).exists());
+    assert!(log.contains("Checksum mismatch for Main entry 2."), "{log}");
+    assert!(direct(corrupted).exists());
+    assert!(!orphan.exists());
+    assert!(unknown.exists());
+    assert!(!data_dir.join("server.lock").exists());
+}
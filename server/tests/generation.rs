@@ -0,0 +1,72 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::{
+    generation,
+    generation::Generation,
+    protocol::{
+        composite_id, AddResponse, ListResponse, MimeType, Request, RingKind, SwapResponse,
+    },
+};
+use ringboard_sdk::{api::GenerationRequest, EntrySource, RemoteReader};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    net::SocketAddrUnix,
+};
+
+mod common;
+
+fn generation(client: &OwnedFd) -> u64 {
+    GenerationRequest::response(client).unwrap().generation
+}
+
+#[test]
+fn generation_is_bumped_by_changes_only() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-generation-{}", process::id())));
+    let client = server.connect();
+    let file = Generation::open(&server.data_dir().join(generation::FILE_NAME)).unwrap();
+    let start = generation(&client);
+    assert_eq!(file.get(), start);
+
+    let data = memfd_create(c"ringboard-generation", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, b"hello").unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    assert!(matches!(
+        request(&client, &add, Some(&data)),
+        AddResponse::Success { .. }
+    ));
+    let changed = generation(&client);
+    assert_ne!(changed, start);
+    assert_eq!(file.get(), changed);
+
+    let list = Request::List {
+        ring: RingKind::Main,
+        start: 0,
+        count: 1,
+    };
+    let _: ListResponse = request(&client, &list, None);
+    assert_eq!(generation(&client), changed);
+
+    let swap = Request::Swap {
+        id1: composite_id(RingKind::Main, 0),
+        id2: composite_id(RingKind::Main, 42),
+    };
+    let SwapResponse { error1, error2 } = request(&client, &swap, None);
+    assert!(
+        error1.is_none() && error2.is_some(),
+        "{error1:?} {error2:?}"
+    );
+    assert_eq!(generation(&client), changed);
+
+    // Readers without access to the database ask the server instead.
+    let addr = SocketAddrUnix::new(server.data_dir().with_file_name("server.sock")).unwrap();
+    let reader = RemoteReader::connect(&addr).unwrap();
+    assert_eq!(reader.generation(), Some(changed));
+}
@@ -0,0 +1,102 @@
+use std::{
+    env, fs,
+    fs::File,
+    path::{Path, PathBuf},
+    process,
+    process::{Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{create_data_dir, Server};
+
+mod common;
+
+/// A server started next to [`Server`]s on the same database, logging to its
+/// own file.
+fn claimant(dir: &Path, name: &str) -> (Child, PathBuf) {
+    let log = dir.join(format!("{name}.log"));
+    let child = Command::new(env!("CARGO_BIN_EXE_ringboard-server"))
+        .env("RINGBOARD_DATA_DIR", dir.join("clipboard-history"))
+        .env("RINGBOARD_SOCK", dir.join("server.sock"))
+        .env("RUST_LOG", "info")
+        .stderr(File::create(&log).unwrap())
+        .spawn()
+        .unwrap();
+    (child, log)
+}
+
+/// Leaves a lock file naming a live process that isn't a server, as if a
+/// crashed server's PID was reused.
+fn leave_stale_lock(dir: &Path) {
+    let data_dir = dir.join("clipboard-history");
+    create_data_dir(&data_dir);
+    fs::write(data_dir.join("server.lock"), process::id().to_string()).unwrap();
+}
+
+#[test]
+fn live_servers_keep_their_lock() {
+    let dir = env::temp_dir().join(format!("ringboard-lock-live-{}", process::id()));
+    let server = Server::start(dir.clone());
+    server.assert_responsive();
+
+    let (mut second, log) = claimant(&dir, "second");
+    assert!(!second.wait().unwrap().success());
+    let log = fs::read_to_string(log).unwrap();
+    assert!(log.contains("server already running"), "{log}");
+    server.assert_responsive();
+}
+
+#[test]
+fn locks_with_reused_pids_are_adopted() {
+    let dir = env::temp_dir().join(format!("ringboard-lock-reused-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    leave_stale_lock(&dir);
+
+    let server = Server::start(dir);
+    server.assert_responsive();
+    assert!(server.log().contains("Unclean shutdown detected"));
+}
+
+#[test]
+fn racing_servers_resolve_to_one_owner() {
+    let dir = env::temp_dir().join(format!("ringboard-lock-race-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    leave_stale_lock(&dir);
+
+    let mut claimants = (0..8)
+        .map(|i| claimant(&dir, &format!("claimant-{i}")))
+        .collect::<Vec<_>>();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut exited = Vec::new();
+    while exited.len() < claimants.len() - 1 && Instant::now() < deadline {
+        for (i, (child, _)) in claimants.iter_mut().enumerate() {
+            if !exited.contains(&i) && child.try_wait().unwrap().is_some() {
+                exited.push(i);
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    // Give a second winner a chance to show itself.
+    thread::sleep(Duration::from_millis(500));
+
+    let mut winners = Vec::new();
+    for (i, (child, log)) in claimants.iter_mut().enumerate() {
+        match child.try_wait().unwrap() {
+            None => winners.push(child.id()),
+            Some(status) => {
+                assert!(!status.success(), "Claimant {i} exited cleanly.");
+                let log = fs::read_to_string(log).unwrap();
+                assert!(log.contains("server already running"), "{log}");
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+    let [winner] = winners[..] else {
+        panic!("Expected exactly one server to claim the lock: {winners:?}");
+    };
+    let lock = fs::read_to_string(dir.join("clipboard-history").join("server.lock")).unwrap();
+    assert_eq!(lock, winner.to_string());
+    let _ = fs::remove_dir_all(dir);
+}
@@ -0,0 +1,68 @@
+use std::{env, ffi::OsStr, fs, process, thread, time::Duration};
+
+use common::{request, Server};
+use ringboard_core::protocol::{Request, ServerInfoResponse};
+
+mod common;
+
+#[test]
+fn client_lifecycles_are_logged_as_json_to_the_log_file() {
+    let dir = env::temp_dir().join(format!("ringboard-logging-{}", process::id()));
+    let log_file = dir.join("ringboard.log");
+    let server = Server::start_with_args(
+        dir,
+        "",
+        &[
+            OsStr::new("-v"),
+            OsStr::new("--log-format=json"),
+            OsStr::new("--log-file"),
+            log_file.as_os_str(),
+        ],
+    );
+    let client = server.connect();
+    let info = request::<ServerInfoResponse>(&client, &Request::ServerInfo, None);
+    assert!(!info.version.is_empty());
+    drop(client);
+
+    let disconnected = "disconnected (closed by the client) after 1 request(s) and ";
+    let log = (0..100)
+        .find_map(|_| {
+            let log = fs::read_to_string(&log_file).unwrap_or_default();
+            if log.contains(disconnected) {
+                Some(log)
+            } else {
+                thread::sleep(Duration::from_millis(50));
+                None
+            }
+        })
+        .expect("server never logged the disconnect");
+    for line in log.lines() {
+        assert!(
+            line.starts_with("{\"ts\":") && line.ends_with("\"}"),
+            "{line}"
+        );
+    }
+    assert!(log.contains("connected with protocol v"), "{log}");
+    // Nothing else is written to stderr once the log file is open.
+    assert_eq!(server.log(), "");
+}
+
+#[test]
+fn unusable_log_files_fall_back_to_stderr() {
+    let dir = env::temp_dir().join(format!("ringboard-logging-fallback-{}", process::id()));
+    let log_file = dir.join("missing/ringboard.log");
+    let server = Server::start_with_args(
+        dir,
+        "",
+        &[
+            OsStr::new("--log-file"),
+            log_file.as_os_str(),
+            OsStr::new("--log-format"),
+            OsStr::new("xml"),
+        ],
+    );
+
+    server.assert_responsive();
+    server.wait_for_log("failed to open log file");
+    server.wait_for_log("unknown log format");
+}
@@ -0,0 +1,124 @@
+use std::{
+    env,
+    fs::{self, File},
+    os::fd::OwnedFd,
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::{request, Server};
+use ringboard_core::protocol::{
+    AddResponse, MimeType, ReadOutcome, ReadResponse, Request, RestoreResponse, RingKind,
+    StatsResponse,
+};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    process::Signal,
+};
+
+mod common;
+
+const TWO_HOURS_AGO: Duration = Duration::from_secs(2 * 60 * 60);
+
+fn add(client: &OwnedFd, to: RingKind, text: &[u8], mime_type: &str, age: Duration) -> u64 {
+    let data = memfd_create(c"ringboard-max-age", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let copied_at = SystemTime::now() - age;
+    let add = Request::Add {
+        to,
+        sensitive: false,
+        mime_type: MimeType::from(mime_type).unwrap(),
+        copied_at: copied_at.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+    };
+    match request(client, &add, Some(&data)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn exists(client: &OwnedFd, id: u64) -> bool {
+    let read = Request::Read {
+        id,
+        offset: 0,
+        len: u32::MAX,
+    };
+    !matches!(
+        request::<ReadResponse>(client, &read, None).outcome,
+        ReadOutcome::Error(_)
+    )
+}
+
+#[test]
+fn old_main_entries_are_deleted() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-max-age-{}", process::id())));
+    let client = server.connect();
+    let old = add(&client, RingKind::Main, b"old", "", TWO_HOURS_AGO);
+    let old_file = add(&client, RingKind::Main, b"old", "image/png", TWO_HOURS_AGO);
+    let favorite = add(&client, RingKind::Favorites, b"kept", "", TWO_HOURS_AGO);
+    let fresh = add(&client, RingKind::Main, b"fresh", "", Duration::ZERO);
+
+    server.write_settings("main_max_age = 3600");
+    server.signal(Signal::Hup);
+    server.wait_for_log("Deleted 2 entries older than 3600 seconds.");
+
+    assert!(!exists(&client, old));
+    assert!(!exists(&client, old_file));
+    assert!(exists(&client, favorite));
+    assert!(exists(&client, fresh));
+    // Their data is freed rather than kept around to restore.
+    assert!(matches!(
+        request(&client, &Request::Restore { tombstone_id: old }, None),
+        RestoreResponse::NotInTrash
+    ));
+    let direct_file = server
+        .data_dir()
+        .join("direct")
+        .join(format!("{old_file:0>13}"));
+    assert!(!direct_file.exists());
+    let stats = request::<StatsResponse>(&client, &Request::Stats, None);
+    assert_eq!(stats.aged_out_entries, 2);
+    server.assert_responsive();
+}
+
+#[test]
+fn entries_without_timestamps_fall_back_to_file_times() {
+    let mut server =
+        Server::start(env::temp_dir().join(format!("ringboard-max-age-legacy-{}", process::id())));
+    let client = server.connect();
+    let bucketed = add(&client, RingKind::Main, b"old", "", TWO_HOURS_AGO);
+    let file = add(&client, RingKind::Main, b"old", "image/png", TWO_HOURS_AGO);
+    let fresh_file = add(
+        &client,
+        RingKind::Main,
+        b"fresh",
+        "image/png",
+        Duration::ZERO,
+    );
+    drop(client);
+    server.signal(Signal::Term);
+    server.wait();
+
+    // Databases from before timestamps were recorded only have file times.
+    fs::remove_file(server.data_dir().join("timestamps")).unwrap();
+    File::options()
+        .write(true)
+        .open(
+            server
+                .data_dir()
+                .join("direct")
+                .join(format!("{file:0>13}")),
+        )
+        .unwrap()
+        .set_modified(SystemTime::now() - TWO_HOURS_AGO)
+        .unwrap();
+    server.write_settings("main_max_age = 3600");
+    server.restart();
+    server.wait_for_log("Deleted 1 entries older than 3600 seconds.");
+
+    let client = server.connect();
+    assert!(exists(&client, bucketed));
+    assert!(!exists(&client, file));
+    assert!(exists(&client, fresh_file));
+}
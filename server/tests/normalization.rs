@@ -0,0 +1,55 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{AddResponse, MimeType, ReadOutcome, ReadResponse, Request, RingKind};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, text: &str) -> (u64, bool) {
+    let data = memfd_create(c"ringboard-normalization", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text.as_bytes()).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&data)) {
+        AddResponse::Success { id, normalized, .. } => (id, normalized),
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn read(client: &OwnedFd, id: u64) -> String {
+    let read = Request::Read {
+        id,
+        offset: 0,
+        len: u32::MAX,
+    };
+    match request(client, &read, None) {
+        response @ ReadResponse {
+            outcome: ReadOutcome::Inline,
+            ..
+        } => String::from_utf8(response.data().to_vec()).unwrap(),
+        response => panic!("Failed to read entry {id}: {response:?}"),
+    }
+}
+
+#[test]
+fn tracking_params_are_stripped_when_configured() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-normalization-{}", process::id())),
+        "strip_query_params = utm_*, fbclid\n",
+    );
+    let client = server.connect();
+
+    let (id, normalized) = add(&client, "https://a.com/?utm_source=x&id=1#top");
+    assert!(normalized);
+    assert_eq!(read(&client, id), "https://a.com/?id=1#top");
+
+    let (id, normalized) = add(&client, "https://a.com/?id=1");
+    assert!(!normalized);
+    assert_eq!(read(&client, id), "https://a.com/?id=1");
+}
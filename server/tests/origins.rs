@@ -0,0 +1,69 @@
+use std::{env, fs, os::fd::OwnedFd, process};
+
+use common::{create_data_dir, request, Server};
+use ringboard_core::{
+    origins,
+    origins::Origins,
+    protocol::{decompose_id, AddResponse, MimeType, Request, RingKind, MIN_VERSION, VERSION},
+};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    process::getuid,
+};
+
+mod common;
+
+fn add(client: &OwnedFd, text: &[u8]) -> (RingKind, u32) {
+    let data = memfd_create(c"ringboard-origins", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&data)) {
+        AddResponse::Success { id, .. } => decompose_id(id).unwrap(),
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn named(server: &Server, name: &[u8]) -> OwnedFd {
+    let mut hello = vec![VERSION, u8::try_from(Request::KINDS).unwrap(), MIN_VERSION];
+    hello.extend_from_slice(name);
+    server.handshake(&hello).0
+}
+
+#[test]
+fn entries_remember_who_added_them() {
+    let dir = env::temp_dir().join(format!("ringboard-origins-{}", process::id()));
+    let server = Server::start(dir.clone());
+    let uid = getuid().as_raw();
+
+    let (ring, named_index) = add(&named(&server, b"firefox watcher!"), b"garbage");
+    let (_, unnamed_index) = add(&server.connect(), b"more garbage");
+
+    let origins = Origins::open(&dir.join("clipboard-history").join(origins::FILE_NAME)).unwrap();
+    let origin = origins.get(ring, named_index).unwrap();
+    assert_eq!(origin.name(), "firefox_watcher_");
+    assert_eq!(origin.uid(), uid);
+
+    // Unnamed clients are named after their process.
+    let comm = fs::read_to_string("/proc/self/comm").unwrap();
+    let origin = origins.get(ring, unnamed_index).unwrap();
+    assert_eq!(origin.name(), comm.trim());
+    assert_eq!(origin.uid(), uid);
+}
+
+#[test]
+fn origins_are_deleted_when_disabled() {
+    let dir = env::temp_dir().join(format!("ringboard-origins-disabled-{}", process::id()));
+    let file = dir.join("clipboard-history").join(origins::FILE_NAME);
+    create_data_dir(file.parent().unwrap());
+    fs::write(&file, [1; 64]).unwrap();
+    let server = Server::start_with_settings(dir, "record_origins = false\n");
+
+    add(&named(&server, b"firefox-watcher"), b"garbage");
+    assert!(!file.exists());
+}
@@ -0,0 +1,253 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::Server;
+use ringboard_core::{
+    packed,
+    protocol::{
+        AddResponse, GarbageCollectResponse, MimeType, MoveToFrontResponse, RemoveResponse,
+        RingKind, StatsResponse, SwapResponse,
+    },
+};
+use ringboard_sdk::{
+    api::{
+        connect_to_server, fetch, remote_search, AddRequest, EntryPayload, GarbageCollectRequest,
+        MoveToFrontRequest, RemoveRequest, StatsRequest, SwapRequest,
+    },
+    DatabaseReader, EntryReader,
+};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    net::SocketAddrUnix,
+    process::Signal,
+};
+
+mod common;
+
+const SETTINGS: &str = "pack_small_entries = true";
+
+/// Starts a server for `name`, or returns nothing if this machine can't run
+/// one.
+fn start(name: &str, settings: &str) -> Option<Server> {
+    if !common::io_uring_available() {
+        return None;
+    }
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-packed-{name}-{}", process::id())),
+        settings,
+    );
+    server.assert_responsive();
+    Some(server)
+}
+
+fn connect(server: &Server) -> OwnedFd {
+    connect_to_server(&SocketAddrUnix::new(server.socket_file()).unwrap()).unwrap()
+}
+
+fn add(client: &OwnedFd, to: RingKind, data: &[u8]) -> u64 {
+    let file = memfd_create(c"ringboard-packed", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    match AddRequest::response(client, to, MimeType::new(), &file).unwrap() {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn remove(client: &OwnedFd, id: u64) {
+    let RemoveResponse { error } = RemoveRequest::response(client, id).unwrap();
+    assert!(error.is_none(), "{error:?}");
+}
+
+fn stats(client: &OwnedFd) -> StatsResponse {
+    StatsRequest::response(client).unwrap()
+}
+
+/// Every entry as read by a client and by the server, favorites first.
+fn contents(server: &Server, client: &OwnedFd) -> Vec<(u64, Vec<u8>)> {
+    let mut dir = server.data_dir();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+    database
+        .favorites()
+        .chain(database.main())
+        .map(|entry| {
+            let data = entry.to_slice(&reader).unwrap().to_vec();
+            let (_, EntryPayload::Inline(fetched)) = fetch(client, entry.id()).unwrap() else {
+                panic!("Short entries should be sent inline.");
+            };
+            assert_eq!(fetched, data);
+            (entry.id(), data)
+        })
+        .collect()
+}
+
+fn texts(contents: &[(u64, Vec<u8>)]) -> Vec<&[u8]> {
+    contents.iter().map(|(_, data)| &data[..]).collect()
+}
+
+#[test]
+fn short_text_is_packed_and_read_back() {
+    let Some(server) = start("read", SETTINGS) else {
+        return;
+    };
+    let client = connect(&server);
+    let hello = add(&client, RingKind::Main, b"hello");
+    let longest = add(&client, RingKind::Main, &[b'p'; packed::MAX_LEN as usize]);
+    let bucketed = add(
+        &client,
+        RingKind::Main,
+        &[b'b'; packed::MAX_LEN as usize + 1],
+    );
+
+    let stats = stats(&client);
+    let usage = stats.packed.unwrap();
+    assert_eq!(usage.entries, 2);
+    assert_eq!(usage.live_bytes, 5 + u64::from(packed::MAX_LEN));
+    assert_eq!(usage.log_bytes, usage.live_bytes);
+    assert_eq!(stats.buckets.iter().map(|b| b.slots).sum::<u32>(), 1);
+
+    assert_eq!(
+        contents(&server, &client),
+        [
+            (hello, b"hello".to_vec()),
+            (longest, vec![b'p'; packed::MAX_LEN as usize]),
+            (bucketed, vec![b'b'; packed::MAX_LEN as usize + 1]),
+        ]
+    );
+    let matches = remote_search(&client, "ell", false, u32::MAX)
+        .unwrap()
+        .map(|m| m.unwrap().id)
+        .collect::<Vec<_>>();
+    assert_eq!(matches, [hello]);
+}
+
+#[test]
+fn packed_entries_follow_moves_swaps_and_removals() {
+    let Some(server) = start("moves", SETTINGS) else {
+        return;
+    };
+    let client = connect(&server);
+    let first = add(&client, RingKind::Main, b"first");
+    let second = add(&client, RingKind::Main, b"second");
+    let favorite = add(&client, RingKind::Favorites, b"favorite");
+    let bucketed = add(&client, RingKind::Main, &[b'b'; 300]);
+
+    let MoveToFrontResponse::Success { id: moved } =
+        MoveToFrontRequest::response(&client, first, None).unwrap()
+    else {
+        panic!("Failed to move entry.");
+    };
+    assert_eq!(
+        texts(&contents(&server, &client)),
+        [&b"favorite"[..], b"second", &[b'b'; 300], b"first"]
+    );
+
+    let SwapResponse { error1, error2 } =
+        SwapRequest::response(&client, favorite, bucketed).unwrap();
+    assert!(error1.is_none() && error2.is_none());
+    assert_eq!(
+        texts(&contents(&server, &client)),
+        [&[b'b'; 300][..], b"second", b"favorite", b"first"]
+    );
+
+    remove(&client, moved);
+    remove(&client, second);
+    assert_eq!(
+        texts(&contents(&server, &client)),
+        [&[b'b'; 300][..], b"favorite"]
+    );
+    let usage = stats(&client).packed.unwrap();
+    assert_eq!((usage.entries, usage.live_bytes), (1, 8));
+}
+
+#[test]
+fn gc_compacts_the_log() {
+    let Some(server) = start("gc", SETTINGS) else {
+        return;
+    };
+    let client = connect(&server);
+    let ids = (0..100)
+        .map(|i| add(&client, RingKind::Main, format!("entry {i:>3}").as_bytes()))
+        .collect::<Vec<_>>();
+    for &id in ids.iter().step_by(2) {
+        remove(&client, id);
+    }
+    let before = contents(&server, &client);
+    assert_eq!(before.len(), 50);
+
+    let GarbageCollectResponse {
+        bytes_freed,
+        bucket_bytes_freed: _,
+        packed_bytes_freed,
+    } = GarbageCollectRequest::response(&client, 0).unwrap();
+    assert_eq!(packed_bytes_freed, 50 * 9);
+    assert!(bytes_freed >= packed_bytes_freed);
+    assert_eq!(contents(&server, &client), before);
+    let usage = stats(&client).packed.unwrap();
+    assert_eq!(usage.log_bytes, usage.live_bytes);
+
+    // Entries added after compacting go after the moved ones.
+    let added = add(&client, RingKind::Main, b"added");
+    assert_eq!(
+        contents(&server, &client).last(),
+        Some(&(added, b"added".to_vec()))
+    );
+}
+
+#[test]
+fn packed_entries_survive_restarts_and_crashes() {
+    let Some(mut server) = start("restart", SETTINGS) else {
+        return;
+    };
+    let client = connect(&server);
+    for text in ["one", "two", "three"] {
+        add(&client, RingKind::Main, text.as_bytes());
+    }
+    let removed = add(&client, RingKind::Main, b"removed");
+    remove(&client, removed);
+    let before = contents(&server, &client);
+    drop(client);
+
+    server.signal(Signal::Term);
+    server.restart();
+    server.assert_responsive();
+    let client = connect(&server);
+    assert_eq!(contents(&server, &client), before);
+    let usage = stats(&client).packed.unwrap();
+    assert_eq!((usage.entries, usage.live_bytes), (3, 11));
+    drop(client);
+
+    server.crash();
+    server.restart();
+    server.assert_responsive();
+    let client = connect(&server);
+    assert_eq!(contents(&server, &client), before);
+    let four = add(&client, RingKind::Main, b"four");
+    assert_eq!(
+        contents(&server, &client).last(),
+        Some(&(four, b"four".to_vec()))
+    );
+}
+
+#[test]
+fn packing_is_chosen_when_the_database_is_created() {
+    let Some(mut server) = start("setting", "") else {
+        return;
+    };
+    let client = connect(&server);
+    add(&client, RingKind::Main, b"bucketed");
+    assert!(stats(&client).packed.is_none());
+    drop(client);
+
+    server.signal(Signal::Term);
+    server.write_settings(SETTINGS);
+    server.restart();
+    server.assert_responsive();
+    let client = connect(&server);
+    add(&client, RingKind::Main, b"still bucketed");
+    let stats = stats(&client);
+    assert!(stats.packed.is_none());
+    assert_eq!(stats.buckets.iter().map(|b| b.slots).sum::<u32>(), 2);
+    assert!(!server.data_dir().join(packed::LOG_FILE_NAME).exists());
+    assert!(server.log().contains("Ignoring pack_small_entries setting"));
+}
@@ -0,0 +1,99 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    fs::Permissions,
+    io::IoSlice,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    process, thread,
+};
+
+use common::Server;
+use ringboard_core::protocol::{PERMISSION_DENIED, VERSION};
+use rustix::{
+    net::{recv, sendmsg, RecvFlags, SendAncillaryBuffer, SendFlags},
+    process::{getuid, Signal, Uid},
+    thread::set_thread_uid,
+};
+
+mod common;
+
+/// Stops the server and opens up its data directory to other users.
+fn share_data_dir(server: &mut Server) {
+    server.signal(Signal::Term);
+    server.wait();
+    fs::set_permissions(server.data_dir(), Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn socket_is_private() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-perms-socket-{}", process::id())));
+    server.assert_responsive();
+
+    let mode = fs::metadata(server.socket_file()).unwrap().mode();
+    assert_eq!(mode & 0o777, 0o600, "{mode:o}");
+}
+
+#[test]
+fn shared_data_dirs_are_restricted() {
+    let mut server =
+        Server::start(env::temp_dir().join(format!("ringboard-perms-shared-{}", process::id())));
+    server.assert_responsive();
+    share_data_dir(&mut server);
+
+    server.restart();
+    server.wait_for_log("Took permissions 055 away from other users on the data directory");
+    server.assert_responsive();
+    let mode = fs::metadata(server.data_dir()).unwrap().mode();
+    assert_eq!(mode & 0o777, 0o700, "{mode:o}");
+}
+
+#[test]
+fn shared_data_dirs_can_be_allowed() {
+    let mut server = Server::start_with_args(
+        env::temp_dir().join(format!("ringboard-perms-allowed-{}", process::id())),
+        "",
+        &[OsStr::new("--allow-shared-data-dir")],
+    );
+    server.assert_responsive();
+    share_data_dir(&mut server);
+
+    server.restart();
+    server.wait_for_log("Serving a data directory other users can access");
+    server.assert_responsive();
+}
+
+#[test]
+fn other_users_are_refused() {
+    if !getuid().is_root() {
+        eprintln!("Skipping, only root can connect as another user.");
+        return;
+    }
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-perms-users-{}", process::id())));
+    // The socket is private, so the client has to be let in by its owner.
+    let client = server.dial();
+
+    // Credentials are those of the thread sending the message.
+    let reply = thread::scope(|s| {
+        s.spawn(|| {
+            set_thread_uid(unsafe { Uid::from_raw(65534) }).unwrap();
+            sendmsg(
+                &client,
+                &[IoSlice::new(&[VERSION])],
+                &mut SendAncillaryBuffer::default(),
+                SendFlags::empty(),
+            )
+            .unwrap();
+            let mut reply = [0; 8];
+            let len = recv(&client, &mut reply, RecvFlags::empty()).unwrap();
+            reply[..len].to_vec()
+        })
+        .join()
+        .unwrap()
+    });
+    assert_eq!(reply, [PERMISSION_DENIED]);
+    server.wait_for_log("Refusing client running as user 65534.");
+    server.assert_responsive();
+}
@@ -0,0 +1,25 @@
+use std::{env, process, thread, time::Duration};
+
+use common::Server;
+use ringboard_core::protocol::{RATE_LIMITED, VERSION};
+
+mod common;
+
+#[test]
+fn connections_beyond_the_burst_are_turned_away() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-rate-limit-{}", process::id())),
+        "max_connections_per_second = 10\nconnection_burst = 3\n",
+    );
+
+    for _ in 0..3 {
+        assert_eq!(server.handshake(&[VERSION]).1, [VERSION]);
+    }
+    for _ in 0..5 {
+        assert_eq!(server.handshake(&[VERSION]).1, [RATE_LIMITED]);
+    }
+    assert!(server.log().contains("Rejected 1 connection(s)"));
+
+    thread::sleep(Duration::from_millis(200));
+    server.assert_responsive();
+}
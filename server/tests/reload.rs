@@ -0,0 +1,58 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{AddResponse, MimeType, Request, RingKind};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    process::Signal,
+};
+
+mod common;
+
+fn add(client: &OwnedFd, data: &[u8]) -> bool {
+    let file = memfd_create(c"ringboard-reload", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success { deduplicated, .. } => deduplicated,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+#[test]
+fn sighup_reloads_settings() {
+    let mut server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-reload-{}", process::id())),
+        "coalesce_adds = false\ndedupe_adds = false\n",
+    );
+    let client = server.connect();
+    assert!(!add(&client, b"hello"));
+    assert!(!add(&client, b"hello"));
+
+    server.write_settings("coalesce_adds = false\nmain_max_entries = 5000\n");
+    server.signal(Signal::Hup);
+    server.wait_for_log("Reloaded settings.");
+    assert!(server.log().contains("main_max_entries only take effect"));
+    assert!(!add(&client, b"world"));
+    assert!(add(&client, b"world"));
+
+    // A broken file keeps the settings in effect.
+    server.write_settings("dedupe_adds = maybe\n");
+    server.signal(Signal::Hup);
+    server.wait_for_log("Keeping current settings");
+    assert!(add(&client, b"world"));
+
+    // Signals arriving back to back are all handled.
+    server.write_settings("coalesce_adds = false\ndedupe_adds = false\n");
+    server.signal(Signal::Hup);
+    server.signal(Signal::Hup);
+    server.signal(Signal::Term);
+    assert!(server.wait().success());
+    assert!(server.log().contains("Shutting down on signal"));
+}
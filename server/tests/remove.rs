@@ -0,0 +1,77 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{
+    composite_id, AddResponse, IdNotFoundError, MimeType, ReadOutcome, ReadResponse,
+    RemoveResponse, Request, RingKind,
+};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, text: &[u8]) -> u64 {
+    let data = memfd_create(c"ringboard-remove", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&data)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn remove(client: &OwnedFd, id: u64) -> Option<IdNotFoundError> {
+    let RemoveResponse { error } = request(client, &Request::Remove { id }, None);
+    error
+}
+
+fn read(client: &OwnedFd, id: u64) -> ReadResponse {
+    let read = Request::Read {
+        id,
+        offset: 0,
+        len: u32::MAX,
+    };
+    request(client, &read, None)
+}
+
+#[test]
+fn removed_entries_are_gone() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-remove-{}", process::id())));
+    let client = server.connect();
+    let first = add(&client, b"snippet");
+    let newest = add(&client, b"newest");
+
+    // The newest entry sits right behind the write head.
+    assert!(remove(&client, newest).is_none());
+    assert!(matches!(
+        read(&client, newest).outcome,
+        ReadOutcome::Error(IdNotFoundError::Entry(1))
+    ));
+    assert!(matches!(
+        remove(&client, newest),
+        Some(IdNotFoundError::Entry(1))
+    ));
+    // Nothing was ever written under the write head.
+    assert!(matches!(
+        remove(&client, composite_id(RingKind::Main, 2)),
+        Some(IdNotFoundError::Entry(2))
+    ));
+
+    assert!(matches!(
+        read(&client, first),
+        ReadResponse {
+            outcome: ReadOutcome::Inline,
+            len: 7,
+            ..
+        }
+    ));
+    assert_eq!(
+        add(&client, b"replacement"),
+        composite_id(RingKind::Main, 2)
+    );
+}
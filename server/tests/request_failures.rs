@@ -0,0 +1,125 @@
+use std::{
+    env,
+    fs::File,
+    io::IoSlice,
+    os::fd::{AsFd, OwnedFd},
+    process,
+};
+
+use common::{request, Server};
+use ringboard_core::{
+    protocol::{
+        AddResponse, ErrorCode, MimeType, Request, RequestFailed, Response, RingKind,
+        ServerInfoResponse,
+    },
+    AsBytes,
+};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    io::pwrite,
+    net::{recv, send, sendmsg, RecvFlags, SendAncillaryBuffer, SendAncillaryMessage, SendFlags},
+};
+
+mod common;
+
+fn add_request() -> Request {
+    Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    }
+}
+
+fn entry(data: &[u8]) -> OwnedFd {
+    let fd = memfd_create(c"ringboard-entry", MemfdFlags::empty()).unwrap();
+    pwrite(&fd, data, 0).unwrap();
+    fd
+}
+
+/// Adds each of `data`, batched if there's more than one, and returns the raw
+/// reply.
+fn send_adds(client: &OwnedFd, data: &[&File]) -> Vec<u8> {
+    let mut requests = Vec::new();
+    if data.len() > 1 {
+        requests.push(Request::Batch {
+            len: u8::try_from(data.len()).unwrap(),
+        });
+    }
+    requests.extend(data.iter().map(|_| add_request()));
+    let payload = requests
+        .iter()
+        .map(|request| IoSlice::new(request.as_bytes()))
+        .collect::<Vec<_>>();
+    let fds = data.iter().map(AsFd::as_fd).collect::<Vec<_>>();
+    let mut space = [0; rustix::cmsg_space!(ScmRights(4))];
+    let mut ancillary = SendAncillaryBuffer::new(&mut space);
+    assert!(ancillary.push(SendAncillaryMessage::ScmRights(&fds)));
+    sendmsg(client, &payload, &mut ancillary, SendFlags::empty()).unwrap();
+
+    let mut reply = vec![0; 1024];
+    let len = recv(client, &mut reply, RecvFlags::empty()).unwrap();
+    reply.truncate(len);
+    reply
+}
+
+fn request_failed(reply: &[u8]) -> RequestFailed {
+    assert_eq!(reply.len(), RequestFailed::LEN);
+    let mut buf = [0; size_of::<Response<RequestFailed>>()];
+    buf[..reply.len()].copy_from_slice(reply);
+    unsafe {
+        buf.as_ptr()
+            .cast::<Response<RequestFailed>>()
+            .read_unaligned()
+            .value
+    }
+}
+
+#[test]
+fn failed_requests_are_reported_without_disrupting_anyone() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-failures-{}", process::id())));
+    let bystander = server.connect();
+    let client = server.connect();
+    let vandal = server.connect();
+
+    // Write-only files can't be read back by the server.
+    let unreadable = File::create(server.data_dir().with_file_name("unreadable")).unwrap();
+    let failed = request_failed(&send_adds(&client, &[&unreadable]));
+    assert_eq!(failed.code, ErrorCode::UnreadableData);
+    assert!(!failed.context.is_empty());
+
+    // Garbage gets its sender disconnected instead.
+    send(&vandal, &[0xAA; 7], SendFlags::empty()).unwrap();
+    send(&vandal, &[0xAA; 4096], SendFlags::empty()).unwrap();
+    assert_eq!(recv(&vandal, &mut [0; 64], RecvFlags::empty()).unwrap(), 0);
+
+    // The failed client keeps its connection and everyone else is still served.
+    let info = request::<ServerInfoResponse>(&client, &Request::ServerInfo, None);
+    assert!(!info.version.is_empty());
+    let response = request::<AddResponse>(&bystander, &add_request(), Some(&entry(b"still here")));
+    assert!(
+        matches!(response, AddResponse::Success { .. }),
+        "{response:?}"
+    );
+    server.assert_responsive();
+}
+
+#[test]
+fn failures_end_batches_early() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-failed-batch-{}", process::id())));
+    let client = server.connect();
+
+    let readable = File::from(entry(b"first"));
+    let unreadable = File::create(server.data_dir().with_file_name("unreadable")).unwrap();
+    let failed = request_failed(&send_adds(&client, &[&readable, &unreadable, &readable]));
+    assert_eq!(failed.code, ErrorCode::UnreadableData);
+
+    // Requests before the failed one went through.
+    let response = request::<AddResponse>(&client, &add_request(), Some(&entry(b"second")));
+    let AddResponse::Success { id, .. } = response else {
+        panic!("{response:?}");
+    };
+    assert_eq!(id & u64::from(u32::MAX), 1);
+}
@@ -0,0 +1,57 @@
+use std::{env, fs, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{composite_id, AddResponse, MimeType, Request, RingKind};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, text: &str) -> u64 {
+    let data = memfd_create(c"ringboard-capacity", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text.as_bytes()).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&data)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+#[test]
+fn rings_grow_but_refuse_to_shrink() {
+    let dir = env::temp_dir().join(format!("ringboard-capacity-{}", process::id()));
+    let mut server = Server::start_with_settings(dir.clone(), "main_max_entries = 3\n");
+    let client = server.connect();
+    let ids = (0..4)
+        .map(|i| add(&client, &format!("entry {i}")))
+        .collect::<Vec<_>>();
+    let main = |index| composite_id(RingKind::Main, index);
+    // The fourth entry wraps around to overwrite the oldest.
+    assert_eq!(ids, [main(0), main(1), main(2), main(0)]);
+    drop(client);
+    server.crash();
+
+    let settings = dir.join("clipboard-history").join("settings");
+    fs::write(&settings, "main_max_entries = 2\n").unwrap();
+    let output = server.fsck();
+    let log = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{log}");
+    assert!(
+        log.contains("set `main_max_entries` to at least 3"),
+        "{log}"
+    );
+
+    let server = Server::start_with_settings(dir, "main_max_entries = 5\n");
+    let client = server.connect();
+    let ids = (4..7)
+        .map(|i| add(&client, &format!("entry {i}")))
+        .collect::<Vec<_>>();
+    // The oldest entry is overwritten until the write head reaches the new
+    // space.
+    assert_eq!(ids, [main(1), main(2), main(3)]);
+}
@@ -0,0 +1,197 @@
+use std::{collections::BTreeSet, env, fs, os::fd::OwnedFd, process, thread};
+
+use common::Server;
+use ringboard_core::{
+    protocol::{
+        AddResponse, IdNotFoundError, MimeType, MoveToFrontResponse, RemoveResponse, Request,
+        RingKind,
+    },
+    AsBytes,
+};
+use ringboard_sdk::{
+    api::{connect_to_server, AddRequest, MoveToFrontRequest, RemoveRequest},
+    DatabaseReader, Entry, EntryReader,
+};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    net::{send, SendFlags, SocketAddrUnix},
+    process::Signal,
+};
+
+mod common;
+
+/// Starts a server for `name`, or returns nothing if this machine can't run
+/// one.
+fn start(name: &str) -> Option<Server> {
+    if !common::io_uring_available() {
+        return None;
+    }
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-sdk-{name}-{}", process::id())));
+    server.assert_responsive();
+    Some(server)
+}
+
+fn connect(server: &Server) -> OwnedFd {
+    connect_to_server(&SocketAddrUnix::new(server.socket_file()).unwrap()).unwrap()
+}
+
+fn add(client: &OwnedFd, to: RingKind, text: &str) -> u64 {
+    let data = memfd_create(c"ringboard-sdk", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text.as_bytes()).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    match AddRequest::response(client, to, MimeType::new(), &data).unwrap() {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+/// What the database holds as seen by a reader, newest first.
+struct Contents {
+    main: Vec<String>,
+    favorites: Vec<String>,
+}
+
+fn contents(server: &Server) -> Contents {
+    let mut dir = server.data_dir();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+    let text = |entry: Entry| String::from_utf8(entry.to_slice(&reader).unwrap().to_vec()).unwrap();
+    Contents {
+        main: database.main().rev().map(text).collect(),
+        favorites: database.favorites().rev().map(text).collect(),
+    }
+}
+
+/// Reads a single entry back by its ID.
+fn get(server: &Server, id: u64) -> Result<String, IdNotFoundError> {
+    let mut dir = server.data_dir();
+    let database = DatabaseReader::open(&mut dir).unwrap();
+    let reader = EntryReader::open(&mut dir).unwrap();
+    let entry = database.get_raw(id)?;
+    Ok(String::from_utf8(entry.to_slice(&reader).unwrap().to_vec()).unwrap())
+}
+
+#[test]
+fn sdk_requests_are_reflected_in_the_database() {
+    let Some(server) = start("round-trip") else {
+        return;
+    };
+    let client = connect(&server);
+    let [a, b, c] = ["a", "b", "c"].map(|s| add(&client, RingKind::Main, s));
+    assert_eq!(get(&server, b).unwrap(), "b");
+    assert_eq!(contents(&server).main, ["c", "b", "a"]);
+
+    let MoveToFrontResponse::Success { id: a } =
+        MoveToFrontRequest::response(&client, a, None).unwrap()
+    else {
+        panic!("Failed to move entry to the front");
+    };
+    assert_eq!(contents(&server).main, ["a", "c", "b"]);
+
+    let MoveToFrontResponse::Success { id: b } =
+        MoveToFrontRequest::response(&client, b, Some(RingKind::Favorites)).unwrap()
+    else {
+        panic!("Failed to favorite entry");
+    };
+    let Contents { main, favorites } = contents(&server);
+    assert_eq!(main, ["a", "c"]);
+    assert_eq!(favorites, ["b"]);
+    assert_eq!(get(&server, b).unwrap(), "b");
+
+    let RemoveResponse { error } = RemoveRequest::response(&client, c).unwrap();
+    assert!(error.is_none(), "{error:?}");
+    assert!(get(&server, c).is_err());
+    assert_eq!(contents(&server).main, ["a"]);
+    assert_eq!(get(&server, a).unwrap(), "a");
+}
+
+#[test]
+fn concurrent_clients_all_have_their_entries_stored() {
+    const CLIENTS: usize = 8;
+    const ADDS: usize = 25;
+
+    let Some(server) = start("concurrent") else {
+        return;
+    };
+    let ids = thread::scope(|s| {
+        #[allow(clippy::needless_collect)] // Every client has to start before any is joined.
+        let threads = (0..CLIENTS)
+            .map(|t| {
+                let server = &server;
+                s.spawn(move || {
+                    let client = connect(server);
+                    (0..ADDS)
+                        .map(|i| add(&client, RingKind::Main, &format!("{t}-{i}")))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect::<BTreeSet<_>>()
+    });
+    assert_eq!(ids.len(), CLIENTS * ADDS);
+
+    let main = contents(&server).main;
+    assert_eq!(main.len(), CLIENTS * ADDS);
+    for t in 0..CLIENTS {
+        // Each client's entries land in the order it added them.
+        let mine = main
+            .iter()
+            .rev()
+            .filter(|text| text.starts_with(&format!("{t}-")))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            mine,
+            (0..ADDS).map(|i| format!("{t}-{i}")).collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn truncated_requests_leave_the_database_alone() {
+    let Some(server) = start("truncated") else {
+        return;
+    };
+    let client = connect(&server);
+    add(&client, RingKind::Main, "before");
+
+    let request = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    let bytes = request.as_bytes();
+    send(&client, &bytes[..bytes.len() / 2], SendFlags::empty()).unwrap();
+    server.wait_for_log("Dropping invalid request (too short).");
+
+    // The same connection keeps working.
+    add(&client, RingKind::Main, "after");
+    assert_eq!(contents(&server).main, ["after", "before"]);
+}
+
+#[test]
+fn sigterm_shuts_down_cleanly_and_releases_the_lock() {
+    let Some(mut server) = start("sigterm") else {
+        return;
+    };
+    let client = connect(&server);
+    add(&client, RingKind::Main, "kept");
+    let lock = server.data_dir().join("server.lock");
+    assert_eq!(fs::read_to_string(&lock).unwrap(), server.pid().to_string());
+
+    server.signal(Signal::Term);
+    assert!(server.wait().success());
+    assert!(!lock.exists());
+    assert!(server.log().contains("Shutting down on signal"));
+
+    // The next server finds a clean database with everything still in it.
+    server.restart();
+    server.assert_responsive();
+    assert!(!server.log().contains("Unclean shutdown detected"));
+    assert_eq!(contents(&server).main, ["kept"]);
+}
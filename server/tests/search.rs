@@ -0,0 +1,92 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::{
+    protocol::{AddResponse, MimeType, Request, RingKind, SearchQuery, StatsResponse},
+    AsBytes,
+};
+use ringboard_sdk::{api::remote_search, ClientError};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    net::{send, SendFlags},
+};
+
+mod common;
+
+fn add(client: &OwnedFd, data: &[u8]) -> u64 {
+    let file = memfd_create(c"ringboard-search", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn search(client: &OwnedFd, query: &str, regex: bool, limit: u32) -> Vec<u64> {
+    let mut ids = remote_search(client, query, regex, limit)
+        .unwrap()
+        .map(|m| m.unwrap().id)
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn searches_stream_matches_across_responses() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-search-{}", process::id())));
+    let client = server.connect();
+    let mut needles = (0..300)
+        .map(|i| add(&client, format!("needle {i}").as_bytes()))
+        .collect::<Vec<_>>();
+    needles.sort_unstable();
+    add(&client, b"haystack");
+
+    assert_eq!(search(&client, "needle", false, u32::MAX), needles);
+    assert_eq!(search(&client, r"^needle \d$", true, u32::MAX).len(), 10);
+    assert_eq!(search(&client, "needle", false, 5).len(), 5);
+    assert!(search(&client, "pin", false, u32::MAX).is_empty());
+
+    // Stopping early leaves the connection ready for the next request.
+    let mut matches = remote_search(&client, "needle", false, 200).unwrap();
+    assert!(matches.next().unwrap().is_ok());
+    drop(matches);
+    let stats = request::<StatsResponse>(&client, &Request::Stats, None);
+    assert_eq!(stats.connected_clients, 1);
+
+    let invalid = remote_search(&client, "(", true, 1)
+        .unwrap()
+        .collect::<Vec<_>>();
+    assert!(
+        matches!(invalid[..], [Err(ClientError::InvalidQuery { .. })]),
+        "{invalid:?}"
+    );
+}
+
+#[test]
+fn clients_can_leave_in_the_middle_of_a_search() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-search-leave-{}", process::id())));
+    let client = server.connect();
+    for i in 0..300 {
+        add(&client, format!("needle {i}").as_bytes());
+    }
+
+    let request = Request::Search {
+        query: SearchQuery::from("needle").unwrap(),
+        regex: false,
+        limit: u32::MAX,
+    };
+    send(&client, request.as_bytes(), SendFlags::empty()).unwrap();
+    drop(client);
+
+    server.assert_responsive();
+    let client = server.connect();
+    assert_eq!(search(&client, "needle", false, u32::MAX).len(), 300);
+}
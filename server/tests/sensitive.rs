@@ -0,0 +1,119 @@
+use std::{
+    env,
+    fs::File,
+    os::fd::OwnedFd,
+    process, thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::{request, Server};
+use ringboard_core::{
+    protocol::{
+        AddResponse, MimeType, ReadOutcome, ReadResponse, RemoveResponse, Request, RestoreResponse,
+        RingKind,
+    },
+    sensitive,
+};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+const SETTINGS: &str = "sensitive_entry_ttl = 1";
+
+fn add(client: &OwnedFd, text: &[u8], sensitive: bool) -> u64 {
+    let data = memfd_create(c"ringboard-sensitive", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&data, text).unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&data)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn read(client: &OwnedFd, id: u64) -> ReadResponse {
+    let read = Request::Read {
+        id,
+        offset: 0,
+        len: u32::MAX,
+    };
+    request(client, &read, None)
+}
+
+fn restore(client: &OwnedFd, tombstone_id: u64) -> RestoreResponse {
+    request(client, &Request::Restore { tombstone_id }, None)
+}
+
+#[test]
+fn sensitive_entries_are_deleted_once_they_expire() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-sensitive-{}", process::id())),
+        SETTINGS,
+    );
+    let client = server.connect();
+    let secret = add(&client, b"hunter2", true);
+    // Sensitive entries are never deduplicated against permanent ones.
+    let copy = add(&client, b"hunter2", false);
+    assert_ne!(secret, copy);
+
+    let file = File::open(
+        server
+            .data_dir()
+            .join("direct")
+            .join(format!("{secret:0>13}")),
+    )
+    .unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let expires_at = sensitive::read(&file).unwrap().unwrap();
+    assert!((now..=now + 2).contains(&expires_at), "{expires_at}");
+    assert!(!matches!(
+        read(&client, secret).outcome,
+        ReadOutcome::Error(_)
+    ));
+
+    // Nothing needs to ask for the entry for it to be deleted.
+    server.wait_for_log(&format!("Deleted expired sensitive entry {secret}."));
+    assert!(matches!(
+        read(&client, secret).outcome,
+        ReadOutcome::Error(_)
+    ));
+    assert!(matches!(
+        restore(&client, secret),
+        RestoreResponse::NotInTrash
+    ));
+    assert!(matches!(
+        read(&client, copy),
+        ReadResponse {
+            outcome: ReadOutcome::Inline,
+            len: 7,
+            ..
+        }
+    ));
+    server.assert_responsive();
+}
+
+#[test]
+fn removed_sensitive_entries_leave_the_trash_once_they_expire() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-sensitive-trash-{}", process::id())),
+        SETTINGS,
+    );
+    let client = server.connect();
+    let secret = add(&client, b"hunter2", true);
+    let RemoveResponse { error } = request(&client, &Request::Remove { id: secret }, None);
+    assert!(error.is_none(), "{error:?}");
+
+    thread::sleep(Duration::from_millis(2500));
+    assert!(matches!(
+        restore(&client, secret),
+        RestoreResponse::NotInTrash
+    ));
+}
@@ -0,0 +1,94 @@
+use std::{env, fs, io, io::Write, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{AddResponse, MimeType, Request, RingKind};
+use rustix::fs::{getxattr, memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, data: &OwnedFd) -> AddResponse {
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    request(client, &add, Some(data))
+}
+
+fn file(data: &[u8]) -> OwnedFd {
+    let file = memfd_create(c"ringboard-size-limit", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    file
+}
+
+/// A pipe holding `data`, whose size the server can't know until it reads it.
+fn pipe(data: &[u8]) -> OwnedFd {
+    let (reader, mut writer) = io::pipe().unwrap();
+    writer.write_all(data).unwrap();
+    reader.into()
+}
+
+#[test]
+fn oversized_entries_are_rejected() {
+    let server = Server::start_with_settings(
+        env::temp_dir().join(format!("ringboard-size-reject-{}", process::id())),
+        "max_entry_size = 16\naccept_pipes = true",
+    );
+    let client = server.connect();
+
+    for data in [file(&[b'a'; 16]), pipe(&[b'b'; 16])] {
+        assert!(matches!(
+            add(&client, &data),
+            AddResponse::Success {
+                truncated: false,
+                ..
+            }
+        ));
+    }
+    for data in [file(&[b'c'; 17]), pipe(&[b'd'; 1000])] {
+        assert!(matches!(
+            add(&client, &data),
+            AddResponse::TooLarge { max: 16 }
+        ));
+    }
+    server.assert_responsive();
+}
+
+#[test]
+fn oversized_entries_are_truncated() {
+    let dir = env::temp_dir().join(format!("ringboard-size-truncate-{}", process::id()));
+    let server = Server::start_with_settings(
+        dir.clone(),
+        "max_entry_size = 16\naccept_pipes = true\ntruncate_oversized_entries = true",
+    );
+    let client = server.connect();
+    let direct = |id: u64| {
+        dir.join("clipboard-history/direct")
+            .join(format!("{id:0>13}"))
+    };
+
+    assert!(matches!(
+        add(&client, &file(&[b'a'; 16])),
+        AddResponse::Success {
+            truncated: false,
+            ..
+        }
+    ));
+    for (data, byte) in [(file(&[b'b'; 17]), b'b'), (pipe(&[b'c'; 1000]), b'c')] {
+        let AddResponse::Success {
+            id,
+            truncated: true,
+            ..
+        } = add(&client, &data)
+        else {
+            panic!("Entry wasn't truncated.");
+        };
+        assert_eq!(fs::read(direct(id)).unwrap(), [byte; 16]);
+        let mut marker = [0; 8];
+        let len = getxattr(direct(id), c"user.truncated", &mut marker).unwrap();
+        assert_eq!(&marker[..len], b"1");
+    }
+    server.assert_responsive();
+}
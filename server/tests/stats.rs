@@ -0,0 +1,59 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{
+    AddResponse, MimeType, RemoveResponse, Request, RingKind, StatsResponse,
+};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, data: &[u8], mime_type: &str) -> u64 {
+    let file = memfd_create(c"ringboard-stats", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::from(mime_type).unwrap(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn remove(client: &OwnedFd, id: u64) {
+    let RemoveResponse { error } = request(client, &Request::Remove { id }, None);
+    assert!(error.is_none());
+}
+
+#[test]
+fn stats_track_storage_and_connections() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-stats-{}", process::id())));
+    let client = server.connect();
+    let stats = request::<StatsResponse>(&client, &Request::Stats, None);
+    assert_eq!(
+        (stats.main_len, stats.main_capacity),
+        (0, RingKind::Main.default_max_entries())
+    );
+    assert_eq!((stats.direct.files, stats.direct.bytes), (0, 0));
+    assert_eq!((stats.connected_clients, stats.total_connections), (1, 1));
+    assert!(stats.started_at > 0);
+
+    let text = add(&client, b"hello", "");
+    let image = add(&client, &[0; 10_000], "image/png");
+    let stats = request::<StatsResponse>(&client, &Request::Stats, None);
+    assert_eq!(stats.main_len, 2);
+    assert_eq!((stats.direct.files, stats.direct.bytes), (1, 10_000));
+    assert_eq!(stats.buckets.iter().map(|b| b.slots).sum::<u32>(), 1);
+
+    remove(&client, text);
+    remove(&client, image);
+    let other = server.connect();
+    let stats = request::<StatsResponse>(&other, &Request::Stats, None);
+    assert_eq!((stats.direct.files, stats.direct.bytes), (0, 0));
+    assert_eq!(stats.buckets.iter().map(|b| b.free_slots).sum::<u32>(), 1);
+    assert_eq!((stats.connected_clients, stats.total_connections), (2, 2));
+}
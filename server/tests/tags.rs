@@ -0,0 +1,173 @@
+use std::{env, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::{
+    protocol::{
+        AddResponse, Label, ListTagsResponse, MimeType, MoveToFrontResponse, RemoveResponse,
+        Request, RestoreResponse, RingKind, SetLabelResponse, SwapResponse, Tag, TagResponse,
+    },
+    tags,
+};
+use rustix::{
+    fs::{memfd_create, MemfdFlags},
+    process::Signal,
+};
+
+mod common;
+
+fn add(client: &OwnedFd, data: &[u8]) -> u64 {
+    let file = memfd_create(c"ringboard-tags", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn tag(name: &str) -> Tag {
+    tags::parse(name).unwrap()
+}
+
+fn add_tag(client: &OwnedFd, id: u64, name: &str) -> TagResponse {
+    request(client, &Request::AddTag { id, tag: tag(name) }, None)
+}
+
+fn tags_of(client: &OwnedFd, id: u64) -> Vec<String> {
+    let response: ListTagsResponse = request(client, &Request::ListTags { id }, None);
+    assert!(response.error.is_none(), "{response:?}");
+    response.tags.iter().map(String::from).collect()
+}
+
+#[test]
+fn tags_are_kept_for_bucketed_and_direct_entries() {
+    let mut server =
+        Server::start(env::temp_dir().join(format!("ringboard-tags-kinds-{}", process::id())));
+    let client = server.connect();
+    let small = add(&client, b"hello");
+    let large = add(&client, &vec![b'x'; 64 * 1024]);
+
+    for id in [small, large] {
+        assert!(matches!(
+            add_tag(&client, id, "work"),
+            TagResponse::Success { changed: true }
+        ));
+        assert!(matches!(
+            add_tag(&client, id, "work"),
+            TagResponse::Success { changed: false }
+        ));
+        assert!(matches!(
+            add_tag(&client, id, "urgent"),
+            TagResponse::Success { changed: true }
+        ));
+        let response: TagResponse = request(
+            &client,
+            &Request::RemoveTag {
+                id,
+                tag: tag("work"),
+            },
+            None,
+        );
+        assert!(matches!(response, TagResponse::Success { changed: true }));
+        assert_eq!(tags_of(&client, id), ["urgent"]);
+    }
+
+    let invalid = Tag::from("Not Valid").unwrap();
+    let response: TagResponse = request(
+        &client,
+        &Request::AddTag {
+            id: small,
+            tag: invalid,
+        },
+        None,
+    );
+    assert!(matches!(response, TagResponse::InvalidTag), "{response:?}");
+    let long = "x".repeat(tags::MAX_TAG_LEN - 1);
+    let full = ('a'..='z')
+        .map(|c| add_tag(&client, small, &format!("{c}{long}")))
+        .any(|response| matches!(response, TagResponse::TooManyTags));
+    assert!(full);
+
+    drop(client);
+    server.signal(Signal::Term);
+    server.wait();
+    server.restart();
+    let client = server.connect();
+    assert_eq!(tags_of(&client, large), ["urgent"]);
+    assert_eq!(tags_of(&client, small)[0], "urgent");
+}
+
+#[test]
+fn tags_follow_entries_around() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-tags-moves-{}", process::id())));
+    let client = server.connect();
+    let first = add(&client, b"first");
+    let second = add(&client, b"second");
+    assert!(matches!(
+        add_tag(&client, first, "first"),
+        TagResponse::Success { changed: true }
+    ));
+
+    let MoveToFrontResponse::Success { id: moved } = request(
+        &client,
+        &Request::MoveToFront {
+            id: first,
+            to: Some(RingKind::Favorites),
+        },
+        None,
+    ) else {
+        panic!("Failed to favorite entry.");
+    };
+    assert_eq!(tags_of(&client, moved), ["first"]);
+    // The slot the entry left is reused without its tags.
+    let third = add(&client, b"third");
+    assert!(tags_of(&client, third).is_empty());
+
+    let response: SwapResponse = request(
+        &client,
+        &Request::Swap {
+            id1: moved,
+            id2: second,
+        },
+        None,
+    );
+    assert!(response.error1.is_none() && response.error2.is_none());
+    assert_eq!(tags_of(&client, second), ["first"]);
+    assert!(tags_of(&client, moved).is_empty());
+
+    // Labeling moves bucketed entries into files of their own.
+    let response: SetLabelResponse = request(
+        &client,
+        &Request::SetLabel {
+            id: second,
+            label: Label::from("labeled").unwrap(),
+        },
+        None,
+    );
+    assert!(response.error.is_none());
+    assert_eq!(tags_of(&client, second), ["first"]);
+
+    let response: RemoveResponse = request(&client, &Request::Remove { id: second }, None);
+    assert!(response.error.is_none());
+    let RestoreResponse::Added(AddResponse::Success { id: restored, .. }) = request(
+        &client,
+        &Request::Restore {
+            tombstone_id: second,
+        },
+        None,
+    ) else {
+        panic!("Failed to restore entry.");
+    };
+    assert_eq!(tags_of(&client, restored), ["first"]);
+
+    let response: ListTagsResponse = request(&client, &Request::ListTags { id: second }, None);
+    assert!(response.error.is_some(), "{response:?}");
+    server.assert_responsive();
+}
@@ -0,0 +1,43 @@
+use std::{env, process};
+
+use common::Server;
+use ringboard_core::{
+    protocol::{RemoveResponse, Request, Response},
+    AsBytes,
+};
+use rustix::net::{recv, send, RecvFlags, SendFlags};
+
+mod common;
+
+#[test]
+fn error_responses_carry_the_logged_trace_id() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-trace-{}", process::id())));
+    let client = server.connect();
+
+    send(
+        &client,
+        Request::Remove { id: u64::MAX }.as_bytes(),
+        SendFlags::empty(),
+    )
+    .unwrap();
+    let mut buf = [0; 64];
+    let len = recv(&client, &mut buf, RecvFlags::empty()).unwrap();
+    assert_eq!(len, size_of::<Response<RemoveResponse>>());
+    let Response {
+        sequence_number: _,
+        trace,
+        value: RemoveResponse { error },
+    } = unsafe {
+        buf.as_ptr()
+            .cast::<Response<RemoveResponse>>()
+            .read_unaligned()
+    };
+
+    assert!(error.is_some());
+    assert!(
+        server
+            .log()
+            .contains(&format!("[{trace}] Processing request")),
+        "trace {trace} missing from the server log"
+    );
+}
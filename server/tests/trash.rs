@@ -0,0 +1,85 @@
+use std::{env, fs::File, io::Read, os::fd::OwnedFd, process};
+
+use common::{request, Server};
+use ringboard_core::protocol::{
+    AddResponse, EmptyTrashResponse, MimeType, RemoveResponse, Request, RestoreResponse, RingKind,
+};
+use ringboard_sdk::api::{fetch, EntryPayload};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+fn add(client: &OwnedFd, data: &[u8], mime_type: &str) -> u64 {
+    let file = memfd_create(c"ringboard-trash", MemfdFlags::empty()).unwrap();
+    rustix::io::write(&file, data).unwrap();
+    rustix::fs::seek(&file, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::from(mime_type).unwrap(),
+        copied_at: 0,
+    };
+    match request(client, &add, Some(&file)) {
+        AddResponse::Success { id, .. } => id,
+        response => panic!("Failed to add entry: {response:?}"),
+    }
+}
+
+fn remove(client: &OwnedFd, id: u64) {
+    let response: RemoveResponse = request(client, &Request::Remove { id }, None);
+    assert!(response.error.is_none(), "{response:?}");
+}
+
+fn restore(client: &OwnedFd, tombstone_id: u64) -> RestoreResponse {
+    request(client, &Request::Restore { tombstone_id }, None)
+}
+
+fn contents(client: &OwnedFd, id: u64) -> (MimeType, Vec<u8>) {
+    let (mime_type, payload) = fetch(client, id).unwrap();
+    let data = match payload {
+        EntryPayload::Inline(data) => data,
+        EntryPayload::File(fd) => {
+            let mut data = Vec::new();
+            File::from(fd).read_to_end(&mut data).unwrap();
+            data
+        }
+    };
+    (mime_type, data)
+}
+
+#[test]
+fn removed_entries_are_restored_until_the_trash_is_emptied() {
+    let server = Server::start(env::temp_dir().join(format!("ringboard-trash-{}", process::id())));
+    let client = server.connect();
+    let small = add(&client, b"hello", "");
+    let image = vec![42; 64 * 1024];
+    let large = add(&client, &image, "image/png");
+
+    remove(&client, small);
+    remove(&client, large);
+    // Both bucketed and direct entries keep their data and mime type.
+    for (id, data, mime_type) in [(small, &b"hello"[..], ""), (large, &image, "image/png")] {
+        let RestoreResponse::Added(AddResponse::Success { id: new_id, .. }) = restore(&client, id)
+        else {
+            panic!("Failed to restore entry {id}.");
+        };
+        assert_eq!(
+            contents(&client, new_id),
+            (MimeType::from(mime_type).unwrap(), data.to_vec())
+        );
+    }
+    assert!(matches!(
+        restore(&client, small),
+        RestoreResponse::NotInTrash
+    ));
+
+    let gone = add(&client, b"gone", "");
+    remove(&client, gone);
+    let response: EmptyTrashResponse = request(&client, &Request::EmptyTrash, None);
+    assert_eq!(response.purged, 1);
+    assert!(matches!(
+        restore(&client, gone),
+        RestoreResponse::NotInTrash
+    ));
+    server.assert_responsive();
+}
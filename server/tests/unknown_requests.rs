@@ -0,0 +1,53 @@
+use std::{env, process};
+
+use common::Server;
+use ringboard_core::{
+    protocol::{Request, Response, UnknownRequest, MIN_VERSION, VERSION},
+    AsBytes,
+};
+use rustix::net::{recv, send, RecvFlags, SendFlags};
+
+mod common;
+
+#[test]
+fn requests_from_newer_clients_are_rejected_without_disconnecting() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-unknown-{}", process::id())));
+    let client = server.connect();
+
+    // What a client with one more kind of request than this server would send.
+    let mut fixture = [0xAA; size_of::<Request>()];
+    fixture[..size_of::<u32>()].copy_from_slice(&Request::KINDS.to_ne_bytes());
+    send(&client, &fixture, SendFlags::empty()).unwrap();
+
+    let mut buf = [0; 64];
+    let len = recv(&client, &mut buf, RecvFlags::empty()).unwrap();
+    assert_eq!(len, size_of::<Response<UnknownRequest>>());
+    let Response {
+        sequence_number: _,
+        trace: _,
+        value: UnknownRequest { tag },
+    } = unsafe {
+        buf.as_ptr()
+            .cast::<Response<UnknownRequest>>()
+            .read_unaligned()
+    };
+    assert_eq!(tag, Request::KINDS);
+
+    send(&client, Request::ServerInfo.as_bytes(), SendFlags::empty()).unwrap();
+    assert!(recv(&client, &mut [0; 256], RecvFlags::empty()).unwrap() > 0);
+}
+
+#[test]
+fn negotiating_clients_learn_the_servers_requests() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-negotiate-{}", process::id())));
+
+    let kinds = u8::try_from(Request::KINDS).unwrap();
+    let (_, reply) = server.handshake(&[VERSION, kinds, MIN_VERSION]);
+    assert_eq!(reply, [VERSION, kinds, VERSION]);
+
+    // Clients that predate negotiation only get the version back.
+    let (_, reply) = server.handshake(&[VERSION]);
+    assert_eq!(reply, [VERSION]);
+}
@@ -0,0 +1,61 @@
+use std::{env, fs::File, io::IoSlice, os::fd::AsFd, process, thread, time::Duration};
+
+use common::Server;
+use ringboard_core::{
+    protocol::{MimeType, Request, RingKind, VERSION},
+    AsBytes,
+};
+use rustix::net::{
+    recv, send, sendmsg, RecvFlags, SendAncillaryBuffer, SendAncillaryMessage, SendFlags,
+};
+
+mod common;
+
+#[test]
+fn requests_pipelined_after_a_bad_version_are_discarded() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-mismatch-{}", process::id())));
+    let client = server.dial();
+    let open_fds = server.open_fds();
+    send(&client, &[VERSION.wrapping_add(1)], SendFlags::empty()).unwrap();
+    let data = File::open("/dev/null").unwrap();
+    let fds = [data.as_fd()];
+    let mut space = [0; rustix::cmsg_space!(ScmRights(1))];
+    let mut ancillary = SendAncillaryBuffer::new(&mut space);
+    assert!(ancillary.push(SendAncillaryMessage::ScmRights(&fds)));
+    let request = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at: 0,
+    };
+    sendmsg(
+        &client,
+        &[IoSlice::new(request.as_bytes())],
+        &mut ancillary,
+        SendFlags::empty(),
+    )
+    .unwrap();
+    drop(data);
+
+    // Give the server time to see both messages so a premature close would
+    // reset the connection before the rejection is read.
+    thread::sleep(Duration::from_millis(100));
+    let mut reply = [0; 64];
+    assert_eq!(recv(&client, &mut reply, RecvFlags::empty()).unwrap(), 1);
+    assert_eq!(reply[0], VERSION);
+    drop(client);
+
+    // The server hangs up once the client does.
+    let disconnected = "disconnected (no supported protocol version)";
+    for _ in 0..100 {
+        if server.log().contains(disconnected) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(server.log().contains(disconnected));
+    assert!(!server.log().contains("Processing request: Add"));
+    assert!(server.open_fds() <= open_fds);
+    server.assert_responsive();
+}
@@ -0,0 +1,81 @@
+use std::{
+    env, process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::{request, Server};
+use ringboard_core::{
+    protocol::{decompose_id, AddResponse, MimeType, Request, RingKind, MIN_VERSION, VERSION},
+    timestamps,
+    timestamps::Timestamps,
+};
+use rustix::fs::{memfd_create, MemfdFlags};
+
+mod common;
+
+const LAST_YEAR: u64 = 1_700_000_000;
+
+fn add_copied_at(server: &Server, hello: &[u8], copied_at: u64) -> Option<SystemTime> {
+    let kinds = u8::try_from(Request::KINDS).unwrap();
+    let (client, reply) = server.handshake(hello);
+    assert_eq!(reply[..2], [hello[0].min(VERSION), kinds]);
+
+    let data = memfd_create(c"ringboard-negotiation", MemfdFlags::empty()).unwrap();
+    rustix::io::write(
+        &data,
+        format!("v{} copied at {copied_at}", hello[0]).as_bytes(),
+    )
+    .unwrap();
+    rustix::fs::seek(&data, rustix::fs::SeekFrom::Start(0)).unwrap();
+    let add = Request::Add {
+        to: RingKind::Main,
+        sensitive: false,
+        mime_type: MimeType::new(),
+        copied_at,
+    };
+    let AddResponse::Success { id, .. } = request(&client, &add, Some(&data)) else {
+        panic!("Failed to add entry.");
+    };
+    let (ring, index) = decompose_id(id).unwrap();
+    Timestamps::open(&server.data_dir().join(timestamps::FILE_NAME))
+        .unwrap()
+        .get(ring, index)
+}
+
+#[test]
+fn clients_speaking_any_supported_version_are_accepted() {
+    let server =
+        Server::start(env::temp_dir().join(format!("ringboard-negotiation-{}", process::id())));
+    let kinds = u8::try_from(Request::KINDS).unwrap();
+
+    // Version 1 clients sent padding where the copy time now lives, so it must
+    // be ignored.
+    let copied = add_copied_at(&server, &[MIN_VERSION, kinds], LAST_YEAR).unwrap();
+    assert!(copied > UNIX_EPOCH + Duration::from_secs(LAST_YEAR));
+
+    let copied = add_copied_at(&server, &[VERSION, kinds, MIN_VERSION], LAST_YEAR).unwrap();
+    assert_eq!(
+        copied,
+        UNIX_EPOCH + Duration::from_secs(LAST_YEAR / 60 * 60)
+    );
+
+    // Clients newer than the server settle on the newest version it speaks.
+    let (_, reply) = server.handshake(&[VERSION + 1, kinds, MIN_VERSION]);
+    assert_eq!(reply, [VERSION, kinds, VERSION]);
+}
+
+#[test]
+fn clients_without_a_common_version_are_rejected() {
+    let server = Server::start(
+        env::temp_dir().join(format!("ringboard-negotiation-reject-{}", process::id())),
+    );
+    let kinds = u8::try_from(Request::KINDS).unwrap();
+
+    let (_, reply) = server.handshake(&[VERSION + 2, kinds, VERSION + 1]);
+    assert_eq!(reply, [VERSION, kinds, VERSION]);
+    // Clients that predate version ranges still get the reply they expect.
+    let (_, reply) = server.handshake(&[MIN_VERSION - 1, kinds]);
+    assert_eq!(reply, [VERSION, kinds]);
+    assert!(server.log().contains("Protocol version mismatch"));
+    server.assert_responsive();
+}
@@ -0,0 +1,398 @@
+//! Which keys do what in the entry list.
+//!
+//! The defaults can be changed in `$XDG_CONFIG_HOME/ringboard/tui-keys`, a
+//! file of `Action = keys` lines (see [`AppAction`] for the names) with keys
+//! separated by spaces, e.g. `Delete = d Ctrl-D`. Listing an action replaces
+//! its default keys and listing none unbinds it. Keys are written like `j`,
+//! `G`, `Ctrl-U`, `Alt-F`, `Shift-Up`, `PageDown`, `Space` or `F1`. Problems
+//! with the file are reported instead of keeping the TUI from starting.
+
+use std::{fmt::Write, fs, path::PathBuf};
+
+use ratatui::crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::strings::{self, Str};
+
+/// A key and the modifiers held with it.
+///
+/// Shift isn't recorded for characters since their case already says whether
+/// it was held, while letters typed with Ctrl or Alt are lowercase unless Shift
+/// was also held.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Binding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Binding {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers.intersection(KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+
+    const fn key(c: char) -> Self {
+        Self::with(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    const fn with(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    const fn ctrl(c: char) -> Self {
+        Self::with(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    const fn alt(c: char) -> Self {
+        Self::with(KeyCode::Char(c), KeyModifiers::ALT)
+    }
+
+    /// Parses a key written like `Ctrl-D`, `J`, `Shift-Up` or `Space`.
+    fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        'prefixes: loop {
+            for (prefix, modifier) in [
+                ("ctrl-", KeyModifiers::CONTROL),
+                ("alt-", KeyModifiers::ALT),
+                ("shift-", KeyModifiers::SHIFT),
+            ] {
+                if rest.len() > prefix.len()
+                    && rest.is_char_boundary(prefix.len())
+                    && rest[..prefix.len()].eq_ignore_ascii_case(prefix)
+                {
+                    modifiers.insert(modifier);
+                    rest = &rest[prefix.len()..];
+                    continue 'prefixes;
+                }
+            }
+            break;
+        }
+
+        let mut chars = rest.chars();
+        let code = if let (Some(c), None) = (chars.next(), chars.next()) {
+            if modifiers.contains(KeyModifiers::SHIFT) {
+                KeyCode::Char(c.to_ascii_uppercase())
+            } else if modifiers.is_empty() {
+                KeyCode::Char(c)
+            } else {
+                KeyCode::Char(c.to_ascii_lowercase())
+            }
+        } else {
+            match &*rest.to_ascii_lowercase() {
+                "space" => KeyCode::Char(' '),
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                name => KeyCode::F(name.strip_prefix('f')?.parse().ok()?),
+            }
+        };
+        Some(Self::new(code, modifiers))
+    }
+
+    /// The key's name as it's written in the help, spelling out arrows for
+    /// screen readers if `accessible`.
+    fn name(self, accessible: bool) -> String {
+        let Self { code, modifiers } = self;
+        let mut out = String::new();
+        if modifiers.contains(KeyModifiers::CONTROL) {
+            out.push_str("Ctrl-");
+        }
+        if modifiers.contains(KeyModifiers::ALT) {
+            out.push_str("Alt-");
+        }
+        let shifted = modifiers.contains(KeyModifiers::SHIFT)
+            || (!modifiers.is_empty()
+                && matches!(code, KeyCode::Char(c) if c.is_ascii_uppercase()));
+        if shifted || code == KeyCode::BackTab {
+            out.push_str("Shift-");
+        }
+        match code {
+            KeyCode::Char(' ') => out.push_str(strings::get(Str::KeySpace)),
+            KeyCode::Char(c) if modifiers.is_empty() => out.push(c),
+            KeyCode::Char(c) => out.push(c.to_ascii_uppercase()),
+            KeyCode::Enter => out.push_str(strings::get(Str::KeyEnter)),
+            KeyCode::Up if !accessible => out.push('↑'),
+            KeyCode::Down if !accessible => out.push('↓'),
+            KeyCode::Left if !accessible => out.push('←'),
+            KeyCode::Right if !accessible => out.push('→'),
+            KeyCode::BackTab => out.push_str("Tab"),
+            KeyCode::F(n) => write!(out, "F{n}").unwrap(),
+            code => write!(out, "{code:?}").unwrap(),
+        }
+        out
+    }
+}
+
+macro_rules! actions {
+    ($($(#[$attr:meta])* $action:ident($help:ident) = [$($key:expr),*],)*) => {
+        /// Something a key does in the entry list, named in the key bindings
+        /// file after its variant.
+        #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+        pub enum AppAction {
+            $($(#[$attr])* $action,)*
+        }
+
+        impl AppAction {
+            pub const ALL: &[Self] = &[$(Self::$action,)*];
+
+            const fn name(self) -> &'static str {
+                match self {
+                    $(Self::$action => stringify!($action),)*
+                }
+            }
+
+            /// How the help describes the action, with a placeholder for its
+            /// keys.
+            const fn help(self) -> Str {
+                match self {
+                    $(Self::$action => Str::$help,)*
+                }
+            }
+
+            const fn default_keys(self) -> &'static [Binding] {
+                match self {
+                    $(Self::$action => {
+                        const KEYS: &[Binding] = &[$($key),*];
+                        KEYS
+                    })*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    MoveDown(HelpMoveDown) = [Binding::key('j'), Binding::with(KeyCode::Down, KeyModifiers::NONE)],
+    MoveUp(HelpMoveUp) = [Binding::key('k'), Binding::with(KeyCode::Up, KeyModifiers::NONE)],
+    /// Shows the selected entry's details or expands its run.
+    Select(HelpSelect) = [Binding::key('l'), Binding::with(KeyCode::Right, KeyModifiers::NONE)],
+    Unselect(HelpUnselect) = [Binding::key('h'), Binding::with(KeyCode::Left, KeyModifiers::NONE)],
+    ToggleDetails(HelpToggleDetails) = [Binding::key(' ')],
+    /// Pastes the selected entry or expands its run.
+    Paste(HelpPaste) = [Binding::with(KeyCode::Enter, KeyModifiers::NONE)],
+    /// Puts the selected entry on the clipboard with the display server's
+    /// clipboard tool, for when there's no watcher to paste through.
+    Yank(HelpYank) = [Binding::key('y')],
+    /// Clears marks, closes what's open or leaves the search, quitting if
+    /// there's nothing left to close.
+    Back(HelpBack) = [Binding::with(KeyCode::Esc, KeyModifiers::NONE)],
+    Quit(HelpQuit) = [Binding::key('q'), Binding::ctrl('c')],
+    Search(HelpSearch) = [Binding::key('/'), Binding::key('s')],
+    RegexSearch(HelpRegexSearch) = [Binding::key('x')],
+    MimeSearch(HelpMimeSearch) = [Binding::key('m')],
+    /// Lists the entries with a tag.
+    TagSearch(HelpTagSearch) = [Binding::key('T')],
+    /// Cycles between searching everything, the main ring and favorites.
+    SearchScope(HelpSearchScope) = [Binding::alt('f')],
+    /// Cycles between searching any entry, only text and only images.
+    MimeScope(HelpMimeScope) = [Binding::alt('m')],
+    MatchCase(HelpMatchCase) = [Binding::alt('c')],
+    FavoritesFirst(HelpFavoritesFirst) = [Binding::alt('p')],
+    Filter(HelpFilter) = [Binding::ctrl('f')],
+    Refresh(HelpRefresh) = [Binding::key('r')],
+    /// Forgets the UI state, e.g. what's selected or searched, and reloads.
+    Reset(HelpReset) = [Binding::ctrl('r')],
+    Favorite(HelpFavorite) = [Binding::key('f')],
+    Mark(HelpMark) = [Binding::key('v')],
+    MarkRange(HelpMarkRange) = [Binding::key('V')],
+    Reorder(HelpReorder) = [Binding::key('R')],
+    MoveFavoriteUp(HelpMoveFavoriteUp) = [Binding::with(KeyCode::Up, KeyModifiers::SHIFT)],
+    MoveFavoriteDown(HelpMoveFavoriteDown) = [Binding::with(KeyCode::Down, KeyModifiers::SHIFT)],
+    Split(HelpSplit) = [Binding::key('|')],
+    SwitchPane(HelpSwitchPane) = [Binding::with(KeyCode::Tab, KeyModifiers::NONE)],
+    Label(HelpLabel) = [Binding::key('a')],
+    /// Tags the selected entry, or untags it given a tag starting with `-`.
+    Tag(HelpTag) = [Binding::key('t')],
+    Open(HelpOpen) = [Binding::key('o')],
+    Delete(HelpDelete) = [Binding::key('d')],
+    /// Restores the entry deleted last, while the server still has it.
+    UndoDelete(HelpUndoDelete) = [Binding::key('u')],
+    /// Deletes every entry another entry has the same contents as, once
+    /// confirmed.
+    Deduplicate(HelpDeduplicate) = [Binding::key('D')],
+    Actions(HelpActions) = [Binding::key('.')],
+    ScrollDetailsDown(HelpScrollDetailsDown) = [Binding::key('J')],
+    ScrollDetailsUp(HelpScrollDetailsUp) = [Binding::key('K')],
+    ToggleWrap(HelpToggleWrap) = [Binding::key('w')],
+    ScrollDetailsLeft(HelpScrollDetailsLeft) = [Binding::key('H')],
+    ScrollDetailsRight(HelpScrollDetailsRight) = [Binding::key('L')],
+    /// Goes to the next match of the details' search, wrapping around.
+    NextMatch(HelpNextMatch) = [Binding::key('n')],
+    PreviousMatch(HelpPreviousMatch) = [Binding::key('N')],
+    /// Searches for entries like the one whose details are open.
+    FindSimilar(HelpFindSimilar) = [Binding::key('*')],
+    Top(HelpTop) = [Binding::key('g')],
+    Bottom(HelpBottom) = [Binding::key('G')],
+    PageDown(HelpPageDown) = [
+        Binding::with(KeyCode::PageDown, KeyModifiers::NONE),
+        Binding::ctrl('d')
+    ],
+    PageUp(HelpPageUp) = [Binding::with(KeyCode::PageUp, KeyModifiers::NONE), Binding::ctrl('u')],
+    /// Goes to an entry by id, or searches and replaces in the open text entry.
+    Jump(HelpJump) = [Binding::key(':')],
+    Edit(HelpEdit) = [Binding::key('e')],
+    ImageInfo(HelpImageInfo) = [Binding::key('i')],
+    BinaryAsText(HelpBinaryAsText) = [Binding::key('b')],
+    PrettyJson(HelpPrettyJson) = [Binding::key('p')],
+    /// Shows the hidden contents of the open sensitive entry.
+    Reveal(HelpReveal) = [Binding::key('P')],
+    Statistics(HelpStatistics) = [Binding::key('S')],
+    ServerStats(HelpServerStats) = [Binding::key('I')],
+    ErrorReport(HelpErrorReport) = [Binding::key('E')],
+    Help(HelpHelp) = [Binding::key('?')],
+}
+
+impl AppAction {
+    /// Whether the action changes how the search box searches, so it also
+    /// works while typing in it if it's bound to a key held with Ctrl or Alt.
+    pub const fn is_search_option(self) -> bool {
+        matches!(
+            self,
+            Self::SearchScope | Self::MimeScope | Self::MatchCase | Self::FavoritesFirst
+        )
+    }
+}
+
+/// Maps keys to actions in the entry list.
+#[derive(Debug)]
+pub struct Keymap {
+    bindings: Vec<(Binding, AppAction)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::parse("").0
+    }
+}
+
+impl Keymap {
+    /// Loads the key bindings file, along with the problems found in it.
+    pub fn load() -> (Self, Vec<String>) {
+        let Some(config) = config_path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return (Self::default(), Vec::new());
+        };
+        Self::parse(&config)
+    }
+
+    /// Builds a keymap from a key bindings file, keeping the defaults for
+    /// every action it doesn't mention.
+    ///
+    /// A key bound to several actions stays with the first one listed, with
+    /// actions listed in the file coming before the defaults.
+    pub fn parse(config: &str) -> (Self, Vec<String>) {
+        let mut problems = Vec::new();
+        let mut configured = Vec::<(AppAction, Vec<Binding>)>::new();
+        for (number, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let number = number + 1;
+            let Some((name, keys)) = line.split_once('=') else {
+                problems.push(strings::fill(Str::KeymapMalformed, &[&number]));
+                continue;
+            };
+            let name = name.trim();
+            let Some(&action) = AppAction::ALL.iter().find(|a| a.name() == name) else {
+                problems.push(strings::fill(Str::KeymapUnknownAction, &[&number, &name]));
+                continue;
+            };
+            let keys = keys
+                .split_whitespace()
+                .filter_map(|key| {
+                    let binding = Binding::parse(key);
+                    if binding.is_none() {
+                        problems.push(strings::fill(Str::KeymapUnknownKey, &[&number, &key]));
+                    }
+                    binding
+                })
+                .collect();
+
+            // The last line for an action wins.
+            configured.retain(|&(a, _)| a != action);
+            configured.push((action, keys));
+        }
+
+        let defaults = AppAction::ALL
+            .iter()
+            .filter(|&&action| configured.iter().all(|&(a, _)| a != action))
+            .map(|&action| (action, action.default_keys().to_vec()))
+            .collect::<Vec<_>>();
+        let mut bindings = Vec::<(Binding, AppAction)>::new();
+        for (action, keys) in configured.into_iter().chain(defaults) {
+            for key in keys {
+                if let Some(&(_, kept)) = bindings.iter().find(|&&(b, _)| b == key) {
+                    if kept != action {
+                        problems.push(strings::fill(
+                            Str::KeymapConflict,
+                            &[&key.name(false), &kept.name(), &action.name()],
+                        ));
+                    }
+                    continue;
+                }
+                bindings.push((key, action));
+            }
+        }
+        (Self { bindings }, problems)
+    }
+
+    pub fn get(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<AppAction> {
+        let key = Binding::new(code, modifiers);
+        self.bindings
+            .iter()
+            .find(|&&(b, _)| b == key)
+            .map(|&(_, action)| action)
+    }
+
+    /// The name of the first key bound to `action`, if any.
+    pub fn key_name(&self, action: AppAction, accessible: bool) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|&&(_, a)| a == action)
+            .map(|&(key, _)| key.name(accessible))
+    }
+
+    /// Describes what every bound key does.
+    pub fn help(&self, accessible: bool) -> String {
+        let mut out = String::new();
+        for &action in AppAction::ALL {
+            let mut keys = String::new();
+            for &(key, _) in self.bindings.iter().filter(|&&(_, a)| a == action) {
+                if !keys.is_empty() {
+                    keys.push('/');
+                }
+                keys.push_str(&key.name(accessible));
+            }
+            if keys.is_empty() {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push_str(", ");
+            }
+            out.push_str(&strings::fill(action.help(), &[&keys]));
+        }
+        out
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    let mut path = strings::config_dir()?;
+    path.push("tui-keys");
+    Some(path)
+}
@@ -1,17 +1,27 @@
 #![feature(let_chains)]
 
 use std::{
-    fmt::Write,
-    fs::File,
+    borrow::Cow,
+    cmp::{max, min},
+    collections::{HashMap, HashSet, VecDeque},
+    env,
+    fmt::{Debug, Display, Write as _},
+    fs,
+    fs::{File, OpenOptions},
     io,
     io::BufWriter,
+    iter::once,
+    mem,
     mem::ManuallyDrop,
+    ops::Range,
     os::fd::FromRawFd,
+    path::{Path, PathBuf},
     sync::{
         mpsc,
         mpsc::{Receiver, Sender},
     },
     thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use ratatui::{
@@ -19,31 +29,69 @@ use ratatui::{
     buffer::Buffer,
     crossterm::{
         event,
-        event::{Event, KeyEvent, KeyEventKind, KeyModifiers},
+        event::{
+            DisableBracketedPaste, DisableFocusChange, EnableBracketedPaste, EnableFocusChange,
+            Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+        },
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
         ExecutableCommand,
     },
     layout::{Alignment, Constraint, Layout, Rect},
-    style::{Modifier, Style, Stylize},
-    text::Line,
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
     widgets::{
-        Block, Borders, HighlightSpacing, List, ListState, Padding, Paragraph, StatefulWidget,
+        Block, Borders, Clear, HighlightSpacing, List, ListItem, ListState, Padding, Paragraph,
+        Row, Scrollbar, ScrollbarOrientation, ScrollbarState, Sparkline, StatefulWidget, Table,
         Widget, Wrap,
     },
     Terminal,
 };
-use ratatui_image::{picker::Picker, protocol::StatefulProtocol, StatefulImage};
+use ratatui_image::{
+    picker::Picker,
+    protocol::{Protocol, StatefulProtocol},
+    FontSize, Image, Resize, StatefulImage,
+};
+use clap::{ArgAction, Parser};
+use regex::{Regex, RegexBuilder};
 use ringboard_sdk::{
-    core::{protocol::RingKind, Error as CoreError, IoErr},
+    api::set_client_name,
+    clipboard::CopyError,
+    core::{
+        bucket_to_length,
+        checksums::Verification,
+        dirs::data_dir,
+        protocol::{
+            decompose_id, BucketUsage, DirectUsage, Label, MimeType, PackedUsage, RingKind,
+            ServerInfoResponse, StatsResponse,
+        },
+        tags, Error as CoreError, IoErr,
+    },
+    format_id, parse_id,
     search::CancellationToken,
     ui_actor::{
-        controller, Command, CommandError, DetailedEntry, Message, SearchKind, UiEntry,
-        UiEntryCache,
+        controller, remove_opened_files, search_matches, watch_database, Command, CommandError,
+        Dashboard, DetailedEntry, EntryBadge, ImageMetadata, LargeEntry, Message, MimeTypeStats,
+        MoveDirection, SearchKind, UiEntry, UiEntryCache, DASHBOARD_DAYS,
     },
+    ClientError, Entry, Kind, TextKind,
+};
+use rustix::{
+    process::{getpid, kill_process, Signal},
+    stdio::raw_stdout,
 };
-use rustix::stdio::raw_stdout;
 use thiserror::Error;
 use tui_textarea::{CursorMove, Input, Key, TextArea};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{
+    keymap::{AppAction, Keymap},
+    session::Session,
+    strings::Str,
+};
+
+mod keymap;
+mod session;
+mod strings;
 
 #[cfg(feature = "trace")]
 #[global_allocator]
@@ -53,6 +101,8 @@ static GLOBAL: tracy_client::ProfiledAllocator<std::alloc::System> =
 enum Action {
     Controller(Message),
     User(io::Result<Event>),
+    /// Sent periodically so notifications can fade without user input.
+    Tick,
 }
 
 impl From<Message> for Action {
@@ -71,9 +121,194 @@ struct App {
     requests: Sender<Command>,
     responses: Receiver<Action>,
     picker: Picker,
+    badges: BadgeStyle,
+    accessible: bool,
+    favorites_only: bool,
+    runtime: RuntimeInfo,
+    announcer: Option<Announcer>,
     state: State,
 }
 
+/// Where this session's history lives, shown in the title bar so a database
+/// picked through environment overrides isn't mistaken for the usual one.
+#[derive(Debug, Default)]
+struct RuntimeInfo {
+    /// The database directory, only if it isn't the default one.
+    database: Option<PathBuf>,
+}
+
+impl RuntimeInfo {
+    fn from_env() -> Self {
+        let database = data_dir();
+        let default = env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".local/share/clipboard-history"));
+        Self {
+            database: (default.as_ref() != Some(&database)).then_some(database),
+        }
+    }
+}
+
+/// An interactive terminal client for browsing and pasting your clipboard
+/// history.
+#[derive(Parser, Debug)]
+#[command(version, author = "Alex Saveau (@SUPERCILEX)")]
+#[command(disable_help_flag = true)]
+#[command(max_term_width = 100)]
+#[cfg_attr(test, command(help_expected = true))]
+struct Cli {
+    /// Launch as a snippet picker: only favorites are shown with their
+    /// quick-select digits, and the app exits once an entry is pasted
+    #[arg(long)]
+    favorites: bool,
+
+    /// Collapse runs of near-identical consecutive entries into a single row
+    #[arg(long)]
+    collapse: bool,
+
+    /// Walk through the basics even if the tutorial was already dismissed
+    #[arg(long)]
+    tutorial: bool,
+
+    /// Avoid glyphs screen readers trip over (also set with
+    /// `RINGBOARD_ACCESSIBLE=1`)
+    #[arg(long)]
+    accessible: bool,
+
+    /// Write a line describing the selected entry to this file or FIFO for
+    /// screen readers (also set with `RINGBOARD_ANNOUNCE`)
+    #[arg(long, value_name = "PATH")]
+    announce: Option<PathBuf>,
+
+    #[arg(short, long, short_alias = '?')]
+    #[arg(action = ArgAction::Help, help = "Print help (use `--help` for more detail)")]
+    #[arg(long_help = "Print help (use `-h` for a summary)")]
+    help: Option<bool>,
+}
+
+/// Remembers UI state across runs. Its absence means this is the first run.
+fn ui_state_file() -> PathBuf {
+    let mut file = data_dir();
+    file.push("tui-state");
+    file
+}
+
+/// Whether to walk the user through the basics: on first run (outside the
+/// snippet picker) or when `requested` with `--tutorial`.
+fn tutorial_mode(state_file: &Path, favorites_only: bool, requested: bool) -> bool {
+    requested || (!favorites_only && !state_file.exists())
+}
+
+/// Makes sure the tutorial isn't shown again.
+fn record_tutorial_dismissed(state_file: &Path) {
+    // Failing just means the tutorial comes back next time.
+    if let Some(dir) = state_file.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(state_file, "tutorial-dismissed\n");
+}
+
+/// How every on/off environment variable is read: `0`, `off`, `false` and
+/// `no` (in any case) turn a flag off, other values turn it on, and empty
+/// values count as unset.
+fn flag_value(value: &str) -> Option<bool> {
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(
+            !["0", "off", "false", "no"]
+                .iter()
+                .any(|off| value.eq_ignore_ascii_case(off)),
+        )
+    }
+}
+
+fn env_flag(name: &str) -> Option<bool> {
+    env::var(name).ok().as_deref().and_then(flag_value)
+}
+
+/// How many bytes an entry can hold before pasting it must be confirmed, set
+/// with `RINGBOARD_PASTE_WARNING`. Turning the flag off disables the warning.
+fn paste_warning_from_env() -> Option<u64> {
+    const DEFAULT: u64 = 1 << 20;
+
+    if env_flag("RINGBOARD_PASTE_WARNING") == Some(false) {
+        return None;
+    }
+    Some(
+        env::var("RINGBOARD_PASTE_WARNING")
+            .ok()
+            .and_then(|bytes| bytes.trim().parse().ok())
+            .unwrap_or(DEFAULT),
+    )
+}
+
+/// Whether `RINGBOARD_QUICK_SELECT=off` turned off the digits that paste the
+/// entries shown next to them, leaving `1`-`9` free.
+fn quick_select_disabled_from_env() -> bool {
+    env_flag("RINGBOARD_QUICK_SELECT") == Some(false)
+}
+
+struct Accessibility {
+    enabled: bool,
+    announce: Option<PathBuf>,
+}
+
+impl Accessibility {
+    /// Flags win over their environment variables.
+    fn new(accessible: bool, announce: Option<PathBuf>) -> Self {
+        Self {
+            enabled: accessible || env_flag("RINGBOARD_ACCESSIBLE").unwrap_or(false),
+            announce: announce.or_else(|| env::var_os("RINGBOARD_ANNOUNCE").map(PathBuf::from)),
+        }
+    }
+}
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug)]
+enum BadgeStyle {
+    NerdFont,
+    #[default]
+    Ascii,
+    Off,
+}
+
+impl BadgeStyle {
+    fn from_env() -> Self {
+        Self::parse(env::var("RINGBOARD_BADGES").ok().as_deref())
+    }
+
+    /// Unknown styles fall back to the default rather than failing startup.
+    fn parse(style: Option<&str>) -> Self {
+        match style {
+            Some("nerd") => Self::NerdFont,
+            Some("off") => Self::Off,
+            _ => Self::default(),
+        }
+    }
+
+    fn glyph(self, badge: EntryBadge) -> &'static str {
+        match self {
+            Self::NerdFont => match badge {
+                EntryBadge::None => "  ",
+                EntryBadge::Image => "\u{f03e} ",
+                EntryBadge::Document => "\u{f0219} ",
+                EntryBadge::Code => "\u{f121} ",
+                EntryBadge::Url => "\u{f0c1} ",
+                EntryBadge::ImageText => "\u{f031} ",
+            },
+            Self::Ascii => match badge {
+                EntryBadge::None => "  ",
+                EntryBadge::Image => strings::get(Str::BadgeImage),
+                EntryBadge::Document => strings::get(Str::BadgeDocument),
+                EntryBadge::Code => strings::get(Str::BadgeCode),
+                EntryBadge::Url => strings::get(Str::BadgeUrl),
+                EntryBadge::ImageText => strings::get(Str::BadgeImageText),
+            },
+            Self::Off => "",
+        }
+    }
+}
+
 #[derive(Default)]
 struct State {
     entries: UiEntries,
@@ -84,774 +319,7847 @@ struct State {
 struct UiEntries {
     loaded_entries: Box<[UiEntry]>,
     search_results: Box<[UiEntry]>,
+    filtered_entries: Option<Box<[usize]>>,
+    /// Only set in collapse mode.
+    groups: Option<Groups>,
+    reorder: Option<Reorder>,
+    /// The entry that owns the system clipboard, as of the last load.
+    on_clipboard: Option<u64>,
+    /// Entries marked with v or V, which d and f then act on together.
+    marked: HashSet<u64>,
+    /// Where V starts marking from.
+    last_mark: Option<u64>,
+    /// Bumped whenever the entries, which of them are shown, or how they're
+    /// marked change.
+    generation: u64,
+    rows: RowsCache,
+    favorite_rows: RowsCache,
 
     loaded_state: ListState,
+    /// The favorites pane's selection in split mode, where `loaded_state` is
+    /// the main ring pane's.
+    favorites_state: ListState,
     search_state: ListState,
 }
 
+/// The entry list as last rendered, which is too expensive to rebuild on
+/// every frame for large pages.
+#[derive(Default)]
+struct RowsCache {
+    key: Option<RowsKey>,
+    list: List<'static>,
+    /// The row the favorites end at, which is followed by a separator.
+    separator_after: Option<usize>,
+}
+
+/// Everything the rendered rows depend on besides the entries themselves.
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct RowsKey {
+    generation: u64,
+    width: u16,
+    searching: bool,
+    pane: Option<RingKind>,
+    reorder: Option<(usize, usize)>,
+}
+
+/// Runs of near-identical consecutive entries, e.g. the same command with a
+/// changed argument, which are shown as a single row until expanded.
+///
+/// Grouping only affects how the loaded entries are displayed.
+#[derive(Default)]
+struct Groups {
+    /// The members of each run, newest first, keyed by its newest entry.
+    runs: HashMap<u64, Box<[u64]>>,
+    expanded: HashSet<u64>,
+}
+
+/// A favorite lifted out of the list to be moved elsewhere.
+///
+/// The loaded entries keep the database's order: the move is only previewed
+/// by rearranging the rows until it's committed.
+#[derive(Copy, Clone)]
+struct Reorder {
+    /// The lifted entry's index in the loaded entries.
+    from: usize,
+    /// The row it's shown in.
+    to: usize,
+    /// Whether the move was sent to the server.
+    committed: bool,
+}
+
+impl Reorder {
+    /// The loaded entry shown in a row of the preview.
+    const fn source_index(self, row: usize) -> usize {
+        let Self { from, to, .. } = self;
+        if row == to {
+            from
+        } else if from < to && row >= from && row < to {
+            row + 1
+        } else if to < from && row > to && row <= from {
+            row - 1
+        } else {
+            row
+        }
+    }
+}
+
+/// The narrowest terminal the main ring and favorites fit side by side in.
+const MIN_SPLIT_WIDTH: u16 = 60;
+/// Below this size, the layout is replaced with a note asking for more room.
+const MIN_WIDTH: u16 = 20;
+const MIN_HEIGHT: u16 = 5;
+
+/// How many older entries to load at a time once the bottom is reached.
+const PAGE_SIZE: usize = 100;
+
+/// How much of two entries' text must match for them to be grouped.
+const SIMILARITY_PERCENT: usize = 70;
+
+/// The largest entry that can be edited or searched and replaced in, in bytes.
+const MAX_REPLACE_LEN: u64 = 1 << 20;
+
+/// The narrowest list entry ages are shown in, leaving room for the entries.
+const MIN_AGES_WIDTH: u16 = 24;
+/// How many columns H/L scroll unwrapped details by.
+const HORIZONTAL_SCROLL_STEP: u16 = 8;
+/// Room for ages like ` 12m`.
+const MAX_AGE_WIDTH: u16 = 5;
+/// How wide the dashboard's column of entry sizes is, e.g. `  12.5 KiB `.
+const SIZE_COLUMN_WIDTH: usize = 11;
+
+/// How many columns image thumbnails take up in the entry list.
+const THUMBNAIL_WIDTH: u16 = 4;
+/// The room left at the start of entry rows for their quick-select digit.
+const QUICK_SELECT_PADDING: &str = "  ";
+
+/// The statistics dashboard, filled in as the controller scans entries.
+#[derive(Default)]
+struct DashboardView {
+    stats: Option<Box<Dashboard>>,
+    token: Option<CancellationToken>,
+    /// The highlighted entry among the largest ones.
+    selected: usize,
+}
+
+/// How far the controller got towards the first page of entries.
+#[derive(Default, Copy, Clone, Eq, PartialEq)]
+enum Startup {
+    #[default]
+    Opening,
+    Loading,
+    Loaded,
+}
+
 #[derive(Default)]
 struct UiState {
     last_error: Option<CommandError>,
+    /// The last error's full chain, kept once it's no longer shown so it can be
+    /// reported.
+    error_report: Option<String>,
+    /// The error report's scroll offset while it's open.
+    error_report_scroll: Option<u16>,
+    keymap: Keymap,
+    status: StatusLine,
     outstanding_request: Option<u64>,
+    /// The main ring entry the next page is being loaded after.
+    page_requested: Option<u64>,
+    /// Whether the oldest main ring entry has been loaded.
+    loaded_all: bool,
+    startup: Startup,
+    /// A change to a collapsed run waiting for the user to say whether it
+    /// applies to every member.
+    pending_change: Option<(Change, Entry)>,
+    /// Whether deleting every duplicate entry is waiting for the user to
+    /// confirm it.
+    pending_deduplicate: bool,
 
     details_requested: Option<u64>,
+    /// The [fingerprint](UiEntry::fingerprint) of the entry the details were
+    /// requested for, so they're only fetched again if its contents changed.
+    details_fingerprint: u64,
     detailed_entry: Option<Result<DetailedEntry, CoreError>>,
+    /// The detailed entry's text wrapped to the width it was last shown at.
+    detail_rows: Option<(u16, Box<[Range<usize>]>)>,
+    /// Where the search query matches the detailed entry's text, worked out
+    /// along with its rows.
+    detail_matches: Box<[Range<usize>]>,
+    /// Whether to scroll the details to the first match once they're shown.
+    scroll_to_match: bool,
+    /// Text being found in the open details, whose matches are highlighted
+    /// instead of the search's.
+    detail_query: TextArea<'static>,
+    /// Whether the details query is being typed.
+    finding: bool,
+    /// The match n and N last went to, which stands out from the others.
+    current_match: Option<usize>,
     detail_scroll: u16,
+    /// Whether long lines in the details run off the side instead of being
+    /// wrapped, for things like logs and minified JSON. Kept across entries.
+    unwrapped_details: bool,
+    /// How many columns unwrapped details are scrolled sideways.
+    detail_column: u16,
     detail_image_state: Option<ImageState>,
+    /// Whether to show facts about images, like their size, below them.
+    show_image_metadata: bool,
+    /// Whether to show binary entries as lossy UTF-8 instead of a hex dump.
+    binary_as_text: bool,
+    /// Whether JSON entries are shown reindented in the details. Kept across
+    /// entries.
+    pretty_json: bool,
+    /// The sensitive entry whose contents were asked to be shown. Hidden again
+    /// once another entry is opened.
+    revealed: Option<u64>,
+    thumbnails: Thumbnails,
+    scroll_positions: ScrollPositions,
 
     query: TextArea<'static>,
     search_state: Option<SearchState>,
-    pending_search_token: Option<CancellationToken>,
+    /// The ring searches are limited to, if any. Kept across searches.
+    search_scope: Option<RingKind>,
+    /// The kind of entries searches are limited to, if any. Kept across
+    /// searches.
+    search_mime: Option<MimeScope>,
+    /// Whether plain searches match case. Kept across searches.
+    match_case: bool,
+    /// Whether search results list favorites before newer entries. Kept
+    /// across searches.
+    favorites_first: bool,
+    pending_search: Option<PendingSearch>,
     queued_searches: u32,
+    /// Whether the search being sent was asked for by the user, so its
+    /// number of matches is worth a notification.
+    announce_search: bool,
+
+    filter: TextArea<'static>,
+    filter_state: Option<FilterState>,
+
+    label: TextArea<'static>,
+    /// The entry whose label is being edited.
+    labeling: Option<u64>,
+
+    tag: TextArea<'static>,
+    /// The entry a tag is being typed for.
+    tagging: Option<u64>,
+
+    substitution: TextArea<'static>,
+    /// The entry a `s/pattern/replacement/` command is being typed for.
+    substituting: Option<u64>,
+    /// Why the last command couldn't be applied, shown until it's edited.
+    substitution_error: Option<&'static str>,
+    /// A replacement waiting for the user to confirm it.
+    pending_replace: Option<PendingReplace>,
+
+    editor: TextArea<'static>,
+    /// The text entry being edited in place of its details.
+    editing: Option<u64>,
+
+    jump: TextArea<'static>,
+    /// Whether the id of an entry to select is being typed.
+    jumping: bool,
+
+    /// The highlighted row of the selected entry's actions menu while it's
+    /// open.
+    actions_menu: Option<usize>,
+
+    /// Entries bigger than this many bytes are only pasted once confirmed.
+    paste_warning: Option<u64>,
+    /// A large entry waiting for the user to confirm pasting it.
+    pending_paste: Option<PendingPaste>,
+    /// Whether digits are left alone instead of pasting the entries shown next
+    /// to them.
+    disable_quick_select: bool,
+
+    /// The focused pane when the main ring and favorites are shown side by
+    /// side.
+    split: Option<RingKind>,
+    /// The terminal's width as of the last frame.
+    terminal_width: u16,
+    /// How many entries the focused list showed as of the last frame.
+    page_height: u16,
 
     show_help: bool,
+    server_info: Option<ServerInfoResponse>,
+    /// The tutorial page being shown, if any.
+    tutorial: Option<usize>,
+    /// The statistics dashboard while it's open.
+    dashboard: Option<DashboardView>,
+    /// The server's stats while they're shown in place of the details, empty
+    /// until they arrive.
+    server_stats: Option<Option<Box<StatsResponse>>>,
+    /// Where dismissing the tutorial is recorded.
+    state_file: Option<PathBuf>,
+    /// The last session, restored once the first page is loaded and its
+    /// search, if any, has results.
+    session: Option<Session>,
 
     cache: String,
 }
 
-struct SearchState {
-    focused: bool,
-    kind: SearchKind,
+struct PendingPaste {
+    id: u64,
+    len: u64,
+    mime_type: Box<str>,
 }
 
-enum ImageState {
-    Requested(u64),
-    Loaded(Box<dyn StatefulProtocol>),
+/// The latest search, whose results are still coming in.
+struct PendingSearch {
+    generation: u64,
+    token: CancellationToken,
+    /// Whether its results replaced the previous search's yet.
+    shown: bool,
 }
 
-macro_rules! active_entries {
-    ($entries:expr, $state:expr) => {{
-        if $state.query.is_empty() {
-            &$entries.loaded_entries
-        } else {
-            &$entries.search_results
-        }
-    }};
+struct PendingReplace {
+    id: u64,
+    text: Box<str>,
+    matches: usize,
+    /// The lines that changed, old ones first.
+    diff: Box<[Line<'static>]>,
 }
 
-macro_rules! active_list_state {
-    ($entries:expr, $state:expr) => {{
-        if $state.query.is_empty() {
-            &mut $entries.loaded_state
-        } else {
-            &mut $entries.search_state
-        }
-    }};
+/// Thumbnails of recently shown image entries, so scrolling back to them
+/// doesn't decode them again.
+#[derive(Default)]
+struct Thumbnails {
+    entries: VecDeque<(u64, u64, Thumbnail)>,
 }
 
-macro_rules! selected_entry {
-    ($entries:expr, $state:expr) => {{
-        if $state.query.is_empty() {
-            &$entries.loaded_state
-        } else {
-            &$entries.search_state
+enum Thumbnail {
+    Requested,
+    Loaded(Box<dyn Protocol>),
+    /// The entry couldn't be decoded, so only its placeholder text is shown.
+    Failed,
+}
+
+impl Thumbnails {
+    /// Enough for every row of a tall terminal in split mode.
+    const CAPACITY: usize = 256;
+
+    /// The entry's thumbnail, unless its contents changed since it was made.
+    fn get(&mut self, id: u64, fingerprint: u64) -> Option<&Thumbnail> {
+        let index = self.entries.iter().position(|&(i, _, _)| i == id)?;
+        let entry = self.entries.remove(index)?;
+        if entry.1 != fingerprint {
+            return None;
         }
-        .selected()
-        .and_then(|selected| active_entries!($entries, $state).get(selected))
-    }};
+        self.entries.push_back(entry);
+        self.entries.back().map(|(_, _, thumbnail)| thumbnail)
+    }
+
+    fn insert(&mut self, id: u64, fingerprint: u64, thumbnail: Thumbnail) {
+        self.entries.retain(|&(i, _, _)| i != id);
+        if self.entries.len() == Self::CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((id, fingerprint, thumbnail));
+    }
+
+    /// Fills in a requested thumbnail, unless it was evicted meanwhile.
+    fn loaded(&mut self, id: u64, thumbnail: Thumbnail) {
+        if let Some((_, _, requested @ Thumbnail::Requested)) =
+            self.entries.iter_mut().find(|(i, _, _)| *i == id)
+        {
+            *requested = thumbnail;
+        }
+    }
 }
 
-#[derive(Error, Debug)]
-enum Wrapper {
-    #[error("{0}")]
-    W(String),
+/// Detail pane scroll offsets of recently viewed entries.
+#[derive(Default)]
+struct ScrollPositions {
+    entries: VecDeque<(u64, Kind, u16)>,
 }
 
-fn main() -> error_stack::Result<(), Wrapper> {
-    #[cfg(not(debug_assertions))]
-    error_stack::Report::install_debug_hook::<std::panic::Location>(|_, _| {});
+impl ScrollPositions {
+    const CAPACITY: usize = 32;
 
-    run().map_err(|e| {
-        let wrapper = Wrapper::W(e.to_string());
-        e.into_report(wrapper)
-    })
+    fn remember(&mut self, id: u64, kind: Kind, scroll: u16) {
+        self.entries.retain(|&(i, _, _)| i != id);
+        if scroll == 0 {
+            return;
+        }
+        if self.entries.len() == Self::CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((id, kind, scroll));
+    }
+
+    /// The remembered offset if the entry hasn't been replaced since.
+    fn take(&mut self, id: u64, kind: Kind) -> Option<u16> {
+        let index = self.entries.iter().position(|&(i, _, _)| i == id)?;
+        let (_, previous_kind, scroll) = self.entries.remove(index)?;
+        (previous_kind == kind).then_some(scroll)
+    }
+
+    /// Forgets entries whose contents might have changed without a visible
+    /// difference in their metadata.
+    fn forget_files(&mut self) {
+        self.entries.retain(|&(_, kind, _)| kind != Kind::File);
+    }
 }
 
-fn run() -> Result<(), CoreError> {
-    let stdout = ManuallyDrop::new(unsafe { File::from_raw_fd(raw_stdout()) });
-    let mut stdout = BufWriter::new(&*stdout);
+/// The footer's line of feedback: notifications that fade after a while or
+/// on the next key press, or a prompt that stays until it's answered.
+#[derive(Default)]
+struct StatusLine {
+    /// The shown notification followed by those waiting their turn.
+    notifications: VecDeque<Cow<'static, str>>,
+    /// When the front notification started being shown.
+    shown_at: Option<Instant>,
+    prompt: Option<Cow<'static, str>>,
+}
 
-    let mut terminal = init_terminal(&mut stdout)?;
-    let r = App::init(&mut terminal).and_then(|app| app.run(terminal));
-    restore_terminal(&mut stdout)?;
-    r
+impl StatusLine {
+    const CAPACITY: usize = 4;
+    const TIMEOUT: Duration = Duration::from_secs(4);
+    /// How often the UI is woken up to let notifications fade.
+    const TICK: Duration = Duration::from_millis(250);
+
+    fn show(&mut self, message: impl Into<Cow<'static, str>>) {
+        let message = message.into();
+        // A burst of the same news, e.g. from changing many entries, is said once.
+        if self.notifications.back() == Some(&message) {
+            return;
+        }
+        if self.notifications.len() == Self::CAPACITY {
+            self.notifications.pop_front();
+            self.shown_at = None;
+        }
+        self.notifications.push_back(message);
+        self.shown_at.get_or_insert_with(Instant::now);
+    }
+
+    /// Asks the user something, overriding notifications until cleared.
+    fn prompt(&mut self, message: impl Into<Cow<'static, str>>) {
+        self.prompt = Some(message.into());
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn current(&self) -> Option<&str> {
+        self.prompt
+            .as_deref()
+            .or_else(|| self.notifications.front().map(|message| &**message))
+    }
+
+    /// Drops the shown notification if it's been up for long enough, returning
+    /// whether anything changed.
+    fn expire(&mut self, now: Instant) -> bool {
+        let Some(shown_at) = self.shown_at else {
+            return false;
+        };
+        if now < shown_at + Self::TIMEOUT {
+            return false;
+        }
+        self.notifications.pop_front();
+        self.shown_at = (!self.notifications.is_empty()).then_some(now);
+        self.prompt.is_none()
+    }
 }
 
-fn init_terminal(mut stdout: impl io::Write) -> Result<Terminal<impl Backend>, CoreError> {
-    enable_raw_mode().map_io_err(|| "Failed to enable raw mode.")?;
-    stdout
-        .execute(EnterAlternateScreen)
-        .map_io_err(|| "Failed to enter alternate screen.")?;
-    Terminal::new(CrosstermBackend::new(stdout)).map_io_err(|| "Failed to initialize terminal.")
+struct SearchState {
+    focused: bool,
+    kind: SearchKind,
 }
 
-fn restore_terminal(mut stdout: impl io::Write) -> Result<(), CoreError> {
-    disable_raw_mode().map_io_err(|| "Failed to disable raw mode.")?;
-    stdout
-        .execute(LeaveAlternateScreen)
-        .map_io_err(|| "Failed to leave alternate screen.")?;
-    Ok(())
+#[derive(Copy, Clone)]
+enum Change {
+    Favorite,
+    Delete,
 }
 
-impl App {
-    fn init(terminal: &mut Terminal<impl Backend>) -> Result<Self, CoreError> {
-        let (command_sender, command_receiver) = mpsc::channel();
-        let (response_sender, response_receiver) = mpsc::sync_channel(8);
-        let mut state = State::default();
+struct FilterState {
+    focused: bool,
+}
 
-        AppWrapper {
-            state: &mut state,
-            requests: &command_sender,
-        }
-        .draw(terminal)
-        .map_io_err(|| "Failed to write to terminal.")?;
+enum ImageState {
+    Requested {
+        id: u64,
+        token: Option<CancellationToken>,
+        progress: Option<u8>,
+    },
+    Loaded(Box<dyn StatefulProtocol>, ImageMetadata),
+    Failed(CommandError),
+}
 
-        let mut picker = Picker::from_termios().unwrap_or_else(|_| Picker::new((2, 4)));
-        picker.guess_protocol();
+#[derive(Copy, Clone)]
+struct ActiveEntries<'a> {
+    entries: &'a [UiEntry],
+    filter: Option<&'a [usize]>,
+    reorder: Option<Reorder>,
+}
 
-        thread::spawn({
-            let sender = response_sender.clone();
-            move || controller(&command_receiver, |m| sender.send(m.into()))
-        });
-        thread::spawn(move || {
-            loop {
-                let r = event::read();
-                let oopsies = r.is_err();
-                if response_sender.send(r.into()).is_err() || oopsies {
-                    break;
-                }
-            }
-        });
+impl<'a> ActiveEntries<'a> {
+    fn len(self) -> usize {
+        self.filter.map_or(self.entries.len(), <[usize]>::len)
+    }
 
-        Ok(Self {
-            requests: command_sender,
-            responses: response_receiver,
-            picker,
+    fn is_empty(self) -> bool {
+        self.len() == 0
+    }
 
-            state,
-        })
+    fn get(self, index: usize) -> Option<&'a UiEntry> {
+        let index = self.reorder.map_or(index, |r| r.source_index(index));
+        match self.filter {
+            None => self.entries.get(index),
+            Some(filter) => filter.get(index).map(|&i| &self.entries[i]),
+        }
     }
-}
 
-impl App {
-    fn run(mut self, mut terminal: Terminal<impl Backend>) -> Result<(), CoreError> {
-        let Self {
-            requests,
-            responses,
-            ref mut picker,
-            ref mut state,
-        } = self;
+    fn iter(self) -> impl ExactSizeIterator<Item = &'a UiEntry> {
+        (0..self.len()).map(move |i| self.get(i).unwrap())
+    }
 
-        let mut local_state = Option::default();
-        for action in responses {
-            if match action {
-                Action::Controller(message) => {
-                    handle_message(message, state, &mut local_state, picker, &requests)?
-                }
-                Action::User(event) => handle_event(
-                    event.map_io_err(|| "Failed to read terminal.")?,
-                    state,
-                    &requests,
-                ),
-            } {
-                break;
-            }
+    /// The last favorite if the favorites are pinned above main ring entries.
+    fn last_pinned_favorite(self) -> Option<usize> {
+        let first_main = self.iter().position(|e| e.entry.ring() == RingKind::Main)?;
+        let pinned = self
+            .iter()
+            .skip(first_main)
+            .all(|e| e.entry.ring() == RingKind::Main);
+        first_main.checked_sub(1).filter(|_| pinned)
+    }
 
-            AppWrapper {
-                state,
-                requests: &requests,
+    /// Narrows loaded entries down to one ring's pane in split mode.
+    fn pane(self, pane: Option<RingKind>) -> Self {
+        let Some(ring) = pane else {
+            return self;
+        };
+        let favorites = self
+            .entries
+            .iter()
+            .take_while(|e| e.entry.ring() == RingKind::Favorites)
+            .count();
+        let (entries, filter) = match self.filter {
+            None => (
+                match ring {
+                    RingKind::Favorites => &self.entries[..favorites],
+                    RingKind::Main => &self.entries[favorites..],
+                },
+                None,
+            ),
+            Some(filter) => {
+                let (favorites, main) = filter.split_at(filter.partition_point(|&i| i < favorites));
+                (
+                    self.entries,
+                    Some(match ring {
+                        RingKind::Favorites => favorites,
+                        RingKind::Main => main,
+                    }),
+                )
             }
-            .draw(&mut terminal)
-            .map_io_err(|| "Failed to write to terminal.")?;
+        };
+        Self {
+            entries,
+            filter,
+            // Favorites can't be moved in split mode.
+            reorder: None,
         }
-        Ok(())
     }
 }
 
-fn handle_message(
-    message: Message,
-    State { entries, ui }: &mut State,
-    pending_favorite_change: &mut Option<u64>,
-    picker: &mut Picker,
-    requests: &Sender<Command>,
-) -> Result<bool, CoreError> {
-    let UiEntries {
-        loaded_entries,
-        search_results,
-        loaded_state,
-        search_state,
-    } = entries;
-    let UiState {
-        details_requested,
-        detailed_entry,
-        pending_search_token,
-        queued_searches,
-        last_error,
-        outstanding_request,
-        ..
-    } = ui;
+impl UiEntries {
+    fn loaded(&self) -> ActiveEntries<'_> {
+        ActiveEntries {
+            entries: &self.loaded_entries,
+            filter: self.filtered_entries.as_deref(),
+            reorder: self.reorder,
+        }
+    }
 
-    last_error.take();
-    match message {
-        Message::FatalDbOpen(e) => return Err(e)?,
-        Message::Error(e) => {
-            *last_error = Some(e);
-            *queued_searches = queued_searches.saturating_sub(1);
+    fn pane_state(&mut self, pane: Option<RingKind>) -> &mut ListState {
+        if pane == Some(RingKind::Favorites) {
+            &mut self.favorites_state
+        } else {
+            &mut self.loaded_state
         }
-        Message::LoadedFirstPage {
-            entries: new_entries,
-            default_focused_id,
-        } => {
-            *loaded_entries = new_entries;
-            if loaded_state.selected().is_none() {
-                loaded_state.select(default_focused_id.and_then(|selected_id| {
-                    loaded_entries
-                        .iter()
-                        .position(|e| e.entry.id() == selected_id)
-                }));
+    }
+
+    fn pane_rows(&mut self, pane: Option<RingKind>) -> &mut RowsCache {
+        if pane == Some(RingKind::Favorites) {
+            &mut self.favorite_rows
+        } else {
+            &mut self.rows
+        }
+    }
+
+    /// The number of favorites, which always come first.
+    fn favorites(&self) -> usize {
+        self.loaded_entries
+            .iter()
+            .take_while(|e| e.entry.ring() == RingKind::Favorites)
+            .count()
+    }
+
+    fn regroup(&mut self) {
+        let Some(Groups { runs, expanded }) = &mut self.groups else {
+            return;
+        };
+        *runs = similar_runs(&self.loaded_entries);
+        expanded.retain(|id| runs.contains_key(id));
+    }
+
+    /// The loaded entries left after hiding all but the first member of
+    /// collapsed runs.
+    fn collapsed_rows(&self) -> Option<Box<[usize]>> {
+        let Groups { runs, expanded } = self.groups.as_ref()?;
+        let mut rows = Vec::with_capacity(self.loaded_entries.len());
+        let mut hidden = 0;
+        for (i, e) in self.loaded_entries.iter().enumerate() {
+            if hidden > 0 {
+                hidden -= 1;
+                continue;
             }
-            if let Some(id) = pending_favorite_change.take() {
-                if let Some(index) = active_entries!(entries, ui)
-                    .iter()
-                    .position(|e| e.entry.id() == id)
-                {
-                    active_list_state!(entries, ui).select(Some(index));
-                    if details_requested.is_some() {
-                        *details_requested = Some(id);
-                    }
-                }
+            rows.push(i);
+            if let Some(run) = runs.get(&e.entry.id())
+                && !expanded.contains(&e.entry.id())
+            {
+                hidden = run.len() - 1;
             }
         }
-        Message::EntryDetails { id, result } => {
-            if *details_requested == Some(id) {
-                *detailed_entry = Some(result);
+        Some(rows.into())
+    }
+}
+
+fn similar_runs(entries: &[UiEntry]) -> HashMap<u64, Box<[u64]>> {
+    let mut runs = HashMap::new();
+    let mut start = 0;
+    for end in 1..=entries.len() {
+        if end < entries.len() && similar(&entries[end - 1], &entries[end]) {
+            continue;
+        }
+        if end - start > 1 {
+            runs.insert(
+                entries[start].entry.id(),
+                entries[start..end].iter().map(|e| e.entry.id()).collect(),
+            );
+        }
+        start = end;
+    }
+    runs
+}
+
+/// Compares entries by their common prefix and suffix, which is enough to
+/// catch edits in the middle of otherwise identical text.
+fn similar(a: &UiEntry, b: &UiEntry) -> bool {
+    let (
+        UiEntryCache::Text {
+            one_liner: a_text, ..
+        },
+        UiEntryCache::Text {
+            one_liner: b_text, ..
+        },
+    ) = (&a.cache, &b.cache)
+    else {
+        return false;
+    };
+    if a.entry.ring() != b.entry.ring() {
+        return false;
+    }
+
+    let (a, b) = (a_text.trim().as_bytes(), b_text.trim().as_bytes());
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take(min(a.len(), b.len()) - prefix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    (prefix + suffix) * 100 >= max(a.len(), b.len()) * SIMILARITY_PERCENT
+}
+
+/// The members of the run the entry heads if it's currently shown collapsed.
+fn collapsed_run<'a>(entries: &'a UiEntries, ui: &UiState, id: u64) -> Option<&'a [u64]> {
+    let Groups { runs, expanded } = entries.groups.as_ref()?;
+    if !ui.query.is_empty() || !filter_query(&ui.filter).is_empty() || expanded.contains(&id) {
+        return None;
+    }
+    runs.get(&id).map(|run| &**run)
+}
+
+fn expand_run(entries: &mut UiEntries, ui: &UiState, id: u64) {
+    if let Some(Groups { expanded, .. }) = &mut entries.groups {
+        expanded.insert(id);
+    }
+    apply_filter(entries, ui.split, filter_query(&ui.filter), Some(id));
+}
+
+macro_rules! active_entries {
+    ($entries:expr, $state:expr) => {
+        active_entries!($entries, $state, $state.split)
+    };
+    ($entries:expr, $state:expr, $pane:expr) => {{
+        if $state.query.is_empty() {
+            ActiveEntries {
+                entries: &$entries.loaded_entries,
+                filter: $entries.filtered_entries.as_deref(),
+                reorder: $entries.reorder,
+            }
+            .pane($pane)
+        } else {
+            ActiveEntries {
+                entries: &$entries.search_results,
+                filter: None,
+                reorder: None,
             }
         }
-        Message::SearchResults(entries) => {
-            *queued_searches = queued_searches.saturating_sub(1);
-            if pending_search_token.take().is_some() {
-                *search_results = entries;
-                if search_state.selected().is_none() {
-                    search_state.select_first();
+    }};
+}
+
+macro_rules! active_list_state {
+    ($entries:expr, $state:expr) => {
+        active_list_state!($entries, $state, $state.split)
+    };
+    ($entries:expr, $state:expr, $pane:expr) => {{
+        if !$state.query.is_empty() {
+            &mut $entries.search_state
+        } else if $pane == Some(RingKind::Favorites) {
+            &mut $entries.favorites_state
+        } else {
+            &mut $entries.loaded_state
+        }
+    }};
+}
+
+macro_rules! selected_entry {
+    ($entries:expr, $state:expr) => {{
+        if !$state.query.is_empty() {
+            &$entries.search_state
+        } else if $state.split == Some(RingKind::Favorites) {
+            &$entries.favorites_state
+        } else {
+            &$entries.loaded_state
+        }
+        .selected()
+        .and_then(|selected| active_entries!($entries, $state).get(selected))
+    }};
+}
+
+/// Writes a line describing the selected entry to a file (or FIFO,
+/// `/dev/fd/N`, etc.) for screen readers.
+///
+/// Lines are written by a helper thread so a slow reader can never stall the
+/// UI. When selections change faster than [`ANNOUNCE_INTERVAL`], only the
+/// latest one is announced.
+struct Announcer {
+    lines: Sender<String>,
+    last: Option<u64>,
+}
+
+const ANNOUNCE_INTERVAL: Duration = Duration::from_millis(150);
+
+impl Announcer {
+    fn spawn(path: PathBuf) -> Self {
+        let (lines, receiver) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) else {
+                return;
+            };
+            while let Ok(line) = receiver.recv() {
+                let line = receiver.try_iter().last().unwrap_or(line);
+                if io::Write::write_all(&mut file, line.as_bytes()).is_err() {
+                    break;
                 }
+                thread::sleep(ANNOUNCE_INTERVAL);
             }
+        });
+        Self { lines, last: None }
+    }
+
+    fn selection_changed(&mut self, State { entries, ui }: &State) {
+        let active = active_entries!(entries, ui);
+        let selected = if !ui.query.is_empty() {
+            &entries.search_state
+        } else if ui.split == Some(RingKind::Favorites) {
+            &entries.favorites_state
+        } else {
+            &entries.loaded_state
         }
-        Message::FavoriteChange(id) => {
-            *pending_favorite_change = Some(id);
-            outstanding_request.take_if(|&mut req_id| req_id == id);
+        .selected()
+        .and_then(|index| Some((index, active.get(index)?)));
+
+        let id = selected.map(|(_, e)| e.entry.id());
+        if id == self.last {
+            return;
         }
-        Message::Deleted(id) => {
-            outstanding_request.take_if(|&mut req_id| req_id == id);
+        self.last = id;
+
+        let Some((
+            index,
+            UiEntry {
+                entry,
+                cache,
+                badge,
+                label,
+                len: _,
+                fingerprint: _,
+                timestamp: _,
+            },
+        )) = selected
+        else {
+            return;
+        };
+        let mut line = strings::fill(Str::AnnounceEntry, &[&(index + 1), &active.len()]);
+        if entry.ring() == RingKind::Favorites {
+            line.push_str(strings::get(Str::AnnounceFavorite));
         }
-        Message::LoadedImage { id, image } => {
-            if let Some(ImageState::Requested(requested_id)) = ui.detail_image_state
-                && requested_id == id
-            {
-                ui.detail_image_state = Some(ImageState::Loaded(picker.new_resize_protocol(image)));
-            }
+        if entries.on_clipboard == id {
+            line.push_str(strings::get(Str::AnnounceOnClipboard));
         }
-        Message::PendingSearch(token) => {
-            if *queued_searches > 1 {
-                token.cancel();
+        if entries.marked.contains(&entry.id()) {
+            line.push_str(strings::get(Str::AnnounceMarked));
+        }
+        if let Some(label) = label {
+            line.push_str(&strings::fill(Str::AnnounceLabel, &[label]));
+        }
+        match cache {
+            UiEntryCache::Text { one_liner, .. } => line.push_str(&strings::fill(
+                Str::AnnounceText,
+                &[&one_liner.chars().take(80).collect::<String>()],
+            )),
+            UiEntryCache::Image if *badge == EntryBadge::ImageText => {
+                line.push_str(strings::get(Str::AnnounceImageText));
+            }
+            UiEntryCache::Image => line.push_str(strings::get(Str::AnnounceImage)),
+            UiEntryCache::Binary { mime_type } => {
+                line.push_str(&strings::fill(Str::AnnounceBinary, &[mime_type]));
             }
-            *pending_search_token = Some(token);
+            UiEntryCache::Sensitive => line.push_str(strings::get(Str::AnnounceSensitive)),
+            UiEntryCache::Error(e) => line.push_str(&strings::fill(Str::AnnounceError, &[e])),
         }
-        Message::Pasted => return Ok(true),
+        line.push('\n');
+        let _ = self.lines.send(line);
     }
-    if ui.details_requested.is_some() {
-        maybe_get_details(entries, ui, requests);
-    }
-    Ok(false)
 }
 
-fn maybe_get_details(entries: &UiEntries, ui: &mut UiState, requests: &Sender<Command>) {
-    if let Some(&UiEntry { entry, ref cache }) = selected_entry!(entries, ui)
-        && ui.details_requested != Some(entry.id())
-    {
-        ui.details_requested = Some(entry.id());
-        ui.detailed_entry = None;
-        ui.detail_scroll = 0;
-        ui.detail_image_state = None;
-        let _ = requests.send(Command::GetDetails {
-            id: entry.id(),
-            with_text: matches!(cache, UiEntryCache::Text { .. }),
-        });
-    }
+#[derive(Error, Debug)]
+enum Wrapper {
+    #[error("{0}")]
+    W(String),
 }
 
-fn handle_event(event: Event, state: &mut State, requests: &Sender<Command>) -> bool {
-    let State { entries, ui } = state;
+fn main() -> error_stack::Result<(), Wrapper> {
+    #[cfg(not(debug_assertions))]
+    error_stack::Report::install_debug_hook::<std::panic::Location>(|_, _| {});
 
-    let unselect = |ui: &mut UiState| {
-        ui.details_requested = None;
-        ui.detailed_entry = None;
-    };
-    let search = |ui: &mut UiState, kind: SearchKind| {
-        if let Some(token) = &ui.pending_search_token {
-            token.cancel();
+    run().map_err(|e| {
+        let wrapper = Wrapper::W(e.to_string());
+        e.into_report(wrapper)
+    })
+}
+
+fn run() -> Result<(), CoreError> {
+    strings::init();
+    set_client_name(env!("CARGO_BIN_NAME"));
+
+    let cli = Cli::parse();
+
+    let stdout = ManuallyDrop::new(unsafe { File::from_raw_fd(raw_stdout()) });
+    let mut stdout = BufWriter::new(&*stdout);
+
+    let mut terminal = init_terminal(&mut stdout)?;
+    let r =
+        App::init(&mut terminal, RuntimeInfo::from_env(), cli).and_then(|app| app.run(terminal));
+    restore_terminal(&mut stdout)?;
+    remove_opened_files();
+    r
+}
+
+fn init_terminal<W: io::Write>(mut stdout: W) -> Result<Terminal<CrosstermBackend<W>>, CoreError> {
+    setup_terminal(&mut stdout)?;
+    Terminal::new(CrosstermBackend::new(stdout)).map_io_err(|| "Failed to initialize terminal.")
+}
+
+fn setup_terminal(mut stdout: impl io::Write) -> Result<(), CoreError> {
+    enable_raw_mode().map_io_err(|| "Failed to enable raw mode.")?;
+    stdout
+        .execute(EnterAlternateScreen)
+        .map_io_err(|| "Failed to enter alternate screen.")?
+        .execute(EnableBracketedPaste)
+        .map_io_err(|| "Failed to enable bracketed paste.")?
+        .execute(EnableFocusChange)
+        .map_io_err(|| "Failed to enable focus change events.")?;
+    Ok(())
+}
+
+fn restore_terminal(mut stdout: impl io::Write) -> Result<(), CoreError> {
+    disable_raw_mode().map_io_err(|| "Failed to disable raw mode.")?;
+    stdout
+        .execute(DisableFocusChange)
+        .map_io_err(|| "Failed to disable focus change events.")?
+        .execute(DisableBracketedPaste)
+        .map_io_err(|| "Failed to disable bracketed paste.")?
+        .execute(LeaveAlternateScreen)
+        .map_io_err(|| "Failed to leave alternate screen.")?;
+    Ok(())
+}
+
+impl App {
+    fn init(
+        terminal: &mut Terminal<impl Backend>,
+        runtime: RuntimeInfo,
+        Cli {
+            favorites: favorites_only,
+            collapse,
+            tutorial,
+            accessible,
+            announce,
+            help: _,
+        }: Cli,
+    ) -> Result<Self, CoreError> {
+        let (command_sender, command_receiver) = mpsc::channel();
+        let (response_sender, response_receiver) = mpsc::sync_channel(8);
+        let mut state = State::default();
+        let Accessibility {
+            enabled: accessible,
+            announce,
+        } = Accessibility::new(accessible, announce);
+        let badges = match BadgeStyle::from_env() {
+            BadgeStyle::NerdFont if accessible => BadgeStyle::Ascii,
+            badges => badges,
+        };
+        if favorites_only {
+            state.ui.search_scope = Some(RingKind::Favorites);
         }
-        let _ = requests.send(Command::Search {
-            query: ui.query.lines().first().unwrap().to_string().into(),
-            kind,
-        });
-        ui.queued_searches += 1;
-    };
-    let refresh = |ui: &mut UiState| {
-        let _ = requests.send(Command::LoadFirstPage);
-        if let &Some(SearchState { focused: _, kind }) = &ui.search_state {
-            search(ui, kind);
+        state.ui.paste_warning = paste_warning_from_env();
+        state.ui.disable_quick_select = quick_select_disabled_from_env();
+        let (keymap, problems) = Keymap::load();
+        state.ui.keymap = keymap;
+        if !problems.is_empty() {
+            state
+                .ui
+                .status
+                .show(strings::fill(Str::KeymapProblems, &[&problems.len()]));
+            state.ui.error_report = Some(problems.join("\n"));
+        }
+        if collapse {
+            state.entries.groups = Some(Groups::default());
+        }
+        let state_file = ui_state_file();
+        if tutorial_mode(&state_file, favorites_only, tutorial) {
+            state.ui.tutorial = Some(0);
+        }
+        state.ui.state_file = Some(state_file);
+        // The snippet picker always starts afresh and doesn't disturb the
+        // session of the full TUI.
+        if !favorites_only {
+            state.ui.session = Session::load();
         }
-    };
 
-    match event {
-        Event::Key(KeyEvent {
-            code,
-            modifiers,
-            kind,
-            state: _,
-        }) => {
-            if kind == KeyEventKind::Press {
-                use ratatui::crossterm::event::KeyCode::{Char, Down, Enter, Esc, Left, Right, Up};
-                match code {
-                    Esc => {
-                        if let Some(SearchState { focused, .. }) = &mut ui.search_state
-                            && *focused
-                        {
-                            *focused = false;
-                        } else if ui.details_requested.is_some() {
-                            unselect(ui);
-                        } else if ui.search_state.is_some() {
-                            ui.search_state = None;
-                            ui.query = TextArea::default();
-                        } else {
-                            return true;
-                        }
-                    }
-                    Enter => {
-                        if let Some(SearchState { focused, .. }) = &mut ui.search_state
-                            && *focused
-                        {
-                            *focused = false;
-                        } else if let Some(&UiEntry { entry, cache: _ }) =
-                            selected_entry!(entries, ui)
-                        {
-                            let _ = requests.send(Command::Paste(entry.id()));
-                        }
-                    }
-                    _ => {}
+        AppWrapper {
+            state: &mut state,
+            requests: &command_sender,
+            badges,
+            accessible,
+            favorites_only,
+            runtime: &runtime,
+            font_size: font_size(),
+        }
+        .draw(terminal)
+        .map_io_err(|| "Failed to write to terminal.")?;
+
+        let picker = image_picker();
+
+        thread::spawn({
+            let sender = response_sender.clone();
+            move || controller(&command_receiver, |m| sender.send(m.into()))
+        });
+        watch_database({
+            let sender = command_sender.clone();
+            move |command| sender.send(command).map_err(drop)
+        });
+        thread::spawn({
+            let sender = response_sender.clone();
+            move || {
+                while sender.send(Action::Tick).is_ok() {
+                    thread::sleep(StatusLine::TICK);
+                }
+            }
+        });
+        thread::spawn(move || {
+            loop {
+                let r = event::read();
+                let oopsies = r.is_err();
+                if response_sender.send(r.into()).is_err() || oopsies {
+                    break;
                 }
+            }
+        });
 
-                if let &mut Some(SearchState {
-                    ref mut focused,
-                    kind,
-                }) = &mut ui.search_state
-                    && *focused
-                {
-                    let changed = match Input::from(event) {
-                        Input {
-                            key: Key::Left,
-                            ctrl: true,
-                            alt: false,
-                            shift: _,
-                        } => {
-                            ui.query.move_cursor(CursorMove::WordBack);
-                            false
-                        }
-                        Input {
-                            key: Key::Right,
-                            ctrl: true,
-                            alt: false,
-                            shift: _,
-                        } => {
-                            ui.query.move_cursor(CursorMove::WordForward);
-                            false
-                        }
-                        i => ui.query.input(i),
-                    };
-                    if changed {
-                        search(ui, kind);
-                    } else if code == Up || code == Down {
-                        *focused = false;
-                    }
+        Ok(Self {
+            requests: command_sender,
+            responses: response_receiver,
+            picker,
+            badges,
+            accessible,
+            favorites_only,
+            runtime,
+            announcer: announce.map(Announcer::spawn),
+
+            state,
+        })
+    }
+}
+
+fn image_picker() -> Picker {
+    let mut picker = Picker::new(font_size());
+    picker.guess_protocol();
+    picker
+}
+
+/// The size of a terminal cell in pixels, guessed if the terminal won't say.
+fn font_size() -> FontSize {
+    Picker::from_termios().map_or((2, 4), |picker| picker.font_size)
+}
+
+/// Images are shrunk to twice the terminal's size in pixels before they're
+/// shown, which keeps them sharp in any pane without making every frame resize
+/// a huge image.
+fn max_image_size(terminal: Rect, (font_width, font_height): FontSize) -> (u32, u32) {
+    (
+        2 * u32::from(terminal.width) * u32::from(font_width),
+        2 * u32::from(terminal.height) * u32::from(font_height),
+    )
+}
+
+/// Whether the event is Ctrl-Z, which raw mode delivers as a key press
+/// instead of suspending the process.
+fn is_suspend(event: &io::Result<Event>) -> bool {
+    matches!(
+        event,
+        Ok(Event::Key(KeyEvent {
+            code: KeyCode::Char('z'),
+            modifiers: KeyModifiers::CONTROL,
+            kind: KeyEventKind::Press,
+            state: _,
+        }))
+    )
+}
+
+/// Hands the terminal back to the shell and stops the process like Ctrl-Z
+/// normally would, taking the terminal back over once resumed.
+///
+/// The event reader thread is stopped along with everything else, so it
+/// simply carries on reading once the process continues.
+fn suspend<W: io::Write>(
+    terminal: &mut Terminal<CrosstermBackend<W>>,
+    picker: &mut Picker,
+) -> Result<(), CoreError> {
+    terminal
+        .show_cursor()
+        .map_io_err(|| "Failed to show cursor.")?;
+    restore_terminal(terminal.backend_mut())?;
+    kill_process(getpid(), Signal::Stop).map_io_err(|| "Failed to suspend.")?;
+
+    setup_terminal(terminal.backend_mut())?;
+    // We may have been resumed in a different terminal.
+    *picker = image_picker();
+    terminal
+        .clear()
+        .map_io_err(|| "Failed to write to terminal.")
+}
+
+impl App {
+    fn run<W: io::Write>(
+        mut self,
+        mut terminal: Terminal<CrosstermBackend<W>>,
+    ) -> Result<(), CoreError> {
+        let Self {
+            requests,
+            responses,
+            ref mut picker,
+            badges,
+            accessible,
+            favorites_only,
+            ref runtime,
+            ref mut announcer,
+            ref mut state,
+        } = self;
+
+        let mut local_state = Option::default();
+        let mut last_frame = Instant::now();
+        let mut first_undrawn = None;
+        let mut next = responses.recv().ok();
+        while let Some(action) = next {
+            // Ticks only need a frame if a notification faded.
+            if matches!(action, Action::Tick)
+                && !state.ui.status.expire(Instant::now())
+                && first_undrawn.is_none()
+            {
+                next = responses.recv().ok();
+                continue;
+            }
+            let undrawn_since = *first_undrawn.get_or_insert_with(Instant::now);
+            if let Action::User(event) = &action
+                && is_suspend(event)
+            {
+                suspend(&mut terminal, picker)?;
+            } else if match action {
+                Action::Controller(message) => handle_message(
+                    message,
+                    state,
+                    &mut local_state,
+                    picker,
+                    &requests,
+                    favorites_only,
+                    accessible,
+                )?,
+                Action::User(event) => handle_event(
+                    event.map_io_err(|| "Failed to read terminal.")?,
+                    state,
+                    &requests,
+                ),
+                Action::Tick => false,
+            } {
+                break;
+            }
+
+            // Apply whatever is already waiting, along with anything arriving
+            // before the next frame is due, and draw once. A steady stream of
+            // actions still gets a frame every interval.
+            let now = Instant::now();
+            let frame_due = last_frame + MIN_FRAME_INTERVAL;
+            next = if now < frame_due {
+                responses.recv_timeout(frame_due - now).ok()
+            } else if now < undrawn_since + MIN_FRAME_INTERVAL {
+                responses.try_recv().ok()
+            } else {
+                None
+            };
+            if next.is_some() {
+                continue;
+            }
+
+            if let Some(announcer) = announcer {
+                announcer.selection_changed(state);
+            }
+            AppWrapper {
+                state,
+                requests: &requests,
+                badges,
+                accessible,
+                favorites_only,
+                runtime,
+                font_size: picker.font_size,
+            }
+            .draw(&mut terminal)
+            .map_io_err(|| "Failed to write to terminal.")?;
+            last_frame = Instant::now();
+            first_undrawn = None;
+            next = responses.recv().ok();
+        }
+
+        // Quitting right after deleting or (un)favoriting an entry would otherwise
+        // kill the controller before it got around to telling the server.
+        let deadline = Instant::now() + EXIT_TIMEOUT;
+        while state.ui.outstanding_request.is_some()
+            && let Ok(action) =
+                responses.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            if let Action::Controller(message) = action {
+                handle_message(
+                    message,
+                    state,
+                    &mut local_state,
+                    picker,
+                    &requests,
+                    favorites_only,
+                    accessible,
+                )?;
+            }
+        }
+        if !favorites_only {
+            current_session(state).save();
+        }
+        Ok(())
+    }
+}
+
+fn current_session(State { entries, ui }: &State) -> Session {
+    let search = ui.search_state.as_ref().filter(|_| !ui.query.is_empty());
+    Session {
+        selected: selected_entry!(entries, ui).map(|e| e.entry.id()),
+        query: search
+            .map(|_| ui.query.lines().first().unwrap().clone())
+            .unwrap_or_default(),
+        kind: search.map_or(SearchKind::Plain, |s| s.kind),
+        details: ui.details_requested.is_some(),
+    }
+}
+
+/// Starts picking up where the last session left off once the first page is
+/// loaded, searching again first if it had a search open.
+fn restore_session(entries: &mut UiEntries, ui: &mut UiState, requests: &Sender<Command>) {
+    let Some(session) = ui.session.take() else {
+        return;
+    };
+    // Anything the user did while the first page loaded wins.
+    if !ui.query.is_empty() || ui.search_state.is_some() || ui.filter_state.is_some() {
+        return;
+    }
+    if session.query.is_empty() {
+        finish_restoring_session(entries, ui, requests, &session);
+    } else {
+        ui.query = TextArea::new(vec![session.query.clone()]);
+        ui.search_state = Some(SearchState {
+            focused: false,
+            kind: session.kind,
+        });
+        send_search(ui, session.kind, requests);
+        ui.session = Some(session);
+    }
+}
+
+/// Selects the last session's entry if it's still around, keeping the default
+/// selection otherwise, and reopens its details.
+fn finish_restoring_session(
+    entries: &mut UiEntries,
+    ui: &mut UiState,
+    requests: &Sender<Command>,
+    session: &Session,
+) {
+    if let Some(id) = session.selected {
+        if ui.query.is_empty()
+            && ui.split.is_some()
+            && let Some(e) = entries.loaded_entries.iter().find(|e| e.entry.id() == id)
+        {
+            ui.split = Some(e.entry.ring());
+        }
+        if let Some(index) = active_entries!(entries, ui)
+            .iter()
+            .position(|e| e.entry.id() == id)
+        {
+            active_list_state!(entries, ui).select(Some(index));
+        }
+    }
+    if session.details {
+        maybe_get_details(entries, ui, requests);
+    }
+}
+
+/// The least time between frames, so bursts of search results or held-down
+/// keys are drawn once instead of once each.
+const MIN_FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// How long quitting waits for the controller to finish outstanding changes.
+const EXIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+fn handle_message(
+    message: Message,
+    State { entries, ui }: &mut State,
+    pending_favorite_change: &mut Option<u64>,
+    picker: &mut Picker,
+    requests: &Sender<Command>,
+    favorites_only: bool,
+    accessible: bool,
+) -> Result<bool, CoreError> {
+    let UiEntries {
+        loaded_entries: _,
+        search_results,
+        filtered_entries: _,
+        groups: _,
+        reorder: _,
+        on_clipboard: _,
+        marked: _,
+        last_mark: _,
+        generation,
+        rows: _,
+        favorite_rows: _,
+        loaded_state: _,
+        favorites_state: _,
+        search_state,
+    } = entries;
+    let UiState {
+        details_requested,
+        detailed_entry,
+        pending_search,
+        queued_searches,
+        last_error,
+        error_report,
+        error_report_scroll,
+        outstanding_request,
+        ..
+    } = ui;
+
+    if let Some(e) = last_error.take()
+        && !is_query_error(&e)
+    {
+        *error_report = Some(format_error(e));
+    }
+    match message {
+        Message::FatalDbOpen(e) => return Err(e)?,
+        Message::Connected => ui.startup = Startup::Loading,
+        Message::Error(CommandError::Sdk(ClientError::FavoritesFull)) => {
+            ui.status.show(strings::get(Str::FavoritesFull));
+        }
+        Message::Error(CommandError::NothingToUndo) => {
+            ui.status.show(strings::get(Str::NothingToUndo));
+        }
+        Message::Error(CommandError::NothingToMatch) => {
+            ui.status.show(strings::get(Str::NothingToMatch));
+            *queued_searches = queued_searches.saturating_sub(1);
+        }
+        Message::Error(CommandError::Clipboard(CopyError::NoDisplayServer)) => {
+            ui.status.show(strings::get(Str::NoDisplayServer));
+        }
+        Message::Error(CommandError::Clipboard(CopyError::NoCopier { .. })) => {
+            ui.status.show(strings::get(Str::NoCopier));
+        }
+        // Replacing the report being read would lose it, e.g. if copying it failed.
+        Message::Error(e) if error_report_scroll.is_some() => {
+            if let Some(report) = error_report {
+                *report = strings::fill(Str::ErrorReportFollowUp, &[report, &format_error(e)]);
+            }
+            *queued_searches = queued_searches.saturating_sub(1);
+        }
+        Message::Error(e) => {
+            if !is_query_error(&e) {
+                ui.status.show(strings::fill(Str::ErrorTitle, &[&e]));
+            }
+            *last_error = Some(e);
+            *queued_searches = queued_searches.saturating_sub(1);
+        }
+        Message::LoadedFirstPage {
+            entries: new_entries,
+            default_focused_id,
+            on_clipboard,
+        } => {
+            let previous = panes(ui.split)
+                .iter()
+                .map(|&pane| {
+                    let index = entries.pane_state(pane).selected()?;
+                    Some((index, entries.loaded().pane(pane).get(index)?.entry.id()))
+                })
+                .collect::<Vec<_>>();
+            // The preview's indices don't survive the entries changing.
+            entries.reorder = None;
+            entries.on_clipboard = on_clipboard;
+            // Entries may have been added since, so the oldest might not be loaded.
+            ui.loaded_all = false;
+            ui.startup = Startup::Loaded;
+            entries.loaded_entries = if favorites_only {
+                only_favorites(new_entries)
+            } else {
+                new_entries
+            };
+            // Marks follow ids, so only entries that went away lose theirs.
+            let known = entries
+                .loaded_entries
+                .iter()
+                .chain(&*entries.search_results)
+                .map(|e| e.entry.id())
+                .collect::<HashSet<_>>();
+            entries.marked.retain(|id| known.contains(id));
+            entries.last_mark.take_if(|id| !known.contains(id));
+            refresh_visible(entries, ui);
+            for (&pane, previous) in panes(ui.split).iter().zip(previous) {
+                let Some(previous) = previous else {
+                    continue;
+                };
+                let selected = find_selection(
+                    entries.loaded().pane(pane).iter().map(|e| e.entry.id()),
+                    previous,
+                );
+                entries
+                    .pane_state(pane)
+                    .select(selected.map_or_else(|neighbor| neighbor, Some));
+                if selected.is_err() && ui.query.is_empty() && pane == ui.split {
+                    close_removed_entry(ui);
+                }
+            }
+
+            for &pane in panes(ui.split) {
+                if entries.pane_state(pane).selected().is_some() {
+                    continue;
+                }
+                let visible = entries.loaded().pane(pane);
+                let selected = if favorites_only || pane == Some(RingKind::Favorites) {
+                    (!visible.is_empty()).then_some(0)
                 } else {
-                    match code {
-                        Char('q') => return true,
-                        Char('c') if modifiers == KeyModifiers::CONTROL => return true,
-                        Char(c @ '0'..='9') => {
-                            if let Some(UiEntry { entry, cache: _ }) = active_entries!(entries, ui)
-                                .get(usize::try_from(u32::from(c) - u32::from('0')).unwrap())
-                            {
-                                let _ = requests.send(Command::Paste(entry.id()));
-                            }
-                        }
-                        Char('h') | Left => unselect(ui),
-                        Char('j') | Down => {
-                            let state = active_list_state!(entries, ui);
-                            let len = active_entries!(entries, ui).len();
-                            let next = state
-                                .selected()
-                                .map_or(0, |i| if i + 1 == len { 0 } else { i + 1 });
-                            state.select(Some(next.min(len)));
-                        }
-                        Char('J') => {
-                            ui.detail_scroll = ui.detail_scroll.saturating_add(1);
-                        }
-                        Char('k') | Up => {
-                            let state = active_list_state!(entries, ui);
-                            let len = active_entries!(entries, ui).len();
-                            let previous = state.selected().map_or(usize::MAX, |i| {
-                                if i == 0 { len.wrapping_sub(1) } else { i - 1 }
-                            });
-                            if let Some(SearchState { focused, .. }) = &mut ui.search_state
-                                && Some(previous) > state.selected()
-                            {
-                                *focused = true;
-                            } else {
-                                state.select(Some(previous.min(len)));
-                            }
-                        }
-                        Char('K') => {
-                            ui.detail_scroll = ui.detail_scroll.saturating_sub(1);
-                        }
-                        Char('l') | Right => maybe_get_details(entries, ui, requests),
-                        Char(' ') => {
-                            if ui.details_requested.is_some() {
-                                unselect(ui);
-                            } else {
-                                maybe_get_details(entries, ui, requests);
-                            }
-                        }
-                        Char(c @ ('/' | 's' | 'x' | 'm')) => {
-                            let kind = match c {
-                                'x' => SearchKind::Regex,
-                                'm' => SearchKind::Mime,
-                                _ => SearchKind::Plain,
-                            };
-                            ui.search_state = Some(SearchState {
-                                focused: true,
-                                kind,
-                            });
-                            search(ui, kind);
-                        }
-                        Char('f') => {
-                            if let Some(&UiEntry { entry, cache: _ }) = selected_entry!(entries, ui)
-                                && ui.outstanding_request != Some(entry.id())
-                            {
-                                ui.outstanding_request = Some(entry.id());
-                                match entry.ring() {
-                                    RingKind::Favorites => {
-                                        let _ = requests.send(Command::Unfavorite(entry.id()));
-                                    }
-                                    RingKind::Main => {
-                                        let _ = requests.send(Command::Favorite(entry.id()));
-                                    }
-                                }
-                                refresh(ui);
-                            }
-                        }
-                        Char('d') => {
-                            if let Some(&UiEntry { entry, cache: _ }) = selected_entry!(entries, ui)
-                                && ui.outstanding_request != Some(entry.id())
-                            {
-                                ui.outstanding_request = Some(entry.id());
-                                let _ = requests.send(Command::Delete(entry.id()));
-                                refresh(ui);
-                            }
-                        }
-                        Char('?') => {
-                            ui.show_help ^= true;
-                        }
-                        Char('r') => {
-                            if modifiers == KeyModifiers::CONTROL {
-                                *state = State::default();
-                            }
-                            refresh(&mut state.ui);
-                            return false;
-                        }
-                        _ => {}
+                    default_focused_id.and_then(|selected_id| {
+                        visible.iter().position(|e| e.entry.id() == selected_id)
+                    })
+                };
+                entries.pane_state(pane).select(selected);
+            }
+            restore_session(entries, ui, requests);
+            if let Some(id) = pending_favorite_change.take() {
+                // Follow the entry into the other pane if it changed rings.
+                if ui.split.is_some()
+                    && let Some(e) = entries.loaded_entries.iter().find(|e| e.entry.id() == id)
+                {
+                    ui.split = Some(e.entry.ring());
+                }
+                if let Some(index) = active_entries!(entries, ui)
+                    .iter()
+                    .position(|e| e.entry.id() == id)
+                {
+                    active_list_state!(entries, ui).select(Some(index));
+                    if ui.details_requested.is_some() {
+                        ui.details_requested = Some(id);
                     }
                 }
             }
         }
-        Event::FocusGained => {
-            refresh(ui);
+        Message::LoadedPage {
+            after_id,
+            entries: page,
+        } => {
+            if ui.page_requested == Some(after_id) {
+                ui.page_requested = None;
+            }
+            if page.is_empty() {
+                ui.loaded_all = true;
+            } else if !favorites_only {
+                append_page(entries, ui, page);
+            }
+        }
+        Message::LoadedThrough {
+            after_id,
+            id,
+            entries: page,
+        } => {
+            if ui.page_requested == Some(after_id) {
+                ui.page_requested = None;
+            }
+            if let Some(page) = page
+                && page.iter().any(|e| e.entry.id() == id)
+            {
+                append_page(entries, ui, page);
+                jump_to_id(entries, ui, requests, id);
+            } else {
+                ui.status
+                    .show(strings::fill(Str::UnknownEntry, &[&format_id(id)]));
+            }
+        }
+        Message::EntryDetails { id, result } => {
+            if *details_requested == Some(id) {
+                if let Err(CoreError::IdNotFound(_)) = result {
+                    remove_entry(entries, ui, id);
+                    close_removed_entry(ui);
+                } else {
+                    *detailed_entry = Some(result);
+                    ui.detail_rows = None;
+                    ui.scroll_to_match = true;
+                }
+            }
+        }
+        Message::SearchResults {
+            generation: search,
+            batch,
+            done,
+        } => {
+            if done {
+                *queued_searches = queued_searches.saturating_sub(1);
+            }
+            if let Some(pending) = pending_search
+                .as_mut()
+                .filter(|p| p.generation == search && !p.token.is_cancelled())
+            {
+                let batch = if favorites_only {
+                    only_favorites(batch)
+                } else {
+                    batch
+                };
+                // Later batches are appended so the selected row stays put.
+                *search_results = if pending.shown {
+                    let mut results = mem::take(search_results).into_vec();
+                    results.extend(batch);
+                    results.into()
+                } else {
+                    batch
+                };
+                pending.shown = true;
+                *generation += 1;
+                if search_state.selected().is_none() {
+                    search_state.select_first();
+                }
+            }
+            if done
+                && pending_search
+                    .take_if(|p| p.generation == search)
+                    .is_some_and(|p| !p.token.is_cancelled())
+                && mem::take(&mut ui.announce_search)
+            {
+                ui.status
+                    .show(strings::fill(Str::SearchMatches, &[&search_results.len()]));
+            }
+            // The restored entry may only turn up in a later batch.
+            if let Some(session) = ui.session.take_if(|session| {
+                done || session
+                    .selected
+                    .is_some_and(|id| entries.search_results.iter().any(|e| e.entry.id() == id))
+            }) {
+                finish_restoring_session(entries, ui, requests, &session);
+            }
+        }
+        Message::FavoriteChange(id) => {
+            *pending_favorite_change = Some(id);
+            outstanding_request.take_if(|&mut req_id| req_id == id);
+            // The id is the entry's new one, in the ring it was moved to.
+            ui.status.show(strings::get(match decompose_id(id) {
+                Ok((RingKind::Favorites, _)) => Str::Favorited,
+                _ => Str::Unfavorited,
+            }));
+        }
+        Message::Deleted(id) => {
+            outstanding_request.take_if(|&mut req_id| req_id == id);
+            ui.status.show(
+                match ui.keymap.key_name(AppAction::UndoDelete, accessible) {
+                    Some(key) => strings::fill(Str::DeletedUndoable, &[&format_id(id), &key]),
+                    None => strings::fill(Str::Deleted, &[&format_id(id)]),
+                },
+            );
+            remove_entry(entries, ui, id);
+        }
+        Message::DeletedMany(ids) => {
+            outstanding_request.take_if(|req_id| ids.contains(req_id));
+            ui.status
+                .show(strings::fill(Str::DeletedMany, &[&ids.len()]));
+            for &id in &ids {
+                remove_entry(entries, ui, id);
+            }
+        }
+        Message::Deduplicated { groups, deleted } => {
+            for &id in &deleted {
+                remove_entry(entries, ui, id);
+            }
+            ui.status
+                .show(strings::fill(Str::Deduplicated, &[&deleted.len(), &groups]));
+        }
+        Message::Restored { id: _, new_id } => {
+            ui.status.show(strings::fill(Str::Restored, &[&new_id]));
+        }
+        Message::PendingDashboard(token) => {
+            if let Some(dashboard) = &mut ui.dashboard {
+                dashboard.token = Some(token);
+            } else {
+                token.cancel();
+            }
+        }
+        Message::Dashboard(stats) => {
+            if let Some(dashboard) = &mut ui.dashboard {
+                dashboard.stats = Some(stats);
+            }
+        }
+        Message::Reordered { id, moved } => {
+            if moved {
+                *pending_favorite_change = Some(id);
+            } else {
+                ui.status.show(strings::get(Str::ReorderFailed));
+            }
+            let _ = requests.send(Command::LoadFirstPage);
+        }
+        Message::PendingImage { id, token } => {
+            if let Some(ImageState::Requested {
+                id: requested_id,
+                token: pending_token,
+                ..
+            }) = &mut ui.detail_image_state
+                && *requested_id == id
+            {
+                *pending_token = Some(token);
+            } else {
+                token.cancel();
+            }
+        }
+        Message::ImageProgress { id, pct } => {
+            if let Some(ImageState::Requested {
+                id: requested_id,
+                progress,
+                ..
+            }) = &mut ui.detail_image_state
+                && *requested_id == id
+            {
+                *progress = Some(pct);
+            }
+        }
+        Message::LoadedImage {
+            id,
+            image,
+            metadata,
+        } => {
+            if let Some(ImageState::Requested {
+                id: requested_id, ..
+            }) = ui.detail_image_state
+                && requested_id == id
+            {
+                ui.detail_image_state = Some(ImageState::Loaded(
+                    picker.new_resize_protocol(image),
+                    metadata,
+                ));
+            }
+        }
+        Message::LoadedThumbnail { id, image } => {
+            let thumbnail = image
+                .and_then(|image| {
+                    picker
+                        .new_protocol(
+                            image,
+                            Rect::new(0, 0, THUMBNAIL_WIDTH, 1),
+                            Resize::Fit(None),
+                        )
+                        .ok()
+                })
+                .map_or(Thumbnail::Failed, Thumbnail::Loaded);
+            ui.thumbnails.loaded(id, thumbnail);
+        }
+        Message::ImageFailed { id, error } => {
+            if let Some(ImageState::Requested {
+                id: requested_id, ..
+            }) = ui.detail_image_state
+                && requested_id == id
+            {
+                ui.detail_image_state = Some(ImageState::Failed(error));
+            }
+        }
+        Message::FindingSimilar { id: _, query } => {
+            let mut query = TextArea::new(vec![query.into()]);
+            query.move_cursor(CursorMove::End);
+            ui.query = query;
+            ui.search_state = Some(SearchState {
+                focused: true,
+                kind: SearchKind::Plain,
+            });
+        }
+        Message::PendingSearch { generation, token } => {
+            if *queued_searches > 1 {
+                token.cancel();
+            }
+            *pending_search = Some(PendingSearch {
+                generation,
+                token,
+                shown: false,
+            });
+        }
+        Message::DbRefreshed {
+            changed,
+            new_entries,
+            removed_entries,
+        } => {
+            if changed {
+                ui.scroll_positions.forget_files();
+            }
+            match (new_entries, removed_entries) {
+                (0, _) => {}
+                (new, 0) => ui.status.show(strings::fill(Str::NewEntries, &[&new])),
+                (new, removed) => ui
+                    .status
+                    .show(strings::fill(Str::NewEntriesReplacing, &[&new, &removed])),
+            }
+            if changed && let &Some(SearchState { focused: _, kind }) = &ui.search_state {
+                send_search(ui, kind, requests);
+                // Entries coming and going in the background aren't news.
+                ui.announce_search = false;
+            }
+        }
+        Message::Pasted { .. } => return Ok(true),
+        Message::Copied => ui.status.show(strings::get(Str::ErrorReportCopied)),
+        Message::Yanked(_) => ui.status.show(strings::get(Str::Yanked)),
+        Message::ServerInfo(info) => ui.server_info = Some(info),
+        Message::Stats(stats) => {
+            if let Some(shown) = &mut ui.server_stats {
+                *shown = Some(stats);
+            }
+        }
+        Message::Replaced(id) => {
+            outstanding_request.take_if(|&mut req_id| req_id == id);
+            ui.status.show(strings::get(Str::Replaced));
+            *pending_favorite_change = Some(id);
+            let _ = requests.send(Command::LoadFirstPage);
+            if let &Some(SearchState { focused: _, kind }) = &ui.search_state {
+                send_search(ui, kind, requests);
+            }
+        }
+        Message::EntryReplaced { id, new_id } => {
+            outstanding_request.take_if(|&mut req_id| req_id == id);
+            ui.status.show(strings::get(Str::Replaced));
+            *pending_favorite_change = Some(new_id);
+            let _ = requests.send(Command::LoadFirstPage);
+            if let &Some(SearchState { focused: _, kind }) = &ui.search_state {
+                send_search(ui, kind, requests);
+            }
+        }
+        Message::Labeled(id) | Message::Tagged(id) => {
+            outstanding_request.take_if(|&mut req_id| req_id == id);
+            *pending_favorite_change = Some(id);
+            let _ = requests.send(Command::LoadFirstPage);
+            if let &Some(SearchState { focused: _, kind }) = &ui.search_state {
+                send_search(ui, kind, requests);
+            }
+        }
+    }
+    if ui.details_requested.is_some() {
+        maybe_get_details(entries, ui, requests);
+    }
+    Ok(false)
+}
+
+fn only_favorites(entries: Box<[UiEntry]>) -> Box<[UiEntry]> {
+    let mut entries = entries.into_vec();
+    entries.retain(|e| e.entry.ring() == RingKind::Favorites);
+    entries.into()
+}
+
+fn filter_query<'a>(filter: &'a TextArea) -> &'a str {
+    filter.lines().first().map_or("", String::as_str)
+}
+
+/// The oldest loaded main ring entry if moving past the bottom of the list
+/// should load the entries before it instead of wrapping around.
+fn next_page_start(entries: &UiEntries, ui: &UiState) -> Option<u64> {
+    if !ui.query.is_empty() || ui.split == Some(RingKind::Favorites) || ui.loaded_all {
+        return None;
+    }
+    entries
+        .loaded_entries
+        .iter()
+        .rfind(|e| e.entry.ring() == RingKind::Main)
+        .map(|e| e.entry.id())
+}
+
+/// Adds older entries to the end of the loaded ones, keeping each pane's
+/// selection.
+fn append_page(entries: &mut UiEntries, ui: &UiState, page: Box<[UiEntry]>) {
+    let previous = panes(ui.split)
+        .iter()
+        .map(|&pane| {
+            let index = entries.pane_state(pane).selected()?;
+            Some(entries.loaded().pane(pane).get(index)?.entry.id())
+        })
+        .collect::<Vec<_>>();
+    let mut loaded = mem::take(&mut entries.loaded_entries).into_vec();
+    // A reload may have picked up part of the page already.
+    let known = loaded.iter().map(|e| e.entry.id()).collect::<HashSet<_>>();
+    loaded.extend(
+        page.into_vec()
+            .into_iter()
+            .filter(|e| !known.contains(&e.entry.id())),
+    );
+    entries.loaded_entries = loaded.into();
+    refresh_visible(entries, ui);
+    for (&pane, previous) in panes(ui.split).iter().zip(previous) {
+        let selected = previous.and_then(|id| {
+            entries
+                .loaded()
+                .pane(pane)
+                .iter()
+                .position(|e| e.entry.id() == id)
+        });
+        entries.pane_state(pane).select(selected);
+    }
+}
+
+/// Recomputes which loaded entries are shown after they changed.
+fn refresh_visible(entries: &mut UiEntries, ui: &UiState) {
+    entries.regroup();
+    if ui.filter_state.is_some() {
+        apply_filter(entries, ui.split, filter_query(&ui.filter), None);
+    } else {
+        entries.filtered_entries = entries.collapsed_rows();
+        entries.generation += 1;
+    }
+}
+
+fn apply_filter(
+    entries: &mut UiEntries,
+    split: Option<RingKind>,
+    query: &str,
+    selected_id: Option<u64>,
+) {
+    entries.generation += 1;
+    entries.filtered_entries = if query.is_empty() {
+        entries.collapsed_rows()
+    } else {
+        let ignore_case = query
+            .chars()
+            .all(|c| !char::is_alphabetic(c) || char::is_lowercase(c));
+        Some(
+            entries
+                .loaded_entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| {
+                    let label = e.label.as_deref();
+                    let text = match &e.cache {
+                        UiEntryCache::Text { one_liner, .. } => Some(&**one_liner),
+                        UiEntryCache::Image
+                        | UiEntryCache::Binary { .. }
+                        | UiEntryCache::Sensitive
+                        | UiEntryCache::Error(_) => None,
+                    };
+                    label.into_iter().chain(text).any(|s| {
+                        if ignore_case {
+                            s.to_lowercase().contains(query)
+                        } else {
+                            s.contains(query)
+                        }
+                    })
+                })
+                .map(|(i, _)| i)
+                .collect(),
+        )
+    };
+
+    reselect(entries, split, selected_id);
+}
+
+/// The panes loaded entries are shown in, each with its own selection.
+const fn panes(split: Option<RingKind>) -> &'static [Option<RingKind>] {
+    if split.is_some() {
+        &[Some(RingKind::Main), Some(RingKind::Favorites)]
+    } else {
+        &[None]
+    }
+}
+
+const fn other_pane(pane: RingKind) -> RingKind {
+    match pane {
+        RingKind::Main => RingKind::Favorites,
+        RingKind::Favorites => RingKind::Main,
+    }
+}
+
+/// Selects the entry in whichever pane shows it, falling back to the first
+/// entry of every other pane.
+fn reselect(entries: &mut UiEntries, split: Option<RingKind>, selected_id: Option<u64>) {
+    for &pane in panes(split) {
+        let visible = entries.loaded().pane(pane);
+        let selected = selected_id
+            .and_then(|id| visible.iter().position(|e| e.entry.id() == id))
+            .or_else(|| (!visible.is_empty()).then_some(0));
+        entries.pane_state(pane).select(selected);
+    }
+}
+
+/// Shows the main ring and favorites side by side, or goes back to a single
+/// list, keeping the selected entry selected.
+fn toggle_split(entries: &mut UiEntries, ui: &mut UiState) {
+    let selected = selected_entry!(entries, ui).map(|e| e.entry);
+    if ui.split.is_some() {
+        ui.split = None;
+    } else {
+        // Search results aren't split up, so they'd hide the panes.
+        if ui.search_state.is_some() {
+            ui.search_state = None;
+            ui.query = TextArea::default();
+        }
+        ui.split = Some(selected.map_or(RingKind::Main, |e| e.ring()));
+    }
+    reselect(entries, ui.split, selected.map(|e| e.id()));
+}
+
+/// Finds the previously selected entry in a changed list, or its nearest
+/// surviving neighbor if the entry is gone.
+fn find_selection(
+    mut ids: impl ExactSizeIterator<Item = u64>,
+    (index, id): (usize, u64),
+) -> Result<usize, Option<usize>> {
+    let len = ids.len();
+    ids.position(|i| i == id)
+        .ok_or_else(|| len.checked_sub(1).map(|last| index.min(last)))
+}
+
+fn remove_entry(entries: &mut UiEntries, ui: &UiState, id: u64) {
+    let previous = if ui.query.is_empty() {
+        &entries.loaded_state
+    } else {
+        &entries.search_state
+    }
+    .selected()
+    .and_then(|index| Some((index, active_entries!(entries, ui).get(index)?.entry.id())));
+
+    for list in [&mut entries.loaded_entries, &mut entries.search_results] {
+        let mut retained = mem::take(list).into_vec();
+        retained.retain(|e| e.entry.id() != id);
+        *list = retained.into();
+    }
+    refresh_visible(entries, ui);
+
+    if let Some(previous) = previous {
+        let selected = find_selection(
+            active_entries!(entries, ui).iter().map(|e| e.entry.id()),
+            previous,
+        );
+        active_list_state!(entries, ui).select(selected.map_or_else(|neighbor| neighbor, Some));
+    }
+}
+
+fn close_removed_entry(ui: &mut UiState) {
+    ui.details_requested = None;
+    ui.detailed_entry = None;
+    clear_detail_image(ui);
+    // Keep more specific news, e.g. why a move failed.
+    if ui.status.current().is_none() {
+        ui.status.show(strings::get(Str::EntryRemoved));
+    }
+}
+
+/// Drops the detail pane's image, abandoning its load if it's still going.
+fn clear_detail_image(ui: &mut UiState) {
+    if let Some(ImageState::Requested {
+        token: Some(token), ..
+    }) = ui.detail_image_state.take()
+    {
+        token.cancel();
+    }
+}
+
+fn maybe_get_details(entries: &UiEntries, ui: &mut UiState, requests: &Sender<Command>) {
+    if let Some(&UiEntry {
+        entry,
+        ref cache,
+        fingerprint,
+        ..
+    }) = selected_entry!(entries, ui)
+        && (ui.details_requested != Some(entry.id()) || ui.details_fingerprint != fingerprint)
+    {
+        remember_detail_scroll(entries, ui);
+        if ui.details_requested != Some(entry.id()) {
+            end_find(ui);
+            ui.revealed = None;
+        }
+        ui.details_requested = Some(entry.id());
+        ui.details_fingerprint = fingerprint;
+        ui.detailed_entry = None;
+        ui.detail_scroll = ui
+            .scroll_positions
+            .take(entry.id(), entry.kind())
+            .unwrap_or(0);
+        ui.detail_column = 0;
+        clear_detail_image(ui);
+        let _ = requests.send(Command::GetDetails {
+            id: entry.id(),
+            with_text: match cache {
+                UiEntryCache::Text { .. } | UiEntryCache::Binary { .. } => true,
+                UiEntryCache::Sensitive => ui.revealed == Some(entry.id()),
+                UiEntryCache::Image | UiEntryCache::Error(_) => false,
+            },
+        });
+    }
+}
+
+fn remember_detail_scroll(entries: &UiEntries, ui: &mut UiState) {
+    let Some(id) = ui.details_requested else {
+        return;
+    };
+    if let Some(UiEntry { entry, .. }) = entries
+        .loaded_entries
+        .iter()
+        .chain(&*entries.search_results)
+        .find(|e| e.entry.id() == id)
+    {
+        ui.scroll_positions
+            .remember(id, entry.kind(), ui.detail_scroll);
+    }
+}
+
+/// Highlights the details query's matches and scrolls to the first one.
+fn find_in_details(ui: &mut UiState) {
+    ui.current_match = Some(0);
+    ui.detail_rows = None;
+    ui.scroll_to_match = true;
+}
+
+/// Goes back to highlighting the search's matches in the details, if any.
+fn end_find(ui: &mut UiState) {
+    ui.finding = false;
+    ui.detail_query = TextArea::default();
+    ui.current_match = None;
+    ui.detail_matches = Box::default();
+    ui.detail_rows = None;
+}
+
+fn send_search(ui: &mut UiState, kind: SearchKind, requests: &Sender<Command>) {
+    if let Some(PendingSearch { token, .. }) = &ui.pending_search {
+        token.cancel();
+    }
+    let _ = requests.send(Command::Search {
+        query: ui.query.lines().first().unwrap().to_string().into(),
+        kind,
+        case_sensitive: ui.match_case,
+        ring: ui.search_scope,
+        mime_filter: ui.search_mime.map(MimeScope::filter),
+        favorites_first: ui.favorites_first,
+    });
+    ui.queued_searches += 1;
+    ui.announce_search = true;
+    ui.detail_rows = None;
+    ui.scroll_to_match = true;
+}
+
+/// Searches for entries like the one whose details are open. The search box
+/// is filled in once the query is known.
+fn find_similar(ui: &mut UiState, requests: &Sender<Command>) {
+    let Some(id) = ui.details_requested else {
+        return;
+    };
+    if let Some(PendingSearch { token, .. }) = &ui.pending_search {
+        token.cancel();
+    }
+    let _ = requests.send(Command::FindSimilar(id));
+    ui.queued_searches += 1;
+    ui.announce_search = true;
+    ui.detail_rows = None;
+    ui.scroll_to_match = true;
+}
+
+/// Cycles through searching everything, the main ring, and favorites.
+const fn next_search_scope(scope: Option<RingKind>) -> Option<RingKind> {
+    match scope {
+        None => Some(RingKind::Main),
+        Some(RingKind::Main) => Some(RingKind::Favorites),
+        Some(RingKind::Favorites) => None,
+    }
+}
+
+#[derive(Copy, Clone)]
+enum MimeScope {
+    Text,
+    Images,
+}
+
+impl MimeScope {
+    fn filter(self) -> MimeType {
+        MimeType::from(match self {
+            Self::Text => "text/",
+            Self::Images => "image/",
+        })
+        .unwrap()
+    }
+}
+
+/// Cycles through searching only text, only images, and everything.
+const fn next_mime_scope(scope: Option<MimeScope>) -> Option<MimeScope> {
+    match scope {
+        None => Some(MimeScope::Text),
+        Some(MimeScope::Text) => Some(MimeScope::Images),
+        Some(MimeScope::Images) => None,
+    }
+}
+
+/// Changes one of the search options, leaving running the search again to the
+/// caller.
+const fn toggle_search_option(ui: &mut UiState, option: AppAction) {
+    match option {
+        AppAction::SearchScope => ui.search_scope = next_search_scope(ui.search_scope),
+        AppAction::MimeScope => ui.search_mime = next_mime_scope(ui.search_mime),
+        AppAction::MatchCase => ui.match_case ^= true,
+        AppAction::FavoritesFirst => ui.favorites_first ^= true,
+        _ => {}
+    }
+}
+
+/// Pastes an entry, first asking for confirmation if it's large enough to
+/// make a mess of wherever it ends up.
+fn paste(ui: &mut UiState, requests: &Sender<Command>, entry: &UiEntry) {
+    let &UiEntry {
+        entry,
+        ref cache,
+        len,
+        ..
+    } = entry;
+    if ui.paste_warning.is_some_and(|max| len > max) {
+        ui.pending_paste = Some(PendingPaste {
+            id: entry.id(),
+            len,
+            mime_type: match cache {
+                UiEntryCache::Text { .. } | UiEntryCache::Sensitive | UiEntryCache::Error(_) => {
+                    "text/*".into()
+                }
+                UiEntryCache::Image => "image/*".into(),
+                UiEntryCache::Binary { mime_type } => mime_type.clone(),
+            },
+        });
+    } else {
+        let _ = requests.send(Command::Paste(entry.id()));
+    }
+}
+
+fn send_change(
+    ui: &mut UiState,
+    requests: &Sender<Command>,
+    change: Change,
+    ring: RingKind,
+    ids: &[u64],
+) {
+    for &id in ids {
+        ui.outstanding_request = Some(id);
+        let _ = requests.send(match (change, ring) {
+            (Change::Favorite, RingKind::Favorites) => Command::Unfavorite(id),
+            (Change::Favorite, RingKind::Main) => Command::Favorite(id),
+            (Change::Delete, _) => Command::Delete(id),
+        });
+    }
+}
+
+/// Applies a change to every marked entry and clears the marks.
+fn send_marked_change(
+    entries: &mut UiEntries,
+    ui: &mut UiState,
+    requests: &Sender<Command>,
+    change: Change,
+) {
+    let mut marked = mem::take(&mut entries.marked);
+    entries.last_mark = None;
+    entries.generation += 1;
+    // Marks can be made in both the loaded entries and search results.
+    let marked = entries
+        .loaded_entries
+        .iter()
+        .chain(&*entries.search_results)
+        .map(|e| e.entry)
+        .filter(|e| marked.remove(&e.id()))
+        .collect::<Vec<_>>();
+    match change {
+        Change::Favorite => {
+            for entry in marked {
+                send_change(ui, requests, change, entry.ring(), &[entry.id()]);
+            }
+        }
+        Change::Delete => {
+            ui.outstanding_request = marked.last().map(Entry::id);
+            let _ = requests.send(Command::DeleteMany(marked.iter().map(Entry::id).collect()));
+        }
+    }
+}
+
+/// Marks or unmarks the selected entry.
+fn toggle_mark(entries: &mut UiEntries, ui: &UiState) {
+    let Some(id) = selected_entry!(entries, ui).map(|e| e.entry.id()) else {
+        return;
+    };
+    if !entries.marked.remove(&id) {
+        entries.marked.insert(id);
+        entries.last_mark = Some(id);
+    }
+    entries.generation += 1;
+}
+
+/// Marks every entry between the last mark and the selected one, or just the
+/// selected one if the last mark isn't shown.
+fn mark_range(entries: &mut UiEntries, ui: &UiState) {
+    let active = active_entries!(entries, ui);
+    let Some(to) = active_list_state!(entries, ui).selected() else {
+        return;
+    };
+    let Some(to_id) = active.get(to).map(|e| e.entry.id()) else {
+        return;
+    };
+    let from = entries
+        .last_mark
+        .and_then(|id| active.iter().position(|e| e.entry.id() == id))
+        .unwrap_or(to);
+    let range = active
+        .iter()
+        .skip(min(from, to))
+        .take(from.abs_diff(to) + 1)
+        .map(|e| e.entry.id())
+        .collect::<Vec<_>>();
+    entries.marked.extend(range);
+    entries.last_mark = Some(to_id);
+    entries.generation += 1;
+}
+
+fn close_dashboard(ui: &mut UiState) {
+    if let Some(DashboardView {
+        token: Some(token), ..
+    }) = ui.dashboard.take()
+    {
+        token.cancel();
+    }
+}
+
+/// Selects a loaded entry, switching to the pane it's in.
+fn jump_to_entry(entries: &mut UiEntries, ui: &mut UiState, id: u64) {
+    if ui.query.is_empty()
+        && let Some(entry) = entries.loaded_entries.iter().find(|e| e.entry.id() == id)
+        && ui.split.is_some()
+    {
+        ui.split = Some(entry.entry.ring());
+    }
+    if let Some(index) = active_entries!(entries, ui)
+        .iter()
+        .position(|e| e.entry.id() == id)
+    {
+        active_list_state!(entries, ui).select(Some(index));
+    } else {
+        ui.status.show(strings::get(Str::DashboardEntryNotShown));
+    }
+}
+
+/// Selects the entry with a typed in id, paging in main ring entries until
+/// it's loaded.
+fn jump_to_id(entries: &mut UiEntries, ui: &mut UiState, requests: &Sender<Command>, id: u64) {
+    if ui.search_state.is_some() {
+        ui.search_state = None;
+        ui.query = TextArea::default();
+    }
+    if ui.filter_state.is_some() {
+        ui.filter_state = None;
+        ui.filter = TextArea::default();
+        apply_filter(entries, ui.split, "", None);
+    }
+    if entries.loaded_entries.iter().any(|e| e.entry.id() == id) {
+        if let Some(Groups { runs, .. }) = &entries.groups
+            && let Some((&head, _)) = runs
+                .iter()
+                .find(|&(&head, run)| head != id && run.contains(&id))
+        {
+            expand_run(entries, ui, head);
+        }
+        jump_to_entry(entries, ui, id);
+    } else if !ui.loaded_all
+        && let Some(after) = entries
+            .loaded_entries
+            .iter()
+            .rfind(|e| e.entry.ring() == RingKind::Main)
+    {
+        let after_id = after.entry.id();
+        ui.page_requested = Some(after_id);
+        let _ = requests.send(Command::LoadThrough { after_id, id });
+    } else {
+        ui.status
+            .show(strings::fill(Str::UnknownEntry, &[&format_id(id)]));
+    }
+}
+
+/// Moves the selection by as many entries as fit on screen, loading the next
+/// page when moving down from the bottom.
+fn move_by_page(entries: &mut UiEntries, ui: &mut UiState, requests: &Sender<Command>, down: bool) {
+    let len = active_entries!(entries, ui).len();
+    let selected = active_list_state!(entries, ui).selected();
+    if len == 0 {
+        return;
+    }
+    let step = usize::from(ui.page_height.max(1));
+    let next = if down {
+        if selected == Some(len - 1)
+            && let Some(after_id) = next_page_start(entries, ui)
+        {
+            load_next_page(ui, requests, after_id);
+            return;
+        }
+        selected.map_or(0, |i| min(i + step, len - 1))
+    } else {
+        selected.map_or(0, |i| i.saturating_sub(step))
+    };
+    active_list_state!(entries, ui).select(Some(next));
+}
+
+fn load_next_page(ui: &mut UiState, requests: &Sender<Command>, after_id: u64) {
+    if ui.page_requested.is_none() {
+        ui.page_requested = Some(after_id);
+        let _ = requests.send(Command::LoadPage {
+            after_id,
+            count: PAGE_SIZE,
+        });
+    }
+}
+
+/// The selected favorite's index if favorites are listed in full and in order,
+/// so they can be moved.
+fn movable_favorite(entries: &UiEntries, ui: &UiState) -> Option<usize> {
+    let selected = entries.loaded_state.selected()?;
+    (ui.query.is_empty()
+        && ui.filter_state.is_none()
+        && ui.split.is_none()
+        && entries.loaded().len() == entries.loaded_entries.len()
+        && selected < entries.favorites())
+    .then_some(selected)
+}
+
+/// Swaps the selected favorite with the one above or below it.
+fn move_favorite(
+    entries: &UiEntries,
+    ui: &mut UiState,
+    requests: &Sender<Command>,
+    direction: MoveDirection,
+) {
+    let Some(index) = movable_favorite(entries, ui) else {
+        ui.status.show(strings::get(Str::ReorderUnavailable));
+        return;
+    };
+    let at_edge = match direction {
+        MoveDirection::Up => index == 0,
+        MoveDirection::Down => index + 1 == entries.favorites(),
+    };
+    if !at_edge {
+        let id = entries.loaded_entries[index].entry.id();
+        let _ = requests.send(Command::MoveFavorite { id, direction });
+    }
+}
+
+/// Handles keys while a favorite is lifted: it follows j/k until Enter moves
+/// it for real or Esc puts it back.
+fn move_reordered(
+    entries: &mut UiEntries,
+    ui: &mut UiState,
+    code: KeyCode,
+    action: Option<AppAction>,
+    requests: &Sender<Command>,
+) {
+    use ratatui::crossterm::event::KeyCode::{Enter, Esc};
+
+    let favorites = entries.favorites();
+    let Some(reorder) = &mut entries.reorder else {
+        return;
+    };
+    if reorder.committed {
+        return;
+    }
+    ui.status.prompt(strings::get(Str::Reordering));
+    match (code, action) {
+        (_, Some(AppAction::MoveDown)) => reorder.to = min(reorder.to + 1, favorites - 1),
+        (_, Some(AppAction::MoveUp)) => reorder.to = reorder.to.saturating_sub(1),
+        (Enter, _) => {
+            ui.status.clear();
+            let Reorder { from, to, .. } = *reorder;
+            if from == to {
+                entries.reorder = None;
+                return;
+            }
+
+            let ids = entries.loaded_entries[min(from, to)..=max(from, to)]
+                .iter()
+                .map(|e| e.entry.id());
+            let _ = requests.send(Command::Reorder(if from < to {
+                ids.collect()
+            } else {
+                ids.rev().collect()
+            }));
+            reorder.committed = true;
+            return;
+        }
+        (Esc, _) => {
+            let from = reorder.from;
+            entries.reorder = None;
+            entries.loaded_state.select(Some(from));
+            ui.status.clear();
+            return;
+        }
+        _ => {}
+    }
+    let to = reorder.to;
+    entries.loaded_state.select(Some(to));
+}
+
+/// Something that can be done to the selected entry with a single key.
+struct EntryAction {
+    action: AppAction,
+    name: Str,
+    applies: fn(&UiEntry) -> bool,
+}
+
+/// The key bindings that act on the selected entry, which the actions menu
+/// lists and dispatches through.
+const ENTRY_ACTIONS: [EntryAction; 9] = [
+    EntryAction {
+        action: AppAction::Paste,
+        name: Str::ActionPaste,
+        applies: |_| true,
+    },
+    EntryAction {
+        action: AppAction::ToggleDetails,
+        name: Str::ActionDetails,
+        applies: |_| true,
+    },
+    EntryAction {
+        action: AppAction::Favorite,
+        name: Str::ActionFavorite,
+        applies: |entry| entry.entry.ring() == RingKind::Main,
+    },
+    EntryAction {
+        action: AppAction::Favorite,
+        name: Str::ActionUnfavorite,
+        applies: |entry| entry.entry.ring() == RingKind::Favorites,
+    },
+    EntryAction {
+        action: AppAction::Open,
+        name: Str::ActionOpen,
+        applies: |_| true,
+    },
+    EntryAction {
+        action: AppAction::Yank,
+        name: Str::ActionYank,
+        applies: |_| true,
+    },
+    EntryAction {
+        action: AppAction::Label,
+        name: Str::ActionLabel,
+        applies: |_| true,
+    },
+    EntryAction {
+        action: AppAction::Tag,
+        name: Str::ActionTag,
+        applies: |_| true,
+    },
+    EntryAction {
+        action: AppAction::Delete,
+        name: Str::ActionDelete,
+        applies: |_| true,
+    },
+];
+
+fn handle_event(event: Event, state: &mut State, requests: &Sender<Command>) -> bool {
+    let State { entries, ui } = state;
+
+    let unselect = |entries: &UiEntries, ui: &mut UiState| {
+        remember_detail_scroll(entries, ui);
+        ui.details_requested = None;
+        ui.detailed_entry = None;
+    };
+    let search = |ui: &mut UiState, kind: SearchKind| send_search(ui, kind, requests);
+    let clear_filter = |entries: &mut UiEntries, ui: &mut UiState| {
+        let selected_id = selected_entry!(entries, ui).map(|e| e.entry.id());
+        ui.filter_state = None;
+        ui.filter = TextArea::default();
+        apply_filter(entries, ui.split, "", selected_id);
+    };
+    let refresh = |ui: &mut UiState| {
+        let _ = requests.send(Command::LoadFirstPage);
+        if let &Some(SearchState { focused: _, kind }) = &ui.search_state {
+            search(ui, kind);
+        }
+    };
+
+    match event {
+        Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind,
+            state: _,
+        }) => {
+            if kind == KeyEventKind::Press {
+                use ratatui::crossterm::event::KeyCode::{Char, Down, Enter, Esc, Left, Right, Up};
+                ui.status.clear();
+                let mut action = ui.keymap.get(code, modifiers);
+                // Modal views close on Esc whatever the bindings, as well as on Quit or the key
+                // that opened them.
+                let closes = |own| {
+                    code == Esc || matches!(action, Some(a) if a == own || a == AppAction::Quit)
+                };
+                if entries.reorder.is_some() {
+                    if code == Char('c') && modifiers == KeyModifiers::CONTROL {
+                        return true;
+                    }
+                    move_reordered(entries, ui, code, action, requests);
+                    return false;
+                }
+                if let Some(scroll) = ui.error_report_scroll {
+                    match action {
+                        _ if code == Char('c') && modifiers == KeyModifiers::CONTROL => {
+                            return true;
+                        }
+                        Some(AppAction::MoveDown) => {
+                            ui.error_report_scroll = Some(scroll.saturating_add(1));
+                        }
+                        Some(AppAction::MoveUp) => {
+                            ui.error_report_scroll = Some(scroll.saturating_sub(1));
+                        }
+                        _ if code == Char('y') => {
+                            if let Some(report) = &ui.error_report {
+                                let report = full_error_report(report, ui.server_info.as_ref());
+                                let _ = requests.send(Command::Copy(report.into()));
+                            }
+                        }
+                        _ if closes(AppAction::ErrorReport) => ui.error_report_scroll = None,
+                        _ => {}
+                    }
+                    return false;
+                }
+                if let Some(dashboard) = &mut ui.dashboard {
+                    let largest = dashboard.stats.as_ref().map_or(0, |s| s.largest.len());
+                    match action {
+                        _ if code == Char('c') && modifiers == KeyModifiers::CONTROL => {
+                            return true;
+                        }
+                        Some(AppAction::MoveDown) => {
+                            dashboard.selected =
+                                min(dashboard.selected + 1, largest.saturating_sub(1));
+                        }
+                        Some(AppAction::MoveUp) => {
+                            dashboard.selected = dashboard.selected.saturating_sub(1);
+                        }
+                        _ if code == Enter => {
+                            let id = dashboard
+                                .stats
+                                .as_ref()
+                                .and_then(|s| s.largest.get(dashboard.selected))
+                                .map(|e| e.id);
+                            close_dashboard(ui);
+                            if let Some(id) = id {
+                                jump_to_entry(entries, ui, id);
+                            }
+                        }
+                        _ if closes(AppAction::Statistics) => close_dashboard(ui),
+                        _ => {}
+                    }
+                    return false;
+                }
+                // Errors are shown before the tutorial so they don't go unnoticed.
+                if let Some(page) = ui.tutorial
+                    && ui.last_error.is_none()
+                {
+                    match code {
+                        Char('c') if modifiers == KeyModifiers::CONTROL => return true,
+                        Left => ui.tutorial = Some(page.saturating_sub(1)),
+                        Right => ui.tutorial = Some(min(page + 1, TUTORIAL_PAGES.len() - 1)),
+                        Enter => {
+                            ui.tutorial = None;
+                            if let Some(state_file) = &ui.state_file {
+                                record_tutorial_dismissed(state_file);
+                            }
+                        }
+                        Esc => ui.tutorial = None,
+                        _ => {}
+                    }
+                    return false;
+                }
+                if let Some(pending) = ui.pending_paste.take() {
+                    match code {
+                        Enter => {
+                            let _ = requests.send(Command::Paste(pending.id));
+                        }
+                        Esc => {}
+                        _ => ui.pending_paste = Some(pending),
+                    }
+                    return false;
+                }
+                if let Some((change, entry)) = ui.pending_change.take() {
+                    let ids = match code {
+                        Char('y') => collapsed_run(entries, ui, entry.id())
+                            .map_or_else(|| vec![entry.id()], <[u64]>::to_vec),
+                        Char('n') => vec![entry.id()],
+                        _ => return false,
+                    };
+                    send_change(ui, requests, change, entry.ring(), &ids);
+                    refresh(ui);
+                    return false;
+                }
+                if mem::take(&mut ui.pending_deduplicate) {
+                    if code == Char('y') {
+                        let _ = requests.send(Command::Deduplicate);
+                    }
+                    ui.status.clear();
+                    return false;
+                }
+                if let Some(pending) = ui.pending_replace.take() {
+                    match code {
+                        Enter => {
+                            ui.outstanding_request = Some(pending.id);
+                            let _ = requests.send(Command::Replace {
+                                id: pending.id,
+                                text: pending.text,
+                            });
+                        }
+                        // Back to the command so it can be tweaked.
+                        Esc => ui.substituting = Some(pending.id),
+                        _ => ui.pending_replace = Some(pending),
+                    }
+                    return false;
+                }
+                if let Some(id) = ui.substituting {
+                    match code {
+                        Esc => {
+                            ui.substituting = None;
+                            ui.substitution_error = None;
+                        }
+                        Enter => match prepare_replace(ui, id) {
+                            Ok(pending) => {
+                                ui.substituting = None;
+                                ui.pending_replace = Some(pending);
+                            }
+                            Err(e) => ui.substitution_error = Some(e),
+                        },
+                        _ => {
+                            if ui.substitution.input(Input::from(event)) {
+                                ui.substitution_error = None;
+                            }
+                        }
+                    }
+                    return false;
+                }
+                if let Some(id) = ui.editing {
+                    match code {
+                        Esc => ui.editing = None,
+                        Char('s') if modifiers == KeyModifiers::CONTROL => {
+                            ui.editing = None;
+                            let text = mem::take(&mut ui.editor).into_lines().join("\n");
+                            if let Some(Ok(DetailedEntry {
+                                full_text: Some(original),
+                                ..
+                            })) = &ui.detailed_entry
+                                && **original == text
+                            {
+                                return false;
+                            }
+                            ui.outstanding_request = Some(id);
+                            let _ = requests.send(Command::ReplaceEntry {
+                                id,
+                                data: text.into_bytes().into(),
+                            });
+                        }
+                        _ => {
+                            ui.editor.input(Input::from(event));
+                        }
+                    }
+                    return false;
+                }
+                if ui.jumping {
+                    match code {
+                        Esc => ui.jumping = false,
+                        Enter => {
+                            ui.jumping = false;
+                            let text = mem::take(&mut ui.jump).into_lines().remove(0);
+                            if let Ok(id) = parse_id(&text) {
+                                jump_to_id(entries, ui, requests, id);
+                            } else {
+                                ui.status
+                                    .show(strings::fill(Str::UnknownEntry, &[&text.trim()]));
+                            }
+                        }
+                        _ => {
+                            ui.jump.input(Input::from(event));
+                        }
+                    }
+                    return false;
+                }
+                if ui.finding {
+                    match code {
+                        Esc => end_find(ui),
+                        Enter => ui.finding = false,
+                        _ => {
+                            if ui.detail_query.input(Input::from(event)) {
+                                find_in_details(ui);
+                            }
+                        }
+                    }
+                    return false;
+                }
+                if let Some(id) = ui.labeling {
+                    match code {
+                        Esc => {
+                            ui.labeling = None;
+                        }
+                        Enter => {
+                            ui.labeling = None;
+                            let text = mem::take(&mut ui.label).into_lines().remove(0);
+                            let mut label = Label::new();
+                            for c in text.trim().chars() {
+                                if label.try_push(c).is_err() {
+                                    break;
+                                }
+                            }
+                            ui.outstanding_request = Some(id);
+                            let _ = requests.send(Command::SetLabel { id, label });
+                        }
+                        _ => {
+                            ui.label.input(Input::from(event));
+                        }
+                    }
+                    return false;
+                }
+                if let Some(id) = ui.tagging {
+                    match code {
+                        Esc => {
+                            ui.tagging = None;
+                        }
+                        Enter => {
+                            let text = ui.tag.lines()[0].trim();
+                            let (untag, text) = text
+                                .strip_prefix('-')
+                                .map_or((false, text), |text| (true, text));
+                            if let Some(tag) = tags::parse(text) {
+                                ui.tagging = None;
+                                ui.tag = TextArea::default();
+                                ui.outstanding_request = Some(id);
+                                let _ = requests.send(if untag {
+                                    Command::Untag { id, tag }
+                                } else {
+                                    Command::Tag { id, tag }
+                                });
+                            } else {
+                                ui.status.show(strings::get(Str::InvalidTag));
+                            }
+                        }
+                        _ => {
+                            ui.tag.input(Input::from(event));
+                        }
+                    }
+                    return false;
+                }
+                if let Some(selected) = ui.actions_menu {
+                    match action {
+                        Some(AppAction::MoveDown) => {
+                            ui.actions_menu = Some((selected + 1) % ENTRY_ACTIONS.len());
+                            return false;
+                        }
+                        Some(AppAction::MoveUp) => {
+                            ui.actions_menu =
+                                Some(selected.checked_sub(1).unwrap_or(ENTRY_ACTIONS.len() - 1));
+                            return false;
+                        }
+                        _ if code == Enter => {
+                            ui.actions_menu = None;
+                            let EntryAction {
+                                action: chosen,
+                                applies,
+                                ..
+                            } = ENTRY_ACTIONS[selected];
+                            if !selected_entry!(entries, ui).is_some_and(applies) {
+                                return false;
+                            }
+                            // Carry on as if the action's key had been pressed.
+                            action = Some(chosen);
+                        }
+                        _ if closes(AppAction::Actions) => {
+                            ui.actions_menu = None;
+                            return false;
+                        }
+                        _ => return false,
+                    }
+                }
+
+                // Keys typed into the search or filter box go to it, except for the ones that
+                // leave it.
+                let typing = matches!(ui.search_state, Some(SearchState { focused: true, .. }))
+                    || matches!(ui.filter_state, Some(FilterState { focused: true }));
+                let (back, confirm) = if typing {
+                    (code == Esc, code == Enter)
+                } else {
+                    (
+                        action == Some(AppAction::Back),
+                        action == Some(AppAction::Paste),
+                    )
+                };
+                if back {
+                    if !entries.marked.is_empty() {
+                        entries.marked.clear();
+                        entries.last_mark = None;
+                        entries.generation += 1;
+                    } else if let Some(SearchState { focused, .. }) = &mut ui.search_state
+                        && *focused
+                    {
+                        *focused = false;
+                    } else if let Some(FilterState { focused: true }) = ui.filter_state {
+                        clear_filter(entries, ui);
+                    } else if ui.server_stats.is_some() {
+                        ui.server_stats = None;
+                    } else if !ui.detail_query.is_empty() {
+                        end_find(ui);
+                    } else if ui.details_requested.is_some() {
+                        unselect(entries, ui);
+                    } else if ui.filter_state.is_some() {
+                        clear_filter(entries, ui);
+                    } else if ui.search_state.is_some() {
+                        ui.search_state = None;
+                        ui.query = TextArea::default();
+                    } else {
+                        return true;
+                    }
+                } else if confirm {
+                    if let Some(SearchState { focused, .. }) = &mut ui.search_state
+                        && *focused
+                    {
+                        *focused = false;
+                    } else if let Some(FilterState { focused }) = &mut ui.filter_state
+                        && *focused
+                    {
+                        *focused = false;
+                    } else if let Some(selected) = selected_entry!(entries, ui) {
+                        let id = selected.entry.id();
+                        if collapsed_run(entries, ui, id).is_some() {
+                            expand_run(entries, ui, id);
+                        } else {
+                            paste(ui, requests, selected);
+                        }
+                    }
+                } else if let Some(option) = action.filter(|a| a.is_search_option())
+                    // Plain keys type into the search box, so only shortcuts change options there.
+                    && (!typing || modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT))
+                    && let &Some(SearchState { focused: _, kind }) = &ui.search_state
+                {
+                    toggle_search_option(ui, option);
+                    search(ui, kind);
+                } else if let &mut Some(SearchState {
+                    ref mut focused,
+                    ref mut kind,
+                }) = &mut ui.search_state
+                    && *focused
+                {
+                    let changed = match Input::from(event) {
+                        Input {
+                            key: Key::Char('x'),
+                            ctrl: true,
+                            alt: false,
+                            shift: _,
+                        } => {
+                            *kind = match *kind {
+                                SearchKind::Regex => SearchKind::Plain,
+                                SearchKind::Plain | SearchKind::Mime | SearchKind::Tag => {
+                                    SearchKind::Regex
+                                }
+                            };
+                            true
+                        }
+                        Input {
+                            key: Key::Left,
+                            ctrl: true,
+                            alt: false,
+                            shift: _,
+                        } => {
+                            ui.query.move_cursor(CursorMove::WordBack);
+                            false
+                        }
+                        Input {
+                            key: Key::Right,
+                            ctrl: true,
+                            alt: false,
+                            shift: _,
+                        } => {
+                            ui.query.move_cursor(CursorMove::WordForward);
+                            false
+                        }
+                        Input {
+                            key: Key::Char('u'),
+                            ctrl: true,
+                            alt: false,
+                            shift: _,
+                        } => {
+                            ui.query.move_cursor(CursorMove::Head);
+                            ui.query.delete_line_by_end()
+                        }
+                        i => ui.query.input(i),
+                    };
+                    if changed {
+                        let kind = *kind;
+                        search(ui, kind);
+                    } else if code == Up || code == Down {
+                        *focused = false;
+                    }
+                } else if let Some(FilterState { focused }) = &mut ui.filter_state
+                    && *focused
+                {
+                    if code == Up || code == Down {
+                        *focused = false;
+                    } else if ui.filter.input(Input::from(event)) {
+                        let selected_id = selected_entry!(entries, ui).map(|e| e.entry.id());
+                        apply_filter(entries, ui.split, filter_query(&ui.filter), selected_id);
+                    }
+                } else {
+                    match action {
+                        Some(AppAction::Quit) => return true,
+                        Some(AppAction::Filter) => {
+                            if ui.search_state.is_some() {
+                                ui.search_state = None;
+                                ui.query = TextArea::default();
+                            }
+                            ui.filter_state = Some(FilterState { focused: true });
+                        }
+                        // Digits paste the entry shown next to them unless they're bound.
+                        None => {
+                            if let Char(c @ '1'..='9') = code
+                                && !ui.disable_quick_select
+                            {
+                                let index = active_list_state!(entries, ui).offset()
+                                    + usize::try_from(u32::from(c) - u32::from('1')).unwrap();
+                                if index < active_entries!(entries, ui).len() {
+                                    active_list_state!(entries, ui).select(Some(index));
+                                }
+                                if let Some(entry) = active_entries!(entries, ui).get(index) {
+                                    paste(ui, requests, entry);
+                                }
+                            }
+                        }
+                        Some(AppAction::MoveFavoriteUp) => {
+                            move_favorite(entries, ui, requests, MoveDirection::Up);
+                        }
+                        Some(AppAction::MoveFavoriteDown) => {
+                            move_favorite(entries, ui, requests, MoveDirection::Down);
+                        }
+                        Some(AppAction::Unselect) => unselect(entries, ui),
+                        Some(AppAction::MoveDown) => {
+                            let len = active_entries!(entries, ui).len();
+                            let at_bottom =
+                                active_list_state!(entries, ui).selected().map(|i| i + 1)
+                                    == Some(len);
+                            if at_bottom && let Some(after_id) = next_page_start(entries, ui) {
+                                load_next_page(ui, requests, after_id);
+                            } else if len > 0 {
+                                let state = active_list_state!(entries, ui);
+                                let next = state
+                                    .selected()
+                                    .map_or(0, |i| if i + 1 == len { 0 } else { i + 1 });
+                                state.select(Some(next.min(len)));
+                            }
+                        }
+                        Some(AppAction::ScrollDetailsDown) => {
+                            ui.detail_scroll = ui.detail_scroll.saturating_add(1);
+                        }
+                        Some(AppAction::MoveUp) => {
+                            let state = active_list_state!(entries, ui);
+                            let len = active_entries!(entries, ui).len();
+                            let previous = state.selected().map_or(usize::MAX, |i| {
+                                if i == 0 { len.wrapping_sub(1) } else { i - 1 }
+                            });
+                            if let Some(SearchState { focused, .. }) = &mut ui.search_state
+                                && (len == 0 || Some(previous) > state.selected())
+                            {
+                                *focused = true;
+                            } else if len > 0 {
+                                state.select(Some(previous.min(len - 1)));
+                            }
+                        }
+                        Some(AppAction::ScrollDetailsUp) => {
+                            ui.detail_scroll = ui.detail_scroll.saturating_sub(1);
+                        }
+                        Some(AppAction::ToggleWrap) => {
+                            ui.unwrapped_details ^= true;
+                            ui.detail_column = 0;
+                            ui.detail_rows = None;
+                        }
+                        Some(AppAction::ScrollDetailsRight) if ui.unwrapped_details => {
+                            ui.detail_column =
+                                ui.detail_column.saturating_add(HORIZONTAL_SCROLL_STEP);
+                        }
+                        Some(AppAction::ScrollDetailsLeft) => {
+                            ui.detail_column =
+                                ui.detail_column.saturating_sub(HORIZONTAL_SCROLL_STEP);
+                        }
+                        Some(AppAction::Top) => {
+                            if !active_entries!(entries, ui).is_empty() {
+                                active_list_state!(entries, ui).select(Some(0));
+                            }
+                        }
+                        Some(AppAction::Bottom) => {
+                            let len = active_entries!(entries, ui).len();
+                            if len > 0 {
+                                active_list_state!(entries, ui).select(Some(len - 1));
+                            }
+                        }
+                        Some(AppAction::PageDown) => move_by_page(entries, ui, requests, true),
+                        Some(AppAction::PageUp) => move_by_page(entries, ui, requests, false),
+                        Some(AppAction::Select) => {
+                            if let Some(&UiEntry { entry, .. }) = selected_entry!(entries, ui)
+                                && collapsed_run(entries, ui, entry.id()).is_some()
+                            {
+                                expand_run(entries, ui, entry.id());
+                            } else {
+                                maybe_get_details(entries, ui, requests);
+                            }
+                        }
+                        Some(AppAction::ToggleDetails) => {
+                            if ui.details_requested.is_some() {
+                                unselect(entries, ui);
+                            } else {
+                                maybe_get_details(entries, ui, requests);
+                            }
+                        }
+                        Some(AppAction::Search) if ui.details_requested.is_some() => {
+                            if matches!(
+                                ui.detailed_entry,
+                                Some(Ok(DetailedEntry {
+                                    full_text: Some(_),
+                                    ..
+                                }))
+                            ) {
+                                end_find(ui);
+                                ui.finding = true;
+                            } else {
+                                ui.status.show(strings::get(Str::NoTextToSearch));
+                            }
+                        }
+                        Some(a @ (AppAction::NextMatch | AppAction::PreviousMatch))
+                            if ui.details_requested.is_some() =>
+                        {
+                            let len = ui.detail_matches.len();
+                            if len == 0 {
+                                ui.status.show(strings::get(Str::NoMatches));
+                            } else {
+                                ui.current_match = Some(match (a, ui.current_match) {
+                                    (AppAction::NextMatch, Some(i)) => (i + 1) % len,
+                                    (AppAction::NextMatch, None) => 0,
+                                    (_, Some(i)) => i.checked_sub(1).unwrap_or(len - 1),
+                                    (_, None) => len - 1,
+                                });
+                                ui.scroll_to_match = true;
+                            }
+                        }
+                        Some(AppAction::FindSimilar) if ui.details_requested.is_some() => {
+                            if ui.filter_state.is_some() {
+                                clear_filter(entries, ui);
+                            }
+                            find_similar(ui, requests);
+                        }
+                        Some(
+                            a @ (AppAction::Search
+                            | AppAction::RegexSearch
+                            | AppAction::MimeSearch
+                            | AppAction::TagSearch),
+                        ) => {
+                            let kind = match a {
+                                AppAction::RegexSearch => SearchKind::Regex,
+                                AppAction::MimeSearch => SearchKind::Mime,
+                                AppAction::TagSearch => SearchKind::Tag,
+                                _ => SearchKind::Plain,
+                            };
+                            if ui.filter_state.is_some() {
+                                clear_filter(entries, ui);
+                            }
+                            ui.search_state = Some(SearchState {
+                                focused: true,
+                                kind,
+                            });
+                            search(ui, kind);
+                        }
+                        Some(AppAction::Mark) => toggle_mark(entries, ui),
+                        Some(AppAction::MarkRange) => mark_range(entries, ui),
+                        Some(a @ (AppAction::Favorite | AppAction::Delete)) => {
+                            let change = if a == AppAction::Favorite {
+                                Change::Favorite
+                            } else {
+                                Change::Delete
+                            };
+                            if !entries.marked.is_empty() {
+                                send_marked_change(entries, ui, requests, change);
+                                refresh(ui);
+                            } else if let Some(&UiEntry { entry, .. }) =
+                                selected_entry!(entries, ui)
+                                && ui.outstanding_request != Some(entry.id())
+                            {
+                                if collapsed_run(entries, ui, entry.id()).is_some() {
+                                    ui.pending_change = Some((change, entry));
+                                    ui.status.prompt(strings::get(match change {
+                                        Change::Favorite => Str::ConfirmFavoriteGroup,
+                                        Change::Delete => Str::ConfirmDeleteGroup,
+                                    }));
+                                } else {
+                                    send_change(ui, requests, change, entry.ring(), &[entry.id()]);
+                                    refresh(ui);
+                                }
+                            }
+                        }
+                        Some(AppAction::UndoDelete) => {
+                            let _ = requests.send(Command::UndoDelete);
+                            refresh(ui);
+                        }
+                        Some(AppAction::Deduplicate) => {
+                            ui.pending_deduplicate = true;
+                            ui.status.prompt(strings::get(Str::ConfirmDeduplicate));
+                        }
+                        Some(AppAction::Actions) => {
+                            if selected_entry!(entries, ui).is_some() {
+                                ui.actions_menu = Some(0);
+                            }
+                        }
+                        Some(AppAction::Statistics) => {
+                            ui.dashboard = Some(DashboardView::default());
+                            let _ = requests.send(Command::ComputeDashboard);
+                        }
+                        Some(AppAction::ServerStats) => {
+                            if ui.server_stats.take().is_none() {
+                                ui.server_stats = Some(None);
+                                let _ = requests.send(Command::GetStats);
+                            }
+                        }
+                        Some(AppAction::ErrorReport) => {
+                            if let Some(e) = ui.last_error.take_if(|e| !is_query_error(e)) {
+                                ui.error_report = Some(format_error(e));
+                            }
+                            if ui.error_report.is_some() {
+                                ui.error_report_scroll = Some(0);
+                                if ui.server_info.is_none() {
+                                    let _ = requests.send(Command::GetServerInfo);
+                                }
+                            } else {
+                                ui.status.show(strings::get(Str::NoErrorReport));
+                            }
+                        }
+                        Some(AppAction::ImageInfo) => {
+                            if ui.details_requested.is_some()
+                                && let Some(UiEntry {
+                                    cache: UiEntryCache::Image,
+                                    ..
+                                }) = selected_entry!(entries, ui)
+                            {
+                                ui.show_image_metadata ^= true;
+                            }
+                        }
+                        Some(AppAction::BinaryAsText) => {
+                            if ui.details_requested.is_some()
+                                && let Some(UiEntry {
+                                    cache: UiEntryCache::Binary { .. },
+                                    ..
+                                }) = selected_entry!(entries, ui)
+                            {
+                                ui.binary_as_text ^= true;
+                                ui.detail_rows = None;
+                            }
+                        }
+                        Some(AppAction::PrettyJson) => {
+                            if ui.details_requested.is_some()
+                                && let Some(UiEntry {
+                                    cache:
+                                        UiEntryCache::Text {
+                                            highlight: Some(TextKind::Json),
+                                            ..
+                                        },
+                                    ..
+                                }) = selected_entry!(entries, ui)
+                            {
+                                ui.pretty_json ^= true;
+                                ui.detail_rows = None;
+                            }
+                        }
+                        Some(AppAction::Reveal) => {
+                            if let Some(&UiEntry {
+                                entry,
+                                cache: UiEntryCache::Sensitive,
+                                ..
+                            }) = selected_entry!(entries, ui)
+                                && ui.details_requested == Some(entry.id())
+                                && ui.revealed != Some(entry.id())
+                            {
+                                ui.revealed = Some(entry.id());
+                                ui.detailed_entry = None;
+                                ui.detail_rows = None;
+                                let _ = requests.send(Command::GetDetails {
+                                    id: entry.id(),
+                                    with_text: true,
+                                });
+                            }
+                        }
+                        Some(AppAction::Yank) => {
+                            if let Some(UiEntry { entry, .. }) = selected_entry!(entries, ui) {
+                                let _ = requests.send(Command::Yank(entry.id()));
+                            }
+                        }
+                        Some(AppAction::Open) => {
+                            if let Some(UiEntry { entry, .. }) = selected_entry!(entries, ui) {
+                                let _ = requests.send(Command::OpenExternal(entry.id()));
+                            }
+                        }
+                        Some(AppAction::Label) => {
+                            if let Some(UiEntry { entry, label, .. }) = selected_entry!(entries, ui)
+                            {
+                                ui.labeling = Some(entry.id());
+                                ui.label = TextArea::default();
+                                ui.label.insert_str(label.as_deref().unwrap_or_default());
+                            }
+                        }
+                        Some(AppAction::Tag) => {
+                            if let Some(UiEntry { entry, .. }) = selected_entry!(entries, ui) {
+                                ui.tagging = Some(entry.id());
+                                ui.tag = TextArea::default();
+                            }
+                        }
+                        Some(AppAction::Edit) => {
+                            if let Some(&UiEntry {
+                                entry,
+                                cache: UiEntryCache::Text { .. },
+                                len,
+                                ..
+                            }) = selected_entry!(entries, ui)
+                                && ui.details_requested == Some(entry.id())
+                                && len < MAX_REPLACE_LEN
+                                && let Some(Ok(DetailedEntry {
+                                    full_text: Some(text),
+                                    ..
+                                })) = &ui.detailed_entry
+                            {
+                                // Splitting on newlines rather than lines keeps a trailing one.
+                                ui.editor =
+                                    TextArea::new(text.split('\n').map(String::from).collect());
+                                ui.editing = Some(entry.id());
+                            } else {
+                                ui.status.show(strings::get(Str::ReplaceUnavailable));
+                            }
+                        }
+                        Some(AppAction::Jump) if ui.details_requested.is_none() => {
+                            ui.jumping = true;
+                            ui.jump = TextArea::default();
+                        }
+                        Some(AppAction::Jump) => {
+                            if let Some(&UiEntry {
+                                entry,
+                                cache: UiEntryCache::Text { .. },
+                                len,
+                                ..
+                            }) = selected_entry!(entries, ui)
+                                && ui.details_requested == Some(entry.id())
+                                && len < MAX_REPLACE_LEN
+                            {
+                                ui.substituting = Some(entry.id());
+                                ui.substitution_error = None;
+                                ui.substitution = TextArea::default();
+                                ui.substitution.insert_str("s/");
+                            } else {
+                                ui.status.show(strings::get(Str::ReplaceUnavailable));
+                            }
+                        }
+                        Some(AppAction::Split) => {
+                            if ui.split.is_none() && ui.terminal_width < MIN_SPLIT_WIDTH {
+                                ui.status.show(strings::get(Str::SplitTooNarrow));
+                            } else {
+                                toggle_split(entries, ui);
+                            }
+                        }
+                        Some(AppAction::SwitchPane) => {
+                            if let Some(focus) = &mut ui.split
+                                && ui.query.is_empty()
+                            {
+                                *focus = other_pane(*focus);
+                            }
+                        }
+                        Some(AppAction::Reorder) => {
+                            if let Some(from) = movable_favorite(entries, ui) {
+                                entries.reorder = Some(Reorder {
+                                    from,
+                                    to: from,
+                                    committed: false,
+                                });
+                                ui.status.prompt(strings::get(Str::Reordering));
+                            } else {
+                                ui.status.show(strings::get(Str::ReorderUnavailable));
+                            }
+                        }
+                        Some(AppAction::Help) => {
+                            ui.show_help ^= true;
+                            if ui.show_help && ui.server_info.is_none() {
+                                let _ = requests.send(Command::GetServerInfo);
+                            }
+                        }
+                        Some(a @ (AppAction::Refresh | AppAction::Reset)) => {
+                            if a == AppAction::Reset {
+                                let keymap = mem::take(&mut ui.keymap);
+                                *state = State::default();
+                                state.ui.keymap = keymap;
+                            }
+                            refresh(&mut state.ui);
+                            state.ui.status.show(strings::get(Str::Refreshed));
+                            return false;
+                        }
+                        // Handled above, or nothing to do for now.
+                        Some(
+                            AppAction::ScrollDetailsRight
+                            | AppAction::NextMatch
+                            | AppAction::PreviousMatch
+                            | AppAction::FindSimilar
+                            | AppAction::Back
+                            | AppAction::Paste
+                            | AppAction::SearchScope
+                            | AppAction::MimeScope
+                            | AppAction::MatchCase
+                            | AppAction::FavoritesFirst,
+                        ) => {}
+                    }
+                }
+            }
+        }
+        Event::Paste(text) => {
+            // Inputs besides the editor are single line, so they only get the first line
+            // of the paste.
+            let line = text.lines().next().unwrap_or_default();
+            if ui.editing.is_some() {
+                ui.editor.insert_str(&text);
+            } else if ui.labeling.is_some() {
+                ui.label.insert_str(line);
+            } else if ui.tagging.is_some() {
+                ui.tag.insert_str(line);
+            } else if ui.jumping {
+                ui.jump.insert_str(line);
+            } else if ui.finding {
+                if ui.detail_query.insert_str(line) {
+                    find_in_details(ui);
+                }
+            } else if ui.substituting.is_some() {
+                if ui.substitution.insert_str(line) {
+                    ui.substitution_error = None;
+                }
+            } else if let &Some(SearchState {
+                focused: true,
+                kind,
+            }) = &ui.search_state
+            {
+                if ui.query.insert_str(line) {
+                    search(ui, kind);
+                }
+            } else if let Some(FilterState { focused: true }) = ui.filter_state
+                && ui.filter.insert_str(line)
+            {
+                let selected_id = selected_entry!(entries, ui).map(|e| e.entry.id());
+                apply_filter(entries, ui.split, filter_query(&ui.filter), selected_id);
+            }
+        }
+        Event::FocusGained => {
+            let _ = requests.send(Command::RefreshDb);
+        }
+        Event::Resize(width, _) => {
+            ui.terminal_width = width;
+            // Images are scaled to fit when their protocol is created, so reload
+            // them at the new size. Scrolled text is clamped to the new layout as
+            // it's drawn.
+            if matches!(ui.detail_image_state, Some(ImageState::Loaded(..))) {
+                ui.detail_image_state = None;
+            }
+            if ui.split.is_some() && width < MIN_SPLIT_WIDTH {
+                toggle_split(entries, ui);
+                ui.status.show(strings::get(Str::SplitTooNarrow));
+            }
+        }
+        _ => {}
+    }
+    if ui.details_requested.is_some() {
+        maybe_get_details(entries, ui, requests);
+    }
+    false
+}
+
+struct AppWrapper<'a> {
+    requests: &'a Sender<Command>,
+    state: &'a mut State,
+    badges: BadgeStyle,
+    accessible: bool,
+    favorites_only: bool,
+    runtime: &'a RuntimeInfo,
+    font_size: FontSize,
+}
+
+impl AppWrapper<'_> {
+    fn draw(&mut self, terminal: &mut Terminal<impl Backend>) -> io::Result<()> {
+        terminal.draw(|f| f.render_widget(self, f.area()))?;
+        Ok(())
+    }
+}
+
+impl Widget for &mut AppWrapper<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        self.state.ui.terminal_width = area.width;
+        if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+            Paragraph::new(strings::fill(
+                Str::TerminalTooSmall,
+                &[&MIN_WIDTH, &MIN_HEIGHT],
+            ))
+            .wrap(Wrap { trim: true })
+            .render(area, buf);
+            return;
+        }
+        let State { entries: _, ui } = &self.state;
+        // Query errors are shown in the search box so the results stay visible.
+        let has_error = ui.last_error.is_some() && query_error(ui).is_none();
+
+        // The picker stays out of the way until it's asked to search.
+        let show_header =
+            !self.favorites_only || ui.search_state.is_some() || ui.filter_state.is_some();
+        let [header_area, main_area, status_area, footer_area] = Layout::vertical([
+            Constraint::Length(u16::from(show_header)),
+            Constraint::Min(0),
+            Constraint::Length(u16::from(ui.status.current().is_some())),
+            Constraint::Length(if ui.show_help { 3 } else { 0 }),
+        ])
+        .areas(area);
+
+        let show_details = ui.details_requested.is_some() || ui.server_stats.is_some() || has_error;
+        let split = ui.split.filter(|_| ui.query.is_empty());
+        let [entry_list_area, _padding, selected_entry_area] = if let Some(focus) = split {
+            let [main_ring_area, padding, favorites_area] = Layout::horizontal([
+                Constraint::Percentage(50),
+                Constraint::Length(1),
+                Constraint::Percentage(50),
+            ])
+            .areas(main_area);
+            // The details take the place of the pane that isn't focused.
+            match focus {
+                RingKind::Main => [main_ring_area, padding, favorites_area],
+                RingKind::Favorites => [favorites_area, padding, main_ring_area],
+            }
+        } else {
+            if !show_details {
+                Layout::vertical([
+                    Constraint::Min(0),
+                    Constraint::Length(0),
+                    Constraint::Length(0),
+                ])
+            } else if area.width <= area.height * 3 {
+                Layout::vertical([
+                    Constraint::Percentage(50),
+                    Constraint::Length(0),
+                    Constraint::Percentage(50),
+                ])
+            } else {
+                Layout::horizontal([
+                    Constraint::Percentage(50),
+                    Constraint::Length(1),
+                    Constraint::Percentage(50),
+                ])
+            }
+            .areas(main_area)
+        };
+
+        AppWrapper::render_title(header_area, buf, self.runtime);
+        if let Some(status) = ui.status.current() {
+            Line::raw(status).italic().render(status_area, buf);
+        }
+        self.render_entries(entry_list_area, buf, split);
+        if has_error {
+            self.render_error(selected_entry_area, buf);
+        } else if self.state.ui.server_stats.is_some() {
+            self.render_server_stats(selected_entry_area, buf);
+        } else if let Some(focus) = split
+            && !show_details
+        {
+            self.render_entries(selected_entry_area, buf, Some(other_pane(focus)));
+        } else {
+            self.render_selected_entry(selected_entry_area, buf);
+        }
+        AppWrapper::render_footer(
+            footer_area,
+            buf,
+            self.state.ui.server_info.as_ref(),
+            &self.state.ui.keymap,
+            self.accessible,
+        );
+        self.render_actions_menu(entry_list_area, buf);
+        self.render_paste_confirmation(area, buf);
+        self.render_replace_preview(area, buf);
+        self.render_tutorial(area, buf);
+        self.render_dashboard(area, buf);
+        self.render_error_report(area, buf);
+    }
+}
+
+const TUTORIAL_PAGES: [Str; 5] = [
+    Str::TutorialCapture,
+    Str::TutorialNavigation,
+    Str::TutorialSearch,
+    Str::TutorialFavorites,
+    Str::TutorialPasting,
+];
+
+/// A `width` by `height` rectangle in the middle of `area`, shrunk to fit.
+fn centered_popup(area: Rect, width: usize, height: usize) -> Rect {
+    let width = u16::try_from(width).unwrap_or(u16::MAX).min(area.width);
+    let height = u16::try_from(height).unwrap_or(u16::MAX).min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// Shows `text` in a bordered box over the middle of `area`.
+fn render_text_popup(area: Rect, buf: &mut Buffer, title: &str, text: &str) {
+    let width = text
+        .lines()
+        .chain([title])
+        .map(|line| Line::raw(line).width())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let popup = centered_popup(area, width, text.lines().count() + 2);
+
+    Clear.render(popup, buf);
+    Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::new().bold())
+                .title(title),
+        )
+        .render(popup, buf);
+}
+
+/// Formats a byte count with binary units, e.g. `1.5 MiB`.
+/// Describes an image in the strip below it, one fact per line.
+fn image_metadata_lines(
+    &ImageMetadata {
+        width,
+        height,
+        format,
+        len,
+        ref taken,
+        ref camera,
+    }: &ImageMetadata,
+    accessible: bool,
+) -> Vec<Line<'static>> {
+    let format = format.map_or_else(String::new, |format| format!("{format:?}").to_uppercase());
+    let mut lines = vec![Line::from(strings::fill(
+        if accessible {
+            Str::ImageInfoAccessible
+        } else {
+            Str::ImageInfo
+        },
+        &[&width, &height, &format, &format_size(len)],
+    ))];
+    if let Some(taken) = taken {
+        lines.push(Line::from(strings::fill(Str::ImageTaken, &[taken])));
+    }
+    if let Some(camera) = camera {
+        lines.push(Line::from(strings::fill(Str::ImageCamera, &[camera])));
+    }
+    lines
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.;
+    let mut unit = 0;
+    while size >= 1024. && unit + 1 < UNITS.len() {
+        size /= 1024.;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Shows the start of a binary entry `len` bytes long, noting if the rest was
+/// left out.
+fn binary_preview(bytes: &[u8], len: u64, as_text: bool, width: u16) -> String {
+    let mut preview = if as_text {
+        String::from_utf8_lossy(bytes).into_owned()
+    } else {
+        hex_dump(bytes, width)
+    };
+    let shown = u64::try_from(bytes.len()).unwrap();
+    if shown < len {
+        preview.push_str("\n\n");
+        let (shown, len) = (format_size(shown), format_size(len));
+        preview.push_str(&strings::fill(Str::PreviewTruncated, &[&shown, &len]));
+    }
+    preview
+}
+
+/// Reindents JSON with two spaces per level, leaving keys and values as they
+/// are.
+///
+/// Returns [`None`] if the brackets don't match up, e.g. because the entry
+/// only looked like JSON.
+fn pretty_json(text: &str) -> Option<String> {
+    fn newline(pretty: &mut String, depth: usize) {
+        pretty.push('\n');
+        for _ in 0..depth {
+            pretty.push_str("  ");
+        }
+    }
+
+    let mut pretty = String::with_capacity(text.len() * 2);
+    let mut closers = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                pretty.push(c);
+                loop {
+                    let c = chars.next()?;
+                    pretty.push(c);
+                    match c {
+                        '\\' => pretty.push(chars.next()?),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' | '[' => {
+                let closer = if c == '{' { '}' } else { ']' };
+                pretty.push(c);
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                // Empty objects and arrays stay on one line.
+                if chars.next_if_eq(&closer).is_some() {
+                    pretty.push(closer);
+                    continue;
+                }
+                closers.push(closer);
+                newline(&mut pretty, closers.len());
+            }
+            '}' | ']' => {
+                if closers.pop()? != c {
+                    return None;
+                }
+                newline(&mut pretty, closers.len());
+                pretty.push(c);
+            }
+            ',' => {
+                pretty.push(c);
+                newline(&mut pretty, closers.len());
+            }
+            ':' => pretty.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => pretty.push(c),
+        }
+    }
+    closers.is_empty().then_some(pretty)
+}
+
+/// Formats bytes as offset, hex and ASCII columns with as many bytes per row as
+/// fit in `width` columns.
+fn hex_dump(bytes: &[u8], width: u16) -> String {
+    // The offset, gaps and bars take 13 columns and each byte takes 4.
+    let per_row = [16, 8]
+        .into_iter()
+        .find(|n| 4 * n + 13 <= usize::from(width))
+        .unwrap_or(4);
+    let mut dump = String::with_capacity(bytes.len().div_ceil(per_row) * (4 * per_row + 14));
+    for (i, row) in bytes.chunks(per_row).enumerate() {
+        if i > 0 {
+            dump.push('\n');
+        }
+        let _ = write!(dump, "{:08x} ", i * per_row);
+        for j in 0..per_row {
+            match row.get(j) {
+                Some(b) => {
+                    let _ = write!(dump, " {b:02x}");
+                }
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        dump.extend(row.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                char::from(b)
+            } else {
+                '.'
+            }
+        }));
+        dump.push('|');
+    }
+    dump
+}
+
+/// How far lines of the given widths can be scrolled sideways before the
+/// longest one has been seen in full through `room` columns.
+fn max_column(widths: impl Iterator<Item = usize>, room: u16) -> u16 {
+    let longest = widths.max().unwrap_or(0);
+    u16::try_from(longest.saturating_sub(usize::from(room))).unwrap_or(u16::MAX)
+}
+
+/// Splits text into the rows it occupies when word wrapped to `width` columns.
+///
+/// Rows are only broken inside a word if it doesn't fit on a row of its own,
+/// and wide characters are never split across rows.
+fn wrapped_lines(text: &str, width: u16) -> Vec<&str> {
+    let width = usize::from(width.max(1));
+    let mut rows = Vec::new();
+    for line in text.lines() {
+        let mut start = 0;
+        let mut row_width = 0;
+        // Where the row may be broken after whitespace and its width up to there.
+        let mut word_break = None;
+        for (i, c) in line.char_indices() {
+            let char_width = c.width().unwrap_or(0);
+            while row_width > 0 && row_width + char_width > width {
+                let (end, end_width) = word_break.take().unwrap_or((i, row_width));
+                rows.push(&line[start..end]);
+                start = end;
+                row_width -= end_width;
+            }
+            row_width += char_width;
+            if c.is_whitespace() {
+                word_break = Some((i + c.len_utf8(), row_width));
+            }
+        }
+        rows.push(&line[start..]);
+    }
+    rows
+}
+
+/// Shortens text to at most `width` columns by replacing its end with an
+/// ellipsis.
+fn truncate_end(text: &str, width: usize) -> Cow<str> {
+    if text.width() <= width {
+        return Cow::Borrowed(text);
+    }
+    let Some(room) = width.checked_sub(1) else {
+        return Cow::Borrowed("");
+    };
+    let mut end = 0;
+    let mut kept = 0;
+    for (i, c) in text.char_indices() {
+        kept += c.width().unwrap_or(0);
+        if kept > room {
+            break;
+        }
+        end = i + c.len_utf8();
+    }
+    Cow::Owned(format!("{}\u{2026}", &text[..end]))
+}
+
+/// Shortens text to at most `width` columns by replacing its start with an
+/// ellipsis, keeping the end which is the most specific part of a path.
+fn truncate_start(text: &str, width: usize) -> Cow<str> {
+    if text.width() <= width {
+        return Cow::Borrowed(text);
+    }
+    let Some(room) = width.checked_sub(1) else {
+        return Cow::Borrowed("");
+    };
+    let mut start = text.len();
+    let mut kept = 0;
+    for (i, c) in text.char_indices().rev() {
+        kept += c.width().unwrap_or(0);
+        if kept > room {
+            break;
+        }
+        start = i;
+    }
+    Cow::Owned(format!("\u{2026}{}", &text[start..]))
+}
+
+/// Draws a scrollbar over the right edge of `area` if the content overflows it.
+fn render_scrollbar(
+    area: Rect,
+    buf: &mut Buffer,
+    content_len: usize,
+    position: usize,
+    accessible: bool,
+) {
+    let viewport = usize::from(area.height);
+    if content_len <= viewport {
+        return;
+    }
+
+    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+        .begin_symbol(None)
+        .end_symbol(None);
+    let scrollbar = if accessible {
+        scrollbar.track_symbol(Some("|")).thumb_symbol("#")
+    } else {
+        scrollbar
+    };
+    StatefulWidget::render(
+        scrollbar,
+        area,
+        buf,
+        &mut ScrollbarState::new(content_len - viewport + 1)
+            .position(position)
+            .viewport_content_length(viewport),
+    );
+}
+
+const MAX_QUERY_ROWS: u16 = 3;
+
+fn query_rows(query: &TextArea, width: u16) -> u16 {
+    // Leave room for the cursor after the last character.
+    let cells = query.lines()[0].chars().count() + 1;
+    u16::try_from(cells.div_ceil(usize::from(width.max(1))))
+        .unwrap_or(u16::MAX)
+        .clamp(1, MAX_QUERY_ROWS)
+}
+
+/// Shows control characters and trailing whitespace as placeholder glyphs. Each
+/// character maps to exactly one glyph so cursor positions carry over.
+fn visible_query(query: &str) -> impl Iterator<Item = char> + '_ {
+    let trailing_whitespace = query.trim_end().len();
+    query.char_indices().map(move |(i, c)| match c {
+        '\0'..='\x1f' => char::from_u32(0x2400 + u32::from(c)).unwrap(),
+        '\x7f' => '\u{2421}',
+        c if i >= trailing_whitespace && c.is_whitespace() => '\u{b7}',
+        c => c,
+    })
+}
+
+fn render_query(query: &TextArea, focused: bool, accessible: bool, area: Rect, buf: &mut Buffer) {
+    if area.is_empty() {
+        return;
+    }
+    let width = usize::from(area.width);
+    let visible = visible_query(&query.lines()[0]).collect::<Vec<_>>();
+    let (_, cursor) = query.cursor();
+
+    // Scroll just enough to keep the cursor in view.
+    let skipped_rows = (cursor / width).saturating_sub(usize::from(area.height) - 1);
+    Paragraph::new(
+        visible
+            .chunks(width)
+            .skip(skipped_rows)
+            .map(|row| Line::raw(row.iter().collect::<String>()))
+            .collect::<Vec<_>>(),
+    )
+    .render(area, buf);
+
+    if focused
+        && let Some(cell) = buf.cell_mut((
+            area.x + u16::try_from(cursor % width).unwrap(),
+            area.y + u16::try_from(cursor / width - skipped_rows).unwrap(),
+        ))
+    {
+        cell.set_style(if accessible {
+            Style::new().fg(Color::Black).bg(Color::Yellow)
+        } else {
+            Style::new().add_modifier(Modifier::REVERSED)
+        });
+    }
+}
+
+/// Marks where the pinned favorites end and the main ring starts.
+fn render_separator(area: Rect, buf: &mut Buffer) {
+    Clear.render(area, buf);
+    Block::new()
+        .borders(Borders::TOP)
+        .title_alignment(Alignment::Center)
+        .title(strings::get(Str::MainRingSeparator))
+        .dim()
+        .render(area, buf);
+}
+
+/// The digit that pastes the entry shown `position` rows into the list, if
+/// any.
+fn quick_select_label(position: usize) -> Span<'static> {
+    const DIGITS: [&str; 9] = ["1 ", "2 ", "3 ", "4 ", "5 ", "6 ", "7 ", "8 ", "9 "];
+    Span::raw(
+        DIGITS
+            .get(position)
+            .copied()
+            .unwrap_or(QUICK_SELECT_PADDING),
+    )
+}
+
+fn error_details(e: &(impl Display + Debug)) -> String {
+    strings::fill(Str::ErrorDetails, &[e, &format_args!("{e:#?}")])
+}
+
+/// Formats an error's whole chain the way fatal errors are printed on exit.
+fn format_error(e: CommandError) -> String {
+    let wrapper = Wrapper::W(e.to_string());
+    let report = format!("{:?}", e.into_report(wrapper));
+
+    // Styling is for terminals, not for reports pasted into issues.
+    let mut plain = String::with_capacity(report.len());
+    let mut rest = &*report;
+    while let Some(escape) = rest.find('\x1b') {
+        plain.push_str(&rest[..escape]);
+        rest = &rest[escape..];
+        rest = &rest[rest.find('m').map_or(rest.len(), |end| end + 1)..];
+    }
+    plain.push_str(rest);
+    plain
+}
+
+/// The error report as it's shown and copied, with the versions involved.
+fn full_error_report(report: &str, server_info: Option<&ServerInfoResponse>) -> String {
+    let server = server_info.map_or_else(
+        || strings::get(Str::ServerVersionUnknown).into(),
+        |ServerInfoResponse { version, git_hash }| {
+            strings::fill(Str::ServerVersion, &[version, git_hash])
+        },
+    );
+    strings::fill(
+        Str::ErrorReport,
+        &[&report, &env!("CARGO_PKG_VERSION"), &server],
+    )
+}
+
+fn search_scope_name(ui: &UiState) -> Cow<'static, str> {
+    let ring = strings::get(match ui.search_scope {
+        None => Str::ScopeAll,
+        Some(RingKind::Main) => Str::ScopeMain,
+        Some(RingKind::Favorites) => Str::ScopeFavorites,
+    });
+    let scope: Cow<'static, str> = match ui.search_mime {
+        None => ring.into(),
+        Some(mime) => strings::fill(
+            Str::ScopeWithMime,
+            &[
+                &ring,
+                &strings::get(match mime {
+                    MimeScope::Text => Str::ScopeText,
+                    MimeScope::Images => Str::ScopeImages,
+                }),
+            ],
+        )
+        .into(),
+    };
+    if ui.favorites_first && ui.search_scope.is_none() {
+        strings::fill(Str::ScopeFavoritesFirst, &[&scope]).into()
+    } else {
+        scope
+    }
+}
+
+/// Whether the error is about what's typed in the search box, like a malformed
+/// pattern, rather than something worth reporting.
+const fn is_query_error(e: &CommandError) -> bool {
+    matches!(e, CommandError::Regex(_) | CommandError::InvalidTag)
+}
+
+const fn query_error(ui: &UiState) -> Option<Str> {
+    match ui.last_error {
+        Some(CommandError::Regex(_)) => Some(Str::InvalidRegex),
+        Some(CommandError::InvalidTag) => Some(Str::InvalidTag),
+        _ => None,
+    }
+}
+
+/// A sed style `s/pattern/replacement/flags` command, where `g` replaces
+/// every match instead of the first and `i` ignores case.
+struct Substitution {
+    regex: Regex,
+    replacement: String,
+    global: bool,
+}
+
+fn parse_substitution(command: &str) -> Result<Substitution, &'static str> {
+    let invalid = strings::get(Str::InvalidSubstitution);
+    let rest = command.strip_prefix("s/").ok_or(invalid)?;
+    let mut parts = Vec::with_capacity(3);
+    let mut part = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('/') => part.push('/'),
+                next => {
+                    part.push('\\');
+                    part.extend(next);
+                }
+            },
+            '/' if parts.len() < 2 => parts.push(mem::take(&mut part)),
+            c => part.push(c),
+        }
+    }
+    parts.push(part);
+    // The closing slash is optional when there are no flags.
+    let [pattern, replacement, flags @ ..] = &mut *parts else {
+        return Err(invalid);
+    };
+
+    let (mut global, mut case_insensitive) = (false, false);
+    for flag in flags.first().map_or("", String::as_str).chars() {
+        match flag {
+            'g' => global = true,
+            'i' => case_insensitive = true,
+            _ => return Err(invalid),
+        }
+    }
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|_| strings::get(Str::InvalidRegex))?;
+    Ok(Substitution {
+        regex,
+        replacement: mem::take(replacement),
+        global,
+    })
+}
+
+/// Applies the typed substitution to the detailed entry's text, ready to be
+/// confirmed.
+fn prepare_replace(ui: &UiState, id: u64) -> Result<PendingReplace, &'static str> {
+    let Substitution {
+        regex,
+        replacement,
+        global,
+    } = parse_substitution(&ui.substitution.lines()[0])?;
+    let Some(Ok(DetailedEntry {
+        full_text: Some(text),
+        ..
+    })) = ui
+        .detailed_entry
+        .as_ref()
+        .filter(|_| ui.details_requested == Some(id))
+    else {
+        return Err(strings::get(Str::Loading));
+    };
+
+    let matches = if global {
+        regex.find_iter(text).count()
+    } else {
+        usize::from(regex.is_match(text))
+    };
+    if matches == 0 {
+        return Err(strings::get(Str::NoMatches));
+    }
+    // A limit of zero replaces every match.
+    let replaced = regex.replacen(text, usize::from(!global), &*replacement);
+    if replaced == **text {
+        return Err(strings::get(Str::ReplaceUnchanged));
+    }
+    Ok(PendingReplace {
+        id,
+        diff: line_diff(text, &replaced),
+        text: replaced.into(),
+        matches,
+    })
+}
+
+/// The lines that differ between `old` and `new`, leaving out the ones they
+/// start and end with in common.
+///
+/// Replacements that keep the number of lines are shown line by line instead.
+fn line_diff(old: &str, new: &str) -> Box<[Line<'static>]> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let (old, new) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let removed = |line| Line::raw(format!("- {line}")).red();
+    let added = |line| Line::raw(format!("+ {line}")).green();
+    if old.len() == new.len() {
+        // The lines were edited in place, so only show the ones that changed.
+        return old
+            .iter()
+            .zip(new)
+            .filter(|(a, b)| a != b)
+            .flat_map(|(a, b)| [removed(a), added(b)])
+            .collect();
+    }
+    old.iter()
+        .map(removed)
+        .chain(new.iter().map(added))
+        .collect()
+}
+
+/// How long ago `time` was in its largest whole unit, e.g. "3m" or "2d".
+fn relative_age(time: SystemTime, now: SystemTime) -> String {
+    const HOUR: u64 = 60;
+    const DAY: u64 = 24 * HOUR;
+    const WEEK: u64 = 7 * DAY;
+    const YEAR: u64 = 365 * DAY;
+
+    // Entries from the future are clock skew, so treat them as brand new.
+    let minutes = now.duration_since(time).unwrap_or_default().as_secs() / 60;
+    let (key, count) = match minutes {
+        0 => return strings::get(Str::AgeNow).to_string(),
+        1..HOUR => (Str::AgeMinutes, minutes),
+        HOUR..DAY => (Str::AgeHours, minutes / HOUR),
+        DAY..WEEK => (Str::AgeDays, minutes / DAY),
+        WEEK..YEAR => (Str::AgeWeeks, minutes / WEEK),
+        _ => (Str::AgeYears, minutes / YEAR),
+    };
+    strings::fill(key, &[&count])
+}
+
+/// Formats `time` as a UTC date and time to the minute, the precision
+/// timestamps are recorded with.
+fn utc_time(time: SystemTime) -> String {
+    let minutes = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 60;
+    let (days, minute) = (minutes / (24 * 60), minutes % (24 * 60));
+
+    // Howard Hinnant's days-to-civil algorithm, with eras starting on March 1st.
+    let days = days + 719_468;
+    let (era, day_of_era) = (days / 146_097, days % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{year}-{month:02}-{day:02} {:02}:{:02}",
+        minute / 60,
+        minute % 60
+    )
+}
+
+/// Splits a row of text into spans, highlighting the parts of it that matched
+/// and the `current` match most of all.
+fn highlighted_row<'a>(
+    text: &'a str,
+    row: Range<usize>,
+    matches: &[Range<usize>],
+    current: Option<usize>,
+) -> Line<'a> {
+    let first = matches.partition_point(|m| m.end <= row.start);
+    let mut spans = Vec::new();
+    let mut pos = row.start;
+    for (i, m) in matches
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take_while(|(_, m)| m.start < row.end)
+    {
+        // Snapping to characters can make neighboring matches overlap.
+        let (start, end) = (m.start.clamp(pos, row.end), m.end.min(row.end));
+        if start >= end {
+            continue;
+        }
+        if pos < start {
+            spans.push(Span::raw(&text[pos..start]));
+        }
+        spans.push(Span::styled(
+            &text[start..end],
+            if current == Some(i) {
+                CURRENT_MATCH_STYLE
+            } else {
+                MATCH_STYLE
+            },
+        ));
+        pos = end;
+    }
+    if pos < row.end {
+        spans.push(Span::raw(&text[pos..row.end]));
+    }
+    Line::from(spans)
+}
+
+/// How search matches stand out, including within the selected row.
+const MATCH_STYLE: Style = Style::new()
+    .add_modifier(Modifier::BOLD)
+    .add_modifier(Modifier::UNDERLINED);
+
+/// How the match n and N went to stands out from the other matches.
+const CURRENT_MATCH_STYLE: Style = MATCH_STYLE.add_modifier(Modifier::REVERSED);
+
+/// How entries marked for bulk changes stand out.
+const MARKED_STYLE: Style = Style::new().fg(Color::Black).bg(Color::Magenta);
+
+/// Builds an entry's row, cutting its text short to fit in `width` columns
+/// along with a note of how much more there is.
+fn ui_entry_line(
+    UiEntry {
+        entry: _,
+        cache,
+        badge,
+        label,
+        len,
+        fingerprint: _,
+        timestamp: _,
+    }: &UiEntry,
+    badges: BadgeStyle,
+    marked: bool,
+    accessible: bool,
+    width: usize,
+) -> Line<'static> {
+    let more = match cache {
+        UiEntryCache::Text { lines, .. } if *lines > 1 => Some(strings::fill(
+            if accessible {
+                Str::LineCountAccessible
+            } else {
+                Str::LineCount
+            },
+            &[lines],
+        )),
+        UiEntryCache::Text { one_liner, .. } if one_liner.ends_with('…') => {
+            Some(strings::fill(Str::TextSize, &[&format_size(*len)]))
+        }
+        _ => None,
+    };
+    let highlight = match cache {
+        UiEntryCache::Text { highlight, .. } => *highlight,
+        _ => None,
+    };
+    let swatch = match highlight {
+        Some(TextKind::Color([r, g, b])) if !accessible => {
+            Some(Span::styled("■ ", Style::new().fg(Color::Rgb(r, g, b))))
+        }
+        _ => None,
+    };
+    let room = width
+        .saturating_sub(if badges == BadgeStyle::Off {
+            0
+        } else {
+            badges.glyph(*badge).width()
+        })
+        .saturating_sub(label.as_ref().map_or(0, |label| label.width() + 1))
+        .saturating_sub(more.as_ref().map_or(0, |more| more.width()))
+        .saturating_sub(swatch.as_ref().map_or(0, Span::width));
+
+    let preview = match cache {
+        UiEntryCache::Text {
+            one_liner,
+            matched: Some(matched),
+            ..
+        } => {
+            let shown = truncate_end(one_liner, room);
+            let kept = if shown.len() == one_liner.len() {
+                shown.len()
+            } else {
+                shown.len().saturating_sub('…'.len_utf8())
+            };
+            let (start, end) = (matched.start.min(kept), matched.end.min(kept));
+            vec![
+                Span::raw(shown[..start].to_string()),
+                Span::styled(shown[start..end].to_string(), MATCH_STYLE),
+                Span::raw(shown[end..].to_string()),
+            ]
+        }
+        UiEntryCache::Text { one_liner, .. } => {
+            vec![Span::raw(truncate_end(one_liner, room).into_owned())]
+        }
+        UiEntryCache::Image if *badge == EntryBadge::ImageText => {
+            vec![Span::raw(strings::get(Str::ImageTextPreview)).italic()]
+        }
+        UiEntryCache::Image => vec![Span::raw(strings::get(Str::ImagePreview)).italic()],
+        UiEntryCache::Binary { mime_type } => {
+            let format = strings::fill(Str::UnsupportedFormat, &[&format_args!("{mime_type:?}")]);
+            vec![Span::raw(format).italic()]
+        }
+        UiEntryCache::Sensitive => vec![Span::raw(strings::get(Str::SensitivePreview)).italic()],
+        UiEntryCache::Error(e) => vec![Span::raw(error_details(e)).italic()],
+    };
+    let preview = match highlight {
+        Some(TextKind::Url) => preview.into_iter().map(Span::underlined).collect(),
+        Some(TextKind::Path { exists: false }) => preview.into_iter().map(Span::dim).collect(),
+        _ => preview,
+    };
+    let mut spans = Vec::with_capacity(preview.len() + 4);
+    if badges != BadgeStyle::Off {
+        spans.push(Span::raw(badges.glyph(*badge)).dim());
+    }
+    if let Some(label) = label {
+        spans.push(Span::raw(format!("{label} ")).bold().cyan());
+        spans.extend(swatch);
+        spans.extend(preview.into_iter().map(Span::dim));
+    } else {
+        spans.extend(swatch);
+        spans.extend(preview);
+    }
+    if let Some(more) = more {
+        spans.push(Span::raw(more).dim());
+    }
+    let line = Line::from(spans);
+    if marked {
+        line.style(MARKED_STYLE)
+    } else {
+        line
+    }
+}
+
+impl AppWrapper<'_> {
+    /// Renders a pane of entries, all of them outside split mode.
+    fn render_entries(&mut self, area: Rect, buf: &mut Buffer, pane: Option<RingKind>) {
+        let Self {
+            state: State { entries, ui },
+            requests,
+            badges,
+            accessible,
+            favorites_only,
+            runtime: _,
+            font_size: _,
+        } = self;
+        let focused = pane.is_none() || pane == ui.split;
+
+        let [search_area, entries_area] = Layout::vertical([
+            Constraint::Length(if ui.search_state.is_some() {
+                2 + query_rows(&ui.query, area.width.saturating_sub(2))
+            } else if ui.filter_state.is_some()
+                || ui.labeling.is_some()
+                || ui.tagging.is_some()
+                || ui.jumping
+                || ui.finding
+                || ui.substituting.is_some()
+            {
+                3
+            } else {
+                0
+            }),
+            Constraint::Min(0),
+        ])
+        .areas(area);
+
+        // The other pane leaves the inputs' room empty to stay lined up.
+        if focused {
+            if ui.labeling.is_some() {
+                ui.label.set_block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::new().bold())
+                        .title(strings::get(Str::Label)),
+                );
+                ui.label.render(search_area, buf);
+            } else if ui.tagging.is_some() {
+                ui.tag.set_block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::new().bold())
+                        .title(strings::get(Str::Tag)),
+                );
+                ui.tag.render(search_area, buf);
+            } else if ui.jumping {
+                ui.jump.set_block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::new().bold())
+                        .title(strings::get(Str::JumpToEntry)),
+                );
+                ui.jump.render(search_area, buf);
+            } else if ui.finding {
+                ui.detail_query.set_block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::new().bold())
+                        .title(strings::get(Str::FindInDetails)),
+                );
+                ui.detail_query.render(search_area, buf);
+            } else if ui.substituting.is_some() {
+                ui.substitution.set_block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(if ui.substitution_error.is_some() {
+                            Style::new().bold().red()
+                        } else {
+                            Style::new().bold()
+                        })
+                        .title(
+                            ui.substitution_error
+                                .unwrap_or(strings::get(Str::Substitute)),
+                        ),
+                );
+                ui.substitution.render(search_area, buf);
+            } else if let &Some(SearchState { focused, kind }) = &ui.search_state {
+                let query_error = query_error(ui);
+                let block = Block::default()
+                    .borders(Borders::ALL)
+                    .border_style({
+                        let style = if focused {
+                            Style::new().bold()
+                        } else {
+                            Style::default()
+                        };
+                        if kind == SearchKind::Regex {
+                            style.magenta()
+                        } else {
+                            style
+                        }
+                    })
+                    .title(Line::raw(if ui.queued_searches > 0 {
+                        strings::ellipsis(strings::get(Str::Searching), *accessible)
+                    } else if let Some(error) = query_error {
+                        strings::get(error).into()
+                    } else {
+                        strings::fill(
+                            Str::ScopedSearch,
+                            &[
+                                &strings::get(match kind {
+                                    SearchKind::Plain if ui.match_case => Str::CaseSensitiveSearch,
+                                    SearchKind::Plain => Str::Search,
+                                    SearchKind::Regex => Str::RegexSearch,
+                                    SearchKind::Mime => Str::MimeSearch,
+                                    SearchKind::Tag => Str::TagSearch,
+                                }),
+                                &search_scope_name(ui),
+                            ],
+                        )
+                        .into()
+                    }));
+                let query_area = block.inner(search_area);
+                block.render(search_area, buf);
+                render_query(&ui.query, focused, *accessible, query_area, buf);
+            } else if let Some(FilterState { focused }) = ui.filter_state {
+                ui.filter.set_block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(if focused {
+                            Style::new().bold()
+                        } else {
+                            Style::default()
+                        })
+                        .title(strings::get(Str::Filter)),
+                );
+                ui.filter.render(search_area, buf);
+            }
+        }
+
+        let outer_block = Block::new()
+            .title_alignment(Alignment::Center)
+            .borders(Borders::TOP)
+            .title(strings::get(
+                if *favorites_only || pane == Some(RingKind::Favorites) {
+                    Str::Favorites
+                } else {
+                    Str::Entries
+                },
+            ))
+            .title_style(if pane.is_some() && focused {
+                Style::new().bold()
+            } else {
+                Style::default()
+            });
+        let inner_block = Block::new().borders(Borders::NONE);
+        let inner_area = outer_block.inner(entries_area);
+        if focused {
+            ui.page_height = inner_area.height;
+        }
+
+        outer_block.render(entries_area, buf);
+
+        if ui.startup != Startup::Loaded {
+            Line::raw(strings::ellipsis(
+                strings::get(if ui.startup == Startup::Opening {
+                    Str::OpeningDatabase
+                } else {
+                    Str::Loading
+                }),
+                *accessible,
+            ))
+            .italic()
+            .render(inner_area, buf);
+        } else if *favorites_only && entries.loaded_entries.is_empty() {
+            Paragraph::new(strings::get(Str::NoFavorites))
+                .italic()
+                .wrap(Wrap { trim: true })
+                .render(inner_area, buf);
+        } else if active_entries!(entries, ui, pane).is_empty() {
+            Line::raw(strings::ellipsis(
+                strings::get(Str::NothingToSeeHere),
+                *accessible,
+            ))
+            .italic()
+            .render(inner_area, buf);
+        } else {
+            let len = active_entries!(entries, ui, pane).len();
+            let [list_area, scrollbar_area] = Layout::horizontal([
+                Constraint::Fill(1),
+                Constraint::Length(u16::from(len > usize::from(inner_area.height))),
+            ])
+            .areas(inner_area);
+
+            // Leave room for the ages drawn over the end of each row.
+            let width = if list_area.width >= MIN_AGES_WIDTH {
+                list_area.width.saturating_sub(MAX_AGE_WIDTH)
+            } else {
+                list_area.width
+            };
+            let key = RowsKey {
+                generation: entries.generation,
+                width,
+                searching: !ui.query.is_empty(),
+                pane,
+                reorder: entries.reorder.map(|Reorder { from, to, .. }| (from, to)),
+            };
+            if entries.pane_rows(pane).key != Some(key) {
+                let separator_after = active_entries!(entries, ui, pane).last_pinned_favorite();
+                let list = List::new(active_entries!(entries, ui, pane).iter().enumerate().map(
+                    |(i, entry)| {
+                        let mut prefix = Vec::new();
+                        // The digits depend on scrolling, so they're drawn over this.
+                        if !ui.disable_quick_select {
+                            prefix.push(Span::raw(QUICK_SELECT_PADDING));
+                        }
+                        if let Some(run) = collapsed_run(entries, ui, entry.entry.id()) {
+                            prefix.push(
+                                strings::fill(
+                                    if *accessible {
+                                        Str::CollapsedRunAccessible
+                                    } else {
+                                        Str::CollapsedRun
+                                    },
+                                    &[&run.len()],
+                                )
+                                .bold(),
+                            );
+                        }
+                        if entries.on_clipboard == Some(entry.entry.id()) {
+                            prefix.push(strings::get(Str::OnClipboardMarker).bold());
+                        }
+
+                        let prefix_width = prefix.iter().map(Span::width).sum::<usize>();
+                        let mut line = ui_entry_line(
+                            entry,
+                            *badges,
+                            entries.marked.contains(&entry.entry.id()),
+                            *accessible,
+                            usize::from(width).saturating_sub(prefix_width),
+                        );
+                        line.spans.splice(0..0, prefix);
+                        // Leave a row for the separator, which is drawn once the list is so
+                        // it isn't highlighted along with the entry.
+                        if separator_after == Some(i) {
+                            ListItem::new(vec![line, Line::default()])
+                        } else {
+                            ListItem::new(line)
+                        }
+                    },
+                ));
+                *entries.pane_rows(pane) = RowsCache {
+                    key: Some(key),
+                    list,
+                    separator_after,
+                };
+            }
+
+            let list = mem::take(&mut entries.pane_rows(pane).list)
+                .block(inner_block)
+                .highlight_style(if !focused {
+                    Style::default()
+                } else if entries.reorder.is_some() && ui.query.is_empty() {
+                    // Lift the entry being moved off the page.
+                    if *accessible {
+                        Style::new().fg(Color::Black).bg(Color::Cyan).bold()
+                    } else {
+                        Style::new().cyan().bold().italic().reversed()
+                    }
+                } else if *accessible {
+                    Style::new().fg(Color::Black).bg(Color::Yellow).bold()
+                } else {
+                    Style::default()
+                        .add_modifier(Modifier::BOLD)
+                        .add_modifier(Modifier::REVERSED)
+                })
+                .highlight_spacing(HighlightSpacing::Always);
+            StatefulWidget::render(&list, list_area, buf, active_list_state!(entries, ui, pane));
+            entries.pane_rows(pane).list = list;
+            let offset = active_list_state!(entries, ui, pane).offset();
+            // Ages and thumbnails change without the rows doing so, so they're drawn
+            // over the cached rows rather than being part of them.
+            let now = SystemTime::now();
+            let mut images = Vec::new();
+            let separator_after = entries.pane_rows(pane).separator_after;
+            let mut rows = list_area.rows();
+            for (i, entry) in active_entries!(entries, ui, pane)
+                .iter()
+                .enumerate()
+                .skip(offset)
+            {
+                let Some(Rect { y, .. }) = rows.next() else {
+                    break;
+                };
+                if separator_after == Some(i)
+                    && let Some(row) = rows.next()
+                {
+                    render_separator(row, buf);
+                }
+                // A gutter rather than part of the entry, so it's never highlighted.
+                if !ui.disable_quick_select {
+                    quick_select_label(i - offset)
+                        .style(Style::reset().dim())
+                        .render(Rect::new(list_area.x, y, list_area.width, 1), buf);
+                }
+                let mut right = list_area.right();
+                if let Some(timestamp) = entry.timestamp
+                    && list_area.width >= MIN_AGES_WIDTH
+                {
+                    let age = Span::raw(format!(" {}", relative_age(timestamp, now))).dim();
+                    let width = u16::try_from(age.width()).unwrap();
+                    right = right.saturating_sub(width);
+                    age.render(Rect::new(right, y, width, 1), buf);
+                }
+                if matches!(entry.cache, UiEntryCache::Image) && !*accessible {
+                    let x = right.saturating_sub(THUMBNAIL_WIDTH + 1).max(list_area.x);
+                    images.push((
+                        entry.entry.id(),
+                        entry.fingerprint,
+                        Rect::new(x, y, THUMBNAIL_WIDTH, 1),
+                    ));
+                }
+            }
+            for (id, fingerprint, area) in images {
+                match ui.thumbnails.get(id, fingerprint) {
+                    Some(Thumbnail::Loaded(image)) => Image::new(&**image).render(area, buf),
+                    Some(Thumbnail::Requested | Thumbnail::Failed) => {}
+                    None => {
+                        ui.thumbnails.insert(id, fingerprint, Thumbnail::Requested);
+                        let _ = requests.send(Command::LoadThumbnail(id));
+                    }
+                }
+            }
+            render_scrollbar(scrollbar_area, buf, len, offset, *accessible);
+        }
+    }
+
+    fn render_selected_entry(&mut self, area: Rect, buf: &mut Buffer) {
+        let Self {
+            state: State { entries, ui },
+            requests,
+            badges: _,
+            accessible,
+            favorites_only: _,
+            runtime: _,
+            font_size,
+        } = self;
+        if area.is_empty() {
+            return;
+        }
+        let Some(UiEntry {
+            entry, cache, len, ..
+        }) = selected_entry!(entries, ui)
+        else {
+            return;
+        };
+
+        let outer_block = {
+            let details = ui.detailed_entry.as_ref().and_then(|r| r.as_ref().ok());
+            let mime_type = details.map_or("", |d| &*d.mime_type);
+            let added_by = details.and_then(|d| d.added_by);
+            let tags = details.map(|d| d.tags).unwrap_or_default();
+            let timestamp = details.and_then(|d| d.timestamp);
+            let corrupted = details.is_some_and(|d| d.verification == Verification::Mismatch);
+            let truncated = details.is_some_and(|d| d.truncated);
+            let image = details.and_then(|d| d.image.as_ref());
+
+            Block::new()
+                .borders(Borders::TOP)
+                .title_alignment(Alignment::Center)
+                .title_style(if corrupted {
+                    Style::new().red()
+                } else {
+                    Style::new()
+                })
+                .title({
+                    let favorite = entry.ring() == RingKind::Favorites;
+                    if mime_type.is_empty() {
+                        strings::fill_into(
+                            &mut ui.cache,
+                            if favorite {
+                                Str::FavoriteTitle
+                            } else {
+                                Str::EntryTitle
+                            },
+                            &[&format_id(entry.id())],
+                        );
+                    } else {
+                        strings::fill_into(
+                            &mut ui.cache,
+                            if favorite {
+                                Str::FavoriteTitleWithMime
+                            } else {
+                                Str::EntryTitleWithMime
+                            },
+                            &[&format_id(entry.id()), &mime_type],
+                        );
+                    }
+                    if let Some(&ImageMetadata {
+                        width, height, len, ..
+                    }) = image
+                    {
+                        ui.cache = strings::fill(
+                            if *accessible {
+                                Str::ImageTitleAccessible
+                            } else {
+                                Str::ImageTitle
+                            },
+                            &[&ui.cache, &width, &height, &format_size(len)],
+                        );
+                    }
+                    if let Some(added_by) = added_by {
+                        ui.cache = strings::fill(Str::AddedByTitle, &[&ui.cache, &added_by]);
+                    }
+                    if !tags.is_empty() {
+                        let tags = tags.iter().collect::<Vec<_>>().join(", ");
+                        ui.cache = strings::fill(Str::TagsTitle, &[&ui.cache, &tags]);
+                    }
+                    if let Some(timestamp) = timestamp {
+                        ui.cache =
+                            strings::fill(Str::CopiedAtTitle, &[&ui.cache, &utc_time(timestamp)]);
+                    }
+                    if entries.on_clipboard == Some(entry.id()) {
+                        ui.cache = strings::fill(Str::OnClipboardTitle, &[&ui.cache]);
+                    }
+                    if truncated {
+                        ui.cache =
+                            strings::fill(Str::TruncatedTitle, &[&ui.cache, &format_size(*len)]);
+                    }
+                    if corrupted {
+                        ui.cache = strings::fill(Str::ChecksumMismatchTitle, &[&ui.cache]);
+                    }
+                    if ui.detail_column > 0 {
+                        ui.cache =
+                            strings::fill(Str::ColumnTitle, &[&ui.cache, &(ui.detail_column + 1)]);
+                    }
+                    ui.cache.as_str()
+                })
+        };
+        let inner_block = Block::new()
+            .borders(Borders::NONE)
+            .padding(Padding::horizontal(1));
+        let inner_area = outer_block.inner(area);
+
+        outer_block.render(area, buf);
+
+        let error = ui
+            .detailed_entry
+            .as_ref()
+            .and_then(|r| r.as_ref().err())
+            .map_or(String::new(), error_details);
+
+        if ui.editing == Some(entry.id()) {
+            ui.editor
+                .set_block(inner_block.title(strings::get(Str::Editing)));
+            ui.editor.render(inner_area, buf);
+        } else if matches!(cache, UiEntryCache::Image) && *accessible {
+            Paragraph::new(strings::get(Str::ImagePreviewsDisabled))
+                .block(inner_block)
+                .render(inner_area, buf);
+        } else if matches!(cache, UiEntryCache::Image) {
+            let mut image_area = inner_area;
+            if ui.show_image_metadata
+                && let Some(ImageState::Loaded(_, metadata)) = &ui.detail_image_state
+            {
+                let lines = image_metadata_lines(metadata, *accessible);
+                let [image, strip] = Layout::vertical([
+                    Constraint::Min(0),
+                    Constraint::Length(u16::try_from(lines.len()).unwrap() + 1),
+                ])
+                .areas(inner_area);
+                Paragraph::new(lines)
+                    .block(
+                        Block::new()
+                            .borders(Borders::TOP)
+                            .padding(Padding::horizontal(1)),
+                    )
+                    .render(strip, buf);
+                image_area = image;
+            }
+
+            match &mut ui.detail_image_state {
+                Some(ImageState::Loaded(image_state, _)) => {
+                    StatefulImage::new(None).render(image_area, buf, image_state);
+                }
+                Some(ImageState::Failed(e)) => {
+                    let error = error_details(e);
+                    let room = inner_block.inner(inner_area).width;
+                    let paragraph = Paragraph::new(&*error).block(inner_block);
+                    if ui.unwrapped_details {
+                        let lines = error.lines().map(|line| line.width());
+                        ui.detail_column = ui.detail_column.min(max_column(lines, room));
+                        paragraph.scroll((0, ui.detail_column))
+                    } else {
+                        paragraph.wrap(Wrap { trim: false })
+                    }
+                    .render(inner_area, buf);
+                }
+                Some(ImageState::Requested {
+                    progress: Some(pct),
+                    ..
+                }) => {
+                    Paragraph::new(strings::ellipsis(
+                        &strings::fill(Str::LoadingProgress, &[pct]),
+                        *accessible,
+                    ))
+                    .block(inner_block)
+                    .render(inner_area, buf);
+                }
+                Some(ImageState::Requested { progress: None, .. }) | None => {
+                    Paragraph::new(strings::ellipsis(strings::get(Str::Loading), *accessible))
+                        .block(inner_block)
+                        .render(inner_area, buf);
+                }
+            }
+            if ui.detail_image_state.is_none() {
+                ui.detail_image_state = Some(ImageState::Requested {
+                    id: entry.id(),
+                    token: None,
+                    progress: None,
+                });
+                let _ = requests.send(Command::LoadImage {
+                    id: entry.id(),
+                    max_size: Some(max_image_size(buf.area, *font_size)),
+                });
+            }
+        } else {
+            let text_area = inner_block.inner(inner_area);
+            let loading = strings::ellipsis(strings::get(Str::Loading), *accessible);
+            let preview;
+            let text = match &ui.detailed_entry {
+                None => &*loading,
+                Some(Ok(DetailedEntry {
+                    full_text: Some(text),
+                    ..
+                })) => {
+                    if ui.pretty_json
+                        && let UiEntryCache::Text {
+                            highlight: Some(TextKind::Json),
+                            ..
+                        } = cache
+                        && let Some(pretty) = pretty_json(text)
+                    {
+                        preview = pretty;
+                        &*preview
+                    } else {
+                        &**text
+                    }
+                }
+                Some(Ok(DetailedEntry {
+                    preview: Some(bytes),
+                    ..
+                })) => {
+                    preview = binary_preview(bytes, *len, ui.binary_as_text, text_area.width);
+                    &preview
+                }
+                Some(Ok(_)) if matches!(cache, UiEntryCache::Sensitive) => {
+                    let key = ui.keymap.key_name(AppAction::Reveal, *accessible);
+                    preview = strings::fill(Str::SensitiveHidden, &[&key.unwrap_or_default()]);
+                    &preview
+                }
+                Some(Ok(_)) => strings::get(Str::BinaryData),
+                Some(Err(_)) => &error,
+            };
+            if ui.detailed_entry.is_none() {
+                ui.detail_rows = None;
+            }
+            if ui
+                .detail_rows
+                .as_ref()
+                .is_none_or(|&(width, _)| width != text_area.width)
+            {
+                let rows = if ui.unwrapped_details {
+                    text.lines().collect()
+                } else {
+                    wrapped_lines(text, text_area.width)
+                };
+                let rows: Box<[_]> = rows
+                    .into_iter()
+                    .map(|row| {
+                        let start = row.as_ptr().addr() - text.as_ptr().addr();
+                        start..start + row.len()
+                    })
+                    .collect();
+                ui.detail_matches = match (&ui.search_state, &ui.detailed_entry) {
+                    (_, &Some(Ok(_))) if !ui.detail_query.is_empty() => search_matches(
+                        &ui.detail_query.lines()[0],
+                        SearchKind::Plain,
+                        ui.match_case,
+                        text,
+                    )
+                    .into(),
+                    (&Some(SearchState { kind, .. }), &Some(Ok(_))) if !ui.query.is_empty() => {
+                        search_matches(&ui.query.lines()[0], kind, ui.match_case, text).into()
+                    }
+                    _ => Box::default(),
+                };
+                ui.current_match = ui.current_match.filter(|&i| i < ui.detail_matches.len());
+                ui.detail_rows = Some((text_area.width, rows));
+            }
+            let (_, lines) = ui.detail_rows.as_ref().unwrap();
+            if mem::take(&mut ui.scroll_to_match)
+                && let Some(target) = ui.detail_matches.get(ui.current_match.unwrap_or(0))
+            {
+                let row = lines.partition_point(|row| row.end <= target.start);
+                ui.detail_scroll = u16::try_from(row).unwrap_or(u16::MAX);
+                // Unwrapped rows can hide the match off to the side instead.
+                if ui.unwrapped_details
+                    && let Some(row) = lines.get(row)
+                {
+                    let column = text
+                        .get(row.start..target.start)
+                        .map_or(0, UnicodeWidthStr::width);
+                    let column = u16::try_from(column).unwrap_or(u16::MAX);
+                    if column < ui.detail_column
+                        || column >= ui.detail_column.saturating_add(text_area.width)
+                    {
+                        ui.detail_column = column.saturating_sub(text_area.width / 2);
+                    }
+                }
+            }
+            let scroll = if ui.detailed_entry.is_some() {
+                // Restored offsets may point past the end of content that has since
+                // shrunk, so keep the last page in view.
+                let max_scroll = lines.len().saturating_sub(usize::from(text_area.height));
+                ui.detail_scroll = ui
+                    .detail_scroll
+                    .min(u16::try_from(max_scroll).unwrap_or(u16::MAX));
+                usize::from(ui.detail_scroll)
+            } else {
+                0
+            };
+            let visible = lines
+                .iter()
+                .skip(scroll)
+                .take(usize::from(text_area.height));
+            // Only scroll as far as the longest line in view needs to be seen in full.
+            ui.detail_column = if ui.unwrapped_details {
+                let widths = visible.clone().map(|row| text[row.clone()].width());
+                ui.detail_column.min(max_column(widths, text_area.width))
+            } else {
+                0
+            };
+
+            Paragraph::new(
+                visible
+                    .map(|row| {
+                        highlighted_row(text, row.clone(), &ui.detail_matches, ui.current_match)
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .block(inner_block)
+            .scroll((0, ui.detail_column))
+            .render(inner_area, buf);
+            // The scrollbar sits in the block's right padding.
+            render_scrollbar(inner_area, buf, lines.len(), scroll, *accessible);
+        }
+    }
+
+    fn render_actions_menu(&self, area: Rect, buf: &mut Buffer) {
+        let State { entries, ui } = &self.state;
+        let Some(selected) = ui.actions_menu else {
+            return;
+        };
+        let Some(entry) = selected_entry!(entries, ui) else {
+            return;
+        };
+
+        let rows = ENTRY_ACTIONS
+            .iter()
+            .map(|action| {
+                let key = ui.keymap.key_name(action.action, self.accessible);
+                let line = Line::from(vec![
+                    Span::raw(format!("{:>5} ", key.unwrap_or_default())).bold(),
+                    Span::raw(strings::get(action.name)),
+                ]);
+                if (action.applies)(entry) {
+                    line
+                } else {
+                    line.dim()
+                }
+            })
+            .collect::<Vec<_>>();
+        let width = rows.iter().map(Line::width).max().unwrap_or(0) + 2;
+        let popup = centered_popup(area, width, rows.len() + 2);
+
+        Clear.render(popup, buf);
+        StatefulWidget::render(
+            List::new(rows)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::new().bold())
+                        .title(strings::get(Str::Actions)),
+                )
+                .highlight_style(if self.accessible {
+                    Style::new().fg(Color::Black).bg(Color::Yellow).bold()
+                } else {
+                    Style::default()
+                        .add_modifier(Modifier::BOLD)
+                        .add_modifier(Modifier::REVERSED)
+                }),
+            popup,
+            buf,
+            &mut ListState::default().with_selected(Some(selected)),
+        );
+    }
+
+    fn render_paste_confirmation(&self, area: Rect, buf: &mut Buffer) {
+        let Some(PendingPaste { len, mime_type, .. }) = &self.state.ui.pending_paste else {
+            return;
+        };
+
+        render_text_popup(
+            area,
+            buf,
+            strings::get(Str::LargePaste),
+            &strings::fill(Str::ConfirmLargePaste, &[&format_size(*len), mime_type]),
+        );
+    }
+
+    fn render_replace_preview(&self, area: Rect, buf: &mut Buffer) {
+        let Some(PendingReplace { matches, diff, .. }) = &self.state.ui.pending_replace else {
+            return;
+        };
+
+        let title = strings::fill(Str::ReplacePreview, &[matches]);
+        let width = diff
+            .iter()
+            .map(Line::width)
+            .chain([Line::raw(&*title).width()])
+            .max()
+            .unwrap_or(0)
+            + 2;
+        let popup = centered_popup(area, width, diff.len() + 2);
+
+        Clear.render(popup, buf);
+        Paragraph::new(diff.to_vec())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::new().bold())
+                    .title(title),
+            )
+            .render(popup, buf);
+    }
+
+    fn render_tutorial(&self, area: Rect, buf: &mut Buffer) {
+        let ui = &self.state.ui;
+        let Some(page) = ui.tutorial else {
+            return;
+        };
+        if ui.last_error.is_some() {
+            return;
+        }
+
+        render_text_popup(
+            area,
+            buf,
+            &strings::fill(Str::TutorialTitle, &[&(page + 1), &TUTORIAL_PAGES.len()]),
+            &strings::fill(
+                Str::TutorialPage,
+                &[
+                    &strings::get(TUTORIAL_PAGES[page]),
+                    &strings::get(Str::TutorialKeys),
+                ],
+            ),
+        );
+    }
+
+    fn render_server_stats(&self, area: Rect, buf: &mut Buffer) {
+        let Some(stats) = &self.state.ui.server_stats else {
+            return;
+        };
+        let block = Block::new()
+            .borders(Borders::TOP)
+            .title_alignment(Alignment::Center)
+            .title(strings::get(Str::ServerStatsTitle))
+            .padding(Padding::horizontal(1));
+        let inner_area = block.inner(area);
+        block.render(area, buf);
+        let Some(stats) = stats else {
+            Line::raw(strings::ellipsis(
+                strings::get(Str::Loading),
+                self.accessible,
+            ))
+            .italic()
+            .render(inner_area, buf);
+            return;
+        };
+        let &StatsResponse {
+            ref buckets,
+            favorites,
+            max_favorites,
+            main_len,
+            main_capacity,
+            direct: DirectUsage { files, bytes },
+            packed,
+            started_at,
+            connected_clients,
+            total_connections,
+            ..
+        } = &**stats;
+
+        let started_at = UNIX_EPOCH + Duration::from_secs(started_at);
+        let mut summary = vec![
+            (
+                Str::ServerStatsMain,
+                strings::fill(Str::ServerStatsMainValue, &[&main_len, &main_capacity]),
+            ),
+            (
+                Str::ServerStatsFavorites,
+                strings::fill(
+                    Str::ServerStatsFavoritesValue,
+                    &[&favorites, &max_favorites],
+                ),
+            ),
+            (
+                Str::ServerStatsDirect,
+                strings::fill(Str::ServerStatsDirectValue, &[&files, &format_size(bytes)]),
+            ),
+        ];
+        summary.extend(packed.map(
+            |PackedUsage {
+                 entries,
+                 live_bytes,
+                 allocated_bytes,
+                 ..
+             }| {
+                (
+                    Str::ServerStatsPacked,
+                    strings::fill(
+                        Str::ServerStatsPackedValue,
+                        &[
+                            &entries,
+                            &format_size(live_bytes),
+                            &format_size(allocated_bytes),
+                        ],
+                    ),
+                )
+            },
+        ));
+        summary.extend([
+            (
+                Str::ServerStatsUptime,
+                strings::fill(
+                    Str::ServerStatsUptimeValue,
+                    &[
+                        &relative_age(started_at, SystemTime::now()),
+                        &utc_time(started_at),
+                    ],
+                ),
+            ),
+            (
+                Str::ServerStatsClients,
+                strings::fill(
+                    Str::ServerStatsClientsValue,
+                    &[&connected_clients, &total_connections],
+                ),
+            ),
+        ]);
+        let [summary_area, _padding, buckets_area] = Layout::vertical([
+            Constraint::Length(u16::try_from(summary.len()).unwrap()),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ])
+        .areas(inner_area);
+
+        let label_width = summary
+            .iter()
+            .map(|&(label, _)| strings::get(label).width())
+            .max()
+            .unwrap_or_default();
+        let summary = Table::new(
+            summary.into_iter().map(|(label, value)| {
+                Row::new([Line::raw(strings::get(label)).bold(), Line::raw(value)])
+            }),
+            [
+                Constraint::Length(u16::try_from(label_width).unwrap_or(u16::MAX)),
+                Constraint::Min(0),
+            ],
+        )
+        .column_spacing(2);
+        Widget::render(summary, summary_area, buf);
+
+        let rows = buckets.iter().enumerate().map(
+            |(
+                bucket,
+                &BucketUsage {
+                    used_bytes,
+                    allocated_bytes,
+                    slots,
+                    free_slots,
+                },
+            )| {
+                Row::new([
+                    format_size(bucket_to_length(bucket).into()),
+                    slots.to_string(),
+                    free_slots.to_string(),
+                    format_size(used_bytes),
+                    format_size(allocated_bytes),
+                ])
+            },
+        );
+        let buckets = Table::new(rows, [Constraint::Ratio(1, 5); 5]).header(
+            Row::new(
+                [
+                    Str::ServerStatsBucket,
+                    Str::ServerStatsSlots,
+                    Str::ServerStatsFree,
+                    Str::ServerStatsUsed,
+                    Str::ServerStatsOnDisk,
+                ]
+                .map(strings::get),
+            )
+            .bold(),
+        );
+        Widget::render(buckets, buckets_area, buf);
+    }
+
+    fn render_dashboard(&self, area: Rect, buf: &mut Buffer) {
+        let State { entries, ui } = &self.state;
+        let Some(DashboardView {
+            stats, selected, ..
+        }) = &ui.dashboard
+        else {
+            return;
+        };
+
+        let popup = centered_popup(
+            area,
+            usize::from(area.width.saturating_sub(4)),
+            usize::from(area.height.saturating_sub(2)),
+        );
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::new().bold())
+            .title(strings::get(Str::DashboardTitle));
+        let inner_area = block.inner(popup);
+        Clear.render(popup, buf);
+        block.render(popup, buf);
+        let Some(stats) = stats else {
+            Line::raw(strings::get(Str::Loading))
+                .italic()
+                .render(inner_area, buf);
+            return;
+        };
+        let &Dashboard {
+            ref adds_per_day,
+            ref mime_types,
+            ref largest,
+            duplicates,
+            scanned,
+            total,
+        } = &**stats;
+
+        let [summary_area, adds_area, tables_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(if self.accessible { 2 } else { 5 }),
+            // The chart shrinks first on short terminals.
+            Constraint::Min(3),
+        ])
+        .areas(inner_area);
+
+        let duplicate_pct = (u64::from(duplicates) * 100)
+            .checked_div(u64::from(scanned))
+            .unwrap_or(0);
+        let mut summary = strings::fill(Str::DashboardSummary, &[&total, &duplicate_pct]);
+        if scanned < total {
+            let scanned_pct = u64::from(scanned) * 100 / u64::from(total);
+            summary.push_str(&strings::fill(Str::DashboardScanning, &[&scanned_pct]));
+        }
+        Line::raw(summary).bold().render(summary_area, buf);
+
+        let adds_title = strings::fill(Str::DashboardAdds, &[&DASHBOARD_DAYS]);
+        if self.accessible {
+            let counts = adds_per_day
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            Paragraph::new(vec![
+                Line::raw(adds_title).bold(),
+                Line::raw(strings::fill(Str::DashboardAddsAccessible, &[&counts])),
+            ])
+            .render(adds_area, buf);
+        } else {
+            // Oldest first so time runs left to right.
+            let data = adds_per_day
+                .iter()
+                .rev()
+                .map(|&adds| u64::from(adds))
+                .collect::<Vec<_>>();
+            Sparkline::default()
+                .block(Block::default().title(Line::raw(adds_title).bold()))
+                .data(&data)
+                .style(Style::new().cyan())
+                .render(adds_area, buf);
+        }
+
+        let [mime_area, _padding, largest_area] = Layout::horizontal([
+            Constraint::Percentage(40),
+            Constraint::Length(1),
+            Constraint::Percentage(60),
+        ])
+        .areas(tables_area);
+
+        let mime_rows = mime_types.iter().map(
+            |MimeTypeStats {
+                 mime_type,
+                 count,
+                 bytes,
+             }| {
+                let mime_type = if mime_type.is_empty() {
+                    strings::get(Str::DashboardNoMimeType)
+                } else {
+                    mime_type
+                };
+                let size = format_size(*bytes);
+                let row = strings::fill(Str::DashboardMimeType, &[&mime_type, count, &size]);
+                Line::raw(row)
+            },
+        );
+        Paragraph::new(
+            once(Line::raw(strings::get(Str::DashboardMimeTypes)).bold())
+                .chain(mime_rows)
+                .collect::<Vec<_>>(),
+        )
+        .render(mime_area, buf);
+
+        let [largest_title_area, largest_list_area] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(largest_area);
+        Line::raw(strings::get(Str::DashboardLargest))
+            .bold()
+            .render(largest_title_area, buf);
+        let rows = largest.iter().map(|&LargeEntry { id, len }| {
+            let mut line = entries
+                .loaded_entries
+                .iter()
+                .find(|e| e.entry.id() == id)
+                .map_or_else(
+                    || Line::raw(strings::get(Str::DashboardEntryNotLoaded)).italic(),
+                    |entry| {
+                        ui_entry_line(
+                            entry,
+                            BadgeStyle::Off,
+                            false,
+                            self.accessible,
+                            usize::from(largest_list_area.width).saturating_sub(SIZE_COLUMN_WIDTH),
+                        )
+                    },
+                );
+            line.spans
+                .insert(0, Span::raw(format!("{:>10} ", format_size(len))).bold());
+            line
+        });
+        StatefulWidget::render(
+            List::new(rows).highlight_style(if self.accessible {
+                Style::new().fg(Color::Black).bg(Color::Yellow).bold()
+            } else {
+                Style::default()
+                    .add_modifier(Modifier::BOLD)
+                    .add_modifier(Modifier::REVERSED)
+            }),
+            largest_list_area,
+            buf,
+            &mut ListState::default().with_selected(Some(*selected)),
+        );
+    }
+
+    fn render_error_report(&mut self, area: Rect, buf: &mut Buffer) {
+        let ui = &mut self.state.ui;
+        let (Some(scroll), Some(report)) = (ui.error_report_scroll, &ui.error_report) else {
+            return;
+        };
+
+        let popup = centered_popup(
+            area,
+            usize::from(area.width.saturating_sub(4)),
+            usize::from(area.height.saturating_sub(2)),
+        );
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::new().bold())
+            .title(strings::get(Str::ErrorReportTitle));
+        let inner_area = block.inner(popup);
+        // The scrollbar sits in the right padding.
+        let text_area = Block::new()
+            .padding(Padding::horizontal(1))
+            .inner(inner_area);
+        let text = full_error_report(report, ui.server_info.as_ref());
+        let rows = wrapped_lines(&text, text_area.width);
+        let max_scroll = rows.len().saturating_sub(usize::from(text_area.height));
+        let scroll = min(usize::from(scroll), max_scroll);
+        ui.error_report_scroll = Some(u16::try_from(scroll).unwrap());
+
+        Clear.render(popup, buf);
+        block.render(popup, buf);
+        Paragraph::new(
+            rows.iter()
+                .skip(scroll)
+                .take(usize::from(text_area.height))
+                .map(|&row| Line::raw(row))
+                .collect::<Vec<_>>(),
+        )
+        .render(text_area, buf);
+        render_scrollbar(inner_area, buf, rows.len(), scroll, self.accessible);
+    }
+
+    fn render_title(area: Rect, buf: &mut Buffer, runtime: &RuntimeInfo) {
+        let title = strings::fill(Str::Title, &[&env!("CARGO_PKG_VERSION")]);
+        let title_width = Line::raw(&title).width();
+        Paragraph::new(title).bold().centered().render(area, buf);
+        if let Some(database) = &runtime.database {
+            // The title is centered, so the path only gets the space to its right.
+            let room = (usize::from(area.width).saturating_sub(title_width) / 2).saturating_sub(1);
+            Line::raw(truncate_start(&database.to_string_lossy(), room))
+                .dim()
+                .right_aligned()
+                .render(area, buf);
+        }
+    }
+
+    fn render_error(&self, area: Rect, buf: &mut Buffer) {
+        if area.is_empty() {
+            return;
+        }
+        let Some(error) = &self.state.ui.last_error else {
+            return;
+        };
+
+        let outer_block = Block::new()
+            .borders(Borders::TOP)
+            .border_style(Style::new().bold())
+            .title_alignment(Alignment::Center)
+            .title(strings::fill(Str::ErrorTitle, &[error]));
+        let inner_block = Block::new().borders(Borders::NONE);
+        let inner_area = outer_block.inner(area);
+
+        outer_block.render(area, buf);
+
+        Paragraph::new(format!("{error:#?}"))
+            .wrap(Wrap { trim: false })
+            .block(inner_block)
+            .render(inner_area, buf);
+    }
+
+    fn render_footer(
+        area: Rect,
+        buf: &mut Buffer,
+        server_info: Option<&ServerInfoResponse>,
+        keymap: &Keymap,
+        accessible: bool,
+    ) {
+        if area.is_empty() {
+            return;
+        }
+
+        let mut outer_block = Block::new()
+            .borders(Borders::TOP)
+            .title_alignment(Alignment::Center)
+            .title(strings::get(Str::Help));
+        if let Some(ServerInfoResponse { version, git_hash }) = server_info {
+            outer_block = outer_block.title(
+                Line::raw(strings::fill(Str::ServerVersion, &[version, git_hash])).right_aligned(),
+            );
+        }
+        let inner_block = Block::new().borders(Borders::NONE);
+        let inner_area = outer_block.inner(area);
+
+        outer_block.render(area, buf);
+
+        Paragraph::new(strings::fill(Str::HelpKeys, &[&keymap.help(accessible)]))
+            .wrap(Wrap { trim: true })
+            .block(inner_block)
+            .centered()
+            .render(inner_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::PathBuf,
+        process, str,
+        sync::{
+            mpsc,
+            mpsc::{Receiver, Sender},
+        },
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    };
+
+    use ratatui::{
+        backend::TestBackend,
+        buffer::{Buffer, Cell},
+        crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers},
+        style::{Color, Modifier},
+        Terminal,
+    };
+    use ratatui_image::picker::Picker;
+    use ringboard_sdk::{
+        clipboard::{CopyError, DisplayServer},
+        core::protocol::{composite_id, RingKind},
+        testing::MockDatabase,
+        ui_actor::{
+            Command, CommandError, Controller, DetailedEntry, EntryBadge, ImageMetadata, Message,
+            ServerConnection, UiEntryCache, DETAILS_PREVIEW_SIZE,
+        },
+        EntrySource,
+    };
+
+    use super::{
+        apply_filter, binary_preview, current_session, find_selection, flag_value, format_id,
+        handle_event, handle_message, hex_dump, pretty_json, relative_age, strings, truncate_end,
+        truncate_start, tutorial_mode, utc_time, wrapped_lines, ActiveEntries, AppAction,
+        AppWrapper, BadgeStyle, Cli, Groups, ImageState, Keymap, RuntimeInfo, SearchKind,
+        SearchState, Session, Startup, State, StatusLine, Str, Thumbnail, PAGE_SIZE,
+        TUTORIAL_PAGES,
+    };
+
+    /// Runs the UI against a mock controller without a terminal.
+    struct Harness {
+        controller: Controller<MockDatabase, MockDatabase>,
+        state: State,
+        pending_favorite_change: Option<u64>,
+        picker: Picker,
+        requests: Sender<Command>,
+        commands: Receiver<Command>,
+        runtime: RuntimeInfo,
+        width: u16,
+        height: u16,
+    }
+
+    impl Harness {
+        fn new(db: &MockDatabase) -> Self {
+            let (requests, commands) = mpsc::channel();
+            let mut me = Self {
+                controller: db.controller(),
+                state: State::default(),
+                pending_favorite_change: None,
+                picker: Picker::new((8, 16)),
+                requests,
+                commands,
+                runtime: RuntimeInfo::default(),
+                width: 40,
+                height: 10,
+            };
+            me.execute(Command::LoadFirstPage);
+            me
+        }
+
+        fn execute(&mut self, command: Command) {
+            let mut messages = Vec::new();
+            if let Some(reply) = self.controller.handle(command, |message| {
+                messages.push(message);
+                Ok::<_, ()>(())
+            }) {
+                messages.push(reply);
+            }
+
+            for message in messages {
+                let exit = handle_message(
+                    message,
+                    &mut self.state,
+                    &mut self.pending_favorite_change,
+                    &mut self.picker,
+                    &self.requests,
+                    false,
+                    false,
+                )
+                .unwrap();
+                assert!(!exit);
+            }
+        }
+
+        /// Presses a key and executes the commands it sent, in order, as the
+        /// controller thread would.
+        fn press(&mut self, key: KeyCode) {
+            self.press_with(key, KeyModifiers::NONE);
+        }
+
+        fn press_with(&mut self, key: KeyCode, modifiers: KeyModifiers) {
+            let event = Event::Key(KeyEvent::new(key, modifiers));
+            assert!(!handle_event(event, &mut self.state, &self.requests));
+            self.settle();
+        }
+
+        fn settle(&mut self) {
+            while let Ok(command) = self.commands.try_recv() {
+                self.execute(command);
+            }
+        }
+
+        fn selected_id(&self) -> Option<u64> {
+            selected_entry!(self.state.entries, self.state.ui).map(|e| e.entry.id())
+        }
+
+        fn render(&mut self) -> Buffer {
+            let mut terminal = Terminal::new(TestBackend::new(self.width, self.height)).unwrap();
+            AppWrapper {
+                state: &mut self.state,
+                requests: &self.requests,
+                badges: BadgeStyle::Off,
+                accessible: false,
+                favorites_only: false,
+                runtime: &self.runtime,
+                font_size: self.picker.font_size,
+            }
+            .draw(&mut terminal)
+            .unwrap();
+            terminal.backend().buffer().clone()
+        }
+
+        fn highlighted_rows(&mut self) -> Vec<String> {
+            let buffer = self.render();
+            buffer
+                .content
+                .chunks(usize::from(buffer.area.width))
+                .map(|row| {
+                    row.iter()
+                        .filter(|c| c.modifier.contains(Modifier::REVERSED))
+                        .map(Cell::symbol)
+                        .collect::<String>()
+                })
+                .filter(|row| !row.is_empty())
+                .map(|row| row.trim().to_string())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn startup_progress_is_shown_until_the_first_page_arrives() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"hello");
+        let mut app = Harness::new(&db);
+        app.state = State::default();
+        let text = |app: &mut Harness| {
+            let buffer = app.render();
+            buffer.content.iter().map(Cell::symbol).collect::<String>()
+        };
+        assert!(text(&mut app).contains(strings::get(Str::OpeningDatabase)));
+
+        handle_message(
+            Message::Connected,
+            &mut app.state,
+            &mut app.pending_favorite_change,
+            &mut app.picker,
+            &app.requests,
+            false,
+            false,
+        )
+        .unwrap();
+        assert!(app.state.ui.startup == Startup::Loading);
+        assert!(text(&mut app).contains(strings::get(Str::Loading)));
+
+        app.execute(Command::LoadFirstPage);
+        let text = text(&mut app);
+        assert!(text.contains("hello"));
+        assert!(!text.contains(strings::get(Str::Loading)));
+    }
+
+    #[test]
+    fn selection_survives_new_entries() {
+        let db = MockDatabase::default();
+        let oldest = db.add(RingKind::Main, *b"oldest");
+        db.add(RingKind::Main, *b"middle");
+        db.add(RingKind::Main, *b"newest");
+        let mut app = Harness::new(&db);
+        app.state.entries.loaded_state.select(Some(2));
+        assert_eq!(app.selected_id(), Some(oldest));
+
+        db.add(RingKind::Main, *b"brand new");
+        app.execute(Command::RefreshDb);
+
+        assert_eq!(app.selected_id(), Some(oldest));
+        assert_eq!(app.highlighted_rows(), ["oldest"]);
+        assert_eq!(app.state.ui.status.current(), Some("1 new entry(ies)."));
+    }
+
+    #[test]
+    fn selection_follows_favorited_entry() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"plain");
+        let snippet = db.add(RingKind::Main, *b"snippet");
+        db.add(RingKind::Main, *b"clipboard");
+        let mut app = Harness::new(&db);
+        assert_eq!(app.selected_id(), Some(snippet));
+
+        app.press(KeyCode::Char('f'));
+
+        assert_eq!(app.state.entries.loaded_state.selected(), Some(0));
+        assert_eq!(app.highlighted_rows(), ["snippet"]);
+        assert!(app.pending_favorite_change.is_none());
+    }
+
+    #[test]
+    fn actions_menu_runs_the_keybindings() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"plain");
+        db.add(RingKind::Main, *b"snippet");
+        db.add(RingKind::Main, *b"clipboard");
+        let mut app = Harness::new(&db);
+
+        app.press(KeyCode::Char('.'));
+        // Only entries that are already favorites can be unfavorited.
+        let buffer = app.render();
+        let symbols = buffer.content.iter().map(Cell::symbol).collect::<String>();
+        let dimmed = |name: &str| {
+            let cell = symbols[..symbols.find(name).unwrap()].chars().count();
+            buffer.content[cell].modifier.contains(Modifier::DIM)
+        };
+        assert!(dimmed("Unfavorite"));
+        assert!(!dimmed("Favorite"));
+
+        app.press(KeyCode::Char('j'));
+        app.press(KeyCode::Char('j'));
+        app.press(KeyCode::Enter);
+
+        assert!(app.state.ui.actions_menu.is_none());
+        assert_eq!(app.state.entries.loaded_state.selected(), Some(0));
+        assert_eq!(app.highlighted_rows(), ["snippet"]);
+    }
+
+    #[test]
+    fn yanking_explains_why_nothing_was_copied() {
+        let db = MockDatabase::default();
+        let id = db.add(RingKind::Main, *b"yank me");
+        let mut app = Harness::new(&db);
+        // Yanking runs the system's clipboard tool, so look at the command instead.
+        let event = Event::Key(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::NONE));
+        assert!(!handle_event(event, &mut app.state, &app.requests));
+        let commands = app.commands.try_iter().collect::<Vec<_>>();
+        assert!(matches!(commands[..], [Command::Yank(yanked)] if yanked == id));
+
+        for (message, status) in [
+            (
+                Message::Error(CommandError::Clipboard(CopyError::NoDisplayServer)),
+                Str::NoDisplayServer,
+            ),
+            (
+                Message::Error(CommandError::Clipboard(CopyError::NoCopier {
+                    display_server: DisplayServer::X11,
+                })),
+                Str::NoCopier,
+            ),
+            (Message::Yanked(id), Str::Yanked),
+        ] {
+            // Each try is a key press of its own, which dismisses the last one's news.
+            app.state.ui.status.clear();
+            let exit = handle_message(
+                message,
+                &mut app.state,
+                &mut app.pending_favorite_change,
+                &mut app.picker,
+                &app.requests,
+                false,
+                false,
+            )
+            .unwrap();
+            assert!(!exit);
+            assert_eq!(app.state.ui.status.current(), Some(strings::get(status)));
+            assert!(app.state.ui.last_error.is_none());
+        }
+    }
+
+    #[test]
+    fn large_pastes_must_be_confirmed() {
+        let db = MockDatabase::default();
+        let small = db.add(RingKind::Main, *b"small");
+        let large = db.add(RingKind::Main, vec![b'x'; 2048]);
+        let mut app = Harness::new(&db);
+        app.state.ui.paste_warning = Some(1024);
+        // Pasting quits, so look at the commands instead of running them.
+        let press = |app: &mut Harness, key| {
+            let event = Event::Key(KeyEvent::new(key, KeyModifiers::NONE));
+            assert!(!handle_event(event, &mut app.state, &app.requests));
+            app.commands.try_iter().find_map(|command| match command {
+                Command::Paste(id) => Some(id),
+                _ => None,
+            })
+        };
+
+        assert_eq!(press(&mut app, KeyCode::Char('2')), Some(small));
+
+        assert_eq!(press(&mut app, KeyCode::Char('1')), None);
+        let buffer = app.render();
+        let symbols = buffer.content.iter().map(Cell::symbol).collect::<String>();
+        assert!(symbols.contains("Paste 2.0 KiB of text/*?"));
+        assert_eq!(press(&mut app, KeyCode::Esc), None);
+        assert!(app.state.ui.pending_paste.is_none());
+
+        // Pasting from the actions menu asks too.
+        app.state.entries.loaded_state.select(Some(0));
+        assert_eq!(press(&mut app, KeyCode::Char('.')), None);
+        assert_eq!(press(&mut app, KeyCode::Enter), None);
+        assert_eq!(press(&mut app, KeyCode::Enter), Some(large));
+        assert!(app.state.ui.pending_paste.is_none());
+    }
+
+    #[test]
+    fn digits_paste_the_entries_shown_next_to_them() {
+        let db = MockDatabase::default();
+        let ids = (0..20)
+            .map(|i| db.add(RingKind::Main, format!("entry-{i:02}").into_bytes()))
+            .collect::<Vec<_>>();
+        let mut app = Harness::new(&db);
+        let press = |app: &mut Harness, key| {
+            let event = Event::Key(KeyEvent::new(key, KeyModifiers::NONE));
+            assert!(!handle_event(event, &mut app.state, &app.requests));
+            app.commands.try_iter().find_map(|command| match command {
+                Command::Paste(id) => Some(id),
+                _ => None,
+            })
+        };
+        // The entry each digit labels, going by what's on screen.
+        let labelled = |app: &mut Harness| {
+            let buffer = app.render();
+            buffer
+                .content
+                .chunks(usize::from(buffer.area.width))
+                .filter_map(|row| {
+                    let row = row.iter().map(Cell::symbol).collect::<String>();
+                    let digit = row.chars().find(char::is_ascii_digit)?;
+                    let (_, entry) = row.split_once("entry-")?;
+                    Some((digit, ids[entry[..2].parse::<usize>().unwrap()]))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let shown = labelled(&mut app);
+        assert_eq!(shown[..2], [('1', ids[19]), ('2', ids[18])]);
+        assert_eq!(press(&mut app, KeyCode::Char('2')), Some(ids[18]));
+
+        // The digits follow the list as it scrolls.
+        app.press(KeyCode::Char('G'));
+        let shown = labelled(&mut app);
+        assert_eq!(shown.last().unwrap().1, ids[0]);
+        assert_eq!(shown[0].0, '1');
+        let id = shown[0].1;
+        assert_ne!(id, ids[19]);
+        assert_eq!(press(&mut app, KeyCode::Char('1')), Some(id));
+        assert_eq!(app.selected_id(), Some(id));
+
+        app.state.ui.disable_quick_select = true;
+        assert_eq!(press(&mut app, KeyCode::Char('1')), None);
+    }
+
+    #[test]
+    fn sensitive_entries_stay_hidden_until_revealed() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"hunter2");
+        let mut app = Harness::new(&db);
+        // The mock database can't mark entries sensitive.
+        app.state.entries.loaded_entries[0].cache = UiEntryCache::Sensitive;
+        let shown = |app: &mut Harness| {
+            app.render();
+            app.settle();
+            let buffer = app.render();
+            buffer.content.iter().map(Cell::symbol).collect::<String>()
+        };
+
+        let symbols = shown(&mut app);
+        assert!(symbols.contains("••••• sensitive entry"), "{symbols}");
+        app.press(KeyCode::Char('l'));
+        let symbols = shown(&mut app);
+        // The hint is wrapped in the narrow details pane.
+        assert!(symbols.contains("Sensitive entry"), "{symbols}");
+        assert!(symbols.contains("hidden: P to"), "{symbols}");
+        assert!(!symbols.contains("hunter2"));
+
+        app.press(KeyCode::Char('P'));
+        assert!(shown(&mut app).contains("hunter2"));
+
+        // Opening it again hides it.
+        app.press(KeyCode::Char('h'));
+        app.press(KeyCode::Char('l'));
+        assert!(!shown(&mut app).contains("hunter2"));
+    }
+
+    /// A black 2×3 PNG.
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x02\0\0\0\x03\x08\0\0\0\0\x9c\x81\x81]\0\0\0\x0bIDATx\x9cc`\x80\x02\0\0\t\0\x01\xfbR\xb8\xa9\0\0\0\0IEND\xaeB`\x82";
+
+    #[test]
+    fn image_metadata_is_shown_below_the_image() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, PNG);
+        let mut app = Harness::new(&db);
+        // Images small enough for the buckets are sniffed despite having no mime type.
+        assert!(matches!(
+            app.state.entries.loaded_entries[0].cache,
+            UiEntryCache::Image
+        ));
+        let shown = |app: &mut Harness| {
+            let buffer = app.render();
+            app.settle();
+            let symbols = buffer.content.iter().map(Cell::symbol).collect::<String>();
+            symbols.contains("2×3 PNG, 68 B")
+        };
+
+        app.press(KeyCode::Char('l'));
+        shown(&mut app);
+        assert!(matches!(
+            app.state.ui.detail_image_state,
+            Some(ImageState::Loaded(..))
+        ));
+        assert!(!shown(&mut app));
+
+        app.press(KeyCode::Char('i'));
+        assert!(shown(&mut app));
+        app.press(KeyCode::Char('i'));
+        assert!(!shown(&mut app));
+
+        // Resizing reloads the image to fit the new size.
+        assert!(!handle_event(
+            Event::Resize(60, 20),
+            &mut app.state,
+            &app.requests
+        ));
+        assert!(app.state.ui.detail_image_state.is_none());
+        app.press(KeyCode::Char('i'));
+        shown(&mut app);
+        assert!(shown(&mut app));
+    }
+
+    #[test]
+    fn image_size_is_shown_in_the_details_title() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, PNG);
+        let mut app = Harness::new(&db);
+        app.width = 100;
+        app.press(KeyCode::Char('l'));
+        // Stand in for a photo much larger than the test image.
+        let Some(Ok(details)) = &mut app.state.ui.detailed_entry else {
+            panic!("Details weren't loaded.");
+        };
+        details.image = Some(ImageMetadata {
+            width: 4000,
+            height: 3000,
+            len: 2_400_000,
+            ..ImageMetadata::default()
+        });
+
+        let buffer = app.render();
+        let symbols = buffer.content.iter().map(Cell::symbol).collect::<String>();
+        assert!(symbols.contains("4000×3000, 2.3 MiB"), "{symbols}");
+    }
+
+    #[test]
+    fn binary_details_are_hex_dumped_unless_shown_as_text() {
+        let db = MockDatabase::default();
+        let mut data = b"\xff{\\rtf1 hi}".to_vec();
+        data.resize(DETAILS_PREVIEW_SIZE * 2, 0);
+        db.add(RingKind::Main, data);
+        let mut app = Harness::new(&db);
+        app.width = 120;
+        let rendered = |app: &mut Harness| {
+            let buffer = app.render();
+            app.settle();
+            buffer.content.iter().map(Cell::symbol).collect::<String>()
+        };
+
+        // Bucketed entries have no mime type, so it's sniffed.
+        assert!(matches!(
+            &app.state.entries.loaded_entries[0].cache,
+            UiEntryCache::Binary { mime_type } if &**mime_type == "application/octet-stream"
+        ));
+        app.press(KeyCode::Char('l'));
+        rendered(&mut app);
+        let Some(Ok(DetailedEntry {
+            full_text: None,
+            preview: Some(preview),
+            mime_type,
+            ..
+        })) = &app.state.ui.detailed_entry
+        else {
+            panic!("binary details weren't previewed");
+        };
+        assert_eq!(preview.len(), DETAILS_PREVIEW_SIZE);
+        assert_eq!(&**mime_type, "application/octet-stream");
+        let symbols = rendered(&mut app);
+        assert!(symbols.contains("00000000  ff 7b 5c 72 74 66 31 20"));
+        assert!(symbols.contains("|.{\\rtf1 |"));
+
+        app.press(KeyCode::Char('b'));
+        assert!(rendered(&mut app).contains("\u{fffd}{\\rtf1 hi}"));
+        app.press(KeyCode::Char('b'));
+        assert!(rendered(&mut app).contains("00000000  ff 7b"));
+    }
+
+    #[test]
+    fn image_rows_show_thumbnails_unless_they_fail_to_decode() {
+        let db = MockDatabase::default();
+        let broken = db.add(RingKind::Main, *b"\x89PNG\r\n\x1a\ntruncated");
+        let image = db.add(RingKind::Main, PNG);
+        let mut app = Harness::new(&db);
+        for entry in &mut app.state.entries.loaded_entries {
+            entry.cache = UiEntryCache::Image;
+        }
+        let fingerprints = app
+            .state
+            .entries
+            .loaded_entries
+            .iter()
+            .map(|e| e.fingerprint)
+            .collect::<Vec<_>>();
+
+        app.render();
+        app.settle();
+        let thumbnails = &mut app.state.ui.thumbnails;
+        assert!(matches!(
+            thumbnails.get(image, fingerprints[0]),
+            Some(Thumbnail::Loaded(_))
+        ));
+        assert!(matches!(
+            thumbnails.get(broken, fingerprints[1]),
+            Some(Thumbnail::Failed)
+        ));
+
+        // Shown thumbnails aren't decoded again, and the broken entry keeps its
+        // placeholder.
+        let buffer = app.render();
+        assert!(app.commands.try_recv().is_err());
+        let symbols = buffer.content.iter().map(Cell::symbol).collect::<String>();
+        assert!(symbols.contains("Image: open"));
+    }
+
+    #[test]
+    fn stale_details_are_ignored() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"first");
+        db.add(RingKind::Main, *b"second");
+        db.add(RingKind::Main, *b"third");
+        let mut app = Harness::new(&db);
+
+        // Move on before the controller gets around to the first request.
+        let event = Event::Key(KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE));
+        handle_event(event, &mut app.state, &app.requests);
+        app.press(KeyCode::Char('j'));
+
+        let details = app.state.ui.detailed_entry.as_ref().unwrap();
+        let details = details.as_ref().unwrap();
+        assert_eq!(details.full_text.as_deref(), Some("first"));
+        assert_eq!(app.state.ui.details_requested, app.selected_id());
+    }
+
+    #[test]
+    fn details_are_only_fetched_again_for_changed_entries() {
+        let db = MockDatabase::default();
+        let first = db.add(RingKind::Main, *b"first");
+        let second = db.add(RingKind::Main, *b"second");
+        let mut app = Harness::new(&db);
+        assert_eq!(app.selected_id(), Some(first));
+        app.press(KeyCode::Char('l'));
+        let details_requests = |app: &Harness| {
+            app.commands
+                .try_iter()
+                .filter(|command| matches!(command, Command::GetDetails { .. }))
+                .count()
+        };
+
+        app.execute(Command::LoadFirstPage);
+        assert_eq!(details_requests(&app), 0);
+        assert!(app.state.ui.detailed_entry.is_some());
+
+        // The selected id now holds different contents.
+        let mut server = db.clone();
+        assert!(server.swap(first, second).unwrap().error1.is_none());
+        app.execute(Command::LoadFirstPage);
+        assert_eq!(app.selected_id(), Some(first));
+        assert_eq!(details_requests(&app), 1);
+    }
+
+    /// Loads three similar commands on top of an unrelated entry in collapse
+    /// mode, selecting the newest command.
+    fn collapsed_commands(db: &MockDatabase) -> Harness {
+        db.add(RingKind::Main, *b"unrelated");
+        for package in ["core", "server", "tui"] {
+            db.add(
+                RingKind::Main,
+                format!("cargo test --package {package}").into_bytes(),
+            );
+        }
+        let mut app = Harness::new(db);
+        app.state.entries.groups = Some(Groups::default());
+        app.execute(Command::LoadFirstPage);
+        app.state.entries.loaded_state.select(Some(0));
+        app
+    }
+
+    #[test]
+    fn similar_entries_collapse_until_expanded() {
+        let db = MockDatabase::default();
+        let mut app = collapsed_commands(&db);
+        let head = app.selected_id();
+        assert_eq!(app.state.entries.loaded().len(), 2);
+        assert_eq!(app.highlighted_rows(), ["×3 cargo test --package tui"]);
+
+        app.press(KeyCode::Enter);
+
+        assert_eq!(app.state.entries.loaded().len(), 4);
+        assert_eq!(app.selected_id(), head);
+    }
+
+    #[test]
+    fn collapsed_run_changes_ask_first() {
+        let db = MockDatabase::default();
+        let mut app = collapsed_commands(&db);
+
+        app.press(KeyCode::Char('d'));
+        assert!(app.state.ui.status.current().is_some());
+        assert_eq!(app.state.entries.loaded().len(), 2);
+
+        app.press(KeyCode::Char('y'));
+        assert_eq!(app.state.entries.loaded().len(), 1);
+    }
+
+    /// The text of the loaded entries in the order they're shown.
+    fn rows(app: &Harness) -> Vec<&str> {
+        app.state
+            .entries
+            .loaded()
+            .iter()
+            .map(|e| match &e.cache {
+                UiEntryCache::Text { one_liner, .. } => &**one_liner,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reordering_previews_until_committed() {
+        let db = MockDatabase::default();
+        for snippet in ["a", "b", "c"] {
+            db.add(RingKind::Favorites, snippet.as_bytes());
+        }
+        let mut app = Harness::new(&db);
+        assert_eq!(app.state.entries.loaded_state.selected(), Some(0));
+
+        app.press(KeyCode::Char('R'));
+        app.press(KeyCode::Char('j'));
+        app.press(KeyCode::Char('j'));
+        assert_eq!(rows(&app), ["b", "a", "c"]);
+        assert_eq!(app.highlighted_rows(), ["c"]);
+        app.press(KeyCode::Esc);
+        assert_eq!(rows(&app), ["c", "b", "a"]);
+
+        app.press(KeyCode::Char('R'));
+        app.press(KeyCode::Char('j'));
+        app.press(KeyCode::Enter);
+        assert!(app.state.entries.reorder.is_none());
+        assert_eq!(rows(&app), ["b", "c", "a"]);
+        assert_eq!(app.highlighted_rows(), ["c"]);
+    }
+
+    #[test]
+    fn reordering_removed_entry_reloads() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Favorites, *b"a");
+        let lifted = db.add(RingKind::Favorites, *b"b");
+        let mut app = Harness::new(&db);
+
+        app.press(KeyCode::Char('R'));
+        app.press(KeyCode::Char('j'));
+        let removed = ServerConnection::remove(&mut db.clone(), lifted).unwrap();
+        assert!(removed.error.is_none());
+        app.press(KeyCode::Enter);
+
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some(strings::get(Str::ReorderFailed))
+        );
+        assert_eq!(rows(&app), ["a"]);
+    }
+
+    #[test]
+    fn shift_arrows_nudge_favorites() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"x");
+        for snippet in ["a", "b", "c", "d"] {
+            db.add(RingKind::Favorites, snippet.as_bytes());
+        }
+        let mut app = Harness::new(&db);
+        app.state.entries.loaded_state.select(Some(0));
+
+        app.press_with(KeyCode::Down, KeyModifiers::SHIFT);
+        app.press_with(KeyCode::Down, KeyModifiers::SHIFT);
+        assert_eq!(rows(&app), ["c", "b", "d", "a", "x"]);
+        assert_eq!(app.highlighted_rows(), ["d"]);
+
+        let removed = app.state.entries.loaded_entries[1].entry.id();
+        assert!(
+            ServerConnection::remove(&mut db.clone(), removed)
+                .unwrap()
+                .error
+                .is_none()
+        );
+        app.execute(Command::LoadFirstPage);
+        assert_eq!(rows(&app), ["c", "d", "a", "x"]);
+
+        // The newest favorite moves past the hole left by the removal.
+        app.state.entries.loaded_state.select(Some(0));
+        app.press_with(KeyCode::Down, KeyModifiers::SHIFT);
+        app.press_with(KeyCode::Down, KeyModifiers::SHIFT);
+        assert_eq!(rows(&app), ["d", "a", "c", "x"]);
+        // The last favorite stays put rather than moving into the main ring.
+        app.press_with(KeyCode::Down, KeyModifiers::SHIFT);
+        assert_eq!(rows(&app), ["d", "a", "c", "x"]);
+
+        for _ in 0..3 {
+            app.press_with(KeyCode::Up, KeyModifiers::SHIFT);
+        }
+        assert_eq!(rows(&app), ["c", "d", "a", "x"]);
+        assert_eq!(app.highlighted_rows(), ["c"]);
+
+        // New favorites still go on top.
+        db.add(RingKind::Favorites, *b"e");
+        app.execute(Command::LoadFirstPage);
+        assert_eq!(rows(&app), ["e", "c", "d", "a", "x"]);
+    }
+
+    #[test]
+    fn key_bindings_can_be_changed() {
+        let db = MockDatabase::default();
+        for snippet in ["a", "b", "c"] {
+            db.add(RingKind::Main, snippet.as_bytes());
+        }
+        let mut app = Harness::new(&db);
+        app.state.ui.keymap = Keymap::parse("Delete = x Ctrl-X\nRegexSearch =\n").0;
+        app.state.entries.loaded_state.select(Some(0));
+
+        app.press(KeyCode::Char('d'));
+        assert_eq!(rows(&app), ["c", "b", "a"]);
+        app.press(KeyCode::Char('x'));
+        assert_eq!(rows(&app), ["b", "a"]);
+        app.press_with(KeyCode::Char('x'), KeyModifiers::CONTROL);
+        assert_eq!(rows(&app), ["a"]);
+        assert!(app.state.ui.search_state.is_none());
+
+        // The actions menu runs the action rather than replaying a key.
+        app.press(KeyCode::Char('.'));
+        app.press(KeyCode::Up);
+        app.press(KeyCode::Enter);
+        assert!(rows(&app).is_empty());
+    }
+
+    #[test]
+    fn key_binding_problems_are_reported() {
+        let (keymap, problems) = Keymap::parse(
+            "
+            # Vim users
+            Delete = j Ctrl-Q
+            Bogus = z
+            Quit = Hyper-Q q
+            Search
+            ",
+        );
+
+        assert_eq!(
+            problems,
+            [
+                "Key bindings line 4: unknown action Bogus",
+                "Key bindings line 5: unknown key Hyper-Q",
+                "Key bindings line 6: expected Action = keys",
+                "Key bindings: j is bound to both Delete and MoveDown, keeping the first",
+            ]
+        );
+        assert_eq!(
+            keymap.get(KeyCode::Char('j'), KeyModifiers::NONE),
+            Some(AppAction::Delete)
+        );
+        assert_eq!(
+            keymap.get(KeyCode::Down, KeyModifiers::NONE),
+            Some(AppAction::MoveDown)
+        );
+        assert_eq!(
+            keymap.get(KeyCode::Char('q'), KeyModifiers::NONE),
+            Some(AppAction::Quit)
+        );
+        assert_eq!(keymap.get(KeyCode::Char('c'), KeyModifiers::CONTROL), None);
+        assert_eq!(keymap.get(KeyCode::Char('d'), KeyModifiers::NONE), None);
+        // Terminals report Shift with capitals.
+        assert_eq!(
+            keymap.get(KeyCode::Char('G'), KeyModifiers::SHIFT),
+            Some(AppAction::Bottom)
+        );
+    }
+
+    #[test]
+    fn help_lists_the_effective_bindings() {
+        let help = Keymap::default().help(false);
+        assert!(help.starts_with("j/↓ to move down, k/↑ to move"), "{help}");
+        assert!(help.contains(", q/Ctrl-C to quit, "), "{help}");
+        assert!(help.contains(", Shift-↑ to nudge a favorite"), "{help}");
+        let help = Keymap::default().help(true);
+        assert!(help.starts_with("j/Down to move down, "), "{help}");
+
+        let (keymap, _) = Keymap::parse("Quit = Ctrl-Shift-q F10\nHelp =");
+        let help = keymap.help(false);
+        assert!(help.contains(", Ctrl-Shift-Q/F10 to quit, "), "{help}");
+        assert!(!help.contains("to toggle this help"), "{help}");
+    }
+
+    #[test]
+    fn clipboard_marker_tracks_the_clipboard() {
+        let db = MockDatabase::default();
+        let pasted = db.add(RingKind::Main, *b"a");
+        let copied = db.add(RingKind::Main, *b"b");
+        let mut app = Harness::new(&db);
+        let marked_rows = |app: &mut Harness| {
+            let buffer = app.render();
+            buffer
+                .content
+                .chunks(usize::from(buffer.area.width))
+                .map(|row| row.iter().map(Cell::symbol).collect::<String>())
+                .filter(|row| row.contains('◉'))
+                .map(|row| row.trim().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert!(marked_rows(&mut app).is_empty());
+
+        let mut server = db.clone();
+        assert!(server.set_clipboard(Some(copied)).unwrap().error.is_none());
+        app.execute(Command::RefreshDb);
+        assert_eq!(marked_rows(&mut app), ["1 ◉ b"]);
+
+        // Copying something that isn't stored clears the marker.
+        assert!(server.set_clipboard(None).unwrap().error.is_none());
+        app.execute(Command::RefreshDb);
+        assert!(marked_rows(&mut app).is_empty());
+
+        // Pasting moves the entry to the front.
+        let Some(Message::Pasted { id, new_id }) = app
+            .controller
+            .handle(Command::Paste(pasted), |_| Ok::<_, ()>(()))
+        else {
+            unreachable!()
+        };
+        assert_eq!(id, pasted);
+        assert_ne!(new_id, pasted);
+        assert_eq!(server.current_clipboard().unwrap(), Some(new_id));
+        app.execute(Command::RefreshDb);
+        assert_eq!(marked_rows(&mut app), ["1 ◉ a"]);
+    }
+
+    #[test]
+    fn entries_show_their_age() {
+        let db = MockDatabase::default();
+        let now = SystemTime::now();
+        let old = db.add(RingKind::Main, *b"old");
+        db.set_timestamp(old, now - Duration::from_secs(2 * 24 * 60 * 60 + 30));
+        let new = db.add(RingKind::Main, *b"new");
+        let copied = now - Duration::from_secs(3 * 60 + 30);
+        db.set_timestamp(new, copied);
+        db.add(RingKind::Main, *b"unknown");
+        let mut app = Harness::new(&db);
+        app.width = 60;
+        let rows = |app: &mut Harness| {
+            let buffer = app.render();
+            buffer
+                .content
+                .chunks(usize::from(buffer.area.width))
+                .map(|row| row.iter().map(Cell::symbol).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+
+        let list = rows(&mut app);
+        let row = |text: &str| {
+            let row = list.iter().find(|row| row.contains(text)).unwrap();
+            row.trim_end_matches(['│', '┃', '║', ' ']).to_string()
+        };
+        assert!(row("unknown").ends_with("unknown"));
+        assert!(row("new").ends_with(" 3m"), "{}", row("new"));
+        assert!(row("old").ends_with(" 2d"), "{}", row("old"));
+
+        assert_eq!(app.selected_id(), Some(new));
+        app.press(KeyCode::Char(' '));
+        app.width = 100;
+        let title = format!("copied {} UTC", utc_time(copied));
+        assert!(rows(&mut app).iter().any(|row| row.contains(&title)));
+    }
+
+    #[test]
+    fn long_entries_say_how_much_is_hidden() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"    first\n    second\n    third\n");
+        db.add(RingKind::Main, "word ".repeat(200).into_bytes());
+        db.add(RingKind::Main, *b"short");
+        let mut app = Harness::new(&db);
+        app.width = 40;
+        let buffer = app.render();
+        let rows = buffer
+            .content
+            .chunks(usize::from(buffer.area.width))
+            .map(|row| row.iter().map(Cell::symbol).collect::<String>())
+            .collect::<Vec<_>>();
+        let row = |text: &str| {
+            let row = rows.iter().find(|row| row.contains(text)).unwrap();
+            row.trim_end_matches(['│', '┃', '║', ' ']).to_string()
+        };
+
+        assert!(row("first second").ends_with("third ⏎ ×3"));
+        assert!(row("word word").ends_with("\u{2026} (1000 B)"));
+        assert!(row("short").ends_with("short"));
+
+        assert_eq!(truncate_end("abc", 3), "abc");
+        assert_eq!(truncate_end("abcd", 3), "ab\u{2026}");
+        assert_eq!(truncate_end("\u{4f60}\u{597d}a", 4), "\u{4f60}\u{2026}");
+        assert_eq!(truncate_end("abcd", 0), "");
+    }
+
+    #[test]
+    fn ages_use_the_largest_whole_unit() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for (ago, expected) in [
+            (0, "now"),
+            (59, "now"),
+            (60, "1m"),
+            (59 * 60, "59m"),
+            (5 * 60 * 60, "5h"),
+            (2 * 24 * 60 * 60, "2d"),
+            (15 * 24 * 60 * 60, "2w"),
+            (800 * 24 * 60 * 60, "2y"),
+        ] {
+            assert_eq!(
+                relative_age(now - Duration::from_secs(ago), now),
+                expected,
+                "{ago}"
+            );
+        }
+        assert_eq!(relative_age(now + Duration::from_secs(600), now), "now");
+
+        assert_eq!(utc_time(UNIX_EPOCH), "1970-01-01 00:00");
+        assert_eq!(utc_time(now), "2023-11-14 22:13");
+        assert_eq!(
+            utc_time(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29 00:00"
+        );
+    }
+
+    #[test]
+    fn non_default_databases_are_shown_in_the_title() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"hello");
+        let mut harness = Harness::new(&db);
+        let title_row = |harness: &mut Harness| {
+            let buffer = harness.render();
+            buffer.content[..usize::from(buffer.area.width)]
+                .iter()
+                .map(Cell::symbol)
+                .collect::<String>()
+        };
+        assert!(!title_row(&mut harness).contains('/'));
+
+        harness.runtime.database = Some(PathBuf::from("/home/me/profiles/work/clipboard-history"));
+        harness.width = 100;
+        assert!(
+            title_row(&mut harness)
+                .trim_end()
+                .ends_with(" /home/me/profiles/work/clipboard-history")
+        );
+        harness.width = 60;
+        assert!(
+            title_row(&mut harness)
+                .trim_end()
+                .ends_with(" \u{2026}rk/clipboard-history")
+        );
+
+        assert_eq!(truncate_start("abc", 3), "abc");
+        assert_eq!(truncate_start("abcd", 3), "\u{2026}cd");
+        assert_eq!(truncate_start("a\u{4f60}\u{597d}", 4), "\u{2026}\u{597d}");
+        assert_eq!(truncate_start("abcd", 0), "");
+    }
+
+    #[test]
+    fn labels_replace_previews_and_match_filters() {
+        let db = MockDatabase::default();
+        let token = db.add(RingKind::Main, *b"hunter2");
+        db.add(RingKind::Main, *b"other");
+        let mut app = Harness::new(&db);
+        app.state.entries.loaded_state.select(Some(1));
+
+        app.press(KeyCode::Char('a'));
+        assert_eq!(app.state.ui.labeling, Some(token));
+        for c in "prod".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        app.press(KeyCode::Enter);
+        assert!(app.state.ui.labeling.is_none());
+        assert!(app.state.ui.outstanding_request.is_none());
+        assert_eq!(app.selected_id(), Some(token));
+
+        // The mock has nowhere to store labels, so fake what the reload would show.
+        app.state.entries.loaded_entries[1].label = Some("AWS prod token".into());
+        assert_eq!(app.highlighted_rows(), ["AWS prod token hunter2"]);
+        apply_filter(&mut app.state.entries, None, "aws", None);
+        assert_eq!(app.state.entries.loaded().len(), 1);
+        assert_eq!(app.selected_id(), Some(token));
+    }
+
+    #[test]
+    fn tags_are_typed_in_a_prompt_and_filtered_on() {
+        let db = MockDatabase::default();
+        let report = db.add(RingKind::Main, *b"report");
+        db.add(RingKind::Main, *b"other");
+        let mut app = Harness::new(&db);
+        app.state.entries.loaded_state.select(Some(1));
+        let tag = |app: &mut Harness, text: &str| {
+            app.press(KeyCode::Char('t'));
+            assert_eq!(app.state.ui.tagging, Some(report));
+            for c in text.chars() {
+                app.press(KeyCode::Char(c));
+            }
+            app.press(KeyCode::Enter);
+        };
+        let results = |app: &Harness| {
+            app.state
+                .entries
+                .search_results
+                .iter()
+                .map(|e| e.entry.id())
+                .collect::<Vec<_>>()
+        };
+
+        // Invalid tags keep the prompt open to be fixed.
+        tag(&mut app, "two words");
+        assert_eq!(app.state.ui.tagging, Some(report));
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some(strings::get(Str::InvalidTag))
+        );
+        app.press(KeyCode::Esc);
+        tag(&mut app, "Work");
+        tag(&mut app, "home");
+        tag(&mut app, "-home");
+        assert!(app.state.ui.tagging.is_none());
+        assert!(app.state.ui.outstanding_request.is_none());
+
+        app.press(KeyCode::Char('T'));
+        assert!(matches!(
+            app.state.ui.search_state,
+            Some(SearchState {
+                kind: SearchKind::Tag,
+                ..
+            })
+        ));
+        for c in "work".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        assert_eq!(results(&app), [report]);
+        let text = app
+            .render()
+            .content
+            .iter()
+            .map(Cell::symbol)
+            .collect::<String>();
+        assert!(text.contains("Tag filter (all)"), "{text}");
+
+        app.press_with(KeyCode::Char('u'), KeyModifiers::CONTROL);
+        for c in "home".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        assert!(results(&app).is_empty());
+        assert!(app.state.ui.last_error.is_none());
+    }
+
+    #[test]
+    fn substitutions_are_previewed_before_replacing() {
+        let db = MockDatabase::default();
+        let greeting = db.add(RingKind::Main, *b"hello world\nsame\nbye world");
+        db.add(RingKind::Main, *b"other");
+        let mut app = Harness::new(&db);
+        let type_command = |app: &mut Harness, command: &str| {
+            for _ in 0..app.state.ui.substitution.lines()[0].len() {
+                app.press(KeyCode::Backspace);
+            }
+            for c in command.chars() {
+                app.press(KeyCode::Char(c));
+            }
+            app.press(KeyCode::Enter);
+        };
+
+        // Replacing needs the entry's full text, : jumps to an id without it.
+        app.press(KeyCode::Char(':'));
+        assert!(app.state.ui.substituting.is_none());
+        assert!(app.state.ui.jumping);
+        app.press(KeyCode::Esc);
+        app.press(KeyCode::Char(' '));
+        app.press(KeyCode::Char(':'));
+        assert_eq!(app.state.ui.substituting, Some(greeting));
+
+        for (command, error) in [
+            ("s/[/x/", Str::InvalidRegex),
+            ("s/world", Str::InvalidSubstitution),
+            ("s/world/x/q", Str::InvalidSubstitution),
+            ("s/nope/x/", Str::NoMatches),
+        ] {
+            type_command(&mut app, command);
+            assert_eq!(app.state.ui.substituting, Some(greeting), "{command}");
+            assert_eq!(app.state.ui.substitution_error, Some(strings::get(error)));
+        }
+
+        type_command(&mut app, "s/WORLD/there/gi");
+        assert!(app.state.ui.substituting.is_none());
+        let rendered = app
+            .render()
+            .content
+            .iter()
+            .map(Cell::symbol)
+            .collect::<String>();
+        assert!(rendered.contains("- hello world"), "{rendered}");
+        assert!(rendered.contains("+ bye there"), "{rendered}");
+        assert!(!rendered.contains("same"), "{rendered}");
+
+        // Going back keeps the command to tweak it.
+        app.press(KeyCode::Esc);
+        assert_eq!(app.state.ui.substituting, Some(greeting));
+        type_command(&mut app, "s/world/there/");
+        assert_eq!(app.state.ui.pending_replace.as_ref().unwrap().matches, 1);
+        app.press(KeyCode::Enter);
+        assert!(app.state.ui.pending_replace.is_none());
+        assert!(app.state.ui.outstanding_request.is_none());
+        assert_eq!(app.selected_id(), Some(greeting));
+
+        let Some(Ok(DetailedEntry {
+            full_text: Some(text),
+            ..
+        })) = &app.state.ui.detailed_entry
+        else {
+            panic!("details weren't reloaded");
+        };
+        assert_eq!(&**text, "hello there\nsame\nbye world");
+    }
+
+    #[test]
+    fn edits_replace_the_entry_unless_cancelled() {
+        let db = MockDatabase::default();
+        let big = "x".repeat(10_000);
+        db.add(RingKind::Main, big.as_bytes());
+        let snippet = db.add(RingKind::Main, *b"hello\n");
+        db.add(RingKind::Main, *b"newest");
+        let mut app = Harness::new(&db);
+        let contents = |id| {
+            let mut db = db.clone();
+            let entry = EntrySource::get(&mut db, id).unwrap();
+            EntrySource::to_slice(&mut db, entry).unwrap().to_vec()
+        };
+        let edit = |app: &mut Harness, suffix: &str| {
+            app.press(KeyCode::Char('e'));
+            app.press(KeyCode::End);
+            for c in suffix.chars() {
+                app.press(KeyCode::Char(c));
+            }
+        };
+
+        // Editing needs the entry's full text.
+        assert_eq!(app.selected_id(), Some(snippet));
+        app.press(KeyCode::Char('e'));
+        assert!(app.state.ui.editing.is_none());
+        app.press(KeyCode::Char(' '));
+        edit(&mut app, "!");
+        assert_eq!(app.state.ui.editing, Some(snippet));
+        assert_eq!(app.state.ui.editor.lines(), ["hello!", ""]);
+        assert!(app.render().content.iter().any(|c| c.symbol() == "!"));
+        app.press(KeyCode::Esc);
+        assert!(app.state.ui.editing.is_none());
+        assert_eq!(contents(snippet), b"hello\n");
+
+        edit(&mut app, "!");
+        app.press_with(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        assert!(app.state.ui.editing.is_none());
+        assert!(EntrySource::get(&mut db.clone(), snippet).is_err());
+        let edited = app.selected_id().unwrap();
+        assert_ne!(edited, snippet);
+        assert_eq!(app.state.entries.loaded_entries[0].entry.id(), edited);
+        assert_eq!(contents(edited), b"hello!\n");
+
+        // Entries too big for a bucket are edited the same way.
+        app.press(KeyCode::Char('G'));
+        app.press(KeyCode::Char(' '));
+        edit(&mut app, "y");
+        app.press_with(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        let edited = app.selected_id().unwrap();
+        assert_eq!(contents(edited), format!("{big}y").into_bytes());
+    }
+
+    #[test]
+    fn searches_ignore_case_unless_asked_not_to() {
+        let db = MockDatabase::default();
+        let lower = db.add(RingKind::Main, *b"see github.com");
+        let upper = db.add(RingKind::Main, "ÉTÉ on GitHub".as_bytes());
+        let mut app = Harness::new(&db);
+        let results = |app: &Harness| {
+            app.state
+                .entries
+                .search_results
+                .iter()
+                .map(|e| e.entry.id())
+                .collect::<Vec<_>>()
+        };
+
+        app.press(KeyCode::Char('/'));
+        for c in "GitHub".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        assert_eq!(results(&app), [upper, lower]);
+        assert_eq!(app.state.ui.status.current(), Some("Search: 2 match(es)."));
+
+        app.press_with(KeyCode::Char('c'), KeyModifiers::ALT);
+        assert_eq!(results(&app), [upper]);
+        assert_eq!(app.state.ui.status.current(), Some("Search: 1 match(es)."));
+
+        app.press(KeyCode::Esc);
+        app.press(KeyCode::Esc);
+        app.press(KeyCode::Char('/'));
+        for c in "été".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        // Matching case is kept across searches.
+        assert!(results(&app).is_empty());
+        app.press_with(KeyCode::Char('c'), KeyModifiers::ALT);
+        assert_eq!(results(&app), [upper]);
+    }
+
+    #[test]
+    fn similar_entries_are_found_from_the_details() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"\xff\xfe binary");
+        db.add(RingKind::Main, *b"ls -la");
+        let source = db.add(RingKind::Main, *b"cargo  test   -p tui");
+        let similar = db.add(RingKind::Main, *b"cargo test -p tui --release");
+        let mut app = Harness::new(&db);
+        assert_eq!(app.selected_id(), Some(source));
+
+        app.press(KeyCode::Char('l'));
+        app.press(KeyCode::Char('*'));
+        let results = app
+            .state
+            .entries
+            .search_results
+            .iter()
+            .map(|e| e.entry.id())
+            .collect::<Vec<_>>();
+        assert_eq!(results, [similar]);
+        assert_eq!(app.state.ui.query.lines(), ["cargo test -p tui"]);
+        assert_eq!(app.state.ui.status.current(), Some("Search: 1 match(es)."));
+
+        app.press(KeyCode::Esc);
+        app.press(KeyCode::Esc);
+        app.press(KeyCode::Esc);
+        assert!(app.state.ui.search_state.is_none());
+        app.press(KeyCode::Char('G'));
+        app.press(KeyCode::Char('l'));
+        app.state.ui.status.clear();
+        app.press(KeyCode::Char('*'));
+        assert_eq!(app.state.ui.status.current(), Some("Nothing to match on."));
+    }
+
+    #[test]
+    fn unwrapped_details_scroll_sideways() {
+        let db = MockDatabase::default();
+        let line = (0..40).map(|i| format!("{i:02} ")).collect::<String>();
+        db.add(RingKind::Main, format!("{line}\nend").into_bytes());
+        let mut app = Harness::new(&db);
+        app.width = 80;
+        let screen = |app: &mut Harness| {
+            app.render();
+            app.settle();
+            let buffer = app.render();
+            buffer.content.iter().map(Cell::symbol).collect::<String>()
+        };
+
+        app.press(KeyCode::Char('l'));
+        assert!(screen(&mut app).contains("39"));
+
+        app.press(KeyCode::Char('w'));
+        let unwrapped = screen(&mut app);
+        assert!(unwrapped.contains("00 01") && !unwrapped.contains("39"));
+
+        // Scrolling stops once the end of the longest line is in view.
+        for _ in 0..100 {
+            app.press(KeyCode::Char('L'));
+        }
+        let scrolled = screen(&mut app);
+        assert!(scrolled.contains("38 39") && !scrolled.contains("09 10"));
+        let column = app.state.ui.detail_column;
+        assert!(scrolled.contains(&format!("from column {}", column + 1)));
+        assert!(usize::from(column) < line.len());
+
+        app.press(KeyCode::Char('H'));
+        assert_eq!(app.state.ui.detail_column, column - 8);
+        app.press(KeyCode::Char('w'));
+        let wrapped = screen(&mut app);
+        assert!(wrapped.contains("00 01") && wrapped.contains("39"));
+        assert!(!wrapped.contains("from column"));
+    }
+
+    #[test]
+    fn search_matches_are_highlighted() {
+        let db = MockDatabase::default();
+        let mut long = (0..20).map(|i| format!("line {i}\n")).collect::<String>();
+        long.push_str("a needle, a NEEDLE");
+        db.add(RingKind::Main, long.into_bytes());
+        db.add(RingKind::Main, *b"  two\n\n  n\xc3\xa9edles");
+        let mut app = Harness::new(&db);
+        let underlined_rows = |app: &mut Harness| {
+            let buffer = app.render();
+            buffer
+                .content
+                .chunks(usize::from(buffer.area.width))
+                .map(|row| {
+                    row.iter()
+                        .filter(|c| c.modifier.contains(Modifier::UNDERLINED))
+                        .map(Cell::symbol)
+                        .collect::<String>()
+                })
+                .filter(|row| !row.is_empty())
+                .collect::<Vec<_>>()
+        };
+
+        app.press(KeyCode::Char('/'));
+        for c in "NÉEDLE".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        assert_eq!(underlined_rows(&mut app), ["néedle"]);
+
+        app.press(KeyCode::Esc);
+        app.press(KeyCode::Esc);
+        app.press(KeyCode::Char('x'));
+        for c in "(?i)^|eed*".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        app.press(KeyCode::Enter);
+        app.press(KeyCode::Char('l'));
+        // The empty match at the start of every entry has nothing to show.
+        assert!(underlined_rows(&mut app).is_empty());
+
+        app.press(KeyCode::Char('j'));
+        // The details scroll down far enough to show the first match.
+        assert_eq!(underlined_rows(&mut app), ["eedEED"]);
+    }
+
+    #[test]
+    fn details_can_be_searched_in_place() {
+        let db = MockDatabase::default();
+        let mut text = "word ".repeat(60);
+        for i in 0..30 {
+            text.push_str(&format!("\nline {i}"));
+            match i {
+                12 => text.push_str(" needle"),
+                20 => text.push_str(" NEEDLE"),
+                28 => text.push_str(" Needle"),
+                _ => {}
+            }
+        }
+        db.add(RingKind::Main, text.into_bytes());
+        db.add(RingKind::Main, *b"\xff\xfe binary needle");
+        let mut app = Harness::new(&db);
+        app.width = 60;
+        let current = Modifier::UNDERLINED | Modifier::REVERSED;
+        let current_match = |app: &mut Harness| {
+            app.render()
+                .content
+                .iter()
+                .filter(|c| c.modifier.contains(current))
+                .map(Cell::symbol)
+                .collect::<String>()
+        };
+
+        app.press(KeyCode::Char('l'));
+        app.render();
+        app.press(KeyCode::Char('/'));
+        assert!(app.state.ui.finding);
+        for c in "needle".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        // The wrapped first line pushes the matches below their line numbers.
+        assert_eq!(current_match(&mut app), "needle");
+        app.press(KeyCode::Enter);
+        assert!(!app.state.ui.finding);
+
+        for expected in ["NEEDLE", "Needle", "needle"] {
+            app.press(KeyCode::Char('n'));
+            assert_eq!(current_match(&mut app), expected);
+        }
+        app.press(KeyCode::Char('N'));
+        assert_eq!(current_match(&mut app), "Needle");
+
+        // Esc ends the search before closing the details.
+        app.press(KeyCode::Esc);
+        assert_eq!(current_match(&mut app), "");
+        assert!(app.state.ui.details_requested.is_some());
+
+        app.press(KeyCode::Char('k'));
+        app.render();
+        app.press(KeyCode::Char('/'));
+        assert!(!app.state.ui.finding);
+        assert_eq!(app.state.ui.status.current(), Some("No text to search."));
+    }
+
+    #[test]
+    fn urls_colors_and_paths_are_styled() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"https://example.com");
+        db.add(RingKind::Main, *b"#ff8000");
+        db.add(RingKind::Main, *b"/ringboard/missing");
+        let mut app = Harness::new(&db);
+        let buffer = app.render();
+        let styled = |keep: fn(&Cell) -> bool| {
+            buffer
+                .content
+                .iter()
+                .filter(|&c| keep(c))
+                .map(Cell::symbol)
+                .collect::<String>()
+        };
+
+        assert_eq!(
+            styled(|c| c.modifier.contains(Modifier::UNDERLINED)),
+            "https://example.com"
+        );
+        assert!(styled(|c| c.fg == Color::Rgb(0xFF, 0x80, 0x00)).starts_with('■'));
+        assert!(styled(|c| c.modifier.contains(Modifier::DIM)).contains("/ringboard/missing"));
+    }
+
+    #[test]
+    fn json_can_be_pretty_printed() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *br#"{"a": [1, {}], "b": "x,{y"}"#);
+        let mut app = Harness::new(&db);
+        let rows = |app: &mut Harness| {
+            app.render();
+            app.settle();
+            let buffer = app.render();
+            buffer
+                .content
+                .chunks(usize::from(buffer.area.width))
+                .map(|row| row.iter().map(Cell::symbol).collect::<String>())
+                .map(|row| row.trim().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        app.press(KeyCode::Char('l'));
+        app.press(KeyCode::Char('p'));
+        let pretty = rows(&mut app);
+        for row in [r#""a": ["#, "1,", "{}", "],", r#""b": "x,{y""#] {
+            assert!(pretty.iter().any(|r| r == row), "{row} in {pretty:?}");
+        }
+
+        app.press(KeyCode::Char('p'));
+        assert!(!rows(&mut app).iter().any(|r| r == "1,"));
+        assert_eq!(pretty_json("[1, {]"), None);
+    }
+
+    #[test]
+    fn search_scope_is_shown_and_kept_across_searches() {
+        let db = MockDatabase::default();
+        let pie = db.add(RingKind::Main, *b"apple pie");
+        let tart = db.add(RingKind::Favorites, *b"apple tart");
+        let mut app = Harness::new(&db);
+        let results = |app: &Harness| {
+            app.state
+                .entries
+                .search_results
+                .iter()
+                .map(|e| e.entry.id())
+                .collect::<Vec<_>>()
+        };
+        let title = |app: &mut Harness| {
+            let buffer = app.render();
+            buffer
+                .content
+                .chunks(usize::from(buffer.area.width))
+                .map(|row| row.iter().map(Cell::symbol).collect::<String>())
+                .find(|row| row.contains("earch"))
+                .unwrap()
+                .trim_matches(['┌', '─', '┐', ' '])
+                .to_string()
+        };
+
+        app.press(KeyCode::Char('/'));
+        for c in "apple".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        assert_eq!(results(&app), [tart, pie]);
+        assert_eq!(title(&mut app), "Search (all)");
+
+        app.press_with(KeyCode::Char('f'), KeyModifiers::ALT);
+        assert_eq!(results(&app), [pie]);
+        assert_eq!(title(&mut app), "Search (main)");
+
+        // Results can be rescoped without going back to the search box.
+        app.press(KeyCode::Enter);
+        app.press_with(KeyCode::Char('f'), KeyModifiers::ALT);
+        assert_eq!(results(&app), [tart]);
+        assert_eq!(title(&mut app), "Search (favorites)");
+
+        app.press(KeyCode::Esc);
+        app.press(KeyCode::Char('x'));
+        for c in "pie|tart".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        assert_eq!(results(&app), [tart]);
+        assert_eq!(title(&mut app), "RegEx search (favorites)");
+
+        // Mock entries have no mime type, which makes them text.
+        app.width = 60;
+        app.press_with(KeyCode::Char('m'), KeyModifiers::ALT);
+        assert_eq!(results(&app), [tart]);
+        assert_eq!(title(&mut app), "RegEx search (favorites, text only)");
+        app.press_with(KeyCode::Char('m'), KeyModifiers::ALT);
+        assert!(results(&app).is_empty());
+        assert_eq!(title(&mut app), "RegEx search (favorites, images only)");
+        app.press(KeyCode::Enter);
+        app.press_with(KeyCode::Char('m'), KeyModifiers::ALT);
+        assert_eq!(results(&app), [tart]);
+        assert_eq!(title(&mut app), "RegEx search (favorites)");
+    }
+
+    #[test]
+    fn search_results_are_newest_first_unless_favorites_are_pinned() {
+        let db = MockDatabase::default();
+        let tart = db.add(RingKind::Favorites, *b"apple tart");
+        let cake = db.add(RingKind::Favorites, *b"apple cake");
+        let pie = db.add(RingKind::Main, *b"apple pie");
+        let mut app = Harness::new(&db);
+        let results = |app: &Harness| {
+            app.state
+                .entries
+                .search_results
+                .iter()
+                .map(|e| e.entry.id())
+                .collect::<Vec<_>>()
+        };
+
+        app.press(KeyCode::Char('/'));
+        for c in "apple".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        assert_eq!(results(&app), [cake, pie, tart]);
+
+        app.press_with(KeyCode::Char('p'), KeyModifiers::ALT);
+        assert_eq!(results(&app), [cake, tart, pie]);
+        let buffer = app.render();
+        assert!(
+            buffer
+                .content
+                .iter()
+                .map(Cell::symbol)
+                .collect::<String>()
+                .contains("Search (all, favorites first)")
+        );
+
+        // Pinning sticks around for the next search.
+        app.press_with(KeyCode::Char('u'), KeyModifiers::CONTROL);
+        for c in "pie".chars() {
+            app.press(KeyCode::Char(c));
+        }
+        assert!(app.state.ui.favorites_first);
+        assert_eq!(results(&app), [pie]);
+    }
+
+    #[test]
+    fn redraws_reuse_rendered_rows() {
+        let db = MockDatabase::default();
+        for i in 0..1000 {
+            db.add(
+                RingKind::Favorites,
+                format!("favorite number {i}").into_bytes(),
+            );
         }
-        _ => {}
+        let mut app = Harness::new(&db);
+        assert_eq!(app.state.entries.loaded_entries.len(), 1000);
+
+        let frame = app.render();
+        let key = app.state.entries.rows.key;
+        assert!(key.is_some());
+        app.press(KeyCode::Down);
+        app.render();
+        assert!(app.state.entries.rows.key == key);
+        app.press(KeyCode::Up);
+        assert_eq!(app.render(), frame);
+
+        apply_filter(&mut app.state.entries, None, "number 99", None);
+        app.render();
+        assert!(app.state.entries.rows.key != key);
+        assert_eq!(app.state.entries.rows.list.len(), 11);
+
+        apply_filter(&mut app.state.entries, None, "", None);
+        let mut redraw = |invalidate: bool| {
+            let start = Instant::now();
+            for _ in 0..20 {
+                if invalidate {
+                    app.state.entries.generation += 1;
+                }
+                app.render();
+            }
+            start.elapsed()
+        };
+        let rebuilt = redraw(true);
+        let cached = redraw(false);
+        assert!(cached < rebuilt, "{cached:?} cached vs {rebuilt:?} rebuilt");
     }
-    if ui.details_requested.is_some() {
-        maybe_get_details(entries, ui, requests);
+
+    #[test]
+    fn split_mode_moves_favorites_across_panes() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Favorites, *b"snippet");
+        db.add(RingKind::Main, *b"plain");
+        let moved = db.add(RingKind::Main, *b"command");
+        let mut app = Harness::new(&db);
+        app.state.entries.loaded_state.select(Some(1));
+        assert_eq!(app.selected_id(), Some(moved));
+
+        app.render();
+        app.press(KeyCode::Char('|'));
+        assert_eq!(app.state.ui.split, None);
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some(strings::get(Str::SplitTooNarrow))
+        );
+
+        app.width = 80;
+        app.render();
+        app.press(KeyCode::Char('|'));
+        assert_eq!(app.state.ui.split, Some(RingKind::Main));
+        assert_eq!(app.selected_id(), Some(moved));
+        assert_eq!(app.highlighted_rows(), ["command"]);
+
+        app.press(KeyCode::Char('f'));
+        assert_eq!(app.state.ui.split, Some(RingKind::Favorites));
+        assert_eq!(app.highlighted_rows(), ["command"]);
+        assert_eq!(
+            app.state.entries.loaded().pane(Some(RingKind::Main)).len(),
+            1
+        );
+        assert_eq!(
+            app.state
+                .entries
+                .loaded()
+                .pane(Some(RingKind::Favorites))
+                .len(),
+            2
+        );
+
+        app.press(KeyCode::Tab);
+        assert_eq!(app.highlighted_rows(), ["plain"]);
+
+        app.press(KeyCode::Char('|'));
+        assert_eq!(app.state.ui.split, None);
+        assert_eq!(app.highlighted_rows(), ["plain"]);
     }
-    false
-}
 
-struct AppWrapper<'a> {
-    requests: &'a Sender<Command>,
-    state: &'a mut State,
-}
+    #[test]
+    fn favorites_are_pinned_above_the_main_ring() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"plain");
+        db.add(RingKind::Main, *b"command");
+        db.add(RingKind::Favorites, *b"snippet");
+        let mut app = Harness::new(&db);
+        let lines = |app: &mut Harness| {
+            let buffer = app.render();
+            buffer
+                .content
+                .chunks(usize::from(buffer.area.width))
+                .map(|row| row.iter().map(Cell::symbol).collect::<String>())
+                .map(|row| row.trim_matches([' ', '─']).to_string())
+                .filter(|row| !row.is_empty())
+                // Past the header and list title.
+                .skip(2)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines(&mut app),
+            ["1 snippet", "Main ring", "2 command", "3 plain"]
+        );
 
-impl AppWrapper<'_> {
-    fn draw(&mut self, terminal: &mut Terminal<impl Backend>) -> io::Result<()> {
-        terminal.draw(|f| f.render_widget(self, f.area()))?;
-        Ok(())
+        // The separator isn't a row of its own.
+        app.state.entries.loaded_state.select(Some(0));
+        assert_eq!(app.highlighted_rows(), ["snippet"]);
+        app.press(KeyCode::Char('j'));
+        assert_eq!(app.highlighted_rows(), ["command"]);
+
+        app.press(KeyCode::Char('f'));
+        assert_eq!(
+            lines(&mut app),
+            [
+                "1 command",
+                "2 snippet",
+                "Main ring",
+                "3 plain",
+                "Favorited."
+            ]
+        );
+        assert_eq!(app.highlighted_rows(), ["command"]);
+        app.press(KeyCode::Char('G'));
+        app.press(KeyCode::Char('f'));
+        // Without main ring entries there's nothing to separate.
+        assert_eq!(
+            lines(&mut app),
+            ["1 plain", "2 command", "3 snippet", "Favorited."]
+        );
+        assert_eq!(app.highlighted_rows(), ["plain"]);
     }
-}
 
-impl Widget for &mut AppWrapper<'_> {
-    fn render(self, area: Rect, buf: &mut Buffer) {
-        let State { entries: _, ui } = &self.state;
-        let has_error = ui.last_error.is_some();
+    #[test]
+    fn marked_entries_are_deleted_together() {
+        let db = MockDatabase::default();
+        let [first, second, third, fourth, fifth] =
+            [*b"first", *b"secnd", *b"third", *b"forth", *b"fifth"]
+                .map(|text| db.add(RingKind::Main, text));
+        let mut app = Harness::new(&db);
+        let marked = |app: &Harness| {
+            let mut marked = app.state.entries.marked.iter().copied().collect::<Vec<_>>();
+            marked.sort_unstable();
+            marked
+        };
 
-        let [header_area, main_area, footer_area] = Layout::vertical([
-            Constraint::Length(1),
-            Constraint::Min(0),
-            Constraint::Length(if ui.show_help { 3 } else { 0 }),
-        ])
-        .areas(area);
+        app.state.entries.loaded_state.select(Some(1));
+        app.press(KeyCode::Char('v'));
+        app.press(KeyCode::Down);
+        app.press(KeyCode::Down);
+        app.press(KeyCode::Char('V'));
+        assert_eq!(marked(&app), [second, third, fourth]);
 
-        let [entry_list_area, _padding, selected_entry_area] =
-            if ui.details_requested.is_none() && !has_error {
-                Layout::vertical([
-                    Constraint::Min(0),
-                    Constraint::Length(0),
-                    Constraint::Length(0),
-                ])
-            } else if area.width <= area.height * 3 {
-                Layout::vertical([
-                    Constraint::Percentage(50),
-                    Constraint::Length(0),
-                    Constraint::Percentage(50),
-                ])
-            } else {
-                Layout::horizontal([
-                    Constraint::Percentage(50),
-                    Constraint::Length(1),
-                    Constraint::Percentage(50),
-                ])
-            }
-            .areas(main_area);
+        // Marks follow ids rather than rows through a reload.
+        let newest = db.add(RingKind::Main, *b"sixth");
+        app.execute(Command::RefreshDb);
+        assert_eq!(marked(&app), [second, third, fourth]);
 
-        AppWrapper::render_title(header_area, buf);
-        self.render_entries(entry_list_area, buf);
-        if has_error {
-            self.render_error(selected_entry_area, buf);
-        } else {
-            self.render_selected_entry(selected_entry_area, buf);
-        }
-        AppWrapper::render_footer(footer_area, buf);
+        app.press(KeyCode::Char('d'));
+        let loaded = app
+            .state
+            .entries
+            .loaded_entries
+            .iter()
+            .map(|e| e.entry.id())
+            .collect::<Vec<_>>();
+        assert_eq!(loaded, [newest, fifth, first]);
+        assert!(marked(&app).is_empty());
+        assert!(app.state.ui.outstanding_request.is_none());
+
+        // Esc clears the marks instead of quitting.
+        app.press(KeyCode::Char('v'));
+        assert_eq!(marked(&app).len(), 1);
+        app.press(KeyCode::Esc);
+        assert!(marked(&app).is_empty());
     }
-}
 
-fn ui_entry_line(UiEntry { entry: _, cache }: &UiEntry) -> Line {
-    match cache {
-        UiEntryCache::Text { one_liner } => Line::raw(&**one_liner),
-        UiEntryCache::Image => Line::raw("Image: open details to view.").italic(),
-        UiEntryCache::Binary { mime_type } => {
-            Line::raw(format!("Unable to display format of type {mime_type:?}.")).italic()
-        }
-        UiEntryCache::Error(e) => Line::raw(format!("Error: {e}\nDetails: {e:#?}")).italic(),
+    #[test]
+    fn duplicates_are_deleted_once_confirmed() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"copy");
+        let unique = db.add(RingKind::Main, *b"unique");
+        db.add(RingKind::Main, *b"copy");
+        let favorite = db.add(RingKind::Favorites, *b"copy");
+        let mut app = Harness::new(&db);
+        let loaded = |app: &Harness| {
+            let mut loaded = app
+                .state
+                .entries
+                .loaded_entries
+                .iter()
+                .map(|e| e.entry.id())
+                .collect::<Vec<_>>();
+            loaded.sort_unstable();
+            loaded
+        };
+
+        app.press(KeyCode::Char('D'));
+        app.press(KeyCode::Char('n'));
+        assert_eq!(loaded(&app).len(), 4);
+
+        // The favorite wins over newer copies.
+        app.press(KeyCode::Char('D'));
+        app.press(KeyCode::Char('y'));
+        assert_eq!(loaded(&app), [favorite, unique]);
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some("Deleted 2 duplicate(s) of 1 entry(ies).")
+        );
     }
-}
 
-impl AppWrapper<'_> {
-    fn render_entries(&mut self, area: Rect, buf: &mut Buffer) {
-        let Self {
-            state: State { entries, ui },
-            requests: _,
-        } = self;
+    #[test]
+    fn notifications_take_turns_and_fade() {
+        let mut status = StatusLine::default();
+        status.show("first");
+        status.show("second");
+        status.show("second");
+        assert_eq!(status.current(), Some("first"));
 
-        let [search_area, entries_area] = Layout::vertical([
-            Constraint::Length(if ui.search_state.is_some() { 3 } else { 0 }),
-            Constraint::Min(0),
-        ])
-        .areas(area);
+        let shown_at = status.shown_at.unwrap();
+        assert!(!status.expire(shown_at + StatusLine::TIMEOUT / 2));
+        assert_eq!(status.current(), Some("first"));
+        let later = shown_at + StatusLine::TIMEOUT;
+        assert!(status.expire(later));
+        assert_eq!(status.current(), Some("second"));
+        // The next one gets its full turn.
+        assert!(!status.expire(later + StatusLine::TIMEOUT / 2));
 
-        if let &Some(SearchState { focused, kind }) = &ui.search_state {
-            ui.query.set_block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_style(if focused {
-                        Style::new().bold()
-                    } else {
-                        Style::default()
-                    })
-                    .title(if ui.queued_searches > 0 {
-                        "Searching…"
-                    } else {
-                        match kind {
-                            SearchKind::Plain => "Search",
-                            SearchKind::Regex => "RegEx search",
-                            SearchKind::Mime => "Mime type search",
-                        }
-                    }),
-            );
-            ui.query.render(search_area, buf);
-        }
+        // Prompts wait for an answer however long it takes.
+        status.prompt("sure?");
+        assert!(!status.expire(later + StatusLine::TIMEOUT));
+        assert_eq!(status.current(), Some("sure?"));
+        status.clear();
+        assert_eq!(status.current(), None);
+        assert!(!status.expire(later + StatusLine::TIMEOUT * 2));
+    }
 
-        let outer_block = Block::new()
-            .title_alignment(Alignment::Center)
-            .borders(Borders::TOP)
-            .title("Entries");
-        let inner_block = Block::new().borders(Borders::NONE);
-        let inner_area = outer_block.inner(entries_area);
+    #[test]
+    fn deletes_can_be_undone_once() {
+        let db = MockDatabase::default();
+        let older = db.add(RingKind::Main, *b"older");
+        let newer = db.add(RingKind::Main, *b"newer");
+        let mut app = Harness::new(&db);
+        let loaded = |app: &Harness| {
+            app.state
+                .entries
+                .loaded_entries
+                .iter()
+                .map(|e| e.entry.id())
+                .collect::<Vec<_>>()
+        };
 
-        outer_block.render(entries_area, buf);
+        app.press(KeyCode::Char('u'));
+        assert_eq!(app.state.ui.status.current(), Some("Nothing to undo."));
 
-        if active_entries!(entries, ui).is_empty() {
-            Line::raw("Nothing to see here…")
-                .italic()
-                .render(inner_area, buf);
-        } else {
-            StatefulWidget::render(
-                List::new(active_entries!(entries, ui).iter().map(ui_entry_line))
-                    .block(inner_block)
-                    .highlight_style(
-                        Style::default()
-                            .add_modifier(Modifier::BOLD)
-                            .add_modifier(Modifier::REVERSED),
-                    )
-                    .highlight_spacing(HighlightSpacing::Always),
-                inner_area,
-                buf,
-                active_list_state!(entries, ui),
-            );
+        app.state.entries.loaded_state.select(Some(0));
+        app.press(KeyCode::Char('d'));
+        assert_eq!(loaded(&app), [older]);
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some(format!("Deleted entry {} (u to undo).", format_id(newer)).as_str())
+        );
+
+        // Restored entries come back at the front under a new id.
+        app.press(KeyCode::Char('u'));
+        let restored = composite_id(RingKind::Main, 2);
+        assert_eq!(loaded(&app), [restored, older]);
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some(format!("Restored entry {restored}.").as_str())
+        );
+
+        app.press(KeyCode::Char('u'));
+        assert_eq!(app.state.ui.status.current(), Some("Nothing to undo."));
+        assert_eq!(loaded(&app), [restored, older]);
+    }
+
+    #[test]
+    fn tiny_terminals_ask_for_more_room() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"hello");
+        let mut app = Harness::new(&db);
+        app.press(KeyCode::Char('l'));
+
+        for (width, height) in [(0, 10), (10, 0), (1, 1), (19, 10), (40, 4)] {
+            app.width = width;
+            app.height = height;
+            app.render();
         }
+        app.width = 50;
+        app.height = 3;
+        let buffer = app.render();
+        let symbols = buffer.content.iter().map(Cell::symbol).collect::<String>();
+        assert!(symbols.starts_with("Terminal too small (need 20x5)."));
     }
 
-    fn render_selected_entry(&mut self, area: Rect, buf: &mut Buffer) {
-        let Self {
-            state: State { entries, ui },
-            requests,
-        } = self;
-        if area.is_empty() {
-            return;
+    #[test]
+    fn moving_through_an_empty_list_selects_nothing() {
+        let db = MockDatabase::default();
+        let mut app = Harness::new(&db);
+
+        for key in ['k', 'j'] {
+            app.press(KeyCode::Char(key));
+            assert_eq!(app.state.entries.loaded_state.selected(), None);
         }
-        let Some(UiEntry { entry, cache }) = selected_entry!(entries, ui) else {
-            return;
-        };
+        app.render();
 
-        let outer_block = {
-            let mime_type = ui
-                .detailed_entry
-                .as_ref()
-                .and_then(|r| r.as_ref().ok())
-                .map_or("", |d| &*d.mime_type);
+        // Searching has the box to move up into instead.
+        app.press(KeyCode::Char('/'));
+        app.press(KeyCode::Esc);
+        app.press(KeyCode::Char('k'));
+        assert_eq!(app.state.entries.loaded_state.selected(), None);
+        assert!(matches!(
+            app.state.ui.search_state,
+            Some(SearchState { focused: true, .. })
+        ));
+    }
 
-            Block::new()
-                .borders(Borders::TOP)
-                .title_alignment(Alignment::Center)
-                .title({
-                    ui.cache.clear();
-                    write!(
-                        ui.cache,
-                        "{} ({}",
-                        match entry.ring() {
-                            RingKind::Favorites => "Favorite entry",
-                            RingKind::Main => "Entry",
-                        },
-                        entry.id()
-                    )
-                    .unwrap();
-                    if mime_type.is_empty() {
-                        write!(ui.cache, ")")
-                    } else {
-                        write!(ui.cache, "; {mime_type})")
-                    }
-                    .unwrap();
-                    ui.cache.as_str()
-                })
+    #[test]
+    fn dashboard_summarizes_entries_and_jumps_to_large_ones() {
+        let db = MockDatabase::default();
+        let now = SystemTime::now();
+        let old = db.add(RingKind::Main, *b"old");
+        db.set_timestamp(old, now - Duration::from_secs(2 * 24 * 60 * 60 + 30));
+        db.add(RingKind::Main, *b"copy");
+        db.add(RingKind::Main, *b"copy");
+        let big = db.add(RingKind::Main, vec![b'x'; 5000]);
+        db.set_timestamp(big, now);
+        let mut app = Harness::new(&db);
+        app.width = 80;
+        app.state.entries.loaded_state.select(Some(3));
+
+        app.press(KeyCode::Char('S'));
+        let dashboard = app.state.ui.dashboard.as_ref().unwrap();
+        let stats = dashboard.stats.as_ref().unwrap();
+        assert!(stats.is_complete());
+        assert_eq!((stats.total, stats.duplicates), (4, 1));
+        assert_eq!(stats.adds_per_day[..3], [1, 0, 1]);
+        assert_eq!(stats.adds_per_day[3..].iter().sum::<u32>(), 0);
+        assert_eq!(stats.mime_types.len(), 1);
+        let untyped = &stats.mime_types[0];
+        assert_eq!((untyped.count, untyped.bytes), (4, 5011));
+        assert_eq!(stats.largest[0].id, big);
+        let frame = app.render();
+        let text = frame.content.iter().map(|c| c.symbol()).collect::<String>();
+        assert!(text.contains(strings::get(Str::DashboardLargest)));
+
+        app.press(KeyCode::Enter);
+        assert!(app.state.ui.dashboard.is_none());
+        assert_eq!(app.selected_id(), Some(big));
+
+        // Closing the dashboard leaves the entries alone.
+        app.press(KeyCode::Char('S'));
+        assert!(app.state.ui.dashboard.is_some());
+        app.press(KeyCode::Esc);
+        assert!(app.state.ui.dashboard.is_none());
+        assert_eq!(app.selected_id(), Some(big));
+    }
+
+    #[test]
+    fn error_report_can_be_copied() {
+        let db = MockDatabase::default();
+        let missing = composite_id(RingKind::Main, 42);
+        let mut app = Harness::new(&db);
+
+        app.press(KeyCode::Char('E'));
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some(strings::get(Str::NoErrorReport))
+        );
+
+        app.execute(Command::Delete(missing));
+        assert!(app.state.ui.last_error.is_some());
+        app.press(KeyCode::Char('E'));
+        assert!(app.state.ui.last_error.is_none());
+        assert_eq!(app.state.ui.error_report_scroll, Some(0));
+        app.render();
+
+        app.press(KeyCode::Char('y'));
+        let [copied] = &*db.pasted() else {
+            panic!("{:?}", db.pasted());
         };
-        let inner_block = Block::new()
-            .borders(Borders::NONE)
-            .padding(Padding::horizontal(1));
-        let inner_area = outer_block.inner(area);
+        let copied = str::from_utf8(copied).unwrap();
+        assert!(copied.starts_with("ID not found\n"), "{copied}");
+        assert!(copied.contains("(mock)"), "{copied}");
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some(strings::get(Str::ErrorReportCopied))
+        );
 
-        outer_block.render(area, buf);
+        // Errors while the report is open add to it instead of replacing it.
+        app.execute(Command::Delete(missing));
+        assert!(app.state.ui.last_error.is_none());
+        let report = app.state.ui.error_report.as_deref().unwrap();
+        assert!(report.starts_with(&copied[..30]), "{report}");
+        assert!(report.contains("Another error"), "{report}");
 
-        let error = ui
-            .detailed_entry
-            .as_ref()
-            .and_then(|r| r.as_ref().err())
-            .map_or(String::new(), |e| format!("Error: {e}\nDetails: {e:#?}"));
+        app.press(KeyCode::Esc);
+        assert_eq!(app.state.ui.error_report_scroll, None);
+    }
 
-        if matches!(cache, UiEntryCache::Image) {
-            if let Some(ImageState::Loaded(image_state)) = &mut ui.detail_image_state {
-                StatefulImage::new(None).render(inner_area, buf, image_state);
-            } else {
-                Paragraph::new("Loading…")
-                    .block(inner_block)
-                    .render(inner_area, buf);
+    #[test]
+    fn older_entries_are_paged_in_at_the_bottom() {
+        let db = MockDatabase::default();
+        let mut ids = (0..150)
+            .map(|i| db.add(RingKind::Main, format!("entry {i}").into_bytes()))
+            .collect::<Vec<_>>();
+        ids.reverse();
+        let mut app = Harness::new(&db);
+        assert_eq!(app.state.entries.loaded_entries.len(), PAGE_SIZE);
+
+        assert_eq!(app.selected_id(), Some(ids[1]));
+        for _ in 2..PAGE_SIZE {
+            app.press(KeyCode::Char('j'));
+        }
+        assert_eq!(app.selected_id(), Some(ids[PAGE_SIZE - 1]));
+        app.press(KeyCode::Char('j'));
+        assert_eq!(app.state.entries.loaded_entries.len(), ids.len());
+        assert_eq!(app.selected_id(), Some(ids[PAGE_SIZE - 1]));
+        app.press(KeyCode::Char('j'));
+        assert_eq!(app.selected_id(), Some(ids[PAGE_SIZE]));
+
+        // Reloading keeps the paged in entries.
+        app.execute(Command::LoadFirstPage);
+        assert_eq!(app.state.entries.loaded_entries.len(), ids.len());
+        assert_eq!(app.selected_id(), Some(ids[PAGE_SIZE]));
+
+        // Running out of entries wraps around as before.
+        for _ in PAGE_SIZE..ids.len() - 1 {
+            app.press(KeyCode::Char('j'));
+        }
+        assert_eq!(app.selected_id(), ids.last().copied());
+        app.press(KeyCode::Char('j'));
+        assert!(app.state.ui.loaded_all);
+        assert_eq!(app.selected_id(), ids.last().copied());
+        app.press(KeyCode::Char('j'));
+        assert_eq!(app.selected_id(), Some(ids[0]));
+        assert_eq!(app.state.entries.loaded_entries.len(), ids.len());
+    }
+
+    #[test]
+    fn entries_can_be_jumped_to() {
+        let db = MockDatabase::default();
+        let mut ids = (0..250)
+            .map(|i| db.add(RingKind::Main, format!("entry {i}").into_bytes()))
+            .collect::<Vec<_>>();
+        ids.reverse();
+        let mut app = Harness::new(&db);
+        let jump = |app: &mut Harness, id: &str| {
+            app.press(KeyCode::Char(':'));
+            for c in id.chars() {
+                app.press(KeyCode::Char(c));
             }
-            if ui.detail_image_state.is_none() {
-                ui.detail_image_state = Some(ImageState::Requested(entry.id()));
-                let _ = requests.send(Command::LoadImage(entry.id()));
+            app.press(KeyCode::Enter);
+        };
+
+        app.press(KeyCode::Char('G'));
+        assert_eq!(app.selected_id(), Some(ids[PAGE_SIZE - 1]));
+        app.press(KeyCode::Char('g'));
+        assert_eq!(app.selected_id(), Some(ids[0]));
+
+        app.render();
+        let page = usize::from(app.state.ui.page_height);
+        assert!(page > 1);
+        app.press(KeyCode::PageDown);
+        assert_eq!(app.selected_id(), Some(ids[page]));
+        app.press_with(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        assert_eq!(app.selected_id(), Some(ids[2 * page]));
+        app.press_with(KeyCode::Char('u'), KeyModifiers::CONTROL);
+        app.press(KeyCode::PageUp);
+        assert_eq!(app.selected_id(), Some(ids[0]));
+
+        // Entries that haven't been paged in yet are loaded up to the target.
+        jump(&mut app, &ids[220].to_string());
+        assert_eq!(app.selected_id(), Some(ids[220]));
+        assert_eq!(app.state.entries.loaded_entries.len(), 221);
+        assert!(app.state.ui.page_requested.is_none());
+        jump(&mut app, &format_id(ids[5]));
+        assert_eq!(app.selected_id(), Some(ids[5]));
+
+        for (typed, status) in [
+            ("nope", "Unknown entry: nope"),
+            ("123456789", "Unknown entry: fav-123456789"),
+            ("main-9999", "Unknown entry: main-9999"),
+        ] {
+            jump(&mut app, typed);
+            assert_eq!(app.state.ui.status.current(), Some(status));
+            assert_eq!(app.selected_id(), Some(ids[5]));
+        }
+    }
+
+    #[test]
+    fn corrupted_image_shows_error() {
+        let db = MockDatabase::default();
+        let id = db.add(RingKind::Main, *b"\x89PNG\r\n\x1a\ntruncated");
+        let mut app = Harness::new(&db);
+
+        app.state.ui.detail_image_state = Some(ImageState::Requested {
+            id,
+            token: None,
+            progress: None,
+        });
+        app.execute(Command::LoadImage { id, max_size: None });
+
+        assert!(matches!(
+            app.state.ui.detail_image_state,
+            Some(ImageState::Failed(_))
+        ));
+    }
+
+    #[test]
+    fn stale_image_progress_is_ignored() {
+        let db = MockDatabase::default();
+        let old = db.add(RingKind::Main, *b"\x89PNG\r\n\x1a\ntruncated");
+        let new = db.add(RingKind::Main, *b"\x89PNG\r\n\x1a\ntruncated");
+        let mut app = Harness::new(&db);
+
+        app.state.ui.detail_image_state = Some(ImageState::Requested {
+            id: new,
+            token: None,
+            progress: None,
+        });
+        app.execute(Command::LoadImage {
+            id: old,
+            max_size: None,
+        });
+
+        assert!(matches!(
+            app.state.ui.detail_image_state,
+            Some(ImageState::Requested {
+                id,
+                token: None,
+                progress: None,
+            }) if id == new
+        ));
+    }
+
+    #[test]
+    fn selection_moves_to_neighbor_of_removed_entry() {
+        // Pages as a controller would send them, with entries dropped in between.
+        let pages: [&[u64]; 4] = [&[1, 2, 3, 4], &[1, 2, 4], &[1, 2], &[]];
+
+        let mut selected = (2, 3);
+        let mut results = Vec::new();
+        for ids in &pages[1..] {
+            let result = find_selection(ids.iter().copied(), selected);
+            if let Ok(index) | Err(Some(index)) = result {
+                selected = (index, ids[index]);
             }
-        } else {
-            Paragraph::new(ui.detailed_entry.as_ref().map_or("Loading…", |r| match r {
-                Ok(DetailedEntry {
-                    mime_type: _,
-                    full_text,
-                }) => full_text.as_deref().unwrap_or("Binary data."),
-                Err(_) => &error,
-            }))
-            .block(inner_block)
-            .wrap(Wrap { trim: false })
-            .scroll((ui.detail_scroll, 0))
-            .render(inner_area, buf);
+            results.push(result);
         }
+
+        assert_eq!(results, [Err(Some(2)), Err(Some(1)), Err(None)]);
     }
 
-    fn render_title(area: Rect, buf: &mut Buffer) {
-        Paragraph::new(concat!("Ringboard v", env!("CARGO_PKG_VERSION")))
-            .bold()
-            .centered()
-            .render(area, buf);
+    #[test]
+    fn selection_follows_entry_id() {
+        assert_eq!(find_selection([9, 1, 2, 3].into_iter(), (2, 3)), Ok(3));
     }
 
-    fn render_error(&self, area: Rect, buf: &mut Buffer) {
-        if area.is_empty() {
-            return;
+    #[test]
+    fn entries_removed_between_messages_close_their_details() {
+        let db = MockDatabase::default();
+        let oldest = db.add(RingKind::Main, *b"oldest");
+        let middle = db.add(RingKind::Main, *b"middle");
+        let newest = db.add(RingKind::Main, *b"newest");
+        let mut app = Harness::new(&db);
+        app.state.entries.loaded_state.select(Some(1));
+        app.press(KeyCode::Char('l'));
+        assert_eq!(app.state.ui.details_requested, Some(middle));
+        assert!(app.state.ui.detailed_entry.is_some());
+
+        // Another client deletes the entry before its details are fetched again.
+        let mut server = db.clone();
+        assert!(server.remove(middle).unwrap().error.is_none());
+        app.execute(Command::GetDetails {
+            id: middle,
+            with_text: true,
+        });
+        assert_eq!(app.state.ui.details_requested, None);
+        assert!(app.state.ui.detailed_entry.is_none());
+        assert_eq!(app.selected_id(), Some(oldest));
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some(strings::get(Str::EntryRemoved))
+        );
+
+        // Or before the list is reloaded.
+        app.state.ui.status.clear();
+        app.press(KeyCode::Char('l'));
+        assert_eq!(app.state.ui.details_requested, Some(oldest));
+        assert!(server.remove(oldest).unwrap().error.is_none());
+        app.execute(Command::RefreshDb);
+        assert_eq!(app.state.ui.details_requested, None);
+        assert_eq!(app.selected_id(), Some(newest));
+        assert_eq!(
+            app.state.ui.status.current(),
+            Some(strings::get(Str::EntryRemoved))
+        );
+    }
+
+    #[test]
+    fn wrapping_breaks_between_words() {
+        assert_eq!(wrapped_lines("hello world", 8), ["hello ", "world"]);
+        assert_eq!(wrapped_lines("abcdefgh ij", 4), ["abcd", "efgh", " ij"]);
+        assert_eq!(wrapped_lines("a\n\nb", 4), ["a", "", "b"]);
+        assert!(wrapped_lines("", 4).is_empty());
+    }
+
+    #[test]
+    fn hex_dumps_fit_their_width() {
+        assert_eq!(hex_dump(b"Hi\0", 29), "00000000  48 69 00     |Hi.|");
+        assert_eq!(
+            hex_dump(b"0123456789", 45),
+            "00000000  30 31 32 33 34 35 36 37  |01234567|\n00000008  38 39                    \
+             |89|"
+        );
+        assert_eq!(
+            binary_preview(b"ab", 2048, true, 80),
+            "ab\n\nShowing the first 2 B of 2.0 KiB."
+        );
+    }
+
+    #[test]
+    fn wrapping_measures_wide_chars() {
+        assert_eq!(
+            wrapped_lines("日本語テキスト", 6),
+            ["日本語", "テキス", "ト"]
+        );
+        assert_eq!(wrapped_lines("a日本", 4), ["a日", "本"]);
+        assert_eq!(wrapped_lines("日本", 1), ["日", "本"]);
+        assert_eq!(wrapped_lines("ab 日本語", 6), ["ab ", "日本語"]);
+    }
+
+    #[test]
+    fn verify_cli() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn env_flags_share_truthiness() {
+        for on in ["1", "on", "yes", "TRUE", "anything"] {
+            assert_eq!(flag_value(on), Some(true), "{on}");
         }
-        let Some(error) = &self.state.ui.last_error else {
-            return;
+        for off in ["0", "off", "Off", "false", "no", " 0 "] {
+            assert_eq!(flag_value(off), Some(false), "{off}");
+        }
+        assert_eq!(flag_value(""), None);
+    }
+
+    #[test]
+    fn tutorial_is_dismissed_for_good() {
+        let db = MockDatabase::default();
+        db.add(RingKind::Main, *b"entry");
+        let mut app = Harness::new(&db);
+        let state_file = env::temp_dir().join(format!("ringboard-tui-state-{}", process::id()));
+        assert!(tutorial_mode(&state_file, false, false));
+        assert!(!tutorial_mode(&state_file, true, false));
+        app.state.ui.tutorial = Some(0);
+        app.state.ui.state_file = Some(state_file.clone());
+
+        let title =
+            |page: usize| strings::fill(Str::TutorialTitle, &[&page, &TUTORIAL_PAGES.len()]);
+        let rendered = |app: &mut Harness| {
+            app.render()
+                .content
+                .iter()
+                .map(Cell::symbol)
+                .collect::<String>()
         };
+        app.press(KeyCode::Left);
+        assert!(rendered(&mut app).contains(&title(1)));
+        for _ in 0..TUTORIAL_PAGES.len() + 1 {
+            app.press(KeyCode::Right);
+        }
+        assert!(rendered(&mut app).contains(&title(TUTORIAL_PAGES.len())));
+        // Other keys, like q to quit, are ignored while the tutorial is open.
+        app.press(KeyCode::Char('q'));
 
-        let outer_block = Block::new()
-            .borders(Borders::TOP)
-            .border_style(Style::new().bold())
-            .title_alignment(Alignment::Center)
-            .title(format!("Error: {error}"));
-        let inner_block = Block::new().borders(Borders::NONE);
-        let inner_area = outer_block.inner(area);
+        app.press(KeyCode::Enter);
+        assert_eq!(app.state.ui.tutorial, None);
+        assert!(!rendered(&mut app).contains(&title(TUTORIAL_PAGES.len())));
+        assert!(!tutorial_mode(&state_file, false, false));
+        assert!(tutorial_mode(&state_file, false, true));
+        fs::remove_file(state_file).unwrap();
+    }
 
-        outer_block.render(area, buf);
+    #[test]
+    fn sessions_survive_being_saved_and_bad_files_are_ignored() {
+        let session = Session {
+            selected: Some(42),
+            query: "a = b ".to_string(),
+            kind: SearchKind::Regex,
+            details: true,
+        };
+        assert_eq!(Session::parse(&session.to_string()), Some(session));
+        assert_eq!(
+            Session::parse(&Session::default().to_string()),
+            Some(Session::default())
+        );
 
-        Paragraph::new(format!("{error:#?}"))
-            .wrap(Wrap { trim: false })
-            .block(inner_block)
-            .render(inner_area, buf);
+        assert_eq!(Session::parse(""), None);
+        assert_eq!(Session::parse("version = 2\ndetails = true\n"), None);
+        assert_eq!(Session::parse("version = 1\nselected = -1\n"), None);
+        assert_eq!(Session::parse("version = 1\nkind = fuzzy\n"), None);
+        assert_eq!(Session::parse("version = 1\n\u{0}\u{1}garbage\n"), None);
     }
 
-    fn render_footer(area: Rect, buf: &mut Buffer) {
-        if area.is_empty() {
-            return;
+    #[test]
+    fn last_session_is_picked_up_once_the_first_page_loads() {
+        let db = MockDatabase::default();
+        let oldest = db.add(RingKind::Main, *b"oldest");
+        db.add(RingKind::Main, *b"middle");
+        db.add(RingKind::Main, *b"newest");
+        let mut app = Harness::new(&db);
+        let default = app.selected_id();
+        let restart = |app: &mut Harness, session| {
+            app.state = State::default();
+            app.state.ui.session = Some(session);
+            app.execute(Command::LoadFirstPage);
+            app.settle();
+        };
+
+        restart(
+            &mut app,
+            Session {
+                selected: Some(oldest),
+                details: true,
+                ..Session::default()
+            },
+        );
+        assert_eq!(app.selected_id(), Some(oldest));
+        assert_eq!(app.state.ui.details_requested, Some(oldest));
+
+        restart(
+            &mut app,
+            Session {
+                selected: Some(oldest),
+                query: "o.*t".to_string(),
+                kind: SearchKind::Regex,
+                details: false,
+            },
+        );
+        assert_eq!(app.state.ui.query.lines(), ["o.*t"]);
+        assert!(app.state.ui.session.is_none());
+        assert_eq!(app.selected_id(), Some(oldest));
+        assert_eq!(app.state.ui.details_requested, None);
+        assert_eq!(
+            current_session(&app.state),
+            Session {
+                selected: Some(oldest),
+                query: "o.*t".to_string(),
+                kind: SearchKind::Regex,
+                details: false,
+            }
+        );
+
+        // Deleted since.
+        restart(
+            &mut app,
+            Session {
+                selected: Some(oldest + 100),
+                ..Session::default()
+            },
+        );
+        assert_eq!(app.selected_id(), default);
+    }
+
+    #[test]
+    fn render_code_has_no_hardcoded_strings() {
+        let source = include_str!("main.rs");
+        let source = &source[..source.find("#[cfg(test)]\nmod tests").unwrap()];
+        for &key in Str::ALL {
+            let literal = format!("{:?}", key.english());
+            assert!(
+                !source.contains(&literal),
+                "{key:?} is hard-coded: {literal}"
+            );
         }
+        for call in [
+            "Paragraph::new(\"",
+            "Line::raw(\"",
+            "Span::raw(\"",
+            ".title(\"",
+        ] {
+            assert!(!source.contains(call), "Untranslated text in {call}");
+        }
+    }
 
-        let outer_block = Block::new()
-            .borders(Borders::TOP)
-            .title_alignment(Alignment::Center)
-            .title("Help");
-        let inner_block = Block::new().borders(Borders::NONE);
-        let inner_area = outer_block.inner(area);
+    #[test]
+    fn translations_fall_back_to_english() {
+        let table = strings::parse(
+            r"
+            # German
+            Search = Suche
+            EntryTitle = Eintrag
+            ErrorDetails = Fehler: {}\nDetails: {}
+            BadgeImage = Bild
+            Unknown = ?
+            ",
+        );
 
-        outer_block.render(area, buf);
+        assert_eq!(&*table[Str::Search as usize], "Suche");
+        assert_eq!(&*table[Str::Help as usize], "Help");
+        // Translations that drop placeholders are ignored.
+        assert_eq!(&*table[Str::EntryTitle as usize], "Entry ({})");
+        assert_eq!(
+            &*table[Str::ErrorDetails as usize],
+            "Fehler: {}\nDetails: {}"
+        );
+        // Badges must keep their width.
+        assert_eq!(&*table[Str::BadgeImage as usize], "Bi");
+    }
 
-        Paragraph::new(
-            "Use ↓↑ to move, ←→ to (un)select, / to search, x to search with RegEx, m to search \
-             mime types, r to reload, f to (un)favorite, d to delete, J/K to scroll entry details.",
-        )
-        .wrap(Wrap { trim: true })
-        .block(inner_block)
-        .centered()
-        .render(inner_area, buf);
+    #[test]
+    fn badge_styles_are_parsed() {
+        assert_eq!(BadgeStyle::parse(Some("nerd")), BadgeStyle::NerdFont);
+        assert_eq!(BadgeStyle::parse(Some("off")), BadgeStyle::Off);
+        assert_eq!(BadgeStyle::parse(None), BadgeStyle::Ascii);
+        for invalid in ["", "ascii", "Nerd", "OFF", " off", "0", "none"] {
+            assert_eq!(
+                BadgeStyle::parse(Some(invalid)),
+                BadgeStyle::Ascii,
+                "{invalid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn badges_keep_their_width_in_every_style() {
+        let badges = [
+            EntryBadge::Image,
+            EntryBadge::Document,
+            EntryBadge::Code,
+            EntryBadge::Url,
+            EntryBadge::ImageText,
+        ];
+        for style in [BadgeStyle::NerdFont, BadgeStyle::Ascii] {
+            assert_eq!(style.glyph(EntryBadge::None), "  ");
+            let glyphs = badges.map(|badge| style.glyph(badge));
+            for (i, glyph) in glyphs.iter().enumerate() {
+                assert_eq!(glyph.chars().count(), 2, "{style:?} {glyph:?}");
+                assert!(!glyphs[..i].contains(glyph), "{style:?} {glyph:?}");
+            }
+        }
+        assert_eq!(
+            BadgeStyle::Ascii.glyph(EntryBadge::Url),
+            strings::get(Str::BadgeUrl)
+        );
+        for badge in badges.into_iter().chain([EntryBadge::None]) {
+            assert_eq!(BadgeStyle::Off.glyph(badge), "");
+        }
     }
 }
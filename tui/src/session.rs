@@ -0,0 +1,104 @@
+//! Where the user left off, so reopening the TUI picks up from there.
+//!
+//! The session is saved to `$XDG_STATE_HOME/ringboard/tui.session` on exit as
+//! `key = value` lines under a version line. A file that can't be read, is
+//! from another version or doesn't parse is ignored rather than reported: the
+//! worst that happens is starting from the newest entry as usual.
+
+use std::{env, fs, path::PathBuf};
+
+use ringboard_sdk::ui_actor::SearchKind;
+
+const VERSION: &str = "version = 1";
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Session {
+    /// The id of the selected entry.
+    pub selected: Option<u64>,
+    /// The search query, empty if no search was open.
+    pub query: String,
+    pub kind: SearchKind,
+    /// Whether the selected entry's details were open.
+    pub details: bool,
+}
+
+impl Session {
+    pub fn load() -> Option<Self> {
+        Self::parse(&fs::read_to_string(session_path()?).ok()?)
+    }
+
+    /// Saves the session, leaving the previous one in place if that fails.
+    pub fn save(&self) {
+        let Some(path) = session_path() else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, self.to_string());
+    }
+
+    pub fn parse(file: &str) -> Option<Self> {
+        let mut lines = file.lines();
+        if lines.next()? != VERSION {
+            return None;
+        }
+        let mut session = Self::default();
+        for line in lines {
+            let (key, value) = line.split_once(" = ")?;
+            match key {
+                "selected" => session.selected = Some(value.parse().ok()?),
+                "query" => session.query = value.to_string(),
+                "kind" => {
+                    session.kind = match value {
+                        "plain" => SearchKind::Plain,
+                        "regex" => SearchKind::Regex,
+                        "mime" => SearchKind::Mime,
+                        "tag" => SearchKind::Tag,
+                        _ => return None,
+                    }
+                }
+                "details" => session.details = value.parse().ok()?,
+                _ => return None,
+            }
+        }
+        Some(session)
+    }
+}
+
+impl std::fmt::Display for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            selected,
+            query,
+            kind,
+            details,
+        } = self;
+        writeln!(f, "{VERSION}")?;
+        if let Some(selected) = selected {
+            writeln!(f, "selected = {selected}")?;
+        }
+        // Queries are a single line, so they can be written as is.
+        if !query.is_empty() {
+            writeln!(f, "query = {query}")?;
+            let kind = match kind {
+                SearchKind::Plain => "plain",
+                SearchKind::Regex => "regex",
+                SearchKind::Mime => "mime",
+                SearchKind::Tag => "tag",
+            };
+            writeln!(f, "kind = {kind}")?;
+        }
+        writeln!(f, "details = {details}")
+    }
+}
+
+fn session_path() -> Option<PathBuf> {
+    let mut path = env::var_os("XDG_STATE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+    path.push("ringboard");
+    path.push("tui.session");
+    Some(path)
+}
@@ -0,0 +1,449 @@
+//! User-facing text.
+//!
+//! Every string the TUI shows goes through this table so it can be
+//! translated. A translation is a file of `Str = text` lines (see [`Str`] for
+//! the names) read from `$XDG_CONFIG_HOME/ringboard/strings/<language>`, where
+//! the language comes from `RINGBOARD_LANG` or the usual locale variables.
+//! Missing or invalid translations fall back to English.
+
+use std::{
+    borrow::Cow,
+    env,
+    fmt::{Display, Write},
+    fs,
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+use unicode_width::UnicodeWidthChar;
+
+macro_rules! strings {
+    ($($(#[$attr:meta])* $key:ident $(($width:literal))? = $english:literal,)*) => {
+        /// A user-facing string.
+        ///
+        /// `{}` placeholders are filled in order and translations must keep all
+        /// of them. Strings with a width are truncated to that many columns.
+        #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+        pub enum Str {
+            $($(#[$attr])* $key,)*
+        }
+
+        impl Str {
+            pub const ALL: &[Self] = &[$(Self::$key,)*];
+
+            const fn name(self) -> &'static str {
+                match self {
+                    $(Self::$key => stringify!($key),)*
+                }
+            }
+
+            pub const fn english(self) -> &'static str {
+                match self {
+                    $(Self::$key => $english,)*
+                }
+            }
+
+            const fn max_width(self) -> Option<usize> {
+                match self {
+                    $(Self::$key => strings!(@width $($width)?),)*
+                }
+            }
+        }
+    };
+    (@width) => { None };
+    (@width $width:literal) => { Some($width) };
+}
+
+strings! {
+    Title = "Ringboard v{}",
+    Help = "Help",
+    ServerVersion = "Server v{} ({})",
+    HelpKeys = "Use {}.",
+    HelpMoveDown = "{} to move down",
+    HelpMoveUp = "{} to move up",
+    HelpSelect = "{} to select",
+    HelpUnselect = "{} to unselect",
+    HelpToggleDetails = "{} to toggle details",
+    HelpPaste = "{} to paste",
+    HelpYank = "{} to copy without pasting",
+    HelpBack = "{} to go back",
+    HelpQuit = "{} to quit",
+    HelpSearch = "{} to search, or to find text in open details (Ctrl-U to clear, from:<name> to \
+                  only search what a client added)",
+    HelpRegexSearch = "{} to search with RegEx (Ctrl-X to toggle)",
+    HelpMimeSearch = "{} to search mime types",
+    HelpTagSearch = "{} to list entries with a tag",
+    HelpSearchScope = "{} to change which entries are searched",
+    HelpMimeScope = "{} to only search text or images",
+    HelpMatchCase = "{} to match case",
+    HelpFavoritesFirst = "{} to list favorites first",
+    HelpFilter = "{} to filter loaded entries",
+    HelpRefresh = "{} to reload",
+    HelpReset = "{} to reset and reload",
+    HelpFavorite = "{} to (un)favorite",
+    HelpMark = "{} to mark entries for favoriting and deleting",
+    HelpMarkRange = "{} to mark a range",
+    HelpReorder = "{} to move a favorite",
+    HelpMoveFavoriteUp = "{} to nudge a favorite up",
+    HelpMoveFavoriteDown = "{} to nudge a favorite down",
+    HelpSplit = "{} to show favorites side by side",
+    HelpSwitchPane = "{} to switch between them",
+    HelpLabel = "{} to label",
+    HelpTag = "{} to tag (-tag to untag)",
+    HelpOpen = "{} to open an entry with its default app",
+    HelpDelete = "{} to delete",
+    HelpUndoDelete = "{} to undo the last delete",
+    HelpDeduplicate = "{} to delete duplicate entries",
+    HelpActions = "{} to list actions",
+    HelpScrollDetailsDown = "{} to scroll entry details down",
+    HelpScrollDetailsUp = "{} to scroll entry details up",
+    HelpToggleWrap = "{} to toggle wrapping them",
+    HelpScrollDetailsLeft = "{} to scroll unwrapped details left",
+    HelpScrollDetailsRight = "{} to scroll unwrapped details right",
+    HelpNextMatch = "{} to go to the next match in them",
+    HelpPreviousMatch = "{} to go to the previous match",
+    HelpFindSimilar = "{} to find entries like it",
+    HelpTop = "{} to go to the top",
+    HelpBottom = "{} to go to the bottom",
+    HelpPageDown = "{} to move a page down",
+    HelpPageUp = "{} to move a page up",
+    HelpJump = "{} to go to an entry by id or to search and replace in an open text entry",
+    HelpEdit = "{} to edit an open text entry",
+    HelpImageInfo = "{} to show image info",
+    HelpBinaryAsText = "{} to show binary data as text",
+    HelpPrettyJson = "{} to pretty-print JSON",
+    HelpReveal = "{} to reveal a sensitive entry",
+    HelpStatistics = "{} to show statistics",
+    HelpServerStats = "{} to show server stats",
+    HelpErrorReport = "{} to report the last error",
+    HelpHelp = "{} to toggle this help",
+    KeymapMalformed = "Key bindings line {}: expected Action = keys",
+    KeymapUnknownAction = "Key bindings line {}: unknown action {}",
+    KeymapUnknownKey = "Key bindings line {}: unknown key {}",
+    KeymapConflict = "Key bindings: {} is bound to both {} and {}, keeping the first",
+    KeymapProblems = "{} problem(s) with key bindings: see the error report",
+    ErrorTitle = "Error: {}",
+    ErrorDetails = "Error: {}\nDetails: {}",
+    ErrorReportTitle = "Error report: j/k to scroll, y to copy, Esc to close",
+    ErrorReport = "{}\n\nringboard-tui v{}\n{}",
+    ServerVersionUnknown = "Server version unknown",
+    ErrorReportFollowUp = "{}\n\nAnother error occurred while showing this report:\n{}",
+    NoErrorReport = "No errors to report.",
+    ErrorReportCopied = "Error report copied.",
+
+    Search = "Search",
+    CaseSensitiveSearch = "Case-sensitive search",
+    RegexSearch = "RegEx search",
+    MimeSearch = "Mime type search",
+    TagSearch = "Tag filter",
+    Searching = "Searching…",
+    ScopedSearch = "{} ({})",
+    ScopeAll = "all",
+    ScopeMain = "main",
+    ScopeFavorites = "favorites",
+    ScopeWithMime = "{}, {}",
+    ScopeText = "text only",
+    ScopeImages = "images only",
+    ScopeFavoritesFirst = "{}, favorites first",
+    InvalidRegex = "Invalid RegEx",
+    Filter = "Filter (loaded)",
+    Label = "Label (empty to remove)",
+    Tag = "Tag (-tag to remove)",
+    InvalidTag = "Tags are made of letters, digits, '-' and '_'",
+    JumpToEntry = "Go to entry (id like main-42)",
+    UnknownEntry = "Unknown entry: {}",
+    Editing = "Ctrl-S to save, Esc to cancel",
+    Substitute = "Replace (s/pattern/replacement/ with optional g and i flags)",
+    InvalidSubstitution = "Expected s/pattern/replacement/ with optional g and i flags",
+    NoMatches = "No matches",
+    FindInDetails = "Find in details",
+    NoTextToSearch = "No text to search.",
+    NothingToMatch = "Nothing to match on.",
+
+    Entries = "Entries",
+    Favorites = "Favorites",
+    NoFavorites = "No favorites yet: run without --favorites and press f on an entry to add it.",
+    NothingToSeeHere = "Nothing to see here…",
+    MainRingSeparator = "Main ring",
+    ImagePreview = "Image: open details to view.",
+    ImageTextPreview = "Image: matched in image text, open details to view.",
+    UnsupportedFormat = "Unable to display format of type {}.",
+    SensitivePreview = "••••• sensitive entry",
+    CollapsedRun(6) = "×{} ",
+    CollapsedRunAccessible(16) = "{} similar: ",
+    LineCount(12) = " ⏎ ×{}",
+    LineCountAccessible(16) = ", {} lines",
+    TextSize(12) = " ({})",
+    BadgeImage(2) = "I ",
+    BadgeDocument(2) = "D ",
+    BadgeCode(2) = "C ",
+    BadgeUrl(2) = "U ",
+    BadgeImageText(2) = "T ",
+    OnClipboardMarker(2) = "◉ ",
+    AgeNow = "now",
+    AgeMinutes = "{}m",
+    AgeHours = "{}h",
+    AgeDays = "{}d",
+    AgeWeeks = "{}w",
+    AgeYears = "{}y",
+
+    EntryTitle = "Entry ({})",
+    EntryTitleWithMime = "Entry ({}; {})",
+    FavoriteTitle = "Favorite entry ({})",
+    FavoriteTitleWithMime = "Favorite entry ({}; {})",
+    AddedByTitle = "{}, from {}",
+    TagsTitle = "{}, tagged {}",
+    OnClipboardTitle = "{}, on clipboard",
+    CopiedAtTitle = "{}, copied {} UTC",
+    ChecksumMismatchTitle = "{}, checksum mismatch: the data may be corrupted",
+    TruncatedTitle = "{}, truncated to {}",
+    ImageTitle = "{}, {}×{}, {}",
+    ImageTitleAccessible = "{}, {} by {} pixels, {}",
+    ColumnTitle = "{}, from column {}",
+    Loading = "Loading…",
+    OpeningDatabase = "Opening the database…",
+    LoadingProgress = "Loading… {}%",
+    BinaryData = "Binary data.",
+    SensitiveHidden = "Sensitive entry hidden: {} to reveal it.",
+    PreviewTruncated = "Showing the first {} of {}.",
+    ImagePreviewsDisabled = "Image: previews are disabled in accessible mode.",
+    ImageInfo = "{}×{} {}, {}",
+    ImageInfoAccessible = "{} by {} pixel {}, {}",
+    ImageTaken = "Taken {}",
+    ImageCamera = "Camera: {}",
+
+    EntryRemoved = "Entry was removed.",
+    ConfirmFavoriteGroup = "(Un)favorite every entry in the group? (y/n)",
+    ConfirmDeleteGroup = "Delete every entry in the group? (y/n)",
+    ConfirmDeduplicate = "Delete every duplicate entry, keeping the newest copy or a favorite? (y/n)",
+    Deduplicated = "Deleted {} duplicate(s) of {} entry(ies).",
+    Restored = "Restored entry {}.",
+    NothingToUndo = "Nothing to undo.",
+    Deleted = "Deleted entry {}.",
+    DeletedUndoable = "Deleted entry {} ({} to undo).",
+    DeletedMany = "Deleted {} entry(ies).",
+    Favorited = "Favorited.",
+    Unfavorited = "Unfavorited.",
+    Refreshed = "Reloaded the entries.",
+    NewEntries = "{} new entry(ies).",
+    NewEntriesReplacing = "{} new entry(ies), replacing the {} oldest.",
+    SearchMatches = "Search: {} match(es).",
+    Yanked = "Copied to the clipboard.",
+    NoDisplayServer = "Nothing to copy to: neither WAYLAND_DISPLAY nor DISPLAY is set.",
+    NoCopier = "Copying needs wl-copy on Wayland, or xclip (or xsel for text) on X11.",
+    Reordering = "Moving favorite: j/k to move, Enter to save, Esc to cancel",
+    ReorderUnavailable = "Only favorites can be moved, with no search, filter, split, or \
+                          collapsed runs.",
+    ReorderFailed = "Favorite was removed before it could be moved.",
+    SplitTooNarrow = "Too narrow to show favorites side by side.",
+    TerminalTooSmall = "Terminal too small (need {}x{}).",
+    FavoritesFull = "Favorites are full: unfavorite the oldest one to make room.",
+    LargePaste = "Large entry",
+    ConfirmLargePaste = "Paste {} of {}?\nEnter to paste, Esc to cancel.",
+    ReplaceUnavailable = "Only text entries under 1 MiB can be edited, with their details open.",
+    ReplaceUnchanged = "Replacing leaves the text unchanged.",
+    ReplacePreview = "Replace {} match(es): Enter to save, Esc to go back",
+    Replaced = "Entry replaced.",
+
+    TutorialTitle = "Welcome to Ringboard ({}/{})",
+    TutorialPage = "{}\n\n{}",
+    TutorialKeys = "Left/Right to turn the page, Enter to get started.",
+    TutorialCapture = "Ringboard keeps everything you copy. Entries are captured by a\n\
+                       clipboard watcher (ringboard-x11 or ringboard-wayland), which must\n\
+                       be running alongside the server: without one this list stays empty.",
+    TutorialNavigation = "Move between entries with j/k or the arrow keys. Right or Space\n\
+                          shows an entry's details and Left hides them. Press ? for every\n\
+                          key and q to quit.",
+    TutorialSearch = "Press / to search, x to search with RegEx, or m to search mime\n\
+                      types. Enter or Esc leaves the search box and Esc again clears it.",
+    TutorialFavorites = "Press f to favorite an entry so it's kept no matter how much you\n\
+                         copy, and | to show favorites next to everything else.",
+    TutorialPasting = "Press Enter to put the selected entry back on the clipboard and\n\
+                       close Ringboard, or . to list everything you can do with it.",
+
+    DashboardTitle = "Dashboard: j/k to pick an entry, Enter to jump to it, Esc to close",
+    DashboardSummary = "{} entries, {}% duplicates",
+    DashboardScanning = " (scanning… {}%)",
+    DashboardAdds = "Adds per day, last {} days",
+    DashboardAddsAccessible = "Today first: {}",
+    DashboardMimeTypes = "Top mime types",
+    DashboardMimeType = "{}: {} ({})",
+    DashboardNoMimeType = "untyped",
+    DashboardLargest = "Largest entries",
+    DashboardEntryNotLoaded = "Not loaded: scroll down to it first.",
+    DashboardEntryNotShown = "That entry isn't shown: load it or clear the search first.",
+
+    ServerStatsTitle = "Server stats: I or Esc to close",
+    ServerStatsMain = "Main ring",
+    ServerStatsMainValue = "{} of {} slots written",
+    ServerStatsFavorites = "Favorites",
+    ServerStatsFavoritesValue = "{} of {}",
+    ServerStatsDirect = "Files",
+    ServerStatsDirectValue = "{} ({})",
+    ServerStatsPacked = "Packed",
+    ServerStatsPackedValue = "{} entries ({}, {} on disk)",
+    ServerStatsUptime = "Uptime",
+    ServerStatsUptimeValue = "{} (since {})",
+    ServerStatsClients = "Clients",
+    ServerStatsClientsValue = "{} connected, {} since start",
+    ServerStatsBucket = "Bucket",
+    ServerStatsSlots = "Slots",
+    ServerStatsFree = "Free",
+    ServerStatsUsed = "Used",
+    ServerStatsOnDisk = "On disk",
+
+    Actions = "Actions",
+    ActionPaste = "Paste",
+    ActionDetails = "Show details",
+    ActionFavorite = "Favorite",
+    ActionUnfavorite = "Unfavorite",
+    ActionOpen = "Open",
+    ActionYank = "Copy",
+    ActionLabel = "Label",
+    ActionTag = "Tag",
+    ActionDelete = "Delete",
+    KeyEnter = "Enter",
+    KeySpace = "Space",
+
+    AnnounceEntry = "Entry {} of {}, ",
+    AnnounceFavorite = "favorite ",
+    AnnounceOnClipboard = "on clipboard, ",
+    AnnounceMarked = "marked, ",
+    AnnounceLabel = "labeled {}, ",
+    AnnounceText = "text: {}",
+    AnnounceImage = "image",
+    AnnounceImageText = "image, matched in image text",
+    AnnounceBinary = "binary data of type {}",
+    AnnounceSensitive = "sensitive entry",
+    AnnounceError = "error: {}",
+}
+
+static TABLE: OnceLock<Box<[Box<str>]>> = OnceLock::new();
+
+/// Loads the translation for the user's language.
+///
+/// Must be called before any string is looked up to take effect.
+pub fn init() {
+    let table = translation_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map_or_else(english, |translation| parse(&translation));
+    let _ = TABLE.set(table);
+}
+
+fn translation_path() -> Option<PathBuf> {
+    let lang = ["RINGBOARD_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .find_map(|var| env::var(var).ok().filter(|v| !v.is_empty()))?;
+    // Strip the encoding and modifier, e.g. `de_DE.UTF-8@euro`.
+    let lang = lang.split(['.', '@']).next().unwrap_or_default();
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return None;
+    }
+
+    let mut dir = config_dir()?;
+    dir.push("strings");
+    // Fall back from the region to the plain language, e.g. `de_DE` to `de`.
+    for lang in [Some(lang), lang.split_once('_').map(|(lang, _)| lang)]
+        .into_iter()
+        .flatten()
+    {
+        let path = dir.join(lang);
+        if path.exists() {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Where Ringboard's config files live.
+pub fn config_dir() -> Option<PathBuf> {
+    let mut dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    dir.push("ringboard");
+    Some(dir)
+}
+
+fn english() -> Box<[Box<str>]> {
+    Str::ALL.iter().map(|key| key.english().into()).collect()
+}
+
+/// Builds a table from a translation, keeping English for every string the
+/// translation is missing or gets wrong.
+pub fn parse(translation: &str) -> Box<[Box<str>]> {
+    let mut table = english();
+    for line in translation.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, text)) = line.split_once('=') else {
+            continue;
+        };
+        let Some(&key) = Str::ALL.iter().find(|key| key.name() == name.trim()) else {
+            continue;
+        };
+
+        let mut text = text.trim().replace("\\n", "\n");
+        if text.matches("{}").count() != key.english().matches("{}").count() {
+            continue;
+        }
+        if !text.contains("{}") {
+            truncate(key, &mut text);
+        }
+        table[key as usize] = text.into();
+    }
+    table
+}
+
+fn truncate(key: Str, text: &mut String) {
+    let Some(max_width) = key.max_width() else {
+        return;
+    };
+    let mut width = 0;
+    if let Some((end, _)) = text.char_indices().find(|&(_, c)| {
+        width += c.width().unwrap_or(0);
+        width > max_width
+    }) {
+        text.truncate(end);
+    }
+}
+
+pub fn get(key: Str) -> &'static str {
+    TABLE
+        .get()
+        .map_or_else(|| key.english(), |table| &table[key as usize])
+}
+
+/// Fills in a string's placeholders.
+pub fn fill(key: Str, args: &[&dyn Display]) -> String {
+    let mut out = String::new();
+    fill_into(&mut out, key, args);
+    out
+}
+
+/// Like [`fill`], but reuses `out`'s allocation.
+pub fn fill_into(out: &mut String, key: Str, args: &[&dyn Display]) {
+    out.clear();
+    let mut args = args.iter();
+    let mut parts = get(key).split("{}");
+    out.push_str(parts.next().unwrap_or_default());
+    for part in parts {
+        if let Some(arg) = args.next() {
+            write!(out, "{arg}").unwrap();
+        }
+        out.push_str(part);
+    }
+    truncate(key, out);
+}
+
+/// Screen readers don't reliably pronounce `…`, so spell it out in accessible
+/// mode.
+pub fn ellipsis(text: &str, accessible: bool) -> Cow<'_, str> {
+    if accessible && text.contains('…') {
+        Cow::Owned(text.replace('…', "..."))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
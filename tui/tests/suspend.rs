@@ -0,0 +1,189 @@
+//! Suspends the TUI running on a pseudo terminal to check that the shell gets
+//! a usable terminal back.
+
+use std::{
+    env, fs,
+    fs::File,
+    io::{Read, Write},
+    os::{fd::OwnedFd, unix::process::CommandExt},
+    path::{Path, PathBuf},
+    process,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ringboard_sdk::core::{open_buckets, protocol::RingKind, ring::MAGIC, IoErr};
+use rustix::{
+    fs::{openat, Mode, OFlags, CWD},
+    process::{
+        ioctl_tiocsctty, kill_process, setsid, waitpid, Pid, Signal, WaitOptions, WaitStatus,
+    },
+    pty::{grantpt, openpt, ptsname, unlockpt, OpenptFlags},
+    termios::{tcgetattr, tcsetwinsize, LocalModes, Winsize},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An empty database, as left behind by a server that never got any entries.
+fn empty_database(dir: &Path) {
+    let mut database = dir.join("clipboard-history");
+    fs::create_dir_all(database.join("buckets")).unwrap();
+    fs::create_dir_all(database.join("direct")).unwrap();
+    for kind in [RingKind::Main, RingKind::Favorites] {
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&[0; 5]);
+        fs::write(database.join(kind.file_name()), header).unwrap();
+    }
+
+    database.push("buckets");
+    open_buckets(|name| {
+        openat(
+            CWD,
+            database.join(name),
+            OFlags::RDWR | OFlags::CREATE,
+            Mode::RUSR | Mode::WUSR,
+        )
+        .map_io_err(|| format!("Failed to create bucket: {name}"))
+    })
+    .unwrap();
+}
+
+struct Tui {
+    process: Child,
+    pty: OwnedFd,
+    terminal: OwnedFd,
+    dir: PathBuf,
+}
+
+impl Tui {
+    fn start(dir: PathBuf) -> Self {
+        empty_database(&dir);
+        // A returning user, so the first run tutorial doesn't swallow keys.
+        fs::write(
+            dir.join("clipboard-history").join("tui-state"),
+            "tutorial-dismissed\n",
+        )
+        .unwrap();
+
+        let pty = openpt(OpenptFlags::RDWR | OpenptFlags::NOCTTY).unwrap();
+        grantpt(&pty).unwrap();
+        unlockpt(&pty).unwrap();
+        tcsetwinsize(
+            &pty,
+            Winsize {
+                ws_row: 24,
+                ws_col: 80,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            },
+        )
+        .unwrap();
+        let terminal = openat(
+            CWD,
+            ptsname(&pty, Vec::new()).unwrap().as_c_str(),
+            OFlags::RDWR | OFlags::NOCTTY,
+            Mode::empty(),
+        )
+        .unwrap();
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_ringboard-tui"));
+        command
+            .env("XDG_DATA_HOME", &dir)
+            .env("RINGBOARD_SOCK", dir.join("server.sock"))
+            // Known to support images so the TUI doesn't query the terminal,
+            // which would swallow the keys sent here.
+            .env("TERM", "xterm-kitty")
+            .stdin(Stdio::from(terminal.try_clone().unwrap()))
+            .stdout(Stdio::from(terminal.try_clone().unwrap()))
+            .stderr(Stdio::from(terminal.try_clone().unwrap()));
+        // Like a shell, give the TUI the terminal as its controlling terminal.
+        unsafe {
+            command.pre_exec(|| {
+                setsid()?;
+                ioctl_tiocsctty(std::io::stdin())?;
+                Ok(())
+            });
+        }
+        let process = command.spawn().unwrap();
+
+        // Nobody is looking at the screen, but the TUI blocks once the pty's
+        // buffer fills up.
+        let mut screen = File::from(pty.try_clone().unwrap());
+        thread::spawn(move || while screen.read(&mut [0; 4096]).is_ok_and(|n| n > 0) {});
+
+        Self {
+            process,
+            pty,
+            terminal,
+            dir,
+        }
+    }
+
+    fn pid(&self) -> Pid {
+        Pid::from_child(&self.process)
+    }
+
+    fn press(&self, key: &[u8]) {
+        File::from(self.pty.try_clone().unwrap())
+            .write_all(key)
+            .unwrap();
+    }
+
+    fn is_raw(&self) -> bool {
+        !tcgetattr(&self.terminal)
+            .unwrap()
+            .local_modes
+            .intersects(LocalModes::ICANON | LocalModes::ECHO)
+    }
+
+    fn is_cooked(&self) -> bool {
+        tcgetattr(&self.terminal)
+            .unwrap()
+            .local_modes
+            .contains(LocalModes::ICANON | LocalModes::ECHO)
+    }
+
+    fn wait_until(&self, mut condition: impl FnMut(&Self) -> bool) {
+        let deadline = Instant::now() + TIMEOUT;
+        while !condition(self) {
+            assert!(Instant::now() < deadline, "timed out waiting for the TUI");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Waits for the process to stop or exit.
+    fn wait(&self, options: WaitOptions) -> WaitStatus {
+        let mut status = None;
+        self.wait_until(|me| {
+            status = waitpid(Some(me.pid()), options | WaitOptions::NOHANG).unwrap();
+            status.is_some()
+        });
+        status.unwrap()
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn ctrl_z_hands_the_terminal_back_until_resumed() {
+    let tui = Tui::start(env::temp_dir().join(format!("ringboard-tui-suspend-{}", process::id())));
+    tui.wait_until(Tui::is_raw);
+
+    tui.press(b"\x1a");
+    assert!(tui.wait(WaitOptions::UNTRACED).stopped());
+    assert!(tui.is_cooked());
+
+    kill_process(tui.pid(), Signal::Cont).unwrap();
+    tui.wait_until(Tui::is_raw);
+
+    tui.press(b"q");
+    assert_eq!(tui.wait(WaitOptions::empty()).exit_status(), Some(0));
+    assert!(tui.is_cooked());
+}
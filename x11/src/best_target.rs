@@ -1,6 +1,10 @@
 use ringboard_sdk::core::{protocol::MimeType, TEXT_MIMES};
 use x11rb::protocol::xproto::Atom;
 
+/// Targets password managers offer alongside data they don't want kept in
+/// clipboard histories.
+const SENSITIVE_HINTS: [&str; 2] = ["x-kde-passwordManagerHint", "CLIPBOARD_STATE"];
+
 #[derive(Copy, Clone)]
 struct SeenMime {
     atom: Atom,
@@ -21,6 +25,7 @@ pub struct BestMimeTypeFinder {
     seen: KnownSeenMimes,
     best_mime: MimeType,
     block_text: bool,
+    sensitive: bool,
 }
 
 impl BestMimeTypeFinder {
@@ -36,8 +41,14 @@ impl BestMimeTypeFinder {
                 },
             best_mime,
             block_text,
+            sensitive,
         } = self;
 
+        if SENSITIVE_HINTS.contains(&mime.as_str()) {
+            *sensitive = true;
+            return;
+        }
+
         let target = if TEXT_MIMES.iter().any(|b| mime.eq_ignore_ascii_case(b)) {
             if *block_text {
                 return;
@@ -80,6 +91,11 @@ impl BestMimeTypeFinder {
         self.block_text = true;
     }
 
+    /// Whether the selection was marked as sensitive, e.g. a password.
+    pub const fn is_sensitive(&self) -> bool {
+        self.sensitive
+    }
+
     pub fn best(&self) -> Option<(Atom, MimeType)> {
         self.seen.best().map(|atom| (atom, self.best_mime))
     }
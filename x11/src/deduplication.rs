@@ -34,7 +34,7 @@ impl CopyDeduplication {
     pub fn new() -> Result<Self, CoreError> {
         let mut main = ArrayMap::default();
         let mut favorites = ArrayMap::default();
-        let (database, mut reader) = {
+        let (database, reader) = {
             let mut database = data_dir();
             (
                 DatabaseReader::open(&mut database)?,
@@ -65,11 +65,11 @@ impl CopyDeduplication {
 
                 for entry in iter {
                     let hash = match entry.kind() {
-                        Kind::Bucket(_) => entry.to_slice(&mut reader).map(|data| {
+                        Kind::Bucket(_) => entry.to_slice(&reader).map(|data| {
                             Self::hash(CopyData::Slice(&data), u64::try_from(data.len()).unwrap())
                         })?,
                         Kind::File => {
-                            let file = entry.to_file(&mut reader)?;
+                            let file = entry.to_file(&reader)?;
                             Self::hash(
                                 CopyData::File(&file),
                                 statx(&*file, c"", AtFlags::EMPTY_PATH, StatxFlags::SIZE)
@@ -128,7 +128,7 @@ impl CopyDeduplication {
                 match data {
                     CopyData::Slice(data) => {
                         **entry
-                            .to_slice(&mut self.reader)
+                            .to_slice(&self.reader)
                             .inspect_err(|e| {
                                 error!("Failed to load entry: {entry:?}\nError: {e:?}");
                             })
@@ -137,7 +137,7 @@ impl CopyDeduplication {
                     }
                     CopyData::File(data) => {
                         let a = entry
-                            .to_slice(&mut self.reader)
+                            .to_slice(&self.reader)
                             .inspect_err(|e| {
                                 error!("Failed to load entry: {entry:?}\nError: {e:?}");
                             })
@@ -8,18 +8,25 @@ use std::{
     os::{fd::AsFd, unix::fs::FileExt},
     rc::Rc,
     slice,
+    time::{Duration, Instant},
 };
 
 use arrayvec::ArrayVec;
 use error_stack::Report;
 use log::{debug, error, info, trace, warn};
 use ringboard_sdk::{
-    api::{connect_to_server, AddRequest, MoveToFrontRequest},
+    api::{
+        negotiate_with_server, set_client_name, AddRequest, MoveToFrontRequest, SetClipboardRequest,
+    },
     core::{
-        dirs::{paste_socket_file, socket_file},
+        dirs::{data_dir, paste_socket_file, socket_file},
         init_unix_server,
-        protocol::{AddResponse, IdNotFoundError, MimeType, MoveToFrontResponse, RingKind},
+        protocol::{
+            AddResponse, IdNotFoundError, MimeType, MoveToFrontResponse, Request, RingKind,
+            SetClipboardResponse,
+        },
         ring::Mmap,
+        settings::{EntryLimits, SensitiveEntries, SizeCheck},
         Error, IoErr,
     },
 };
@@ -28,7 +35,7 @@ use rustix::{
     fs::{memfd_create, openat, MemfdFlags, Mode, OFlags, CWD},
     net::{
         recvmsg, RecvAncillaryBuffer, RecvAncillaryMessage::ScmRights, RecvFlags, SocketAddrUnix,
-        SocketType,
+        SocketFlags, SocketType,
     },
     path::Arg,
 };
@@ -152,16 +159,26 @@ enum State {
         selection: Atom,
         allow_plain_text: bool,
     },
+    /// Plain text from the fast path, held back until the selection's targets
+    /// show whether it was marked sensitive.
+    HintCheck {
+        data: Vec<u8>,
+    },
     PendingSelection {
         mime_atom: Atom,
         mime_type: MimeType,
+        sensitive: bool,
     },
     PendingIncr {
         mime_atom: Atom,
         mime_type: MimeType,
+        sensitive: bool,
         file: Option<File>,
         written: u64,
     },
+    /// An INCR transfer that was given up on. Its next chunk is left unread,
+    /// which stops the selection owner from sending more.
+    AbandonedIncr,
 }
 
 const MAX_CONCURRENT_TRANSFERS: usize = 4;
@@ -169,6 +186,11 @@ const BASE_TRANSFER_ATOM: AtomEnum = AtomEnum::CUT_BUFFE_R0;
 
 const MAX_TRANSFER_SIZE: usize = 1 << 20;
 
+/// How long the clipboard must keep its owner before the selection is fetched.
+/// Some apps take ownership on every keystroke while text is being selected, so
+/// only the selection they settle on is saved.
+const SELECTION_DEBOUNCE: Duration = Duration::from_millis(100);
+
 #[derive(Default)]
 struct TransferAtomAllocator {
     windows: [Window; MAX_CONCURRENT_TRANSFERS],
@@ -182,7 +204,7 @@ impl TransferAtomAllocator {
 
         let next = usize::from(self.next);
 
-        if !matches!(self.states[next], State::Free) {
+        if !matches!(self.states[next], State::Free | State::AbandonedIncr) {
             warn!("Too many ongoing transfers, dropping old transfer.");
         }
         let state = &mut self.states[next];
@@ -241,13 +263,30 @@ fn run() -> Result<(), CliError> {
         env!("CARGO_PKG_VERSION")
     );
 
-    let server = {
+    set_client_name(env!("CARGO_BIN_NAME"));
+    // Selections the server won't store in full aren't fetched in full either.
+    let limits = EntryLimits::load(&mut data_dir())?;
+    let (server, capabilities) = {
         let socket_file = socket_file();
         let addr = SocketAddrUnix::new(&socket_file)
             .map_io_err(|| format!("Failed to make socket address: {socket_file:?}"))?;
-        connect_to_server(&addr)?
+        negotiate_with_server(&addr, SocketFlags::empty())?
     };
     debug!("Ringboard connection established.");
+    let skip_sensitive = SensitiveEntries::load(&mut data_dir())?.skip_sensitive_entries || {
+        let sensitive_add = Request::Add {
+            to: RingKind::Main,
+            sensitive: true,
+            mime_type: MimeType::new_const(),
+            copied_at: 0,
+        };
+        // Servers that can't expire sensitive entries would keep them forever.
+        let unsupported = !capabilities.supports(&sensitive_add);
+        if unsupported {
+            warn!("Sensitive selections will be dropped: the server can't expire them.");
+        }
+        unsupported
+    };
 
     let (conn, root) = {
         let (conn, screen_num) = x11rb::connect(None)?;
@@ -348,6 +387,7 @@ fn run() -> Result<(), CliError> {
         next: 0,
     };
     let mut paste_allocator = Default::default();
+    let mut pending_selection = None;
 
     let mut deduplicator = CopyDeduplication::new()?;
 
@@ -361,15 +401,28 @@ fn run() -> Result<(), CliError> {
                 &mut allocator,
                 &server,
                 &mut deduplicator,
+                limits,
+                skip_sensitive,
+                &mut pending_selection,
                 paste_window,
                 &mut last_paste,
                 &mut paste_allocator,
             )?;
         }
+        if let Some((selection, deadline)) = pending_selection
+            && deadline <= Instant::now()
+        {
+            pending_selection = None;
+            request_selection(&conn, &atoms, &mut allocator, selection)?;
+        }
         conn.flush()?;
 
         trace!("Waiting for event.");
-        epoll::wait(&epoll, &mut epoll_events, -1)
+        let timeout = pending_selection.map_or(-1, |(_, deadline)| {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            i32::try_from(remaining.as_millis() + 1).unwrap_or(i32::MAX)
+        });
+        epoll::wait(&epoll, &mut epoll_events, timeout)
             .map_io_err(|| "Failed to wait for epoll events.")?;
 
         for epoll::Event { flags: _, data } in &epoll_events {
@@ -389,6 +442,29 @@ fn run() -> Result<(), CliError> {
     }
 }
 
+/// Starts fetching a selection, trying for plain text first.
+fn request_selection(
+    conn: &RustConnection,
+    &Atoms {
+        UTF8_STRING: utf8_string_atom,
+        ..
+    }: &Atoms,
+    allocator: &mut TransferAtomAllocator,
+    selection: Atom,
+) -> Result<(), CliError> {
+    let (state, transfer_window, transfer_atom) = allocator.alloc();
+    *state = State::FastPathPendingSelection { selection };
+
+    conn.convert_selection(
+        transfer_window,
+        selection,
+        utf8_string_atom,
+        transfer_atom,
+        x11rb::CURRENT_TIME,
+    )?;
+    Ok(())
+}
+
 fn handle_x11_event(
     event: Event,
     conn: &RustConnection,
@@ -396,6 +472,9 @@ fn handle_x11_event(
     allocator: &mut TransferAtomAllocator,
     server: impl AsFd,
     deduplicator: &mut CopyDeduplication,
+    limits: EntryLimits,
+    skip_sensitive: bool,
+    pending_selection: &mut Option<(Atom, Instant)>,
 
     paste_window: Window,
     last_paste: &mut Option<(PasteFile, PasteAtom)>,
@@ -594,19 +673,12 @@ fn handle_x11_event(
                 return Ok(());
             }
 
-            info!("Selection notification received.");
-            let (state, transfer_window, transfer_atom) = allocator.alloc();
-            *state = State::FastPathPendingSelection {
-                selection: event.selection,
-            };
-
-            conn.convert_selection(
-                transfer_window,
-                event.selection,
-                utf8_string_atom,
-                transfer_atom,
-                x11rb::CURRENT_TIME,
-            )?;
+            if pending_selection.is_some() {
+                debug!("Selection changed again before settling.");
+            } else {
+                info!("Selection notification received.");
+            }
+            *pending_selection = Some((event.selection, Instant::now() + SELECTION_DEBOUNCE));
         }
         Event::SelectionNotify(event) => {
             let Some((state, transfer_atom)) = allocator.get(event.requestor) else {
@@ -641,13 +713,28 @@ fn handle_x11_event(
                         *state = State::default();
                     }
                 }
+                State::HintCheck { .. } => {
+                    if event.property == x11rb::NONE {
+                        debug!("Targets unavailable, assuming the selection isn't sensitive.");
+                        let State::HintCheck { data } = mem::take(state) else {
+                            unreachable!()
+                        };
+                        add_small_selection(
+                            &server,
+                            deduplicator,
+                            MimeType::new_const(),
+                            &data,
+                            false,
+                        )?;
+                    }
+                }
                 State::PendingSelection { .. } => {
                     if event.property == x11rb::NONE {
                         warn!("Selection transfer cancelled.");
                         *state = State::default();
                     }
                 }
-                State::Free | State::PendingIncr { .. } => {
+                State::Free | State::PendingIncr { .. } | State::AbandonedIncr => {
                     // Nothing to do
                 }
             }
@@ -664,6 +751,18 @@ fn handle_x11_event(
                 );
                 return Ok(());
             }
+            let Some((state, transfer_atom)) = allocator.get(event.window) else {
+                warn!(
+                    "Ignoring property notify to unknown requester {}.",
+                    event.window
+                );
+                return Ok(());
+            };
+            if matches!(state, State::AbandonedIncr) {
+                debug!("Leaving the rest of an abandoned INCR transfer unread.");
+                *state = State::Free;
+                return Ok(());
+            }
             let property = conn.get_property(
                 true,
                 event.window,
@@ -673,19 +772,9 @@ fn handle_x11_event(
                 u32::MAX,
             )?;
             conn.flush()?;
-            let Some((state, transfer_atom)) = allocator.get(event.window) else {
-                warn!(
-                    "Ignoring property notify to unknown requester {}.",
-                    event.window
-                );
-                return Ok(());
-            };
 
             match mem::take(state) {
-                State::TargetsRequest {
-                    selection,
-                    allow_plain_text,
-                } => {
+                s @ (State::TargetsRequest { .. } | State::HintCheck { .. }) => {
                     let property = property.reply()?;
                     if property.type_ == incr_atom {
                         warn!("Ignoring abusive TARGETS property.");
@@ -698,7 +787,11 @@ fn handle_x11_event(
                     };
 
                     let mut finder = BestMimeTypeFinder::default();
-                    if !allow_plain_text {
+                    if let State::TargetsRequest {
+                        allow_plain_text: false,
+                        ..
+                    } = s
+                    {
                         debug!(
                             "Blocking plain text as it returned a blank or empty result on the \
                              fast path."
@@ -727,15 +820,34 @@ fn handle_x11_event(
                         pending_atom_cookies.push((conn.get_atom_name(atom)?, atom));
                     }
 
+                    let sensitive = finder.is_sensitive();
+                    if sensitive && skip_sensitive {
+                        info!("Dropping selection: it's marked sensitive.");
+                        return set_clipboard(&server, None);
+                    }
+                    let selection = match s {
+                        State::TargetsRequest { selection, .. } => selection,
+                        State::HintCheck { data } => {
+                            return add_small_selection(
+                                &server,
+                                deduplicator,
+                                MimeType::new_const(),
+                                &data,
+                                sensitive,
+                            );
+                        }
+                        _ => unreachable!(),
+                    };
                     let Some((target, target_mime)) = finder.best() else {
                         warn!("No usable targets returned, dropping selection.");
-                        return Ok(());
+                        return set_clipboard(&server, None);
                     };
                     info!("Choosing target {target_mime:?} on atom {target}.",);
 
                     *state = State::PendingSelection {
                         mime_atom: target,
                         mime_type: target_mime,
+                        sensitive,
                     };
                     conn.convert_selection(
                         event.window,
@@ -746,23 +858,44 @@ fn handle_x11_event(
                     )?;
                 }
                 s @ (State::FastPathPendingSelection { .. } | State::PendingSelection { .. }) => {
-                    let (mime_atom, mime_type, fast_path) = match s {
-                        State::FastPathPendingSelection { selection } => {
-                            (utf8_string_atom, MimeType::new_const(), Some(selection))
-                        }
+                    // Large selections aren't checked for sensitivity hints since
+                    // passwords never need more than one transfer.
+                    let (mime_atom, mime_type, sensitive, fast_path) = match s {
+                        State::FastPathPendingSelection { selection } => (
+                            utf8_string_atom,
+                            MimeType::new_const(),
+                            false,
+                            Some(selection),
+                        ),
                         State::PendingSelection {
                             mime_atom,
                             mime_type,
-                        } => (mime_atom, mime_type, None),
+                            sensitive,
+                        } => (mime_atom, mime_type, sensitive, None),
                         _ => unreachable!(),
                     };
 
                     let property = property.reply()?;
                     if property.type_ == incr_atom {
+                        // Owners announce a lower bound on the selection's size.
+                        let announced = property
+                            .value32()
+                            .and_then(|mut value| value.next())
+                            .map_or(0, u64::from);
+                        if limits.check(announced) == SizeCheck::Reject {
+                            warn!(
+                                "Dropping selection: it's larger than {} bytes.",
+                                limits.max_entry_size
+                            );
+                            *state = State::AbandonedIncr;
+                            return set_clipboard(&server, None);
+                        }
+
                         debug!("Waiting for INCR transfer.");
                         *state = State::PendingIncr {
                             mime_atom,
                             mime_type,
+                            sensitive,
                             file: None,
                             written: 0,
                         };
@@ -788,46 +921,38 @@ fn handle_x11_event(
                                 )?;
                             } else {
                                 warn!("Dropping empty or blank selection.");
+                                set_clipboard(&server, None)?;
                             }
                             return Ok(());
                         }
 
-                        let data_hash = CopyDeduplication::hash(
-                            CopyData::Slice(&property.value),
-                            u64::try_from(property.value.len()).unwrap(),
-                        );
-                        if let Some(existing) =
-                            deduplicator.check(data_hash, CopyData::Slice(&property.value))
-                        {
-                            info!("Promoting duplicate small selection to front.");
-                            if let MoveToFrontResponse::Success { id } =
-                                MoveToFrontRequest::response(&server, existing, None)?
-                            {
-                                deduplicator.remember(data_hash, id);
-                                return Ok(());
-                            }
+                        if let Some(selection) = fast_path {
+                            debug!("Checking the selection's targets for sensitivity hints.");
+                            *state = State::HintCheck {
+                                data: property.value,
+                            };
+                            conn.convert_selection(
+                                event.window,
+                                selection,
+                                targets_atom,
+                                transfer_atom,
+                                x11rb::CURRENT_TIME,
+                            )?;
+                            return Ok(());
                         }
-
-                        let file = File::from(
-                            memfd_create(c"ringboard_x11_selection", MemfdFlags::empty())
-                                .map_io_err(|| "Failed to create selection transfer temp file.")?,
-                        );
-                        file.write_all_at(&property.value, 0)
-                            .map_io_err(|| "Failed to write data to temp file.")?;
-
-                        let AddResponse::Success { id } = AddRequest::response_add_unchecked(
+                        add_small_selection(
                             &server,
-                            RingKind::Main,
+                            deduplicator,
                             mime_type,
-                            file,
+                            &property.value,
+                            sensitive,
                         )?;
-                        deduplicator.remember(data_hash, id);
-                        info!("Small selection transfer complete.");
                     }
                 }
                 State::PendingIncr {
                     mime_atom,
                     mime_type,
+                    sensitive,
                     file,
                     written,
                 } => {
@@ -844,42 +969,56 @@ fn handle_x11_event(
                     if property.value.is_empty() {
                         if written == 0 {
                             warn!("Dropping empty INCR selection.");
-                            return Ok(());
+                            return set_clipboard(&server, None);
                         }
 
-                        let data_hash = CopyDeduplication::hash(CopyData::File(&file), written);
-                        if let Some(existing) = deduplicator.check(data_hash, CopyData::File(&file))
-                        {
-                            info!("Promoting duplicate large selection to front.");
-                            if let MoveToFrontResponse::Success { id } =
-                                MoveToFrontRequest::response(&server, existing, None)?
-                            {
-                                deduplicator.remember(data_hash, id);
-                                return Ok(());
-                            }
-                        }
-
-                        let AddResponse::Success { id } = AddRequest::response_add_unchecked(
+                        add_large_selection(
                             &server,
-                            RingKind::Main,
+                            deduplicator,
                             mime_type,
+                            sensitive,
                             file,
+                            written,
                         )?;
-                        deduplicator.remember(data_hash, id);
-                        info!("Large selection transfer complete.");
                     } else {
                         debug!("Writing {} bytes for INCR transfer.", property.value.len());
                         file.write_all_at(&property.value, written)
                             .map_io_err(|| "Failed to write data to temp file.")?;
-                        *state = State::PendingIncr {
-                            mime_atom,
-                            mime_type,
-                            file: Some(file),
-                            written: written + u64::try_from(property.value.len()).unwrap(),
+                        let written = written + u64::try_from(property.value.len()).unwrap();
+                        match limits.check(written) {
+                            SizeCheck::Fits => {
+                                *state = State::PendingIncr {
+                                    mime_atom,
+                                    mime_type,
+                                    sensitive,
+                                    file: Some(file),
+                                    written,
+                                };
+                            }
+                            SizeCheck::Truncate(_) => {
+                                info!("Truncating large selection.");
+                                *state = State::AbandonedIncr;
+                                add_large_selection(
+                                    &server,
+                                    deduplicator,
+                                    mime_type,
+                                    sensitive,
+                                    file,
+                                    written,
+                                )?;
+                            }
+                            SizeCheck::Reject => {
+                                warn!(
+                                    "Dropping selection: it's larger than {} bytes.",
+                                    limits.max_entry_size
+                                );
+                                *state = State::AbandonedIncr;
+                                set_clipboard(&server, None)?;
+                            }
                         }
                     }
                 }
-                State::Free => {
+                State::Free | State::AbandonedIncr => {
                     error!(
                         "Received property notification for free atom {}.",
                         conn.get_atom_name(event.atom)?
@@ -898,6 +1037,123 @@ fn handle_x11_event(
     Ok(())
 }
 
+/// Adds a selection that arrived in a single transfer.
+fn add_small_selection(
+    server: impl AsFd,
+    deduplicator: &mut CopyDeduplication,
+    mime_type: MimeType,
+    data: &[u8],
+    sensitive: bool,
+) -> Result<(), CliError> {
+    let file = File::from(
+        memfd_create(c"ringboard_x11_selection", MemfdFlags::empty())
+            .map_io_err(|| "Failed to create selection transfer temp file.")?,
+    );
+    file.write_all_at(data, 0)
+        .map_io_err(|| "Failed to write data to temp file.")?;
+
+    add_selection(
+        server,
+        deduplicator,
+        mime_type,
+        CopyData::Slice(data),
+        u64::try_from(data.len()).unwrap(),
+        &file,
+        sensitive,
+        "small",
+    )
+}
+
+/// Adds a selection that arrived in an INCR transfer. Once the server's size
+/// limit is reached, the rest of the transfer isn't waited for and the server
+/// truncates what was received.
+fn add_large_selection(
+    server: impl AsFd,
+    deduplicator: &mut CopyDeduplication,
+    mime_type: MimeType,
+    sensitive: bool,
+    file: File,
+    written: u64,
+) -> Result<(), CliError> {
+    add_selection(
+        server,
+        deduplicator,
+        mime_type,
+        CopyData::File(&file),
+        written,
+        &file,
+        sensitive,
+        "large",
+    )
+}
+
+/// Sends a transferred selection to the server. Sensitive selections bypass
+/// deduplication so they're never promoted into or remembered as permanent
+/// entries.
+#[allow(clippy::too_many_arguments)]
+fn add_selection(
+    server: impl AsFd,
+    deduplicator: &mut CopyDeduplication,
+    mime_type: MimeType,
+    data: CopyData,
+    len: u64,
+    file: &File,
+    sensitive: bool,
+    size: &str,
+) -> Result<(), CliError> {
+    let data_hash = CopyDeduplication::hash(data, len);
+    if !sensitive && let Some(existing) = deduplicator.check(data_hash, data) {
+        info!("Promoting duplicate {size} selection to front.");
+        if let MoveToFrontResponse::Success { id } =
+            MoveToFrontRequest::response(&server, existing, None)?
+        {
+            deduplicator.remember(data_hash, id);
+            return set_clipboard(&server, Some(id));
+        }
+    }
+
+    let response = if sensitive {
+        AddRequest::response_add_sensitive(&server, RingKind::Main, mime_type, file)?
+    } else {
+        AddRequest::response_add_unchecked(&server, RingKind::Main, mime_type, file)?
+    };
+    match response {
+        AddResponse::Success { id, .. } => {
+            if !sensitive {
+                deduplicator.remember(data_hash, id);
+            }
+            info!("Selection transfer complete ({size}).");
+            set_clipboard(&server, Some(id))?;
+        }
+        AddResponse::DatabaseFull => {
+            warn!("Dropping selection: the database is full.");
+            set_clipboard(&server, None)?;
+        }
+        AddResponse::UnsupportedData => {
+            warn!("Dropping selection: the server rejected its data.");
+            set_clipboard(&server, None)?;
+        }
+        AddResponse::Empty => {
+            warn!("Dropping selection: it was empty.");
+            set_clipboard(&server, None)?;
+        }
+        AddResponse::TooLarge { max } => {
+            warn!("Dropping selection: it's larger than {max} bytes.");
+            set_clipboard(&server, None)?;
+        }
+    }
+    Ok(())
+}
+
+/// Tells the server which entry now owns the clipboard, if any, so clients can
+/// point it out.
+fn set_clipboard(server: impl AsFd, id: Option<u64>) -> Result<(), CliError> {
+    if let SetClipboardResponse { error: Some(e) } = SetClipboardRequest::response(server, id)? {
+        warn!("Failed to mark entry {id:?} as on the clipboard: {e}");
+    }
+    Ok(())
+}
+
 fn handle_paste_event(
     conn: &RustConnection,
     clipboard_atom: Atom,